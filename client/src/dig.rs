@@ -0,0 +1,107 @@
+// Standard
+use std::{collections::HashMap, time::Duration};
+
+// Library
+use vek::*;
+
+// Project
+use common::{
+    terrain::{chunk::Block, VoxAbs},
+    util::{msg::ClientMsg, recording::Event},
+    Uid,
+};
+
+// Local
+use crate::{Client, Payloads};
+
+// Constants
+// The server is told how a dig is going each time it gets this much further, so that nearby players can see it
+const PROGRESS_STEP: f32 = 0.125;
+
+/// A block being dug out, and how far the dig has got from 0 to 1
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Dig {
+    pub pos: Vec3<VoxAbs>,
+    pub progress: f32,
+}
+
+// The player's own dig, with the block it's of, and the ones other players nearby are in the middle of
+#[derive(Default)]
+pub(crate) struct Digs {
+    own: Option<(Dig, Block)>,
+    others: HashMap<Uid, Dig>,
+}
+
+impl<P: Payloads> Client<P> {
+    /// Keep digging the block at `pos` for another `dt`, returning whether it broke. Digging a different block than
+    /// last time, or the block changing under the dig, starts over. Blocks that can't be dug, or that aren't loaded,
+    /// stop the dig instead.
+    pub fn dig(&self, pos: Vec3<VoxAbs>, dt: Duration) -> bool {
+        let block = match self.chunk_mgr().get_block(pos) {
+            Some(block) if block.is_breakable() => block,
+            _ => {
+                self.stop_digging();
+                return false;
+            },
+        };
+
+        let mut digs = self.digs.lock();
+        let before = match digs.own {
+            Some((dig, old)) if dig.pos == pos && old == block => Some(dig.progress),
+            _ => None,
+        };
+        let progress = (before.unwrap_or(0.0) + dt.as_float_secs() as f32 / block.hardness()).min(1.0);
+        let step = |progress: f32| (progress / PROGRESS_STEP) as u32;
+        let report = before.map(|before| step(before) != step(progress)).unwrap_or(true);
+
+        if progress >= 1.0 {
+            digs.own = None;
+        } else {
+            digs.own = Some((Dig { pos, progress }, block));
+        }
+        drop(digs);
+
+        if report {
+            self.send(ClientMsg::DigProgress { pos, progress });
+        }
+        if progress < 1.0 {
+            return false;
+        }
+
+        // Broken straight away rather than once the server agrees, which it tells us if it doesn't
+        self.chunk_mgr().set_block(pos, Block::AIR);
+        self.record(|r| r.record(&Event::Block { pos, block: Block::AIR }));
+        true
+    }
+
+    /// Give up on the block the player is digging, if they are. The next dig starts from nothing.
+    pub fn stop_digging(&self) {
+        if let Some((dig, _)) = self.digs.lock().own.take() {
+            self.send(ClientMsg::DigProgress {
+                pos: dig.pos,
+                progress: 0.0,
+            });
+        }
+    }
+
+    /// The block the player is digging, if they are
+    pub fn own_dig(&self) -> Option<Dig> { self.digs.lock().own.map(|(dig, _)| dig) }
+
+    /// The blocks other players nearby are digging
+    pub fn others_digs(&self) -> Vec<Dig> { self.digs.lock().others.values().cloned().collect() }
+
+    pub(crate) fn recv_dig_progress(&self, uid: Uid, pos: Vec3<VoxAbs>, progress: f32) {
+        // Our own digs are already known better than the server can tell us
+        if Some(uid) == self.player().entity_uid {
+            return;
+        }
+        if progress > 0.0 {
+            self.digs.lock().others.insert(uid, Dig { pos, progress });
+        } else {
+            self.forget_dig(uid);
+        }
+    }
+
+    // Forget what a player who's gone was digging
+    pub(crate) fn forget_dig(&self, uid: Uid) { self.digs.lock().others.remove(&uid); }
+}
@@ -0,0 +1,116 @@
+// Standard
+use std::collections::VecDeque;
+
+// Library
+use vek::*;
+
+// Project
+use common::{
+    terrain::{chunk::Block, VoxAbs},
+    util::{msg::ClientMsg, recording::Event},
+};
+
+// Local
+use crate::{Client, ClientEvent, Payloads};
+
+// Constants
+// How many of the player's block changes can be undone
+const MAX_UNDO: usize = 64;
+
+/// A block the player changed, and what it was before
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub pos: Vec3<VoxAbs>,
+    pub old: Block,
+    pub new: Block,
+}
+
+impl Edit {
+    fn inverse(self) -> Self {
+        Self {
+            pos: self.pos,
+            old: self.new,
+            new: self.old,
+        }
+    }
+}
+
+// The player's latest edits, oldest first, and the ones they've undone since they last made one
+#[derive(Default)]
+pub(crate) struct EditHistory {
+    done: VecDeque<Edit>,
+    undone: Vec<Edit>,
+}
+
+impl<P: Payloads> Client<P> {
+    /// Change a block, remembering the change so that it can be undone. Fails if the block's chunk isn't loaded.
+    pub fn set_block(&self, pos: Vec3<VoxAbs>, block: Block) -> bool {
+        let old = match self.chunk_mgr().get_block(pos) {
+            Some(old) => old,
+            None => return false,
+        };
+        self.send_edit(Edit { pos, old, new: block });
+
+        let mut edits = self.edits.lock();
+        if edits.done.len() >= MAX_UNDO {
+            edits.done.pop_front();
+        }
+        edits.done.push_back(Edit { pos, old, new: block });
+        edits.undone.clear();
+        true
+    }
+
+    /// Put back the block the player last changed, returning whether there was anything to undo. If someone else has
+    /// changed the block since, it's left as it is and the edit is forgotten.
+    pub fn undo_last_edit(&self) -> bool {
+        let edit = match self.edits.lock().done.pop_back() {
+            Some(edit) => edit,
+            None => return false,
+        };
+        if !self.try_edit(edit.inverse(), "undo") {
+            return false;
+        }
+        self.edits.lock().undone.push(edit);
+        true
+    }
+
+    /// Make the change the player last undid again. Like undoing, this is skipped if the block has changed since.
+    pub fn redo_last_edit(&self) -> bool {
+        let edit = match self.edits.lock().undone.pop() {
+            Some(edit) => edit,
+            None => return false,
+        };
+        if !self.try_edit(edit, "redo") {
+            return false;
+        }
+        self.edits.lock().done.push_back(edit);
+        true
+    }
+
+    // Make an edit only if the block is still what the edit expects it to be
+    fn try_edit(&self, edit: Edit, what: &str) -> bool {
+        if self.chunk_mgr().get_block(edit.pos) != Some(edit.old) {
+            self.publish(ClientEvent::ChatReceived {
+                text: format!("[Couldn't {} the change to {}, it's been changed since]", what, edit.pos),
+            });
+            return false;
+        }
+        self.send_edit(edit);
+        true
+    }
+
+    fn send_edit(&self, edit: Edit) {
+        // Made here straight away rather than once the server confirms it, so that building doesn't feel laggy
+        self.chunk_mgr().set_block(edit.pos, edit.new);
+        self.record(|r| {
+            r.record(&Event::Block {
+                pos: edit.pos,
+                block: edit.new,
+            })
+        });
+        self.send(ClientMsg::SetBlock {
+            pos: edit.pos,
+            block: edit.new,
+        });
+    }
+}
@@ -7,7 +7,11 @@ use common::net;
 #[derive(Debug)]
 pub enum Error {
     InvalidResponse,
+    // The server wouldn't let us in, such as for being banned, and said why
+    Refused(String),
     AlreadyRunning,
+    // A local client has no server to reconnect to
+    NoServer,
     MpscRecvErr(mpsc::RecvError),
     MpscRecvTimeoutErr(mpsc::RecvTimeoutError),
     MpscSendErr,
@@ -0,0 +1,145 @@
+// Standard
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+    Arc,
+};
+
+// Library
+use parking_lot::Mutex;
+use vek::*;
+
+// Project
+use common::{
+    ecs::{craft::CraftError, inventory::Item},
+    terrain::VolOffs,
+    util::msg::ServerInfo,
+    Uid,
+};
+
+// Local
+use crate::ClientStatus;
+
+// Constants
+/// How many events a subscriber can fall behind by before further events are dropped for it
+pub const EVENT_QUEUE_LEN: usize = 1024;
+
+#[derive(Clone, Debug)]
+pub enum ClientEvent {
+    ChatReceived { text: String },
+    EntitySpawned { uid: Uid },
+    EntityRemoved { uid: Uid },
+    ChunkLoaded { pos: Vec3<VolOffs> },
+    StatusChanged { status: ClientStatus },
+    /// The server ended the connection, and isn't expecting us back
+    Kicked { reason: String },
+    /// The player died, and can't move until they respawn
+    Died { cause: String },
+    /// The player came back to life after dying, and can move again
+    Respawned,
+    /// The server crafted what the player asked for, or said why it couldn't
+    CraftResult { recipe: String, result: Result<Item, CraftError> },
+    /// The server said what it is and what its message of the day is, as it does when the player joins
+    ServerInfo { info: ServerInfo },
+}
+
+/// The receiving end of a subscription to a client's events. Each subscriber gets its own copy of every event.
+pub struct EventReceiver {
+    recv: Receiver<ClientEvent>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl EventReceiver {
+    pub fn try_recv(&self) -> Option<ClientEvent> { self.recv.try_recv().ok() }
+
+    /// Take every event that's waiting
+    pub fn drain(&self) -> Vec<ClientEvent> { self.recv.try_iter().collect() }
+
+    /// How many events were dropped because this subscriber didn't keep up
+    pub fn dropped(&self) -> usize { self.dropped.load(Ordering::Relaxed) }
+}
+
+/// Hands out events to any number of subscribers. Publishing never blocks: a subscriber whose queue is full misses
+/// the event, and a subscriber that has gone away is forgotten.
+pub(crate) struct EventBus {
+    subscribers: Mutex<Vec<(SyncSender<ClientEvent>, Arc<AtomicUsize>)>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            subscribers: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn subscribe(&self, capacity: usize) -> EventReceiver {
+        let (send, recv) = sync_channel(capacity);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        self.subscribers.lock().push((send, dropped.clone()));
+        EventReceiver { recv, dropped }
+    }
+
+    pub fn publish(&self, event: ClientEvent) {
+        self.subscribers
+            .lock()
+            .retain(|(send, dropped)| match send.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                },
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(text: &str) -> ClientEvent { ClientEvent::ChatReceived { text: text.to_string() } }
+
+    #[test]
+    fn every_subscriber_gets_every_event() {
+        let bus = EventBus::new();
+        let a = bus.subscribe(8);
+        let b = bus.subscribe(8);
+
+        bus.publish(chat("hello"));
+        bus.publish(ClientEvent::EntityRemoved { uid: 3 });
+
+        for recv in &[a, b] {
+            let events = recv.drain();
+            assert_eq!(events.len(), 2);
+            match &events[0] {
+                ClientEvent::ChatReceived { text } => assert_eq!(text, "hello"),
+                e => panic!("Unexpected event: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn slow_subscribers_drop_events() {
+        let bus = EventBus::new();
+        let slow = bus.subscribe(2);
+        let fast = bus.subscribe(8);
+
+        for i in 0..5 {
+            bus.publish(chat(&i.to_string()));
+        }
+
+        // The oldest events are kept, and the rest are counted
+        assert_eq!(slow.drain().len(), 2);
+        assert_eq!(slow.dropped(), 3);
+        assert_eq!(fast.drain().len(), 5);
+        assert_eq!(fast.dropped(), 0);
+    }
+
+    #[test]
+    fn departed_subscribers_are_forgotten() {
+        let bus = EventBus::new();
+        drop(bus.subscribe(8));
+        bus.publish(chat("anyone?"));
+        assert!(bus.subscribers.lock().is_empty());
+    }
+}
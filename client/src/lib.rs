@@ -1,12 +1,18 @@
-#![feature(nll, euclidean_division, duration_as_u128, label_break_value)]
+#![feature(nll, euclidean_division, duration_as_u128, duration_float, label_break_value)]
 
 // Crates
+#[cfg(feature = "local-world")]
 extern crate world as world_crate; // TODO: Fix this naming conflict
 #[macro_use]
 extern crate log;
 
 // Modules
+mod dig;
+mod edit;
 mod error;
+mod event;
+#[cfg(feature = "local-world")]
+mod local;
 mod music;
 mod net;
 mod player;
@@ -14,15 +20,20 @@ mod tick;
 mod world;
 
 // Reexport
-pub use common::util::msg::PlayMode;
+pub use common::util::msg::{PlayMode, ServerInfo};
 
 // Standard
 use std::{
     collections::HashMap,
-    mem,
-    net::ToSocketAddrs,
-    sync::{atomic::Ordering, Arc},
-    time::Duration,
+    env, io, mem,
+    net::{SocketAddr, ToSocketAddrs},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 // Library
@@ -31,22 +42,52 @@ use vek::*;
 
 // Project
 use common::{
-    audio::{AudioGen, AudioMgr, Buffer},
-    get_asset_path,
-    terrain::{chunk::ChunkContainer, ChunkMgr, Entity, FnDropFunc, FnGenFunc, VolGen, VolOffs, VoxRel},
+    audio::{AudioGen, AudioMgr},
+    ecs::{
+        craft::Recipe,
+        inventory::{Inventory, InventoryAction, Item, HOTBAR_SLOTS},
+        net::{make_uid, uid_generation, uid_index},
+    },
+    net::Encryption,
+    physics::config::PhysicsConfig,
+    terrain::{
+        chunk::{Block, ChunkContainer},
+        self, ChunkMgr, Entity, FnDropFunc, FnPayloadFunc, VolGen, VolOffs, VoxAbs, VoxRel, Voxel, WorldBorder,
+    },
     util::{
-        clock::Clock,
+        clock::{Clock, FixedStep},
+        cmd::CmdSpec,
+        jobs::{JobHandle, Jobs},
         manager::{Managed, Manager},
         msg::{ClientMsg, ClientPostOffice, ServerMsg, SessionKind},
+        recording::{Event, Recorder},
     },
+    weather::Weather,
     Uid,
 };
 
 // Local
-use crate::{error::Error, player::Player};
+use crate::{
+    dig::Digs,
+    edit::EditHistory,
+    event::{EventBus, EVENT_QUEUE_LEN},
+    music::{Ambience, Sounds},
+    player::{Life, Player},
+    world::Loading,
+};
+#[cfg(feature = "local-world")]
+use crate::local::generate_locally;
 
 // Reexports
-pub use common::terrain::chunk::CHUNK_SIZE;
+pub use crate::{
+    dig::Dig,
+    error::Error,
+    event::{ClientEvent, EventReceiver},
+    world::LoadProgress,
+};
+#[cfg(feature = "local-world")]
+pub use crate::local::LocalWorld;
+pub use common::terrain::{chunk::CHUNK_SIZE, RayHit};
 
 // Constants
 pub const CHUNK_MID: Vec3<f32> = Vec3 {
@@ -54,11 +95,21 @@ pub const CHUNK_MID: Vec3<f32> = Vec3 {
     y: CHUNK_SIZE.y as f32 / 2.0,
     z: CHUNK_SIZE.z as f32 / 2.0,
 };
+// How much game time each physics step covers
+const PHYSICS_STEP: Duration = Duration::from_millis(20);
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
-
-#[derive(Copy, Clone, PartialEq)]
+// How often the incoming messages worker checks whether the client has reconnected
+const RECONNECT_POLL: Duration = Duration::from_millis(100);
+/// How far away, in blocks, the player can target blocks
+pub const BLOCK_REACH: f32 = 6.0;
+// How many chunks out from the player's own have to be loaded before they can move, unless set otherwise
+const DEFAULT_READY_RADIUS: usize = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ClientStatus {
     Connected,
+    /// The connection dropped and `Client::reconnect` is trying to get it back
+    Reconnecting,
     Timeout,
     Disconnected,
 }
@@ -69,108 +120,516 @@ pub trait Payloads: 'static {
     type Audio: AudioGen + Send + Sync + 'static;
 }
 
-pub enum ClientEvent {
-    RecvChatMsg { text: String },
+// What the server told us when we connected, or what a local client starts out with instead
+struct Handshake {
+    // `None` for a local client, which has no server
+    postoffice: Option<Manager<ClientPostOffice>>,
+    player_uid: Option<Uid>,
+    time: Duration,
+    session: u64,
+    physics: PhysicsConfig,
+    border: WorldBorder,
+    world_seed: u64,
+}
+
+// Connect to the server and log in, resuming `session` if there is one
+fn handshake(
+    remote_addrs: &[SocketAddr],
+    encryption: Encryption,
+    alias: &str,
+    mode: PlayMode,
+    session: Option<u64>,
+) -> Result<Handshake, Error> {
+    // Attempt to connect to the server
+    let postoffice = ClientPostOffice::to_server(remote_addrs, encryption)?;
+
+    // Initiate a connection handshake
+    let pb = postoffice.create_postbox(SessionKind::Connect);
+    let _ = pb.send(ClientMsg::Connect {
+        alias: alias.to_string(),
+        mode,
+        session,
+        login_token: None,
+    });
+
+    // Was the handshake successful?
+    match pb.recv_timeout(CONNECT_TIMEOUT)? {
+        ServerMsg::Connected {
+            player_uid,
+            time,
+            session,
+            physics,
+            border,
+            world_seed,
+        } => Ok(Handshake {
+            postoffice: Some(postoffice),
+            player_uid,
+            time,
+            session,
+            physics,
+            border,
+            world_seed,
+        }),
+        ServerMsg::Disconnect { reason } => Err(Error::Refused(reason)),
+        _ => Err(Error::InvalidResponse),
+    }
+}
+
+/// Ask a server about itself without joining it, the way a list of servers would
+pub fn server_status<S: ToSocketAddrs>(remote_addr: S, encryption: Encryption) -> Result<ServerInfo, Error> {
+    let postoffice = ClientPostOffice::to_server(remote_addr, encryption)?;
+    let pb = postoffice.create_postbox(SessionKind::Status);
+    let _ = pb.send(ClientMsg::Status);
+    match pb.recv_timeout(CONNECT_TIMEOUT)? {
+        ServerMsg::ServerInfo(info) => Ok(info),
+        _ => Err(Error::InvalidResponse),
+    }
 }
 
 pub struct Client<P: Payloads> {
     status: RwLock<ClientStatus>,
-    postoffice: Manager<ClientPostOffice>,
+    // Replaced when reconnecting, so it's shared with whoever is using the old one until they notice. A local client
+    // has none.
+    postoffice: RwLock<Option<Arc<Manager<ClientPostOffice>>>>,
+    remote_addrs: Vec<SocketAddr>,
+    encryption: Encryption,
+    mode: PlayMode,
+    // Presented to the server to resume where we left off if the connection drops
+    session: RwLock<u64>,
 
     clock: RwLock<Clock>,
     clock_tick_time: RwLock<Duration>,
+    // Real time that hasn't been simulated yet
+    fixed_step: Mutex<FixedStep>,
     player: RwLock<Player>,
+    life: RwLock<Life>,
+    inventory: RwLock<Inventory>,
+    // The hotbar slot the player is holding
+    held_slot: AtomicUsize,
+    // The commands the player may use, as last sent by the server
+    commands: RwLock<Vec<CmdSpec>>,
+    // The recipes the player can craft, as sent by the server when they joined
+    recipes: RwLock<Vec<Recipe>>,
+    server_info: RwLock<Option<ServerInfo>>,
     entities: RwLock<HashMap<Uid, Arc<RwLock<Entity<<P as Payloads>::Entity>>>>>,
+    // The newest generation seen for each uid index
+    uid_generations: RwLock<HashMap<u64, u64>>,
     phys_lock: Mutex<()>,
+    // Decided by the server, so that the player moves the way the server expects
+    physics: RwLock<PhysicsConfig>,
+    border: RwLock<WorldBorder>,
+    // Shared with the local generator, if chunks are generated here, which follows it to the server's world
+    world_seed: Arc<RwLock<u64>>,
+    // The weather where the player is, and how heavy it is
+    weather: RwLock<(Weather, f32)>,
+    // Where the player's movement is being recorded to, if it is
+    recorder: Mutex<Option<Recorder>>,
 
     chunk_mgr: ChunkMgr<<P as Payloads>::Chunk>,
+    // Chunks waiting on the server, and when they were last requested (`None` if they haven't been yet)
+    chunk_requests: Arc<Mutex<HashMap<Vec3<VolOffs>, Option<Instant>>>>,
+    // Block changes for chunks that were still on their way, to be made once they arrive
+    block_updates: Mutex<Vec<(Vec3<VoxAbs>, Block)>>,
+    // The player's own block changes, so that they can be undone
+    edits: Mutex<EditHistory>,
+    // Blocks being dug, by the player and by others nearby
+    digs: Mutex<Digs>,
+    // Whether the player is held in place waiting for the chunks around them, and where
+    loading: RwLock<Loading>,
+    ready_radius: AtomicUsize,
     audio_mgr: AudioMgr<<P as Payloads>::Audio>,
 
-    events: Mutex<Vec<ClientEvent>>,
+    events: Arc<EventBus>,
+    // Backs `get_events`, for frontends that haven't moved to `subscribe` yet
+    legacy_events: Mutex<EventReceiver>,
 
+    sounds: RwLock<Sounds>,
+    // The ambience that's playing and its stream
+    ambience: Mutex<Option<(Ambience, u64)>>,
     next_ambient: RwLock<Duration>,
     next_steps: RwLock<Duration>,
+    step_count: AtomicUsize,
     view_distance: i64,
+    // Work that blocks, done off whichever thread asked for it
+    jobs: Jobs<Client<P>>,
+}
+
+#[cfg(not(feature = "local-world"))]
+fn generate_locally<C: Send + Sync + 'static>(
+    _vol_gen: &mut VolGen<Vec3<VolOffs>, ChunkContainer<C>>,
+    _seed: Arc<RwLock<u64>>,
+    _events: Arc<EventBus>,
+) {
+    warn!("This client was built without the `local-world` feature, so its chunks come from the server");
 }
 
 impl<P: Payloads> Client<P> {
     pub fn new<
         S: ToSocketAddrs,
-        GP: FnGenFunc<Vec3<VolOffs>, ChunkContainer<P::Chunk>>,
+        GP: FnPayloadFunc<Vec3<VolOffs>, ChunkContainer<P::Chunk>>,
         DP: FnDropFunc<Vec3<VolOffs>, ChunkContainer<P::Chunk>>,
     >(
         mode: PlayMode,
         alias: String,
         remote_addr: S,
+        encryption: Encryption,
         gen_payload: GP,
         drop_payload: DP,
         audio_gen: Arc<<P as Payloads>::Audio>,
         view_distance: i64,
+        record: Option<&Path>,
     ) -> Result<Manager<Client<P>>, Error> {
-        // Attempt to connect to the server
-        let postoffice = ClientPostOffice::to_server(remote_addr)?;
+        let remote_addrs = remote_addr
+            .to_socket_addrs()
+            .map_err(|e| Error::NetworkErr(e.into()))?
+            .collect::<Vec<_>>();
+        let handshake = handshake(&remote_addrs, encryption, &alias, mode, None)?;
+        Ok(Self::start(
+            handshake,
+            remote_addrs,
+            encryption,
+            mode,
+            alias,
+            gen_payload,
+            drop_payload,
+            audio_gen,
+            view_distance,
+            record,
+        ))
+    }
+
+    /// A client for a world generated here from `seed`, with no server. The player starts where they would on a server
+    /// with that seed, and can walk around and look at real terrain, but there's no one else around and nothing they do
+    /// is sent anywhere. Useful for menu backgrounds, screenshots and benchmarks.
+    #[cfg(feature = "local-world")]
+    pub fn local<
+        GP: FnPayloadFunc<Vec3<VolOffs>, ChunkContainer<P::Chunk>>,
+        DP: FnDropFunc<Vec3<VolOffs>, ChunkContainer<P::Chunk>>,
+    >(
+        seed: u64,
+        alias: String,
+        gen_payload: GP,
+        drop_payload: DP,
+        audio_gen: Arc<<P as Payloads>::Audio>,
+        view_distance: i64,
+    ) -> Manager<Client<P>> {
+        let handshake = Handshake {
+            postoffice: None,
+            player_uid: Some(make_uid(0, 0)),
+            time: Duration::from_secs(0),
+            session: 0,
+            physics: PhysicsConfig::default(),
+            border: WorldBorder::default(),
+            world_seed: seed,
+        };
+        let client = Self::start(
+            handshake,
+            vec![],
+            Encryption::Preferred,
+            PlayMode::Character,
+            alias,
+            gen_payload,
+            drop_payload,
+            audio_gen,
+            view_distance,
+            None,
+        );
+
+        // There's no server to put the player anywhere, so they start where a server would spawn them
+        let spawn = LocalWorld::new(seed).spawn_point();
+        client.add_entity(make_uid(0, 0), Entity::new(spawn, Vec3::zero(), Vec3::zero(), Vec2::unit_y()));
+        *client.loading.write() = Loading::At(spawn);
+        client
+    }
 
-        // Initiate a connection handshake
-        let pb = postoffice.create_postbox(SessionKind::Connect);
-        let _ = pb.send(ClientMsg::Connect {
-            alias: alias.clone(),
+    fn start<
+        GP: FnPayloadFunc<Vec3<VolOffs>, ChunkContainer<P::Chunk>>,
+        DP: FnDropFunc<Vec3<VolOffs>, ChunkContainer<P::Chunk>>,
+    >(
+        handshake: Handshake,
+        remote_addrs: Vec<SocketAddr>,
+        encryption: Encryption,
+        mode: PlayMode,
+        alias: String,
+        gen_payload: GP,
+        drop_payload: DP,
+        audio_gen: Arc<<P as Payloads>::Audio>,
+        view_distance: i64,
+        record: Option<&Path>,
+    ) -> Manager<Client<P>> {
+        let events = Arc::new(EventBus::new());
+        let legacy_events = events.subscribe(EVENT_QUEUE_LEN);
+
+        // Chunks are streamed from the server unless local generation is requested (useful for offline testing)
+        let chunk_requests = Arc::new(Mutex::new(HashMap::new()));
+        let requests = chunk_requests.clone();
+        let mut vol_gen = VolGen::new(
+            move |pos, _con| {
+                requests.lock().entry(pos).or_insert(None);
+            },
+            gen_payload,
+            |_pos, _con| {},
+            drop_payload,
+        );
+
+        let Handshake {
+            postoffice,
+            player_uid,
+            time,
+            session,
+            physics,
+            border,
+            world_seed,
+        } = handshake;
+        let world_seed = Arc::new(RwLock::new(world_seed));
+        // A local client has no server to stream chunks from
+        if postoffice.is_none() || env::var("VELOREN_LOCAL_CHUNKS").is_ok() {
+            // Generated from the seed the server uses, if there is one, so that it's the same world
+            generate_locally(&mut vol_gen, world_seed.clone(), events.clone());
+        }
+
+        // The player's movement is recorded to `record` if given, to replay it later and see where it went differently
+        // to the server
+        let recorder = record.and_then(|path| match Recorder::create(path, physics) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                warn!("Couldn't start recording to {:?}: {}", path, e);
+                None
+            },
+        });
+
+        let client = Manager::init(Client {
+            status: RwLock::new(ClientStatus::Connected),
+            postoffice: RwLock::new(postoffice.map(Arc::new)),
+            remote_addrs,
+            encryption,
             mode,
+            session: RwLock::new(session),
+
+            clock: RwLock::new(Clock::new(PHYSICS_STEP)),
+            clock_tick_time: RwLock::new(time),
+            fixed_step: Mutex::new(FixedStep::new(PHYSICS_STEP)),
+            // The player's entity is set up front so that nothing sent about it is missed
+            player: RwLock::new(Player {
+                entity_uid: player_uid,
+                ..Player::new(alias)
+            }),
+            life: RwLock::new(Life::Alive),
+            inventory: RwLock::new(Inventory::new()),
+            held_slot: AtomicUsize::new(0),
+            commands: RwLock::new(vec![]),
+            recipes: RwLock::new(vec![]),
+            server_info: RwLock::new(None),
+            entities: RwLock::new(HashMap::new()),
+            uid_generations: RwLock::new(HashMap::new()),
+            phys_lock: Mutex::new(()),
+            physics: RwLock::new(physics),
+            border: RwLock::new(border),
+            world_seed,
+            weather: RwLock::new((Weather::Clear, 0.0)),
+            recorder: Mutex::new(recorder),
+
+            chunk_mgr: ChunkMgr::new(CHUNK_SIZE, vol_gen),
+            chunk_requests,
+            block_updates: Mutex::new(vec![]),
+            edits: Mutex::new(EditHistory::default()),
+            digs: Mutex::new(Digs::default()),
+            loading: RwLock::new(Loading::Joining),
+            ready_radius: AtomicUsize::new(DEFAULT_READY_RADIUS),
+            audio_mgr: AudioMgr::new(audio_gen),
+
+            events,
+            legacy_events: Mutex::new(legacy_events),
+            sounds: RwLock::new(Sounds::default()),
+            ambience: Mutex::new(None),
+            next_ambient: RwLock::new(time),
+            next_steps: RwLock::new(time),
+            step_count: AtomicUsize::new(0),
+
+            view_distance: view_distance.max(CHUNK_SIZE.x as i64),
+            jobs: Jobs::new(),
         });
+        client.jobs.set_root(Manager::internal(&client).clone());
 
-        // Was the handshake successful?
-        if let ServerMsg::Connected { player_uid, time } = pb.recv_timeout(CONNECT_TIMEOUT)? {
-            let client = Manager::init(Client {
-                status: RwLock::new(ClientStatus::Connected),
-                postoffice,
+        client
+    }
 
-                clock: RwLock::new(Clock::new(Duration::from_millis(20))),
-                clock_tick_time: RwLock::new(time),
-                player: RwLock::new(Player::new(alias)),
-                entities: RwLock::new(HashMap::new()),
-                phys_lock: Mutex::new(()),
+    /// Get back onto the server after the connection dropped, with the same alias. If the server is still holding on to
+    /// our player (it keeps them for a while after their connection drops), we carry on as them; otherwise we start
+    /// afresh as a new player. Loaded chunks are kept either way, unless the world has a new seed, and chunks that were
+    /// requested but never arrived are asked for again. A local client has no server to reconnect to.
+    pub fn reconnect(&self) -> Result<(), Error> {
+        let postoffice = self.postoffice().ok_or(Error::NoServer)?;
+        self.set_status(ClientStatus::Reconnecting);
+        postoffice.stop();
+
+        let alias = self.player().alias.clone();
+        let session = *self.session.read();
+        let handshake = match handshake(&self.remote_addrs, self.encryption, &alias, self.mode, Some(session)) {
+            Ok(handshake) => handshake,
+            Err(e) => {
+                self.set_status(ClientStatus::Disconnected);
+                return Err(e);
+            },
+        };
+
+        // The server sends every entity again each tick, so only our own player is worth keeping. Anything that went
+        // away while we were gone would linger otherwise.
+        let resumed = handshake.player_uid.is_some() && handshake.player_uid == self.player().entity_uid;
+        let keep = if resumed { handshake.player_uid } else { None };
+        self.entities.write().retain(|uid, _| Some(*uid) == keep);
+        if !resumed {
+            self.player_mut().entity_uid = handshake.player_uid;
+            // A new player starts out alive
+            if mem::replace(&mut *self.life.write(), Life::Alive) != Life::Alive {
+                self.publish(ClientEvent::Respawned);
+            }
+            *self.inventory.write() = Inventory::new();
+            self.held_slot.store(0, Ordering::Relaxed);
+            // It may not be the same server, and a new one could hand out old generations again
+            self.uid_generations.write().clear();
+        }
 
-                chunk_mgr: ChunkMgr::new(
-                    CHUNK_SIZE,
-                    VolGen::new(world::gen_chunk, gen_payload, world::drop_chunk, drop_payload),
-                ),
-                audio_mgr: AudioMgr::new(audio_gen),
+        // A different seed is a different world, so none of the chunks we have are right any more
+        if handshake.world_seed != self.world_seed() {
+            *self.world_seed.write() = handshake.world_seed;
+            self.chunk_mgr.retain(|_| false);
+            self.block_updates.lock().clear();
+        }
+        // Requests sent over the old connection were lost with it
+        for requested in self.chunk_requests.lock().values_mut() {
+            *requested = None;
+        }
+        // The server holds the player in place until we say we're ready again
+        *self.loading.write() = Loading::Joining;
+
+        *self.session.write() = handshake.session;
+        *self.clock_tick_time.write() = handshake.time;
+        *self.physics.write() = handshake.physics;
+        self.record(|r| r.record(&Event::Physics(handshake.physics)));
+        *self.border.write() = handshake.border;
+        *self.postoffice.write() = handshake.postoffice.map(Arc::new);
+        self.set_status(ClientStatus::Connected);
+        Ok(())
+    }
 
-                events: Mutex::new(vec![]),
-                next_ambient: RwLock::new(time),
-                next_steps: RwLock::new(time),
+    /// Like `reconnect`, but on its own thread, since connecting blocks. Asking again while an attempt is still going
+    /// gets the result of that attempt rather than starting another.
+    pub fn reconnect_in_background(&self) -> JobHandle<Result<(), String>> {
+        self.jobs.spawn_named("reconnect", |client| client.reconnect().map_err(|e| format!("{:?}", e)))
+    }
 
-                view_distance: view_distance.max(CHUNK_SIZE.x as i64),
-            });
+    pub(crate) fn postoffice(&self) -> Option<Arc<Manager<ClientPostOffice>>> { self.postoffice.read().clone() }
 
-            client.player.write().entity_uid = player_uid;
+    /// Whether the world was generated here with no server, as by `Client::local`
+    pub fn is_local(&self) -> bool { self.postoffice.read().is_none() }
 
-            Ok(client)
-        } else {
-            Err(Error::InvalidResponse)
+    // Send a message to the server, if there is one
+    pub(crate) fn send(&self, msg: ClientMsg) {
+        if let Some(postoffice) = self.postoffice() {
+            let _ = postoffice.send_one(msg);
         }
     }
 
-    pub fn send_chat_msg(&self, text: String) { let _ = self.postoffice.send_one(ClientMsg::ChatMsg { text }); }
+    pub(crate) fn is_connected(&self) -> bool { *self.status() == ClientStatus::Connected }
+
+    pub fn send_chat_msg(&self, text: String) { self.send(ClientMsg::ChatMsg { text }); }
+
+    pub fn send_cmd(&self, args: Vec<String>) { self.send(ClientMsg::Cmd { args }); }
 
-    pub fn send_cmd(&self, args: Vec<String>) { let _ = self.postoffice.send_one(ClientMsg::Cmd { args }); }
+    pub fn send_inventory_action(&self, action: InventoryAction) {
+        self.send(ClientMsg::InventoryAction(action));
+    }
+
+    /// Hold the items in a hotbar slot. Slots past the end of the hotbar are ignored.
+    pub fn select_slot(&self, slot: usize) {
+        if slot < HOTBAR_SLOTS {
+            self.held_slot.store(slot, Ordering::Relaxed);
+            self.send(ClientMsg::SelectSlot { slot });
+        }
+    }
+
+    /// Ask to craft a recipe `count` times over. How it went comes back as a `ClientEvent::CraftResult`.
+    pub fn craft(&self, recipe: &str, count: u32) {
+        self.send(ClientMsg::Craft {
+            recipe: recipe.to_string(),
+            count,
+        });
+    }
+
+    pub fn send_attack(&self, dir: Vec3<f32>) { self.send(ClientMsg::Attack { dir }); }
 
     pub fn view_distance(&self) -> f32 { self.view_distance as f32 }
 
     pub fn chunk_mgr(&self) -> &ChunkMgr<<P as Payloads>::Chunk> { &self.chunk_mgr }
 
-    pub fn get_events(&self) -> Vec<ClientEvent> {
-        let mut events = vec![];
-        mem::swap(&mut events, &mut self.events.lock());
-        events
+    /// The block at `pos` in the world, or `None` if the chunk it's in isn't loaded. Only the chunk manager's locks are
+    /// taken, and they're let go of before it returns, so it's safe to call while holding the physics lock or an
+    /// entity's lock. It mustn't be called while holding a lock on a chunk, though.
+    pub fn block_at(&self, pos: Vec3<f64>) -> Option<Block> { self.chunk_mgr.get_block(terrain::pos_to_voxabs(pos)) }
+
+    /// Whether the block at `pos` is solid, or `None` if the chunk it's in isn't loaded. Locks as `block_at` does.
+    pub fn is_solid_at(&self, pos: Vec3<f64>) -> Option<bool> { self.block_at(pos).map(|block| block.is_solid()) }
+
+    /// Every block the box from `min` to `max` is in any part of, along with where it is, with `None` for blocks in
+    /// chunks that aren't loaded. Much quicker than `block_at` for each of them, since each chunk is only locked once.
+    /// Locks as `block_at` does.
+    pub fn blocks_in_aabb(&self, min: Vec3<f64>, max: Vec3<f64>) -> Vec<(Vec3<VoxAbs>, Option<Block>)> {
+        self.chunk_mgr.blocks_in_aabb(min, max)
+    }
+
+    /// Find the block the player is looking at from `origin` in direction `dir`, if it's within reach
+    pub fn ray_cast(&self, origin: Vec3<f32>, dir: Vec3<f32>) -> Option<RayHit> {
+        self.chunk_mgr.ray_cast(origin, dir, BLOCK_REACH)
     }
 
+    pub fn audio_mgr(&self) -> &AudioMgr<<P as Payloads>::Audio> { &self.audio_mgr }
+
+    /// Receive everything that happens to the client from now on. Any number of subscribers can listen at once; one
+    /// that falls more than `EVENT_QUEUE_LEN` events behind misses events rather than holding the client up.
+    pub fn subscribe(&self) -> EventReceiver { self.events.subscribe(EVENT_QUEUE_LEN) }
+
+    #[deprecated(note = "use `Client::subscribe` instead")]
+    pub fn get_events(&self) -> Vec<ClientEvent> { self.legacy_events.lock().drain() }
+
+    pub(crate) fn publish(&self, event: ClientEvent) { self.events.publish(event) }
+
     pub fn status<'a>(&'a self) -> RwLockReadGuard<'a, ClientStatus> { self.status.read() }
 
+    pub(crate) fn set_status(&self, status: ClientStatus) {
+        let old = mem::replace(&mut *self.status.write(), status);
+        if old != status {
+            self.publish(ClientEvent::StatusChanged { status });
+        }
+    }
+
     pub fn time(&self) -> Duration { *self.clock_tick_time.read() }
 
+    /// How far the present moment is between the last two physics steps, from 0 to 1. Entities are drawn this far
+    /// along between where they were and where they are.
+    pub fn tick_alpha(&self) -> f32 { self.fixed_step.lock().alpha(Instant::now()) }
+
     pub fn player<'a>(&'a self) -> RwLockReadGuard<'a, Player> { self.player.read() }
     pub fn player_mut<'a>(&'a self) -> RwLockWriteGuard<'a, Player> { self.player.write() }
 
+    /// The player's inventory, as last sent by the server
+    pub fn inventory<'a>(&'a self) -> RwLockReadGuard<'a, Inventory> { self.inventory.read() }
+
+    /// The hotbar slot the player is holding
+    pub fn held_slot(&self) -> usize { self.held_slot.load(Ordering::Relaxed) }
+
+    /// The items in the player's hand, if the slot they're holding isn't empty
+    pub fn held_item(&self) -> Option<Item> { self.inventory.read().get(self.held_slot()) }
+
+    /// The commands the player may use and the arguments they take, for completing them as they're typed
+    pub fn commands<'a>(&'a self) -> RwLockReadGuard<'a, Vec<CmdSpec>> { self.commands.read() }
+
+    /// Every recipe the server knows, whether or not the player has what it takes
+    pub fn recipes<'a>(&'a self) -> RwLockReadGuard<'a, Vec<Recipe>> { self.recipes.read() }
+
+    /// What the server said about itself when the player joined, or `None` if it hasn't yet
+    pub fn server_info(&self) -> Option<ServerInfo> { self.server_info.read().clone() }
+
     pub fn entities<'a>(&'a self) -> RwLockReadGuard<'a, HashMap<Uid, Arc<RwLock<Entity<<P as Payloads>::Entity>>>>> {
         self.entities.read()
     }
@@ -186,6 +645,27 @@ impl<P: Payloads> Client<P> {
 
     pub fn take_phys_lock<'a>(&'a self) -> MutexGuard<'a, ()> { self.phys_lock.lock() }
 
+    /// Add to the recording of the player's movement, if one is being made. Recording stops if it can't be written.
+    pub(crate) fn record<F: FnOnce(&mut Recorder) -> io::Result<()>>(&self, f: F) {
+        let mut recorder = self.recorder.lock();
+        if let Some(Err(e)) = recorder.as_mut().map(f) {
+            warn!("Stopped recording, it couldn't be written: {}", e);
+            *recorder = None;
+        }
+    }
+
+    /// The physics config the server told us to use
+    pub fn physics(&self) -> PhysicsConfig { *self.physics.read() }
+
+    /// The edge of the world, which the player can't go beyond
+    pub fn world_border(&self) -> WorldBorder { *self.border.read() }
+
+    /// What the server generates the world from, for anything generated on this side that should match it
+    pub fn world_seed(&self) -> u64 { *self.world_seed.read() }
+
+    /// The weather where the player is, as last told by the server, and how heavy it is from 0 to 1
+    pub fn weather(&self) -> (Weather, f32) { *self.weather.read() }
+
     pub fn add_entity(&self, uid: Uid, entity: Entity<<P as Payloads>::Entity>) -> bool {
         !self
             .entities
@@ -194,7 +674,32 @@ impl<P: Payloads> Client<P> {
             .is_none()
     }
 
-    pub fn remove_entity(&self, uid: Uid) -> bool { !self.entities.write().remove(&uid).is_some() }
+    /// Forget about an entity the server says is gone, returning whether it was known about. Removals can race with
+    /// the updates that create entities, so an unknown uid is nothing to worry about.
+    pub fn remove_entity(&self, uid: Uid) -> bool {
+        let removed = self.entities.write().remove(&uid).is_some();
+        if removed {
+            self.forget_dig(uid);
+            self.publish(ClientEvent::EntityRemoved { uid });
+        }
+        removed
+    }
+
+    /// Whether `uid` belongs to an entity the server has since replaced with a newer one under the same index, so that
+    /// anything still in flight about it should be ignored. Seeing a newer generation for the first time forgets the
+    /// entity it replaced, in case news of its deletion was lost.
+    pub fn is_stale_uid(&self, uid: Uid) -> bool {
+        let (index, generation) = (uid_index(uid), uid_generation(uid));
+        let newest = *self.uid_generations.write().entry(index).or_insert(generation);
+        if generation < newest {
+            return true;
+        }
+        if generation > newest {
+            self.uid_generations.write().insert(index, generation);
+            self.remove_entity(make_uid(index, newest));
+        }
+        false
+    }
 
     pub fn player_entity(&self) -> Option<Arc<RwLock<Entity<<P as Payloads>::Entity>>>> {
         self.player().entity_uid.and_then(|uid| self.entity(uid))
@@ -205,34 +710,40 @@ impl<P: Payloads> Managed for Client<P> {
     fn init_workers(&self, manager: &mut Manager<Self>) {
         // Incoming messages worker
         Manager::add_worker(manager, |client, running, mut mgr| {
-            while running.load(Ordering::Relaxed) && *client.status() == ClientStatus::Connected {
-                client.handle_incoming(&mut mgr);
+            while running.load(Ordering::Relaxed) {
+                if client.is_connected() && !client.is_local() {
+                    client.handle_incoming(&mut mgr);
+                } else {
+                    // Wait to be reconnected, or for good if there's no server
+                    thread::sleep(RECONNECT_POLL);
+                }
             }
-
-            // Send a disconnect message to the server
-            let _ = client
-                .postoffice
-                .create_postbox(SessionKind::Disconnect)
-                .send(ClientMsg::Disconnect {
-                    reason: "Logging out".into(),
-                });
         });
 
         // Tick worker
         Manager::add_worker(manager, |client, running, mut mgr| {
-            while running.load(Ordering::Relaxed) && *client.status() == ClientStatus::Connected {
+            while running.load(Ordering::Relaxed) {
                 let mut clocklock = client.clock.write();
-                client.tick(clocklock.reference_duration(), &mut mgr);
+                // However late the worker wakes up, physics runs in steps of the same length for the time that really
+                // passed, so that it runs the same at any load
+                let steps = client.fixed_step.lock().advance(Instant::now());
+                for _ in 0..steps {
+                    if client.is_connected() {
+                        client.tick(PHYSICS_STEP, &mut mgr);
+                    }
+                    *client.clock_tick_time.write() += PHYSICS_STEP;
+                }
                 clocklock.tick();
-                *client.clock_tick_time.write() += clocklock.reference_duration();
             }
         });
 
         // Chunkmgr worker
         Manager::add_worker(manager, |client, running, mut mgr| {
             let mut clock = Clock::new(Duration::from_millis(200));
-            while running.load(Ordering::Relaxed) && *client.status() == ClientStatus::Connected {
-                client.manage_chunks(&mut mgr);
+            while running.load(Ordering::Relaxed) {
+                if client.is_connected() {
+                    client.manage_chunks(&mut mgr);
+                }
                 clock.tick();
             }
         });
@@ -240,39 +751,41 @@ impl<P: Payloads> Managed for Client<P> {
         // Debug worker
         Manager::add_worker(manager, |client, running, mut mgr| {
             let mut clock = Clock::new(Duration::from_millis(5000));
-            while running.load(Ordering::Relaxed) && *client.status() == ClientStatus::Connected {
-                client.debug(&mut mgr);
+            while running.load(Ordering::Relaxed) {
+                if client.is_connected() {
+                    client.debug(&mut mgr);
+                }
                 clock.tick();
             }
         });
 
         // Audio worker
         Manager::add_worker(manager, |client, running, mut mgr| {
-            client
-                .audio_mgr
-                .gen_buffer(Buffer::File(get_asset_path("voxygen/audio/ambient/ambient1.ogg")));
-            client
-                .audio_mgr
-                .gen_buffer(Buffer::File(get_asset_path("voxygen/audio/ambient/ambient2.ogg")));
-            client
-                .audio_mgr
-                .gen_buffer(Buffer::File(get_asset_path("voxygen/audio/effects/step_lth1.ogg")));
-            client
-                .audio_mgr
-                .gen_buffer(Buffer::File(get_asset_path("voxygen/audio/effects/step_lth2.ogg")));
-            client.audio_mgr.gen_buffer(Buffer::File(get_asset_path(
-                "voxygen/audio/music/Snowtop_with_Celesta.ogg",
-            )));
+            client.load_sounds();
             let mut clock = Clock::new(Duration::from_millis(100));
-            while running.load(Ordering::Relaxed) && *client.status() == ClientStatus::Connected {
-                client.manage_audio(&mut mgr);
+            while running.load(Ordering::Relaxed) {
+                if client.is_connected() {
+                    client.manage_audio(&mut mgr);
+                }
                 clock.tick();
             }
         });
     }
 
     fn on_drop(&self, _: &mut Manager<Self>) {
-        *self.status.write() = ClientStatus::Disconnected;
-        self.postoffice.stop();
+        // Tell the server we're logging out, so it doesn't wait for us to reconnect
+        let postoffice = self.postoffice();
+        if let Some(postoffice) = &postoffice {
+            let _ = postoffice
+                .create_postbox(SessionKind::Disconnect)
+                .send(ClientMsg::Disconnect {
+                    reason: "Logging out".into(),
+                });
+        }
+
+        self.set_status(ClientStatus::Disconnected);
+        if let Some(postoffice) = postoffice {
+            postoffice.stop();
+        }
     }
 }
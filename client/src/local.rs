@@ -0,0 +1,154 @@
+// Standard
+use std::sync::Arc;
+
+// Library
+use parking_lot::{Mutex, RwLock};
+use vek::*;
+
+// Project
+use common::terrain::{
+    chunk::{Chunk, ChunkContainer},
+    VolGen, VolOffs,
+};
+
+// Local
+use crate::{
+    event::{ClientEvent, EventBus},
+    world_crate::{GenConfig, Generator},
+};
+
+/// A world generated on the client from a seed, with no server. It's generated by the server's own generator, so the
+/// same seed gives the same blocks made of the same materials wherever it's generated.
+pub struct LocalWorld {
+    seed: u64,
+    generator: Generator,
+}
+
+impl LocalWorld {
+    pub fn new(seed: u64) -> Self {
+        // Only the area around one player is generated, so the caches needn't be as big as the server's
+        let config = GenConfig {
+            overworld_cache_size: 1024,
+            city_cache_size: 256,
+            building_cache_size: 256,
+            structure_cache_size: 64,
+        };
+        Self {
+            seed,
+            generator: Generator::new(&config, seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 { self.seed }
+
+    /// Where a player joining the world would start
+    pub fn spawn_point(&self) -> Vec3<f32> { self.generator.spawn_point() }
+
+    /// The chunk at `pos`, as the server would generate it
+    pub fn gen_chunk(&self, pos: Vec3<VolOffs>) -> Chunk { self.generator.gen_chunk(pos) }
+}
+
+/// Have `vol_gen` generate chunks from `seed` instead of asking the server for them. If `seed` changes, as when
+/// reconnecting to a server with a different world, the generator is rebuilt to match. Chunks aren't saved to disk,
+/// since the same seed always generates them again the same.
+pub(crate) fn generate_locally<P: Send + Sync + 'static>(
+    vol_gen: &mut VolGen<Vec3<VolOffs>, ChunkContainer<P>>,
+    seed: Arc<RwLock<u64>>,
+    events: Arc<EventBus>,
+) {
+    let world = RwLock::new(LocalWorld::new(*seed.read()));
+    vol_gen.gen_vol = Arc::new(move |pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<P>>>>| {
+        let seed = *seed.read();
+        if world.read().seed() != seed {
+            let mut world = world.write();
+            // Another chunk may have got here first
+            if world.seed() != seed {
+                *world = LocalWorld::new(seed);
+            }
+        }
+        let chunk = world.read().gen_chunk(pos);
+        *con.lock() = Some(ChunkContainer::<P>::new(chunk));
+        events.publish(ClientEvent::ChunkLoaded { pos });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Client;
+    use common::{
+        audio::{AudioGen, Buffer, Stream},
+        terrain::{chunk::CHUNK_SIZE, voxabs_to_voloffs, Container, VolCluster, VoxAbs},
+    };
+    use std::{collections::HashMap, thread, time::Duration};
+
+    struct NoAudio;
+    impl AudioGen for NoAudio {
+        fn gen_stream(&self, _id: u64, _buffer: &Buffer, _stream: &Stream) {}
+        fn update_stream(&self, _id: u64, _buffer: &Buffer, _stream: &Stream) {}
+        fn set_listener(&self, _pos: Vec3<f32>, _ori: Vec3<f32>) {}
+        fn gen_buffer(&self, _id: u64, _buffer: &Buffer) {}
+        fn drop_stream(&self, _id: u64, _buffer: &Buffer, _stream: &Stream) {}
+        fn drop_buffer(&self, _id: u64, _buffer: &Buffer) {}
+    }
+
+    struct Payloads;
+    impl crate::Payloads for Payloads {
+        type Chunk = ();
+        type Entity = ();
+        type Audio = NoAudio;
+    }
+
+    // The client waits for the chunks around the player to have payloads, and there's nothing to draw
+    fn gen_payload(
+        _pos: Vec3<VolOffs>,
+        con: &ChunkContainer<()>,
+        _neighbours: &HashMap<Vec3<VolOffs>, Arc<ChunkContainer<()>>>,
+    ) {
+        *con.payload_mut() = Some(());
+    }
+
+    #[test]
+    fn local_chunks_are_the_ones_the_server_generates() {
+        let seed = 1337;
+        let world = LocalWorld::new(seed);
+        let server = Generator::new(&GenConfig::default(), seed);
+
+        // Around spawn, where there's ground and not just air
+        let spawn = voxabs_to_voloffs(world.spawn_point().map(|e| e.floor() as VoxAbs), CHUNK_SIZE);
+        for z in -1..=1 {
+            let pos = spawn + Vec3::new(0, 0, z);
+            assert_eq!(
+                world.gen_chunk(pos).to_bytes(),
+                server.gen_chunk(pos).to_bytes(),
+                "chunk {} differs",
+                pos
+            );
+            // Whatever else the world has generated before
+            assert_eq!(
+                world.gen_chunk(pos).to_bytes(),
+                LocalWorld::new(seed).gen_chunk(pos).to_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn a_local_client_loads_the_world_around_spawn_with_no_server() {
+        let seed = 1337;
+        let client = Client::<Payloads>::local(seed, "Local".to_string(), gen_payload, |_, _| {}, Arc::new(NoAudio), 0);
+        assert!(client.is_local());
+
+        let mut waited = Duration::from_secs(0);
+        while client.loading().is_some() {
+            assert!(waited < Duration::from_secs(30), "the chunks around spawn never loaded");
+            thread::sleep(Duration::from_millis(10));
+            waited += Duration::from_millis(10);
+        }
+        let player = client.player_entity().expect("the local player has no entity");
+        let spawn = LocalWorld::new(seed).spawn_point();
+        assert!(player.read().pos().distance(spawn) < CHUNK_SIZE.x as f32);
+
+        // There's nothing to reconnect to
+        assert!(client.reconnect().is_err());
+    }
+}
@@ -1,110 +1,236 @@
 // Standard
-use std::time::Duration;
+use std::{collections::HashMap, sync::atomic::Ordering, time::Duration};
 
 // Library
 use vek::*;
 
 // Project
 use common::{
-    audio::{Position, Stream},
-    terrain::{chunk::Block, VoxAbs},
+    audio::{Buffer, Fade, Group, Position, SoundId, Stream},
+    get_asset_path,
+    terrain::{self, chunk::Block, VoxAbs},
     util::manager::Manager,
+    weather::Precipitation,
 };
 
 // Local
 use crate::{Client, Payloads};
 
+// Constants
+const AMBIENCE_FADE: Duration = Duration::from_secs(3);
+const STEP_INTERVAL: Duration = Duration::from_millis(300);
+const WATER_SEARCH_RADIUS: VoxAbs = 20;
+// Players above this altitude hear the highland ambience
+const HIGHLANDS_ALTITUDE: f32 = 200.0;
+// Rain any lighter than this is drowned out by the rest of the ambience
+const AUDIBLE_RAIN: f32 = 0.2;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Ambience {
+    Plains,
+    Water,
+    Highlands,
+    Rain,
+}
+
+impl Ambience {
+    fn file(&self) -> &'static str {
+        match self {
+            Ambience::Plains => "voxygen/audio/ambient/ambient1.ogg",
+            Ambience::Water => "voxygen/audio/ambient/ambient2.ogg",
+            Ambience::Highlands => "voxygen/audio/ambient/highlands.ogg",
+            Ambience::Rain => "voxygen/audio/ambient/rain.ogg",
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        match self {
+            Ambience::Plains => Duration::from_secs(160),
+            Ambience::Water => Duration::from_secs(90),
+            Ambience::Highlands => Duration::from_secs(120),
+            Ambience::Rain => Duration::from_secs(60),
+        }
+    }
+}
+
+/// Buffer ids of the sounds the client plays. Sounds whose files couldn't be found are `None`.
+#[derive(Default)]
+pub(crate) struct Sounds {
+    plains: Option<u64>,
+    water: Option<u64>,
+    highlands: Option<u64>,
+    rain: Option<u64>,
+    steps: Vec<u64>,
+    // Sounds the server asks for, with how long each plays
+    events: HashMap<SoundId, (u64, Duration)>,
+}
+
+impl Sounds {
+    fn ambience(&self, ambience: Ambience) -> Option<u64> {
+        match ambience {
+            Ambience::Plains => self.plains,
+            Ambience::Water => self.water,
+            Ambience::Highlands => self.highlands,
+            Ambience::Rain => self.rain,
+        }
+    }
+}
+
 impl<P: Payloads> Client<P> {
+    pub(crate) fn load_sounds(&self) {
+        let load = |file: &str| self.audio_mgr.gen_buffer(Buffer::File(get_asset_path(file)));
+
+        *self.sounds.write() = Sounds {
+            plains: load(Ambience::Plains.file()),
+            water: load(Ambience::Water.file()),
+            highlands: load(Ambience::Highlands.file()),
+            rain: load(Ambience::Rain.file()),
+            steps: ["voxygen/audio/effects/step_lth1.ogg", "voxygen/audio/effects/step_lth2.ogg"]
+                .iter()
+                .filter_map(|file| load(file))
+                .collect(),
+            events: SoundId::ALL
+                .iter()
+                .filter_map(|&sound| {
+                    let (file, duration) = sound.asset()?;
+                    load(file).map(|buffer| (sound, (buffer, duration)))
+                })
+                .collect(),
+        };
+    }
+
+    /// Play a sound the server asked for once, at `pos`. Sounds we don't know or couldn't load are left out.
+    pub(crate) fn play_sound(&self, sound: SoundId, pos: Vec3<f32>, volume: f32, pitch: f32) {
+        let (buffer, duration) = match self.sounds.read().events.get(&sound) {
+            Some(&event) => event,
+            None => return,
+        };
+        // A sound can't be played backwards or infinitely fast
+        if !pitch.is_finite() || pitch <= 0.0 {
+            return;
+        }
+        self.audio_mgr.gen_stream(Stream {
+            buffer,
+            group: Group::Sfx,
+            start_tick: *self.clock_tick_time.read(),
+            // Playing it slower makes it last longer
+            duration: Duration::from_float_secs(duration.as_float_secs() / pitch as f64),
+            volume,
+            pitch,
+            repeat: None,
+            positional: Some(Position {
+                relative: false,
+                pos,
+                vel: Vec3::zero(),
+            }),
+            fading: None,
+        });
+    }
+
+    // Pick an ambience for the player's surroundings
+    fn current_ambience(&self) -> Option<Ambience> {
+        let player_pos = *self.player_entity()?.read().pos();
+
+        let pos = player_pos.map(|e| e as f64);
+        let block_pos = terrain::pos_to_voxabs(pos);
+        let radius = Vec3::broadcast(WATER_SEARCH_RADIUS as f64);
+        let water_nearby = self
+            .blocks_in_aabb(pos - radius, pos + radius)
+            .into_iter()
+            .any(|(_, block)| block == Some(Block::WATER));
+
+        // Rain drowns out everything else, unless there's a roof over the player's head
+        let (weather, intensity) = self.weather();
+        let raining = weather.precipitation() == Some(Precipitation::Rain) && intensity > AUDIBLE_RAIN;
+        let sheltered = self
+            .chunk_mgr
+            .surface_height(Vec2::new(block_pos.x, block_pos.y))
+            .map_or(false, |height| height > block_pos.z + 1);
+
+        Some(if raining && !sheltered {
+            Ambience::Rain
+        } else if water_nearby {
+            Ambience::Water
+        } else if player_pos.z > HIGHLANDS_ALTITUDE {
+            Ambience::Highlands
+        } else {
+            Ambience::Plains
+        })
+    }
+
+    fn is_grounded(&self, pos: Vec3<f32>) -> bool {
+        self.is_solid_at(pos.map(|e| e as f64) - Vec3::new(0.0, 0.0, 0.1))
+            .unwrap_or(false)
+    }
+
     pub(crate) fn maintain_music(&self, _mgr: &mut Manager<Self>) {
-        //ambient sound
-        let start_tick = *self.next_ambient.read();
         let clock_tick_time = *self.clock_tick_time.read();
-        if start_tick < clock_tick_time {
-            //check player pos
-            let mut player_pos = None;
-            let mut player_vel = None;
-            if let Some(player_entity) = self.player_entity() {
-                let player = player_entity.read();
-                player_pos = Some(player.pos().map(|e| e as VoxAbs));
-                player_vel = Some(player.vel().map(|e| e as VoxAbs));
-            }
-            let mut is_water_nearby = false;
-            if let Some(player_pos) = player_pos {
-                let low = player_pos - Vec3::new(20, 20, 20);
-                let high = player_pos + Vec3::new(20, 20, 20);
-                if let Ok(volsample) = self.chunk_mgr.try_get_sample(low, high) {
-                    for (_, b) in volsample.iter() {
-                        if b == Block::WATER {
-                            is_water_nearby = true;
-                            break;
-                        }
-                    }
-                }
-            };
-
-            let duration;
-            let buffer;
-            if is_water_nearby {
-                duration = Duration::from_secs(90);
-                buffer = 1;
-            } else {
-                duration = Duration::from_secs(160);
-                buffer = 0;
+
+        // Ambience. Streams are restarted a little before they end, and when the surroundings change, crossfading
+        // with the previous stream.
+        let ambience = self.current_ambience();
+        let mut current = self.ambience.lock();
+        let changed = current.map(|(a, _)| a) != ambience;
+        if changed || *self.next_ambient.read() < clock_tick_time {
+            if let Some((_, stream)) = current.take() {
+                self.audio_mgr.fade_out(stream, clock_tick_time, AMBIENCE_FADE);
             }
-            self.audio_mgr.gen_stream(Stream {
-                buffer,
-                start_tick: clock_tick_time,
-                duration,
-                volume: 0.5,
-                repeat: None,
-                positional: None,
-                fading: None,
-            });
-            *self.next_ambient.write() = clock_tick_time + duration;
-        }
 
-        // entity sounds
-        let player_id = self.player.read().entity_uid;
-        let start_tick = *self.next_steps.read();
-        if start_tick < clock_tick_time {
-            let duration = Duration::from_millis(600);
-            let entities = self.entities.read();
-            for (id, e) in entities.iter() {
-                let pos;
-                let vel;
-                {
-                    let lock = e.read();
-                    pos = *lock.pos();
-                    vel = *lock.vel();
-                }
-                let mut positional = Some(Position {
-                    relative: false,
-                    pos,
-                    vel,
-                });
-                if let Some(player_id) = player_id {
-                    if player_id == *id {
-                        positional = Some(Position {
-                            relative: true,
-                            pos: Vec3::new(0.0, 0.0, 0.0),
-                            vel,
-                        });
-                    }
-                }
-                if vel.magnitude_squared() > 0.17 && vel.z.abs() < 3.0 {
-                    //some movement on ground
+            if let Some(ambience) = ambience {
+                let duration = ambience.duration();
+                let stream = self.sounds.read().ambience(ambience).and_then(|buffer| {
                     self.audio_mgr.gen_stream(Stream {
-                        buffer: 2,
+                        buffer,
+                        group: Group::Ambience,
                         start_tick: clock_tick_time,
                         duration,
-                        volume: 0.25,
+                        volume: 0.5,
+                        pitch: 1.0,
                         repeat: None,
-                        positional,
-                        fading: None,
-                    });
+                        positional: None,
+                        fading: Some(Fade {
+                            in_duration: AMBIENCE_FADE,
+                            out_duration: AMBIENCE_FADE,
+                        }),
+                    })
+                });
+                *current = stream.map(|stream| (ambience, stream));
+                *self.next_ambient.write() = clock_tick_time + duration - AMBIENCE_FADE;
+            }
+        }
+        drop(current);
+
+        // Footsteps for every entity that's moving along the ground, including the player
+        if *self.next_steps.read() < clock_tick_time {
+            let sounds = self.sounds.read();
+            let step = self.step_count.fetch_add(1, Ordering::Relaxed);
+            for entity in self.entities.read().values() {
+                let (pos, vel) = {
+                    let lock = entity.read();
+                    (*lock.pos(), *lock.vel())
+                };
+
+                if vel.magnitude_squared() > 0.17 && self.is_grounded(pos) {
+                    if let Some(&buffer) = sounds.steps.get(step % sounds.steps.len().max(1)) {
+                        self.audio_mgr.gen_stream(Stream {
+                            buffer,
+                            group: Group::Sfx,
+                            start_tick: clock_tick_time,
+                            duration: STEP_INTERVAL * 2,
+                            volume: 0.25,
+                            pitch: 1.0,
+                            repeat: None,
+                            positional: Some(Position {
+                                relative: false,
+                                pos,
+                                vel,
+                            }),
+                            fading: None,
+                        });
+                    }
                 }
             }
-            *self.next_steps.write() = clock_tick_time + duration / 2;
+            *self.next_steps.write() = clock_tick_time + STEP_INTERVAL;
         }
 
         self.audio_mgr.maintain(clock_tick_time);
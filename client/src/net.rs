@@ -1,5 +1,5 @@
 // Standard
-use std::{thread, time::Duration};
+use std::{sync::Arc, thread, time::Duration};
 
 // Library
 use parking_lot::Mutex;
@@ -7,11 +7,13 @@ use vek::*;
 
 // Project
 use common::{
+    ecs::phys::CollisionBox,
     terrain::Entity,
     util::{
         manager::Manager,
         msg::{ClientMsg, CompStore, ServerMsg, SessionKind},
         post::Incoming,
+        recording::Event,
     },
 };
 
@@ -24,7 +26,11 @@ const PING_FREQ: Duration = Duration::from_secs(2);
 
 impl<P: Payloads> Client<P> {
     pub(crate) fn handle_incoming(&self, mgr: &mut Manager<Self>) {
-        while let Ok(incoming) = self.postoffice.await_incoming() {
+        let postoffice = match self.postoffice() {
+            Some(postoffice) => postoffice,
+            None => return,
+        };
+        while let Ok(incoming) = postoffice.await_incoming() {
             match incoming {
                 // Sessions
                 Incoming::Session(session) => match session.kind {
@@ -37,7 +43,7 @@ impl<P: Payloads> Client<P> {
 
                                 loop {
                                     thread::sleep(PING_FREQ);
-                                    let _ = pb.send(ClientMsg::Ping);
+                                    let _ = pb.send_urgent(ClientMsg::Ping);
 
                                     match pb.recv_timeout(PING_TIMEOUT) {
                                         Ok(ServerMsg::Ping) => {},
@@ -47,40 +53,111 @@ impl<P: Payloads> Client<P> {
                             });
                         })
                     },
+                    // The server is ending the connection, and telling us why
+                    SessionKind::Disconnect => {
+                        if let Ok(ServerMsg::Disconnect { reason }) = session.postbox.recv_timeout(PING_TIMEOUT) {
+                            self.publish(ClientEvent::Kicked { reason });
+                        }
+                    },
                     _ => {},
                 },
 
                 // One-shot messages
-                Incoming::Msg(ServerMsg::ChatMsg { text }) => {
-                    self.events.lock().push(ClientEvent::RecvChatMsg { text })
+                Incoming::Msg(ServerMsg::ChatMsg { text }) => self.publish(ClientEvent::ChatReceived { text }),
+                Incoming::Msg(ServerMsg::Died { cause }) => self.player_died(cause),
+                Incoming::Msg(ServerMsg::CompUpdate { uid, .. }) if self.is_stale_uid(uid) => {},
+                Incoming::Msg(ServerMsg::CompUpdate {
+                    uid,
+                    store: CompStore::Inventory(inventory),
+                    ..
+                }) => {
+                    // Nobody else's inventory is ever sent, but check anyway
+                    if self.player().entity_uid() == Some(uid) {
+                        *self.inventory.write() = inventory;
+                    }
                 },
-                Incoming::Msg(ServerMsg::CompUpdate { uid, store }) => {
+                Incoming::Msg(ServerMsg::CompUpdate { uid, store, forced }) => {
                     let entity = self.entity(uid).unwrap_or_else(|| {
                         // Create an entity with default attributes if it doesn't already exist
                         self.add_entity(
                             uid,
                             Entity::new(Vec3::zero(), Vec3::zero(), Vec3::zero(), Vec2::unit_y()),
                         );
+                        self.publish(ClientEvent::EntitySpawned { uid });
                         // This shouldn't be able to fail since we just created the entity. If it
                         // does (because this is *technically* a data race)... then damn. Unlucky.
                         self.entity(uid).unwrap()
                     });
 
                     match store {
-                        CompStore::Pos(pos) => *entity.write().pos_mut() = pos,
-                        CompStore::Vel(vel) => *entity.write().vel_mut() = vel,
-                        CompStore::Dir(dir) => *entity.write().look_dir_mut() = dir,
+                        // A forced position is a teleport, so snap there and drop any momentum
+                        CompStore::Pos(pos) if forced => {
+                            let pos = pos.to_pos();
+                            let mut entity = entity.write();
+                            let is_player = self.player().entity_uid() == Some(uid);
+                            if is_player {
+                                self.player_moved(*entity.pos(), pos);
+                            }
+                            *entity.pos_mut() = pos;
+                            *entity.vel_mut() = Vec3::zero();
+                            if is_player {
+                                self.record(|r| r.record(&Event::Correction { pos, vel: Vec3::zero() }));
+                                self.player_placed();
+                            }
+                        },
+                        CompStore::Pos(pos) => *entity.write().pos_mut() = pos.to_pos(),
+                        CompStore::Vel(vel) => *entity.write().vel_mut() = vel.to_vel(),
+                        CompStore::Dir(dir) => entity.write().look_towards(dir),
+                        CompStore::MoveMode(mode) => *entity.write().move_mode_mut() = mode,
+                        CompStore::Character { name } => *entity.write().name_mut() = Some(name),
+                        CompStore::CollisionBox(size) => *entity.write().collision_box_mut() = CollisionBox(size),
+                        CompStore::HeldItem { item, .. } => *entity.write().held_item_mut() = item,
+                        CompStore::Item(item) => *entity.write().item_mut() = Some(item),
                         _ => {},
                     }
                 },
                 Incoming::Msg(ServerMsg::EntityDeleted { uid }) => {
                     self.remove_entity(uid);
                 },
+                Incoming::Msg(ServerMsg::EntitiesDeleted { uids }) => {
+                    for uid in uids {
+                        self.remove_entity(uid);
+                    }
+                },
 
                 Incoming::Msg(ServerMsg::TimeUpdate(time)) => {
                     *self.clock_tick_time.write() = time;
                     self.clock.write().reset();
                 },
+                Incoming::Msg(ServerMsg::PhysicsUpdate(physics)) => {
+                    *self.physics.write() = physics;
+                    self.record(|r| r.record(&Event::Physics(physics)));
+                },
+                Incoming::Msg(ServerMsg::BorderUpdate(border)) => *self.border.write() = border,
+
+                Incoming::Msg(ServerMsg::ChunkData { pos, data }) => self.recv_chunk(pos, &data),
+                Incoming::Msg(ServerMsg::BlockUpdate { pos, block }) => self.recv_block(pos, block),
+                Incoming::Msg(ServerMsg::CommandList { commands }) => *self.commands.write() = commands,
+                Incoming::Msg(ServerMsg::RecipeList { recipes }) => *self.recipes.write() = recipes,
+                Incoming::Msg(ServerMsg::ServerInfo(info)) => {
+                    *self.server_info.write() = Some(info.clone());
+                    self.publish(ClientEvent::ServerInfo { info });
+                },
+                Incoming::Msg(ServerMsg::CraftResult { recipe, result }) => {
+                    self.publish(ClientEvent::CraftResult { recipe, result })
+                },
+                Incoming::Msg(ServerMsg::SoundEvent {
+                    sound,
+                    pos,
+                    volume,
+                    pitch,
+                }) => self.play_sound(sound, pos, volume, pitch),
+                Incoming::Msg(ServerMsg::DigProgress { uid, pos, progress }) => {
+                    self.recv_dig_progress(uid, pos, progress)
+                },
+                Incoming::Msg(ServerMsg::WeatherUpdate { state, intensity, .. }) => {
+                    *self.weather.write() = (state, intensity)
+                },
 
                 Incoming::Msg(_) => {},
 
@@ -89,17 +166,23 @@ impl<P: Payloads> Client<P> {
             }
         }
 
-        *self.status.write() = ClientStatus::Disconnected;
+        // If we've already reconnected, the connection that ended was an old one
+        let current = self.postoffice().map_or(false, |current| Arc::ptr_eq(&postoffice, &current));
+        if *self.status() == ClientStatus::Connected && current {
+            self.set_status(ClientStatus::Disconnected);
+        }
     }
 
     /// Update the server with information about the player
     pub(crate) fn update_server(&self) {
         if let Some(player_entity) = self.player_entity() {
             let player_entity = player_entity.read();
-            let _ = self.postoffice.send_one(ClientMsg::PlayerEntityUpdate {
+            self.send(ClientMsg::PlayerEntityUpdate {
                 pos: *player_entity.pos(),
                 vel: *player_entity.vel(),
                 dir: *player_entity.look_dir(),
+                move_mode: player_entity.move_mode(),
+                jumping: player_entity.jump(),
             });
         }
     }
@@ -1,5 +1,11 @@
+// Library
+use vek::*;
+
 // Project
-use common::Uid;
+use common::{util::msg::ClientMsg, Uid};
+
+// Local
+use crate::{Client, ClientEvent, Payloads};
 
 pub struct Player {
     pub alias: String,
@@ -21,3 +27,47 @@ impl Player {
 
     pub fn entity_uid(&self) -> Option<Uid> { self.entity_uid }
 }
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Life {
+    Alive,
+    Dead,
+    // Asked to respawn, but not yet told where
+    Respawning,
+}
+
+impl<P: Payloads> Client<P> {
+    /// Whether the player is dead. They can't be controlled from when the server says they died until it says where
+    /// they've respawned.
+    pub fn is_dead(&self) -> bool { *self.life.read() != Life::Alive }
+
+    /// Ask to come back to life after dying. Asking again before the server answers is harmless, in case the first
+    /// request was lost.
+    pub fn respawn(&self) {
+        let mut life = self.life.write();
+        if *life != Life::Alive {
+            *life = Life::Respawning;
+            self.send(ClientMsg::Respawn);
+        }
+    }
+
+    /// The server says the player died. They stop trying to move, and stay dead whatever else is sent about them.
+    pub(crate) fn player_died(&self, cause: String) {
+        *self.life.write() = Life::Dead;
+        if let Some(player) = self.player_entity() {
+            let mut player = player.write();
+            *player.ctrl_acc_mut() = Vec3::zero();
+            *player.jump_mut() = false;
+        }
+        self.publish(ClientEvent::Died { cause });
+    }
+
+    /// The server put the player somewhere. If they asked to respawn, that's where they came back to life.
+    pub(crate) fn player_placed(&self) {
+        let mut life = self.life.write();
+        if *life == Life::Respawning {
+            *life = Life::Alive;
+            self.publish(ClientEvent::Respawned);
+        }
+    }
+}
@@ -1,8 +1,14 @@
 // Standard
 use std::time::Duration;
 
+// Library
+use vek::*;
+
 // Project
-use common::{physics::physics, util::manager::Manager};
+use common::{
+    physics::physics,
+    util::{manager::Manager, recording::Event},
+};
 
 // Local
 use crate::{Client, ClientStatus, Payloads};
@@ -11,14 +17,66 @@ impl<P: Payloads> Client<P> {
     pub(crate) fn tick(&self, dt: Duration, _mgr: &mut Manager<Self>) -> bool {
         let entities = self.entities.read();
 
-        // Physics tick
+        // Physics tick. The player stays put until the chunks around them have loaded, rather than falling through
+        // terrain that isn't there yet.
+        let held = if self.is_loading() {
+            self.player().entity_uid
+        } else {
+            None
+        };
+        let player_uid = self.player().entity_uid;
+        // The player's steps are recorded, if they're being recorded, unless they're held in place
+        let recorded = player_uid
+            .filter(|uid| Some(*uid) != held)
+            .and_then(|uid| entities.get(&uid));
         {
             // Take the physics lock to sync client and frontend updates
             let _ = self.take_phys_lock();
-            physics::tick(entities.iter(), &self.chunk_mgr, dt);
+            for entity in entities.values() {
+                entity.write().start_step();
+            }
+            if let Some(player) = recorded {
+                let player = player.read();
+                self.record(|r| r.before_step(&player));
+            }
+            physics::tick(
+                entities.iter().filter(|(uid, _)| Some(**uid) != held),
+                &self.chunk_mgr,
+                &self.physics(),
+                dt,
+            );
+            if let Some(player) = recorded {
+                let player = player.read();
+                self.record(|r| r.after_step(dt, &player));
+            }
+        }
+
+        // The server would only push the player back from beyond the border, so they stop at it instead
+        if let Some(player) = player_uid.and_then(|uid| entities.get(&uid)) {
+            let border = self.world_border();
+            let mut player = player.write();
+            if !border.contains(*player.pos()) {
+                *player.pos_mut() = border.clamp(*player.pos(), 0.0);
+                let vel = *player.vel();
+                *player.vel_mut() = Vec3::new(0.0, 0.0, vel.z);
+                self.record(|r| {
+                    r.record(&Event::Correction {
+                        pos: *player.pos(),
+                        vel: *player.vel(),
+                    })
+                });
+            }
         }
 
-        self.update_server();
+        // Other entities turn towards where the server last said they're looking
+        for entity in entities.values() {
+            entity.write().turn(dt.as_float_secs() as f32);
+        }
+
+        // The server ignores where the player is until then too, and while they're dead
+        if held.is_none() && !self.is_dead() {
+            self.update_server();
+        }
 
         *self.status() != ClientStatus::Disconnected
     }
@@ -1,5 +1,9 @@
 // Standard
-use std::{fs::File, io::prelude::*, path::Path, sync::Arc, u8};
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+    u8,
+};
 
 // Library
 use vek::*;
@@ -8,65 +12,37 @@ use vek::*;
 use common::{
     terrain::{
         self,
-        chunk::{Chunk, ChunkContainer, HeterogeneousData},
-        BlockLoader, Container, Key, PersState, VolCluster, VolOffs, VoxAbs,
+        chunk::{Block, Chunk, HeterogeneousData},
+        BlockLoader, Container, PersState, VolCluster, VolOffs, VoxAbs,
+    },
+    util::{
+        manager::Manager,
+        msg::{ClientMsg, LONG_TELEPORT, MAX_CHUNK_REQUEST},
+        recording::Event,
     },
-    util::manager::Manager,
 };
-use parking_lot::{Mutex, RwLock};
+use parking_lot::RwLock;
 
 // Local
-use crate::{world_crate, Client, Payloads, CHUNK_SIZE};
-
-pub(crate) fn gen_chunk<P: Send + Sync + 'static>(pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<P>>>>) {
-    let filename = pos.print() + ".dat";
-    let filepath = "./saves/".to_owned() + &(filename);
-    let path = Path::new(&filepath);
-    'load: {
-        if path.exists() {
-            let datfile = File::open(&filepath);
-            if let Ok(mut datfile) = datfile {
-                let mut content = Vec::<u8>::new();
-                datfile
-                    .read_to_end(&mut content)
-                    .expect(&format!("read of file {} failed", &filepath));
-                let cc = Chunk::from_bytes(&content);
-                if let Ok(cc) = cc {
-                    *con.lock() = Some(ChunkContainer::<P>::new(cc));
-                    break 'load;
-                }
-            }
-            warn!(
-                "there was a problem reading a chunk {} from file, it was newly generated",
-                pos
-            );
-        }
-        let c = world_crate::World::gen_chunk(pos.map(|e| e as i32));
-        *con.lock() = Some(ChunkContainer::<P>::new(c));
-    }
+use crate::{Client, ClientEvent, Payloads, CHUNK_SIZE};
+
+// Constants
+const CHUNK_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many of the chunks the player needs before they can move have loaded
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoadProgress {
+    pub loaded: usize,
+    pub required: usize,
 }
 
-pub(crate) fn drop_chunk<P: Send + Sync + 'static>(pos: Vec3<VolOffs>, con: Arc<ChunkContainer<P>>) {
-    let filename = pos.print() + ".dat";
-    let filepath = "./saves/".to_owned() + &(filename);
-    let path = Path::new(&filepath);
-    'load: {
-        if !path.exists() {
-            let mut data = con.data_mut();
-            let bytes = data.to_bytes();
-            if let Ok(bytes) = bytes {
-                let datfile = File::create(filepath);
-                if let Ok(mut datfile) = datfile {
-                    match datfile.write_all(&bytes) {
-                        Ok(_) => debug!("write to file: {}, bytes: {}", filename, bytes.len()),
-                        Err(_) => warn!("problem writing chunk {} to file, ignoring it", pos),
-                    };
-                } else {
-                    warn!("problem creating file for chunk {}, ignoring it", pos)
-                }
-            }
-        }
-    }
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Loading {
+    // Connected, but the server hasn't said where the player is yet
+    Joining,
+    // Waiting for the chunks around this position
+    At(Vec3<f32>),
+    Done,
 }
 
 impl<P: Payloads> Client<P> {
@@ -93,6 +69,149 @@ impl<P: Payloads> Client<P> {
             }))); // player in 5 sec
         }
         //TODO: maybe remove this from CHUNMGR, and just pass it here
-        self.chunk_mgr().maintain();
+        let unloaded = self.chunk_mgr().maintain();
+        if !unloaded.is_empty() {
+            // So that the server sends them again if we ask for them
+            self.send(ClientMsg::ForgetChunks { positions: unloaded });
+        }
+        self.apply_block_updates();
+
+        self.request_chunks();
+        self.check_loaded();
+    }
+
+    /// How close the player is to being able to move, or `None` if they can. After joining or being moved far, the
+    /// player is held in place until the chunks around them have arrived and had their payloads generated.
+    pub fn loading(&self) -> Option<LoadProgress> {
+        let pos = match *self.loading.read() {
+            Loading::Done => return None,
+            Loading::Joining => {
+                return Some(LoadProgress {
+                    loaded: 0,
+                    required: self.required_chunks(Vec3::zero()).len(),
+                });
+            },
+            Loading::At(pos) => pos,
+        };
+        let required = self.required_chunks(pos);
+        Some(LoadProgress {
+            loaded: required.iter().filter(|pos| self.is_ready(**pos)).count(),
+            required: required.len(),
+        })
+    }
+
+    pub(crate) fn is_loading(&self) -> bool { *self.loading.read() != Loading::Done }
+
+    /// Set how many chunks out from the player's own have to be loaded before they can move. With 1, that's the 3x3
+    /// chunks around them and the 3x3 beneath.
+    pub fn set_ready_radius(&self, radius: usize) { self.ready_radius.store(radius, Ordering::Relaxed); }
+
+    // The chunks around the one at `pos`, and those beneath them for the player to land on
+    fn required_chunks(&self, pos: Vec3<f32>) -> Vec<Vec3<VolOffs>> {
+        let center = terrain::voxabs_to_voloffs(pos.map(|e| e.floor() as VoxAbs), CHUNK_SIZE);
+        let radius = self.ready_radius.load(Ordering::Relaxed) as VolOffs;
+        let mut chunks = vec![];
+        for x in -radius..=radius {
+            for y in -radius..=radius {
+                for z in -1..=0 {
+                    chunks.push(center + Vec3::new(x, y, z));
+                }
+            }
+        }
+        chunks
+    }
+
+    /// The server put the player at `to`. If that's where they're joining, or far from `from`, they wait for the chunks
+    /// around them to load again.
+    pub(crate) fn player_moved(&self, from: Vec3<f32>, to: Vec3<f32>) {
+        let mut loading = self.loading.write();
+        if *loading != Loading::Done || from.distance(to) > LONG_TELEPORT {
+            *loading = Loading::At(to);
+        }
+    }
+
+    // Tell the server once the player can move. A headless player has nothing to wait for.
+    fn check_loaded(&self) {
+        let mut loading = self.loading.write();
+        let ready = match *loading {
+            Loading::Done => return,
+            Loading::Joining => self.player().entity_uid.is_none(),
+            Loading::At(pos) => self.required_chunks(pos).iter().all(|pos| self.is_ready(*pos)),
+        };
+        if ready {
+            *loading = Loading::Done;
+            self.send(ClientMsg::Ready);
+        }
+    }
+
+    // Whether the chunk at `pos` is loaded and its payload, which is its mesh for a client that draws it, has been
+    // generated. A payload being written to isn't ready yet.
+    fn is_ready(&self, pos: Vec3<VolOffs>) -> bool {
+        self.chunk_mgr()
+            .get_chunk(pos)
+            .and_then(|con| con.payload_try().map(|payload| payload.is_some()))
+            .unwrap_or(false)
+    }
+
+    /// Ask the server for chunks we're waiting on, re-requesting any that haven't arrived in time
+    fn request_chunks(&self) {
+        let now = Instant::now();
+        let mut requests = self.chunk_requests.lock();
+
+        requests.retain(|pos, _| self.chunk_mgr().is_pending(*pos));
+
+        let positions = requests
+            .iter_mut()
+            .filter(|(_, requested)| requested.map(|t| now - t > CHUNK_REQUEST_TIMEOUT).unwrap_or(true))
+            .map(|(pos, requested)| {
+                *requested = Some(now);
+                *pos
+            })
+            .collect::<Vec<_>>();
+
+        for batch in positions.chunks(MAX_CHUNK_REQUEST) {
+            self.send(ClientMsg::RequestChunks {
+                positions: batch.to_vec(),
+            });
+        }
+    }
+
+    /// Load a chunk we asked for, or replace one we already have with a version the server has changed since
+    pub(crate) fn recv_chunk(&self, pos: Vec3<VolOffs>, data: &[u8]) {
+        match Chunk::from_bytes(data) {
+            Ok(chunk) => {
+                self.record(|r| r.record(&Event::Chunk { pos, data: data.to_vec() }));
+                self.chunk_requests.lock().remove(&pos);
+                if self.chunk_mgr().is_pending(pos) {
+                    self.chunk_mgr().provide(pos, chunk);
+                    self.publish(ClientEvent::ChunkLoaded { pos });
+                } else if !self.chunk_mgr().replace(pos, chunk) {
+                    // We unloaded it while it was on its way, after telling the server we had forgotten it
+                    self.send(ClientMsg::ForgetChunks { positions: vec![pos] });
+                }
+            },
+            Err(_) => {
+                warn!("received invalid chunk data for {}, it will be requested again", pos);
+                // The server thinks we have it
+                self.send(ClientMsg::ForgetChunks { positions: vec![pos] });
+            },
+        }
+    }
+
+    /// Change a block the server says has changed. If its chunk is still on its way, the data it arrives with may
+    /// predate the change, so the change is made once it's loaded instead.
+    pub(crate) fn recv_block(&self, pos: Vec3<VoxAbs>, block: Block) {
+        self.record(|r| r.record(&Event::Block { pos, block }));
+        if !self.chunk_mgr().set_block(pos, block) {
+            self.block_updates.lock().push((pos, block));
+        }
+    }
+
+    // Make block changes that were waiting on their chunk, forgetting any whose chunk is no longer wanted
+    fn apply_block_updates(&self) {
+        let chunk_mgr = self.chunk_mgr();
+        self.block_updates.lock().retain(|(pos, block)| {
+            !chunk_mgr.set_block(*pos, *block) && chunk_mgr.is_pending(terrain::voxabs_to_voloffs(*pos, CHUNK_SIZE))
+        });
     }
 }
@@ -1,8 +1,15 @@
+// Library
+use vek::*;
+
 // Local
 use crate::audio::{Buffer, Stream};
 
 pub trait AudioGen {
     fn gen_stream(&self, id: u64, buffer: &Buffer, stream: &Stream);
+    /// Called when a playing stream's settings change, e.g. its volume while fading
+    fn update_stream(&self, id: u64, buffer: &Buffer, stream: &Stream);
+    /// Move the listener. `ori` is the direction the listener is facing.
+    fn set_listener(&self, pos: Vec3<f32>, ori: Vec3<f32>);
     fn gen_buffer(&self, id: u64, buffer: &Buffer);
     fn drop_stream(&self, id: u64, buffer: &Buffer, stream: &Stream);
     fn drop_buffer(&self, id: u64, buffer: &Buffer);
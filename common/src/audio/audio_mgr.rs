@@ -10,13 +10,41 @@ use std::{
 
 // Library
 use parking_lot::RwLock;
+use vek::*;
 
 // Local
-use crate::audio::{audio_gen::AudioGen, Buffer, Stream};
+use crate::audio::{audio_gen::AudioGen, Buffer, Fade, Group, Stream};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct GroupGain {
+    volume: f32,
+    muted: bool,
+}
+
+impl Default for GroupGain {
+    fn default() -> Self {
+        GroupGain {
+            volume: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl GroupGain {
+    fn gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume
+        }
+    }
+}
 
 pub struct AudioMgr<G: AudioGen> {
-    //pending: Arc<RwLock<HashMap<Vec3<VolOffs>, Arc<Mutex<Option<ChunkContainer<P>>>>>>>, // Mutex is only needed for compiler, we dont acces it in multiple threads
     streams: RwLock<HashMap<u64, Stream>>,
+    gains: RwLock<HashMap<Group, GroupGain>>,
+    // The tick passed to the last `maintain`, used to work out fading volumes outside of it
+    last_tick: RwLock<Duration>,
     buffers: RwLock<HashMap<u64, Buffer>>,
     next_stream_id: AtomicUsize,
     next_buffer_id: AtomicUsize,
@@ -27,6 +55,8 @@ impl<G: AudioGen> AudioMgr<G> {
     pub fn new(gen: Arc<G>) -> AudioMgr<G> {
         AudioMgr {
             streams: RwLock::new(HashMap::new()),
+            gains: RwLock::new(HashMap::new()),
+            last_tick: RwLock::new(Duration::from_secs(0)),
             buffers: RwLock::new(HashMap::new()),
             next_stream_id: AtomicUsize::new(0),
             next_buffer_id: AtomicUsize::new(0),
@@ -34,22 +64,83 @@ impl<G: AudioGen> AudioMgr<G> {
         }
     }
 
+    /// The gain applied to streams in a group, including the master volume
+    pub fn gain(&self, group: Group) -> f32 {
+        let gains = self.gains.read();
+        let gain = |group| gains.get(&group).cloned().unwrap_or_default().gain();
+        match group {
+            Group::Master => gain(Group::Master),
+            _ => gain(Group::Master) * gain(group),
+        }
+    }
+
+    pub fn group_volume(&self, group: Group) -> f32 { self.gains.read().get(&group).cloned().unwrap_or_default().volume }
+
+    pub fn is_muted(&self, group: Group) -> bool { self.gains.read().get(&group).cloned().unwrap_or_default().muted }
+
+    pub fn set_group_volume(&self, group: Group, volume: f32) {
+        self.gains
+            .write()
+            .entry(group)
+            .or_insert_with(GroupGain::default)
+            .volume = volume.max(0.0);
+        self.update_streams();
+    }
+
+    pub fn mute(&self, group: Group, muted: bool) {
+        self.gains
+            .write()
+            .entry(group)
+            .or_insert_with(GroupGain::default)
+            .muted = muted;
+        self.update_streams();
+    }
+
+    // The stream as it should be played at `tick`, with its fade and group gain applied to the volume
+    fn mixed(&self, stream: &Stream, tick: Duration) -> Stream {
+        Stream {
+            volume: stream.volume_at(tick) * self.gain(stream.group),
+            ..stream.clone()
+        }
+    }
+
+    // Push the current volume of every live stream to the generator
+    fn update_streams(&self) {
+        let tick = *self.last_tick.read();
+        let slock = self.streams.read();
+        let lock = self.buffers.read();
+        for (id, stream) in slock.iter() {
+            if let Some(buf) = lock.get(&stream.buffer) {
+                self.gen.update_stream(*id, buf, &self.mixed(stream, tick));
+            }
+        }
+    }
+
     pub fn gen_stream(&self, stream: Stream) -> Option<u64> {
         let mut slock = self.streams.write();
         let lock = self.buffers.read();
         let buf = lock.get(&stream.buffer);
         if let Some(buf) = buf {
             let id = self.next_stream_id.fetch_add(1, Ordering::Relaxed) as u64;
-            let p = self.gen.gen_stream(id, &buf, &stream);
+            self.gen.gen_stream(id, &buf, &self.mixed(&stream, stream.start_tick));
             slock.insert(id, stream);
             return Some(id);
         }
         None
     }
 
+    /// Register a buffer. Returns `None` if the buffer is a file that doesn't exist, in which case any sound using it
+    /// should be disabled.
     pub fn gen_buffer(&self, buffer: Buffer) -> Option<u64> {
-        let id = self.next_stream_id.fetch_add(1, Ordering::Relaxed) as u64;
-        let p = self.gen.gen_buffer(id, &buffer);
+        if let Buffer::File(ref path) = buffer {
+            if !path.exists() {
+                warn!("audio file {} not found, disabling the sound", path.display());
+                return None;
+            }
+        }
+
+        let id = self.next_buffer_id.fetch_add(1, Ordering::Relaxed) as u64;
+        self.gen.gen_buffer(id, &buffer);
         self.buffers.write().insert(id, buffer);
         Some(id)
     }
@@ -73,6 +164,9 @@ impl<G: AudioGen> AudioMgr<G> {
     pub fn set_stream(&self, id: u64, stream: Stream) {
         let mut lock = self.streams.write();
         if let Some(s) = lock.get_mut(&id) {
+            if let Some(buf) = self.buffers.read().get(&stream.buffer) {
+                self.gen.update_stream(id, buf, &self.mixed(&stream, *self.last_tick.read()));
+            }
             *s = stream;
         }
     }
@@ -84,31 +178,51 @@ impl<G: AudioGen> AudioMgr<G> {
         }
     }
 
+    pub fn set_listener(&self, pos: Vec3<f32>, ori: Vec3<f32>) { self.gen.set_listener(pos, ori); }
+
+    /// Fade a stream out over `duration` starting at `tick`, after which it's dropped
+    pub fn fade_out(&self, id: u64, tick: Duration, duration: Duration) {
+        let mut slock = self.streams.write();
+        if let Some(stream) = slock.get_mut(&id) {
+            let end = (tick + duration).min(stream.start_tick + stream.duration);
+            stream.duration = end.checked_sub(stream.start_tick).unwrap_or(Duration::from_secs(0));
+            stream.fading = Some(Fade {
+                in_duration: stream.fading.as_ref().map(|f| f.in_duration).unwrap_or(Duration::from_secs(0)),
+                out_duration: duration,
+            });
+        }
+    }
+
     // regually call this to handle old streams
     pub fn maintain(&self, tick: Duration) {
+        *self.last_tick.write() = tick;
         let mut slock = self.streams.write();
         let lock = self.buffers.read();
-        slock.retain(|id, mut stream| {
+        slock.retain(|id, stream| {
+            let buf = match lock.get(&stream.buffer) {
+                Some(buf) => buf,
+                None => return false,
+            };
+
             if stream.start_tick + stream.duration < tick {
-                let buf = lock.get(&stream.buffer);
-                if let Some(buf) = buf {
-                    self.gen.drop_stream(*id, &buf, &stream);
-                }
+                self.gen.drop_stream(*id, &buf, &stream);
                 return false;
             }
+
+            // Fading streams need their volume updating as they play
+            if stream.fading.is_some() {
+                self.gen.update_stream(*id, &buf, &self.mixed(stream, tick));
+            }
             true
         });
     }
 
     pub fn drop_stream(&self, id: u64) {
-        let mut slock = self.streams.read();
+        let mut slock = self.streams.write();
         let lock = self.buffers.read();
-        let stream = slock.get(&id);
-        if let Some(stream) = stream {
-            let buf = lock.get(&stream.buffer);
-            if let Some(buf) = buf {
+        if let Some(stream) = slock.remove(&id) {
+            if let Some(buf) = lock.get(&stream.buffer) {
                 self.gen.drop_stream(id, &buf, &stream);
-                self.streams.write().remove(&id);
             }
         }
     }
@@ -3,9 +3,12 @@ use vek::*;
 
 pub mod audio_gen;
 pub mod audio_mgr;
+pub mod sounds;
+#[cfg(test)]
+mod tests;
 
 // Reexports
-pub use crate::audio::{audio_gen::AudioGen, audio_mgr::AudioMgr};
+pub use crate::audio::{audio_gen::AudioGen, audio_mgr::AudioMgr, sounds::SoundId};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Position {
@@ -20,17 +23,51 @@ pub struct Fade {
     pub out_duration: Duration,
 }
 
+/// Streams are mixed in groups so that each kind of sound can have its volume set separately. The master group's
+/// volume applies on top of every other group.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Group {
+    Master,
+    Music,
+    Sfx,
+    Ambience,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Stream {
     pub buffer: u64,
+    pub group: Group,
     pub start_tick: Duration,
     pub duration: Duration,
     pub volume: f32,
+    /// How fast the sound plays, which also raises or lowers it. 1 is as recorded.
+    pub pitch: f32,
     pub repeat: Option<()>,
     pub positional: Option<Position>,
     pub fading: Option<Fade>,
 }
 
+impl Stream {
+    /// The stream's volume at `tick`, taking fading in and out into account
+    pub fn volume_at(&self, tick: Duration) -> f32 {
+        let mut volume = self.volume;
+        if let Some(fade) = &self.fading {
+            let since_start = tick.checked_sub(self.start_tick).unwrap_or(Duration::from_secs(0));
+            let until_end = (self.start_tick + self.duration)
+                .checked_sub(tick)
+                .unwrap_or(Duration::from_secs(0));
+
+            if since_start < fade.in_duration {
+                volume *= since_start.as_float_secs() as f32 / fade.in_duration.as_float_secs() as f32;
+            }
+            if until_end < fade.out_duration {
+                volume *= until_end.as_float_secs() as f32 / fade.out_duration.as_float_secs() as f32;
+            }
+        }
+        volume
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Buffer {
     File(PathBuf),
@@ -0,0 +1,30 @@
+// Standard
+use std::time::Duration;
+
+// Library
+use serde_derive::{Deserialize, Serialize};
+
+/// A sound the server can have clients play. Both sides agree on what each id means through the registry below, so
+/// only the id needs to be sent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SoundId(pub u16);
+
+impl SoundId {
+    pub const PLACE_BLOCK: SoundId = SoundId(0);
+    pub const PROJECTILE_HIT: SoundId = SoundId(1);
+    pub const GROWL: SoundId = SoundId(2);
+
+    /// Every sound in the registry
+    pub const ALL: [SoundId; 3] = [SoundId::PLACE_BLOCK, SoundId::PROJECTILE_HIT, SoundId::GROWL];
+
+    /// The sound's asset file and how long it plays for, or `None` for ids that aren't in the registry, which may come
+    /// from a newer server
+    pub fn asset(&self) -> Option<(&'static str, Duration)> {
+        match *self {
+            SoundId::PLACE_BLOCK => Some(("voxygen/audio/effects/place_block.ogg", Duration::from_millis(300))),
+            SoundId::PROJECTILE_HIT => Some(("voxygen/audio/effects/projectile_hit.ogg", Duration::from_millis(500))),
+            SoundId::GROWL => Some(("voxygen/audio/effects/growl.ogg", Duration::from_millis(1500))),
+            _ => None,
+        }
+    }
+}
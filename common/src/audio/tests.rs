@@ -0,0 +1,101 @@
+// Standard
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+// Library
+use parking_lot::Mutex;
+use vek::*;
+
+// Local
+use super::*;
+
+// Records the volume each stream was last played at
+#[derive(Default)]
+struct TestGen {
+    volumes: Mutex<HashMap<u64, f32>>,
+}
+
+impl AudioGen for TestGen {
+    fn gen_stream(&self, id: u64, _buffer: &Buffer, stream: &Stream) { self.volumes.lock().insert(id, stream.volume); }
+    fn update_stream(&self, id: u64, _buffer: &Buffer, stream: &Stream) {
+        self.volumes.lock().insert(id, stream.volume);
+    }
+    fn set_listener(&self, _pos: Vec3<f32>, _ori: Vec3<f32>) {}
+    fn drop_stream(&self, id: u64, _buffer: &Buffer, _stream: &Stream) { self.volumes.lock().remove(&id); }
+    fn gen_buffer(&self, _id: u64, _buffer: &Buffer) {}
+    fn drop_buffer(&self, _id: u64, _buffer: &Buffer) {}
+}
+
+fn stream(buffer: u64, group: Group, volume: f32) -> Stream {
+    Stream {
+        buffer,
+        group,
+        start_tick: Duration::from_secs(0),
+        duration: Duration::from_secs(10),
+        volume,
+        pitch: 1.0,
+        repeat: None,
+        positional: None,
+        fading: None,
+    }
+}
+
+fn volume(gen: &TestGen, id: u64) -> f32 { gen.volumes.lock()[&id] }
+
+#[test]
+fn group_gain_includes_master() {
+    let mgr = AudioMgr::new(Arc::new(TestGen::default()));
+    assert_eq!(mgr.gain(Group::Sfx), 1.0);
+
+    mgr.set_group_volume(Group::Master, 0.5);
+    mgr.set_group_volume(Group::Sfx, 0.5);
+    assert_eq!(mgr.gain(Group::Master), 0.5);
+    assert_eq!(mgr.gain(Group::Sfx), 0.25);
+    assert_eq!(mgr.gain(Group::Music), 0.5);
+
+    mgr.mute(Group::Music, true);
+    assert_eq!(mgr.gain(Group::Music), 0.0);
+    assert_eq!(mgr.group_volume(Group::Music), 1.0);
+    mgr.mute(Group::Music, false);
+    assert_eq!(mgr.gain(Group::Music), 0.5);
+
+    mgr.mute(Group::Master, true);
+    assert_eq!(mgr.gain(Group::Sfx), 0.0);
+}
+
+#[test]
+fn group_changes_reach_playing_streams() {
+    let gen = Arc::new(TestGen::default());
+    let mgr = AudioMgr::new(gen.clone());
+    let buffer = mgr.gen_buffer(Buffer::Raw(vec![])).unwrap();
+
+    let sfx = mgr.gen_stream(stream(buffer, Group::Sfx, 0.8)).unwrap();
+    let music = mgr.gen_stream(stream(buffer, Group::Music, 0.4)).unwrap();
+    assert_eq!(volume(&gen, sfx), 0.8);
+
+    mgr.set_group_volume(Group::Sfx, 0.5);
+    assert_eq!(volume(&gen, sfx), 0.4);
+    assert_eq!(volume(&gen, music), 0.4);
+
+    mgr.set_group_volume(Group::Master, 0.5);
+    assert_eq!(volume(&gen, sfx), 0.2);
+    assert_eq!(volume(&gen, music), 0.2);
+
+    mgr.mute(Group::Music, true);
+    assert_eq!(volume(&gen, music), 0.0);
+    assert_eq!(volume(&gen, sfx), 0.2);
+}
+
+#[test]
+fn fading_volume() {
+    let mut s = stream(0, Group::Ambience, 1.0);
+    s.fading = Some(Fade {
+        in_duration: Duration::from_secs(2),
+        out_duration: Duration::from_secs(4),
+    });
+
+    assert_eq!(s.volume_at(Duration::from_secs(0)), 0.0);
+    assert_eq!(s.volume_at(Duration::from_secs(1)), 0.5);
+    assert_eq!(s.volume_at(Duration::from_secs(5)), 1.0);
+    assert_eq!(s.volume_at(Duration::from_secs(8)), 0.5);
+    assert_eq!(s.volume_at(Duration::from_secs(12)), 0.0);
+}
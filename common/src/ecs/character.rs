@@ -7,6 +7,10 @@ use crate::util::msg::CompStore;
 // Local
 use super::NetComp;
 
+// Constants
+/// How much health characters start with, and are given back when they respawn
+pub const MAX_HEALTH: u32 = 100;
+
 // Character
 
 #[derive(Debug)]
@@ -28,9 +32,17 @@ impl NetComp for Character {
 
 // Health
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Health(pub u32);
 
+impl Health {
+    /// Take damage, without going below zero. Returns the health that's left.
+    pub fn damage(&mut self, amount: u32) -> u32 {
+        self.0 = self.0.saturating_sub(amount);
+        self.0
+    }
+}
+
 impl Component for Health {
     type Storage = VecStorage<Self>;
 }
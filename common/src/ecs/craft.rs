@@ -0,0 +1,107 @@
+// Standard
+use std::fmt;
+
+// Library
+use serde_derive::{Deserialize, Serialize};
+
+// Project
+use crate::terrain::chunk::Block;
+
+// Local
+use super::inventory::{Inventory, Item};
+
+// Constants
+/// How close, in blocks, a player has to be to a recipe's station to craft it
+pub const STATION_RANGE: f32 = 4.0;
+
+/// A block some recipes can only be crafted near
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Station {
+    Log,
+    Stone,
+    // Any shade of cobblestone
+    Cobblestone,
+    Gold,
+    Glowstone,
+}
+
+impl Station {
+    pub fn is(&self, block: Block) -> bool {
+        match self {
+            Station::Log => block == Block::LOG,
+            Station::Stone => block == Block::STONE,
+            Station::Cobblestone => {
+                block == Block::LIGHT_COBBLE || block == Block::MID_COBBLE || block == Block::DARK_COBBLE
+            },
+            Station::Gold => block == Block::GOLD,
+            Station::Glowstone => block == Block::GLOWSTONE,
+        }
+    }
+}
+
+/// A way of turning some items into another
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    /// What clients ask for the recipe by
+    pub id: String,
+    pub inputs: Vec<Item>,
+    pub output: Item,
+    /// The block that has to be within `STATION_RANGE` of the player, if any
+    #[serde(default)]
+    pub station: Option<Station>,
+}
+
+impl Recipe {
+    /// Craft the recipe `count` times over, returning everything that was made. Either all of it is crafted or, if
+    /// anything is missing or there's no room for the result, the inventory is left as it was.
+    pub fn craft(&self, inv: &mut Inventory, count: u32) -> Result<Item, CraftError> {
+        if count == 0 {
+            return Err(CraftError::InvalidCount);
+        }
+
+        let mut crafted = inv.clone();
+        for input in &self.inputs {
+            let amount = input.amount.checked_mul(count).ok_or(CraftError::InvalidCount)?;
+            crafted
+                .remove(input.kind, amount)
+                .map_err(|_| CraftError::MissingIngredients)?;
+        }
+        let output = Item::new(
+            self.output.kind,
+            self.output.amount.checked_mul(count).ok_or(CraftError::InvalidCount)?,
+        );
+        if crafted.insert(output).is_some() {
+            return Err(CraftError::NoRoom);
+        }
+
+        *inv = crafted;
+        Ok(output)
+    }
+}
+
+/// Why crafting failed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CraftError {
+    UnknownRecipe,
+    InvalidCount,
+    MissingIngredients,
+    NoStation,
+    NoRoom,
+}
+
+impl fmt::Display for CraftError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                CraftError::UnknownRecipe => "There's no such recipe",
+                CraftError::InvalidCount => "You can't craft that many",
+                CraftError::MissingIngredients => "You don't have everything it takes",
+                CraftError::NoStation => "You need to be nearer the right block",
+                CraftError::NoRoom => "There's no room for what it makes",
+            }
+        )
+    }
+}
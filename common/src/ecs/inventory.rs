@@ -0,0 +1,214 @@
+// Standard
+use std::fmt;
+
+// Library
+use serde_derive::{Deserialize, Serialize};
+use specs::{Component, VecStorage};
+
+// Project
+use crate::{terrain::chunk::Block, util::msg::CompStore};
+
+// Local
+use super::NetComp;
+
+// Constants
+pub const INVENTORY_SLOTS: usize = 24;
+/// The first slots of the inventory make up the hotbar, which holds what the player can pick to hold
+pub const HOTBAR_SLOTS: usize = 9;
+
+// Item
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ItemKind {
+    Stone,
+    Wood,
+    Apple,
+    Sword,
+}
+
+impl ItemKind {
+    /// How many of this kind of item fit in one slot
+    pub fn max_stack(&self) -> u32 {
+        match self {
+            ItemKind::Sword => 1,
+            _ => 64,
+        }
+    }
+
+    /// The block placing one of this kind of item puts down, if it can be placed at all
+    pub fn block(&self) -> Option<Block> {
+        match self {
+            ItemKind::Stone => Some(Block::STONE),
+            ItemKind::Wood => Some(Block::LOG),
+            _ => None,
+        }
+    }
+}
+
+/// A stack of items of the same kind
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub amount: u32,
+}
+
+impl Item {
+    pub fn new(kind: ItemKind, amount: u32) -> Item { Item { kind, amount } }
+}
+
+// Inventory
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InventoryError {
+    InvalidSlot,
+    EmptySlot,
+    InvalidAmount,
+    NotEnough,
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                InventoryError::InvalidSlot => "That slot doesn't exist",
+                InventoryError::EmptySlot => "That slot is empty",
+                InventoryError::InvalidAmount => "The amount must be at least 1",
+                InventoryError::NotEnough => "You don't have that many",
+            }
+        )
+    }
+}
+
+/// A fixed number of slots, each holding a stack of items or nothing
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Inventory {
+    slots: Vec<Option<Item>>,
+}
+
+impl Inventory {
+    pub fn new() -> Inventory { Inventory::with_slots(INVENTORY_SLOTS) }
+
+    pub fn with_slots(slots: usize) -> Inventory { Inventory { slots: vec![None; slots] } }
+
+    pub fn slots(&self) -> &[Option<Item>] { &self.slots }
+
+    pub fn get(&self, slot: usize) -> Option<Item> { self.slots.get(slot).cloned().unwrap_or(None) }
+
+    /// Add items, topping up existing stacks of the same kind before filling empty slots. Returns whatever didn't fit.
+    pub fn insert(&mut self, mut item: Item) -> Option<Item> {
+        let max = item.kind.max_stack();
+        for slot in self.slots.iter_mut() {
+            if let Some(stack) = slot {
+                if stack.kind == item.kind && stack.amount < max {
+                    let moved = item.amount.min(max - stack.amount);
+                    stack.amount += moved;
+                    item.amount -= moved;
+                }
+            }
+        }
+        for slot in self.slots.iter_mut() {
+            if item.amount == 0 {
+                break;
+            }
+            if slot.is_none() {
+                let moved = item.amount.min(max);
+                *slot = Some(Item::new(item.kind, moved));
+                item.amount -= moved;
+            }
+        }
+
+        if item.amount > 0 {
+            Some(item)
+        } else {
+            None
+        }
+    }
+
+    /// Take some of the items out of a slot. Taking more than the slot holds fails and leaves the slot untouched.
+    pub fn take(&mut self, slot: usize, amount: u32) -> Result<Item, InventoryError> {
+        let stack = self.slots.get_mut(slot).ok_or(InventoryError::InvalidSlot)?;
+        let item = stack.ok_or(InventoryError::EmptySlot)?;
+        if amount == 0 {
+            return Err(InventoryError::InvalidAmount);
+        } else if amount > item.amount {
+            return Err(InventoryError::NotEnough);
+        }
+
+        *stack = if amount == item.amount {
+            None
+        } else {
+            Some(Item::new(item.kind, item.amount - amount))
+        };
+        Ok(Item::new(item.kind, amount))
+    }
+
+    /// How many items of a kind there are across every slot
+    pub fn count(&self, kind: ItemKind) -> u32 {
+        self.slots
+            .iter()
+            .filter_map(|slot| *slot)
+            .filter(|item| item.kind == kind)
+            .map(|item| item.amount)
+            .sum()
+    }
+
+    /// Take items of a kind out of whichever slots hold them, starting from the last slot so that the hotbar is emptied
+    /// last. Taking more than there are fails and leaves every slot untouched.
+    pub fn remove(&mut self, kind: ItemKind, mut amount: u32) -> Result<(), InventoryError> {
+        if amount == 0 {
+            return Err(InventoryError::InvalidAmount);
+        } else if amount > self.count(kind) {
+            return Err(InventoryError::NotEnough);
+        }
+
+        for slot in self.slots.iter_mut().rev() {
+            if let Some(stack) = slot {
+                if stack.kind == kind {
+                    let moved = amount.min(stack.amount);
+                    stack.amount -= moved;
+                    amount -= moved;
+                }
+            }
+            if slot.map_or(false, |stack| stack.amount == 0) {
+                *slot = None;
+            }
+            if amount == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) -> Result<(), InventoryError> {
+        if a >= self.slots.len() || b >= self.slots.len() {
+            return Err(InventoryError::InvalidSlot);
+        }
+        self.slots.swap(a, b);
+        Ok(())
+    }
+}
+
+impl Component for Inventory {
+    type Storage = VecStorage<Self>;
+}
+
+impl NetComp for Inventory {
+    fn to_store(&self) -> Option<CompStore> { Some(CompStore::Inventory(self.clone())) }
+}
+
+/// The hotbar slot a player has picked, whose items they hold in their hand
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeldItem(pub usize);
+
+impl Component for HeldItem {
+    type Storage = VecStorage<Self>;
+}
+
+/// Something a client asks to do with its own inventory
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InventoryAction {
+    Drop { slot: usize, amount: u32 },
+    Swap { a: usize, b: usize },
+}
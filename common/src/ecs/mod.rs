@@ -1,13 +1,12 @@
 // Modules
 pub mod character;
+pub mod craft;
+pub mod inventory;
 pub mod net;
 pub mod phys;
 #[cfg(test)]
 mod tests;
 
-// Std
-use std::collections::HashMap;
-
 // Library
 use crate::util::msg::CompStore;
 use specs::{saveload::MarkedBuilder, Builder, Component, EntityBuilder, World};
@@ -15,25 +14,29 @@ use vek::*;
 
 // Local
 use self::{
-    character::{Character, Health},
+    character::{Character, Health, MAX_HEALTH},
+    inventory::{HeldItem, Inventory},
     net::{UidMarker, UidNode},
-    phys::{Dir, Pos, Vel},
+    phys::{CollisionBox, Dir, MoveMode, Pos, SpawnPoint, Vel},
 };
 
-const MAX_UIDS: u64 = 1_000_000_000;
-
 pub trait CreateUtil {
     fn create_character(&mut self, name: String) -> EntityBuilder;
 }
 
 impl CreateUtil for World {
     fn create_character(&mut self, name: String) -> EntityBuilder {
+        let spawn = self.res.try_fetch::<SpawnPoint>().map(|s| s.0).unwrap_or(Vec3::zero());
         self.create_entity()
-            .with(Pos(Vec3::zero()))
+            .with(Pos(spawn))
             .with(Vel(Vec3::zero()))
             .with(Dir(Vec2::zero()))
+            .with(MoveMode::Walk)
+            .with(CollisionBox::default())
             .with(Character { name })
-            .with(Health(100))
+            .with(Health(MAX_HEALTH))
+            .with(Inventory::new())
+            .with(HeldItem::default())
             .marked::<UidMarker>()
     }
 }
@@ -43,17 +46,18 @@ pub fn create_world() -> World {
 
     // Net
     world.register::<UidMarker>();
-    world.add_resource(UidNode {
-        range: 0..MAX_UIDS,
-        mapping: HashMap::new(),
-    });
+    world.add_resource(UidNode::new());
     // Phys
     world.register::<Pos>();
     world.register::<Vel>();
     world.register::<Dir>();
+    world.register::<MoveMode>();
+    world.register::<CollisionBox>();
     // Character
     world.register::<Character>();
     world.register::<Health>();
+    world.register::<Inventory>();
+    world.register::<HeldItem>();
 
     world
 }
@@ -1,5 +1,5 @@
 // Standard
-use std::{collections::HashMap, ops::Range};
+use std::collections::{HashMap, VecDeque};
 
 // Library
 use serde_derive::{Deserialize, Serialize};
@@ -9,12 +9,27 @@ use specs::{
     Component, DenseVecStorage, Entity, Join, ReadStorage,
 };
 
+// Local
+use crate::Uid;
+
+// Constants
+/// How many of a uid's low bits are its index. The rest are its generation, which goes up each time the index is
+/// reused, so that a uid held onto after its entity was deleted never refers to the entity that replaced it.
+pub const UID_INDEX_BITS: u32 = 40;
+const MAX_GENERATION: u64 = (1 << (64 - UID_INDEX_BITS)) - 1;
+
 // The marker components and marker allocator here are used
 // to map entities with a unique ID (UidMarker) that is consistent
 // between client and server. This is done because both client and
 // server may have their own entities that screw up allocation of
 // `Entity` ids.
 
+pub fn uid_index(uid: Uid) -> u64 { uid & ((1 << UID_INDEX_BITS) - 1) }
+
+pub fn uid_generation(uid: Uid) -> u64 { uid >> UID_INDEX_BITS }
+
+pub fn make_uid(index: u64, generation: u64) -> Uid { generation << UID_INDEX_BITS | uid_index(index) }
+
 // SyncMarker
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
@@ -41,21 +56,80 @@ impl Marker for UidMarker {
 
 // SyncNode
 
+/// What a `UidNode` needs to carry on where it left off after a restart, without handing out uids it already has
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UidState {
+    pub next: u64,
+    pub free: Vec<Uid>,
+}
+
+/// Hands out uids, reusing the indices of deleted entities with their generation bumped
 pub struct UidNode {
-    pub(crate) range: Range<u64>,
-    pub(crate) mapping: HashMap<u64, Entity>,
+    // The lowest index that has never been handed out
+    next: u64,
+    // Uids ready to be handed out again, oldest first so that an index stays unused for as long as possible
+    free: VecDeque<Uid>,
+    pub(crate) mapping: HashMap<Uid, Entity>,
+}
+
+impl UidNode {
+    pub fn new() -> Self { Self::from_state(UidState::default()) }
+
+    pub fn from_state(state: UidState) -> Self {
+        Self {
+            next: state.next,
+            free: state.free.into_iter().collect(),
+            mapping: HashMap::new(),
+        }
+    }
+
+    /// The state to restore after a restart. Entities alive now won't be then, so their uids count as free.
+    pub fn state(&self) -> UidState {
+        let mut free = self.free.iter().cloned().collect::<Vec<_>>();
+        free.extend(self.mapping.keys().filter_map(|uid| next_generation(*uid)));
+        UidState { next: self.next, free }
+    }
+
+    fn release(&mut self, uid: Uid) {
+        if let Some(uid) = next_generation(uid) {
+            self.free.push_back(uid);
+        }
+    }
+}
+
+impl Default for UidNode {
+    fn default() -> Self { Self::new() }
+}
+
+// The uid the next entity to get `uid`'s index gets, unless the index has been used up
+fn next_generation(uid: Uid) -> Option<Uid> {
+    match uid_generation(uid) {
+        MAX_GENERATION => None,
+        generation => Some(make_uid(uid_index(uid), generation + 1)),
+    }
 }
 
 impl MarkerAllocator<UidMarker> for UidNode {
     fn allocate(&mut self, entity: Entity, id: Option<u64>) -> UidMarker {
-        let id = id.unwrap_or_else(|| self.range.next().expect("Id range must be virtually endless"));
+        let id = id.or_else(|| self.free.pop_front()).unwrap_or_else(|| {
+            assert!(self.next < 1 << UID_INDEX_BITS, "Ran out of uids");
+            self.next += 1;
+            self.next - 1
+        });
+        self.next = self.next.max(uid_index(id) + 1);
         self.mapping.insert(id, entity);
         UidMarker { id, seq: 0 }
     }
 
     fn retrieve_entity_internal(&self, id: u64) -> Option<Entity> { self.mapping.get(&id).cloned() }
 
+    /// Forget deleted entities, freeing their uids to be reused
     fn maintain(&mut self, entities: &EntitiesRes, storage: &ReadStorage<UidMarker>) {
-        self.mapping = (&*entities, storage).join().map(|(e, m)| (m.id(), e)).collect();
+        let mapping = (&*entities, storage).join().map(|(e, m)| (m.id(), e)).collect::<HashMap<_, _>>();
+        let deleted = self.mapping.keys().filter(|uid| !mapping.contains_key(uid)).cloned().collect::<Vec<_>>();
+        for uid in deleted {
+            self.release(uid);
+        }
+        self.mapping = mapping;
     }
 }
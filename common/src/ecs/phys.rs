@@ -1,9 +1,10 @@
 // Library
+use serde_derive::{Deserialize, Serialize};
 use specs::{Component, VecStorage};
 use vek::*;
 
 // Project
-use crate::util::msg::CompStore;
+use crate::util::msg::{CompStore, NetPos, NetVel};
 
 // Local
 use super::NetComp;
@@ -18,7 +19,7 @@ impl Component for Pos {
 }
 
 impl NetComp for Pos {
-    fn to_store(&self) -> Option<CompStore> { Some(CompStore::Pos(self.0)) }
+    fn to_store(&self) -> Option<CompStore> { Some(CompStore::Pos(NetPos::from_pos(self.0))) }
 }
 
 // Vel
@@ -31,7 +32,7 @@ impl Component for Vel {
 }
 
 impl NetComp for Vel {
-    fn to_store(&self) -> Option<CompStore> { Some(CompStore::Vel(self.0)) }
+    fn to_store(&self) -> Option<CompStore> { Some(CompStore::Vel(NetVel::from_vel(self.0))) }
 }
 
 // Dir
@@ -46,3 +47,65 @@ impl Component for Dir {
 impl NetComp for Dir {
     fn to_store(&self) -> Option<CompStore> { Some(CompStore::Dir(self.0)) }
 }
+
+// MoveMode
+
+/// How an entity is moving, which decides how fast it goes (see `PhysicsConfig::speed_factor`). Crouching entities are
+/// also shorter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MoveMode {
+    Walk,
+    Sprint,
+    Crouch,
+}
+
+impl Default for MoveMode {
+    fn default() -> Self { MoveMode::Walk }
+}
+
+impl Component for MoveMode {
+    type Storage = VecStorage<Self>;
+}
+
+impl NetComp for MoveMode {
+    fn to_store(&self) -> Option<CompStore> { Some(CompStore::MoveMode(*self)) }
+}
+
+// CollisionBox
+
+/// How big an entity is standing up, in blocks across, deep and tall. The entity's position is the middle of the
+/// bottom of the box. Crouching entities are half as tall.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CollisionBox(pub Vec3<f32>);
+
+impl CollisionBox {
+    /// The size of the box while crouching
+    pub fn crouched(&self) -> Vec3<f32> { Vec3::new(self.0.x, self.0.y, self.0.z * 0.5) }
+
+    /// The size of the box while moving in `mode`
+    pub fn size(&self, mode: MoveMode) -> Vec3<f32> {
+        match mode {
+            MoveMode::Crouch => self.crouched(),
+            _ => self.0,
+        }
+    }
+}
+
+// Characters are this size
+impl Default for CollisionBox {
+    fn default() -> Self { CollisionBox(Vec3::new(0.9, 0.9, 1.8)) }
+}
+
+impl Component for CollisionBox {
+    type Storage = VecStorage<Self>;
+}
+
+impl NetComp for CollisionBox {
+    fn to_store(&self) -> Option<CompStore> { Some(CompStore::CollisionBox(self.0)) }
+}
+
+// SpawnPoint
+
+/// A resource for where new characters are created. Worlds without one create them at the origin.
+#[derive(Copy, Clone, Debug)]
+pub struct SpawnPoint(pub Vec3<f32>);
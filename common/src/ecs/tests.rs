@@ -1,9 +1,13 @@
 // Library
-use specs::{Builder, World};
+use specs::{
+    saveload::{Marker, MarkerAllocator},
+    Builder, World,
+};
 use vek::*;
 
 // Local
-use super::*;
+use super::{inventory::*, *};
+use crate::Uid;
 
 #[test]
 fn test_create_raw_ecs() {
@@ -36,3 +40,150 @@ fn test_create_world() {
 
     let _c = world.create_character("wollay".to_string()).build();
 }
+
+#[test]
+fn test_inventory_stacks_fill_before_empty_slots() {
+    let mut inv = Inventory::with_slots(3);
+    assert_eq!(inv.insert(Item::new(ItemKind::Stone, 10)), None);
+    assert_eq!(inv.insert(Item::new(ItemKind::Wood, 5)), None);
+    assert_eq!(inv.insert(Item::new(ItemKind::Stone, 60)), None);
+
+    // The first stone stack is topped up to the limit, and the rest overflows into the empty slot
+    assert_eq!(inv.get(0), Some(Item::new(ItemKind::Stone, 64)));
+    assert_eq!(inv.get(1), Some(Item::new(ItemKind::Wood, 5)));
+    assert_eq!(inv.get(2), Some(Item::new(ItemKind::Stone, 6)));
+}
+
+#[test]
+fn test_inventory_unstackable_items_take_a_slot_each() {
+    let mut inv = Inventory::with_slots(2);
+    assert_eq!(
+        inv.insert(Item::new(ItemKind::Sword, 3)),
+        Some(Item::new(ItemKind::Sword, 1))
+    );
+    assert_eq!(inv.get(0), Some(Item::new(ItemKind::Sword, 1)));
+    assert_eq!(inv.get(1), Some(Item::new(ItemKind::Sword, 1)));
+}
+
+#[test]
+fn test_inventory_taking_more_than_you_have_is_rejected() {
+    let mut inv = Inventory::with_slots(2);
+    inv.insert(Item::new(ItemKind::Apple, 4));
+
+    assert_eq!(inv.take(0, 5), Err(InventoryError::NotEnough));
+    assert_eq!(inv.take(0, 0), Err(InventoryError::InvalidAmount));
+    assert_eq!(inv.take(1, 1), Err(InventoryError::EmptySlot));
+    assert_eq!(inv.take(2, 1), Err(InventoryError::InvalidSlot));
+    assert_eq!(inv.get(0), Some(Item::new(ItemKind::Apple, 4)));
+
+    assert_eq!(inv.take(0, 3), Ok(Item::new(ItemKind::Apple, 3)));
+    assert_eq!(inv.take(0, 1), Ok(Item::new(ItemKind::Apple, 1)));
+    assert_eq!(inv.get(0), None);
+}
+
+#[test]
+fn test_inventory_swapping_checks_bounds() {
+    let mut inv = Inventory::with_slots(2);
+    inv.insert(Item::new(ItemKind::Wood, 1));
+
+    assert_eq!(inv.swap(0, 2), Err(InventoryError::InvalidSlot));
+    assert_eq!(inv.swap(0, 1), Ok(()));
+    assert_eq!(inv.slots(), &[None, Some(Item::new(ItemKind::Wood, 1))]);
+}
+
+fn sword_recipe() -> craft::Recipe {
+    craft::Recipe {
+        id: "sword".to_string(),
+        inputs: vec![Item::new(ItemKind::Stone, 2), Item::new(ItemKind::Wood, 1)],
+        output: Item::new(ItemKind::Sword, 1),
+        station: None,
+    }
+}
+
+#[test]
+fn test_crafting_takes_ingredients_from_any_slot() {
+    let mut inv = Inventory::with_slots(4);
+    inv.insert(Item::new(ItemKind::Stone, 3));
+    inv.insert(Item::new(ItemKind::Apple, 1));
+    inv.insert(Item::new(ItemKind::Wood, 1));
+
+    assert_eq!(sword_recipe().craft(&mut inv, 1), Ok(Item::new(ItemKind::Sword, 1)));
+    assert_eq!(inv.count(ItemKind::Stone), 1);
+    assert_eq!(inv.count(ItemKind::Wood), 0);
+    assert_eq!(inv.count(ItemKind::Sword), 1);
+    assert_eq!(inv.get(1), Some(Item::new(ItemKind::Apple, 1)));
+}
+
+#[test]
+fn test_failed_crafting_leaves_the_inventory_untouched() {
+    // Plenty of stone, but not enough wood for two swords
+    let mut inv = Inventory::with_slots(4);
+    inv.insert(Item::new(ItemKind::Stone, 10));
+    inv.insert(Item::new(ItemKind::Wood, 1));
+    let before = inv.clone();
+    assert_eq!(sword_recipe().craft(&mut inv, 2), Err(craft::CraftError::MissingIngredients));
+    assert_eq!(inv, before);
+    assert_eq!(sword_recipe().craft(&mut inv, 0), Err(craft::CraftError::InvalidCount));
+    assert_eq!(inv, before);
+
+    // Everything's there, but the swords have nowhere to go
+    let mut inv = Inventory::with_slots(3);
+    inv.insert(Item::new(ItemKind::Stone, 5));
+    inv.insert(Item::new(ItemKind::Wood, 2));
+    inv.insert(Item::new(ItemKind::Apple, 1));
+    let before = inv.clone();
+    assert_eq!(sword_recipe().craft(&mut inv, 2), Err(craft::CraftError::NoRoom));
+    assert_eq!(inv, before);
+}
+
+// Frees the uids of entities deleted since last time
+fn maintain_uids(world: &mut World) {
+    world.maintain();
+    world
+        .write_resource::<UidNode>()
+        .maintain(&world.entities(), &world.read_storage::<UidMarker>());
+}
+
+fn uid_of(world: &World, entity: specs::Entity) -> Uid { world.read_storage::<UidMarker>().get(entity).unwrap().id() }
+
+#[test]
+fn test_uids_are_recycled_with_a_new_generation() {
+    let mut world = create_world();
+    let first = world.create_character("first".to_string()).build();
+    let old = uid_of(&world, first);
+    world.delete_entity(first).unwrap();
+    maintain_uids(&mut world);
+
+    // The index is reused, but the full uid is different, and the old one doesn't refer to the new entity
+    let second = world.create_character("second".to_string()).build();
+    let new = uid_of(&world, second);
+    assert_ne!(old, new);
+    assert_eq!(net::uid_index(old), net::uid_index(new));
+    assert_eq!(net::uid_generation(new), net::uid_generation(old) + 1);
+    let node = world.read_resource::<UidNode>();
+    assert_eq!(node.retrieve_entity_internal(old), None);
+    assert_eq!(node.retrieve_entity_internal(new), Some(second));
+}
+
+#[test]
+fn test_uids_are_not_reused_after_a_restart() {
+    let mut world = create_world();
+    let mut handed_out = vec![];
+    for i in 0..4 {
+        let entity = world.create_character(format!("{}", i)).build();
+        handed_out.push(uid_of(&world, entity));
+        if i % 2 == 0 {
+            world.delete_entity(entity).unwrap();
+        }
+    }
+    maintain_uids(&mut world);
+    let state = world.read_resource::<UidNode>().state();
+
+    // Whether an entity was deleted before the restart or was still alive, its uid isn't handed out again
+    let mut world = create_world();
+    world.add_resource(UidNode::from_state(state));
+    for i in 0..8 {
+        let entity = world.create_character(format!("{}", i)).build();
+        assert!(!handed_out.contains(&uid_of(&world, entity)));
+    }
+}
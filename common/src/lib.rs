@@ -17,6 +17,7 @@ pub mod net;
 pub mod physics;
 pub mod terrain;
 pub mod util;
+pub mod weather;
 
 // Standard
 use std::path::{Path, PathBuf};
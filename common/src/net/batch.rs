@@ -0,0 +1,55 @@
+// Standard
+use std::mem;
+
+// Library
+use byteorder::{ByteOrder, LittleEndian};
+
+// Constants
+// Each message in a batch comes after its length
+pub const LEN_PREFIX: usize = 4;
+
+/// Messages waiting to go out together as one packet, so that a burst of small ones doesn't cost a packet and its
+/// frames each
+#[derive(Debug, Default)]
+pub struct Batch {
+    bytes: Vec<u8>,
+}
+
+impl Batch {
+    pub fn len(&self) -> usize { self.bytes.len() }
+
+    pub fn is_empty(&self) -> bool { self.bytes.is_empty() }
+
+    /// Whether `message` can be added without the batch growing past `limit` bytes. An empty batch takes anything.
+    pub fn fits(&self, message: &[u8], limit: usize) -> bool {
+        self.bytes.is_empty() || self.bytes.len() + LEN_PREFIX + message.len() <= limit
+    }
+
+    pub fn push(&mut self, message: &[u8]) {
+        let mut len = [0; LEN_PREFIX];
+        LittleEndian::write_u32(&mut len, message.len() as u32);
+        self.bytes.extend_from_slice(&len);
+        self.bytes.extend_from_slice(message);
+    }
+
+    /// Take the batched messages as the data of a packet, leaving the batch empty
+    pub fn take(&mut self) -> Vec<u8> { mem::replace(&mut self.bytes, Vec::new()) }
+}
+
+/// Split the data of a packet back into the messages that were batched into it, or `None` if it isn't a batch
+pub fn unbatch(mut data: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut messages = Vec::new();
+    while !data.is_empty() {
+        if data.len() < LEN_PREFIX {
+            return None;
+        }
+        let len = LittleEndian::read_u32(&data[..LEN_PREFIX]) as usize;
+        let rest = &data[LEN_PREFIX..];
+        if rest.len() < len {
+            return None;
+        }
+        messages.push(&rest[..len]);
+        data = &rest[len..];
+    }
+    Some(messages)
+}
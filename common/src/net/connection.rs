@@ -1,48 +1,117 @@
 // Standard
 use std::{
-    collections::{vec_deque::VecDeque, HashMap},
-    io::ErrorKind,
-    net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    collections::vec_deque::VecDeque,
+    io::{self, ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs},
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc, Arc,
+        mpsc, Arc, Weak,
     },
-    thread::{self, JoinHandle},
 };
 
 // Library
-use get_if_addrs::get_if_addrs;
+use mio::{net::TcpStream as MioTcpStream, Ready, Token};
 use parking_lot::{Mutex, RwLock};
 
+// Project
+use crate::util::logging::PACKET_TARGET;
+
 // Parent
 use super::{
-    packet::{Frame, FrameError, IncomingPacket, OutgoingPacket},
-    protocol::Protocol,
-    tcp::Tcp,
-    udp::Udp,
+    batch::{self, Batch, LEN_PREFIX},
+    crypto::{self, Encryption, Opener, Sealer},
+    packet::{FrameError, OutgoingPacket, PacketAssembler, MAX_PACKET_LEN},
+    poller::{Poller, Source},
+    sequence::{Sequence, Sequencer},
+    traffic,
+    udp_link::{Datagram, Transport, UdpLink, UdpPeer, UdpStatus},
     udpmgr::UdpMgr,
     ConnectionMessage, Error, Message,
 };
 
+// Constants
+// The most of a packet that goes into a single frame, so that big packets don't hold up more urgent ones for long
+const SPLIT_SIZE: u64 = 2000;
+// How far ahead of the socket frames are encoded. Anything more stays queued as packets, where priorities still apply.
+const WRITE_AHEAD: usize = 64 * 1024;
+const READ_CHUNK: usize = 16 * 1024;
+// Packets with this id hold a `ConnectionMessage` for the connection itself rather than a message for its user.
+// Everything else is numbered from 1.
+const CONTROL_ID: u64 = 0;
+const CONTROL_PRIO: usize = 0;
+const URGENT_PRIO: usize = 8;
+const DEFAULT_PRIO: usize = 16;
+
+// Whether a message is small enough for the other end to take it in a packet. A bigger one would only be thrown away
+// there, so it isn't sent at all.
+fn fits_in_packet(message: &[u8]) -> bool {
+    if (LEN_PREFIX + message.len()) as u64 > MAX_PACKET_LEN {
+        error!("Not sending a message of {} bytes, more than a packet can hold", message.len());
+        return false;
+    }
+    true
+}
+
 #[derive(Debug)]
 enum ConnectionError {
     Disconnected,
 }
 
+// Encoded frames on their way out, and how much of them the socket has taken so far
+#[derive(Debug, Default)]
+struct WriteBuf {
+    bytes: Vec<u8>,
+    pos: usize,
+    // Frames are sealed on their way into the buffer if the connection is encrypted
+    sealer: Option<Sealer>,
+    frames: u64,
+    writes: u64,
+}
+
+/// How a connection is getting on
+#[derive(Clone, Debug)]
+pub struct ConnectionStats {
+    pub udp: UdpStatus,
+    /// What messages sent with `send_unreliable` travel over at the moment
+    pub unreliable_transport: Transport,
+    /// Whether frames are encrypted
+    pub encrypted: bool,
+    /// How many frames have gone out over TCP
+    pub frames_sent: u64,
+    /// How many writes to the TCP socket it took to send them
+    pub writes: u64,
+}
+
+/// A connection to a remote postoffice. It doesn't have threads of its own: flushes write straight to the socket
+/// from whichever thread makes them, and one of the shared pollers takes over reading, and writing whatever the socket
+/// couldn't take yet.
+///
+/// Messages go over TCP, except for those sent with `send_unreliable`, which go over UDP once it's known to get
+/// through. See `UdpLink` for how that's worked out. The two ends agree on whether to encrypt before anything else;
+/// see `Encryption`.
+///
+/// Messages sent with `send` are batched, and only go out on the next `flush` or once there are enough of them to fill
+/// a packet. Those sent with `send_urgent` go out straight away.
 #[derive(Debug)]
 pub struct Connection<RM: Message> {
-    // sorted by prio and then chronically
-    tcp: Tcp,
+    stream: MioTcpStream,
+    poller: Arc<Poller>,
+    token: Token,
+    assembler: Mutex<PacketAssembler>,
+    // Incoming frames are opened before they're assembled if the connection is encrypted
+    opener: Mutex<Option<Opener>>,
+    write_buf: Mutex<WriteBuf>,
+    // Messages waiting for a flush
+    batch: Mutex<Batch>,
     udpmgr: Arc<UdpMgr>,
-    udp: Mutex<Option<Udp>>,
-    packet_in: Mutex<HashMap<u64, IncomingPacket>>,
+    udp: Mutex<UdpLink>,
+    // Needed to hand out to whatever routes datagrams here
+    this: Mutex<Weak<Connection<RM>>>,
+    sequencer: Mutex<Sequencer>,
+    // sorted by prio and then chronically
     packet_out: Mutex<Vec<VecDeque<OutgoingPacket>>>,
     packet_out_count: RwLock<u64>,
     running: AtomicBool,
-    send_thread: Mutex<Option<JoinHandle<()>>>,
-    recv_thread: Mutex<Option<JoinHandle<()>>>,
-    send_thread_udp: Mutex<Option<JoinHandle<()>>>,
-    recv_thread_udp: Mutex<Option<JoinHandle<()>>>,
     next_id: Mutex<u64>,
 
     // Message channel
@@ -51,99 +120,212 @@ pub struct Connection<RM: Message> {
 }
 
 impl<RM: Message> Connection<RM> {
-    pub fn new<A: ToSocketAddrs>(remote: &A, udpmgr: Arc<UdpMgr>) -> Result<Arc<Connection<RM>>, Error> {
-        Connection::new_internal(Tcp::new(&remote)?, udpmgr)
+    pub fn new<A: ToSocketAddrs>(
+        remote: &A,
+        udpmgr: Arc<UdpMgr>,
+        encryption: Encryption,
+    ) -> Result<Arc<Connection<RM>>, Error> {
+        Connection::new_stream(TcpStream::connect(remote)?, udpmgr, encryption)
     }
 
-    pub fn new_stream(stream: TcpStream, udpmgr: Arc<UdpMgr>) -> Result<Arc<Connection<RM>>, Error> {
-        Connection::new_internal(Tcp::new_stream(stream)?, udpmgr)
-    }
+    /// Set up a connection over `stream`, first agreeing with the other end on whether to encrypt. This waits for the
+    /// other end to say hello, so a slow one holds it up.
+    pub fn new_stream(
+        mut stream: TcpStream,
+        udpmgr: Arc<UdpMgr>,
+        encryption: Encryption,
+    ) -> Result<Arc<Connection<RM>>, Error> {
+        stream.set_nodelay(true)?;
+        let (sealer, opener, datagram_sealer) = match crypto::handshake(&mut stream, encryption)? {
+            Some((sealer, opener, datagram_sealer)) => (Some(sealer), Some(opener), Some(datagram_sealer)),
+            None => (None, None, None),
+        };
+        let stream = MioTcpStream::from_stream(stream)?;
 
-    fn new_internal(tcp: Tcp, udpmgr: Arc<UdpMgr>) -> Result<Arc<Connection<RM>>, Error> {
         let mut packet_out = Vec::new();
         for _i in 0..255 {
             packet_out.push(VecDeque::new());
         }
 
-        //let (error_sender, error_receiver) = mpsc::channel();
         let (message_sender, message_receiver) = mpsc::channel();
 
+        let poller = Poller::get();
         let m = Connection {
-            tcp,
+            stream,
+            token: poller.next_token(),
+            poller,
+            assembler: Mutex::new(PacketAssembler::new()),
+            opener: Mutex::new(opener),
+            write_buf: Mutex::new(WriteBuf {
+                sealer,
+                ..WriteBuf::default()
+            }),
+            batch: Mutex::new(Batch::default()),
+            udp: Mutex::new(UdpLink::new(udpmgr.config().clone(), datagram_sealer)),
+            this: Mutex::new(Weak::new()),
+            sequencer: Mutex::new(Sequencer::default()),
             udpmgr,
-            udp: Mutex::new(None),
-            packet_in: Mutex::new(HashMap::new()),
             packet_out_count: RwLock::new(0),
             packet_out: Mutex::new(packet_out),
             running: AtomicBool::new(true),
-            send_thread: Mutex::new(None),
-            recv_thread: Mutex::new(None),
-            send_thread_udp: Mutex::new(None),
-            recv_thread_udp: Mutex::new(None),
             next_id: Mutex::new(1),
             recvd_message_write: Mutex::new(message_sender),
             recvd_message_read: Mutex::new(message_receiver),
-            //error_write: Mutex::new(error_sender),
-            //error_read: Mutex::new(error_receiver),
         };
 
-        Ok(Arc::new(m))
+        let conn = Arc::new(m);
+        *conn.this.lock() = Arc::downgrade(&conn);
+        Ok(conn)
     }
 
-    pub fn open_udp<'b>(manager: &'b Arc<Connection<RM>>, listen: SocketAddr, sender: SocketAddr) {
-        if let Some(..) = *manager.udp.lock() {
-            panic!("not implemented");
+    /// Start receiving messages
+    pub fn start<'b>(manager: &'b Arc<Connection<RM>>) {
+        if let Err(e) = manager.poller.register(&manager.stream, manager.token, manager.clone()) {
+            manager.disconnect(e);
+            return;
         }
-        *manager.udp.lock() = Some(Udp::new(listen, sender).unwrap());
-        manager.send(ConnectionMessage::OpenedUdp { host: listen });
-
-        let m = manager.clone();
-        let mut rt = manager.recv_thread_udp.lock();
-        *rt = Some(thread::spawn(move || {
-            m.recv_worker_udp();
-        }));
-
-        let m = manager.clone();
-        let mut st = manager.send_thread_udp.lock();
-        *st = Some(thread::spawn(move || {
-            m.send_worker_udp();
-        }));
-    }
 
-    pub fn start<'b>(manager: &'b Arc<Connection<RM>>) {
-        let m = manager.clone();
-        let mut rt = manager.recv_thread.lock();
-        *rt = Some(thread::spawn(move || {
-            m.recv_worker();
-        }));
-
-        let m = manager.clone();
-        let mut st = manager.send_thread.lock();
-        *st = Some(thread::spawn(move || {
-            m.send_worker();
-        }));
+        // Whoever accepted the connection offers UDP, if they're listening for it
+        if let (Some(endpoint), Some(peer)) = (manager.udpmgr.endpoint(), manager.udp_peer()) {
+            if let Ok(addr) = endpoint.local_addr() {
+                let token = manager.udp.lock().offer(endpoint.clone(), peer);
+                manager.send_control(ConnectionMessage::OfferUdp {
+                    token,
+                    port: addr.port(),
+                });
+            }
+        }
     }
 
     pub fn stop<'b>(manager: &'b Arc<Connection<RM>>) {
-        let m = manager.clone();
-        m.running.store(false, Ordering::Relaxed);
-        let _ = m.recvd_message_write.lock().send(Err(ConnectionError::Disconnected));
-        // non blocking stop for now
+        // Get out whatever the socket will take of what's still queued, such as a goodbye to the other end
+        manager.close_batch(&mut manager.batch.lock());
+        let _ = manager.write_out();
+
+        manager.running.store(false, Ordering::Relaxed);
+        let _ = manager.recvd_message_write.lock().send(Err(ConnectionError::Disconnected));
+        manager.poller.deregister(&manager.stream, manager.token);
+        manager.udp.lock().close();
+        let _ = manager.stream.shutdown(Shutdown::Both);
+    }
+
+    /// Send a message along with everything else sent before the next `flush`
+    pub fn send<M: Message>(&self, message: M) { self.batch(&message.to_bytes().unwrap()); }
+
+    /// Send a message straight away, ahead of any that are batched or queued
+    pub fn send_urgent<M: Message>(&self, message: M) {
+        let bytes = message.to_bytes().unwrap();
+        if !fits_in_packet(&bytes) {
+            return;
+        }
+        let mut batch = Batch::default();
+        batch.push(&bytes);
+        self.queue(OutgoingPacket::new(batch.take(), self.gen_id()), URGENT_PRIO);
+    }
+
+    /// Send the messages batched since the last flush
+    pub fn flush(&self) {
+        self.close_batch(&mut self.batch.lock());
+        self.write_queued();
     }
 
-    pub fn send<M: Message>(&self, message: M) {
+    /// Send a message that may be lost, duplicated or overtaken by later ones. It goes over UDP while that's working,
+    /// and over TCP otherwise.
+    pub fn send_unreliable<M: Message>(&self, message: M) {
+        let bytes = message.to_bytes().unwrap();
+        let sent = self.udp.lock().send(None, &bytes);
+        if !sent {
+            self.batch(&bytes);
+        }
+    }
+
+    /// Send a message on `channel` that's only delivered if nothing sent on the channel after it has been delivered
+    /// already, so that what's delivered is always the newest. Otherwise it's like `send_unreliable`.
+    pub fn send_sequenced<M: Message>(&self, channel: u16, message: M) {
+        let bytes = message.to_bytes().unwrap();
+        let sequence = self.sequencer.lock().next(channel);
+        let sent = self.udp.lock().send(Some(sequence), &bytes);
+        if !sent {
+            self.send_control(ConnectionMessage::Sequenced {
+                channel,
+                seq: sequence.seq,
+                data: bytes,
+            });
+        }
+    }
+
+    fn gen_id(&self) -> u64 {
         let mut id = self.next_id.lock();
-        self.packet_out.lock()[16].push_back(OutgoingPacket::new(message.to_bytes().unwrap(), *id));
         *id += 1;
-        let mut p = self.packet_out_count.write();
-        *p += 1;
-        let mut rt = self.send_thread.lock();
-        if let Some(cb) = rt.as_mut() {
-            //trigger sending
-            cb.thread().unpark();
+        *id - 1
+    }
+
+    fn send_control(&self, message: ConnectionMessage) {
+        self.queue(OutgoingPacket::new(message.to_bytes().unwrap(), CONTROL_ID), CONTROL_PRIO);
+    }
+
+    // Add a message to the batch, sending the batch first if the message would take it past a packet's worth, and
+    // after if the message fills it
+    fn batch(&self, bytes: &[u8]) {
+        if !fits_in_packet(bytes) {
+            return;
+        }
+        let mut batch = self.batch.lock();
+        let mut full = false;
+        if !batch.fits(bytes, SPLIT_SIZE as usize) {
+            self.close_batch(&mut batch);
+            full = true;
+        }
+        batch.push(bytes);
+        if batch.len() >= SPLIT_SIZE as usize {
+            self.close_batch(&mut batch);
+            full = true;
+        }
+        drop(batch);
+
+        if full {
+            self.write_queued();
+        }
+    }
+
+    // Queue what's batched as a packet of its own. The batch stays locked until it's queued, so that batches go out in
+    // the order they were made.
+    fn close_batch(&self, batch: &mut Batch) {
+        if !batch.is_empty() {
+            self.enqueue(OutgoingPacket::new(batch.take(), self.gen_id()), DEFAULT_PRIO);
+        }
+    }
+
+    fn queue(&self, packet: OutgoingPacket, prio: usize) {
+        self.enqueue(packet, prio);
+        self.write_queued();
+    }
+
+    fn enqueue(&self, packet: OutgoingPacket, prio: usize) {
+        self.packet_out.lock()[prio].push_back(packet);
+        *self.packet_out_count.write() += 1;
+    }
+
+    fn write_queued(&self) {
+        if let Err(e) = self.write_out() {
+            self.disconnect(e);
         }
     }
 
+    pub fn stats(&self) -> ConnectionStats {
+        let udp = self.udp.lock().status();
+        let write_buf = self.write_buf.lock();
+        ConnectionStats {
+            udp,
+            unreliable_transport: udp.transport(),
+            encrypted: self.is_encrypted(),
+            frames_sent: write_buf.frames,
+            writes: write_buf.writes,
+        }
+    }
+
+    fn is_encrypted(&self) -> bool { self.opener.lock().is_some() }
+
     pub fn try_recv(&self) -> Result<RM, ()> {
         match self.recvd_message_read.lock().try_recv() {
             Ok(Ok(msg)) => Ok(msg),
@@ -158,223 +340,224 @@ impl<RM: Message> Connection<RM> {
         }
     }
 
-    fn send_worker(&self) {
-        'thread: loop {
-            if !self.running.load(Ordering::Relaxed) {
-                break;
+    // Write queued packets to the socket until they run out or it would block. In the latter case the poller calls
+    // this again once there's room.
+    fn write_out(&self) -> io::Result<()> {
+        if !self.running.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let mut write_buf = self.write_buf.lock();
+        let buf = &mut *write_buf;
+        loop {
+            if buf.bytes.len() - buf.pos < WRITE_AHEAD {
+                self.encode_frames(buf);
             }
-            if *self.packet_out_count.read() == 0 {
-                thread::park();
-                continue;
+            if buf.pos == buf.bytes.len() {
+                return Ok(());
             }
-            // find next package
-            let mut packets = self.packet_out.lock();
-            for i in 0..255 {
-                if packets[i].len() != 0 {
-                    // build part
-                    const SPLIT_SIZE: u64 = 2000;
-                    match packets[i][0].generate_frame(SPLIT_SIZE) {
-                        Ok(frame) => {
-                            // send it
-                            match self.tcp.send(frame) {
-                                Ok(_) => {},
-                                Err(e) => match e {
-                                    Error::NetworkErr(io_err) => match io_err.kind() {
-                                        /* Shut down the thread */
-                                        ErrorKind::ConnectionReset
-                                        | ErrorKind::ConnectionRefused
-                                        | ErrorKind::ConnectionAborted => {
-                                            //Close recv thread, since connection has been severed
-                                            let recvd_message_write = self.recvd_message_write.lock();
-                                            recvd_message_write
-                                                .send(Err(ConnectionError::Disconnected))
-                                                .unwrap_or_else(|e| eprintln!("send_worker> {:?}", e));
-                                            break 'thread;
-                                        },
-                                        e => panic!("{:?}", e), /* Panic on any IOError we aren't expecting here*/
-                                    },
-                                    _ => { /* Cannot (De)Serialize packet, discard */ },
-                                },
-                            }
-                        },
-                        Err(FrameError::SendDone) => {
-                            packets[i].pop_front();
-                            let mut p = self.packet_out_count.write();
-                            *p -= 1;
-                        },
-                    }
 
-                    break;
-                }
+            match (&self.stream).write(&buf.bytes[buf.pos..]) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    traffic::add_sent(n);
+                    buf.pos += n;
+                    buf.writes += 1;
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
             }
         }
     }
 
-    fn recv_worker(&self) {
-        'thread: loop {
-            if !self.running.load(Ordering::Relaxed) {
-                break;
-            }
-            let frame = self.tcp.recv();
-            match frame {
+    // Top up the write buffer with frames from the most urgent packets
+    fn encode_frames(&self, buf: &mut WriteBuf) {
+        buf.bytes.drain(..buf.pos);
+        buf.pos = 0;
+
+        let mut packets = self.packet_out.lock();
+        while buf.bytes.len() < WRITE_AHEAD {
+            let queue = match packets.iter_mut().find(|queue| !queue.is_empty()) {
+                Some(queue) => queue,
+                None => break,
+            };
+            match queue[0].generate_frame(SPLIT_SIZE) {
                 Ok(frame) => {
-                    match frame {
-                        Frame::Header { id, .. } => {
-                            let msg = IncomingPacket::new(frame);
-                            let mut packets = self.packet_in.lock();
-                            packets.insert(id, msg);
-                        },
-                        Frame::Data { id, .. } => {
-                            let mut packets = self.packet_in.lock();
-                            let packet = packets.get_mut(&id);
-                            if packet.unwrap().load_data_frame(frame) {
-                                //convert
-                                let packet = packets.get_mut(&id);
-                                let data = packet.unwrap().data();
-                                debug!("received packet: {:?}", &data);
-
-                                let recvd_message_write = self.recvd_message_write.lock();
-                                recvd_message_write.send(Ok(RM::from_bytes(data).unwrap())).unwrap();
-                            }
+                    match &mut buf.sealer {
+                        Some(sealer) => {
+                            let mut encoded = Vec::new();
+                            frame.encode(&mut encoded);
+                            sealer.seal(&encoded, &mut buf.bytes);
                         },
+                        None => frame.encode(&mut buf.bytes),
                     }
+                    buf.frames += 1;
                 },
-                Err(e) => {
-                    error!("Net Error {:?}", &e);
-
-                    match e {
-                        Error::NetworkErr(io_err) => match io_err.kind() {
-                            ErrorKind::ConnectionReset //Connection reset by remote server
-                            | ErrorKind::ConnectionAborted //Connection aborted (terminated) by remote server
-                            | ErrorKind::ConnectionRefused //Connection refused by remote server
-                            => {
-                                //Close recv thread, since connection has been severed
-                                let recvd_message_write = self.recvd_message_write.lock();
-                                recvd_message_write
-                                    .send(Err(ConnectionError::Disconnected))
-                                    .unwrap_or_else(|e| eprintln!("recv_worker> {:?}", e));
-                                break 'thread;
-                            },
-                            e => {
-                                // Any other IO error
-                                // Panic until we find a suitable way to handle these
-                            	panic!("{:?}", e)
-                            },
-                        },
-
-                        _ => { /* Cannot(De)Serialize, discard packet */ },
-                    }
+                Err(FrameError::SendDone) => {
+                    queue.pop_front();
+                    let mut p = self.packet_out_count.write();
+                    *p -= 1;
                 },
             }
         }
     }
 
-    fn send_worker_udp(&self) {
+    // Read everything the socket has, delivering each packet that's complete
+    fn read(&self) -> io::Result<()> {
+        let mut assembler = self.assembler.lock();
+        let mut buf = Vec::new();
+        let mut chunk = [0; READ_CHUNK];
+        let mut result = Ok(());
         loop {
-            if !self.running.load(Ordering::Relaxed) {
-                break;
+            match (&self.stream).read(&mut chunk) {
+                Ok(0) => {
+                    result = Err(ErrorKind::UnexpectedEof.into());
+                    break;
+                },
+                Ok(n) => {
+                    traffic::add_received(n);
+                    buf.extend_from_slice(&chunk[..n]);
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                },
             }
-            if *self.packet_out_count.read() == 0 {
-                thread::park();
-                continue;
+        }
+
+        // Frames that arrived before the connection closed still count, but nothing that was tampered with does
+        let frames = match &mut *self.opener.lock() {
+            Some(opener) => opener.open(&buf)?,
+            None => buf,
+        };
+        let packets = assembler
+            .push_bytes(&frames)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("Stream stopped making up frames: {:?}", e)))?;
+        for (id, data) in packets {
+            if id == CONTROL_ID {
+                self.handle_control(data);
+            } else {
+                self.deliver_batch(&data);
             }
-            // find next package
-            let mut packets = self.packet_out.lock();
-            for i in 0..255 {
-                if packets[i].len() != 0 {
-                    // build part
-                    const SPLIT_SIZE: u64 = 2000;
-                    match packets[i][0].generate_frame(SPLIT_SIZE) {
-                        Ok(frame) => {
-                            // send it
-                            let mut udp = self.udp.lock();
-                            udp.as_mut().unwrap().send(frame).unwrap();
-                        },
-                        Err(FrameError::SendDone) => {
-                            packets[i].pop_front();
-                            let mut p = self.packet_out_count.write();
-                            *p -= 1;
-                        },
-                    }
+        }
 
-                    break;
+        result
+    }
+
+    // Pass a message on to whoever is receiving, unless it isn't valid
+    fn deliver(&self, data: &[u8]) {
+        debug!(target: PACKET_TARGET, "received message: {:?}", data);
+        match RM::from_bytes(data) {
+            Ok(msg) => {
+                let _ = self.recvd_message_write.lock().send(Ok(msg));
+            },
+            Err(e) => {
+                warn!("Discarding a packet that isn't a valid message: {:?}", e);
+                traffic::add_bad_packet();
+            },
+        }
+    }
+
+    // Deliver each of the messages batched into a packet
+    fn deliver_batch(&self, data: &[u8]) {
+        match batch::unbatch(data) {
+            Some(messages) => {
+                for message in messages {
+                    self.deliver(message);
                 }
-            }
+            },
+            None => {
+                warn!("Discarding a packet that isn't a batch of messages");
+                traffic::add_bad_packet();
+            },
         }
     }
 
-    fn recv_worker_udp(&self) {
-        'thread: loop {
-            if !self.running.load(Ordering::Relaxed) {
-                break;
-            }
-            let mut udp = self.udp.lock();
-            let frame = udp.as_mut().unwrap().recv();
-            match frame {
-                Ok(frame) => {
-                    match frame {
-                        Frame::Header { id, .. } => {
-                            let msg = IncomingPacket::new(frame);
-                            let mut packets = self.packet_in.lock();
-                            packets.insert(id, msg);
-                        },
-                        Frame::Data { id, .. } => {
-                            let mut packets = self.packet_in.lock();
-                            let packet = packets.get_mut(&id);
-                            if packet.unwrap().load_data_frame(frame) {
-                                //convert
-                                let packet = packets.get_mut(&id);
-                                let data = packet.unwrap().data();
-                                debug!("received packet: {:?}", &data);
-
-                                let recvd_message_write = self.recvd_message_write.lock();
-                                recvd_message_write.send(Ok(RM::from_bytes(data).unwrap())).unwrap();
-                            }
-                        },
+    fn handle_control(&self, data: Vec<u8>) {
+        match ConnectionMessage::from_bytes(&data) {
+            Ok(ConnectionMessage::OfferUdp { token, port }) => {
+                let remote = self.stream.peer_addr().map(|addr| SocketAddr::new(addr.ip(), port));
+                if let (Ok(remote), Some(peer)) = (remote, self.udp_peer()) {
+                    if let Err(e) = self.udp.lock().accept(token, remote, peer) {
+                        warn!("Could not open a UDP socket, so everything stays on TCP: {}", e);
                     }
-                },
-                Err(e) => {
-                    error!("Net Error {:?}", &e);
-
-                    match e {
-                        Error::NetworkErr(io_err) => match io_err.kind() {
-                            ErrorKind::ConnectionReset //Connection reset by remote server
-                            | ErrorKind::ConnectionAborted //Connection aborted (terminated) by remote server
-                            | ErrorKind::ConnectionRefused //Connection refused by remote server
-                            => {
-                                //Close recv thread, since connection has been severed
-                                let recvd_message_write = self.recvd_message_write.lock();
-                                recvd_message_write
-                                    .send(Err(ConnectionError::Disconnected))
-                                    .unwrap_or_else(|e| eprintln!("recv_worker_udp> {:?}", e));
-                                break 'thread;
-                            },
-                            e => {
-                                // Any other IO error
-                                // Panic until we find a suitable way to handle these
-                            	panic!("{:?}", e)
-                            },
-                        },
+                }
+            },
+            Ok(ConnectionMessage::Sequenced { channel, seq, data }) => {
+                self.deliver_sequenced(Sequence { channel, seq }, data)
+            },
+            Ok(msg) => debug!("Ignoring connection message {:?}", msg),
+            Err(e) => {
+                warn!("Discarding a connection message that isn't valid: {:?}", e);
+                traffic::add_bad_packet();
+            },
+        }
+    }
 
-                        _ => { /* Cannot(De)Serialize, discard packet */ },
-                    }
-                },
-            }
+    // Deliver a message unless something sent after it on its channel has been delivered already
+    fn deliver_sequenced(&self, sequence: Sequence, data: Vec<u8>) {
+        // Held while delivering, so that TCP and UDP can't race to deliver messages in the wrong order
+        let mut sequencer = self.sequencer.lock();
+        if sequencer.accept(sequence) {
+            self.deliver(&data);
+        } else {
+            debug!("Dropping message {} on channel {}, which is out of date", sequence.seq, sequence.channel);
         }
     }
 
-    #[allow(dead_code)]
-    fn bind_udp<T: ToSocketAddrs>(bind_addr: &T) -> Result<UdpSocket, Error> {
-        let sock = UdpSocket::bind(&bind_addr);
-        match sock {
-            Ok(s) => Ok(s),
-            Err(_e) => {
-                let new_bind = bind_addr.to_socket_addrs()?.next().unwrap().port() + 1;
-                let ip = get_if_addrs().unwrap()[0].ip();
-                let new_addr = SocketAddr::new(ip, new_bind);
-                warn!("Binding local port failed, trying {}", new_addr);
-                Connection::<RM>::bind_udp(&new_addr)
+    fn udp_peer(&self) -> Option<Weak<dyn UdpPeer>> {
+        let this = self.this.lock().upgrade()?;
+        let peer: Arc<dyn UdpPeer> = this;
+        Some(Arc::downgrade(&peer))
+    }
+
+    // The connection has gone, tell whoever is receiving from it
+    fn disconnect(&self, e: io::Error) {
+        match e.kind() {
+            ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => {
+                debug!("Connection closed: {}", e)
             },
+            _ => error!("Net Error {:?}", &e),
+        }
+
+        if self.running.swap(false, Ordering::Relaxed) {
+            let _ = self.recvd_message_write.lock().send(Err(ConnectionError::Disconnected));
+        }
+        self.poller.deregister(&self.stream, self.token);
+        self.udp.lock().close();
+    }
+}
+
+impl<RM: Message> Source for Connection<RM> {
+    fn ready(&self, readiness: Ready) {
+        if readiness.is_readable() {
+            if let Err(e) = self.read() {
+                self.disconnect(e);
+                return;
+            }
+        }
+        if readiness.is_writable() {
+            if let Err(e) = self.write_out() {
+                self.disconnect(e);
+            }
+        }
+    }
+
+    fn tick(&self) { self.udp.lock().tick(); }
+
+    fn abort(&self) { self.disconnect(io::Error::new(ErrorKind::Other, "Failed to handle network events")); }
+}
+
+impl<RM: Message> UdpPeer for Connection<RM> {
+    fn datagram(&self, from: SocketAddr, datagram: Datagram) {
+        let message = self.udp.lock().received(from, datagram);
+        match message {
+            Some((Some(sequence), data)) => self.deliver_sequenced(sequence, data),
+            Some((None, data)) => self.deliver(&data),
+            None => {},
         }
     }
 }
@@ -0,0 +1,308 @@
+// Standard
+use std::{
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+// Library
+use byteorder::{ByteOrder, LittleEndian};
+use chacha20_poly1305_aead::{decrypt, encrypt};
+use rand::RngCore;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{x25519, X25519_BASEPOINT_BYTES};
+
+// Parent
+use super::{
+    packet::{FRAME_DATA_LEN, MAX_FRAME_DATA_LEN},
+    protocol::PROTOCOL_FRAME_SEALED,
+    Error,
+};
+
+// Constants
+/// The version of the protocol spoken on the wire, which both ends tell each other before anything else
+pub const PROTOCOL_VERSION: u32 = 1;
+// The first version whose peers can encrypt. Keys offered by older peers are ignored.
+const ENCRYPTION_SINCE: u32 = 1;
+// A hello is the version, then flags, then the public key, which is zeroes when none is offered
+const HELLO_LEN: usize = 4 + 1 + 32;
+const FLAG_KEY: u8 = 1;
+const FLAG_REQUIRED: u8 = 2;
+// How long the other end has to say hello before the connection is given up on
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+// A sealed frame is its type byte and the length of what's sealed, then the sealed bytes and their tag
+const SEALED_HEADER_LEN: usize = 1 + 4;
+const TAG_LEN: usize = 16;
+const MAX_SEALED_LEN: usize = FRAME_DATA_LEN + MAX_FRAME_DATA_LEN as usize;
+// Mixed into the keys, so that they're only ever used for this. Datagrams have keys of their own, so that their
+// numbers never overlap with those of frames under the same key.
+const KEY_CONTEXT: &[u8] = b"veloren frame key";
+const DATAGRAM_KEY_CONTEXT: &[u8] = b"veloren datagram key";
+// How far behind the newest datagram an older one can arrive and still be let in
+const REPLAY_WINDOW: u64 = 64;
+
+/// Whether a connection's frames are encrypted. Both ends say which they want when they connect: frames are encrypted
+/// if both can, and the connection is refused if one end requires it and the other won't.
+///
+/// Datagrams on an encrypted connection are sealed too, with keys of their own. See `DatagramSealer`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encryption {
+    /// Never encrypt
+    Off,
+    /// Encrypt when the other end can, and carry on in plaintext when it can't
+    Preferred,
+    /// Only ever talk encrypted
+    Required,
+}
+
+// What each end of a connection says first
+#[derive(Debug, PartialEq)]
+struct Hello {
+    version: u32,
+    key: Option<[u8; 32]>,
+    required: bool,
+}
+
+impl Hello {
+    fn encode(&self) -> [u8; HELLO_LEN] {
+        let mut bytes = [0; HELLO_LEN];
+        LittleEndian::write_u32(&mut bytes[..4], self.version);
+        bytes[4] = if self.required { FLAG_REQUIRED } else { 0 };
+        if let Some(key) = self.key {
+            bytes[4] |= FLAG_KEY;
+            bytes[5..].copy_from_slice(&key);
+        }
+        bytes
+    }
+
+    // Flags this version doesn't know are left for later versions to give meaning to
+    fn decode(bytes: &[u8; HELLO_LEN]) -> Hello {
+        let mut key = [0; 32];
+        key.copy_from_slice(&bytes[5..]);
+        Hello {
+            version: LittleEndian::read_u32(&bytes[..4]),
+            key: Some(key).filter(|_| bytes[4] & FLAG_KEY != 0),
+            required: bytes[4] & FLAG_REQUIRED != 0,
+        }
+    }
+}
+
+/// Say hello to the other end of a freshly opened `stream`, before anything else is sent on it, and agree on whether
+/// to encrypt. Both ends say hello at once, so neither waits on the other. Returns what to seal outgoing frames and
+/// open incoming ones with, and what to seal and open datagrams with, or `None` to carry on in plaintext.
+pub fn handshake(
+    stream: &mut TcpStream,
+    encryption: Encryption,
+) -> Result<Option<(Sealer, Opener, DatagramSealer)>, Error> {
+    // A fresh key for every connection, so that nothing recorded from one helps with another
+    let secret = match encryption {
+        Encryption::Off => None,
+        Encryption::Preferred | Encryption::Required => {
+            let mut secret = [0; 32];
+            rand::thread_rng().fill_bytes(&mut secret);
+            Some(secret)
+        },
+    };
+    let ours = Hello {
+        version: PROTOCOL_VERSION,
+        key: secret.map(|secret| x25519(secret, X25519_BASEPOINT_BYTES)),
+        required: encryption == Encryption::Required,
+    };
+
+    stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+    stream.write_all(&ours.encode())?;
+    let mut bytes = [0; HELLO_LEN];
+    stream.read_exact(&mut bytes)?;
+    stream.set_read_timeout(None)?;
+    let theirs = Hello::decode(&bytes);
+
+    let their_key = theirs.key.filter(|_| theirs.version >= ENCRYPTION_SINCE);
+    match (secret, ours.key, their_key) {
+        (Some(secret), Some(our_key), Some(their_key)) => {
+            let shared = x25519(secret, their_key);
+            // Only a key chosen to be weak agrees on nothing but zeroes
+            if shared == [0; 32] {
+                return Err(Error::BadHandshake);
+            }
+            let sealer = Sealer::new(derive_key(KEY_CONTEXT, &shared, &our_key, &their_key));
+            let opener = Opener::new(derive_key(KEY_CONTEXT, &shared, &their_key, &our_key));
+            let datagrams = DatagramSealer::new(
+                derive_key(DATAGRAM_KEY_CONTEXT, &shared, &our_key, &their_key),
+                derive_key(DATAGRAM_KEY_CONTEXT, &shared, &their_key, &our_key),
+            );
+            Ok(Some((sealer, opener, datagrams)))
+        },
+        _ if ours.required || theirs.required => Err(Error::EncryptionMismatch),
+        _ => Ok(None),
+    }
+}
+
+// The key for whatever `context` is for going from the end with public key `from` to the one with `to`. Each direction
+// has its own, so that the two ends never use the same nonce with the same key.
+fn derive_key(context: &[u8], shared: &[u8; 32], from: &[u8; 32], to: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::default();
+    hasher.input(context);
+    hasher.input(shared);
+    hasher.input(from);
+    hasher.input(to);
+    let mut key = [0; 32];
+    key.copy_from_slice(hasher.result().as_slice());
+    key
+}
+
+// Frames are numbered in the order they're sealed, which both ends keep track of, so the number is never sent
+fn nonce(count: u64) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    LittleEndian::write_u64(&mut nonce[4..], count);
+    nonce
+}
+
+/// Encrypts frames on their way out
+pub struct Sealer {
+    key: [u8; 32],
+    count: u64,
+}
+
+impl Sealer {
+    fn new(key: [u8; 32]) -> Sealer { Sealer { key, count: 0 } }
+
+    /// Append an encoded frame to `buf`, sealed
+    pub fn seal(&mut self, frame: &[u8], buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.push(PROTOCOL_FRAME_SEALED);
+        let mut len = [0; 4];
+        LittleEndian::write_u32(&mut len, frame.len() as u32);
+        buf.extend_from_slice(&len);
+
+        let header = buf[start..].to_vec();
+        let tag = encrypt(&self.key, &nonce(self.count), &header, frame, buf).expect("Writing to a Vec can't fail");
+        buf.extend_from_slice(&tag);
+        self.count += 1;
+    }
+}
+
+// Keys are left out, so that they can't end up in a log
+impl fmt::Debug for Sealer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "Sealer {{ count: {} }}", self.count) }
+}
+
+/// Decrypts frames as they come in
+pub struct Opener {
+    key: [u8; 32],
+    count: u64,
+    // Bytes that don't make up a whole sealed frame yet
+    buf: Vec<u8>,
+}
+
+impl Opener {
+    fn new(key: [u8; 32]) -> Opener {
+        Opener {
+            key,
+            count: 0,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Add bytes read from the stream, returning the frames they complete, encoded. Anything that isn't a sealed frame
+    /// or doesn't open was tampered with on the way, and means nothing more from the stream can be trusted.
+    pub fn open(&mut self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        self.buf.extend_from_slice(bytes);
+
+        let mut frames = Vec::new();
+        let mut start = 0;
+        while self.buf.len() - start >= SEALED_HEADER_LEN {
+            let rest = &self.buf[start..];
+            let len = LittleEndian::read_u32(&rest[1..SEALED_HEADER_LEN]) as usize;
+            if rest[0] != PROTOCOL_FRAME_SEALED || len > MAX_SEALED_LEN {
+                return Err(io::Error::new(ErrorKind::InvalidData, "Received a frame that isn't sealed"));
+            }
+            let end = SEALED_HEADER_LEN + len;
+            if rest.len() < end + TAG_LEN {
+                break;
+            }
+
+            let (header, sealed, tag) = (&rest[..SEALED_HEADER_LEN], &rest[SEALED_HEADER_LEN..end], &rest[end..]);
+            decrypt(&self.key, &nonce(self.count), header, sealed, &tag[..TAG_LEN], &mut frames)
+                .map_err(|_| io::Error::new(ErrorKind::InvalidData, "Received a frame that doesn't open"))?;
+            self.count += 1;
+            start += end + TAG_LEN;
+        }
+        self.buf.drain(..start);
+        Ok(frames)
+    }
+}
+
+impl fmt::Debug for Opener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "Opener {{ count: {} }}", self.count) }
+}
+
+/// Encrypts datagrams on their way out and decrypts them as they come in. Datagrams can be lost, or arrive out of
+/// order or more than once, so each carries the number it was sealed with, and any number that's been opened already
+/// or is too far behind the newest is turned away.
+pub struct DatagramSealer {
+    seal_key: [u8; 32],
+    open_key: [u8; 32],
+    count: u64,
+    // The highest number opened so far, and a bit for each of the `REPLAY_WINDOW` below it that's been opened too
+    highest: u64,
+    opened: u64,
+}
+
+impl DatagramSealer {
+    fn new(seal_key: [u8; 32], open_key: [u8; 32]) -> DatagramSealer {
+        DatagramSealer {
+            seal_key,
+            open_key,
+            count: 0,
+            highest: 0,
+            opened: 0,
+        }
+    }
+
+    /// Seal `data`, returning the number it was sealed with and the sealed bytes with their tag. `header` isn't sealed,
+    /// but the datagram won't open if it's changed.
+    pub fn seal(&mut self, header: &[u8], data: &[u8]) -> (u64, Vec<u8>) {
+        // Numbered from 1, so that nothing is ever taken to have been opened already before anything has
+        self.count += 1;
+        let mut sealed = Vec::with_capacity(data.len() + TAG_LEN);
+        let tag = encrypt(&self.seal_key, &nonce(self.count), header, data, &mut sealed)
+            .expect("Writing to a Vec can't fail");
+        sealed.extend_from_slice(&tag);
+        (self.count, sealed)
+    }
+
+    /// Open a datagram sealed with the number `count`, or `None` if it was tampered with or has been opened before
+    pub fn open(&mut self, header: &[u8], count: u64, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < TAG_LEN || !self.is_fresh(count) {
+            return None;
+        }
+        let (sealed, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+        let mut data = Vec::with_capacity(sealed.len());
+        decrypt(&self.open_key, &nonce(count), header, sealed, tag, &mut data).ok()?;
+
+        if count > self.highest {
+            let shift = count - self.highest;
+            self.opened = if shift >= REPLAY_WINDOW { 0 } else { self.opened << shift };
+            self.highest = count;
+        }
+        self.opened |= 1 << (self.highest - count);
+        Some(data)
+    }
+
+    fn is_fresh(&self, count: u64) -> bool {
+        if count > self.highest {
+            return true;
+        }
+        let behind = self.highest - count;
+        behind < REPLAY_WINDOW && self.opened & (1 << behind) == 0
+    }
+}
+
+impl fmt::Debug for DatagramSealer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DatagramSealer {{ count: {}, highest: {} }}", self.count, self.highest)
+    }
+}
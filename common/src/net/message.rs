@@ -1,5 +1,5 @@
 // Standard
-use std::{io, net::SocketAddr};
+use std::io;
 
 // Library
 use bincode;
@@ -11,6 +11,10 @@ pub enum Error {
     NetworkErr(io::Error),
     CannotSerialize,
     CannotDeserialize,
+    /// One end requires encryption and the other won't encrypt
+    EncryptionMismatch,
+    /// The other end's hello made no sense
+    BadHandshake,
 }
 
 impl From<io::Error> for Error {
@@ -30,7 +34,10 @@ pub trait Message: Send + Sync + 'static + serde::Serialize + DeserializeOwned {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ConnectionMessage {
-    OpenedUdp { host: SocketAddr },
+    /// Stamp UDP probes with `token` and send them to `port`, at the address the TCP connection goes to
+    OfferUdp { token: u64, port: u16 },
+    /// A message sent with `Connection::send_sequenced` that couldn't go over UDP
+    Sequenced { channel: u16, seq: u32, data: Vec<u8> },
     Shutdown,
     Ping,
 }
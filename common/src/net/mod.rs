@@ -1,16 +1,24 @@
+mod batch;
 pub mod connection;
+mod crypto;
 pub mod message;
 mod packet;
+mod poller;
 mod protocol;
+mod sequence;
+#[cfg(test)]
 mod tcp;
 #[cfg(test)]
 pub mod tests;
-mod udp;
+pub mod traffic;
+mod udp_link;
 pub mod udpmgr;
 
 // Reexports
 pub use self::{
-    connection::Connection,
+    connection::{Connection, ConnectionStats},
+    crypto::{Encryption, PROTOCOL_VERSION},
     message::{ConnectionMessage, Error, Message},
+    udp_link::{Transport, UdpConfig, UdpStatus},
     udpmgr::UdpMgr,
 };
@@ -1,9 +1,115 @@
+// Standard
+use std::collections::HashMap;
+
+// Library
+use byteorder::{ByteOrder, LittleEndian};
+use crc::crc32;
+
+// Parent
+use super::{
+    protocol::{PROTOCOL_FRAME_DATA, PROTOCOL_FRAME_HEADER},
+    traffic,
+};
+
+// Constants
+// The size of each kind of frame on the wire, not counting its data: a type byte, then 64-bit fields, then a CRC32 of
+// everything before it
+pub const FRAME_HEADER_LEN: usize = 1 + 8 + 8 + 4;
+pub const FRAME_DATA_LEN: usize = 1 + 8 + 8 + 8 + 4;
+// Anything claiming to be bigger than these is taken to be garbage rather than allocated for
+pub const MAX_PACKET_LEN: u64 = 16 * 1024 * 1024;
+pub const MAX_FRAME_DATA_LEN: u64 = 64 * 1024;
+
 #[derive(Debug)]
 pub enum Frame {
     Header { id: u64, length: u64 },
     Data { id: u64, frame_no: u64, data: Vec<u8> },
 }
 
+/// Why the bytes at the start of a buffer couldn't be decoded into a frame
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    /// The whole frame hasn't arrived yet
+    Incomplete,
+    /// The bytes don't describe a frame
+    Invalid,
+    /// The frame was damaged on its way, so its checksum doesn't match
+    Corrupt,
+}
+
+impl Frame {
+    /// Append the frame to `buf` as it's sent over the wire
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        match self {
+            Frame::Header { id, length } => {
+                buf.push(PROTOCOL_FRAME_HEADER);
+                put_u64(buf, *id);
+                put_u64(buf, *length);
+            },
+            Frame::Data { id, frame_no, data } => {
+                buf.push(PROTOCOL_FRAME_DATA);
+                put_u64(buf, *id);
+                put_u64(buf, *frame_no);
+                put_u64(buf, data.len() as u64);
+                buf.extend_from_slice(data);
+            },
+        }
+        let checksum = crc32::checksum_ieee(&buf[start..]);
+        let mut bytes = [0; 4];
+        LittleEndian::write_u32(&mut bytes, checksum);
+        buf.extend_from_slice(&bytes);
+    }
+
+    /// Decode the frame at the start of `buf`, along with how many bytes it took up
+    pub fn decode(buf: &[u8]) -> Result<(Frame, usize), DecodeError> {
+        let len = match buf.first() {
+            None => return Err(DecodeError::Incomplete),
+            Some(&PROTOCOL_FRAME_HEADER) => FRAME_HEADER_LEN,
+            Some(&PROTOCOL_FRAME_DATA) => {
+                if buf.len() < FRAME_DATA_LEN {
+                    return Err(DecodeError::Incomplete);
+                }
+                let data_len = LittleEndian::read_u64(&buf[17..25]);
+                if data_len > MAX_FRAME_DATA_LEN {
+                    return Err(DecodeError::Invalid);
+                }
+                FRAME_DATA_LEN + data_len as usize
+            },
+            Some(_) => return Err(DecodeError::Invalid),
+        };
+        if buf.len() < len {
+            return Err(DecodeError::Incomplete);
+        }
+
+        let checksum = LittleEndian::read_u32(&buf[len - 4..len]);
+        if crc32::checksum_ieee(&buf[..len - 4]) != checksum {
+            return Err(DecodeError::Corrupt);
+        }
+
+        let id = LittleEndian::read_u64(&buf[1..9]);
+        let frame = if buf[0] == PROTOCOL_FRAME_HEADER {
+            Frame::Header {
+                id,
+                length: LittleEndian::read_u64(&buf[9..17]),
+            }
+        } else {
+            Frame::Data {
+                id,
+                frame_no: LittleEndian::read_u64(&buf[9..17]),
+                data: buf[25..len - 4].to_vec(),
+            }
+        };
+        Ok((frame, len))
+    }
+}
+
+fn put_u64(buf: &mut Vec<u8>, n: u64) {
+    let mut bytes = [0; 8];
+    LittleEndian::write_u64(&mut bytes, n);
+    buf.extend_from_slice(&bytes);
+}
+
 #[derive(Debug)]
 pub enum FrameError {
     SendDone,
@@ -28,19 +134,14 @@ pub struct OutgoingPacket {
 #[derive(Debug)]
 pub struct IncomingPacket {
     data: PacketData,
-    pos: u64,
+    // How long the header says the packet is. Only what has actually arrived is held, so a header claiming more than
+    // is ever sent doesn't cost anything.
+    length: u64,
     dataframesno: u64,
 }
 
 impl PacketData {
     pub fn new(bytes: Vec<u8>, id: u64) -> PacketData { PacketData { bytes, id } }
-
-    pub fn new_size(size: u64, id: u64) -> PacketData {
-        PacketData {
-            bytes: vec![0; size as usize],
-            id,
-        }
-    }
 }
 
 impl OutgoingPacket {
@@ -88,8 +189,8 @@ impl IncomingPacket {
     pub fn new(header: Frame) -> IncomingPacket {
         match header {
             Frame::Header { id, length } => IncomingPacket {
-                data: PacketData::new_size(length, id),
-                pos: 0,
+                data: PacketData::new(Vec::new(), id),
+                length,
                 dataframesno: 0,
             },
             Frame::Data { .. } => {
@@ -111,19 +212,104 @@ impl IncomingPacket {
                 if frame_no != self.dataframesno {
                     panic!("bufferin for frames not yet implemented");
                 }
-                // copy data starting from self.pos
-                //TODO: check size of send with reserved
-                for (dst, src) in self.data.bytes[self.pos as usize..].iter_mut().zip(&data) {
-                    *dst = *src;
-                }
-                self.pos += data.len() as u64;
+                self.data.bytes.extend_from_slice(&data);
                 self.dataframesno += 1;
 
-                self.pos == self.data.bytes.len() as u64
+                self.data.bytes.len() as u64 >= self.length
             },
         }
     }
 
+    /// Whether `frame_no`, holding `len` bytes, is the frame that comes next and fits in what's left of the packet
+    pub fn expects(&self, frame_no: u64, len: usize) -> bool {
+        frame_no == self.dataframesno && (self.data.bytes.len() + len) as u64 <= self.length
+    }
+
     #[allow(dead_code)]
     pub fn data(&self) -> &Vec<u8> { &self.data.bytes }
+
+    pub fn into_data(self) -> Vec<u8> { self.data.bytes }
+}
+
+/// Puts packets back together from their frames, or from the bytes those frames arrive as. Anything malformed is
+/// dropped and counted rather than trusted, since it came from the other end of a connection.
+#[derive(Debug, Default)]
+pub struct PacketAssembler {
+    // Bytes that don't make up a whole frame yet
+    buf: Vec<u8>,
+    packets: HashMap<u64, IncomingPacket>,
+}
+
+impl PacketAssembler {
+    pub fn new() -> Self { Self::default() }
+
+    /// Add bytes read from a stream, returning the id and data of every packet they complete. Once the bytes stop
+    /// making up valid frames the stream has lost its place, and there's no telling where the next frame starts, so
+    /// the error is returned and the connection should be given up on.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Result<Vec<(u64, Vec<u8>)>, DecodeError> {
+        self.buf.extend_from_slice(bytes);
+
+        let mut done = Vec::new();
+        let mut start = 0;
+        loop {
+            match Frame::decode(&self.buf[start..]) {
+                Ok((frame, len)) => {
+                    start += len;
+                    if let Some(packet) = self.push_frame(frame) {
+                        done.push(packet);
+                    }
+                },
+                Err(DecodeError::Incomplete) => break,
+                Err(e) => {
+                    if e == DecodeError::Corrupt {
+                        traffic::add_corrupt_frame();
+                    }
+                    self.buf.clear();
+                    return Err(e);
+                },
+            }
+        }
+        self.buf.drain(..start);
+        Ok(done)
+    }
+
+    /// Add a frame that arrived on its own, returning the packet's id and data if that completed it
+    pub fn push_frame(&mut self, frame: Frame) -> Option<(u64, Vec<u8>)> {
+        match frame {
+            Frame::Header { id, length } => {
+                if length > MAX_PACKET_LEN {
+                    warn!("Discarding packet {}, which claims to be {} bytes long", id, length);
+                    traffic::add_corrupt_frame();
+                    return None;
+                }
+                self.packets.insert(id, IncomingPacket::new(Frame::Header { id, length }));
+                None
+            },
+            Frame::Data { id, frame_no, data } => {
+                let complete = match self.packets.get_mut(&id) {
+                    Some(packet) if packet.expects(frame_no, data.len()) => {
+                        packet.load_data_frame(Frame::Data { id, frame_no, data })
+                    },
+                    Some(_) => {
+                        // There's no telling what the packet should have held
+                        warn!("Discarding packet {}, whose frame {} doesn't fit", id, frame_no);
+                        self.packets.remove(&id);
+                        traffic::add_corrupt_frame();
+                        return None;
+                    },
+                    None => {
+                        warn!("Discarding frame {} of unknown packet {}", frame_no, id);
+                        traffic::add_corrupt_frame();
+                        return None;
+                    },
+                };
+
+                if complete {
+                    self.packets.remove(&id).map(|packet| (id, packet.into_data()))
+                } else {
+                    None
+                }
+            },
+        }
+    }
 }
@@ -0,0 +1,136 @@
+// Standard
+use std::{
+    collections::HashMap,
+    fmt, io,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// Library
+use lazy_static::lazy_static;
+use mio::{Events, Evented, Poll, PollOpt, Ready, Token};
+use parking_lot::Mutex;
+
+// Constants
+// Every connection in the process shares these threads, whether it's a client's or one of a server's players
+const POLLER_THREADS: usize = 2;
+const EVENTS_CAPACITY: usize = 1024;
+// How often every source gets a chance to do things that don't wait for its socket, such as sending keepalives
+pub const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+lazy_static! {
+    static ref POLLERS: Vec<Arc<Poller>> = (0..POLLER_THREADS).map(Poller::start).collect();
+    static ref NEXT_POLLER: AtomicUsize = AtomicUsize::new(0);
+}
+
+/// Something with a socket registered with a poller
+pub trait Source: Send + Sync {
+    /// Called on the poller's thread when the socket may have become readable and/or writable. Sockets are registered
+    /// edge-triggered, so the source must read or write until the socket would block before it hears about it again.
+    fn ready(&self, readiness: Ready);
+
+    /// Called on the poller's thread about every `TICK_INTERVAL`, whether or not anything has happened
+    fn tick(&self) {}
+
+    /// Called if handling an event panicked. The source hears nothing more after this.
+    fn abort(&self);
+}
+
+/// A thread that waits for any of the sockets registered with it to be ready, then lets their owners know
+pub struct Poller {
+    poll: Poll,
+    sources: Mutex<HashMap<Token, Arc<dyn Source>>>,
+    next_token: AtomicUsize,
+}
+
+impl Poller {
+    fn start(index: usize) -> Arc<Poller> {
+        let poller = Arc::new(Poller {
+            poll: Poll::new().expect("Failed to create network poller"),
+            sources: Mutex::new(HashMap::new()),
+            next_token: AtomicUsize::new(0),
+        });
+
+        let p = poller.clone();
+        thread::Builder::new()
+            .name(format!("net-poller-{}", index))
+            .spawn(move || p.run())
+            .expect("Failed to start network poller thread");
+        poller
+    }
+
+    /// One of the shared pollers, taking turns so that connections are spread between them
+    pub fn get() -> Arc<Poller> { POLLERS[NEXT_POLLER.fetch_add(1, Ordering::Relaxed) % POLLERS.len()].clone() }
+
+    /// A token that no other source on this poller uses
+    pub fn next_token(&self) -> Token { Token(self.next_token.fetch_add(1, Ordering::Relaxed)) }
+
+    pub fn register<E: Evented>(&self, handle: &E, token: Token, source: Arc<dyn Source>) -> io::Result<()> {
+        // Added first so that the source is there for the first event
+        self.sources.lock().insert(token, source);
+        let result = self
+            .poll
+            .register(handle, token, Ready::readable() | Ready::writable(), PollOpt::edge());
+        if result.is_err() {
+            self.sources.lock().remove(&token);
+        }
+        result
+    }
+
+    /// Stop watching a socket. Events already on their way for it may still be delivered.
+    pub fn deregister<E: Evented>(&self, handle: &E, token: Token) {
+        let _ = self.poll.deregister(handle);
+        self.sources.lock().remove(&token);
+    }
+
+    fn run(&self) {
+        let mut events = Events::with_capacity(EVENTS_CAPACITY);
+        let mut last_tick = Instant::now();
+        loop {
+            if let Err(e) = self.poll.poll(&mut events, Some(TICK_INTERVAL)) {
+                if e.kind() != io::ErrorKind::Interrupted {
+                    error!("Network poller failed: {:?}", e);
+                }
+                continue;
+            }
+
+            for event in events.iter() {
+                // Not held while the source runs, since it may deregister itself
+                let source = self.sources.lock().get(&event.token()).cloned();
+                if let Some(source) = source {
+                    self.run_source(event.token(), &source, |source| source.ready(event.readiness()));
+                }
+            }
+
+            if last_tick.elapsed() >= TICK_INTERVAL {
+                last_tick = Instant::now();
+                let sources = self.sources.lock().iter().map(|(t, s)| (*t, s.clone())).collect::<Vec<_>>();
+                for (token, source) in sources {
+                    self.run_source(token, &source, |source| source.tick());
+                }
+            }
+        }
+    }
+
+    fn run_source<F: FnOnce(&dyn Source)>(&self, token: Token, source: &Arc<dyn Source>, f: F) {
+        // One misbehaving connection mustn't take every other one on this thread down with it
+        if panic::catch_unwind(AssertUnwindSafe(|| f(&**source))).is_err() {
+            error!("Dropping a connection that panicked while handling network events");
+            self.sources.lock().remove(&token);
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| source.abort()));
+        }
+    }
+}
+
+impl fmt::Debug for Poller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Poller")
+            .field("sources", &self.sources.lock().len())
+            .finish()
+    }
+}
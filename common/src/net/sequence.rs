@@ -0,0 +1,42 @@
+// Standard
+use std::collections::HashMap;
+
+/// Where a message comes on the channel it was sent on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Sequence {
+    pub channel: u16,
+    pub seq: u32,
+}
+
+/// Whether sequence number `a` comes after `b`. Numbers wrap around, so anything less than half the range ahead of `b`
+/// counts as after it, and anything else as before it.
+pub fn is_newer(a: u32, b: u32) -> bool { a != b && a.wrapping_sub(b) < 1 << 31 }
+
+/// Numbers the messages sent on each channel, and picks out the ones worth delivering as they arrive
+#[derive(Debug, Default)]
+pub(crate) struct Sequencer {
+    next: HashMap<u16, u32>,
+    newest: HashMap<u16, u32>,
+}
+
+impl Sequencer {
+    /// Where the next message sent on `channel` comes
+    pub fn next(&mut self, channel: u16) -> Sequence {
+        let next = self.next.entry(channel).or_insert(0);
+        let seq = *next;
+        *next = next.wrapping_add(1);
+        Sequence { channel, seq }
+    }
+
+    /// Whether a message that arrived is newer than everything that came before it on its channel, in which case it
+    /// becomes the newest
+    pub fn accept(&mut self, sequence: Sequence) -> bool {
+        match self.newest.get(&sequence.channel) {
+            Some(newest) if !is_newer(sequence.seq, *newest) => false,
+            _ => {
+                self.newest.insert(sequence.channel, sequence.seq);
+                true
+            },
+        }
+    }
+}
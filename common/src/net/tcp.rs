@@ -1,3 +1,6 @@
+// A blocking transport that reads and writes one frame at a time. Connections go through the poller instead, but
+// this is handy for talking to them frame by frame from the tests.
+
 // Standard
 use std::{
     io::{Read, Write},
@@ -5,14 +8,14 @@ use std::{
 };
 
 // Library
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{ByteOrder, LittleEndian};
 use parking_lot::Mutex;
 
 // Parent
 use super::{
-    packet::Frame,
-    protocol::{Protocol, PROTOCOL_FRAME_DATA, PROTOCOL_FRAME_HEADER},
-    Error,
+    packet::{DecodeError, Frame, FRAME_DATA_LEN, FRAME_HEADER_LEN},
+    protocol::{Protocol, PROTOCOL_FRAME_HEADER},
+    traffic, Error,
 };
 
 #[derive(Debug)]
@@ -38,47 +41,40 @@ impl Tcp {
 
 impl Protocol for Tcp {
     fn send(&self, frame: Frame) -> Result<(), Error> {
-        let mut stream = self.stream_out.lock();
-        match frame {
-            Frame::Header { id, length } => {
-                stream.write_u8(PROTOCOL_FRAME_HEADER)?;
-                stream.write_u64::<LittleEndian>(id)?;
-                stream.write_u64::<LittleEndian>(length)?;
-                Ok(())
-            },
-            Frame::Data { id, frame_no, data } => {
-                stream.write_u8(PROTOCOL_FRAME_DATA)?;
-                stream.write_u64::<LittleEndian>(id)?;
-                stream.write_u64::<LittleEndian>(frame_no)?;
-                stream.write_u64::<LittleEndian>(data.len() as u64)?;
-                stream.write_all(&data)?;
-                Ok(())
-            },
-        }
+        let mut buf = Vec::new();
+        frame.encode(&mut buf);
+        self.stream_out.lock().write_all(&buf)?;
+        traffic::add_sent(buf.len());
+        Ok(())
     }
 
     //blocking
     fn recv(&self) -> Result<Frame, Error> {
         let mut stream = self.stream_in.lock();
-        let frame = stream.read_u8()? as u8;
-        match frame {
-            1 => {
-                let id = stream.read_u64::<LittleEndian>()? as u64;
-                let length = stream.read_u64::<LittleEndian>()? as u64;
-                Ok(Frame::Header { id, length })
-            },
-            2 => {
-                let id = stream.read_u64::<LittleEndian>()? as u64;
-                let frame_no = stream.read_u64::<LittleEndian>()? as u64;
-                let packet_size = stream.read_u64::<LittleEndian>()? as u64;
-                let mut data = vec![0; packet_size as usize];
-                stream.read_exact(&mut data)?;
-                Ok(Frame::Data { id, frame_no, data })
-            },
-            x => {
-                error!("invalid frame recieved: {}", x);
-                Err(Error::CannotDeserialize)
-            },
+        let mut buf = Vec::new();
+        // Read just enough for the frame to say how long it is, then the rest of it
+        let mut len = 1;
+        loop {
+            let start = buf.len();
+            buf.resize(len, 0);
+            stream.read_exact(&mut buf[start..])?;
+            match Frame::decode(&buf) {
+                Ok((frame, _)) => {
+                    traffic::add_received(buf.len());
+                    return Ok(frame);
+                },
+                Err(DecodeError::Incomplete) => {
+                    len = match buf[0] {
+                        PROTOCOL_FRAME_HEADER => FRAME_HEADER_LEN,
+                        _ if len < FRAME_DATA_LEN => FRAME_DATA_LEN,
+                        _ => FRAME_DATA_LEN + LittleEndian::read_u64(&buf[17..25]) as usize,
+                    }
+                },
+                Err(e) => {
+                    error!("invalid frame recieved: {:?}", e);
+                    return Err(Error::CannotDeserialize);
+                },
+            }
         }
     }
 }
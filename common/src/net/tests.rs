@@ -1,20 +1,29 @@
 // Standard
 use std::{
-    io::ErrorKind::UnexpectedEof,
+    fs,
+    io::{ErrorKind::UnexpectedEof, Write},
     net::{Shutdown::Both, TcpListener, TcpStream},
+    sync::Arc,
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 // Library
+use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
 
 // Parent
 use super::{
-    message::{Error::NetworkErr, Message},
-    packet::{Frame, FrameError, IncomingPacket, OutgoingPacket},
+    batch::{unbatch, Batch},
+    connection::Connection,
+    crypto::{self, DatagramSealer, Encryption, Opener, Sealer},
+    message::{ConnectionMessage, Error, Error::NetworkErr, Message},
+    packet::{DecodeError, Frame, FrameError, IncomingPacket, OutgoingPacket, PacketAssembler, MAX_PACKET_LEN},
     protocol::Protocol,
+    sequence::{is_newer, Sequence, Sequencer},
     tcp::Tcp,
+    traffic,
+    udp_link::{Datagram, Transport, UdpConfig, UdpStatus},
     udpmgr::UdpMgr,
 };
 
@@ -405,152 +414,944 @@ fn tcp_doublerecv() {
     handle3.join().unwrap();
 }
 
+// Ping the server over UDP and have it answer with `pong`
+fn udp_pingpong_once(server: &Connection<TestMessage>, client: &Connection<TestMessage>, ping: u64, pong: &str) {
+    client.send_unreliable(TestMessage::SmallMessage { value: ping });
+    client.flush();
+    match recv_in_time(server) {
+        TestMessage::SmallMessage { value } => assert_eq!(value, ping),
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+    server.send_unreliable(TestMessage::LargeMessage { text: pong.to_string() });
+    server.flush();
+    match recv_in_time(client) {
+        TestMessage::LargeMessage { text } => assert_eq!(text, pong),
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+}
+
 #[test]
 fn udp_pingpong() {
-    let mgr = UdpMgr::new();
-    let serverip = PORTS.next();
-    let clientip = PORTS.next();
-    let server = UdpMgr::start_udp(mgr.clone(), &serverip, &clientip); // server has to know client ip
-    let client = UdpMgr::start_udp(mgr.clone(), &clientip, &serverip);
-    client.send(Frame::Header { id: 123, length: 9876 }).unwrap(); //send ping
-    let frame = server.recv().unwrap(); //wait for ping
-    match frame {
-        Frame::Header { id, length } => {
-            assert_eq!(id, 123);
-            assert_eq!(length, 9876);
-        },
-        Frame::Data { .. } => {
-            assert!(false);
-        },
+    let udp = UdpMgr::bind(&"127.0.0.1:0", fast_udp()).unwrap();
+    let (server, client) = udp_pair(udp);
+    wait_until(|| client.stats().udp == UdpStatus::Active && server.stats().udp == UdpStatus::Active);
+    udp_pingpong_once(&server, &client, 123, "pong");
+    assert_eq!(client.stats().unreliable_transport, Transport::Udp);
+
+    Connection::stop(&client);
+    Connection::stop(&server);
+}
+
+#[test]
+fn udp_pingpong_2clients() {
+    // Both clients' datagrams arrive at the same server socket, and each answer only reaches the client it's for
+    let udp = UdpMgr::bind(&"127.0.0.1:0", fast_udp()).unwrap();
+    let (server, client) = udp_pair(udp.clone());
+    let (server2, client2) = udp_pair(udp);
+    let conns = [&server, &client, &server2, &client2];
+    wait_until(|| conns.iter().all(|c| c.stats().udp == UdpStatus::Active));
+    udp_pingpong_once(&server, &client, 1, "first");
+    udp_pingpong_once(&server2, &client2, 2, "second");
+    assert!(client.try_recv().is_err());
+    assert!(server.try_recv().is_err());
+
+    for conn in conns.iter() {
+        Connection::stop(conn);
     }
-    server
-        .send(Frame::Data {
-            id: 777,
-            frame_no: 333,
-            data: vec![0, 10],
-        })
-        .unwrap(); //send pong
-    let frame = client.recv().unwrap(); //wait for pong
-    match frame {
-        Frame::Header { .. } => {
-            assert!(false);
+}
+
+#[test]
+fn udp_pingpong_1000() {
+    let udp = UdpMgr::bind(&"127.0.0.1:0", fast_udp()).unwrap();
+    let (server, client) = udp_pair(udp);
+    wait_until(|| client.stats().udp == UdpStatus::Active && server.stats().udp == UdpStatus::Active);
+    // One at a time, since UDP may drop datagrams sent faster than they're read, even to localhost
+    for ping in 0..1000 {
+        udp_pingpong_once(&server, &client, ping, "pong");
+    }
+    assert_eq!(client.stats().unreliable_transport, Transport::Udp);
+    assert_eq!(server.stats().unreliable_transport, Transport::Udp);
+
+    Connection::stop(&client);
+    Connection::stop(&server);
+}
+
+#[test]
+fn frames_survive_encoding() {
+    let mut buf = Vec::new();
+    Frame::Header { id: 5, length: 300 }.encode(&mut buf);
+    Frame::Data {
+        id: 5,
+        frame_no: 0,
+        data: vec![1, 2, 3],
+    }
+    .encode(&mut buf);
+
+    let (header, len) = Frame::decode(&buf).unwrap();
+    check_header(&Ok(header), 5, 300);
+    let (data, data_len) = Frame::decode(&buf[len..]).unwrap();
+    check_data(&Ok(data), 5, 0, vec![1, 2, 3]);
+    assert_eq!(len + data_len, buf.len());
+
+    // Frames that haven't fully arrived are left for later
+    for end in 0..len {
+        assert_eq!(Frame::decode(&buf[..end]).unwrap_err(), DecodeError::Incomplete);
+    }
+    for end in len..buf.len() {
+        assert_eq!(Frame::decode(&buf[len..end]).unwrap_err(), DecodeError::Incomplete);
+    }
+    assert_eq!(Frame::decode(&[99, 1, 2]).unwrap_err(), DecodeError::Invalid);
+}
+
+#[test]
+fn damaged_frames_fail_their_checksum() {
+    let mut buf = Vec::new();
+    Frame::Data {
+        id: 5,
+        frame_no: 0,
+        data: vec![1, 2, 3],
+    }
+    .encode(&mut buf);
+
+    // Changing any byte at all means the frame is rejected, though not always as corrupt, since its length may no
+    // longer add up
+    for i in 0..buf.len() {
+        let mut damaged = buf.clone();
+        damaged[i] ^= 0x10;
+        assert!(Frame::decode(&damaged).is_err(), "Flipping byte {} went unnoticed", i);
+    }
+}
+
+// Every frame of a packet, as it's sent over a stream
+fn encode_packet(id: u64, data: Vec<u8>) -> Vec<u8> {
+    let mut packet = OutgoingPacket::new(data, id);
+    let mut buf = Vec::new();
+    while let Ok(frame) = packet.generate_frame(1000) {
+        frame.encode(&mut buf);
+    }
+    buf
+}
+
+#[test]
+fn assembler_gives_up_on_garbage() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..200 {
+        let mut assembler = PacketAssembler::new();
+        let garbage = (0..rng.gen_range(1, 4096)).map(|_| rng.gen()).collect::<Vec<u8>>();
+
+        // The garbage may look like the start of a frame, holding things up until enough has arrived to tell
+        // otherwise, but nothing that follows it is trusted
+        let mut result = Ok(vec![]);
+        for chunk in garbage.chunks(rng.gen_range(1, 512)) {
+            result = result.and_then(|_| assembler.push_bytes(chunk));
+            assert!(result.as_ref().map_or(true, |packets| packets.is_empty()));
+        }
+        for id in 0..100 {
+            result = result.and_then(|_| assembler.push_bytes(&encode_packet(id, vec![id as u8; 1500])));
+        }
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn assembler_survives_damaged_packets() {
+    let mut rng = rand::thread_rng();
+    let data = (0..5000).map(|_| rng.gen()).collect::<Vec<u8>>();
+    let bytes = encode_packet(1, data.clone());
+    for _ in 0..1000 {
+        let mut damaged = bytes.clone();
+        if rng.gen() {
+            damaged.truncate(rng.gen_range(0, bytes.len()));
+        } else {
+            for _ in 0..rng.gen_range(1, 4) {
+                let i = rng.gen_range(0, bytes.len());
+                damaged[i] = rng.gen();
+            }
+        }
+
+        // Damage to a byte may have left it unchanged
+        let mut assembler = PacketAssembler::new();
+        for packet in assembler.push_bytes(&damaged).unwrap_or_default() {
+            assert_eq!(packet, (1, data.clone()));
+        }
+    }
+}
+
+#[test]
+fn assembler_survives_frames_out_of_place() {
+    let mut rng = rand::thread_rng();
+    let mut assembler = PacketAssembler::new();
+    for _ in 0..10_000 {
+        let id = rng.gen_range(0, 4);
+        let frame = match rng.gen_range(0, 10) {
+            0 => Frame::Header {
+                id,
+                length: std::u64::MAX,
+            },
+            1..=3 => Frame::Header {
+                id,
+                length: rng.gen_range(0, 64),
+            },
+            _ => Frame::Data {
+                id,
+                frame_no: rng.gen_range(0, 4),
+                data: vec![0; rng.gen_range(0, 32)],
+            },
+        };
+        assembler.push_frame(frame);
+    }
+
+    // Frames in the right order still make a packet afterwards
+    let frames = vec![
+        Frame::Header { id: 9, length: 4 },
+        Frame::Data {
+            id: 9,
+            frame_no: 0,
+            data: vec![1, 2],
         },
-        Frame::Data { id, frame_no, data } => {
-            assert_eq!(id, 777);
-            assert_eq!(frame_no, 333);
-            assert_eq!(data, vec![0, 10]);
-            assert_ne!(data, vec![0, 11]);
+        Frame::Data {
+            id: 9,
+            frame_no: 1,
+            data: vec![3, 4],
         },
+    ];
+    let packets = frames
+        .into_iter()
+        .filter_map(|frame| assembler.push_frame(frame))
+        .collect::<Vec<_>>();
+    assert_eq!(packets, vec![(9, vec![1, 2, 3, 4])]);
+}
+
+#[test]
+fn packets_only_hold_what_has_arrived() {
+    // A header can claim as much as it likes without anything being set aside for it
+    let mut packet = IncomingPacket::new(Frame::Header {
+        id: 1,
+        length: MAX_PACKET_LEN,
+    });
+    assert_eq!(packet.data().capacity(), 0);
+    assert!(packet.expects(0, 1000));
+    assert!(!packet.load_data_frame(Frame::Data {
+        id: 1,
+        frame_no: 0,
+        data: vec![7; 1000],
+    }));
+    assert_eq!(packet.data().len(), 1000);
+
+    // Nor can its data go past the length it claimed
+    let mut assembler = PacketAssembler::new();
+    assembler.push_frame(Frame::Header { id: 2, length: 4 });
+    let data = |len| Frame::Data {
+        id: 2,
+        frame_no: 0,
+        data: vec![0; len],
+    };
+    assert_eq!(assembler.push_frame(data(5)), None);
+    // The packet was thrown away along with it
+    assert_eq!(assembler.push_frame(data(4)), None);
+}
+
+// Take the next connection to `listen`, without offering UDP or encryption
+fn accept(listen: &TcpListener) -> Arc<Connection<TestMessage>> {
+    Connection::new_stream(listen.accept().unwrap().0, UdpMgr::new(), Encryption::Off).unwrap()
+}
+
+// A client for sending a connection frames by hand. It says hello first, like a connection would, but won't encrypt.
+fn raw_client(addr: &str) -> Tcp {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    crypto::handshake(&mut stream, Encryption::Off).unwrap();
+    Tcp::new_stream(stream).unwrap()
+}
+
+// A message as the data of a packet of its own, the way a connection batches messages
+fn batched(msg: TestMessage) -> Vec<u8> {
+    let mut batch = Batch::default();
+    batch.push(&msg.to_bytes().unwrap());
+    batch.take()
+}
+
+// Wait for a message, failing the test rather than hanging it if none comes
+fn recv_in_time(conn: &Connection<TestMessage>) -> TestMessage {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(10) {
+        match conn.try_recv() {
+            Ok(msg) => return msg,
+            Err(()) => thread::sleep(Duration::from_millis(1)),
+        }
     }
-    UdpMgr::stop_udp(mgr.clone(), server);
-    UdpMgr::stop_udp(mgr.clone(), client);
+    panic!("Timed out waiting for a message");
+}
+
+fn thread_count() -> Option<usize> {
+    fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find(|line| line.starts_with("Threads:"))
+        .and_then(|line| line["Threads:".len()..].trim().parse().ok())
 }
 
 #[test]
-fn udp_pingpong_2clients() {
-    let mgr = UdpMgr::new();
+fn connection_pingpong() {
     let serverip = PORTS.next();
-    let clientip = PORTS.next();
-    let clientip2 = PORTS.next();
-    let server = UdpMgr::start_udp(mgr.clone(), &serverip, &clientip);
-    let server2 = UdpMgr::start_udp(mgr.clone(), &serverip, &clientip2);
-    let client = UdpMgr::start_udp(mgr.clone(), &clientip, &serverip);
-    let client2 = UdpMgr::start_udp(mgr.clone(), &clientip2, &serverip);
-    client.send(Frame::Header { id: 123, length: 9876 }).unwrap(); //send ping
-    println!("send");
-    let frame = server.recv().unwrap(); //wait for ping
-    println!("recved");
-    match frame {
-        Frame::Header { id, length } => {
-            assert_eq!(id, 123);
-            assert_eq!(length, 9876);
+    let listen = TcpListener::bind(&serverip).unwrap();
+    let handle = thread::spawn(move || {
+        let server = accept(&listen);
+        Connection::start(&server);
+        match recv_in_time(&server) {
+            TestMessage::LargeMessage { text } => assert_eq!(text.len(), 100_000),
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+        server.send(TestMessage::SmallMessage { value: 7 });
+        server.flush();
+        // The other end hanging up is noticed
+        assert!(server.recv().is_err());
+    });
+
+    let client = Connection::<TestMessage>::new(&serverip, UdpMgr::new(), Encryption::Off).unwrap();
+    Connection::start(&client);
+    // Big enough to be split into many frames and fill the socket's buffers
+    client.send(TestMessage::LargeMessage {
+        text: "x".repeat(100_000),
+    });
+    client.flush();
+    match recv_in_time(&client) {
+        TestMessage::SmallMessage { value } => assert_eq!(value, 7),
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+    Connection::stop(&client);
+    assert!(client.recv().is_err());
+    handle.join().unwrap();
+}
+
+#[test]
+fn bad_packets_are_dropped() {
+    let serverip = PORTS.next();
+    let listen = TcpListener::bind(&serverip).unwrap();
+    let handle = thread::spawn(move || {
+        let server = accept(&listen);
+        Connection::start(&server);
+        server
+    });
+    let client = raw_client(&serverip);
+    let server = handle.join().unwrap();
+    let bad_before = traffic::bad_packets();
+
+    // A packet that isn't a message, then one with a frame out of place, then one that's fine
+    let mut frames = vec![
+        Frame::Header { id: 1, length: 3 },
+        Frame::Data {
+            id: 1,
+            frame_no: 0,
+            data: vec![255, 255, 255],
         },
-        Frame::Data { .. } => {
-            assert!(false);
+        Frame::Header { id: 2, length: 3 },
+        Frame::Data {
+            id: 2,
+            frame_no: 5,
+            data: vec![0, 0, 0],
         },
+    ];
+    let mut packet = OutgoingPacket::new(batched(TestMessage::SmallMessage { value: 42 }), 3);
+    while let Ok(frame) = packet.generate_frame(1000) {
+        frames.push(frame);
+    }
+    for frame in frames {
+        client.send(frame).unwrap();
+    }
+
+    match recv_in_time(&server) {
+        TestMessage::SmallMessage { value } => assert_eq!(value, 42),
+        msg => panic!("Unexpected message: {:?}", msg),
     }
-    server2
-        .send(Frame::Data {
-            id: 777,
-            frame_no: 333,
-            data: vec![0, 10],
+    assert!(traffic::bad_packets() > bad_before);
+    assert!(server.try_recv().is_err());
+    Connection::stop(&server);
+}
+
+#[test]
+fn connections_that_lose_their_place_are_dropped() {
+    let serverip = PORTS.next();
+    let listen = TcpListener::bind(&serverip).unwrap();
+    let handle = thread::spawn(move || {
+        let server = accept(&listen);
+        Connection::start(&server);
+        server
+    });
+    let mut stream = TcpStream::connect(&serverip).unwrap();
+    crypto::handshake(&mut stream, Encryption::Off).unwrap();
+    let server = handle.join().unwrap();
+
+    stream.write_all(&[99; 64]).unwrap();
+    assert!(server.recv().is_err());
+}
+
+// Let the process have at least `wanted` files open, as far as the hard limit allows. Both ends of hundreds of
+// connections are more than a default soft limit of 1024 makes room for, alongside the other tests.
+#[cfg(unix)]
+fn raise_fd_limit(wanted: usize) {
+    unsafe {
+        let mut limit: libc::rlimit = std::mem::zeroed();
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) == 0 && limit.rlim_cur < wanted as libc::rlim_t {
+            limit.rlim_cur = limit.rlim_max.min(wanted as libc::rlim_t);
+            libc::setrlimit(libc::RLIMIT_NOFILE, &limit);
+        }
+    }
+}
+
+#[test]
+fn many_connections_share_threads() {
+    // Enough that a thread each would stand out
+    const CONNECTIONS: usize = 500;
+    #[cfg(unix)]
+    raise_fd_limit(4 * CONNECTIONS);
+
+    let serverip = PORTS.next();
+    let listen = TcpListener::bind(&serverip).unwrap();
+    let threads_before = thread_count();
+
+    let handle = thread::spawn(move || {
+        (0..CONNECTIONS)
+            .map(|_| {
+                let conn = accept(&listen);
+                Connection::start(&conn);
+                conn
+            })
+            .collect::<Vec<_>>()
+    });
+    let clients = (0..CONNECTIONS)
+        .map(|_| {
+            let conn = Connection::<TestMessage>::new(&serverip, UdpMgr::new(), Encryption::Off).unwrap();
+            Connection::start(&conn);
+            conn
         })
-        .unwrap(); //send pong
-    let frame = client2.recv().unwrap(); //wait for pong
-    match frame {
-        Frame::Header { .. } => {
-            assert!(false);
+        .collect::<Vec<_>>();
+    let servers = handle.join().unwrap();
+
+    // Connections don't get threads of their own. Other tests may be starting threads meanwhile, so leave some slack.
+    if let (Some(before), Some(after)) = (threads_before, thread_count()) {
+        assert!(after < before + CONNECTIONS / 2, "{} threads became {}", before, after);
+    }
+
+    // Every server echoes whatever its client sends
+    for (i, client) in clients.iter().enumerate() {
+        client.send(TestMessage::SmallMessage { value: i as u64 });
+        client.flush();
+    }
+    for server in &servers {
+        server.send(recv_in_time(server));
+        server.flush();
+    }
+    for (i, client) in clients.iter().enumerate() {
+        match recv_in_time(client) {
+            TestMessage::SmallMessage { value } => assert_eq!(value, i as u64),
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+    }
+
+    // Hanging up is noticed at the other end
+    for client in &clients {
+        Connection::stop(client);
+    }
+    for server in &servers {
+        assert!(server.recv().is_err());
+    }
+}
+
+#[test]
+fn datagrams_survive_encoding() {
+    let datagrams = vec![
+        Datagram::Probe { token: 7, seq: 1 },
+        Datagram::Ack { token: 7, seq: 1 },
+        Datagram::Message {
+            token: 7,
+            sequence: None,
+            data: vec![1, 2, 3],
         },
-        Frame::Data { id, frame_no, data } => {
-            assert_eq!(id, 777);
-            assert_eq!(frame_no, 333);
-            assert_eq!(data, vec![0, 10]);
-            assert_ne!(data, vec![0, 11]);
+        Datagram::Message {
+            token: 7,
+            sequence: Some(Sequence {
+                channel: 2,
+                seq: 300,
+            }),
+            data: vec![4, 5],
         },
+        Datagram::Sealed {
+            token: 7,
+            nonce: 12,
+            sealed: vec![6; 20],
+        },
+    ];
+    for datagram in datagrams {
+        let bytes = datagram.encode();
+        assert_eq!(Datagram::decode(&bytes), Some(datagram));
+        for end in 0..bytes.len() {
+            assert_eq!(Datagram::decode(&bytes[..end]), None);
+        }
+    }
+}
+
+// Timings short enough for the tests not to take long
+fn fast_udp() -> UdpConfig {
+    UdpConfig {
+        probe_interval: Duration::from_millis(50),
+        probe_timeout: Duration::from_secs(2),
+        keepalive_interval: Duration::from_millis(100),
+        keepalive_timeout: Duration::from_millis(600),
+    }
+}
+
+// Wait for something to happen, failing the test rather than hanging it if it doesn't
+fn wait_until<F: FnMut() -> bool>(mut condition: F) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(start.elapsed() < Duration::from_secs(10), "Timed out waiting");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+// A connected server and client, with the server offering UDP if `udp` is listening for it
+fn udp_pair(udp: Arc<UdpMgr>) -> (Arc<Connection<TestMessage>>, Arc<Connection<TestMessage>>) {
+    let serverip = PORTS.next();
+    let listen = TcpListener::bind(&serverip).unwrap();
+    let handle = thread::spawn(move || {
+        let server = Connection::<TestMessage>::new_stream(listen.accept().unwrap().0, udp, Encryption::Off).unwrap();
+        Connection::start(&server);
+        server
+    });
+    let client = Connection::<TestMessage>::new(&serverip, UdpMgr::with_config(fast_udp()), Encryption::Off).unwrap();
+    Connection::start(&client);
+    (handle.join().unwrap(), client)
+}
+
+// Unreliable messages get through either way, since nothing here is lossy
+fn exchange_unreliable(server: &Connection<TestMessage>, client: &Connection<TestMessage>) {
+    client.send_unreliable(TestMessage::SmallMessage { value: 1 });
+    client.flush();
+    match recv_in_time(server) {
+        TestMessage::SmallMessage { value } => assert_eq!(value, 1),
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+    server.send_unreliable(TestMessage::SmallMessage { value: 2 });
+    server.flush();
+    match recv_in_time(client) {
+        TestMessage::SmallMessage { value } => assert_eq!(value, 2),
+        msg => panic!("Unexpected message: {:?}", msg),
     }
-    UdpMgr::stop_udp(mgr.clone(), server);
-    UdpMgr::stop_udp(mgr.clone(), client);
-    UdpMgr::stop_udp(mgr.clone(), server2);
-    UdpMgr::stop_udp(mgr.clone(), client2);
 }
 
 #[test]
-fn udp_pingpong_1000() {
-    let mgr = UdpMgr::new();
+fn udp_is_used_once_probes_get_through() {
+    let udp = UdpMgr::bind(&"127.0.0.1:0", fast_udp()).unwrap();
+    let (server, client) = udp_pair(udp);
+    wait_until(|| client.stats().udp == UdpStatus::Active && server.stats().udp == UdpStatus::Active);
+    assert_eq!(client.stats().unreliable_transport, Transport::Udp);
+    exchange_unreliable(&server, &client);
+
+    Connection::stop(&client);
+    Connection::stop(&server);
+}
+
+#[test]
+fn tcp_is_kept_when_probes_get_no_answer() {
+    let udp = UdpMgr::bind(&"127.0.0.1:0", fast_udp()).unwrap();
+    // UDP is offered, but nothing reads the probes
+    udp.close();
+    let (server, client) = udp_pair(udp);
+
+    wait_until(|| client.stats().udp == UdpStatus::Failed && server.stats().udp == UdpStatus::Failed);
+    assert_eq!(client.stats().unreliable_transport, Transport::Tcp);
+    exchange_unreliable(&server, &client);
+
+    Connection::stop(&client);
+    Connection::stop(&server);
+}
+
+#[test]
+fn udp_falls_back_to_tcp_when_it_goes_quiet() {
+    let udp = UdpMgr::bind(&"127.0.0.1:0", fast_udp()).unwrap();
+    let (server, client) = udp_pair(udp.clone());
+    wait_until(|| client.stats().udp == UdpStatus::Active && server.stats().udp == UdpStatus::Active);
+
+    udp.close();
+    wait_until(|| client.stats().udp == UdpStatus::Lost && server.stats().udp == UdpStatus::Lost);
+    assert_eq!(server.stats().unreliable_transport, Transport::Tcp);
+    exchange_unreliable(&server, &client);
+
+    Connection::stop(&client);
+    Connection::stop(&server);
+}
+
+#[test]
+fn connections_without_udp_never_offer_it() {
+    let (server, client) = udp_pair(UdpMgr::new());
+    // Give an offer time to arrive, were one made
+    exchange_unreliable(&server, &client);
+    assert_eq!(client.stats().udp, UdpStatus::Unavailable);
+    assert_eq!(server.stats().udp, UdpStatus::Unavailable);
+
+    Connection::stop(&client);
+    Connection::stop(&server);
+}
+
+#[test]
+fn sequence_numbers_wrap_around() {
+    assert!(is_newer(1, 0));
+    assert!(!is_newer(0, 1));
+    assert!(!is_newer(5, 5));
+    assert!(is_newer(0, std::u32::MAX));
+    assert!(is_newer(10, std::u32::MAX - 10));
+    assert!(!is_newer(std::u32::MAX, 0));
+
+    let mut sequencer = Sequencer::default();
+    let seq = |seq| Sequence { channel: 1, seq };
+    assert!(sequencer.accept(seq(std::u32::MAX - 1)));
+    assert!(sequencer.accept(seq(std::u32::MAX)));
+    assert!(sequencer.accept(seq(0)));
+    assert!(!sequencer.accept(seq(std::u32::MAX)));
+    assert!(!sequencer.accept(seq(0)));
+    assert!(sequencer.accept(seq(1)));
+    // Channels are numbered separately
+    assert!(sequencer.accept(Sequence { channel: 2, seq: 0 }));
+    assert_eq!(sequencer.next(1).seq, 0);
+    assert_eq!(sequencer.next(1).seq, 1);
+    assert_eq!(sequencer.next(2).seq, 0);
+}
+
+#[test]
+fn stale_sequenced_messages_are_dropped() {
     let serverip = PORTS.next();
-    let clientip = PORTS.next();
-    let server = UdpMgr::start_udp(mgr.clone(), &serverip, &clientip); // server has to know client ip
-    let client = UdpMgr::start_udp(mgr.clone(), &clientip, &serverip);
-    let clientclone = client.clone();
-    let serverclone = server.clone();
+    let listen = TcpListener::bind(&serverip).unwrap();
     let handle = thread::spawn(move || {
-        for i in 0..1 {
-            clientclone.send(Frame::Header { id: 123, length: 9876 }).unwrap(); //send ping
-            if i % 80 == 0 {
-                thread::sleep(Duration::from_millis(150));
-                // i cant send to much because then packages get droped by the udp UdpSocket
-                // Its still a no guarantee protocol, and we expect that 1000 packets arive without loss
-            }
+        let server = accept(&listen);
+        Connection::start(&server);
+        server
+    });
+    let client = raw_client(&serverip);
+    let server = handle.join().unwrap();
+
+    // Sequenced messages as they could arrive after crossing the network, reordered and duplicated, with the value of
+    // each one being the same as its sequence number
+    let arriving = [(1, 0), (1, 2), (1, 1), (1, 2), (2, 0), (1, 3), (2, 0), (1, 3), (2, 1)];
+    let mut packets = arriving
+        .iter()
+        .map(|&(channel, seq)| {
+            let data = TestMessage::SmallMessage { value: seq as u64 }.to_bytes().unwrap();
+            let msg = ConnectionMessage::Sequenced { channel, seq, data };
+            // The id the connection uses for its own messages
+            OutgoingPacket::new(msg.to_bytes().unwrap(), 0)
+        })
+        .collect::<Vec<_>>();
+    // An ordinary message to mark the end
+    let end = TestMessage::LargeMessage {
+        text: "end".to_string(),
+    };
+    packets.push(OutgoingPacket::new(batched(end), 1));
+    for mut packet in packets {
+        while let Ok(frame) = packet.generate_frame(1000) {
+            client.send(frame).unwrap();
         }
-        println!(" -------- finished sending");
+    }
+
+    let mut delivered = Vec::new();
+    loop {
+        match recv_in_time(&server) {
+            TestMessage::SmallMessage { value } => delivered.push(value),
+            TestMessage::LargeMessage { .. } => break,
+        }
+    }
+    // Only messages newer than everything before them on their channel get through
+    assert_eq!(delivered, vec![0, 2, 0, 3, 1]);
+    Connection::stop(&server);
+}
+
+#[test]
+fn sequenced_messages_travel_over_udp() {
+    let udp = UdpMgr::bind(&"127.0.0.1:0", fast_udp()).unwrap();
+    let (server, client) = udp_pair(udp);
+    wait_until(|| client.stats().udp == UdpStatus::Active && server.stats().udp == UdpStatus::Active);
+
+    for value in 0..10 {
+        client.send_sequenced(3, TestMessage::SmallMessage { value });
+    }
+    // Whatever arrives must be in order. Going to localhost, that includes the last one.
+    let mut last = None;
+    while last != Some(9) {
+        match recv_in_time(&server) {
+            TestMessage::SmallMessage { value } => {
+                assert!(last.map(|last| value > last).unwrap_or(true));
+                last = Some(value);
+            },
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+    }
+
+    Connection::stop(&client);
+    Connection::stop(&server);
+}
+
+type ConnResult = Result<Arc<Connection<TestMessage>>, Error>;
+
+// Connect a server and client that want `server_enc` and `client_enc`, with the server offering UDP if `udp` is
+// listening for it. Either may refuse the other.
+fn encryption_pair(udp: Arc<UdpMgr>, server_enc: Encryption, client_enc: Encryption) -> (ConnResult, ConnResult) {
+    let serverip = PORTS.next();
+    let listen = TcpListener::bind(&serverip).unwrap();
+    let handle = thread::spawn(move || {
+        let server = Connection::<TestMessage>::new_stream(listen.accept().unwrap().0, udp, server_enc);
+        if let Ok(server) = &server {
+            Connection::start(server);
+        }
+        server
     });
-    let handle2 = thread::spawn(move || {
-        for i in 0..1 {
-            println!("{}", i);
-            let frame = serverclone.recv().unwrap(); //wait for ping
-            match frame {
-                Frame::Header { id, length } => {
-                    assert_eq!(id, 123);
-                    assert_eq!(length, 9876);
-                },
-                Frame::Data { .. } => {
-                    assert!(false);
-                },
-            }
-            if i % 60 == 0 {
-                thread::sleep(Duration::from_millis(10));
+    let client = Connection::<TestMessage>::new(&serverip, UdpMgr::with_config(fast_udp()), client_enc);
+    if let Ok(client) = &client {
+        Connection::start(client);
+    }
+    (handle.join().unwrap(), client)
+}
+
+// Messages big enough to be split into many frames get through both ways
+fn exchange(server: &Connection<TestMessage>, client: &Connection<TestMessage>) {
+    client.send(TestMessage::LargeMessage {
+        text: "x".repeat(100_000),
+    });
+    client.flush();
+    match recv_in_time(server) {
+        TestMessage::LargeMessage { text } => assert_eq!(text, "x".repeat(100_000)),
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+    server.send(TestMessage::SmallMessage { value: 7 });
+    server.flush();
+    match recv_in_time(client) {
+        TestMessage::SmallMessage { value } => assert_eq!(value, 7),
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+}
+
+#[test]
+fn connections_encrypt_when_both_ends_can() {
+    let udp = UdpMgr::bind(&"127.0.0.1:0", fast_udp()).unwrap();
+    let (server, client) = encryption_pair(udp, Encryption::Required, Encryption::Preferred);
+    let (server, client) = (server.unwrap(), client.unwrap());
+    assert!(server.stats().encrypted && client.stats().encrypted);
+    exchange(&server, &client);
+    client.send_unreliable(TestMessage::SmallMessage { value: 3 });
+    client.flush();
+    match recv_in_time(&server) {
+        TestMessage::SmallMessage { value } => assert_eq!(value, 3),
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+    // Datagrams are sealed too, so UDP is used all the same
+    wait_until(|| client.stats().udp == UdpStatus::Active && server.stats().udp == UdpStatus::Active);
+    assert_eq!(client.stats().unreliable_transport, Transport::Udp);
+    client.send_unreliable(TestMessage::SmallMessage { value: 4 });
+    client.flush();
+    match recv_in_time(&server) {
+        TestMessage::SmallMessage { value } => assert_eq!(value, 4),
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+
+    Connection::stop(&client);
+    Connection::stop(&server);
+}
+
+#[test]
+fn plaintext_is_used_when_one_end_wont_encrypt_and_neither_requires_it() {
+    for &(server_enc, client_enc) in &[
+        (Encryption::Preferred, Encryption::Off),
+        (Encryption::Off, Encryption::Preferred),
+        (Encryption::Off, Encryption::Off),
+    ] {
+        let (server, client) = encryption_pair(UdpMgr::new(), server_enc, client_enc);
+        let (server, client) = (server.unwrap(), client.unwrap());
+        assert!(!server.stats().encrypted && !client.stats().encrypted);
+        exchange(&server, &client);
+
+        Connection::stop(&client);
+        Connection::stop(&server);
+    }
+}
+
+#[test]
+fn mismatched_requirements_are_refused() {
+    let mismatches = [(Encryption::Required, Encryption::Off), (Encryption::Off, Encryption::Required)];
+    for &(server_enc, client_enc) in &mismatches {
+        // Both ends see the mismatch, whichever one requires encryption
+        let (server, client) = encryption_pair(UdpMgr::new(), server_enc, client_enc);
+        for conn in vec![server, client] {
+            match conn {
+                Err(Error::EncryptionMismatch) => {},
+                conn => panic!("Expected a refusal, got {:?}", conn),
             }
         }
-        println!("finished receiving");
-    });
-    handle.join().unwrap();
-    handle2.join().unwrap();
-    UdpMgr::stop_udp(mgr.clone(), server);
-    UdpMgr::stop_udp(mgr.clone(), client);
+    }
 }
 
-//this test should not succed, it should hang. try it manual
-//#[test]
-fn udp_pingpong_2clients_negative() {
-    let mgr = UdpMgr::new();
+type Keys = (Sealer, Opener, DatagramSealer);
+
+// What each end of a real connection ends up encrypting with, the client's first
+fn handshake_pair() -> (Keys, Keys) {
     let serverip = PORTS.next();
-    let clientip = PORTS.next();
-    let clientip2 = PORTS.next();
-    let _server = UdpMgr::start_udp(mgr.clone(), &serverip, &clientip); // server has to know client ip
-    let server2 = UdpMgr::start_udp(mgr.clone(), &serverip, &clientip2); // server has to know client ip
-    let client = UdpMgr::start_udp(mgr.clone(), &clientip, &serverip);
-    let _client2 = UdpMgr::start_udp(mgr.clone(), &clientip2, &serverip);
-    client.send(Frame::Header { id: 123, length: 9876 }).unwrap(); //send ping
-    let _frame = server2.recv().unwrap(); //wait for ping from other client
-    assert!(false);
+    let listen = TcpListener::bind(&serverip).unwrap();
+    let handle = thread::spawn(move || {
+        let mut stream = listen.accept().unwrap().0;
+        crypto::handshake(&mut stream, Encryption::Preferred).unwrap().unwrap()
+    });
+    let mut stream = TcpStream::connect(&serverip).unwrap();
+    let client = crypto::handshake(&mut stream, Encryption::Preferred).unwrap().unwrap();
+    (client, handle.join().unwrap())
+}
+
+// The two halves of an encrypted stream
+fn sealed_pair() -> (Sealer, Opener) {
+    let ((sealer, _, _), (_, opener, _)) = handshake_pair();
+    (sealer, opener)
+}
+
+#[test]
+fn only_untampered_frames_open() {
+    let mut frame = Vec::new();
+    Frame::Header { id: 4, length: 12 }.encode(&mut frame);
+
+    let (mut sealer, mut opener) = sealed_pair();
+    let mut sealed = Vec::new();
+    sealer.seal(&frame, &mut sealed);
+    assert!(!sealed.windows(frame.len()).any(|window| window == &frame[..]));
+    // Bytes can arrive a few at a time
+    let (first, rest) = sealed.split_at(3);
+    assert_eq!(opener.open(first).unwrap(), Vec::<u8>::new());
+    assert_eq!(opener.open(rest).unwrap(), frame);
+    // Frames can't be sent again
+    assert!(opener.open(&sealed).is_err());
+
+    // ...or changed on the way
+    let (mut sealer, mut opener) = sealed_pair();
+    let mut changed = Vec::new();
+    sealer.seal(&frame, &mut changed);
+    let last = changed.len() - 1;
+    changed[last] ^= 1;
+    assert!(opener.open(&changed).is_err());
+
+    // ...or slipped in without being sealed
+    let (_, mut opener) = sealed_pair();
+    assert!(opener.open(&frame).is_err());
+}
+
+#[test]
+fn datagrams_open_once_in_any_order() {
+    let ((_, _, mut client), (_, _, mut server)) = handshake_pair();
+    let header = [7; 8];
+    let (first, sealed_first) = client.seal(&header, b"first");
+    let (second, sealed_second) = client.seal(&header, b"second");
+    assert!(!sealed_first.windows(5).any(|window| window == b"first"));
+
+    // Datagrams can arrive out of order, but only once each
+    assert_eq!(server.open(&header, second, &sealed_second), Some(b"second".to_vec()));
+    assert_eq!(server.open(&header, first, &sealed_first), Some(b"first".to_vec()));
+    assert_eq!(server.open(&header, first, &sealed_first), None);
+    assert_eq!(server.open(&header, second, &sealed_second), None);
+
+    // They can't be changed on the way, or have their header or number changed
+    let (third, mut changed) = client.seal(&header, b"third");
+    assert_eq!(server.open(&[8; 8], third, &changed), None);
+    assert_eq!(server.open(&header, third + 1, &changed), None);
+    changed[0] ^= 1;
+    assert_eq!(server.open(&header, third, &changed), None);
+    changed[0] ^= 1;
+    assert_eq!(server.open(&header, third, &changed), Some(b"third".to_vec()));
+
+    // Each end has its own key, so what one end sends can't be bounced back at it
+    let (fourth, sealed_fourth) = server.seal(&header, b"fourth");
+    assert_eq!(server.open(&header, fourth, &sealed_fourth), None);
+    assert_eq!(client.open(&header, fourth, &sealed_fourth), Some(b"fourth".to_vec()));
+
+    // Datagrams that fall too far behind the newest are turned away, since they can't be told apart from repeats
+    let (old, sealed_old) = client.seal(&header, b"old");
+    for _ in 0..100 {
+        client.seal(&header, b"newer");
+    }
+    let (newest, sealed_newest) = client.seal(&header, b"newest");
+    assert!(server.open(&header, newest, &sealed_newest).is_some());
+    assert_eq!(server.open(&header, old, &sealed_old), None);
+}
+
+#[test]
+fn batches_split_back_into_their_messages() {
+    let mut batch = Batch::default();
+    assert!(batch.fits(&[0; 100], 10));
+    batch.push(&[1, 2, 3]);
+    batch.push(&[]);
+    batch.push(&[4]);
+    assert_eq!(batch.len(), 3 * 4 + 4);
+    assert!(batch.fits(&[5, 6], 22));
+    assert!(!batch.fits(&[5, 6, 7], 22));
+
+    let data = batch.take();
+    assert!(batch.is_empty());
+    assert_eq!(unbatch(&data), Some(vec![&[1, 2, 3][..], &[][..], &[4][..]]));
+    assert_eq!(unbatch(&[]), Some(vec![]));
+    // Cut short, whether in a length or in a message
+    assert_eq!(unbatch(&data[..2]), None);
+    assert_eq!(unbatch(&data[..data.len() - 1]), None);
+}
+
+#[test]
+fn bursts_of_small_messages_share_frames() {
+    const BURST: u64 = 100;
+    let (server, client) = udp_pair(UdpMgr::new());
+
+    // Urgent messages aren't batched, so each costs a packet and a write of its own
+    let before = client.stats();
+    for value in 0..BURST {
+        client.send_urgent(TestMessage::SmallMessage { value });
+    }
+    let urgent = client.stats();
+    for value in 0..BURST {
+        client.send(TestMessage::SmallMessage { value });
+    }
+    client.flush();
+    let batched = client.stats();
+
+    let urgent_frames = urgent.frames_sent - before.frames_sent;
+    let batched_frames = batched.frames_sent - urgent.frames_sent;
+    assert!(batched_frames * 10 <= urgent_frames, "{} frames vs {}", batched_frames, urgent_frames);
+    let urgent_writes = urgent.writes - before.writes;
+    let batched_writes = batched.writes - urgent.writes;
+    assert!(batched_writes * 10 <= urgent_writes, "{} writes vs {}", batched_writes, urgent_writes);
+
+    // Every message arrives, in order
+    for _ in 0..2 {
+        for expected in 0..BURST {
+            match recv_in_time(&server) {
+                TestMessage::SmallMessage { value } => assert_eq!(value, expected),
+                msg => panic!("Unexpected message: {:?}", msg),
+            }
+        }
+    }
+
+    Connection::stop(&client);
+    Connection::stop(&server);
+}
+
+#[test]
+fn urgent_messages_dont_wait_for_a_flush() {
+    let (server, client) = udp_pair(UdpMgr::new());
+    client.send(TestMessage::SmallMessage { value: 1 });
+    client.send_urgent(TestMessage::SmallMessage { value: 2 });
+    match recv_in_time(&server) {
+        TestMessage::SmallMessage { value } => assert_eq!(value, 2),
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+
+    // The batched message waits for the flush
+    thread::sleep(Duration::from_millis(50));
+    assert!(server.try_recv().is_err());
+    client.flush();
+    match recv_in_time(&server) {
+        TestMessage::SmallMessage { value } => assert_eq!(value, 1),
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+
+    Connection::stop(&client);
+    Connection::stop(&server);
 }
@@ -0,0 +1,28 @@
+// Standard
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Totals across every connection this process has made or accepted
+static SENT: AtomicUsize = AtomicUsize::new(0);
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+static CORRUPT_FRAMES: AtomicUsize = AtomicUsize::new(0);
+static BAD_PACKETS: AtomicUsize = AtomicUsize::new(0);
+
+/// How many bytes this process has sent, over TCP and UDP
+pub fn bytes_sent() -> usize { SENT.load(Ordering::Relaxed) }
+
+/// How many bytes this process has received, over TCP and UDP
+pub fn bytes_received() -> usize { RECEIVED.load(Ordering::Relaxed) }
+
+/// How many frames were thrown away because they were damaged or didn't make sense
+pub fn corrupt_frames() -> usize { CORRUPT_FRAMES.load(Ordering::Relaxed) }
+
+/// How many packets arrived whole but were thrown away because they didn't hold a valid message
+pub fn bad_packets() -> usize { BAD_PACKETS.load(Ordering::Relaxed) }
+
+pub(crate) fn add_sent(bytes: usize) { SENT.fetch_add(bytes, Ordering::Relaxed); }
+
+pub(crate) fn add_received(bytes: usize) { RECEIVED.fetch_add(bytes, Ordering::Relaxed); }
+
+pub(crate) fn add_corrupt_frame() { CORRUPT_FRAMES.fetch_add(1, Ordering::Relaxed); }
+
+pub(crate) fn add_bad_packet() { BAD_PACKETS.fetch_add(1, Ordering::Relaxed); }
@@ -0,0 +1,530 @@
+// Standard
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+
+// Library
+use byteorder::{ByteOrder, LittleEndian};
+use crc::crc32;
+use mio::{net::UdpSocket, Ready, Token};
+use parking_lot::RwLock;
+
+// Parent
+use super::{
+    crypto::DatagramSealer,
+    poller::{Poller, Source},
+    sequence::Sequence,
+    traffic,
+};
+
+// Constants
+const DATAGRAM_PROBE: u8 = 1;
+const DATAGRAM_ACK: u8 = 2;
+const DATAGRAM_MESSAGE: u8 = 3;
+const DATAGRAM_SEQUENCED: u8 = 4;
+const DATAGRAM_SEALED: u8 = 5;
+// A kind byte and a token before the body, and a CRC32 of everything before it after
+const DATAGRAM_OVERHEAD: usize = 1 + 8 + 4;
+/// The biggest message that goes over UDP. Anything bigger risks being fragmented or dropped on the way, so it goes
+/// over TCP instead.
+pub const MAX_DATAGRAM_PAYLOAD: usize = 1200;
+// Enough for any datagram at all, whether or not it's one of ours
+const RECV_BUF_LEN: usize = 65536;
+
+/// How eagerly connections try to get UDP going, and how quickly they give up on it
+#[derive(Clone, Debug)]
+pub struct UdpConfig {
+    /// How often probes are sent while waiting for the first to be acknowledged
+    pub probe_interval: Duration,
+    /// How long to wait for a probe to be acknowledged before sticking with TCP
+    pub probe_timeout: Duration,
+    /// How often probes are sent once UDP works, which also keeps NAT mappings open
+    pub keepalive_interval: Duration,
+    /// How long UDP can go quiet before everything goes back to TCP
+    pub keepalive_timeout: Duration,
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        Self {
+            probe_interval: Duration::from_millis(250),
+            probe_timeout: Duration::from_secs(5),
+            keepalive_interval: Duration::from_secs(5),
+            keepalive_timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+/// How far a connection has got with UDP
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UdpStatus {
+    /// Nothing has been offered, or this end isn't listening for UDP
+    Unavailable,
+    /// Waiting for a probe to get through
+    Probing,
+    /// Probes get through, so unreliable messages go over UDP
+    Active,
+    /// No probe got through in time, so everything stays on TCP
+    Failed,
+    /// UDP worked but went quiet, so everything went back to TCP. UDP is used again once probes get through.
+    Lost,
+}
+
+/// What a message travels over
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+impl UdpStatus {
+    /// What unreliable messages travel over
+    pub fn transport(self) -> Transport {
+        match self {
+            UdpStatus::Active => Transport::Udp,
+            _ => Transport::Tcp,
+        }
+    }
+}
+
+/// Everything sent over UDP. Each datagram carries the token of the connection it belongs to, which is what routes it,
+/// since NAT may mean it comes from somewhere other than the TCP connection did.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Datagram {
+    /// Sent by the end that was offered UDP, first to see whether UDP gets through and then to keep it open
+    Probe { token: u64, seq: u64 },
+    /// Sent back for every probe that arrives
+    Ack { token: u64, seq: u64 },
+    Message {
+        token: u64,
+        // Where the message comes on its channel, if it was sent sequenced
+        sequence: Option<Sequence>,
+        data: Vec<u8>,
+    },
+    /// Any of the others, sealed, which is all an encrypted connection sends or accepts. The token is left in the
+    /// open so that the datagram can be routed, but it can't be changed without the datagram failing to open.
+    Sealed { token: u64, nonce: u64, sealed: Vec<u8> },
+}
+
+impl Datagram {
+    pub fn token(&self) -> u64 {
+        match self {
+            Datagram::Probe { token, .. }
+            | Datagram::Ack { token, .. }
+            | Datagram::Message { token, .. }
+            | Datagram::Sealed { token, .. } => *token,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![0; 9];
+        buf[1..9].copy_from_slice(&u64_bytes(self.token()));
+        match self {
+            Datagram::Probe { seq, .. } => {
+                buf[0] = DATAGRAM_PROBE;
+                buf.extend_from_slice(&u64_bytes(*seq));
+            },
+            Datagram::Ack { seq, .. } => {
+                buf[0] = DATAGRAM_ACK;
+                buf.extend_from_slice(&u64_bytes(*seq));
+            },
+            Datagram::Message { sequence, data, .. } => {
+                match sequence {
+                    Some(sequence) => {
+                        buf[0] = DATAGRAM_SEQUENCED;
+                        let mut bytes = [0; 6];
+                        LittleEndian::write_u16(&mut bytes[0..2], sequence.channel);
+                        LittleEndian::write_u32(&mut bytes[2..6], sequence.seq);
+                        buf.extend_from_slice(&bytes);
+                    },
+                    None => buf[0] = DATAGRAM_MESSAGE,
+                }
+                buf.extend_from_slice(data);
+            },
+            Datagram::Sealed { nonce, sealed, .. } => {
+                buf[0] = DATAGRAM_SEALED;
+                buf.extend_from_slice(&u64_bytes(*nonce));
+                buf.extend_from_slice(sealed);
+            },
+        }
+        let mut checksum = [0; 4];
+        LittleEndian::write_u32(&mut checksum, crc32::checksum_ieee(&buf));
+        buf.extend_from_slice(&checksum);
+        buf
+    }
+
+    /// Decode a whole datagram, or `None` if it isn't one of ours or was damaged
+    pub fn decode(buf: &[u8]) -> Option<Datagram> {
+        if buf.len() < DATAGRAM_OVERHEAD {
+            return None;
+        }
+        let (buf, checksum) = buf.split_at(buf.len() - 4);
+        if crc32::checksum_ieee(buf) != LittleEndian::read_u32(checksum) {
+            return None;
+        }
+
+        let token = LittleEndian::read_u64(&buf[1..9]);
+        let body = &buf[9..];
+        match buf[0] {
+            DATAGRAM_PROBE if body.len() == 8 => Some(Datagram::Probe {
+                token,
+                seq: LittleEndian::read_u64(body),
+            }),
+            DATAGRAM_ACK if body.len() == 8 => Some(Datagram::Ack {
+                token,
+                seq: LittleEndian::read_u64(body),
+            }),
+            DATAGRAM_MESSAGE => Some(Datagram::Message {
+                token,
+                sequence: None,
+                data: body.to_vec(),
+            }),
+            DATAGRAM_SEQUENCED if body.len() >= 6 => Some(Datagram::Message {
+                token,
+                sequence: Some(Sequence {
+                    channel: LittleEndian::read_u16(&body[0..2]),
+                    seq: LittleEndian::read_u32(&body[2..6]),
+                }),
+                data: body[6..].to_vec(),
+            }),
+            DATAGRAM_SEALED if body.len() >= 8 => Some(Datagram::Sealed {
+                token,
+                nonce: LittleEndian::read_u64(&body[0..8]),
+                sealed: body[8..].to_vec(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn u64_bytes(n: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    LittleEndian::write_u64(&mut bytes, n);
+    bytes
+}
+
+/// Something that gets the datagrams stamped with its token
+pub(crate) trait UdpPeer: Send + Sync {
+    /// Called on a poller thread for each datagram that arrives
+    fn datagram(&self, from: SocketAddr, datagram: Datagram);
+}
+
+/// A UDP socket watched by one of the pollers, which hands each datagram to the peer its token belongs to. A server
+/// shares one between all of its connections, while a client has one of its own.
+pub(crate) struct UdpEndpoint {
+    socket: UdpSocket,
+    poller: Arc<Poller>,
+    token: Token,
+    peers: RwLock<HashMap<u64, Weak<dyn UdpPeer>>>,
+}
+
+impl UdpEndpoint {
+    pub fn bind(addr: SocketAddr) -> io::Result<Arc<UdpEndpoint>> {
+        let poller = Poller::get();
+        let endpoint = Arc::new(UdpEndpoint {
+            socket: UdpSocket::bind(&addr)?,
+            token: poller.next_token(),
+            poller,
+            peers: RwLock::new(HashMap::new()),
+        });
+        endpoint
+            .poller
+            .register(&endpoint.socket, endpoint.token, endpoint.clone())?;
+        Ok(endpoint)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> { self.socket.local_addr() }
+
+    pub fn add_peer(&self, token: u64, peer: Weak<dyn UdpPeer>) { self.peers.write().insert(token, peer); }
+
+    pub fn remove_peer(&self, token: u64) { self.peers.write().remove(&token); }
+
+    /// Send a datagram, unless the socket can't take it right now. Nothing sent over UDP is sure to arrive anyway.
+    pub fn send(&self, to: SocketAddr, datagram: &Datagram) {
+        match self.socket.send_to(&datagram.encode(), &to) {
+            Ok(n) => traffic::add_sent(n),
+            Err(e) => debug!("Could not send a datagram to {}: {}", to, e),
+        }
+    }
+
+    /// Stop receiving. The poller keeps the endpoint alive until this is called.
+    pub fn close(&self) { self.poller.deregister(&self.socket, self.token); }
+
+    fn dispatch(&self, from: SocketAddr, bytes: &[u8]) {
+        let datagram = match Datagram::decode(bytes) {
+            Some(datagram) => datagram,
+            None => {
+                debug!("Discarding a malformed datagram from {}", from);
+                traffic::add_corrupt_frame();
+                return;
+            },
+        };
+
+        // Not held while the peer runs, since it may remove itself
+        let peer = self.peers.read().get(&datagram.token()).and_then(Weak::upgrade);
+        match peer {
+            Some(peer) => peer.datagram(from, datagram),
+            None => debug!("Discarding a datagram from {} with an unknown token", from),
+        }
+    }
+}
+
+impl Source for UdpEndpoint {
+    fn ready(&self, readiness: Ready) {
+        if !readiness.is_readable() {
+            return;
+        }
+
+        let mut buf = vec![0; RECV_BUF_LEN];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((n, from)) => {
+                    traffic::add_received(n);
+                    self.dispatch(from, &buf[..n]);
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                // Some platforms report an earlier datagram bouncing here, which doesn't stop the socket working
+                Err(ref e) if e.kind() == ErrorKind::Interrupted || e.kind() == ErrorKind::ConnectionReset => {},
+                Err(e) => {
+                    error!("UDP socket failed: {:?}", e);
+                    break;
+                },
+            }
+        }
+    }
+
+    fn abort(&self) { self.peers.write().clear(); }
+}
+
+impl fmt::Debug for UdpEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UdpEndpoint")
+            .field("addr", &self.local_addr().ok())
+            .field("peers", &self.peers.read().len())
+            .finish()
+    }
+}
+
+/// One connection's end of the UDP handshake. The end that accepted the TCP connection offers UDP by sending a token
+/// over TCP. The other end stamps probes with it and sends them to the port offered, and UDP gets used once one is
+/// acknowledged. Probes carry on as keepalives after that, and if they stop getting through both ends go back to TCP
+/// until they do again.
+///
+/// On an encrypted connection, every datagram is sealed with its own keys, and any that doesn't open is ignored.
+#[derive(Debug)]
+pub(crate) struct UdpLink {
+    config: UdpConfig,
+    status: UdpStatus,
+    token: u64,
+    endpoint: Option<Arc<UdpEndpoint>>,
+    // Whether this end made the offer, in which case it answers probes rather than sending them
+    offered: bool,
+    // Where datagrams go. For the end that made the offer, that's wherever probes come from.
+    remote: Option<SocketAddr>,
+    started: Instant,
+    last_probe: Instant,
+    last_heard: Instant,
+    seq: u64,
+    // What datagrams are sealed and opened with, if the connection is encrypted
+    sealer: Option<DatagramSealer>,
+}
+
+impl UdpLink {
+    pub fn new(config: UdpConfig, sealer: Option<DatagramSealer>) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            status: UdpStatus::Unavailable,
+            token: 0,
+            endpoint: None,
+            offered: false,
+            remote: None,
+            started: now,
+            last_probe: now,
+            last_heard: now,
+            seq: 0,
+            sealer,
+        }
+    }
+
+    pub fn status(&self) -> UdpStatus { self.status }
+
+    /// Offer UDP through `endpoint`, returning the token the other end should stamp its probes with
+    pub fn offer(&mut self, endpoint: Arc<UdpEndpoint>, peer: Weak<dyn UdpPeer>) -> u64 {
+        let token = rand::random();
+        endpoint.add_peer(token, peer);
+        self.token = token;
+        self.endpoint = Some(endpoint);
+        self.offered = true;
+        self.start();
+        token
+    }
+
+    /// Take up an offer of UDP, probing `remote` from a socket of our own
+    pub fn accept(&mut self, token: u64, remote: SocketAddr, peer: Weak<dyn UdpPeer>) -> io::Result<()> {
+        if self.status != UdpStatus::Unavailable {
+            return Ok(());
+        }
+
+        let any = match remote {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let endpoint = UdpEndpoint::bind(SocketAddr::new(any, 0))?;
+        endpoint.add_peer(token, peer);
+        self.token = token;
+        self.endpoint = Some(endpoint);
+        self.remote = Some(remote);
+        self.start();
+        self.probe();
+        Ok(())
+    }
+
+    fn start(&mut self) {
+        self.status = UdpStatus::Probing;
+        self.started = Instant::now();
+    }
+
+    fn probe(&mut self) {
+        if let Some(remote) = self.remote.filter(|_| self.endpoint.is_some()) {
+            self.seq += 1;
+            let probe = Datagram::Probe {
+                token: self.token,
+                seq: self.seq,
+            };
+            self.send_datagram(remote, probe);
+        }
+        self.last_probe = Instant::now();
+    }
+
+    // Send a datagram through our endpoint, sealing it first if the connection is encrypted
+    fn send_datagram(&mut self, to: SocketAddr, datagram: Datagram) {
+        let datagram = match &mut self.sealer {
+            Some(sealer) => {
+                let (nonce, sealed) = sealer.seal(&u64_bytes(self.token), &datagram.encode());
+                Datagram::Sealed {
+                    token: self.token,
+                    nonce,
+                    sealed,
+                }
+            },
+            None => datagram,
+        };
+        if let Some(endpoint) = &self.endpoint {
+            endpoint.send(to, &datagram);
+        }
+    }
+
+    /// Handle a datagram stamped with our token, returning the message it carried if there was one, and where it comes
+    /// on its channel if it was sent sequenced
+    pub fn received(&mut self, from: SocketAddr, datagram: Datagram) -> Option<(Option<Sequence>, Vec<u8>)> {
+        let datagram = match (&mut self.sealer, datagram) {
+            (Some(sealer), Datagram::Sealed { token, nonce, sealed }) => {
+                let opened = sealer
+                    .open(&u64_bytes(token), nonce, &sealed)
+                    .and_then(|data| Datagram::decode(&data))
+                    .filter(|inner| inner.token() == token);
+                match opened {
+                    Some(inner) => inner,
+                    None => {
+                        debug!("Discarding a datagram from {} that didn't open", from);
+                        return None;
+                    },
+                }
+            },
+            // Sealed datagrams on a link that isn't encrypted are ignored below
+            (None, datagram) => datagram,
+            (Some(_), _) => {
+                debug!("Discarding an unsealed datagram from {} on an encrypted connection", from);
+                return None;
+            },
+        };
+
+        match datagram {
+            Datagram::Probe { seq, .. } if self.offered => {
+                self.remote = Some(from);
+                self.heard();
+                let ack = Datagram::Ack {
+                    token: self.token,
+                    seq,
+                };
+                self.send_datagram(from, ack);
+                None
+            },
+            Datagram::Ack { .. } if !self.offered && self.remote == Some(from) => {
+                self.heard();
+                None
+            },
+            Datagram::Message { sequence, data, .. } if self.remote == Some(from) => {
+                self.last_heard = Instant::now();
+                Some((sequence, data))
+            },
+            _ => None,
+        }
+    }
+
+    fn heard(&mut self) {
+        if self.status != UdpStatus::Active {
+            info!("UDP is getting through to {:?}", self.remote);
+        }
+        self.status = UdpStatus::Active;
+        self.last_heard = Instant::now();
+    }
+
+    /// Send probes that are due, and notice if UDP has gone quiet
+    pub fn tick(&mut self) {
+        match self.status {
+            UdpStatus::Probing => {
+                if self.started.elapsed() >= self.config.probe_timeout {
+                    info!("No UDP probes got through to {:?}, so everything stays on TCP", self.remote);
+                    self.status = UdpStatus::Failed;
+                    self.close();
+                } else if !self.offered && self.last_probe.elapsed() >= self.config.probe_interval {
+                    self.probe();
+                }
+            },
+            UdpStatus::Active | UdpStatus::Lost => {
+                if self.status == UdpStatus::Active && self.last_heard.elapsed() >= self.config.keepalive_timeout {
+                    warn!("UDP to {:?} went quiet, so everything goes over TCP for now", self.remote);
+                    self.status = UdpStatus::Lost;
+                }
+                if !self.offered && self.last_probe.elapsed() >= self.config.keepalive_interval {
+                    self.probe();
+                }
+            },
+            UdpStatus::Unavailable | UdpStatus::Failed => {},
+        }
+    }
+
+    /// Send a message over UDP if it's working and the message is small enough, returning whether it was sent
+    pub fn send(&mut self, sequence: Option<Sequence>, data: &[u8]) -> bool {
+        match (self.status, self.endpoint.is_some(), self.remote) {
+            (UdpStatus::Active, true, Some(remote)) if data.len() <= MAX_DATAGRAM_PAYLOAD => {
+                let message = Datagram::Message {
+                    token: self.token,
+                    sequence,
+                    data: data.to_vec(),
+                };
+                self.send_datagram(remote, message);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Stop using UDP
+    pub fn close(&mut self) {
+        if let Some(endpoint) = self.endpoint.take() {
+            endpoint.remove_peer(self.token);
+            // A server's endpoint is shared, but the other end's is its own
+            if !self.offered {
+                endpoint.close();
+            }
+        }
+    }
+}
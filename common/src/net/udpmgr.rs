@@ -1,139 +1,61 @@
 // Standard
 use std::{
-    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    io,
+    net::{SocketAddr, ToSocketAddrs},
     sync::Arc,
-    thread::{self, JoinHandle},
 };
 
-// Library
-use parking_lot::RwLock;
-
 // Parent
-use super::udp::Udp;
-
-#[derive(Debug)]
-struct UdpInfo {
-    socket_info: Arc<SocketInfo>,
-    remote: SocketAddr,
-    udp: Arc<Udp>,
-}
-
-#[derive(Debug)]
-struct SocketInfo {
-    socket: UdpSocket,
-    recv_thread: JoinHandle<()>,
-}
+use super::{
+    udp_link::{UdpConfig, UdpEndpoint},
+    Error,
+};
 
 #[derive(Debug)]
 pub struct UdpMgr {
-    subscriber: RwLock<Vec<UdpInfo>>,
-    sockets: RwLock<Vec<Arc<SocketInfo>>>,
+    // Where connections accepted with this manager are offered UDP
+    endpoint: Option<Arc<UdpEndpoint>>,
+    config: UdpConfig,
 }
 
-// One Socket can handle multiple receivers, thats why we need a Manager here
-// Receiving can only be done onces and must be routed to the correct recveiver
+// One socket serves every connection, so a manager shares it between them and routes what it receives
 impl UdpMgr {
-    pub fn new() -> Arc<UdpMgr> {
-        Arc::new(UdpMgr {
-            subscriber: RwLock::new(Vec::new()),
-            sockets: RwLock::new(Vec::new()),
-        })
+    pub fn new() -> Arc<UdpMgr> { UdpMgr::with_config(UdpConfig::default()) }
+
+    /// A manager for connections that take up UDP when it's offered, but don't offer it themselves
+    pub fn with_config(config: UdpConfig) -> Arc<UdpMgr> { Arc::new(UdpMgr::create(None, config)) }
+
+    /// A manager that offers UDP on `addr` to every connection accepted with it
+    pub fn bind<A: ToSocketAddrs>(addr: &A, config: UdpConfig) -> Result<Arc<UdpMgr>, Error> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address to listen for UDP on"))?;
+        Ok(Arc::new(UdpMgr::create(Some(UdpEndpoint::bind(addr)?), config)))
     }
 
-    pub fn start_udp<A: ToSocketAddrs>(mgr: Arc<UdpMgr>, listen: &A, remote: &A) -> Arc<Udp> {
-        let mut socket_info = None;
-        let listen = listen.to_socket_addrs().unwrap().next().unwrap();
-        let remote = remote.to_socket_addrs().unwrap().next().unwrap();
-        {
-            let subscriber = mgr.subscriber.read();
-            for c in &(*subscriber) {
-                if c.socket_info.socket.local_addr().unwrap() == listen {
-                    socket_info = Some(c.socket_info.clone());
-                    break;
-                }
-            }
+    fn create(endpoint: Option<Arc<UdpEndpoint>>, config: UdpConfig) -> UdpMgr {
+        UdpMgr {
+            endpoint,
+            config,
         }
+    }
 
-        if let None = socket_info {
-            // if non eist for this socket create
-            let socket = UdpSocket::bind(listen).unwrap();
-            let socketclone = socket.try_clone().unwrap();
-            let mgrclone = mgr.clone();
-            let recv_thread = thread::spawn(move || {
-                mgrclone.recv_worker_udp(socketclone);
-            });
-            let socketclone = socket.try_clone().unwrap();
-            let si = Arc::new(SocketInfo {
-                socket: socketclone,
-                recv_thread,
-            });
-            mgr.sockets.write().push(si.clone());
-            socket_info = Some(si.clone());
-            debug!("listen on new udp socket, started a new thread {}", listen);
-        }
-        let socket_info = socket_info.unwrap();
+    pub(crate) fn endpoint(&self) -> Option<&Arc<UdpEndpoint>> { self.endpoint.as_ref() }
 
-        let udp = Arc::new(Udp::new_stream(socket_info.socket.try_clone().unwrap(), remote).unwrap());
-        debug!("created udp listnen on {} for remote {}", listen, remote);
-        let ui = UdpInfo {
-            socket_info,
-            remote,
-            udp: udp.clone(),
-        };
+    pub fn config(&self) -> &UdpConfig { &self.config }
 
-        mgr.subscriber.write().push(ui);
-        return udp.clone();
-        /*
-        manager.send(ConnectionMessage::OpenedUdp{ host: listen });*/
-    }
+    /// Where UDP is offered, if it is
+    pub fn local_addr(&self) -> Option<SocketAddr> { self.endpoint.as_ref().and_then(|e| e.local_addr().ok()) }
 
-    pub fn stop_udp(mgr: Arc<UdpMgr>, udp: Arc<Udp>) {
-        let mut subscriber = mgr.subscriber.write();
-        let _sockets = mgr.sockets.write();
-        let mut udp_info = None;
-        for s in subscriber.iter() {
-            if Arc::ptr_eq(&s.udp, &udp) {
-                udp_info = Some(s);
-                break;
-            }
-        }
-        let udp_info = udp_info.expect("udp does not exist");
-        let mut socketusers = 0;
-        for ref s in subscriber.iter() {
-            if Arc::ptr_eq(&s.socket_info, &udp_info.socket_info) {
-                socketusers += 1;
-            }
+    /// Stop listening for UDP. Connections that were using it go back to TCP once they notice.
+    pub fn close(&self) {
+        if let Some(endpoint) = &self.endpoint {
+            endpoint.close();
         }
-        if socketusers == 1 {
-            // stop socket
-            //actually i am to lazy to stop them now. sorry
-        }
-        let index = subscriber
-            .iter()
-            .position(|x| Arc::ptr_eq(&x.udp, &udp_info.udp))
-            .unwrap();
-        subscriber.remove(index);
     }
+}
 
-    fn recv_worker_udp(&self, socket: UdpSocket) {
-        loop {
-            const MAX_UDP_SIZE: usize = 65535; // might not work in IPv6 Jumbograms
-                                               //TODO: not read multiple frames and drop all but the first here
-            let mut buff = vec![0; MAX_UDP_SIZE];
-            let (size, remote) = socket.recv_from(&mut buff).unwrap();
-            buff.resize(size, 0);
-            println!("rcved sth of  {} bytes on {}", size, socket.local_addr().unwrap());
-            let subscriber = self.subscriber.read();
-            for c in subscriber.iter() {
-                if remote == c.remote && socket.local_addr().unwrap() == c.socket_info.socket.local_addr().unwrap() {
-                    println!(
-                        "forwarded it {} - {}",
-                        c.remote,
-                        c.socket_info.socket.local_addr().unwrap()
-                    );
-                    c.udp.received_raw_packet(&buff);
-                }
-            }
-        }
-    }
+impl Drop for UdpMgr {
+    fn drop(&mut self) { self.close(); }
 }
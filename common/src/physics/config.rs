@@ -0,0 +1,209 @@
+// Library
+use serde_derive::{Deserialize, Serialize};
+use vek::*;
+
+// Project
+use crate::{ecs::phys::MoveMode, physics::physics::LENGTH_OF_BLOCK};
+
+/// How entities move. The server decides this and sends it to every client, since the client has to predict its
+/// player's movement the same way the server would. Distances are in blocks and times in seconds.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    /// Pull downwards, in blocks per second per second. Negative pulls down.
+    pub gravity: f32,
+    /// How high a jump from the ground reaches
+    pub jump_height: f32,
+    /// How long after walking off a ledge an entity can still jump
+    pub coyote_time: f32,
+    /// The fastest anything falls
+    pub terminal_velocity: f32,
+    /// How fast an entity climbs onto a block it walks into
+    pub block_hop_speed: f32,
+    /// How hard entities push themselves along when walking, in blocks per second per second
+    pub acceleration: Vec3<f32>,
+    /// How much of `acceleration` an entity gets off the ground
+    pub control_in_air: Vec3<f32>,
+    pub control_in_water: Vec3<f32>,
+    /// How much of an entity's velocity is left after a second of friction
+    pub friction_on_ground: Vec3<f32>,
+    pub friction_in_air: Vec3<f32>,
+    pub friction_in_water: Vec3<f32>,
+    // Recordings hold the config as it's laid out here, so new settings go at the end, along with a new
+    // `RECORDING_VERSION`
+    /// How much faster than walking sprinting entities move
+    pub sprint_speed: f32,
+    /// How much faster than walking crouching entities move, which is less than 1 since they're slower
+    pub crouch_speed: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        PhysicsConfig {
+            gravity: -9.81 / LENGTH_OF_BLOCK,
+            // Enough to hop up onto a block
+            jump_height: 1.4,
+            coyote_time: 0.1,
+            terminal_velocity: 60.0,
+            block_hop_speed: 15.0,
+            acceleration: Vec3::new(24.0, 24.0, 28.0) / LENGTH_OF_BLOCK,
+            control_in_air: Vec3::new(0.17, 0.17, 0.0),
+            control_in_water: Vec3::new(0.05, 0.05, 0.09),
+            friction_on_ground: Vec3::broadcast(0.0015),
+            friction_in_air: Vec3::new(0.2, 0.2, 0.95),
+            friction_in_water: Vec3::new(0.6, 0.6, 0.3),
+            sprint_speed: 1.6,
+            crouch_speed: 0.4,
+        }
+    }
+}
+
+impl PhysicsConfig {
+    /// How much faster than walking an entity moving in `mode` goes
+    pub fn speed_factor(&self, mode: MoveMode) -> f32 {
+        match mode {
+            MoveMode::Walk => 1.0,
+            MoveMode::Sprint => self.sprint_speed,
+            MoveMode::Crouch => self.crouch_speed,
+        }
+    }
+
+    /// How fast an entity leaves the ground when it jumps, which gravity slows to a stop at exactly `jump_height`
+    pub fn jump_speed(&self) -> f32 { (2.0 * -self.gravity * self.jump_height).max(0.0).sqrt() }
+
+    /// The fastest an entity can move horizontally under its own power, whether on the ground, in the air or in water
+    pub fn top_speed(&self) -> f32 {
+        let push = self.acceleration.x.max(self.acceleration.y) * self.sprint_speed.max(self.crouch_speed).max(1.0);
+        let mediums = [
+            (1.0, self.friction_on_ground),
+            (self.control_in_air.x.max(self.control_in_air.y), self.friction_in_air),
+            (self.control_in_water.x.max(self.control_in_water.y), self.friction_in_water),
+        ];
+        mediums
+            .iter()
+            .map(|(control, friction)| settled_speed(push * control, friction.x.max(friction.y)))
+            .fold(0.0, f32::max)
+    }
+
+    /// The fastest an entity can swim upwards
+    pub fn swim_speed(&self) -> f32 {
+        settled_speed(self.acceleration.z * self.control_in_water.z, self.friction_in_water.z)
+    }
+
+    /// The names of the settings, as used by `set`
+    pub const FIELDS: &'static [&'static str] = &[
+        "gravity",
+        "jump_height",
+        "coyote_time",
+        "terminal_velocity",
+        "block_hop_speed",
+        "acceleration",
+        "sprint_speed",
+        "crouch_speed",
+        "control_in_air",
+        "control_in_water",
+        "friction_on_ground",
+        "friction_in_air",
+        "friction_in_water",
+    ];
+
+    /// The current value of the setting called `name`, formatted for display
+    pub fn get(&self, name: &str) -> Option<String> {
+        let vec = |v: Vec3<f32>| Some(format!("{} {} {}", v.x, v.y, v.z));
+        match name {
+            "gravity" => Some(self.gravity.to_string()),
+            "jump_height" => Some(self.jump_height.to_string()),
+            "coyote_time" => Some(self.coyote_time.to_string()),
+            "terminal_velocity" => Some(self.terminal_velocity.to_string()),
+            "block_hop_speed" => Some(self.block_hop_speed.to_string()),
+            "acceleration" => vec(self.acceleration),
+            "sprint_speed" => Some(self.sprint_speed.to_string()),
+            "crouch_speed" => Some(self.crouch_speed.to_string()),
+            "control_in_air" => vec(self.control_in_air),
+            "control_in_water" => vec(self.control_in_water),
+            "friction_on_ground" => vec(self.friction_on_ground),
+            "friction_in_air" => vec(self.friction_in_air),
+            "friction_in_water" => vec(self.friction_in_water),
+            _ => None,
+        }
+    }
+
+    /// Change the setting called `name`. Vector settings take three values, or one to use for all three.
+    pub fn set(&mut self, name: &str, values: &[f32]) -> Result<(), String> {
+        let mut new = *self;
+        let vec = match values {
+            [v] => Ok(Vec3::broadcast(*v)),
+            [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+            _ => Err(format!("'{}' takes one value, or three for x, y and z", name)),
+        };
+        let scalar = match values {
+            [v] => Ok(*v),
+            _ => Err(format!("'{}' takes a single value", name)),
+        };
+        match name {
+            "gravity" => new.gravity = scalar?,
+            "jump_height" => new.jump_height = scalar?,
+            "coyote_time" => new.coyote_time = scalar?,
+            "terminal_velocity" => new.terminal_velocity = scalar?,
+            "block_hop_speed" => new.block_hop_speed = scalar?,
+            "acceleration" => new.acceleration = vec?,
+            "sprint_speed" => new.sprint_speed = scalar?,
+            "crouch_speed" => new.crouch_speed = scalar?,
+            "control_in_air" => new.control_in_air = vec?,
+            "control_in_water" => new.control_in_water = vec?,
+            "friction_on_ground" => new.friction_on_ground = vec?,
+            "friction_in_air" => new.friction_in_air = vec?,
+            "friction_in_water" => new.friction_in_water = vec?,
+            _ => return Err(format!("There's no physics setting called '{}'", name)),
+        }
+        new.validate()?;
+        *self = new;
+        Ok(())
+    }
+
+    /// Check that entities could actually move under this config
+    pub fn validate(&self) -> Result<(), String> {
+        let scalars = [
+            ("jump_height", self.jump_height),
+            ("coyote_time", self.coyote_time),
+            ("terminal_velocity", self.terminal_velocity),
+            ("block_hop_speed", self.block_hop_speed),
+            ("sprint_speed", self.sprint_speed),
+            ("crouch_speed", self.crouch_speed),
+        ];
+        let frictions = [
+            ("friction_on_ground", self.friction_on_ground),
+            ("friction_in_air", self.friction_in_air),
+            ("friction_in_water", self.friction_in_water),
+        ];
+
+        if !self.gravity.is_finite() {
+            return Err("gravity has to be a number".to_string());
+        }
+        if let Some((name, _)) = scalars.iter().find(|(_, v)| !v.is_finite() || *v < 0.0) {
+            return Err(format!("{} can't be negative", name));
+        }
+        let vecs = [self.acceleration, self.control_in_air, self.control_in_water];
+        if vecs.iter().any(|v| v.into_array().iter().any(|e| !e.is_finite())) {
+            return Err("acceleration and control have to be numbers".to_string());
+        }
+        // Friction is raised to the power of each tick's duration, which only makes sense between 0 and 1
+        if let Some((name, _)) = frictions
+            .iter()
+            .find(|(_, v)| v.into_array().iter().any(|e| !(*e > 0.0 && *e <= 1.0)))
+        {
+            return Err(format!("{} has to be more than 0 and at most 1", name));
+        }
+        Ok(())
+    }
+}
+
+/// The speed at which `friction` takes away as much as `acc` adds each second, which is as fast as pushing with `acc`
+/// alone gets an entity
+fn settled_speed(acc: f32, friction: f32) -> f32 {
+    if acc <= 0.0 {
+        0.0
+    } else {
+        acc / -friction.ln()
+    }
+}
@@ -1,4 +1,5 @@
 pub mod collision;
+pub mod config;
 pub mod movement;
 pub mod physics;
 #[cfg(test)]
@@ -7,6 +7,10 @@ use crate::{
     Uid,
 };
 
+// Constants
+// How many times a body may hit something and slide along it in one tick
+pub const MAX_SUBSTEPS: usize = 4;
+
 #[derive(PartialEq, Clone, Debug)]
 pub struct MovingBody {
     pub id: Uid,   // to keep the relation between Moveable and Entity
@@ -15,6 +19,8 @@ pub struct MovingBody {
     pub velocity: Vec3<f32>,
 }
 
+// Touching and Overlapping results are ignored: touching something we're moving alongside doesn't stop us (so that
+// resting on the ground doesn't jitter), and overlaps are resolved by `depenetrate` before moving
 fn handle_res(r: Option<ResolutionTti>, tti: &mut f32, normal: &mut Vec3<f32>) {
     if let Some(ResolutionTti::WillCollide {
         tti: ltti,
@@ -44,8 +50,27 @@ pub fn limit_entity_movement(planed: Vec3<f32>) -> Vec3<f32> {
     return res;
 }
 
+/// Push a primitive out of any terrain it's inside, e.g: because it was spawned there. Returns `false` if it's still
+/// inside something afterwards.
+pub fn depenetrate(primitive: &mut Primitive, nearby: &[Primitive]) -> bool {
+    for _ in 0..MAX_SUBSTEPS {
+        let correction = nearby
+            .iter()
+            .filter_map(|prim| prim.resolve_col(primitive))
+            .map(|res| res.correction)
+            .find(|correction| correction.magnitude() >= PLANCK_LENGTH);
+        match correction {
+            Some(correction) => primitive.move_by(&correction),
+            None => return true,
+        }
+    }
+    false
+}
+
 // This function will check every moveable against all old_primitives and against their own Vector of primitives
-// After that primitives is modified to the new state after dt
+// After that primitives is modified to the new state after dt.
+// Movement is swept: a body only ever moves as far as the first thing in its way, then slides along it with what's
+// left of its movement, so it can't pass through anything however fast it goes.
 
 pub fn movement_tick<
     'a,
@@ -57,10 +82,12 @@ pub fn movement_tick<
     dt: f32,
 ) {
     for (m, nearby) in to_move {
+        depenetrate(&mut m.primitive, nearby);
+
         let mut length = m.velocity * dt;
 
-        // movement can be executed in max 3 steps because we are using TTI
-        for _ in 0..3 {
+        // movement can be executed in max MAX_SUBSTEPS steps because we are using TTI
+        for _ in 0..MAX_SUBSTEPS {
             if length.magnitude() < PLANCK_LENGTH {
                 break;
             }
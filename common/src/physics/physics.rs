@@ -7,22 +7,24 @@ use vek::*;
 
 // Project
 use crate::{
+    ecs::phys::MoveMode,
     physics::{
         collision::{Primitive, ResolutionTti, PLANCK_LENGTH},
+        config::PhysicsConfig,
         movement::{limit_entity_movement, movement_tick, MovingBody},
     },
-    terrain::{VoxAbs, Voxel},
+    terrain::{chunk::Block, ReadVolume, Volume, VoxAbs, Voxel},
 };
 
 use crate::Uid;
 
 // Local
-use crate::terrain::{ChunkMgr, Entity};
+use crate::terrain::{BodyState, ChunkMgr, Entity};
 
 pub const LENGTH_OF_BLOCK: f32 = 0.3;
-const GROUND_GRAVITY: f32 = -9.81;
 const BLOCK_SIZE_PLUS_SMALL: f32 = 1.0 + PLANCK_LENGTH;
-const BLOCK_HOP_SPEED: f32 = 15.0;
+// Keeps blocks that are merely touching an entity from counting as in the way of it growing
+const HEADROOM_MARGIN: f32 = 0.01;
 
 fn adjust_box(low: &mut Vec3<f32>, high: &mut Vec3<f32>, dir: Vec3<f32>) {
     // if dir is lower that low adjust low so that dir fits in. Accordingly if dir is higher than high.
@@ -54,6 +56,17 @@ fn get_nearby(col: &Primitive, velocities: &[Vec3<f32>]) -> (/*low:*/ Vec3<VoxAb
     (low, high)
 }
 
+// The box taken up by an entity of `size` standing at `pos`
+fn body_prim(pos: Vec3<f32>, size: Vec3<f32>) -> Primitive {
+    Primitive::new_cuboid(pos + Vec3::unit_z() * size.z / 2.0, size / 2.0)
+}
+
+// Whether an entity at `pos` has room to be `size`
+fn has_room(pos: Vec3<f32>, size: Vec3<f32>, nearby: &[Primitive]) -> bool {
+    let body = Primitive::new_cuboid(pos + Vec3::unit_z() * size.z / 2.0, size / 2.0 - HEADROOM_MARGIN);
+    nearby.iter().all(|prim| prim.resolve_col(&body).is_none())
+}
+
 #[allow(non_snake_case)]
 pub fn tick<
     'a,
@@ -63,92 +76,92 @@ pub fn tick<
 >(
     entities: I,
     chunk_mgr: &ChunkMgr<CP>,
+    config: &PhysicsConfig,
     dt: Duration,
 ) {
-    const ENTITY_MIDDLE_OFFSET: Vec3<f32> = Vec3 { x: 0.0, y: 0.0, z: 0.9 };
-    const ENTITY_RADIUS: Vec3<f32> = Vec3 {
-        x: 0.45,
-        y: 0.45,
-        z: 0.9,
-    };
     const BLOCK_MIDDLE: Vec3<f32> = Vec3 { x: 0.5, y: 0.5, z: 0.5 };
-    const ENTITY_ACC: Vec3<f32> = Vec3 {
-        x: 24.0 / LENGTH_OF_BLOCK,
-        y: 24.0 / LENGTH_OF_BLOCK,
-        z: 28.0 / LENGTH_OF_BLOCK,
-    };
     const SMALLER_THAN_BLOCK_GOING_DOWN: Vec3<f32> = Vec3 {
         x: 0.0,
         y: 0.0,
         z: -0.1,
     };
-    const CONTROL_IN_AIR: Vec3<f32> = Vec3 {
-        x: 0.17,
-        y: 0.17,
-        z: 0.0,
-    };
-    const CONTROL_IN_WATER: Vec3<f32> = Vec3 {
-        x: 0.05,
-        y: 0.05,
-        z: 0.09,
-    };
-    const FRICTION_ON_GROUND: Vec3<f32> = Vec3 {
-        x: 0.0015,
-        y: 0.0015,
-        z: 0.0015,
-    };
-    const FRICTION_IN_AIR: Vec3<f32> = Vec3 {
-        x: 0.2,
-        y: 0.2,
-        z: 0.95,
-    };
-    const FRICTION_IN_WATER: Vec3<f32> = Vec3 {
-        x: 0.60,
-        y: 0.60,
-        z: 0.30,
-    };
 
     let dt = dt.as_float_secs() as f32;
     let mut moving_bodies = HashMap::new(); // This function will check every colidable against all other colidable and against their own Vector of primitives
     let mut obstacles = HashMap::new();
+    let mut states = HashMap::new();
+    let mut sizes = HashMap::new();
 
     for (id, entity) in entities.clone() {
         let entity = entity.read();
 
-        let middle = *entity.pos() + ENTITY_MIDDLE_OFFSET;
-        let entity_prim = Primitive::new_cuboid(middle, ENTITY_RADIUS);
-
-        let wanted_ctrl_acc = limit_entity_movement(*entity.ctrl_acc()) * ENTITY_ACC;
-        let wanted_offs_vel = wanted_ctrl_acc * dt;
+        // Look for nearby blocks as if at full size and speed, which covers everywhere the entity could get to
+        let full_size = entity.collision_box().0.map2(entity.body_size(), |a, b| a.max(b));
+        let standing_prim = body_prim(*entity.pos(), full_size);
+        let max_speed = config.speed_factor(entity.move_mode()).max(1.0);
+        let max_offs_vel = limit_entity_movement(*entity.ctrl_acc()) * config.acceleration * max_speed * dt;
 
-        let gravity = Vec3::new(0.0, 0.0, GROUND_GRAVITY / LENGTH_OF_BLOCK);
+        let gravity = Vec3::new(0.0, 0.0, config.gravity);
         //let gravity = Vec3::new(0.0,0.0,GROUND_GRAVITY/(1.0+E.powf(middle.z as f64 / 120.0/*adjust this to make gravity last longer in the upper areas*/-3.5/*constant move 1/(1+e^x) to the 1-0 range*/) as f32) / LENGTH_OF_BLOCK );
-        let velocities = [*entity.vel() + wanted_offs_vel, gravity * dt];
+        let velocities = [*entity.vel() + max_offs_vel, gravity * dt];
 
-        let (low, high) = get_nearby(&entity_prim, &velocities);
+        let (low, high) = get_nearby(&standing_prim, &velocities);
         let volsample = chunk_mgr.try_get_sample(low, high);
         if let Err(_) = volsample {
             continue; //skip this entity, because not all chunks are loaded
         }
         let volsample = volsample.unwrap();
+        let size = volsample.size();
+        let mut blocks = vec![Block::empty(); size.map(|e| e as usize).product()];
+        volsample.fill_slice(Vec3::new(0, 0, 0), size, &mut blocks);
         let mut nearby_primitives = Vec::new();
         let mut nearby_primitives_fluid = Vec::new();
-        for (pos, b) in volsample.iter() {
-            if b.is_solid() {
-                nearby_primitives.push(Primitive::new_cuboid(
-                    pos.map(|e| e as f32) + BLOCK_MIDDLE,
-                    BLOCK_MIDDLE,
-                ));
-            }
-            if b.is_fluid() {
-                nearby_primitives_fluid.push(Primitive::new_cuboid(
-                    pos.map(|e| e as f32) + BLOCK_MIDDLE,
-                    BLOCK_MIDDLE,
-                ));
+        let mut blocks = blocks.into_iter();
+        for x in 0..size.x {
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    let b = blocks.next().unwrap();
+                    let pos = low + Vec3::new(x, y, z).map(|e| e as VoxAbs);
+                    if b.is_solid() {
+                        nearby_primitives.push(Primitive::new_cuboid(
+                            pos.map(|e| e as f32) + BLOCK_MIDDLE,
+                            BLOCK_MIDDLE,
+                        ));
+                    }
+                    if b.is_fluid() {
+                        nearby_primitives_fluid.push(Primitive::new_cuboid(
+                            pos.map(|e| e as f32) + BLOCK_MIDDLE,
+                            BLOCK_MIDDLE,
+                        ));
+                    }
+                }
             }
         }
 
-        // is standing on ground to jump
+        // An entity only grows, whether by standing up or by being made bigger, once there's room for it. A crouched
+        // entity stays crouched until then, and moves at crouching speed.
+        let wanted = entity.collision_box().size(entity.move_mode());
+        let current = entity.body_size();
+        let grows = wanted.x > current.x || wanted.y > current.y || wanted.z > current.z;
+        let size = if grows && !has_room(*entity.pos(), wanted, &nearby_primitives) {
+            current
+        } else {
+            wanted
+        };
+        let is_crouching = entity.move_mode() == MoveMode::Crouch || (entity.is_crouching() && size != wanted);
+        let speed = if is_crouching {
+            config.speed_factor(MoveMode::Crouch)
+        } else {
+            config.speed_factor(entity.move_mode())
+        };
+
+        let entity_prim = body_prim(*entity.pos(), size);
+
+        let wanted_ctrl_acc =
+            limit_entity_movement(*entity.ctrl_acc()) * config.acceleration * Vec3::new(speed, speed, 1.0);
+        let wanted_offs_vel = wanted_ctrl_acc * dt;
+
+        // is standing on ground to jump, using a short probe downwards
         let on_ground = nearby_primitives
             .iter()
             .find(|prim| {
@@ -178,25 +191,54 @@ pub fn tick<
             })
             .is_some();
 
-        //adjust movement
+        // Jumps happen when jump is pressed on the ground, or just after walking off a ledge. Jump has to be let go
+        // before it'll jump again.
+        let coyote_time = if on_ground {
+            config.coyote_time
+        } else {
+            (entity.body().coyote_time - dt).max(0.0)
+        };
+        let jump_ready = entity.body().jump_ready || !entity.jump();
+        let jumping = entity.jump() && jump_ready && coyote_time > 0.0 && !in_water;
+        sizes.insert(*id, size);
+        states.insert(
+            *id,
+            BodyState {
+                crouching: is_crouching,
+                grounded: on_ground,
+                coyote_time: if jumping { 0.0 } else { coyote_time },
+                jump_ready: jump_ready && !jumping,
+            },
+        );
+
+        //adjust movement, gravity only pulls on entities that aren't already standing on something
         let mut vel = *entity.vel()
-            + if in_water { gravity * 0.1 } else { gravity } * dt
             + if in_water {
-                wanted_offs_vel * CONTROL_IN_WATER
+                gravity * 0.1
+            } else if on_ground {
+                Vec3::zero()
+            } else {
+                gravity
+            } * dt
+            + if in_water {
+                wanted_offs_vel * config.control_in_water
             } else if on_ground {
-                // calculate jump in vel not acc! assume 0.2 sec jump time
-                Vec3::new(wanted_ctrl_acc.x * dt, wanted_ctrl_acc.y * dt, wanted_ctrl_acc.z * 0.2)
+                Vec3::new(wanted_offs_vel.x, wanted_offs_vel.y, 0.0)
             } else {
-                wanted_offs_vel * CONTROL_IN_AIR
+                wanted_offs_vel * config.control_in_air
             };
         vel *= (if in_water {
-            FRICTION_IN_WATER
+            config.friction_in_water
         } else if on_ground {
-            FRICTION_ON_GROUND
+            config.friction_on_ground
         } else {
-            FRICTION_IN_AIR
+            config.friction_in_air
         })
         .map(|e| e.powf(dt));
+        if jumping {
+            vel.z = config.jump_speed();
+        }
+        vel.z = vel.z.max(-config.terminal_velocity);
 
         let m = MovingBody {
             id: *id,
@@ -211,7 +253,11 @@ pub fn tick<
     movement_tick(moving_bodies.values_mut(), obstacles.values(), dt);
 
     for (id, entity) in entities {
-        if let (Some((mov, nearby)), Some(old_mov)) = (moving_bodies.get_mut(id), obstacles.get(id)) {
+        if let (Some((mov, nearby)), Some(old_mov), Some(state), Some(size)) =
+            (moving_bodies.get_mut(id), obstacles.get(id), states.get(id), sizes.get(id))
+        {
+            let middle_offset = Vec3::unit_z() * size.z / 2.0;
+
             // am i stuck check
             let mut entity_prim_stuck = mov.primitive.clone();
             entity_prim_stuck.scale_by(0.9);
@@ -225,7 +271,7 @@ pub fn tick<
 
             if mov.velocity.x != old_mov.velocity.x || mov.velocity.y != old_mov.velocity.y {
                 // something got stoped, try block hopping
-                let cur_percent_of_hop = (mov.primitive.col_center().z + PLANCK_LENGTH /*needs to be done before substract because of f32 percision CPU inaccurate for 128.9 - 0.9 = 127.9999 */- ENTITY_MIDDLE_OFFSET.z).fract();
+                let cur_percent_of_hop = (mov.primitive.col_center().z + PLANCK_LENGTH /*needs to be done before substract because of f32 percision CPU inaccurate for 128.9 - 0.9 = 127.9999 */- middle_offset.z).fract();
                 let needed_for_step = Vec3::unit_z() * (BLOCK_SIZE_PLUS_SMALL - cur_percent_of_hop + PLANCK_LENGTH);
                 //check top first
                 if nearby
@@ -246,7 +292,7 @@ pub fn tick<
                     if (hopmov.velocity.x != mov.velocity.x || hopmov.velocity.y != mov.velocity.y)
                         && (hopmov.velocity.x != 0.0 || hopmov.velocity.y != 0.0)
                     {
-                        let up = (BLOCK_HOP_SPEED * dt).min(needed_for_step.z);
+                        let up = (config.block_hop_speed * dt).min(needed_for_step.z);
                         mov.primitive.move_by(&(Vec3::unit_z() * up));
                         mov.velocity = hopmov.velocity;
                         mov.velocity.z = 0.0;
@@ -255,8 +301,10 @@ pub fn tick<
             }
 
             let mut entity = entity.write();
-            *entity.pos_mut() = mov.primitive.col_center() - ENTITY_MIDDLE_OFFSET;
+            *entity.pos_mut() = mov.primitive.col_center() - middle_offset;
             *entity.vel_mut() = mov.velocity;
+            *entity.body_mut() = *state;
+            *entity.body_size_mut() = *size;
         }
     }
 }
@@ -1,6 +1,7 @@
 // Standard
 use std::{
     collections::HashMap,
+    iter,
     sync::Arc,
     thread,
     time::{self, Duration},
@@ -13,8 +14,11 @@ use vek::*;
 
 // Parent
 use crate::{
+    ecs::phys::{CollisionBox, MoveMode},
     physics::{
         collision::{Primitive, ResolutionCol, ResolutionTti},
+        config::PhysicsConfig,
+        movement::{depenetrate, movement_tick, MovingBody},
         physics,
     },
     terrain::{
@@ -687,6 +691,76 @@ fn tti_diagonal_in_to_dirs_negative() {
     checkTouching!(m1.time_to_impact(&m2, &vel), normal);
 }
 
+fn moving_body(middle: Vec3<f32>, velocity: Vec3<f32>) -> MovingBody {
+    MovingBody {
+        id: 1,
+        mass: 80.0,
+        primitive: Primitive::new_cuboid(middle, Vec3::new(0.45, 0.45, 0.45)),
+        velocity,
+    }
+}
+
+#[test]
+fn move_fast_no_tunnelling() {
+    // A wall one block thick, and a body heading at it fast enough to cover 50 blocks in a tick
+    let wall = Primitive::new_cuboid(Vec3::new(10.5, 0.5, 0.5), Vec3::new(0.5, 5.0, 5.0));
+    let mut bodies = [(moving_body(Vec3::new(0.5, 0.5, 0.5), Vec3::new(1000.0, 0.0, 0.0)), vec![wall])];
+    movement_tick(bodies.iter_mut(), iter::empty(), 0.05);
+
+    let body = &bodies[0].0;
+    assert!((body.primitive.col_center().x - 9.55).abs() < 0.001);
+    assert_eq!(body.velocity.x, 0.0);
+}
+
+#[test]
+fn move_slide_along_wall() {
+    // Moving diagonally into a wall keeps the movement along it
+    let wall = Primitive::new_cuboid(Vec3::new(10.5, 0.0, 0.5), Vec3::new(0.5, 20.0, 5.0));
+    let mut bodies = [(moving_body(Vec3::new(9.55, 0.0, 0.5), Vec3::new(5.0, 5.0, 0.0)), vec![wall])];
+    movement_tick(bodies.iter_mut(), iter::empty(), 1.0);
+
+    let body = &bodies[0].0;
+    assert!((body.primitive.col_center() - Vec3::new(9.55, 5.0, 0.5)).magnitude() < 0.001);
+    assert_eq!(body.velocity, Vec3::new(0.0, 5.0, 0.0));
+}
+
+#[test]
+fn move_slide_over_seams() {
+    // Sliding along a floor made of separate blocks doesn't catch on the edges between them
+    let floor = (0..5)
+        .map(|x| Primitive::new_cuboid(Vec3::new(x as f32 + 0.5, 0.5, 0.5), Vec3::new(0.5, 0.5, 0.5)))
+        .collect::<Vec<_>>();
+    let mut bodies = [(moving_body(Vec3::new(0.5, 0.5, 1.45), Vec3::new(3.0, 0.0, -1.0)), floor)];
+    movement_tick(bodies.iter_mut(), iter::empty(), 1.0);
+
+    let body = &bodies[0].0;
+    assert!((body.primitive.col_center() - Vec3::new(3.5, 0.5, 1.45)).magnitude() < 0.001);
+    assert_eq!(body.velocity, Vec3::new(3.0, 0.0, 0.0));
+}
+
+#[test]
+fn move_resting_contact_is_still() {
+    let floor = vec![Primitive::new_cuboid(Vec3::new(0.5, 0.5, 0.5), Vec3::new(0.5, 0.5, 0.5))];
+    let mut bodies = [(moving_body(Vec3::new(0.5, 0.5, 1.45), Vec3::zero()), floor)];
+    for _ in 0..100 {
+        movement_tick(bodies.iter_mut(), iter::empty(), 0.01);
+    }
+    assert_eq!(bodies[0].0.primitive.col_center(), Vec3::new(0.5, 0.5, 1.45));
+}
+
+#[test]
+fn depenetrate_spawned_inside() {
+    // A body spawned mostly inside a block is pushed out, rather than being left to fall through it
+    let block = Primitive::new_cuboid(Vec3::new(0.5, 0.5, 0.5), Vec3::new(0.5, 0.5, 0.5));
+    let mut body = Primitive::new_cuboid(Vec3::new(0.5, 0.5, 0.9), Vec3::new(0.45, 0.45, 0.45));
+    assert!(depenetrate(&mut body, &[block.clone()]));
+    assert!(block.resolve_col(&body).map(|res| res.is_touch()).unwrap_or(true));
+
+    let mut bodies = [(moving_body(Vec3::new(0.5, 0.5, 0.9), Vec3::new(0.0, 0.0, -10.0)), vec![block])];
+    movement_tick(bodies.iter_mut(), iter::empty(), 0.1);
+    assert!(bodies[0].0.primitive.col_center().z >= 1.45 - 0.001);
+}
+
 // Constants
 pub const CHUNK_SIZE: Vec3<VoxRel> = Vec3 { x: 64, y: 64, z: 64 }; // TODO: Unify this using the chunk interface
 pub const CHUNK_MID: Vec3<f32> = Vec3 {
@@ -726,11 +800,34 @@ fn gen_chunk_flat_border(_pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContain
     *con.lock() = Some(ChunkContainer::<i64>::new(Chunk::Hetero(c)));
 }
 
-fn gen_payload(_pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<i64>>>>) {
-    let conlock = con.lock();
-    if let Some(ref con) = *conlock {
-        *con.payload_mut() = Some(42);
+fn gen_chunk_flat_ceiling(_pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<i64>>>>) {
+    let mut c = HeterogeneousData::empty(CHUNK_SIZE);
+    for x in 0..CHUNK_SIZE.x {
+        for y in 0..CHUNK_SIZE.y {
+            c.replace_at_unchecked(Vec3::new(x, y, 2), Block::STONE);
+            // Leaves a gap one block high above the floor
+            c.replace_at_unchecked(Vec3::new(x, y, 4), Block::STONE);
+        }
+    }
+    *con.lock() = Some(ChunkContainer::<i64>::new(Chunk::Hetero(c)));
+}
+
+fn gen_chunk_flat_tunnel(_pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<i64>>>>) {
+    let mut c = HeterogeneousData::empty(CHUNK_SIZE);
+    for x in 0..CHUNK_SIZE.x {
+        for y in 0..CHUNK_SIZE.y {
+            c.replace_at_unchecked(Vec3::new(x, y, 2), Block::STONE);
+            // Two blocks in front of the middle, a roof leaves a gap two blocks high above the floor
+            if x >= CHUNK_SIZE.x / 2 + 2 {
+                c.replace_at_unchecked(Vec3::new(x, y, 5), Block::STONE);
+            }
+        }
     }
+    *con.lock() = Some(ChunkContainer::<i64>::new(Chunk::Hetero(c)));
+}
+
+fn gen_payload(_pos: Vec3<VolOffs>, con: &ChunkContainer<i64>, _: &HashMap<Vec3<VolOffs>, Arc<ChunkContainer<i64>>>) {
+    *con.payload_mut() = Some(42);
 }
 
 fn drop_chunk(_pos: Vec3<VolOffs>, _con: Arc<ChunkContainer<i64>>) {}
@@ -764,7 +861,7 @@ fn physics_fall() {
         ))),
     );
     for _ in 0..40 {
-        physics::tick(ent.iter(), &vol_mgr, Duration::from_millis(100))
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(100))
     }
     let p = ent.get(&1);
     let d = *p.unwrap().read().pos() - Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 3.0);
@@ -800,7 +897,7 @@ fn physics_fallfast() {
         ))),
     );
     for _ in 0..100 {
-        physics::tick(ent.iter(), &vol_mgr, Duration::from_millis(100))
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(100))
     }
     let p = ent.get(&1);
     let d = *p.unwrap().read().pos() - Vec3::new(CHUNK_MID.x, CHUNK_MID.y, CHUNK_SIZE.z as f32 + 3.0);
@@ -834,7 +931,7 @@ fn physics_fallfastunloaded() {
         ))),
     );
     for _ in 0..100 {
-        physics::tick(ent.iter(), &vol_mgr, Duration::from_millis(100))
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(100))
     }
     let p = ent.get(&1);
     let d = *p.unwrap().read().pos() - Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 3.0);
@@ -872,14 +969,14 @@ fn physics_jump() {
         ))),
     );
     for _ in 0..3 {
-        physics::tick(ent.iter(), &vol_mgr, Duration::from_millis(40))
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(40))
     }
     {
         let p = ent.get(&1);
         assert!(p.unwrap().read().pos().z > 10.2);
     }
     for _ in 0..50 {
-        physics::tick(ent.iter(), &vol_mgr, Duration::from_millis(100))
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(100))
     }
     {
         let p = ent.get(&1);
@@ -917,7 +1014,7 @@ fn physics_walk() {
         ))),
     );
     for _ in 0..80 {
-        physics::tick(ent.iter(), &vol_mgr, Duration::from_millis(50))
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(50))
     }
     {
         let p = ent.get(&1);
@@ -932,3 +1029,324 @@ fn physics_walk() {
         //assert!(d.magnitude() < 0.01);
     }
 }
+
+fn flat_mgr(gen: fn(Vec3<VolOffs>, Arc<Mutex<Option<ChunkContainer<i64>>>>)) -> ChunkMgr<i64> {
+    let vol_mgr = ChunkMgr::new(CHUNK_SIZE, VolGen::new(gen, gen_payload, drop_chunk, drop_payload));
+    vol_mgr.block_loader_mut().push(Arc::new(RwLock::new(BlockLoader {
+        pos: Vec3::new(0, 0, 0),
+        size: CHUNK_SIZE.map(|e| e as i64 * 10),
+    })));
+    vol_mgr.gen(Vec3::new(0, 0, 1));
+    vol_mgr.gen(Vec3::new(0, 0, 0));
+    vol_mgr.gen(Vec3::new(0, 0, -1));
+    thread::sleep(time::Duration::from_millis(200)); // because this spawns a thread :/
+    vol_mgr.maintain();
+    vol_mgr
+}
+
+// How far an entity on flat ground gets in a second of walking in the given mode
+fn walk_distance(vol_mgr: &ChunkMgr<i64>, move_mode: MoveMode) -> f32 {
+    let mut ent: HashMap<Uid, Arc<RwLock<Entity<()>>>> = HashMap::new();
+    ent.insert(
+        1,
+        Arc::new(RwLock::new(Entity::new(
+            Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec2::new(0.0, 0.0),
+        ))),
+    );
+    // Settle onto the ground first
+    for _ in 0..10 {
+        physics::tick(ent.iter(), vol_mgr, &PhysicsConfig::default(), Duration::from_millis(50))
+    }
+
+    let start = *ent.get(&1).unwrap().read().pos();
+    {
+        let mut entity = ent.get(&1).unwrap().write();
+        *entity.move_mode_mut() = move_mode;
+        *entity.ctrl_acc_mut() = Vec3::new(1.0, 0.0, 0.0);
+    }
+    for _ in 0..20 {
+        physics::tick(ent.iter(), vol_mgr, &PhysicsConfig::default(), Duration::from_millis(50))
+    }
+    let d = *ent.get(&1).unwrap().read().pos() - start;
+    // Moving along x doesn't drift sideways or lift the entity off the ground
+    assert!(d.y.abs() < 0.01 && d.z.abs() < 0.01, "{:?} moved {}", move_mode, d);
+    d.x
+}
+
+#[test]
+fn physics_move_mode_speed() {
+    let vol_mgr = flat_mgr(gen_chunk_flat);
+    let walk = walk_distance(&vol_mgr, MoveMode::Walk);
+    let sprint = walk_distance(&vol_mgr, MoveMode::Sprint);
+    let crouch = walk_distance(&vol_mgr, MoveMode::Crouch);
+
+    assert!(walk > 1.0);
+    assert!(sprint > walk * 1.3);
+    assert!(crouch < walk * 0.7);
+    assert!(crouch > 0.0);
+}
+
+#[test]
+fn physics_uncrouch() {
+    for &(gen, can_stand) in [
+        (gen_chunk_flat as fn(_, _), true),
+        (gen_chunk_flat_ceiling as fn(_, _), false),
+    ]
+    .iter()
+    {
+        let vol_mgr = flat_mgr(gen);
+        let mut ent: HashMap<Uid, Arc<RwLock<Entity<()>>>> = HashMap::new();
+        let mut entity = Entity::new(
+            Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec2::new(0.0, 0.0),
+        );
+        *entity.move_mode_mut() = MoveMode::Crouch;
+        ent.insert(1, Arc::new(RwLock::new(entity)));
+
+        for _ in 0..10 {
+            physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(50))
+        }
+        assert!(ent.get(&1).unwrap().read().is_crouching());
+
+        // Try to stand up
+        *ent.get(&1).unwrap().write().move_mode_mut() = MoveMode::Walk;
+        for _ in 0..10 {
+            physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(50))
+        }
+        let p = ent.get(&1).unwrap();
+        assert_eq!(p.read().is_crouching(), !can_stand);
+        // Standing up under the ceiling would have pushed the entity up through it
+        let d = *p.read().pos() - Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 3.0);
+        assert!(d.magnitude() < 0.01);
+    }
+}
+
+// Walks an entity along x for `ticks` ticks of 50ms each
+fn walk_x(ent: &HashMap<Uid, Arc<RwLock<Entity<()>>>>, vol_mgr: &ChunkMgr<i64>, dir: f32, ticks: usize) {
+    *ent.get(&1).unwrap().write().ctrl_acc_mut() = Vec3::new(dir, 0.0, 0.0);
+    for _ in 0..ticks {
+        physics::tick(ent.iter(), vol_mgr, &PhysicsConfig::default(), Duration::from_millis(50))
+    }
+    *ent.get(&1).unwrap().write().ctrl_acc_mut() = Vec3::zero();
+}
+
+#[test]
+fn physics_collision_box() {
+    let vol_mgr = flat_mgr(gen_chunk_flat_tunnel);
+    let roof_start = CHUNK_MID.x + 2.0;
+    let tall = CollisionBox(Vec3::new(0.9, 0.9, 2.5));
+    for &(collision_box, fits) in [(CollisionBox::default(), true), (tall, false)].iter() {
+        let mut ent: HashMap<Uid, Arc<RwLock<Entity<()>>>> = HashMap::new();
+        let mut entity = Entity::new(
+            Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec2::new(0.0, 0.0),
+        );
+        *entity.collision_box_mut() = collision_box;
+        ent.insert(1, Arc::new(RwLock::new(entity)));
+
+        // Walk into the gap. A character fits under the roof, but something taller is stopped at its edge.
+        walk_x(&ent, &vol_mgr, 1.0, 20);
+        let pos = *ent.get(&1).unwrap().read().pos();
+        assert_eq!(pos.x > roof_start + 1.0, fits, "{:?} walked to {}", collision_box, pos);
+        if !fits {
+            assert!(pos.x + collision_box.0.x / 2.0 < roof_start + 0.01, "walked to {}", pos);
+        }
+        assert!((pos.z - 3.0).abs() < 0.01, "walked to {}", pos);
+    }
+}
+
+#[test]
+fn physics_collision_box_grows_when_there_is_room() {
+    let vol_mgr = flat_mgr(gen_chunk_flat_tunnel);
+    let roof_start = CHUNK_MID.x + 2.0;
+    let tall = CollisionBox(Vec3::new(0.9, 0.9, 2.5));
+    let mut ent: HashMap<Uid, Arc<RwLock<Entity<()>>>> = HashMap::new();
+    ent.insert(
+        1,
+        Arc::new(RwLock::new(Entity::new(
+            Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec2::new(0.0, 0.0),
+        ))),
+    );
+    walk_x(&ent, &vol_mgr, 1.0, 20);
+    assert!(ent.get(&1).unwrap().read().pos().x > roof_start + 1.0);
+
+    // Being made taller under the roof doesn't push the entity up through it, or make it any taller yet
+    *ent.get(&1).unwrap().write().collision_box_mut() = tall;
+    for _ in 0..10 {
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(50))
+    }
+    {
+        let p = ent.get(&1).unwrap().read();
+        assert!((p.pos().z - 3.0).abs() < 0.01);
+        assert_eq!(p.body_size(), CollisionBox::default().0);
+    }
+
+    // Out from under the roof, it grows
+    walk_x(&ent, &vol_mgr, -1.0, 30);
+    let p = ent.get(&1).unwrap().read();
+    assert!(p.pos().x + 0.45 < roof_start);
+    assert_eq!(p.body_size(), tall.0);
+}
+
+// Ticks an entity for a second, returning the highest it got above where it started
+fn jump_apex(ent: &HashMap<Uid, Arc<RwLock<Entity<()>>>>, vol_mgr: &ChunkMgr<i64>) -> f32 {
+    let start = ent.get(&1).unwrap().read().pos().z;
+    let mut apex = start;
+    for _ in 0..100 {
+        physics::tick(ent.iter(), vol_mgr, &PhysicsConfig::default(), Duration::from_millis(10));
+        apex = apex.max(ent.get(&1).unwrap().read().pos().z);
+    }
+    apex - start
+}
+
+#[test]
+fn physics_jump_arc() {
+    let vol_mgr = flat_mgr(gen_chunk_flat);
+    let mut ent: HashMap<Uid, Arc<RwLock<Entity<()>>>> = HashMap::new();
+    ent.insert(
+        1,
+        Arc::new(RwLock::new(Entity::new(
+            Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec2::new(0.0, 0.0),
+        ))),
+    );
+    for _ in 0..10 {
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(10))
+    }
+    assert!(ent.get(&1).unwrap().read().is_grounded());
+
+    // A jump reaches the jump height and comes back down
+    *ent.get(&1).unwrap().write().jump_mut() = true;
+    let apex = jump_apex(&ent, &vol_mgr);
+    assert!((apex - PhysicsConfig::default().jump_height).abs() < 0.15, "apex was {}", apex);
+    assert!(ent.get(&1).unwrap().read().is_grounded());
+
+    // Holding jump after landing doesn't jump again
+    assert!(jump_apex(&ent, &vol_mgr) < 0.01);
+
+    // Pressing jump again does, but not a second time in mid-air
+    *ent.get(&1).unwrap().write().jump_mut() = false;
+    physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(10));
+    *ent.get(&1).unwrap().write().jump_mut() = true;
+    for _ in 0..20 {
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(10))
+    }
+    assert!(!ent.get(&1).unwrap().read().is_grounded());
+    *ent.get(&1).unwrap().write().jump_mut() = false;
+    physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(10));
+    *ent.get(&1).unwrap().write().jump_mut() = true;
+    let mut apex = 0.0f32;
+    for _ in 0..100 {
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(10));
+        apex = apex.max(ent.get(&1).unwrap().read().pos().z - 3.0);
+    }
+    assert!(apex < PhysicsConfig::default().jump_height + 0.15);
+}
+
+#[test]
+fn physics_coyote_time() {
+    let vol_mgr = flat_mgr(gen_chunk_flat);
+    let mut ent: HashMap<Uid, Arc<RwLock<Entity<()>>>> = HashMap::new();
+    ent.insert(
+        1,
+        Arc::new(RwLock::new(Entity::new(
+            Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec2::new(0.0, 0.0),
+        ))),
+    );
+    for _ in 0..10 {
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(10))
+    }
+
+    // Pretend the ground just fell away: a jump shortly afterwards still works, a jump much later doesn't
+    for &(wait, can_jump) in [(0.05, true), (0.2, false)].iter() {
+        {
+            let mut entity = ent.get(&1).unwrap().write();
+            *entity.pos_mut() = Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 20.0);
+            *entity.vel_mut() = Vec3::zero();
+            *entity.jump_mut() = false;
+            entity.body_mut().coyote_time = PhysicsConfig::default().coyote_time;
+        }
+        for _ in 0..(wait * 100.0) as usize {
+            physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(10))
+        }
+        *ent.get(&1).unwrap().write().jump_mut() = true;
+        physics::tick(ent.iter(), &vol_mgr, &PhysicsConfig::default(), Duration::from_millis(10));
+        assert_eq!(ent.get(&1).unwrap().read().vel().z > 0.0, can_jump);
+    }
+}
+
+// Walks and jumps an entity through the same inputs under `config`, returning where it was after each tick
+fn trajectory(vol_mgr: &ChunkMgr<i64>, config: &PhysicsConfig) -> Vec<Vec3<f32>> {
+    let mut ent: HashMap<Uid, Arc<RwLock<Entity<()>>>> = HashMap::new();
+    ent.insert(
+        1,
+        Arc::new(RwLock::new(Entity::new(
+            Vec3::new(CHUNK_MID.x, CHUNK_MID.y, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec2::new(0.0, 0.0),
+        ))),
+    );
+    (0..100)
+        .map(|i| {
+            {
+                let mut entity = ent.get(&1).unwrap().write();
+                *entity.ctrl_acc_mut() = Vec3::new(1.0, 0.5, 0.0);
+                *entity.jump_mut() = i % 40 == 20;
+            }
+            physics::tick(ent.iter(), vol_mgr, config, Duration::from_millis(20));
+            *ent.get(&1).unwrap().read().pos()
+        })
+        .collect()
+}
+
+#[test]
+fn shared_physics_configs_give_identical_trajectories() {
+    let vol_mgr = flat_mgr(gen_chunk_flat);
+    let mut server_config = PhysicsConfig::default();
+    server_config.set("gravity", &[-50.0]).unwrap();
+    server_config.set("friction_in_air", &[0.5, 0.5, 0.9]).unwrap();
+
+    // The client gets its config from the server over the network
+    let client_config: PhysicsConfig = bincode::deserialize(&bincode::serialize(&server_config).unwrap()).unwrap();
+    assert_eq!(client_config, server_config);
+
+    let server = trajectory(&vol_mgr, &server_config);
+    assert_eq!(trajectory(&vol_mgr, &client_config), server);
+    // ...which only works because the config makes a difference
+    assert_ne!(trajectory(&vol_mgr, &PhysicsConfig::default()), server);
+}
+
+#[test]
+fn physics_settings_are_checked() {
+    let mut config = PhysicsConfig::default();
+    assert!(config.set("jump_height", &[2.0]).is_ok());
+    assert!(config.set("acceleration", &[10.0]).is_ok());
+    assert_eq!(config.get("jump_height"), Some("2".to_string()));
+    assert_eq!(config.get("acceleration"), Some("10 10 10".to_string()));
+
+    assert!(config.set("jump_height", &[1.0, 2.0, 3.0]).is_err());
+    assert!(config.set("acceleration", &[1.0, 2.0]).is_err());
+    assert!(config.set("friction_in_air", &[1.5]).is_err());
+    assert!(config.set("coyote_time", &[-1.0]).is_err());
+    assert!(config.set("levity", &[1.0]).is_err());
+    // Nothing changed by the settings that were refused
+    assert_eq!(config.jump_height, 2.0);
+    assert_eq!(config.friction_in_air, PhysicsConfig::default().friction_in_air);
+    assert!(PhysicsConfig::FIELDS.iter().all(|name| config.get(name).is_some()));
+}
@@ -0,0 +1,113 @@
+// Library
+use serde_derive::{Deserialize, Serialize};
+use vek::*;
+
+// Local
+use crate::terrain::{
+    chunk::{Block, Chunk, HomogeneousData},
+    voloffs_to_voxabs, ConstructVolume, VolOffs, VoxRel,
+};
+
+// Constants
+// Far enough out that nobody runs into it by accident, and close enough that f32 positions are still precise there
+const DEFAULT_RADIUS: f32 = 4096.0;
+
+/// What the server puts in place of the terrain beyond the border
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Barrier {
+    /// Solid rock, so that the edge of the world looks like one
+    Wall,
+    /// Nothing at all
+    Void,
+}
+
+/// The edge of the world: a square around `center` that reaches `radius` blocks out along each axis. Nothing can go
+/// beyond it, and no terrain is generated there.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldBorder {
+    pub center: Vec2<f32>,
+    pub radius: f32,
+    pub barrier: Barrier,
+}
+
+impl Default for WorldBorder {
+    fn default() -> Self {
+        WorldBorder {
+            center: Vec2::zero(),
+            radius: DEFAULT_RADIUS,
+            barrier: Barrier::Wall,
+        }
+    }
+}
+
+impl WorldBorder {
+    /// How far `pos` is inside the border, horizontally. Negative beyond it.
+    pub fn distance(&self, pos: Vec3<f32>) -> f32 {
+        let offs = (Vec2::from(pos) - self.center).map(|e| e.abs());
+        self.radius - offs.x.max(offs.y)
+    }
+
+    pub fn contains(&self, pos: Vec3<f32>) -> bool { self.distance(pos) >= 0.0 }
+
+    /// The closest point to `pos` that's at least `margin` inside the border
+    pub fn clamp(&self, pos: Vec3<f32>, margin: f32) -> Vec3<f32> {
+        let reach = (self.radius - margin).max(0.0);
+        let xy = Vec2::from(pos).map2(self.center, |e, c| e.max(c - reach).min(c + reach));
+        Vec3::new(xy.x, xy.y, pos.z)
+    }
+
+    /// Whether all of the chunk at `pos` lies beyond the border
+    pub fn is_chunk_outside(&self, pos: Vec3<VolOffs>, size: Vec3<VoxRel>) -> bool {
+        let low = Vec2::from(voloffs_to_voxabs(pos, size).map(|e| e as f32));
+        let high = low + Vec2::from(size.map(|e| e as f32));
+        let beyond = |low: f32, high: f32, center: f32| low > center + self.radius || high < center - self.radius;
+        beyond(low.x, high.x, self.center.x) || beyond(low.y, high.y, self.center.y)
+    }
+
+    /// What to use for a chunk that's outside the border instead of generating it
+    pub fn barrier_chunk(&self, size: Vec3<VoxRel>) -> Chunk {
+        let block = match self.barrier {
+            Barrier::Wall => Block::STONE,
+            Barrier::Void => Block::AIR,
+        };
+        Chunk::Homo(HomogeneousData::filled(size, block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn border_clamps_to_a_square() {
+        let border = WorldBorder {
+            center: Vec2::new(100.0, -50.0),
+            radius: 10.0,
+            barrier: Barrier::Wall,
+        };
+        assert!(border.contains(Vec3::new(109.0, -59.0, 1000.0)));
+        assert!(!border.contains(Vec3::new(111.0, -50.0, 0.0)));
+        assert_eq!(border.distance(Vec3::new(105.0, -52.0, 0.0)), 5.0);
+
+        assert_eq!(border.clamp(Vec3::new(200.0, -55.0, 3.0), 1.0), Vec3::new(109.0, -55.0, 3.0));
+        assert_eq!(border.clamp(Vec3::new(0.0, 0.0, 3.0), 0.0), Vec3::new(90.0, -40.0, 3.0));
+        // Positions inside are left alone
+        assert_eq!(border.clamp(Vec3::new(101.0, -51.0, 3.0), 1.0), Vec3::new(101.0, -51.0, 3.0));
+    }
+
+    #[test]
+    fn only_chunks_wholly_beyond_the_border_are_outside() {
+        let size = Vec3::new(16, 16, 16);
+        let border = WorldBorder {
+            center: Vec2::zero(),
+            radius: 20.0,
+            barrier: Barrier::Void,
+        };
+        assert!(!border.is_chunk_outside(Vec3::new(0, 0, 5), size));
+        // Reaches from 16 to 32, across the border
+        assert!(!border.is_chunk_outside(Vec3::new(1, -2, 0), size));
+        assert!(border.is_chunk_outside(Vec3::new(2, 0, 0), size));
+        assert!(border.is_chunk_outside(Vec3::new(0, -3, 0), size));
+    }
+}
@@ -1,6 +1,27 @@
+use std::f32::INFINITY;
+
 use serde_derive::{Deserialize, Serialize};
 
-use super::super::Voxel;
+use super::super::{Voxel, MAX_LIGHT};
+
+// How long palette blocks that aren't in `HARDNESS` take to dig, in seconds
+const DEFAULT_HARDNESS: f32 = 1.0;
+// How long each block takes to dig, in seconds
+const HARDNESS: [(Block, f32); 13] = [
+    (Block::GRASS, 0.6),
+    (Block::SAND, 0.5),
+    (Block::EARTH, 0.6),
+    (Block::STONE, 1.5),
+    (Block::SNOW, 0.3),
+    (Block::LOG, 1.2),
+    (Block::LEAF, 0.2),
+    (Block::GOLD, 3.0),
+    (Block::LIGHT_COBBLE, 2.0),
+    (Block::MID_COBBLE, 2.0),
+    (Block::DARK_COBBLE, 2.0),
+    (Block::GLOWSTONE, 0.5),
+    (Block::BEDROCK, INFINITY),
+];
 
 #[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct BlockMat {
@@ -36,6 +57,14 @@ impl Block {
     pub const LIGHT_COBBLE: Block = Block::from_byte(109);
     pub const MID_COBBLE: Block = Block::from_byte(83);
     pub const DARK_COBBLE: Block = Block::from_byte(163);
+    // Palette mode like the blocks above, with a spare bit set so that it isn't taken for the plain orange block
+    pub const GLOWSTONE: Block = Block {
+        mat: BlockMat { grad: 0x81, index: 2 },
+    };
+    // Looks like dark cobble, but can't be dug
+    pub const BEDROCK: Block = Block {
+        mat: BlockMat { grad: 0x82, index: 163 },
+    };
 
     pub const GRAD2_A_GRASS: u8 = 0;
     pub const GRAD2_A_LEAF0: u8 = 1;
@@ -77,6 +106,61 @@ impl Block {
     }
 
     pub fn is_fluid(&self) -> bool { *self == Self::WATER }
+
+    /// How many seconds it takes to dig the block out. Blocks that can't be dug, including air and fluids, are
+    /// infinitely hard. Gradient blocks are as hard as the hardest material they blend.
+    pub fn hardness(&self) -> f32 {
+        if !self.is_solid() {
+            return INFINITY;
+        }
+        let BlockMat { grad, index } = self.mat;
+        match grad & 0xC0 {
+            0x40 => {
+                let a = if index & 0xF == Self::GRAD2_A_GRASS {
+                    Self::GRASS
+                } else {
+                    Self::LEAF
+                };
+                let b = match index >> 4 {
+                    b if b == Self::GRAD2_B_STONE => Self::STONE,
+                    b if b == Self::GRAD2_B_DRY_GRASS => Self::GRASS,
+                    _ => Self::LEAF,
+                };
+                a.hardness().max(b.hardness())
+            },
+            0xC0 => {
+                let o = if index & 0x1 == Self::GRAD3_O_STONE {
+                    Self::STONE
+                } else {
+                    Self::EARTH
+                };
+                let b = if (index >> 2) & 0x1 == Self::GRAD3_B_SAND {
+                    Self::SAND
+                } else {
+                    Self::SNOW
+                };
+                o.hardness().max(Self::GRASS.hardness()).max(b.hardness())
+            },
+            _ => HARDNESS
+                .iter()
+                .filter(|(block, _)| block == self)
+                .map(|(_, hardness)| *hardness)
+                .next()
+                .unwrap_or(DEFAULT_HARDNESS),
+        }
+    }
+
+    /// Whether the block can be dug out at all
+    pub fn is_breakable(&self) -> bool { self.hardness().is_finite() }
+
+    /// How much block light this gives off, from 0 to `MAX_LIGHT`
+    pub fn light_emission(&self) -> u8 {
+        if *self == Self::GLOWSTONE {
+            MAX_LIGHT
+        } else {
+            0
+        }
+    }
 }
 
 impl Voxel for Block {
@@ -3,8 +3,8 @@ use crate::terrain::{
         rle::{BlockRle, BLOCK_RLE_MAX_NUM},
         Block, HeterogeneousData, HomogeneousData, RleData,
     },
-    AnyVolume, ConstructVolume, PersState, PhysicalVolume, ReadVolume, ReadWriteVolume, SerializeVolume, VolCluster,
-    Volume, VoxRel, Voxel,
+    AnyVolume, ConstructVolume, OutOfBounds, PersState, PhysicalVolume, ReadVolume, ReadWriteVolume, SerializeVolume,
+    VolCluster, Volume, VoxRel, Voxel, WriteVolume,
 };
 use vek::*;
 
@@ -15,6 +15,56 @@ pub enum Chunk {
     HeteroAndRle(HeterogeneousData, RleData),
 }
 
+impl Chunk {
+    /// The height of the highest solid block in the column at `x`, `y`, if it has any. Panics if the column is outside
+    /// the chunk.
+    pub fn height_at(&self, x: VoxRel, y: VoxRel) -> Option<VoxRel> {
+        match self {
+            Chunk::Homo(homo) => homo.height_at(x, y),
+            Chunk::Hetero(hetero) | Chunk::HeteroAndRle(hetero, _) => hetero.height_at(x, y),
+            Chunk::Rle(rle) => rle.height_at(x, y),
+        }
+    }
+}
+
+impl Volume for Chunk {
+    type VoxelType = Block;
+
+    fn size(&self) -> Vec3<VoxRel> {
+        match self {
+            Chunk::Homo(homo) => homo.size(),
+            Chunk::Hetero(hetero) | Chunk::HeteroAndRle(hetero, _) => hetero.size(),
+            Chunk::Rle(rle) => rle.size(),
+        }
+    }
+}
+
+// Edits are made to the heterogeneous data, which the chunk is converted to the first time a write changes anything.
+// The other representations are dropped, since they'd be out of date.
+impl WriteVolume for Chunk {
+    fn set(&mut self, off: Vec3<VoxRel>, vox: Block) -> Result<Block, OutOfBounds> {
+        let old = self.prefered().and_then(|vol| vol.at(off)).ok_or(OutOfBounds(off))?;
+        if old == vox {
+            return Ok(old);
+        }
+        self.convert(PersState::Hetero);
+        if self.contains(PersState::Rle) {
+            self.remove(PersState::Rle);
+        }
+        match self {
+            Chunk::Hetero(hetero) => hetero.set(off, vox),
+            _ => unreachable!("Chunk wasn't converted for writing"),
+        }
+    }
+
+    fn take_dirty_region(&mut self) -> Option<Aabb<VoxRel>> {
+        match self {
+            Chunk::Hetero(hetero) | Chunk::HeteroAndRle(hetero, _) => hetero.take_dirty_region(),
+            Chunk::Homo(_) | Chunk::Rle(_) => None,
+        }
+    }
+}
+
 impl VolCluster for Chunk {
     type VoxelType = Block;
 
@@ -35,7 +85,7 @@ impl VolCluster for Chunk {
                     Chunk::Hetero(hetero) => {
                         let t = hetero.at_unchecked(Vec3::new(0, 0, 0));
                         // check if possible!
-                        for e in hetero.voxels_mut().iter() {
+                        for e in hetero.voxels().iter() {
                             if *e != t {
                                 return;
                             }
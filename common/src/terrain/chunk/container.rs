@@ -1,8 +1,9 @@
-use crate::terrain::{chunk::Chunk, Container};
+use crate::terrain::{chunk::Chunk, Container, LightData, VolCluster};
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 pub struct ChunkContainer<P> {
     data: RwLock<Chunk>,
+    light: RwLock<Option<LightData>>,
     payload: RwLock<Option<P>>,
 }
 
@@ -10,9 +11,20 @@ impl<P> ChunkContainer<P> {
     pub fn new(chunk: Chunk) -> Self {
         ChunkContainer {
             data: RwLock::new(chunk),
+            light: RwLock::new(None),
             payload: RwLock::new(None),
         }
     }
+
+    /// The light of the chunk, once it's been lit. Lock `data` first when locking both.
+    pub fn light(&self) -> RwLockReadGuard<Option<LightData>> { self.light.read() }
+    pub fn light_mut(&self) -> RwLockWriteGuard<Option<LightData>> { self.light.write() }
+
+    /// Light the chunk by itself, with sunlight falling onto its top if `sky` is set. See `LightData::lit`.
+    pub fn light_alone(&self, sky: bool) {
+        let light = self.data().prefered().map(|blocks| LightData::lit(blocks, sky));
+        *self.light.write() = light;
+    }
 }
 
 impl<P> Container for ChunkContainer<P> {
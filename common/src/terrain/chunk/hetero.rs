@@ -3,13 +3,24 @@ use vek::*;
 
 // Local
 use crate::terrain::{
-    chunk::Block, ConstructVolume, PhysicalVolume, ReadVolume, ReadWriteVolume, Volume, VoxRel, Voxel,
+    add_to_region, check_slice, chunk::Block, ConstructVolume, OutOfBounds, PhysicalVolume, ReadVolume,
+    ReadWriteVolume, Volume, VoxRel, Voxel, WriteVolume,
 };
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct HeterogeneousData {
     size: Vec3<VoxRel>,
     voxels: Vec<Block>,
+    // What's been changed through `WriteVolume::set` since it was last taken
+    dirty: Option<Aabb<VoxRel>>,
+    // For each column, in the same order as the voxels, one more than the height of its highest solid block, or 0 if
+    // it has none. Kept up to date as voxels are replaced.
+    heights: Vec<u16>,
+}
+
+// Two volumes are the same if their voxels are, however they came to be
+impl PartialEq for HeterogeneousData {
+    fn eq(&self, other: &Self) -> bool { self.size == other.size && self.voxels == other.voxels }
 }
 
 impl HeterogeneousData {
@@ -19,7 +30,33 @@ impl HeterogeneousData {
             + off.z as usize)
     }
 
-    pub(crate) fn voxels_mut(&mut self) -> &mut Vec<Block> { &mut self.voxels }
+    pub(crate) fn voxels(&self) -> &[Block] { &self.voxels }
+
+    /// The height of the highest solid block in the column at `x`, `y`, if it has any
+    pub fn height_at(&self, x: VoxRel, y: VoxRel) -> Option<VoxRel> {
+        match self.heights[x as usize * self.size.y as usize + y as usize] {
+            0 => None,
+            h => Some(h as VoxRel - 1),
+        }
+    }
+
+    // Bring the height of the column `off` is in up to date after the voxel at `off` was replaced
+    fn update_height(&mut self, off: Vec3<VoxRel>) {
+        let col = off.x as usize * self.size.y as usize + off.y as usize;
+        let height = self.heights[col] as VoxRel;
+        if self.at_unchecked(off).is_solid() {
+            if off.z >= height {
+                self.heights[col] = off.z as u16 + 1;
+            }
+        } else if off.z + 1 == height {
+            // The top of the column was taken away, so look down for the new one
+            self.heights[col] = (0..off.z)
+                .rev()
+                .find(|z| self.at_unchecked(Vec3::new(off.x, off.y, *z)).is_solid())
+                .map(|z| z as u16 + 1)
+                .unwrap_or(0);
+        }
+    }
 }
 
 impl Volume for HeterogeneousData {
@@ -30,6 +67,19 @@ impl Volume for HeterogeneousData {
 
 impl ReadVolume for HeterogeneousData {
     fn at_unchecked(&self, off: Vec3<VoxRel>) -> Block { self.voxels[self.calculate_index(off)] }
+
+    // Voxels are stored in the same order as the slice, so each column of the box is one copy
+    fn fill_slice(&self, min: Vec3<VoxRel>, size: Vec3<VoxRel>, out: &mut [Block]) {
+        check_slice(min, size, self.size, out.len());
+        if size.z == 0 {
+            return;
+        }
+        for (i, row) in out.chunks_mut(size.z as usize).enumerate() {
+            let (x, y) = (i as VoxRel / size.y, i as VoxRel % size.y);
+            let start = self.calculate_index(min + Vec3::new(x, y, 0));
+            row.copy_from_slice(&self.voxels[start..start + row.len()]);
+        }
+    }
 }
 
 impl ReadWriteVolume for HeterogeneousData {
@@ -37,6 +87,9 @@ impl ReadWriteVolume for HeterogeneousData {
         let i = self.calculate_index(off);
         let r = self.voxels[i];
         self.voxels[i] = vox;
+        if r.is_solid() != vox.is_solid() {
+            self.update_height(off);
+        }
         r
     }
 
@@ -44,14 +97,32 @@ impl ReadWriteVolume for HeterogeneousData {
         for v in self.voxels.iter_mut() {
             *v = vox;
         }
+        let height = if vox.is_solid() { self.size.z as u16 } else { 0 };
+        for h in self.heights.iter_mut() {
+            *h = height;
+        }
     }
 }
 
+impl WriteVolume for HeterogeneousData {
+    fn set(&mut self, off: Vec3<VoxRel>, vox: Block) -> Result<Block, OutOfBounds> {
+        let old = self.replace_at(off, vox).ok_or(OutOfBounds(off))?;
+        if old != vox {
+            add_to_region(&mut self.dirty, off);
+        }
+        Ok(old)
+    }
+
+    fn take_dirty_region(&mut self) -> Option<Aabb<VoxRel>> { self.dirty.take() }
+}
+
 impl ConstructVolume for HeterogeneousData {
     fn filled(size: Vec3<VoxRel>, vox: Self::VoxelType) -> HeterogeneousData {
         HeterogeneousData {
             size,
             voxels: vec![vox; size.map(|e| e as usize).product()],
+            dirty: None,
+            heights: vec![if vox.is_solid() { size.z as u16 } else { 0 }; (size.x * size.y) as usize],
         }
     }
 
@@ -3,7 +3,9 @@ use serde_derive::{Deserialize, Serialize};
 use vek::*;
 
 // Local
-use crate::terrain::{chunk::Block, ConstructVolume, PhysicalVolume, ReadVolume, Volume, VoxRel, Voxel};
+use crate::terrain::{
+    check_slice, chunk::Block, ConstructVolume, PhysicalVolume, ReadVolume, Volume, VoxRel, Voxel,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct HomogeneousData {
@@ -13,6 +15,15 @@ pub struct HomogeneousData {
 
 impl HomogeneousData {
     pub(crate) fn voxel_mut(&mut self) -> &mut Block { &mut self.voxel }
+
+    /// The height of the highest solid block in a column, which is the same for every column
+    pub fn height_at(&self, _x: VoxRel, _y: VoxRel) -> Option<VoxRel> {
+        if self.voxel.is_solid() && self.size.z > 0 {
+            Some(self.size.z - 1)
+        } else {
+            None
+        }
+    }
 }
 
 impl Volume for HomogeneousData {
@@ -23,6 +34,13 @@ impl Volume for HomogeneousData {
 
 impl ReadVolume for HomogeneousData {
     fn at_unchecked(&self, _off: Vec3<VoxRel>) -> Block { self.voxel }
+
+    fn fill_slice(&self, min: Vec3<VoxRel>, size: Vec3<VoxRel>, out: &mut [Block]) {
+        check_slice(min, size, self.size, out.len());
+        for vox in out.iter_mut() {
+            *vox = self.voxel;
+        }
+    }
 }
 
 impl ConstructVolume for HomogeneousData {
@@ -6,7 +6,9 @@ use serde_derive::{Deserialize, Serialize};
 use vek::*;
 
 // Local
-use crate::terrain::{chunk::Block, ConstructVolume, PhysicalVolume, ReadVolume, Volume, VoxRel, Voxel};
+use crate::terrain::{
+    check_slice, chunk::Block, ConstructVolume, PhysicalVolume, ReadVolume, Volume, VoxRel, Voxel,
+};
 
 //TODO: optimizations:
 // currently even empty blocks generate a BlockRle, one could say that if the 3rd vector is empty that all blocks are empty
@@ -33,6 +35,22 @@ impl RleData {
     pub(crate) fn voxels_mut(&mut self) -> &mut Vec<Vec<BlockRle>> { &mut self.voxels }
 
     pub fn voxels_mut_internal(&mut self) -> &mut Vec<Vec<BlockRle>> { &mut self.voxels }
+
+    /// The height of the highest solid block in the column at `x`, `y`, if it has any. The runs make this quick to
+    /// find, so it isn't stored.
+    pub fn height_at(&self, x: VoxRel, y: VoxRel) -> Option<VoxRel> {
+        let col = &self.voxels[x as usize * self.size.y as usize + y as usize];
+        let mut oldz: VoxRel = 0;
+        let mut height = None;
+        for brle in col {
+            let z: VoxRel = oldz + brle.num_minus_one as VoxRel + 1;
+            if brle.block.is_solid() && oldz < self.size.z {
+                height = Some(z.min(self.size.z) - 1);
+            }
+            oldz = z;
+        }
+        height
+    }
 }
 
 impl Volume for RleData {
@@ -54,6 +72,37 @@ impl ReadVolume for RleData {
         }
         Block::empty()
     }
+
+    // Walk each column's runs once rather than from the bottom for every voxel
+    fn fill_slice(&self, min: Vec3<VoxRel>, size: Vec3<VoxRel>, out: &mut [Block]) {
+        check_slice(min, size, self.size, out.len());
+        if size.z == 0 {
+            return;
+        }
+        let (bottom, top) = (min.z, min.z + size.z);
+        for (i, row) in out.chunks_mut(size.z as usize).enumerate() {
+            let (x, y) = (min.x + i as VoxRel / size.y, min.y + i as VoxRel % size.y);
+            let col = &self.voxels[x as usize * self.size.y as usize + y as usize];
+            let mut filled = bottom;
+            let mut oldz: VoxRel = 0;
+            for brle in col {
+                let z: VoxRel = oldz + brle.num_minus_one as VoxRel + 1;
+                // Runs are contiguous from the bottom, so this run covers whatever of the box is left below `z`
+                let end = z.min(top).max(filled);
+                for vox in &mut row[(filled - bottom) as usize..(end - bottom) as usize] {
+                    *vox = brle.block;
+                }
+                filled = end;
+                oldz = z;
+                if filled == top {
+                    break;
+                }
+            }
+            for vox in &mut row[(filled - bottom) as usize..] {
+                *vox = Block::empty();
+            }
+        }
+    }
 }
 
 impl ConstructVolume for RleData {
@@ -104,32 +104,34 @@ impl<'a> ChunkSample<'a> {
     }
 
     fn access(lock: &RwLockReadGuard<'a, Chunk>, off: Vec3<VoxRel>) -> Block {
+        ChunkSample::<'a>::volume(lock).at_unchecked(off)
+    }
+
+    fn volume<'b>(lock: &'b RwLockReadGuard<'a, Chunk>) -> &'b dyn ReadVolume<VoxelType = Block> {
         match **lock {
-            Chunk::Homo(ref homo) => homo.at_unchecked(off),
-            Chunk::Hetero(ref hetero) => hetero.at_unchecked(off),
-            Chunk::Rle(ref rle) => rle.at_unchecked(off),
-            Chunk::HeteroAndRle(ref hetero, _) => hetero.at_unchecked(off),
+            Chunk::Homo(ref homo) => homo as &dyn ReadVolume<VoxelType = Block>,
+            Chunk::Hetero(ref hetero) => hetero as &dyn ReadVolume<VoxelType = Block>,
+            Chunk::Rle(ref rle) => rle as &dyn ReadVolume<VoxelType = Block>,
+            Chunk::HeteroAndRle(ref hetero, _) => hetero as &dyn ReadVolume<VoxelType = Block>,
         }
     }
 
     pub fn at_abs(&self, off: Vec3<VoxAbs>) -> Option<Block> {
-        let size = self.size();
-        let chunkidx = terrain::voxabs_to_voloffs(off, size);
-        let blockrel = terrain::voxabs_to_voxrel(off, size);
-        let _ = self.map.get(&chunkidx).map(|lock| {
-            return Some(ChunkSample::<'a>::access(&lock, blockrel));
-        });
-        None
+        let chunkidx = terrain::voxabs_to_voloffs(off, self.vol_size);
+        let blockrel = terrain::voxabs_to_voxrel(off, self.vol_size);
+        self.map
+            .get(&chunkidx)
+            .map(|lock| ChunkSample::<'a>::access(&lock, blockrel))
     }
 
     pub fn at_abs_unchecked(&self, off: Vec3<VoxAbs>) -> Block {
-        let size = self.size();
-        let chunkidx = terrain::voxabs_to_voloffs(off, size);
-        let blockrel = terrain::voxabs_to_voxrel(off, size);
-        let _ = self.map.get(&chunkidx).map(|lock| {
-            return ChunkSample::<'a>::access(&lock, blockrel);
-        });
-        panic!("off not inside VolSample: {}, chunkidx: {}", off, chunkidx);
+        self.at_abs(off).unwrap_or_else(|| {
+            panic!(
+                "off not inside VolSample: {}, chunkidx: {}",
+                off,
+                terrain::voxabs_to_voloffs(off, self.vol_size)
+            )
+        })
     }
 
     pub fn size_blocks(&self) -> Vec3<VoxAbs> { self.block_length }
@@ -149,4 +151,31 @@ impl<'a> ReadVolume for ChunkSample<'a> {
         let abs = self.block_from_abs + pos.map(|e| e as VoxAbs);
         self.at_abs_unchecked(abs)
     }
+
+    // Copy each column a chunk at a time, so chunks are looked up once per column rather than once per block
+    fn fill_slice(&self, min: Vec3<VoxRel>, size: Vec3<VoxRel>, out: &mut [Block]) {
+        terrain::check_slice(min, size, self.size(), out.len());
+        for x in 0..size.x {
+            for y in 0..size.y {
+                let mut z = 0;
+                while z < size.z {
+                    let abs = self.block_from_abs + (min + Vec3::new(x, y, z)).map(|e| e as VoxAbs);
+                    let chunkidx = terrain::voxabs_to_voloffs(abs, self.vol_size);
+                    let blockrel = terrain::voxabs_to_voxrel(abs, self.vol_size);
+                    let lock = self
+                        .map
+                        .get(&chunkidx)
+                        .unwrap_or_else(|| panic!("off not inside VolSample: {}, chunkidx: {}", abs, chunkidx));
+                    let len = (self.vol_size.z - blockrel.z).min(size.z - z);
+                    let start = terrain::slice_index(Vec3::new(x, y, z), size);
+                    ChunkSample::<'a>::volume(lock).fill_slice(
+                        blockrel,
+                        Vec3::new(1, 1, len),
+                        &mut out[start..start + len as usize],
+                    );
+                    z += len;
+                }
+            }
+        }
+    }
 }
@@ -1,11 +1,15 @@
 // Library
+use rand::{prng::XorShiftRng, Rng, SeedableRng};
 use std::fmt::Debug;
 use vek::*;
 
 // Local
 use crate::terrain::{
+    self,
     chunk::{Block, BlockRle, Chunk, ChunkContainer, HeterogeneousData, HomogeneousData, RleData},
-    ConstructVolume, Container, PersState, ReadVolume, ReadWriteVolume, VolCluster, Volume, Voxel,
+    figure::Figure,
+    ConstructVolume, Container, OutOfBounds, PersState, ReadVolume, ReadWriteVolume, VolCluster, Volume, VoxRel, Voxel,
+    WriteVolume,
 };
 
 #[test]
@@ -27,6 +31,224 @@ fn test_homo_chunk() {
     test_read_volume::<HomogeneousData>();
 }
 
+#[test]
+fn fill_slice_matches_per_voxel_reads() {
+    // Tall enough that the runs in the rle data are split
+    let size = Vec3::new(5, 6, 300);
+    let mut hetero = HeterogeneousData::empty(size);
+    let mut figure = Figure::empty(size);
+    for x in 0..size.x {
+        for y in 0..size.y {
+            for z in 0..size.z {
+                let pos = Vec3::new(x, y, z);
+                let block = patterned_block(pos);
+                hetero.replace_at_unchecked(pos, block);
+                figure.replace_at_unchecked(pos, block);
+            }
+        }
+    }
+    let mut rle = Chunk::Hetero(hetero.clone());
+    rle.convert(PersState::Rle);
+
+    assert_fill_slice_matches(&hetero);
+    assert_fill_slice_matches(rle.get(PersState::Rle).unwrap());
+    assert_fill_slice_matches(&HomogeneousData::filled(size, Block::STONE));
+    assert_fill_slice_matches(&figure);
+}
+
+#[test]
+#[should_panic]
+fn fill_slice_outside_volume_panics() {
+    let vol = HeterogeneousData::empty(Vec3::new(4, 4, 4));
+    let mut out = vec![Block::empty(); 8];
+    vol.fill_slice(Vec3::new(3, 3, 3), Vec3::new(2, 2, 2), &mut out);
+}
+
+#[test]
+fn writes_outside_the_volume_are_rejected() {
+    let mut vol = HeterogeneousData::empty(Vec3::new(4, 5, 6));
+    for off in [Vec3::new(4, 0, 0), Vec3::new(0, 5, 0), Vec3::new(0, 0, 6), Vec3::new(9, 9, 9)].iter() {
+        assert_eq!(vol.set(*off, Block::STONE), Err(OutOfBounds(*off)));
+    }
+    assert_eq!(vol.take_dirty_region(), None);
+
+    let mut chunk = Chunk::Homo(HomogeneousData::empty(Vec3::new(4, 5, 6)));
+    assert_eq!(chunk.set(Vec3::new(4, 5, 6), Block::STONE), Err(OutOfBounds(Vec3::new(4, 5, 6))));
+    assert!(chunk.contains(PersState::Homo));
+}
+
+#[test]
+fn dirty_region_holds_every_changed_voxel() {
+    let mut vol = HeterogeneousData::empty(Vec3::new(8, 8, 8));
+    assert_eq!(vol.set(Vec3::new(3, 4, 5), Block::STONE), Ok(Block::AIR));
+    assert_eq!(vol.set(Vec3::new(1, 6, 5), Block::EARTH), Ok(Block::AIR));
+    assert_eq!(vol.set(Vec3::new(2, 2, 7), Block::STONE), Ok(Block::AIR));
+    assert_eq!(vol.set(Vec3::new(3, 4, 5), Block::SAND), Ok(Block::STONE));
+    // Writing what's already there changes nothing
+    assert_eq!(vol.set(Vec3::new(0, 0, 0), Block::AIR), Ok(Block::AIR));
+
+    assert_eq!(
+        vol.take_dirty_region(),
+        Some(Aabb {
+            min: Vec3::new(1, 2, 5),
+            max: Vec3::new(3, 6, 7),
+        })
+    );
+}
+
+#[test]
+fn taking_the_dirty_region_clears_it() {
+    let mut vol = HeterogeneousData::empty(Vec3::new(8, 8, 8));
+    vol.set(Vec3::new(7, 7, 7), Block::STONE).unwrap();
+    assert!(vol.take_dirty_region().is_some());
+    assert_eq!(vol.take_dirty_region(), None);
+
+    vol.set(Vec3::new(1, 1, 1), Block::STONE).unwrap();
+    let single = Aabb {
+        min: Vec3::new(1, 1, 1),
+        max: Vec3::new(1, 1, 1),
+    };
+    assert_eq!(vol.take_dirty_region(), Some(single));
+}
+
+#[test]
+fn homogeneous_chunks_expand_on_the_first_change() {
+    let size = Vec3::new(4, 4, 4);
+    let mut chunk = Chunk::Homo(HomogeneousData::filled(size, Block::STONE));
+    assert_eq!(chunk.set(Vec3::new(1, 1, 1), Block::STONE), Ok(Block::STONE));
+    assert!(chunk.contains(PersState::Homo));
+    assert_eq!(chunk.take_dirty_region(), None);
+
+    assert_eq!(chunk.set(Vec3::new(1, 2, 3), Block::AIR), Ok(Block::STONE));
+    assert!(chunk.contains(PersState::Hetero));
+    let vol = chunk.prefered().unwrap();
+    assert_eq!(vol.at(Vec3::new(1, 2, 3)), Some(Block::AIR));
+    assert_eq!(vol.at(Vec3::new(0, 0, 0)), Some(Block::STONE));
+    assert_eq!(
+        chunk.take_dirty_region(),
+        Some(Aabb {
+            min: Vec3::new(1, 2, 3),
+            max: Vec3::new(1, 2, 3),
+        })
+    );
+
+    // Rle data is dropped rather than left out of date
+    let mut chunk = Chunk::Hetero(HeterogeneousData::filled(size, Block::STONE));
+    chunk.convert(PersState::Rle);
+    chunk.remove(PersState::Hetero);
+    chunk.set(Vec3::new(0, 0, 0), Block::AIR).unwrap();
+    assert!(!chunk.contains(PersState::Rle));
+    assert_eq!(chunk.prefered().unwrap().at(Vec3::new(0, 0, 0)), Some(Block::AIR));
+}
+
+#[test]
+fn heights_follow_random_edits() {
+    let size = Vec3::new(6, 5, 40);
+    let mut rng = XorShiftRng::seed_from_u64(42);
+    let blocks = [Block::AIR, Block::STONE, Block::WATER, Block::EARTH];
+
+    let mut hetero = HeterogeneousData::empty(size);
+    let mut chunk = Chunk::Homo(HomogeneousData::filled(size, Block::STONE));
+    assert_heights_match(&chunk);
+    for _ in 0..2000 {
+        let off = Vec3::new(rng.gen_range(0, size.x), rng.gen_range(0, size.y), rng.gen_range(0, size.z));
+        // Mostly near the top of the columns, where edits move the surface
+        let off = if rng.gen() { Vec3::new(off.x, off.y, size.z - 1 - off.z / 8) } else { off };
+        let block = blocks[rng.gen_range(0, blocks.len())];
+        hetero.replace_at_unchecked(off, block);
+        chunk.set(off, block).unwrap();
+    }
+    assert_heights_match(&Chunk::Hetero(hetero.clone()));
+    assert_heights_match(&chunk);
+
+    // A column that's had its every block taken away has no height
+    for z in 0..size.z {
+        hetero.replace_at_unchecked(Vec3::new(2, 3, z), Block::AIR);
+    }
+    assert_eq!(hetero.height_at(2, 3), None);
+
+    hetero.fill(Block::STONE);
+    assert_eq!(hetero.height_at(2, 3), Some(size.z - 1));
+}
+
+#[test]
+fn heights_survive_serialization() {
+    let size = Vec3::new(5, 6, 300);
+    let mut hetero = HeterogeneousData::empty(size);
+    for x in 0..size.x {
+        for y in 0..size.y {
+            for z in 0..size.z {
+                hetero.replace_at_unchecked(Vec3::new(x, y, z), patterned_block(Vec3::new(x, y, z)));
+            }
+        }
+    }
+    let mut chunk = Chunk::Hetero(hetero);
+    let sent = Chunk::from_bytes(&chunk.to_bytes().unwrap()).unwrap();
+    assert_heights_match(&sent);
+    for x in 0..size.x {
+        for y in 0..size.y {
+            assert_eq!(sent.height_at(x, y), chunk.height_at(x, y));
+        }
+    }
+
+    let mut air = Chunk::Homo(HomogeneousData::empty(size));
+    let sent = Chunk::from_bytes(&air.to_bytes().unwrap()).unwrap();
+    assert_eq!(sent.height_at(1, 1), None);
+}
+
+// Checks each column's height against a scan down the column
+fn assert_heights_match(chunk: &Chunk) {
+    let vol = chunk.prefered().unwrap();
+    let size = vol.size();
+    for x in 0..size.x {
+        for y in 0..size.y {
+            let scanned = (0..size.z).rev().find(|z| vol.at_unchecked(Vec3::new(x, y, *z)).is_solid());
+            assert_eq!(chunk.height_at(x, y), scanned, "Column {}, {}", x, y);
+        }
+    }
+}
+
+// Stone hills with water up to a level and scattered earth above
+fn patterned_block(pos: Vec3<VoxRel>) -> Block {
+    if pos.z < 100 + pos.x * 20 + pos.y {
+        Block::STONE
+    } else if pos.z < 170 {
+        Block::WATER
+    } else if (pos.x + pos.y + pos.z) % 7 == 0 {
+        Block::EARTH
+    } else {
+        Block::AIR
+    }
+}
+
+fn assert_fill_slice_matches<V: ReadVolume<VoxelType = Block> + ?Sized>(vol: &V) {
+    let vs = vol.size();
+    let boxes = vec![
+        (Vec3::new(0, 0, 0), vs),
+        (Vec3::new(1, 2, 3), vs - Vec3::new(2, 3, 5)),
+        (Vec3::new(2, 3, 250), Vec3::new(1, 1, 50)),
+        (Vec3::new(4, 5, 0), Vec3::new(1, 1, 1)),
+        (Vec3::new(1, 1, 1), Vec3::new(0, 2, 2)),
+    ];
+    for (min, size) in boxes {
+        let mut out = vec![Block::empty(); size.map(|e| e as usize).product()];
+        vol.fill_slice(min, size, &mut out);
+        for x in 0..size.x {
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    let off = Vec3::new(x, y, z);
+                    assert_eq!(out[terrain::slice_index(off, size)], vol.at_unchecked(min + off));
+                }
+            }
+        }
+    }
+    for (x, y) in vec![(0, 0), (4, 5), (2, 1)] {
+        let column: Vec<Block> = vol.column(x, y).collect();
+        let expected: Vec<Block> = (0..vs.z).map(|z| vol.at_unchecked(Vec3::new(x, y, z))).collect();
+        assert_eq!(column, expected);
+    }
+}
+
 fn test_volume<V: Volume + ConstructVolume>() {
     let (sizes, _offs) = get_sizes_and_offsets();
 
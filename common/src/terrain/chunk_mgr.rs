@@ -1,17 +1,28 @@
 // Standard
-use std::{collections::HashMap, sync::Arc, thread, time::Duration};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
 
 // Library
 use lazy_static::lazy_static;
 use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use smallvec::SmallVec;
 use threadpool::ThreadPool;
 use vek::*;
 
 // Local
 use crate::terrain::{
     self,
-    chunk::{Block, ChunkContainer, ChunkSample},
-    Container, Key, PersState, VolCluster, VolGen, VolOffs, VoxAbs, VoxRel,
+    chunk::{Block, Chunk, ChunkContainer, ChunkSample},
+    light::{LightQueue, LightUpdate},
+    Channel, Container, Key, Light, PersState, RayHit, VolCluster, VolGen, VolOffs, VoxAbs, VoxRel, Voxel, WriteVolume,
+    MAX_LIGHT,
 };
 
 lazy_static! {
@@ -35,12 +46,85 @@ pub struct BlockLoader {
     pub size: Vec3<VoxAbs>,
 }
 
+/// The chunks whose payloads depend on the block at `pos`: its own chunk, and every chunk it borders. A block on an
+/// edge or in a corner of its chunk borders the chunks diagonal to it too.
+pub fn touched_chunks(pos: Vec3<VoxAbs>, vol_size: Vec3<VoxRel>) -> Vec<Vec3<VolOffs>> {
+    let chunk = terrain::voxabs_to_voloffs(pos, vol_size);
+    let rel = terrain::voxabs_to_voxrel(pos, vol_size);
+    // Which way along each axis the block borders another chunk, if it does
+    let sides = rel.map2(vol_size, |e, size| match e {
+        0 => vec![0, -1],
+        e if e == size - 1 => vec![0, 1],
+        _ => vec![0],
+    });
+
+    let mut chunks = vec![];
+    for x in sides.x.iter() {
+        for y in sides.y.iter() {
+            for z in sides.z.iter() {
+                chunks.push(chunk + Vec3::new(*x, *y, *z));
+            }
+        }
+    }
+    chunks
+}
+
+// The directions of the six chunks sharing a face with a chunk
+fn faces() -> [Vec3<VolOffs>; 6] {
+    [
+        Vec3::unit_x(),
+        -Vec3::unit_x(),
+        Vec3::unit_y(),
+        -Vec3::unit_y(),
+        Vec3::unit_z(),
+        -Vec3::unit_z(),
+    ]
+}
+
+// The loaded chunks around a chunk, including those diagonal to it
+fn neighbours<P>(
+    pers: &HashMap<Vec3<VolOffs>, Arc<ChunkContainer<P>>>,
+    pos: Vec3<VolOffs>,
+) -> HashMap<Vec3<VolOffs>, Arc<ChunkContainer<P>>> {
+    let mut map = HashMap::new();
+    for x in -1..2 {
+        for y in -1..2 {
+            for z in -1..2 {
+                let key = pos + Vec3::new(x, y, z);
+                if key == pos {
+                    continue;
+                }
+                if let Some(con) = pers.get(&key) {
+                    map.insert(key, con.clone());
+                }
+            }
+        }
+    }
+    map
+}
+
+/// Where a chunk is in its life in the manager. Chunks start out loading, exist once they've been moved over by
+/// `maintain` and are removed when they're unloaded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChunkState {
+    Loading,
+    Exists,
+    Removed,
+}
+
+/// Where some chunks are, without a heap allocation unless there are a lot of them
+pub type ChunkPositions = SmallVec<[Vec3<VolOffs>; 32]>;
+
 pub struct ChunkMgr<P: Send + Sync + 'static> {
     vol_size: Vec3<VoxRel>,
     pending: Arc<RwLock<HashMap<Vec3<VolOffs>, Arc<Mutex<Option<ChunkContainer<P>>>>>>>, // Mutex is only needed for compiler, we dont acces it in multiple threads
-    pers: RwLock<HashMap<Vec3<VolOffs>, Arc<ChunkContainer<P>>>>,
+    pers: Arc<RwLock<HashMap<Vec3<VolOffs>, Arc<ChunkContainer<P>>>>>,
+    // The heights of the loaded chunks in each column, kept alongside `pers`
+    columns: RwLock<HashMap<Vec2<VolOffs>, BTreeSet<VolOffs>>>,
     gen: VolGen<Vec3<VolOffs>, ChunkContainer<P>>,
     block_loader: RwLock<Vec<Arc<RwLock<BlockLoader>>>>, //TODO: maybe remove this from CHUNMGR, and just pass it
+    // Told about every change of a chunk's state, until they hang up
+    listeners: Mutex<Vec<Sender<(Vec3<VolOffs>, ChunkState)>>>,
 }
 
 impl<P: Send + Sync + 'static> ChunkMgr<P> {
@@ -48,12 +132,40 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
         ChunkMgr {
             vol_size,
             pending: Arc::new(RwLock::new(HashMap::new())),
-            pers: RwLock::new(HashMap::new()),
+            pers: Arc::new(RwLock::new(HashMap::new())),
+            columns: RwLock::new(HashMap::new()),
             gen,
             block_loader: RwLock::new(Vec::new()),
+            listeners: Mutex::new(Vec::new()),
         }
     }
 
+    /// Hear about chunks starting to load, arriving and being unloaded, in the order it happens, so that the state of
+    /// every chunk can be followed without going through them all
+    pub fn subscribe(&self) -> Receiver<(Vec3<VolOffs>, ChunkState)> {
+        let (tx, rx) = mpsc::channel();
+        self.listeners.lock().push(tx);
+        rx
+    }
+
+    // Keep `columns` in step with a chunk that was just loaded or unloaded
+    fn index(&self, pos: Vec3<VolOffs>, loaded: bool) {
+        let mut columns = self.columns.write();
+        let col = Vec2::new(pos.x, pos.y);
+        if loaded {
+            columns.entry(col).or_default().insert(pos.z);
+        } else if let Some(heights) = columns.get_mut(&col) {
+            heights.remove(&pos.z);
+            if heights.is_empty() {
+                columns.remove(&col);
+            }
+        }
+    }
+
+    fn notify(&self, pos: Vec3<VolOffs>, state: ChunkState) {
+        self.listeners.lock().retain(|tx| tx.send((pos, state)).is_ok());
+    }
+
     pub fn exists_block(&self, pos: Vec3<VoxAbs>) -> bool {
         self.exists_chunk(terrain::voxabs_to_voloffs(pos, self.vol_size))
     }
@@ -64,15 +176,85 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
         let chunk = terrain::voxabs_to_voloffs(pos, self.vol_size);
         let off = terrain::voxabs_to_voxrel(pos, self.vol_size);
         if let Some(chunk) = self.pers.read().get(&chunk) {
-            let lock = chunk.data();
-            let hetero = lock.get(PersState::Hetero);
-            if let Some(hetero) = hetero {
-                return hetero.at(off);
-            }
+            return chunk.data().prefered().and_then(|vol| vol.at(off));
         }
         None
     }
 
+    /// `get_block` for every block from `low` up to (but not including) `high`, a row at a time with x varying fastest,
+    /// then y. Each chunk is only locked once rather than for each of its blocks, and only while its own blocks are
+    /// read, so no two locks are ever held at once.
+    pub fn get_blocks(&self, low: Vec3<VoxAbs>, high: Vec3<VoxAbs>) -> Vec<Option<Block>> {
+        let size = (high - low).map(|e| e.max(0));
+        if size.x == 0 || size.y == 0 || size.z == 0 {
+            return vec![];
+        }
+        let chunk_low = terrain::voxabs_to_voloffs(low, self.vol_size);
+        let chunk_high = terrain::voxabs_to_voloffs(high - Vec3::one(), self.vol_size);
+
+        // Taken out of the map first, so that it isn't held while they're read
+        let chunks = {
+            let pers = self.pers.read();
+            let mut chunks = vec![];
+            for z in chunk_low.z..chunk_high.z + 1 {
+                for y in chunk_low.y..chunk_high.y + 1 {
+                    for x in chunk_low.x..chunk_high.x + 1 {
+                        let key = Vec3::new(x, y, z);
+                        if let Some(con) = pers.get(&key) {
+                            chunks.push((key, con.clone()));
+                        }
+                    }
+                }
+            }
+            chunks
+        };
+
+        let mut blocks = vec![None; (size.x * size.y * size.z) as usize];
+        for (key, con) in chunks {
+            let data = con.data();
+            let vol = match data.prefered() {
+                Some(vol) => vol,
+                None => continue,
+            };
+            // The part of the box in this chunk
+            let origin = terrain::voloffs_to_voxabs(key, self.vol_size);
+            let from = low.map2(origin, |e, o| e.max(o));
+            let to = high.map2(origin + self.vol_size.map(|e| e as VoxAbs), |e, o| e.min(o));
+            for z in from.z..to.z {
+                for y in from.y..to.y {
+                    for x in from.x..to.x {
+                        let pos = Vec3::new(x, y, z);
+                        let i = ((pos.z - low.z) * size.y + (pos.y - low.y)) * size.x + (pos.x - low.x);
+                        blocks[i as usize] = vol.at(terrain::voxabs_to_voxrel(pos, self.vol_size));
+                    }
+                }
+            }
+        }
+        blocks
+    }
+
+    /// Every block the box from `min` to `max` is in any part of, along with where it is. Blocks in chunks that aren't
+    /// loaded are `None`.
+    pub fn blocks_in_aabb(&self, min: Vec3<f64>, max: Vec3<f64>) -> Vec<(Vec3<VoxAbs>, Option<Block>)> {
+        let (low, high) = (terrain::pos_to_voxabs(min), terrain::pos_to_voxabs(max) + Vec3::one());
+        let size = high - low;
+        self.get_blocks(low, high)
+            .into_iter()
+            .enumerate()
+            .map(|(i, block)| {
+                let i = i as VoxAbs;
+                (low + Vec3::new(i % size.x, i / size.x % size.y, i / (size.x * size.y)), block)
+            })
+            .collect()
+    }
+
+    /// Find the first solid block along a ray. Blocks in chunks that aren't loaded are treated as empty.
+    pub fn ray_cast(&self, origin: Vec3<f32>, dir: Vec3<f32>, max_dist: f32) -> Option<RayHit> {
+        terrain::ray_cast(origin, dir, max_dist, |pos| {
+            self.get_block(pos).map(|block| block.is_solid()).unwrap_or(false)
+        })
+    }
+
     // Tries getting a Sample
     pub fn try_get_sample(&self, from: Vec3<VoxAbs>, to: Vec3<VoxAbs>) -> Result<ChunkSample, ChunkSampleError> {
         let mut c = 0;
@@ -131,6 +313,7 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
         let gen_vol = self.gen.gen_vol.clone();
         let gen_payload = self.gen.gen_payload.clone();
         let pen = self.pending.clone();
+        let pers = self.pers.clone();
         let con = Arc::new(Mutex::new(None));
         {
             // the lock below guarantees that no 2 threads can generate the same chunk
@@ -140,12 +323,303 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
             }
             pen_lock.insert(pos, con.clone());
         }
+        self.notify(pos, ChunkState::Loading);
         // run expensive operations in own thread
 
         POOL.lock().execute(move || {
             gen_vol(pos, con.clone());
-            gen_payload(pos, con.clone());
+            if let Some(ref con) = *con.lock() {
+                let neighbours = neighbours(&pers.read(), pos);
+                con.light_alone(!neighbours.contains_key(&(pos + Vec3::unit_z())));
+                gen_payload(pos, con, &neighbours);
+            }
+        });
+    }
+
+    /// Fill a pending chunk with data that was produced elsewhere (e.g: received over the network), light it and
+    /// generate its payload. Returns `false` if the chunk isn't pending.
+    pub fn provide(&self, pos: Vec3<VolOffs>, chunk: Chunk) -> bool {
+        let con = match self.pending.read().get(&pos) {
+            Some(con) => con.clone(),
+            None => return false,
+        };
+        let gen_payload = self.gen.gen_payload.clone();
+        let pers = self.pers.clone();
+
+        POOL.lock().execute(move || {
+            let chunk = ChunkContainer::new(chunk);
+            let neighbours = neighbours(&pers.read(), pos);
+            chunk.light_alone(!neighbours.contains_key(&(pos + Vec3::unit_z())));
+            gen_payload(pos, &chunk, &neighbours);
+            *con.lock() = Some(chunk);
         });
+        true
+    }
+
+    /// Generate the payload (e.g: the mesh) of a loaded chunk again, without touching its data. This is needed when
+    /// something the payload depends on changes, such as a block the chunk borders. Returns `false` if the chunk isn't
+    /// loaded.
+    pub fn regen_payload(&self, pos: Vec3<VolOffs>) -> bool {
+        let con = match self.pers.read().get(&pos) {
+            Some(con) => con.clone(),
+            None => return false,
+        };
+        let gen_payload = self.gen.gen_payload.clone();
+        let pers = self.pers.clone();
+
+        POOL.lock().execute(move || {
+            let neighbours = neighbours(&pers.read(), pos);
+            gen_payload(pos, &con, &neighbours);
+        });
+        true
+    }
+
+    /// Change a block in a loaded chunk. The light the change lets through or blocks is updated, and the payload of
+    /// every loaded chunk that the block is part of or borders, or whose light changed, is regenerated. Returns `false`
+    /// if the block's chunk isn't loaded.
+    pub fn set_block(&self, pos: Vec3<VoxAbs>, block: Block) -> bool {
+        let chunk = terrain::voxabs_to_voloffs(pos, self.vol_size);
+        let con = match self.pers.read().get(&chunk) {
+            Some(con) => con.clone(),
+            None => return false,
+        };
+
+        {
+            if con.data_mut().set(terrain::voxabs_to_voxrel(pos, self.vol_size), block).is_err() {
+                return false;
+            }
+        }
+
+        // Take away the light that went through the block, then let the light around it back in
+        let mut queue = LightQueue::default();
+        {
+            let origin = terrain::voloffs_to_voxabs(chunk, self.vol_size);
+            let data = con.data();
+            let mut guard = con.light_mut();
+            if let (Some(blocks), Some(light)) = (data.prefered(), guard.as_mut()) {
+                let rel = terrain::voxabs_to_voxrel(pos, self.vol_size);
+                let (mut relight, mut out) = (vec![], vec![]);
+                for channel in [Channel::Sun, Channel::Block].iter() {
+                    light.take(blocks, origin, *channel, rel, &mut relight, &mut out);
+                }
+                for (channel, rel) in relight {
+                    queue.relight(channel, origin + rel.map(|e| e as VoxAbs));
+                }
+                for update in out {
+                    queue.push(update);
+                }
+            }
+        }
+        for dir in faces().iter() {
+            queue.relight(Channel::Sun, pos + dir.map(|e| e as VoxAbs));
+            queue.relight(Channel::Block, pos + dir.map(|e| e as VoxAbs));
+        }
+        let mut stale = self.process_light(queue);
+
+        stale.extend(touched_chunks(pos, self.vol_size));
+        for chunk in stale {
+            self.regen_payload(chunk);
+        }
+        true
+    }
+
+    /// The height of the highest solid block in a column of the world, among the chunks that are loaded. `None` if none
+    /// of them have a solid block in the column.
+    pub fn surface_height(&self, pos: Vec2<VoxAbs>) -> Option<VoxAbs> {
+        let col = terrain::voxabs_to_voloffs(Vec3::new(pos.x, pos.y, 0), self.vol_size);
+        let rel = terrain::voxabs_to_voxrel(Vec3::new(pos.x, pos.y, 0), self.vol_size);
+        let pers = self.pers.read();
+        let columns = self.columns.read();
+        // From the top down, so the first chunk with anything solid in the column has the surface
+        columns
+            .get(&Vec2::new(col.x, col.y))?
+            .iter()
+            .rev()
+            .filter_map(|z| {
+                let con = pers.get(&Vec3::new(col.x, col.y, *z))?;
+                let height = con.data().height_at(rel.x, rel.y)?;
+                Some(*z as VoxAbs * self.vol_size.z as VoxAbs + height as VoxAbs)
+            })
+            .next()
+    }
+
+    /// `surface_height` for every column from `low` up to (but not including) `high`, a row at a time with x varying
+    /// fastest. Much quicker than asking for each column on its own, since the chunks are only gone through once.
+    pub fn surface_heights(&self, low: Vec2<VoxAbs>, high: Vec2<VoxAbs>) -> Vec<Option<VoxAbs>> {
+        let size = (high - low).map(|e| e.max(0));
+        let col_low = terrain::voxabs_to_voloffs(Vec3::new(low.x, low.y, 0), self.vol_size);
+        let col_high = terrain::voxabs_to_voloffs(Vec3::new(high.x - 1, high.y - 1, 0), self.vol_size);
+
+        let pers = self.pers.read();
+        let mut columns: HashMap<Vec2<VolOffs>, Vec<_>> = HashMap::new();
+        for (p, con) in pers.iter() {
+            if p.x >= col_low.x && p.y >= col_low.y && p.x <= col_high.x && p.y <= col_high.y {
+                columns.entry(Vec2::new(p.x, p.y)).or_default().push((p.z, con));
+            }
+        }
+        // From the top down, as in `surface_height`
+        for chunks in columns.values_mut() {
+            chunks.sort_by_key(|(z, _)| -z);
+        }
+
+        let mut heights = Vec::with_capacity((size.x * size.y) as usize);
+        for y in low.y..low.y + size.y {
+            for x in low.x..low.x + size.x {
+                let pos = Vec3::new(x, y, 0);
+                let col = terrain::voxabs_to_voloffs(pos, self.vol_size);
+                let rel = terrain::voxabs_to_voxrel(pos, self.vol_size);
+                heights.push(columns.get(&Vec2::new(col.x, col.y)).and_then(|chunks| {
+                    chunks
+                        .iter()
+                        .filter_map(|(z, con)| {
+                            let height = con.data().height_at(rel.x, rel.y)?;
+                            Some(*z as VoxAbs * self.vol_size.z as VoxAbs + height as VoxAbs)
+                        })
+                        .next()
+                }));
+            }
+        }
+        heights
+    }
+
+    /// The light at a block, if its chunk is loaded and has been lit
+    pub fn get_light(&self, pos: Vec3<VoxAbs>) -> Option<Light> {
+        let chunk = terrain::voxabs_to_voloffs(pos, self.vol_size);
+        let off = terrain::voxabs_to_voxrel(pos, self.vol_size);
+        if let Some(chunk) = self.pers.read().get(&chunk) {
+            let light = chunk.light().as_ref().map(|light| light.get(off));
+            return light;
+        }
+        None
+    }
+
+    // Carry out the light updates in `queue`, returning the chunks whose light changed
+    fn process_light(&self, queue: LightQueue) -> HashSet<Vec3<VolOffs>> {
+        queue.process(self.vol_size, |pos| self.pers.read().get(&pos).cloned())
+    }
+
+    // Queue the light on the side of a loaded chunk facing `dir` to be spread over the border
+    fn relight_face(&self, pos: Vec3<VolOffs>, con: &ChunkContainer<P>, dir: Vec3<VolOffs>, queue: &mut LightQueue) {
+        let origin = terrain::voloffs_to_voxabs(pos, self.vol_size);
+        if let Some(light) = con.light().as_ref() {
+            for rel in light.face(dir.map(|e| e as i64)) {
+                for channel in [Channel::Sun, Channel::Block].iter() {
+                    if light.level(*channel, rel) > 0 {
+                        queue.relight(*channel, origin + rel.map(|e| e as VoxAbs));
+                    }
+                }
+            }
+        }
+    }
+
+    // Queue the light that crosses the borders between a chunk and the loaded chunks sharing a face with it, both
+    // ways. Sunlight that was assumed to fall onto the top of either of them is taken away where the other covers it.
+    fn exchange_light(&self, pos: Vec3<VolOffs>, queue: &mut LightQueue) {
+        let con = match self.pers.read().get(&pos) {
+            Some(con) => con.clone(),
+            None => return,
+        };
+        for dir in faces().iter() {
+            let other = match self.pers.read().get(&(pos + *dir)) {
+                Some(other) => other.clone(),
+                None => continue,
+            };
+            if dir.z > 0 {
+                self.cover_sky(pos, &con, &other, queue);
+            } else if dir.z < 0 {
+                self.cover_sky(pos + *dir, &other, &con, queue);
+            }
+            self.relight_face(pos, &con, *dir, queue);
+            self.relight_face(pos + *dir, &other, -*dir, queue);
+        }
+    }
+
+    // The chunk above a chunk lit as if under the open sky has turned up, so take the sunlight away from the top of the
+    // chunk below wherever the bottom of the one above doesn't let it through
+    fn cover_sky(
+        &self,
+        pos: Vec3<VolOffs>,
+        below: &ChunkContainer<P>,
+        above: &ChunkContainer<P>,
+        queue: &mut LightQueue,
+    ) {
+        let open = match above.light().as_ref() {
+            Some(light) => light
+                .face(-Vec3::unit_z())
+                .into_iter()
+                .map(|rel| (Vec2::from(rel), light.level(Channel::Sun, rel) == MAX_LIGHT))
+                .collect::<Vec<_>>(),
+            None => return,
+        };
+        let mut guard = below.light_mut();
+        let light = match guard.as_mut() {
+            Some(light) => light,
+            None => return,
+        };
+        if !light.sky_assumed() {
+            return;
+        }
+        light.set_sky_assumed(false);
+
+        let origin = terrain::voloffs_to_voxabs(pos, self.vol_size);
+        let top = light.size().z as VoxAbs - 1;
+        for (col, open) in open {
+            if !open {
+                queue.push(LightUpdate {
+                    pos: origin + Vec3::new(col.x as VoxAbs, col.y as VoxAbs, top),
+                    channel: Channel::Sun,
+                    level: MAX_LIGHT,
+                    down: true,
+                    removed: true,
+                });
+            }
+        }
+    }
+
+    /// Replace the data of a loaded chunk, e.g: with a newer version received over the network. The chunk is lit
+    /// again, and the payload of the chunk, of every loaded chunk sharing a face with it and of every chunk whose light
+    /// changed is regenerated. Returns `false` if the chunk isn't loaded.
+    pub fn replace(&self, pos: Vec3<VolOffs>, chunk: Chunk) -> bool {
+        let con = match self.pers.read().get(&pos) {
+            Some(con) => con.clone(),
+            None => return false,
+        };
+
+        // The light the old chunk let out into its neighbours goes with it
+        let mut queue = LightQueue::default();
+        if let Some(light) = con.light().as_ref() {
+            let origin = terrain::voloffs_to_voxabs(pos, self.vol_size);
+            for dir in faces().iter() {
+                let dir = dir.map(|e| e as i64);
+                for rel in light.face(dir) {
+                    for channel in [Channel::Sun, Channel::Block].iter() {
+                        let level = light.level(*channel, rel);
+                        if level > 0 {
+                            queue.push(LightUpdate {
+                                pos: origin + rel.map(|e| e as VoxAbs) + dir,
+                                channel: *channel,
+                                level,
+                                down: dir.z < 0,
+                                removed: true,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        *con.data_mut() = chunk;
+        con.light_alone(!self.exists_chunk(pos + Vec3::unit_z()));
+        self.exchange_light(pos, &mut queue);
+
+        let mut stale = self.process_light(queue);
+        stale.insert(pos);
+        for dir in faces().iter() {
+            stale.insert(pos + *dir);
+        }
+        for pos in stale {
+            self.regen_payload(pos);
+        }
+        true
     }
 
     pub fn drop(&self, pos: Vec3<VolOffs>) {
@@ -153,7 +627,10 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
         let drop_vol = self.gen.drop_vol.clone();
         let drop_payload = self.gen.drop_payload.clone();
 
-        if let Some(rem) = self.pers.write().remove(&pos) {
+        let removed = self.pers.write().remove(&pos);
+        if let Some(rem) = removed {
+            self.index(pos, false);
+            self.notify(pos, ChunkState::Removed);
             POOL.lock().execute(move || {
                 drop_vol(pos, rem.clone());
                 drop_payload(pos, rem.clone());
@@ -161,8 +638,9 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
         }
     }
 
-    // regually call this to copy over generated chunks
-    pub fn maintain(&self) {
+    // regually call this to copy over generated chunks. Returns the chunks that were unloaded.
+    pub fn maintain(&self) -> Vec<Vec3<VolOffs>> {
+        let mut arrived = vec![];
         {
             // handle new generated chunks
             let mut pen_lock = self.pending.write();
@@ -177,6 +655,9 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
                             let opt = m.into_inner();
                             let arc = Arc::new(opt.unwrap());
                             self.pers.write().insert(pos, arc);
+                            self.index(pos, true);
+                            self.notify(pos, ChunkState::Exists);
+                            arrived.push(pos);
                         },
                         Err(con_arc) => {
                             map.insert(pos, con_arc);
@@ -193,6 +674,25 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
             }
         }
 
+        // Light new chunks and their neighbours from each other
+        let mut queue = LightQueue::default();
+        for pos in arrived.iter() {
+            self.exchange_light(*pos, &mut queue);
+        }
+        let mut stale = self.process_light(queue);
+
+        // Chunks that were loaded before a neighbour arrived treated it as empty, so regenerate their payloads. Only
+        // chunks sharing a face with a new one are regenerated, since that's where faces go missing; neighbours that
+        // only share an edge or corner just shade it slightly differently.
+        for pos in arrived {
+            for dir in faces().iter() {
+                stale.insert(pos + *dir);
+            }
+        }
+        for pos in stale {
+            self.regen_payload(pos);
+        }
+
         // generate new chunks
         let mut chunk_map = HashMap::new();
         let block_loader: Vec<BlockLoader> = self.block_loader.read().iter().map(|e| (*e.read()).clone()).collect(); // buffer blockloader
@@ -229,7 +729,7 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
         for (pos, _diff) in chunks.iter() {
             if !self.exists_chunk(*pos) {
                 // generate up to MAX_CHUNKS_IN_QUEUE chunks around the player
-                if self.pending_chunk_cnt() < MAX_CHUNKS_IN_QUEUE {
+                if self.pending_count() < MAX_CHUNKS_IN_QUEUE {
                     self.gen(*pos);
                 }
             }
@@ -239,11 +739,10 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
         // unload all chunks which have a distance of DIFF_TILL_UNLOAD to a loaded area
 
         // drop old chunks
-        let mut to_remove = Vec::new(); //needed for lock on pers
-        for (k, _) in self.pers.read().iter() {
-            // skip if exists in HashMap
+        self.retain(|k| {
+            // keep if exists in HashMap
             if chunk_map.contains_key(k) {
-                continue;
+                return true;
             }
             let k_mid = terrain::voloffs_to_voxabs(*k, self.vol_size) + self.vol_size.map(|e| e as i64 / 2);
             let mut lowest_dist = diff_till_unload_square - 1; // bigger than DIFF_TILL_UNLOAD
@@ -256,14 +755,8 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
                     lowest_dist = dist;
                 }
             }
-            if lowest_dist > diff_till_unload_square {
-                to_remove.push(*k);
-            }
-        }
-
-        for k in to_remove.iter() {
-            self.drop(*k);
-        }
+            lowest_dist <= diff_till_unload_square
+        })
     }
 
     pub fn debug(&self) {
@@ -297,9 +790,33 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
         );
     }
 
-    pub fn remove(&self, pos: Vec3<VolOffs>) -> bool { self.pers.write().remove(&pos).is_some() }
+    /// Load `chunk` straight away, replacing any chunk already loaded at `pos`. It isn't lit, and its payload isn't
+    /// generated.
+    pub fn insert(&self, pos: Vec3<VolOffs>, chunk: Chunk) {
+        self.pers.write().insert(pos, Arc::new(ChunkContainer::new(chunk)));
+        self.index(pos, true);
+        self.notify(pos, ChunkState::Exists);
+    }
+
+    pub fn remove(&self, pos: Vec3<VolOffs>) -> bool {
+        let removed = self.pers.write().remove(&pos).is_some();
+        if removed {
+            self.index(pos, false);
+            self.notify(pos, ChunkState::Removed);
+        }
+        removed
+    }
+
+    /// The loaded chunk at `pos`, if there is one
+    pub fn get_chunk(&self, pos: Vec3<VolOffs>) -> Option<Arc<ChunkContainer<P>>> {
+        self.pers.read().get(&pos).cloned()
+    }
+
+    pub fn loaded_count(&self) -> usize { self.pers.read().len() }
 
-    pub fn pending_chunk_cnt(&self) -> usize { self.pending.read().len() }
+    pub fn pending_count(&self) -> usize { self.pending.read().len() }
+
+    pub fn is_pending(&self, pos: Vec3<VolOffs>) -> bool { self.pending.read().contains_key(&pos) }
 
     pub fn pers<F>(&self, filter: F) -> HashMap<Vec3<VolOffs>, Arc<ChunkContainer<P>>>
     where
@@ -315,7 +832,410 @@ impl<P: Send + Sync + 'static> ChunkMgr<P> {
         return new_map;
     }
 
+    /// Call `f` with every loaded chunk. The chunks are copied out under a short lock first, so `f` may use the
+    /// manager itself, and chunks can arrive or be unloaded while it runs.
+    pub fn for_each_loaded<F>(&self, mut f: F)
+    where
+        F: FnMut(Vec3<VolOffs>, &Arc<ChunkContainer<P>>),
+    {
+        let loaded: Vec<_> = self.pers.read().iter().map(|(k, a)| (*k, a.clone())).collect();
+        for (pos, con) in loaded.iter() {
+            f(*pos, con);
+        }
+    }
+
+    /// Where every loaded chunk is
+    pub fn positions(&self) -> ChunkPositions { self.pers.read().keys().cloned().collect() }
+
+    /// Unload every chunk `keep` turns down, returning where they were. Their drop functions all run in one job.
+    pub fn retain<F>(&self, keep: F) -> ChunkPositions
+    where
+        F: Fn(&Vec3<VolOffs>) -> bool,
+    {
+        // `keep` runs without the lock held, so that it can use the manager too
+        let gone: ChunkPositions = self.positions().into_iter().filter(|k| !keep(k)).collect();
+        let removed: Vec<_> = {
+            let mut pers = self.pers.write();
+            gone.into_iter().filter_map(|k| pers.remove(&k).map(|a| (k, a))).collect()
+        };
+        let positions: ChunkPositions = removed.iter().map(|(k, _)| *k).collect();
+        for pos in positions.iter() {
+            self.index(*pos, false);
+            self.notify(*pos, ChunkState::Removed);
+        }
+
+        if !removed.is_empty() {
+            let drop_vol = self.gen.drop_vol.clone();
+            let drop_payload = self.gen.drop_payload.clone();
+            POOL.lock().execute(move || {
+                for (pos, rem) in removed {
+                    drop_vol(pos, rem.clone());
+                    drop_payload(pos, rem);
+                }
+            });
+        }
+        positions
+    }
+
     pub fn block_loader(&self) -> RwLockReadGuard<Vec<Arc<RwLock<BlockLoader>>>> { self.block_loader.read() }
 
     pub fn block_loader_mut(&self) -> RwLockWriteGuard<Vec<Arc<RwLock<BlockLoader>>>> { self.block_loader.write() }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Instant,
+    };
+
+    use crate::terrain::{
+        chunk::{HeterogeneousData, HomogeneousData, CHUNK_SIZE},
+        ConstructVolume, ReadWriteVolume,
+    };
+    // What the test payload makes of a chunk: how many sides of its solid blocks face sideways onto air, counting
+    // chunks that aren't loaded as air like the real mesher does, and how many times it's been made
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct Mesh {
+        faces: usize,
+        times: usize,
+    }
+
+    // A stone floor at z = 0 in every chunk
+    fn gen_floor(_pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<Mesh>>>>) {
+        let mut c = HeterogeneousData::filled(CHUNK_SIZE, Block::AIR);
+        for x in 0..CHUNK_SIZE.x {
+            for y in 0..CHUNK_SIZE.y {
+                c.replace_at_unchecked(Vec3::new(x, y, 0), Block::STONE);
+            }
+        }
+        *con.lock() = Some(ChunkContainer::new(Chunk::Hetero(c)));
+    }
+
+    fn gen_mesh(
+        pos: Vec3<VolOffs>,
+        con: &ChunkContainer<Mesh>,
+        neighbours: &HashMap<Vec3<VolOffs>, Arc<ChunkContainer<Mesh>>>,
+    ) {
+        let times = con.payload().map_or(0, |mesh| mesh.times) + 1;
+        let faces = {
+            let blocks = neighbours
+                .iter()
+                .map(|(k, c)| (*k, c.data()))
+                .chain(Some((pos, con.data())))
+                .collect::<HashMap<_, _>>();
+            let solid = |abs: Vec3<VoxAbs>| {
+                blocks
+                    .get(&terrain::voxabs_to_voloffs(abs, CHUNK_SIZE))
+                    .and_then(|chunk| chunk.prefered()?.at(terrain::voxabs_to_voxrel(abs, CHUNK_SIZE)))
+                    .map_or(false, |block| block.is_solid())
+            };
+            let origin = terrain::voloffs_to_voxabs(pos, CHUNK_SIZE);
+            let mut count = 0;
+            for x in 0..CHUNK_SIZE.x as VoxAbs {
+                for y in 0..CHUNK_SIZE.y as VoxAbs {
+                    for z in 0..CHUNK_SIZE.z as VoxAbs {
+                        let abs = origin + Vec3::new(x, y, z);
+                        if solid(abs) {
+                            count += faces()[..4].iter().filter(|dir| !solid(abs + dir.map(|e| e as VoxAbs))).count();
+                        }
+                    }
+                }
+            }
+            count
+        };
+        *con.payload_mut() = Some(Mesh { faces, times });
+    }
+
+    // Wait for every chunk and payload that's being generated to be done
+    fn settle() { POOL.lock().join(); }
+
+    fn mgr(chunks: &[Vec3<VolOffs>]) -> ChunkMgr<Mesh> {
+        let mgr = ChunkMgr::new(CHUNK_SIZE, VolGen::new(gen_floor, gen_mesh, |_, _| {}, |_, _| {}));
+        for chunk in chunks {
+            mgr.gen(*chunk);
+        }
+        settle();
+        mgr.maintain();
+        settle(); // Chunks arriving regenerate their neighbours
+        mgr
+    }
+
+    fn mesh(mgr: &ChunkMgr<Mesh>, pos: Vec3<VolOffs>) -> Mesh {
+        mgr.get_chunk(pos).and_then(|con| *con.payload()).expect("chunk has been meshed")
+    }
+
+    #[test]
+    fn touched_chunks_include_neighbours() {
+        let size = Vec3::new(32, 32, 32);
+        assert_eq!(touched_chunks(Vec3::new(5, 5, 5), size), vec![Vec3::zero()]);
+        assert_eq!(
+            touched_chunks(Vec3::new(32, 5, 5), size),
+            vec![Vec3::new(1, 0, 0), Vec3::new(0, 0, 0)]
+        );
+        assert_eq!(
+            touched_chunks(Vec3::new(31, 5, 5), size),
+            vec![Vec3::new(0, 0, 0), Vec3::new(1, 0, 0)]
+        );
+
+        // A corner block borders seven other chunks
+        let corner = touched_chunks(Vec3::new(0, 0, 0), size);
+        assert_eq!(corner.len(), 8);
+        assert!(corner.contains(&Vec3::new(-1, -1, -1)));
+    }
+
+    #[test]
+    fn border_edits_regenerate_both_chunks() {
+        let (a, b) = (Vec3::new(100, 0, 0), Vec3::new(101, 0, 0));
+        let mgr = mgr(&[a, b]);
+        // Each floor is open to the air on the three sides away from the other
+        assert_eq!(mesh(&mgr, a).faces, 3 * 32);
+        assert_eq!(mesh(&mgr, b).faces, 3 * 32);
+
+        // Dig out the floor at the very start of the second chunk
+        let border = Vec3::new(101 * CHUNK_SIZE.x as i64, 3, 0);
+        assert!(mgr.set_block(border, Block::AIR));
+        settle();
+
+        // The first chunk's floor now faces the hole, and the three blocks around it in the second chunk do too
+        assert_eq!(mesh(&mgr, a).faces, 3 * 32 + 1);
+        assert_eq!(mesh(&mgr, b).faces, 3 * 32 + 3);
+
+        // Both sides of the border see the hole
+        assert_eq!(mgr.get_block(border), Some(Block::AIR));
+        assert_eq!(mgr.get_block(border - Vec3::unit_x()), Some(Block::STONE));
+    }
+
+    #[test]
+    fn inner_edits_regenerate_one_chunk() {
+        let (a, b) = (Vec3::new(200, 0, 0), Vec3::new(201, 0, 0));
+        let mgr = mgr(&[a, b]);
+        let before = mesh(&mgr, a);
+
+        assert!(mgr.set_block(Vec3::new(201 * CHUNK_SIZE.x as i64 + 5, 3, 0), Block::AIR));
+        settle();
+
+        assert_eq!(mesh(&mgr, a), before);
+        assert_eq!(mesh(&mgr, b).faces, 3 * 32 + 4);
+    }
+
+    #[test]
+    fn arriving_chunks_regenerate_neighbours() {
+        let (a, b, far) = (Vec3::new(300, 0, 0), Vec3::new(300, 1, 0), Vec3::new(300, 5, 0));
+        let mgr = mgr(&[a, far]);
+        let before = mesh(&mgr, far);
+        assert_eq!(mesh(&mgr, a).faces, 4 * 32);
+
+        mgr.gen(b);
+        settle();
+        mgr.maintain();
+        settle();
+
+        // The side of the floor facing the new chunk is covered now
+        assert_eq!(mesh(&mgr, a).faces, 3 * 32);
+        assert_eq!(mesh(&mgr, far), before);
+    }
+
+    #[test]
+    fn surface_height_is_the_top_of_the_highest_loaded_chunk() {
+        let (low, high) = (Vec3::new(500, 0, 0), Vec3::new(500, 0, 1));
+        let mgr = mgr(&[low, high]);
+        let col = Vec2::new(500 * CHUNK_SIZE.x as i64 + 3, 3);
+        let floor = CHUNK_SIZE.z as i64;
+        assert_eq!(mgr.surface_height(col), Some(floor));
+
+        // Dig through the upper floor to the lower one, then build up from it
+        assert!(mgr.set_block(Vec3::new(col.x, col.y, floor), Block::AIR));
+        assert_eq!(mgr.surface_height(col), Some(0));
+        assert!(mgr.set_block(Vec3::new(col.x, col.y, floor + 8), Block::STONE));
+        assert_eq!(mgr.surface_height(col), Some(floor + 8));
+
+        // Down to the lower floor again once the upper chunk is unloaded, and nothing once both are
+        assert!(mgr.remove(high));
+        assert_eq!(mgr.surface_height(col), Some(0));
+        mgr.retain(|_| false);
+        assert_eq!(mgr.surface_height(col), None);
+
+        assert_eq!(mgr.surface_height(Vec2::new(-5, -5)), None);
+    }
+
+    #[test]
+    fn surface_heights_match_each_column() {
+        let mgr = mgr(&[Vec3::new(600, 0, 0), Vec3::new(600, 0, 1), Vec3::new(601, 0, 0)]);
+        let floor = CHUNK_SIZE.z as i64;
+        let corner = Vec2::new(600 * CHUNK_SIZE.x as i64 + 3, 3);
+        assert!(mgr.set_block(Vec3::new(corner.x + 2, corner.y + 1, floor), Block::AIR));
+        assert!(mgr.set_block(Vec3::new(corner.x + 1, corner.y, floor + 8), Block::STONE));
+
+        // Straddling both chunks, and unloaded columns beyond them
+        let (from, to) = (corner - Vec2::new(0, 5), corner + Vec2::new(CHUNK_SIZE.x as i64, 4));
+        let heights = mgr.surface_heights(from, to);
+        assert_eq!(heights.len(), ((to.x - from.x) * (to.y - from.y)) as usize);
+        for (i, height) in heights.into_iter().enumerate() {
+            let width = to.x - from.x;
+            let col = from + Vec2::new(i as i64 % width, i as i64 / width);
+            assert_eq!(height, mgr.surface_height(col), "column {:?}", col);
+        }
+    }
+
+    #[test]
+    fn edits_to_unloaded_chunks_fail() {
+        let mgr = mgr(&[]);
+        assert!(!mgr.set_block(Vec3::new(0, 0, 0), Block::AIR));
+        assert!(!mgr.regen_payload(Vec3::new(0, 0, 0)));
+        assert!(!mgr.replace(Vec3::new(0, 0, 0), Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR))));
+    }
+
+    #[test]
+    fn replaced_chunks_regenerate_with_their_neighbours() {
+        let (a, b, far) = (Vec3::new(400, 0, 0), Vec3::new(401, 0, 0), Vec3::new(405, 0, 0));
+        let mgr = mgr(&[a, b, far]);
+        let before = mesh(&mgr, far);
+        assert_eq!(mesh(&mgr, a).faces, 3 * 32);
+
+        assert!(mgr.replace(b, Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR))));
+        settle();
+
+        // The first floor is open to the air where the second used to be
+        assert_eq!(mesh(&mgr, a).faces, 4 * 32);
+        assert_eq!(mesh(&mgr, b).faces, 0);
+        assert_eq!(mesh(&mgr, far), before);
+        assert_eq!(mgr.get_block(Vec3::new(401 * CHUNK_SIZE.x as i64, 3, 0)), Some(Block::AIR));
+    }
+
+    #[test]
+    fn chunk_states_are_sent_as_they_change() {
+        let (a, b) = (Vec3::new(700, 0, 0), Vec3::new(701, 0, 0));
+        let mgr = mgr(&[]);
+        let states = mgr.subscribe();
+        mgr.gen(a);
+        mgr.gen(b);
+        settle();
+        mgr.maintain();
+        assert!(mgr.remove(a));
+        mgr.retain(|_| false);
+
+        let seen = states.try_iter().collect::<Vec<_>>();
+        for pos in [a, b].iter() {
+            let of_pos = seen
+                .iter()
+                .filter(|(p, _)| p == pos)
+                .map(|(_, state)| *state)
+                .collect::<Vec<_>>();
+            assert_eq!(of_pos, vec![ChunkState::Loading, ChunkState::Exists, ChunkState::Removed]);
+        }
+
+        // Listeners that hang up are forgotten
+        drop(states);
+        mgr.gen(a);
+        assert!(mgr.listeners.lock().is_empty());
+    }
+
+    #[test]
+    fn frames_keep_up_while_hundreds_of_chunks_load() {
+        // Far enough apart that none is another's neighbour, so each is meshed once
+        let chunks = (0..200).map(|i| Vec3::new(800 + 2 * i, 0, 0)).collect::<Vec<_>>();
+        let mgr = Arc::new(mgr(&[]));
+        let states = mgr.subscribe();
+        for pos in &chunks {
+            mgr.gen(*pos);
+        }
+        // Chunks are moved over off the render thread, the way the client's tick does it
+        let done = Arc::new(AtomicBool::new(false));
+        let maintainer = {
+            let (mgr, done) = (mgr.clone(), done.clone());
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    mgr.maintain();
+                    thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+
+        // Each frame only looks at the chunks it's told have arrived, and never waits on one being written to
+        let mut arrived = HashSet::new();
+        let mut slowest = Duration::default();
+        let start = Instant::now();
+        while arrived.len() < chunks.len() && start.elapsed() < Duration::from_secs(60) {
+            let frame = Instant::now();
+            for (pos, state) in states.try_iter() {
+                if state == ChunkState::Exists {
+                    let con = mgr.get_chunk(pos).expect("arrived chunk is loaded");
+                    if let Some(payload) = con.payload_try_mut() {
+                        assert!(payload.is_some());
+                    }
+                    arrived.insert(pos);
+                }
+            }
+            slowest = slowest.max(frame.elapsed());
+            thread::sleep(Duration::from_millis(1));
+        }
+        done.store(true, Ordering::Relaxed);
+        maintainer.join().unwrap();
+
+        assert_eq!(arrived.len(), chunks.len());
+        assert!(slowest < Duration::from_millis(50), "a frame took {:?}", slowest);
+    }
+
+    #[test]
+    fn loaded_chunks_can_be_listed_and_unloaded_together() {
+        let (a, b, c) = (Vec3::new(500, 0, 0), Vec3::new(501, 0, 0), Vec3::new(502, 0, 0));
+        let mgr = mgr(&[a, b, c]);
+        assert_eq!(mgr.loaded_count(), 3);
+        assert_eq!(mgr.pending_count(), 0);
+        let positions = mgr.positions();
+        assert_eq!(positions.len(), 3);
+        assert!(positions.contains(&a) && positions.contains(&b) && positions.contains(&c));
+
+        // The manager can be used while going through its chunks
+        let mut seen = vec![];
+        mgr.for_each_loaded(|pos, _| {
+            assert!(mgr.exists_chunk(pos));
+            seen.push(pos);
+        });
+        assert_eq!(seen.len(), 3);
+
+        // Deciding what to keep can look at the manager too
+        assert_eq!(mgr.retain(|pos| *pos != b && mgr.exists_chunk(*pos)).into_vec(), vec![b]);
+        assert_eq!(mgr.loaded_count(), 2);
+        assert!(!mgr.exists_chunk(b));
+        assert!(mgr.retain(|_| true).is_empty());
+    }
+
+    #[test]
+    fn blocks_are_found_below_zero_and_across_chunk_borders() {
+        // Just below the origin in x and y, so every block in it is at negative coordinates
+        let mgr = mgr(&[Vec3::new(-1, -1, 0)]);
+        let block_at = |x, y, z| mgr.get_block(terrain::pos_to_voxabs(Vec3::new(x, y, z)));
+        assert_eq!(terrain::pos_to_voxabs(Vec3::new(-0.5, -0.5, 0.5)), Vec3::new(-1, -1, 0));
+        // Past 2^24, where an f32 can't hold the half
+        assert_eq!(
+            terrain::pos_to_voxabs(Vec3::new(16_777_217.5, -16_777_217.5, 0.5)),
+            Vec3::new(16_777_217, -16_777_218, 0)
+        );
+        assert_eq!(block_at(-0.5, -0.5, 0.5), Some(Block::STONE));
+        assert_eq!(block_at(-0.5, -0.5, 1.5), Some(Block::AIR));
+        assert_eq!(block_at(-32.0, -32.0, 0.0), Some(Block::STONE));
+        // The chunks beside it aren't loaded
+        assert_eq!(block_at(0.5, -0.5, 0.5), None);
+        assert_eq!(block_at(-32.5, -0.5, 0.5), None);
+
+        // Straddling the border with the unloaded chunk in x, a row at a time
+        let blocks = mgr.blocks_in_aabb(Vec3::new(-1.5, -0.5, 0.2), Vec3::new(0.5, -0.2, 1.2));
+        assert_eq!(
+            blocks,
+            vec![
+                (Vec3::new(-2, -1, 0), Some(Block::STONE)),
+                (Vec3::new(-1, -1, 0), Some(Block::STONE)),
+                (Vec3::new(0, -1, 0), None),
+                (Vec3::new(-2, -1, 1), Some(Block::AIR)),
+                (Vec3::new(-1, -1, 1), Some(Block::AIR)),
+                (Vec3::new(0, -1, 1), None),
+            ]
+        );
+        for (pos, block) in mgr.blocks_in_aabb(Vec3::new(-34.0, -3.0, -1.0), Vec3::new(2.0, 1.0, 2.0)) {
+            assert_eq!(block, mgr.get_block(pos), "block at {:?}", pos);
+        }
+    }
+}
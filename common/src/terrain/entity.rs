@@ -1,11 +1,65 @@
+// Standard
+use std::f32::consts::PI;
+
 // Library
+use serde_derive::{Deserialize, Serialize};
 use vek::*;
 
+// Project
+use crate::ecs::{
+    inventory::{Item, ItemKind},
+    phys::{CollisionBox, MoveMode},
+};
+
+// How fast entities turn to look where the server says they are, in radians per second. Fast enough to keep up with
+// players looking around, but not so fast that it looks like snapping.
+const TURN_SPEED: f32 = 4.0 * PI;
+// How fast entities lean towards where the server says they're leaning, per second
+const LEAN_SPEED: f32 = 4.0;
+
+/// Turn the angle `from` at most `max_step` radians towards `to`, whichever way round is shorter
+fn turn_towards(from: f32, to: f32, max_step: f32) -> f32 {
+    let diff = (to - from + PI).mod_euc(2.0 * PI) - PI;
+    if diff.abs() <= max_step {
+        to
+    } else {
+        from + max_step * diff.signum()
+    }
+}
+
+/// What the physics step remembers about an entity from one tick to the next
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BodyState {
+    // Whether the entity is actually crouched, which it stays until there's room to stand up
+    pub(crate) crouching: bool,
+    pub(crate) grounded: bool,
+    // How much longer the entity may still jump after walking off a ledge, in seconds
+    pub(crate) coyote_time: f32,
+    // Cleared by jumping and set again once jump is let go, so that holding jump doesn't keep jumping
+    pub(crate) jump_ready: bool,
+}
+
 pub struct Entity<P: Send + Sync + 'static> {
     pos: Vec3<f32>, //middle x,y of the figure, z pos is on the ground
+    // Where the entity was before the last physics step, to draw it between there and `pos`
+    last_pos: Vec3<f32>,
     vel: Vec3<f32>,
     ctrl_acc: Vec3<f32>,
     look_dir: Vec2<f32>,
+    // Where the entity is turning to look, if it isn't looking there yet
+    look_target: Option<Vec2<f32>>,
+    move_mode: MoveMode,
+    jump: bool,
+    body: BodyState,
+    collision_box: CollisionBox,
+    // The size physics last gave the entity, which stays behind `collision_box` while there's no room to grow into it
+    body_size: Vec3<f32>,
+    // What the entity is called, if the server has said
+    name: Option<String>,
+    // What kind of item the entity is holding, if the server has said it's holding anything
+    held_item: Option<ItemKind>,
+    // What the entity is, if it's an item lying on the ground
+    item: Option<Item>,
     payload: Option<P>,
 }
 
@@ -13,29 +67,147 @@ impl<P: Send + Sync + 'static> Entity<P> {
     pub fn new(pos: Vec3<f32>, vel: Vec3<f32>, ctrl_acc: Vec3<f32>, look_dir: Vec2<f32>) -> Entity<P> {
         Entity {
             pos,
+            last_pos: pos,
             vel,
             ctrl_acc, //entity triest to move in this directory (maybe should be made a acceleration in future versions with correct netwon movement)
             look_dir,
+            look_target: None,
+            move_mode: MoveMode::Walk,
+            jump: false,
+            body: BodyState::default(),
+            collision_box: CollisionBox::default(),
+            body_size: CollisionBox::default().0,
+            name: None,
+            held_item: None,
+            item: None,
             payload: None,
         }
     }
 
     pub fn pos(&self) -> &Vec3<f32> { &self.pos }
 
+    /// Where the entity is `alpha` of the way from where it was before the last physics step to where it is now
+    pub fn interpolated_pos(&self, alpha: f32) -> Vec3<f32> { self.last_pos + (self.pos - self.last_pos) * alpha }
+
     pub fn vel(&self) -> &Vec3<f32> { &self.vel }
 
     pub fn ctrl_acc(&self) -> &Vec3<f32> { &self.ctrl_acc }
 
     pub fn look_dir(&self) -> &Vec2<f32> { &self.look_dir }
 
+    /// The way the entity wants to move. A crouching entity that wants to stand may not have room to yet.
+    pub fn move_mode(&self) -> MoveMode { self.move_mode }
+
+    /// Whether the entity is trying to jump, i.e: the jump key is held
+    pub fn jump(&self) -> bool { self.jump }
+
+    pub fn is_crouching(&self) -> bool { self.body.crouching }
+
+    /// Whether the entity is standing on something, as of the last physics tick
+    pub fn is_grounded(&self) -> bool { self.body.grounded }
+
+    /// How big the entity is standing up
+    pub fn collision_box(&self) -> CollisionBox { self.collision_box }
+
+    /// How big the entity actually is, as of the last physics tick. An entity that's crouching, or that has been made
+    /// bigger without room to grow, is smaller than its collision box.
+    pub fn body_size(&self) -> Vec3<f32> { self.body_size }
+
+    /// The player's alias or the character's name
+    pub fn name(&self) -> &Option<String> { &self.name }
+
+    /// What kind of item the entity has in its hand
+    pub fn held_item(&self) -> Option<ItemKind> { self.held_item }
+
+    /// The item the entity is, if it's been dropped rather than being a creature
+    pub fn item(&self) -> Option<Item> { self.item }
+
     pub fn pos_mut(&mut self) -> &mut Vec3<f32> { &mut self.pos }
 
+    /// Remember where the entity is before another physics step moves it
+    pub fn start_step(&mut self) { self.last_pos = self.pos; }
+
     pub fn vel_mut(&mut self) -> &mut Vec3<f32> { &mut self.vel }
 
     pub fn ctrl_acc_mut(&mut self) -> &mut Vec3<f32> { &mut self.ctrl_acc }
 
     pub fn look_dir_mut(&mut self) -> &mut Vec2<f32> { &mut self.look_dir }
 
+    /// Turn to look in `dir` over the next few ticks rather than all at once, so that jumps in direction between
+    /// updates from the server are smoothed out
+    pub fn look_towards(&mut self, dir: Vec2<f32>) { self.look_target = Some(dir); }
+
+    /// Turn towards where the entity was told to look by how far it can turn in `dt` seconds
+    pub fn turn(&mut self, dt: f32) {
+        if let Some(target) = self.look_target {
+            self.look_dir.x = turn_towards(self.look_dir.x, target.x, TURN_SPEED * dt);
+            let lean = target.y - self.look_dir.y;
+            self.look_dir.y = if lean.abs() <= LEAN_SPEED * dt {
+                target.y
+            } else {
+                self.look_dir.y + LEAN_SPEED * dt * lean.signum()
+            };
+            if self.look_dir == target {
+                self.look_target = None;
+            }
+        }
+    }
+
+    pub fn move_mode_mut(&mut self) -> &mut MoveMode { &mut self.move_mode }
+
+    pub fn jump_mut(&mut self) -> &mut bool { &mut self.jump }
+
+    pub fn name_mut(&mut self) -> &mut Option<String> { &mut self.name }
+
+    pub fn held_item_mut(&mut self) -> &mut Option<ItemKind> { &mut self.held_item }
+
+    pub fn item_mut(&mut self) -> &mut Option<Item> { &mut self.item }
+
+    /// Change how big the entity is. It only grows once there's room for it.
+    pub fn collision_box_mut(&mut self) -> &mut CollisionBox { &mut self.collision_box }
+
+    pub(crate) fn body(&self) -> &BodyState { &self.body }
+    pub(crate) fn body_mut(&mut self) -> &mut BodyState { &mut self.body }
+    pub(crate) fn body_size_mut(&mut self) -> &mut Vec3<f32> { &mut self.body_size }
+
     pub fn payload(&self) -> &Option<P> { &self.payload }
     pub fn payload_mut(&mut self) -> &mut Option<P> { &mut self.payload }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turning_takes_the_short_way_round() {
+        // From just under a full turn to just over none is a small step forwards, not most of a turn backwards
+        let turned = turn_towards(2.0 * PI - 0.1, 0.1, 0.05);
+        assert!((turned - (2.0 * PI - 0.05)).abs() < 0.0001);
+        assert_eq!(turn_towards(2.0 * PI - 0.1, 0.1, 0.5), 0.1);
+        assert!((turn_towards(0.1, -0.1, 0.05) - 0.05).abs() < 0.0001);
+    }
+
+    #[test]
+    fn entities_turn_smoothly_towards_their_target() {
+        let mut entity = Entity::<()>::new(Vec3::zero(), Vec3::zero(), Vec3::zero(), Vec2::new(0.0, 0.0));
+        entity.look_towards(Vec2::new(PI, 0.5));
+
+        entity.turn(0.1);
+        assert!(entity.look_dir().x > 0.0 && entity.look_dir().x < PI);
+        assert!(entity.look_dir().y > 0.0 && entity.look_dir().y < 0.5);
+
+        entity.turn(1.0);
+        assert_eq!(*entity.look_dir(), Vec2::new(PI, 0.5));
+    }
+
+    #[test]
+    fn entities_are_drawn_between_physics_steps() {
+        let mut entity = Entity::<()>::new(Vec3::zero(), Vec3::zero(), Vec3::zero(), Vec2::new(0.0, 0.0));
+        entity.start_step();
+        *entity.pos_mut() = Vec3::new(2.0, 0.0, 1.0);
+
+        assert_eq!(entity.interpolated_pos(0.0), Vec3::zero());
+        assert_eq!(entity.interpolated_pos(0.5), Vec3::new(1.0, 0.0, 0.5));
+        assert_eq!(entity.interpolated_pos(1.0), *entity.pos());
+    }
+}
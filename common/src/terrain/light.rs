@@ -0,0 +1,523 @@
+// Standard
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    mem,
+    sync::Arc,
+};
+
+// Library
+use vek::*;
+
+// Local
+use crate::terrain::{
+    self,
+    chunk::{Block, ChunkContainer},
+    Container, ReadVolume, VolCluster, VolOffs, VoxAbs, VoxRel, Voxel,
+};
+
+/*
+ Every voxel has two kinds of light, each from 0 to `MAX_LIGHT`: sunlight, which falls in from the sky, and block light,
+ which blocks like glowstone give off. Light dims by one level for each voxel it moves through and is stopped by solid
+ blocks, except that full sunlight falls straight down without dimming.
+
+ Light is worked out for each chunk by itself when it's generated, then carried over the borders between chunks as they
+ arrive. Edits take away the light that went through the changed voxel and spread what's left back over it, so only the
+ light that actually changed is touched.
+*/
+
+/// The brightest light can be
+pub const MAX_LIGHT: u8 = 15;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Sun,
+    Block,
+}
+
+const CHANNELS: [Channel; 2] = [Channel::Sun, Channel::Block];
+
+/// The light at a voxel
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Light {
+    pub sun: u8,
+    pub block: u8,
+}
+
+impl Light {
+    /// Under the open sky, with no blocks giving off light nearby
+    pub const SUNLIT: Light = Light {
+        sun: MAX_LIGHT,
+        block: 0,
+    };
+
+    pub fn get(&self, channel: Channel) -> u8 {
+        match channel {
+            Channel::Sun => self.sun,
+            Channel::Block => self.block,
+        }
+    }
+}
+
+fn dirs() -> [Vec3<i64>; 6] {
+    [
+        Vec3::unit_x(),
+        -Vec3::unit_x(),
+        Vec3::unit_y(),
+        -Vec3::unit_y(),
+        Vec3::unit_z(),
+        -Vec3::unit_z(),
+    ]
+}
+
+// The light a voxel gets from a neighbour at `level`, which it's `down` from or beside
+fn next_level(channel: Channel, level: u8, down: bool) -> u8 {
+    if channel == Channel::Sun && down && level == MAX_LIGHT {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(1)
+    }
+}
+
+// Whether light at `level` could have come from a neighbour at `from`, so that it has to go when that does
+fn fed_by(channel: Channel, level: u8, from: u8, down: bool) -> bool {
+    level > 0 && (level < from || (level == MAX_LIGHT && next_level(channel, from, down) == MAX_LIGHT))
+}
+
+/// Light moving into a voxel from one beside it, usually across the border into another chunk
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct LightUpdate {
+    /// The voxel the light moves into
+    pub pos: Vec3<VoxAbs>,
+    pub channel: Channel,
+    /// The light of the voxel it comes from, or what that was before it was taken away
+    pub level: u8,
+    pub down: bool,
+    /// Whether the light is being taken away rather than spread
+    pub removed: bool,
+}
+
+/// The light of every voxel in a chunk
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightData {
+    size: Vec3<VoxRel>,
+    // Sunlight in the high nibble and block light in the low one, in the same order as `HeterogeneousData`
+    values: Vec<u8>,
+    sky_assumed: bool,
+}
+
+impl LightData {
+    pub fn new(size: Vec3<VoxRel>, sky_assumed: bool) -> LightData {
+        LightData {
+            size,
+            values: vec![0; (size.x * size.y * size.z) as usize],
+            sky_assumed,
+        }
+    }
+
+    /// Light a chunk by itself, from the blocks in it that give off light and, if `sky` is set, from sunlight falling
+    /// onto its top. Light from the chunks around it is carried in once they're loaded.
+    pub fn lit(blocks: &dyn ReadVolume<VoxelType = Block>, sky: bool) -> LightData {
+        let mut light = LightData::new(blocks.size(), sky);
+        let mut seeds = vec![];
+        for x in 0..light.size.x {
+            for y in 0..light.size.y {
+                for z in 0..light.size.z {
+                    let pos = Vec3::new(x, y, z);
+                    for channel in CHANNELS.iter() {
+                        let level = light.source(blocks, *channel, pos);
+                        if level > 0 {
+                            light.set_level(*channel, pos, level);
+                            seeds.push((*channel, pos));
+                        }
+                    }
+                }
+            }
+        }
+        // Light leaving the chunk is carried over once its neighbours are known
+        light.spread(blocks, Vec3::zero(), seeds, &mut vec![]);
+        light
+    }
+
+    pub fn size(&self) -> Vec3<VoxRel> { self.size }
+
+    /// Whether sunlight is taken to fall onto the top of the chunk, because the chunk above it wasn't loaded to say
+    /// otherwise when it was lit
+    pub fn sky_assumed(&self) -> bool { self.sky_assumed }
+
+    pub(crate) fn set_sky_assumed(&mut self, sky_assumed: bool) { self.sky_assumed = sky_assumed; }
+
+    pub fn get(&self, pos: Vec3<VoxRel>) -> Light {
+        let value = self.values[self.idx(pos)];
+        Light {
+            sun: value >> 4,
+            block: value & 0x0F,
+        }
+    }
+
+    pub fn level(&self, channel: Channel, pos: Vec3<VoxRel>) -> u8 { self.get(pos).get(channel) }
+
+    fn set_level(&mut self, channel: Channel, pos: Vec3<VoxRel>, level: u8) {
+        let idx = self.idx(pos);
+        let value = self.values[idx];
+        self.values[idx] = match channel {
+            Channel::Sun => (value & 0x0F) | (level << 4),
+            Channel::Block => (value & 0xF0) | (level & 0x0F),
+        };
+    }
+
+    fn idx(&self, pos: Vec3<VoxRel>) -> usize {
+        (pos.x * self.size.y * self.size.z + pos.y * self.size.z + pos.z) as usize
+    }
+
+    // `pos` as a voxel of the chunk, if it's inside it
+    fn inside(&self, pos: Vec3<i64>) -> Option<Vec3<VoxRel>> {
+        if pos.x >= 0
+            && pos.y >= 0
+            && pos.z >= 0
+            && pos.x < self.size.x as i64
+            && pos.y < self.size.y as i64
+            && pos.z < self.size.z as i64
+        {
+            Some(pos.map(|e| e as VoxRel))
+        } else {
+            None
+        }
+    }
+
+    // The light `pos` has whatever is around it
+    fn source(&self, blocks: &dyn ReadVolume<VoxelType = Block>, channel: Channel, pos: Vec3<VoxRel>) -> u8 {
+        let block = blocks.at_unchecked(pos);
+        match channel {
+            Channel::Block => block.light_emission(),
+            Channel::Sun if self.sky_assumed && pos.z == self.size.z - 1 && !block.is_solid() => MAX_LIGHT,
+            Channel::Sun => 0,
+        }
+    }
+
+    /// The voxels along the side of the chunk that faces `dir`, one of the six unit vectors
+    pub(crate) fn face(&self, dir: Vec3<i64>) -> Vec<Vec3<VoxRel>> {
+        let range = |dir: i64, size: VoxRel| match dir {
+            d if d > 0 => size - 1..size,
+            d if d < 0 => 0..1,
+            _ => 0..size,
+        };
+        let mut face = vec![];
+        for x in range(dir.x, self.size.x) {
+            for y in range(dir.y, self.size.y) {
+                for z in range(dir.z, self.size.z) {
+                    face.push(Vec3::new(x, y, z));
+                }
+            }
+        }
+        face
+    }
+
+    /// Spread light out from `seeds`, as bright as they are now. Light that reaches the edge of the chunk, which starts
+    /// at `origin`, is added to `out`. Returns whether any voxel got brighter.
+    pub(crate) fn spread(
+        &mut self,
+        blocks: &dyn ReadVolume<VoxelType = Block>,
+        origin: Vec3<VoxAbs>,
+        seeds: Vec<(Channel, Vec3<VoxRel>)>,
+        out: &mut Vec<LightUpdate>,
+    ) -> bool {
+        let mut changed = false;
+        let mut queue = VecDeque::from(seeds);
+        while let Some((channel, pos)) = queue.pop_front() {
+            let level = self.level(channel, pos);
+            for dir in dirs().iter() {
+                let down = dir.z < 0;
+                let next = next_level(channel, level, down);
+                if next == 0 {
+                    continue;
+                }
+                let to = pos.map(|e| e as i64) + *dir;
+                match self.inside(to) {
+                    Some(to) => {
+                        if next > self.level(channel, to) && !blocks.at_unchecked(to).is_solid() {
+                            self.set_level(channel, to, next);
+                            queue.push_back((channel, to));
+                            changed = true;
+                        }
+                    },
+                    None => out.push(LightUpdate {
+                        pos: origin + to,
+                        channel,
+                        level,
+                        down,
+                        removed: false,
+                    }),
+                }
+            }
+        }
+        changed
+    }
+
+    /// Let in the light of `update`, which comes from outside the chunk. Returns the voxel it lit, if it did, for the
+    /// light to be spread on from.
+    pub(crate) fn receive(
+        &mut self,
+        blocks: &dyn ReadVolume<VoxelType = Block>,
+        origin: Vec3<VoxAbs>,
+        update: &LightUpdate,
+    ) -> Option<Vec3<VoxRel>> {
+        let pos = self.inside(update.pos - origin)?;
+        let next = next_level(update.channel, update.level, update.down);
+        if next > self.level(update.channel, pos) && !blocks.at_unchecked(pos).is_solid() {
+            self.set_level(update.channel, pos, next);
+            Some(pos)
+        } else {
+            None
+        }
+    }
+
+    /// Take away the light that `update` brought in from outside the chunk, along with all the light it fed. Voxels
+    /// that are left lit by something else are added to `relight`, to spread their light back over what was taken
+    /// away. Returns whether any light was taken away.
+    pub(crate) fn remove(
+        &mut self,
+        blocks: &dyn ReadVolume<VoxelType = Block>,
+        origin: Vec3<VoxAbs>,
+        update: &LightUpdate,
+        relight: &mut Vec<(Channel, Vec3<VoxRel>)>,
+        out: &mut Vec<LightUpdate>,
+    ) -> bool {
+        let pos = match self.inside(update.pos - origin) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let level = self.level(update.channel, pos);
+        if fed_by(update.channel, level, update.level, update.down) {
+            self.take(blocks, origin, update.channel, pos, relight, out);
+            true
+        } else {
+            if level > 0 {
+                relight.push((update.channel, pos));
+            }
+            false
+        }
+    }
+
+    /// Take away the light at `pos` along with all the light it fed, e.g: because the block there changed. Whatever
+    /// light `pos` has by itself is put back. Voxels left lit by something else are added to `relight`, and light
+    /// taken away beyond the edge of the chunk is added to `out`.
+    pub(crate) fn take(
+        &mut self,
+        blocks: &dyn ReadVolume<VoxelType = Block>,
+        origin: Vec3<VoxAbs>,
+        channel: Channel,
+        pos: Vec3<VoxRel>,
+        relight: &mut Vec<(Channel, Vec3<VoxRel>)>,
+        out: &mut Vec<LightUpdate>,
+    ) {
+        let mut queue = VecDeque::new();
+        queue.push_back((pos, self.level(channel, pos)));
+        self.reset(blocks, channel, pos, relight);
+
+        while let Some((pos, old)) = queue.pop_front() {
+            for dir in dirs().iter() {
+                let down = dir.z < 0;
+                let to = pos.map(|e| e as i64) + *dir;
+                match self.inside(to) {
+                    Some(to) => {
+                        let level = self.level(channel, to);
+                        if fed_by(channel, level, old, down) {
+                            self.reset(blocks, channel, to, relight);
+                            queue.push_back((to, level));
+                        } else if level > 0 {
+                            relight.push((channel, to));
+                        }
+                    },
+                    None => out.push(LightUpdate {
+                        pos: origin + to,
+                        channel,
+                        level: old,
+                        down,
+                        removed: true,
+                    }),
+                }
+            }
+        }
+    }
+
+    // Put `pos` back to the light it has by itself
+    fn reset(
+        &mut self,
+        blocks: &dyn ReadVolume<VoxelType = Block>,
+        channel: Channel,
+        pos: Vec3<VoxRel>,
+        relight: &mut Vec<(Channel, Vec3<VoxRel>)>,
+    ) {
+        let level = self.source(blocks, channel, pos);
+        self.set_level(channel, pos, level);
+        if level > 0 {
+            relight.push((channel, pos));
+        }
+    }
+}
+
+// Sort things with positions by the chunk they're in
+fn by_chunk<T, F: Fn(&T) -> Vec3<VoxAbs>>(
+    items: Vec<T>,
+    vol_size: Vec3<VoxRel>,
+    pos: F,
+) -> HashMap<Vec3<VolOffs>, Vec<T>> {
+    let mut map: HashMap<_, Vec<T>> = HashMap::new();
+    for item in items {
+        map.entry(terrain::voxabs_to_voloffs(pos(&item), vol_size))
+            .or_default()
+            .push(item);
+    }
+    map
+}
+
+/// Light waiting to be carried through the chunks it reaches. All the light being taken away is taken away before
+/// any is spread, so that light that's about to go isn't spread any further.
+#[derive(Default)]
+pub(crate) struct LightQueue {
+    removed: Vec<LightUpdate>,
+    spread: Vec<LightUpdate>,
+    relight: Vec<(Channel, Vec3<VoxAbs>)>,
+}
+
+impl LightQueue {
+    pub fn push(&mut self, update: LightUpdate) {
+        if update.removed {
+            self.removed.push(update);
+        } else {
+            self.spread.push(update);
+        }
+    }
+
+    /// Spread the light the voxel at `pos` has once everything queued to be taken away has been
+    pub fn relight(&mut self, channel: Channel, pos: Vec3<VoxAbs>) { self.relight.push((channel, pos)); }
+
+    /// Carry out everything queued, finding chunks with `get`. Light reaching chunks that aren't loaded or haven't been
+    /// lit is dropped; it's carried in when they arrive. Returns the chunks whose light changed.
+    pub fn process<P, F>(mut self, vol_size: Vec3<VoxRel>, get: F) -> HashSet<Vec3<VolOffs>>
+    where
+        F: Fn(Vec3<VolOffs>) -> Option<Arc<ChunkContainer<P>>>,
+    {
+        let mut changed = HashSet::new();
+        loop {
+            if !self.removed.is_empty() {
+                let removed = mem::replace(&mut self.removed, vec![]);
+                for (chunk, updates) in by_chunk(removed, vol_size, |update| update.pos) {
+                    let con = match get(chunk) {
+                        Some(con) => con,
+                        None => continue,
+                    };
+                    let origin = terrain::voloffs_to_voxabs(chunk, vol_size);
+                    let data = con.data();
+                    let mut guard = con.light_mut();
+                    let (blocks, light) = match (data.prefered(), guard.as_mut()) {
+                        (Some(blocks), Some(light)) => (blocks, light),
+                        _ => continue,
+                    };
+
+                    let mut relight = vec![];
+                    for update in updates.iter() {
+                        if light.remove(blocks, origin, update, &mut relight, &mut self.removed) {
+                            changed.insert(chunk);
+                        }
+                    }
+                    for (channel, pos) in relight {
+                        self.relight(channel, origin + pos.map(|e| e as VoxAbs));
+                    }
+                }
+                continue;
+            }
+            if self.spread.is_empty() && self.relight.is_empty() {
+                break;
+            }
+
+            let mut spread = by_chunk(mem::replace(&mut self.spread, vec![]), vol_size, |update| update.pos);
+            let mut relight = by_chunk(mem::replace(&mut self.relight, vec![]), vol_size, |(_, pos)| *pos);
+            let chunks = spread.keys().chain(relight.keys()).cloned().collect::<HashSet<_>>();
+            for chunk in chunks {
+                let con = match get(chunk) {
+                    Some(con) => con,
+                    None => continue,
+                };
+                let origin = terrain::voloffs_to_voxabs(chunk, vol_size);
+                let data = con.data();
+                let mut guard = con.light_mut();
+                let (blocks, light) = match (data.prefered(), guard.as_mut()) {
+                    (Some(blocks), Some(light)) => (blocks, light),
+                    _ => continue,
+                };
+
+                let mut seeds = relight
+                    .remove(&chunk)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|(channel, pos)| light.inside(pos - origin).map(|pos| (channel, pos)))
+                    .collect::<Vec<_>>();
+                for update in spread.remove(&chunk).unwrap_or_default().iter() {
+                    if let Some(pos) = light.receive(blocks, origin, update) {
+                        seeds.push((update.channel, pos));
+                        changed.insert(chunk);
+                    }
+                }
+                if light.spread(blocks, origin, seeds, &mut self.spread) {
+                    changed.insert(chunk);
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::{chunk::HeterogeneousData, ConstructVolume, ReadWriteVolume};
+
+    #[test]
+    fn block_light_dims_with_distance() {
+        let mut blocks = HeterogeneousData::filled(Vec3::new(16, 3, 3), Block::AIR);
+        blocks.set_at(Vec3::new(2, 1, 1), Block::GLOWSTONE);
+        let light = LightData::lit(&blocks, false);
+
+        assert_eq!(light.level(Channel::Block, Vec3::new(2, 1, 1)), MAX_LIGHT);
+        assert_eq!(light.level(Channel::Block, Vec3::new(3, 1, 1)), 14);
+        assert_eq!(light.level(Channel::Block, Vec3::new(10, 1, 1)), 7);
+        // Around the corner is further away
+        assert_eq!(light.level(Channel::Block, Vec3::new(10, 0, 0)), 5);
+        assert_eq!(light.level(Channel::Sun, Vec3::new(3, 1, 1)), 0);
+    }
+
+    #[test]
+    fn sunlight_falls_without_dimming_until_stopped() {
+        let mut blocks = HeterogeneousData::filled(Vec3::new(3, 1, 20), Block::AIR);
+        blocks.set_at(Vec3::new(0, 0, 10), Block::STONE);
+        let light = LightData::lit(&blocks, true);
+
+        assert_eq!(light.level(Channel::Sun, Vec3::new(1, 0, 0)), MAX_LIGHT);
+        assert_eq!(light.level(Channel::Sun, Vec3::new(0, 0, 11)), MAX_LIGHT);
+        // Under the stone, it only gets what comes in from the side
+        assert_eq!(light.level(Channel::Sun, Vec3::new(0, 0, 9)), 14);
+        assert_eq!(light.level(Channel::Sun, Vec3::new(0, 0, 0)), 14);
+
+        assert_eq!(LightData::lit(&blocks, false), LightData::new(blocks.size(), false));
+    }
+
+    #[test]
+    fn taking_light_away_leaves_other_light() {
+        let mut blocks = HeterogeneousData::filled(Vec3::new(16, 1, 1), Block::AIR);
+        blocks.set_at(Vec3::new(0, 0, 0), Block::GLOWSTONE);
+        blocks.set_at(Vec3::new(10, 0, 0), Block::GLOWSTONE);
+        let mut light = LightData::lit(&blocks, false);
+        assert_eq!(light.level(Channel::Block, Vec3::new(4, 0, 0)), 11);
+
+        blocks.set_at(Vec3::new(0, 0, 0), Block::AIR);
+        let (mut relight, mut out) = (vec![], vec![]);
+        light.take(&blocks, Vec3::zero(), Channel::Block, Vec3::new(0, 0, 0), &mut relight, &mut out);
+        light.spread(&blocks, Vec3::zero(), relight, &mut out);
+
+        assert_eq!(light, LightData::lit(&blocks, false));
+        assert_eq!(light.level(Channel::Block, Vec3::new(4, 0, 0)), 9);
+        // The light reaching out of the chunk went with it
+        assert!(out.iter().any(|update| update.removed && update.pos == Vec3::new(-1, 0, 0)));
+    }
+}
@@ -1,18 +1,24 @@
+mod border;
 pub mod chunk;
 mod chunk_mgr;
 mod entity;
 pub mod figure;
+mod light;
+mod ray;
 mod vol_gen;
 
 // Reexports
 pub use crate::terrain::{
-    chunk_mgr::{BlockLoader, ChunkMgr},
-    entity::Entity,
-    vol_gen::{FnDropFunc, FnGenFunc, VolGen},
+    border::{Barrier, WorldBorder},
+    chunk_mgr::{BlockLoader, ChunkMgr, ChunkState},
+    entity::{BodyState, Entity},
+    light::{Channel, Light, LightData, MAX_LIGHT},
+    ray::{cast as ray_cast, RayHit},
+    vol_gen::{FnDropFunc, FnGenFunc, FnPayloadFunc, VolGen},
 };
 
 // Standard
-use std::{any::Any, cmp::Eq, fmt::Debug, hash::Hash};
+use std::{any::Any, cmp::Eq, fmt::Debug, hash::Hash, vec};
 
 // Library
 use bincode;
@@ -59,6 +65,11 @@ pub fn voxabs_to_voxrel(voxabs: Vec3<VoxAbs>, vol_size: Vec3<VoxRel>) -> Vec3<Vo
     voxabs.map2(vol_size, |a, s| a.mod_euc(s as VoxAbs) as VoxRel)
 }
 
+/// The block a point in the world is in. Each block spans from its position up to the next block along, so points are
+/// floored rather than rounded towards zero: -0.5 is in block -1. Points are in f64, which still tells blocks apart
+/// far from the origin, where an f32 has nothing left after the decimal point.
+pub fn pos_to_voxabs(pos: Vec3<f64>) -> Vec3<VoxAbs> { pos.map(|e| e.floor() as VoxAbs) }
+
 /// Helper function to manually validate a offset of any time and convert it
 fn validate_offset<T: Num + ToPrimitive>(off: Vec3<T>, size: Vec3<VoxRel>) -> Option<Vec3<VoxRel>> {
     let off = off.map(|e| e.to_i64().unwrap());
@@ -99,6 +110,47 @@ pub trait ReadVolume: Volume {
 
     /// like `at` but without any checks
     fn at_unchecked(&self, off: Vec3<VoxRel>) -> Self::VoxelType;
+
+    /// Copy the voxels in the box of `size` starting at `min` into `out`, in the order `slice_index` gives: z varies
+    /// fastest, then y, then x. Panics if the box isn't inside the volume or `out` isn't exactly the size of the box.
+    fn fill_slice(&self, min: Vec3<VoxRel>, size: Vec3<VoxRel>, out: &mut [Self::VoxelType]) {
+        // Default implementation
+        check_slice(min, size, self.size(), out.len());
+        let mut voxels = out.iter_mut();
+        for x in min.x..min.x + size.x {
+            for y in min.y..min.y + size.y {
+                for z in min.z..min.z + size.z {
+                    *voxels.next().unwrap() = self.at_unchecked(Vec3::new(x, y, z));
+                }
+            }
+        }
+    }
+
+    /// The voxels in the column at `x`, `y`, from the bottom of the volume to the top
+    fn column(&self, x: VoxRel, y: VoxRel) -> vec::IntoIter<Self::VoxelType> {
+        let size = Vec3::new(1, 1, self.size().z);
+        let mut voxels = vec![Self::VoxelType::empty(); size.z as usize];
+        self.fill_slice(Vec3::new(x, y, 0), size, &mut voxels);
+        voxels.into_iter()
+    }
+}
+
+/// Where the voxel at `off` goes in a slice filled by `ReadVolume::fill_slice` with a box of `size`
+pub fn slice_index(off: Vec3<VoxRel>, size: Vec3<VoxRel>) -> usize {
+    (off.x as usize * size.y as usize + off.y as usize) * size.z as usize + off.z as usize
+}
+
+// Panics unless a box of `size` at `min` fits in a volume of `vol_size` and `len` voxels is exactly enough for it
+pub(crate) fn check_slice(min: Vec3<VoxRel>, size: Vec3<VoxRel>, vol_size: Vec3<VoxRel>, len: usize) {
+    let max = min + size;
+    assert!(
+        max.x <= vol_size.x && max.y <= vol_size.y && max.z <= vol_size.z,
+        "Box of {} at {} is outside a volume of {}",
+        size,
+        min,
+        vol_size
+    );
+    assert_eq!(len, size.map(|e| e as usize).product(), "Slice doesn't fit a box of {}", size);
 }
 
 pub trait ReadWriteVolume: ReadVolume {
@@ -127,6 +179,32 @@ pub trait ReadWriteVolume: ReadVolume {
     fn fill(&mut self, vox: Self::VoxelType);
 }
 
+/// An offset that was outside the volume it was used with
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OutOfBounds(pub Vec3<VoxRel>);
+
+/// Checked writes for editing a volume after it's been built. Voxels that are changed this way are remembered, so that
+/// whatever depends on them can be brought up to date with only the part that changed.
+pub trait WriteVolume: Volume {
+    /// Change the voxel at `off`, returning the voxel that was there
+    fn set(&mut self, off: Vec3<VoxRel>, vox: Self::VoxelType) -> Result<Self::VoxelType, OutOfBounds>;
+
+    /// The smallest box, inclusive of both corners, holding every voxel `set` has changed since this was last called.
+    /// `None` if nothing has changed. Writes that leave a voxel as it was don't count.
+    fn take_dirty_region(&mut self) -> Option<Aabb<VoxRel>>;
+}
+
+/// Grow `region` to take in the voxel at `off`
+pub(crate) fn add_to_region(region: &mut Option<Aabb<VoxRel>>, off: Vec3<VoxRel>) {
+    *region = Some(match region.take() {
+        Some(region) => Aabb {
+            min: region.min.map2(off, |a, b| a.min(b)),
+            max: region.max.map2(off, |a, b| a.max(b)),
+        },
+        None => Aabb { min: off, max: off },
+    });
+}
+
 pub trait ConstructVolume: Volume {
     /// Construct a new empty volume with the given size.
     fn empty(size: Vec3<VoxRel>) -> Self;
@@ -0,0 +1,101 @@
+// Standard
+use std::f32;
+
+// Library
+use vek::*;
+
+// Local
+use crate::terrain::VoxAbs;
+
+/// The first solid block hit by a ray
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayHit {
+    pub pos: Vec3<VoxAbs>,
+    /// Normal of the face the ray entered through, zero if the ray started inside the block
+    pub normal: Vec3<VoxAbs>,
+    pub dist: f32,
+}
+
+/// Walk the blocks along a ray (Amanatides & Woo) until `is_hit` returns true or `max_dist` is exceeded
+pub fn cast<F: FnMut(Vec3<VoxAbs>) -> bool>(
+    origin: Vec3<f32>,
+    dir: Vec3<f32>,
+    max_dist: f32,
+    mut is_hit: F,
+) -> Option<RayHit> {
+    if dir.magnitude_squared() == 0.0 {
+        return None;
+    }
+    let dir = dir.normalized().into_array();
+    let origin = origin.into_array();
+
+    let mut pos = [0; 3];
+    let mut step = [0; 3];
+    let mut t_max = [0.0; 3];
+    let mut t_delta = [0.0; 3];
+    for i in 0..3 {
+        pos[i] = origin[i].floor() as VoxAbs;
+        if dir[i] > 0.0 {
+            step[i] = 1;
+            t_max[i] = (pos[i] as f32 + 1.0 - origin[i]) / dir[i];
+            t_delta[i] = 1.0 / dir[i];
+        } else if dir[i] < 0.0 {
+            step[i] = -1;
+            t_max[i] = (origin[i] - pos[i] as f32) / -dir[i];
+            t_delta[i] = -1.0 / dir[i];
+        } else {
+            t_max[i] = f32::INFINITY;
+            t_delta[i] = f32::INFINITY;
+        }
+    }
+
+    let mut normal = [0; 3];
+    let mut dist = 0.0;
+    loop {
+        if is_hit(Vec3::from(pos)) {
+            return Some(RayHit {
+                pos: Vec3::from(pos),
+                normal: Vec3::from(normal),
+                dist,
+            });
+        }
+
+        // Step into the neighbouring block along whichever axis boundary is closest
+        let axis = if t_max[0] < t_max[1] {
+            if t_max[0] < t_max[2] { 0 } else { 2 }
+        } else if t_max[1] < t_max[2] {
+            1
+        } else {
+            2
+        };
+
+        dist = t_max[axis];
+        if dist > max_dist {
+            return None;
+        }
+
+        pos[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        normal = [0; 3];
+        normal[axis] = -step[axis];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_first_solid_block() {
+        let hit = cast(Vec3::new(0.5, 0.5, 0.5), Vec3::unit_x(), 10.0, |pos| pos.x == 4).unwrap();
+        assert_eq!(hit.pos, Vec3::new(4, 0, 0));
+        assert_eq!(hit.normal, Vec3::new(-1, 0, 0));
+        assert!((hit.dist - 3.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn misses_beyond_max_dist() {
+        assert_eq!(cast(Vec3::new(0.5, 0.5, 0.5), -Vec3::unit_z(), 3.0, |pos| pos.z == -5), None);
+        assert_eq!(cast(Vec3::new(0.5, 0.5, 0.5), Vec3::zero(), 3.0, |_| true), None);
+    }
+}
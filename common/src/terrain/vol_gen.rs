@@ -2,7 +2,7 @@
 use crate::terrain::{Container, Key};
 
 // Standard
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 // Library
 use parking_lot::Mutex;
@@ -11,19 +11,28 @@ pub trait FnGenFunc<K: Key, C: Container>: Fn(K, Arc<Mutex<Option<C>>>) + Send +
 
 impl<K: Key, C: Container, T: Fn(K, Arc<Mutex<Option<C>>>)> FnGenFunc<K, C> for T where T: Send + Sync + 'static {}
 
+/// Generates the payload of a container. It's given the loaded containers around it, so that the payload can take its
+/// neighbours into account.
+pub trait FnPayloadFunc<K: Key, C: Container>: Fn(K, &C, &HashMap<K, Arc<C>>) + Send + Sync + 'static {}
+
+impl<K: Key, C: Container, T: Fn(K, &C, &HashMap<K, Arc<C>>)> FnPayloadFunc<K, C> for T where
+    T: Send + Sync + 'static
+{
+}
+
 pub trait FnDropFunc<K: Key, C: Container>: Fn(K, Arc<C>) + Send + Sync + 'static {}
 
 impl<K: Key, C: Container, T: Fn(K, Arc<C>)> FnDropFunc<K, C> for T where T: Send + Sync + 'static {}
 
 pub struct VolGen<K: Key, C: Container> {
     pub gen_vol: Arc<FnGenFunc<K, C, Output = ()>>,
-    pub gen_payload: Arc<FnGenFunc<K, C, Output = ()>>,
+    pub gen_payload: Arc<FnPayloadFunc<K, C, Output = ()>>,
     pub drop_vol: Arc<FnDropFunc<K, C, Output = ()>>,
     pub drop_payload: Arc<FnDropFunc<K, C, Output = ()>>,
 }
 
 impl<K: Key, C: Container> VolGen<K, C> {
-    pub fn new<GV: FnGenFunc<K, C>, GP: FnGenFunc<K, C>, DV: FnDropFunc<K, C>, DP: FnDropFunc<K, C>>(
+    pub fn new<GV: FnGenFunc<K, C>, GP: FnPayloadFunc<K, C>, DV: FnDropFunc<K, C>, DP: FnDropFunc<K, C>>(
         gen_vol: GV,
         gen_payload: GP,
         drop_vol: DV,
@@ -1,8 +1,12 @@
 use std::{
     thread,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
+// The most steps `FixedStep` will ask for at once. After a longer stall the rest of the time is skipped, rather than
+// simulating it all at once and falling further behind.
+const MAX_CATCH_UP: u32 = 10;
+
 /*
  Clock helps keep a stable Ticks per Second over the time of a second
 */
@@ -102,3 +106,86 @@ impl Clock {
     #[allow(dead_code)]
     pub fn reference_duration(&self) -> Duration { self.reference_duration }
 }
+
+/// Splits real time into steps of the same length, so that whatever is simulated in those steps runs the same however
+/// unevenly it's scheduled. Time that doesn't make up a whole step yet is carried over to the next call.
+pub struct FixedStep {
+    step: Duration,
+    accumulated: Duration,
+    last: Option<Instant>,
+}
+
+impl FixedStep {
+    pub fn new(step: Duration) -> FixedStep {
+        FixedStep {
+            step,
+            accumulated: Duration::from_nanos(0),
+            last: None,
+        }
+    }
+
+    pub fn step(&self) -> Duration { self.step }
+
+    /// Take in the time that has passed up until `now`, returning how many steps to simulate to catch up with it
+    pub fn advance(&mut self, now: Instant) -> u32 {
+        if let Some(last) = self.last {
+            self.accumulated += now.duration_since(last);
+        }
+        self.last = Some(now);
+
+        let mut steps = 0;
+        while self.accumulated >= self.step {
+            self.accumulated -= self.step;
+            steps += 1;
+        }
+        if steps > MAX_CATCH_UP {
+            warn!("skipping {} physics steps to catch up", steps - MAX_CATCH_UP);
+            steps = MAX_CATCH_UP;
+        }
+        steps
+    }
+
+    /// How far `now` is from the last step towards the next one, from 0 to 1. Drawing things this far between their
+    /// last two simulated states keeps their motion smooth at any frame rate.
+    pub fn alpha(&self, now: Instant) -> f32 {
+        let since = self.last.map(|last| now.duration_since(last)).unwrap_or_default();
+        ((self.accumulated + since).as_float_secs() / self.step.as_float_secs()).min(1.0) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_steps_keep_up_with_jittery_time() {
+        let step = Duration::from_millis(20);
+        let mut fixed = FixedStep::new(step);
+        let start = Instant::now();
+        assert_eq!(fixed.advance(start), 0);
+
+        // Wake up anywhere from well early to well late, as a busy scheduler would
+        let (mut now, mut simulated) = (start, Duration::from_nanos(0));
+        for i in 0..1000u64 {
+            now += Duration::from_micros(5_000 + (i * 7919) % 30_000);
+            simulated += step * fixed.advance(now);
+
+            let behind = (now - start) - simulated;
+            assert!(behind < step, "{:?} behind after {} updates", behind, i);
+            let alpha = fixed.alpha(now);
+            assert!(alpha >= 0.0 && alpha < 1.0);
+        }
+    }
+
+    #[test]
+    fn fixed_steps_give_up_on_long_stalls() {
+        let step = Duration::from_millis(20);
+        let mut fixed = FixedStep::new(step);
+        let start = Instant::now();
+        fixed.advance(start);
+
+        assert_eq!(fixed.advance(start + Duration::from_secs(5)), MAX_CATCH_UP);
+        // ...and carry on from there rather than still owing the time
+        assert_eq!(fixed.advance(start + Duration::from_secs(5) + step), 1);
+    }
+}
@@ -0,0 +1,126 @@
+// Standard
+use std::{fmt, time::Duration};
+
+// Library
+use serde_derive::{Deserialize, Serialize};
+
+/// What an argument to a command can be. Each kind takes a single word, apart from `Rest`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArgKind {
+    /// The alias of a player who's online
+    Player,
+    /// A whole number
+    Integer,
+    /// Any finite number
+    Float,
+    /// Any word
+    Word,
+    /// One of a fixed set of words
+    Choice(Vec<String>),
+    /// A length of time, as a whole number followed by its unit, like `30m`. See `parse_duration`.
+    Duration,
+    /// Everything left on the line. Only the last argument can be one.
+    Rest,
+}
+
+impl ArgKind {
+    /// What the argument should be, as in "expected a number"
+    pub fn describe(&self) -> String {
+        match self {
+            ArgKind::Player => "a player name".to_string(),
+            ArgKind::Integer => "a whole number".to_string(),
+            ArgKind::Float => "a number".to_string(),
+            ArgKind::Word => "a word".to_string(),
+            ArgKind::Choice(choices) => format!("one of {}", choices.join(", ")),
+            ArgKind::Duration => "a length of time, like 30m".to_string(),
+            ArgKind::Rest => "some text".to_string(),
+        }
+    }
+}
+
+/// Read a length of time like `90s`, `30m`, `12h`, `7d` or `2w`. Anything else, including a number without a unit,
+/// gives `None`.
+pub fn parse_duration(word: &str) -> Option<Duration> {
+    let unit = match word.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let count = word[..word.len() - 1].parse::<u64>().ok()?;
+    count.checked_mul(unit).map(Duration::from_secs)
+}
+
+/// One argument a command takes
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArgSpec {
+    pub name: String,
+    pub kind: ArgKind,
+    pub optional: bool,
+}
+
+impl ArgSpec {
+    pub fn required(name: &str, kind: ArgKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            optional: false,
+        }
+    }
+
+    pub fn optional(name: &str, kind: ArgKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            optional: true,
+        }
+    }
+
+    /// A word that must be given as it is, like the `set` in `worldborder set <radius>`
+    pub fn literal(word: &str) -> Self { Self::required(word, ArgKind::Choice(vec![word.to_string()])) }
+}
+
+impl fmt::Display for ArgSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match &self.kind {
+            ArgKind::Choice(choices) if choices.len() == 1 => return write!(f, "{}", choices[0]),
+            ArgKind::Choice(choices) => choices.join("|"),
+            ArgKind::Rest => format!("{}...", self.name),
+            _ => self.name.clone(),
+        };
+        if self.optional {
+            write!(f, "[{}]", name)
+        } else {
+            write!(f, "<{}>", name)
+        }
+    }
+}
+
+/// What a command is called and the arguments it takes, as much as a client needs to complete it as it's typed
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CmdSpec {
+    pub name: String,
+    pub aliases: Vec<String>,
+    /// Each of the ways the command can be used
+    pub usages: Vec<Vec<ArgSpec>>,
+    pub help: String,
+}
+
+impl CmdSpec {
+    /// How to use the command, one usage after the other, like `tp [alias] <target> | tp [alias] <x> <y> <z>`
+    pub fn usage(&self, prefix: &str) -> String {
+        self.usages
+            .iter()
+            .map(|args| {
+                let mut usage = format!("{}{}", prefix, self.name);
+                for arg in args {
+                    usage += &format!(" {}", arg);
+                }
+                usage
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
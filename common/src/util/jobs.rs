@@ -1,77 +1,262 @@
 // Standard
 use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
     iter::IntoIterator,
-    sync::{Arc, Weak},
-    thread::{self, JoinHandle},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Weak,
+    },
+    thread,
+    time::Duration,
 };
 
 // Library
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
+/// Why a job didn't give back a result
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobError {
+    /// The job panicked, with this message
+    Panicked(String),
+    /// The job's result went missing, because it was already taken or the job's thread died without unwinding
+    Lost,
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobError::Panicked(msg) => write!(f, "job panicked: {}", msg),
+            JobError::Lost => write!(f, "job result lost"),
+        }
+    }
+}
+
+type JobResult<R> = Result<R, JobError>;
+
+// Everyone waiting on a named job that's still running
+type Waiters<R> = Arc<Mutex<Vec<Sender<JobResult<R>>>>>;
+
+/// Runs jobs on their own threads, each handed the root they work on
 pub struct Jobs<T: 'static + Sync + Send> {
     root_ref: RwLock<Weak<T>>,
+    // Named jobs that are still running. The same name can be used for jobs with different results, so they're told
+    // apart by the type of their result too.
+    named: Arc<Mutex<HashMap<(String, TypeId), Box<dyn Any + Send>>>>,
 }
 
 impl<T: 'static + Sync + Send> Jobs<T> {
     pub fn new() -> Jobs<T> {
         Jobs {
             root_ref: RwLock::new(Weak::new()),
+            named: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
     pub fn set_root(&self, root: Arc<T>) { *self.root_ref.write() = Arc::downgrade(&root); }
 
-    pub fn do_once<F, U: 'static + Send>(&self, job_func: F) -> JobHandle<U>
+    fn root(&self) -> Arc<T> { self.root_ref.read().upgrade().expect("Root no longer exists") }
+
+    /// Run `job_func` on its own thread, and hand back what it returns. If it panics, the panic is caught and handed
+    /// back instead.
+    pub fn spawn_result<F, R: 'static + Send>(&self, job_func: F) -> JobHandle<R>
+    where
+        F: FnOnce(&Arc<T>) -> R + Send + 'static,
+    {
+        let root = self.root();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let _ = tx.send(catch(|| job_func(&root))); // Nobody may be waiting for it
+        });
+        JobHandle { result: rx }
+    }
+
+    /// Like `spawn_result`, but if a job with the same name and kind of result is still running, no new job is
+    /// started. The handle gets the result of the one that's running instead.
+    pub fn spawn_named<F, R: 'static + Send + Clone>(&self, name: &str, job_func: F) -> JobHandle<R>
     where
-        F: FnOnce(&Arc<T>) -> U + Send + 'static,
+        F: FnOnce(&Arc<T>) -> R + Send + 'static,
     {
-        let root = self.root_ref.read().upgrade().expect("Root no longer exists");
+        // Before the job is listed, so that nothing is left waiting on a job that never started if the root is gone
+        let root = self.root();
+        let (tx, rx) = mpsc::channel();
+        let key = (name.to_string(), TypeId::of::<R>());
+
+        let mut named = self.named.lock();
+        if let Some(waiters) = named.get(&key).and_then(|waiters| waiters.downcast_ref::<Waiters<R>>()) {
+            waiters.lock().push(tx);
+            return JobHandle { result: rx };
+        }
+        let waiters: Waiters<R> = Arc::new(Mutex::new(vec![tx]));
+        named.insert(key.clone(), Box::new(waiters.clone()));
+        drop(named);
+
+        let named = self.named.clone();
+        let thread_key = key.clone();
+        let started = thread::Builder::new().name(name.to_string()).spawn(move || {
+            let result = catch(|| job_func(&root));
+            // Jobs submitted from here on start afresh, rather than getting this result
+            named.lock().remove(&thread_key);
+            for waiter in waiters.lock().drain(..) {
+                let _ = waiter.send(result.clone());
+            }
+        });
+        if let Err(e) = started {
+            self.named.lock().remove(&key);
+            panic!("Could not start job thread: {}", e);
+        }
+        JobHandle { result: rx }
+    }
 
-        JobHandle::new(thread::spawn(move || {
-            let root_ref = root;
-            job_func(&root_ref)
-        }))
+    /// Run `job_func` on its own thread for what it does, rather than for a result
+    pub fn do_once<F>(&self, job_func: F) -> JobHandle<()>
+    where
+        F: FnOnce(&Arc<T>) + Send + 'static,
+    {
+        self.spawn_result(job_func)
     }
 
+    /// Run `job_func` on its own thread over and over until it returns `false`
     pub fn do_loop<F>(&self, job_func: F) -> JobHandle<()>
     where
         F: Fn(&Arc<T>) -> bool + Copy + Send + 'static,
     {
-        let root = self.root_ref.read().upgrade().expect("Root no longer exists");
-
-        JobHandle::new(thread::spawn(move || {
-            let root_ref = root;
-            while job_func(&root_ref) {}
-        }))
+        self.spawn_result(move |root| while job_func(root) {})
     }
 }
 
-pub struct JobHandle<T> {
-    handle: JoinHandle<T>,
+// Run `f`, turning a panic into an error
+fn catch<R, F: FnOnce() -> R>(f: F) -> JobResult<R> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        let msg = payload
+            .downcast_ref::<&str>()
+            .map(|msg| msg.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        JobError::Panicked(msg)
+    })
 }
 
-impl<T> JobHandle<T> {
-    pub fn new(handle: JoinHandle<T>) -> JobHandle<T> { JobHandle { handle } }
+/// The result of a job, once it's done. Dropping the handle leaves the job running.
+pub struct JobHandle<R> {
+    result: Receiver<JobResult<R>>,
+}
 
-    pub fn reify(self) -> T { self.handle.join().expect("Could not yield job") }
+impl<R> JobHandle<R> {
+    /// Block until the job is done, and take its result
+    pub fn wait(self) -> JobResult<R> { self.result.recv().unwrap_or(Err(JobError::Lost)) }
 
-    pub fn await(self) { self.handle.join().expect("Could not await job"); }
+    /// Take the job's result if it's done within `timeout`, which can be zero to just check. `None` means it's still
+    /// running. Once a result has been taken, there isn't another.
+    pub fn try_wait(&mut self, timeout: Duration) -> Option<JobResult<R>> {
+        match self.result.recv_timeout(timeout) {
+            Ok(result) => Some(result),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(Err(JobError::Lost)),
+        }
+    }
 
     pub fn ignore(self) {}
 }
 
 pub trait JobMultiHandle: Sized {
-    fn await(self);
+    type Output;
+
+    /// Block until every job is done, and take their results in order
+    fn wait(self) -> Vec<JobResult<Self::Output>>;
     fn ignore(self: Self) {}
 }
 
-impl<I, T> JobMultiHandle for I
+impl<I, R> JobMultiHandle for I
 where
-    I: IntoIterator<Item = JobHandle<T>> + Sized,
+    I: IntoIterator<Item = JobHandle<R>> + Sized,
 {
-    fn await(self: Self) {
-        for job in self {
-            job.await();
-        }
+    type Output = R;
+
+    fn wait(self: Self) -> Vec<JobResult<R>> { self.into_iter().map(|job| job.wait()).collect() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Barrier,
+    };
+
+    fn jobs<T: 'static + Send + Sync>(root: &Arc<T>) -> Jobs<T> {
+        let jobs = Jobs::new();
+        jobs.set_root(root.clone());
+        jobs
+    }
+
+    #[test]
+    fn jobs_hand_back_their_results() {
+        let root = Arc::new(20);
+        let jobs = jobs(&root);
+
+        let handles = (0..4).map(|i| jobs.spawn_result(move |root| **root + i)).collect::<Vec<_>>();
+        assert_eq!(handles.wait(), vec![Ok(20), Ok(21), Ok(22), Ok(23)]);
+
+        let mut handle = jobs.spawn_result(|root| root.to_string());
+        let result = loop {
+            if let Some(result) = handle.try_wait(Duration::from_millis(10)) {
+                break result;
+            }
+        };
+        assert_eq!(result, Ok("20".to_string()));
+    }
+
+    #[test]
+    fn panics_are_caught_and_handed_back() {
+        let root = Arc::new(());
+        let jobs = jobs(&root);
+
+        let handle = jobs.spawn_result(|_| -> u32 { panic!("out of {}", "cheese") });
+        assert_eq!(handle.wait(), Err(JobError::Panicked("out of cheese".to_string())));
+
+        // Nothing's left broken for the jobs that come after
+        assert_eq!(jobs.spawn_result(|_| 5).wait(), Ok(5));
+        assert_eq!(jobs.spawn_named("after", |_| 6).wait(), Ok(6));
+    }
+
+    #[test]
+    fn named_jobs_are_only_run_once_at_a_time() {
+        let root = Arc::new((AtomicUsize::new(0), Barrier::new(2)));
+        let jobs = jobs(&root);
+
+        let mesh = |root: &Arc<(AtomicUsize, Barrier)>| {
+            root.1.wait();
+            root.0.fetch_add(1, Ordering::SeqCst)
+        };
+        let first = jobs.spawn_named("mesh chunk (3, 4)", mesh);
+        let second = jobs.spawn_named("mesh chunk (3, 4)", mesh);
+        // A different result is a different job, even with the same name
+        let other = jobs.spawn_named("mesh chunk (3, 4)", |_| "other");
+
+        // Let the job finish
+        root.1.wait();
+        assert_eq!(first.wait(), Ok(0));
+        assert_eq!(second.wait(), Ok(0));
+        assert_eq!(other.wait(), Ok("other"));
+
+        // Once it's done, the same name runs again
+        let third = jobs.spawn_named("mesh chunk (3, 4)", mesh);
+        root.1.wait();
+        assert_eq!(third.wait(), Ok(1));
+    }
+
+    #[test]
+    fn named_jobs_without_a_root_leave_nothing_behind() {
+        let root = Arc::new(());
+        let jobs = jobs(&root);
+        drop(root);
+
+        let started = panic::catch_unwind(AssertUnwindSafe(|| jobs.spawn_named("orphan", |_| ())));
+        assert!(started.is_err());
+        assert!(jobs.named.lock().is_empty());
     }
 }
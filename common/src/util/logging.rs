@@ -0,0 +1,330 @@
+// Standard
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+};
+
+// Library
+use lazy_static::lazy_static;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use parking_lot::{Mutex, RwLock};
+
+// Constants
+/// The target that raw packet contents are logged under. It's off unless enabled by name, e.g. `packets=debug`.
+pub const PACKET_TARGET: &str = "packets";
+/// Environment variables that filters are read from, in order of preference
+const FILTER_VARS: &[&str] = &["VELOREN_LOG", "RUST_LOG"];
+const DEFAULT_MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+const DEFAULT_KEEP_FILES: usize = 4;
+
+lazy_static! {
+    static ref LOGGER: Logger = Logger { state: RwLock::new(None) };
+}
+
+/// How to filter and where to write log records
+#[derive(Clone, Debug)]
+pub struct LogConfig {
+    /// The level for anything without a more specific filter
+    pub level: LevelFilter,
+    /// Levels for module paths or targets, which also apply to everything beneath them. The longest match wins.
+    pub modules: Vec<(String, LevelFilter)>,
+    /// Whether to write to stderr
+    pub stderr: bool,
+    pub file: Option<FileSink>,
+}
+
+/// A log file that gets moved aside once it grows too big. `game.log` becomes `game.log.1`, which becomes
+/// `game.log.2` and so on, until the oldest is deleted.
+#[derive(Clone, Debug)]
+pub struct FileSink {
+    pub path: PathBuf,
+    /// How big the file may grow, in bytes, before it is rotated
+    pub max_size: u64,
+    /// How many rotated files to keep alongside the current one
+    pub keep: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            modules: vec![(PACKET_TARGET.to_string(), LevelFilter::Off)],
+            stderr: true,
+            file: None,
+        }
+    }
+}
+
+impl LogConfig {
+    /// The default config with filters from the environment applied on top
+    pub fn from_env() -> Self {
+        let config = Self::default();
+        match FILTER_VARS.iter().filter_map(|var| env::var(var).ok()).next() {
+            Some(filters) => config.with_filters(&filters),
+            None => config,
+        }
+    }
+
+    /// Apply comma separated filters, each either a level or a `path=level` pair, e.g.
+    /// `warn,server=info,common::net=debug`. Anything that can't be understood is skipped.
+    pub fn with_filters(mut self, filters: &str) -> Self {
+        for filter in filters.split(',').map(|f| f.trim()).filter(|f| !f.is_empty()) {
+            let mut parts = filter.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(level), None) => match level.parse() {
+                    Ok(level) => self.level = level,
+                    // A bare path means everything from it
+                    Err(_) => self.set_module(level, LevelFilter::Trace),
+                },
+                (Some(path), Some(level)) => {
+                    if let Ok(level) = level.parse() {
+                        self.set_module(path, level);
+                    }
+                },
+                _ => {},
+            }
+        }
+        self
+    }
+
+    pub fn with_file(mut self, file: FileSink) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    fn set_module(&mut self, path: &str, level: LevelFilter) {
+        self.modules.retain(|(p, _)| p != path);
+        self.modules.push((path.to_string(), level));
+    }
+
+    /// The level that applies to records from `target`
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(path, _)| target.starts_with(path.as_str()) && is_boundary(&target[path.len()..]))
+            .max_by_key(|(path, _)| path.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules.iter().map(|(_, level)| *level).fold(self.level, |a, b| a.max(b))
+    }
+}
+
+fn is_boundary(rest: &str) -> bool { rest.is_empty() || rest.starts_with("::") }
+
+impl FileSink {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            max_size: DEFAULT_MAX_FILE_SIZE,
+            keep: DEFAULT_KEEP_FILES,
+        }
+    }
+}
+
+/// Start logging with `config`. Logging can be initialized again to replace the config, e.g. once settings have
+/// been loaded, which is why this only fails if the log file can't be opened.
+pub fn init(config: LogConfig) -> io::Result<()> {
+    let file = match &config.file {
+        Some(sink) => Some(Mutex::new(RotatingFile::open(sink.clone())?)),
+        None => None,
+    };
+
+    log::set_max_level(config.max_level());
+    *LOGGER.state.write() = Some(State { config, file });
+    // Fails if we've been here before, in which case the logger is already in place
+    let _ = log::set_logger(&*LOGGER);
+    Ok(())
+}
+
+struct State {
+    config: LogConfig,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+struct Logger {
+    state: RwLock<Option<State>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match &*self.state.read() {
+            Some(state) => metadata.level() <= state.config.level_for(metadata.target()),
+            None => false,
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        let state = self.state.read();
+        let state = match &*state {
+            Some(state) if record.level() <= state.config.level_for(record.target()) => state,
+            _ => return,
+        };
+
+        let line = format_record(record);
+        if state.config.stderr {
+            let _ = io::stderr().write_all(line.as_bytes());
+        }
+        if let Some(file) = &state.file {
+            if let Err(e) = file.lock().write(&line) {
+                eprintln!("Could not write to the log file: {}", e);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(State { file: Some(file), .. }) = &*self.state.read() {
+            let _ = file.lock().file.flush();
+        }
+    }
+}
+
+// Everything runs on several threads, so each record says which one it came from
+fn format_record(record: &Record) -> String {
+    let now = time::now();
+    let thread = thread::current();
+    let thread_name = match thread.name() {
+        Some(name) => name.to_string(),
+        None => format!("{:?}", thread.id()),
+    };
+
+    format!(
+        "{}.{:03} {:<5} [{}] {}: {}\n",
+        time::strftime("%Y-%m-%d %H:%M:%S", &now).unwrap_or_default(),
+        now.tm_nsec / 1_000_000,
+        level_name(record.level()),
+        thread_name,
+        record.target(),
+        record.args()
+    )
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "ERROR",
+        Level::Warn => "WARN",
+        Level::Info => "INFO",
+        Level::Debug => "DEBUG",
+        Level::Trace => "TRACE",
+    }
+}
+
+struct RotatingFile {
+    sink: FileSink,
+    file: File,
+    len: u64,
+}
+
+impl RotatingFile {
+    fn open(sink: FileSink) -> io::Result<Self> {
+        if let Some(dir) = sink.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&sink.path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { sink, file, len })
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        if self.len > 0 && self.len + line.len() as u64 > self.sink.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = &self.sink.path;
+        // Renaming over an existing file doesn't work everywhere, so make room first
+        let _ = fs::remove_file(rotated_path(path, self.sink.keep));
+        for n in (1..self.sink.keep).rev() {
+            let _ = fs::rename(rotated_path(path, n), rotated_path(path, n + 1));
+        }
+        if self.sink.keep > 0 {
+            fs::rename(path, rotated_path(path, 1))?;
+        }
+
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{}", n));
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("veloren-logging-{}-{}", name, rand::random::<u32>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn init_twice() {
+        init(LogConfig::default()).unwrap();
+        info!("logging once");
+        init(LogConfig::default().with_filters("debug")).unwrap();
+        debug!("logging twice");
+        debug!(target: PACKET_TARGET, "this is hidden");
+    }
+
+    #[test]
+    fn most_specific_filter_wins() {
+        let config = LogConfig::default().with_filters("warn,common=info,common::net=debug,bogus=loud");
+
+        assert_eq!(config.level_for("server"), LevelFilter::Warn);
+        assert_eq!(config.level_for("common"), LevelFilter::Info);
+        assert_eq!(config.level_for("common::terrain"), LevelFilter::Info);
+        assert_eq!(config.level_for("common::net::tcp"), LevelFilter::Debug);
+        // Paths only match whole modules
+        assert_eq!(config.level_for("commonplace"), LevelFilter::Warn);
+        assert_eq!(config.level_for("bogus"), LevelFilter::Warn);
+        assert_eq!(config.max_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn packets_need_enabling_by_name() {
+        let config = LogConfig::default().with_filters("trace");
+        assert_eq!(config.level_for(PACKET_TARGET), LevelFilter::Off);
+
+        let config = config.with_filters(&format!("{}=debug", PACKET_TARGET));
+        assert_eq!(config.level_for(PACKET_TARGET), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn files_rotate_when_full() {
+        let dir = temp_dir("rotate");
+        let path = dir.join("test.log");
+        let mut file = RotatingFile::open(FileSink {
+            path: path.clone(),
+            max_size: 10,
+            keep: 2,
+        })
+        .unwrap();
+
+        for line in &["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write(line).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(fs::read_to_string(rotated_path(&path, 1)).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(rotated_path(&path, 2)).unwrap(), "second\n");
+        // Only `keep` old files stick around
+        assert!(!rotated_path(&path, 3).exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
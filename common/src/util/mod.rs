@@ -1,6 +1,10 @@
 pub mod clock;
+pub mod cmd;
+pub mod jobs;
+pub mod logging;
 pub mod manager;
 pub mod msg;
 pub mod names;
 pub mod post;
+pub mod recording;
 pub mod testutils;
@@ -1,15 +1,147 @@
-use std::time::Duration;
+// Standard
+use std::{i16, i32, time::Duration, u16};
 
 // Library
+use serde::{de::Deserializer, ser::Serializer};
 use serde_derive::{Deserialize, Serialize};
 use vek::*;
 
 // Project
 use crate::{
+    audio::SoundId,
+    ecs::{
+        craft::{CraftError, Recipe},
+        inventory::{Inventory, InventoryAction, Item, ItemKind},
+        phys::MoveMode,
+    },
     net::Message,
-    util::post::{PostBox, PostOffice},
+    physics::config::PhysicsConfig,
+    terrain::{
+        chunk::{Block, CHUNK_SIZE},
+        VolOffs, VoxAbs, WorldBorder,
+    },
+    util::{
+        cmd::CmdSpec,
+        post::{PostBox, PostOffice},
+    },
+    weather::Weather,
 };
 
+// Constants
+/// How far, in blocks, the server has to move a player for their client to wait for the chunks around them to load
+/// again before they can move. The server holds the player in place until then.
+pub const LONG_TELEPORT: f32 = 32.0;
+/// The most chunks a client can ask for in one `RequestChunks`. Any more than that are ignored.
+pub const MAX_CHUNK_REQUEST: usize = 256;
+// How many steps each chunk is split into along each axis for positions sent over the network
+const POS_STEPS: f64 = 65536.0;
+// How many steps each block per second is split into for velocities sent over the network
+const VEL_STEPS: f32 = 256.0;
+
+// NetPos
+
+/// A position as sent over the network: the chunk it's in, and how far into that chunk in 65536ths of the chunk along
+/// each axis. That's about 0.0005 of a block for 32 block chunks, however far the position is from the origin.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NetPos {
+    pub chunk: Vec3<i32>,
+    pub offs: Vec3<u16>,
+}
+
+// How a `NetPos` actually goes over the network. Everything inside the default world border is within 128 chunks of the
+// origin, so the chunk goes as a byte per axis and the full coordinates are only sent for anything further out. That
+// comes to 10 bytes where three `f32`s take 12.
+#[derive(Serialize, Deserialize)]
+struct WirePos {
+    offs: Vec3<u16>,
+    near: Vec3<i8>,
+    far: Option<Vec3<i32>>,
+}
+
+impl NetPos {
+    pub fn from_pos(pos: Vec3<f32>) -> NetPos {
+        let axes = pos.map2(CHUNK_SIZE, |e, size| {
+            let size = size as f64;
+            // Casting nonsense to an integer isn't defined, so it goes to the origin
+            let e = if e.is_finite() { e as f64 } else { 0.0 };
+            let chunk = (e / size).floor().max(i32::MIN as f64).min(i32::MAX as f64);
+            let steps = ((e - chunk * size) / size * POS_STEPS).round().max(0.0);
+            if steps < POS_STEPS {
+                (chunk as i32, steps as u16)
+            } else if chunk < i32::MAX as f64 {
+                // Rounding up to the end of a chunk carries over to the start of the next
+                (chunk as i32 + 1, 0)
+            } else {
+                (i32::MAX, u16::MAX)
+            }
+        });
+        NetPos {
+            chunk: axes.map(|(chunk, _)| chunk),
+            offs: axes.map(|(_, offs)| offs),
+        }
+    }
+
+    pub fn to_pos(&self) -> Vec3<f32> {
+        let steps = self.offs.map2(CHUNK_SIZE, |offs, size| offs as f64 / POS_STEPS * size as f64);
+        self.chunk
+            .map2(CHUNK_SIZE, |chunk, size| chunk as f64 * size as f64)
+            .map2(steps, |chunk, steps| (chunk + steps) as f32)
+    }
+}
+
+impl serde::Serialize for NetPos {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let near = self.chunk.map(|e| e as i8);
+        let wire = WirePos {
+            offs: self.offs,
+            near,
+            far: if near.map(|e| e as i32) == self.chunk { None } else { Some(self.chunk) },
+        };
+        serde::Serialize::serialize(&wire, serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for NetPos {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<NetPos, D::Error> {
+        let wire: WirePos = serde::Deserialize::deserialize(deserializer)?;
+        Ok(NetPos {
+            chunk: wire.far.unwrap_or_else(|| wire.near.map(|e| e as i32)),
+            offs: wire.offs,
+        })
+    }
+}
+
+// NetVel
+
+/// A velocity as sent over the network, in 256ths of a block per second. That covers up to 128 blocks per second
+/// along each axis, which is faster than anything normally moves. Anything faster goes as full `f32`s instead.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetVel {
+    pub steps: Vec3<i16>,
+    pub fast: Option<Vec3<f32>>,
+}
+
+impl NetVel {
+    pub fn from_vel(vel: Vec3<f32>) -> NetVel {
+        // Nonsense goes nowhere
+        let vel = vel.map(|e| if e.is_finite() { e } else { 0.0 });
+        let steps = vel.map(|e| (e * VEL_STEPS).round());
+        if steps.map(|e| e >= i16::MIN as f32 && e <= i16::MAX as f32).reduce_and() {
+            NetVel {
+                steps: steps.map(|e| e as i16),
+                fast: None,
+            }
+        } else {
+            NetVel {
+                steps: Vec3::zero(),
+                fast: Some(vel),
+            }
+        }
+    }
+
+    pub fn to_vel(&self) -> Vec3<f32> { self.fast.unwrap_or_else(|| self.steps.map(|e| e as f32 / VEL_STEPS)) }
+}
+
 // SessionKind
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -17,6 +149,8 @@ pub enum SessionKind {
     Connect,
     Disconnect,
     Ping,
+    // Asking about the server without joining it
+    Status,
 }
 
 impl Message for SessionKind {}
@@ -25,22 +159,45 @@ impl Message for SessionKind {}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum CompStore {
-    Pos(Vec3<f32>),
-    Vel(Vec3<f32>),
+    Pos(NetPos),
+    Vel(NetVel),
     Dir(Vec2<f32>),
+    MoveMode(MoveMode),
+    CollisionBox(Vec3<f32>),
     Player { alias: String, mode: PlayMode },
     Character { name: String },
     Health(u32),
+    Inventory(Inventory),
+    // The hotbar slot a player has picked, and what kind of item is in it
+    HeldItem { slot: usize, item: Option<ItemKind> },
+    // An item lying in the world
+    Item(Item),
+    Projectile,
 }
 
 // ServerMsg
 
+/// What a server says about itself, to players who join it and to anyone who asks without joining
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub name: String,
+    /// The message of the day, which may span several lines
+    pub motd: String,
+    pub player_count: u32,
+    pub max_players: u32,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ServerMsg {
     // SessionKind::Connect
     Connected {
         player_uid: Option<u64>,
         time: Duration,
+        // Presented when reconnecting to pick the session back up
+        session: u64,
+        physics: PhysicsConfig,
+        border: WorldBorder,
+        world_seed: u64,
     },
 
     // SessionKind::Disconnect
@@ -51,6 +208,9 @@ pub enum ServerMsg {
     // SessionKind::Ping
     Ping,
 
+    // SessionKind::Status, and one-shot just after the player joins
+    ServerInfo(ServerInfo),
+
     // One-shot
     ChatMsg {
         text: String,
@@ -58,20 +218,74 @@ pub enum ServerMsg {
     EntityDeleted {
         uid: u64,
     },
+    // The client's own player died, and can't do anything until it asks to respawn
+    Died {
+        cause: String,
+    },
+    // Several entities went away in the same tick
+    EntitiesDeleted {
+        uids: Vec<u64>,
+    },
     CompUpdate {
         // This also acts as an EntityCreated message
         uid: u64,
         store: CompStore,
+        // The value is authoritative, even for the client's own player, and should replace any predicted value outright
+        forced: bool,
     },
 
     TimeUpdate(Duration),
+    // The server's physics config changed, and predictions should follow the new one straight away
+    PhysicsUpdate(PhysicsConfig),
+    BorderUpdate(WorldBorder),
+    ChunkData {
+        pos: Vec3<VolOffs>,
+        data: Vec<u8>,
+    },
+    // A block in a chunk the client may already have changed
+    BlockUpdate {
+        pos: Vec3<VoxAbs>,
+        block: Block,
+    },
+    // The commands the player may use, sent when they join and whenever that changes
+    CommandList {
+        commands: Vec<CmdSpec>,
+    },
+    // A sound to play once, where it happened
+    SoundEvent {
+        sound: SoundId,
+        pos: Vec3<f32>,
+        volume: f32,
+        pitch: f32,
+    },
+    // How far a nearby player has got digging out a block, with 0 once they've stopped or finished
+    DigProgress {
+        uid: u64,
+        pos: Vec3<VoxAbs>,
+        progress: f32,
+    },
+    // The weather in the region the player is in, sent when they arrive and every so often while it changes
+    WeatherUpdate {
+        region: Vec2<i32>,
+        state: Weather,
+        intensity: f32,
+    },
+    // Every recipe the server knows, sent when the player joins
+    RecipeList {
+        recipes: Vec<Recipe>,
+    },
+    // How asking to craft a recipe went, with everything that was made if it worked
+    CraftResult {
+        recipe: String,
+        result: Result<Item, CraftError>,
+    },
 }
 
 impl Message for ServerMsg {}
 
 // ClientMsg
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PlayMode {
     Headless,
     Character,
@@ -83,6 +297,10 @@ pub enum ClientMsg {
     Connect {
         alias: String,
         mode: PlayMode,
+        // The session to resume, if the client is reconnecting after its connection dropped
+        session: Option<u64>,
+        // Proof of who the player is from an authentication service, once there is one to check it with
+        login_token: Option<String>,
     },
 
     // SessionKind::Disconnect
@@ -93,6 +311,9 @@ pub enum ClientMsg {
     // SessionKind::Ping
     Ping,
 
+    // SessionKind::Status
+    Status,
+
     // One-shot
     ChatMsg {
         text: String,
@@ -104,7 +325,47 @@ pub enum ClientMsg {
         pos: Vec3<f32>,
         vel: Vec3<f32>,
         dir: Vec2<f32>,
+        move_mode: MoveMode,
+        // Whether jump is held, since a player can only rise faster than they climb onto blocks by jumping
+        jumping: bool,
+    },
+    RequestChunks {
+        positions: Vec<Vec3<VolOffs>>,
+    },
+    // Chunks the client has unloaded, and will have to be sent again if it asks for them
+    ForgetChunks {
+        positions: Vec<Vec3<VolOffs>>,
+    },
+    InventoryAction(InventoryAction),
+    // Pick which hotbar slot to hold, which is what the player places blocks from
+    SelectSlot {
+        slot: usize,
+    },
+    // Craft a recipe `count` times over, all at once or not at all
+    Craft {
+        recipe: String,
+        count: u32,
+    },
+    // Fire in the given direction, which must be roughly the way the player is facing
+    Attack {
+        dir: Vec3<f32>,
+    },
+    // Change a block within the player's reach
+    SetBlock {
+        pos: Vec3<VoxAbs>,
+        block: Block,
+    },
+    // How far the player has got digging out a block, from 0 to 1. Sent as the player starts and whenever it gets
+    // noticeably further, with 1 once they're done, which the server only accepts if they've been at it long enough.
+    // 0 means they've stopped.
+    DigProgress {
+        pos: Vec3<VoxAbs>,
+        progress: f32,
     },
+    // The chunks around the player have loaded since it joined or was moved far away, so it can start moving
+    Ready,
+    // Come back to life at the spawn point after dying
+    Respawn,
 }
 
 impl Message for ClientMsg {}
@@ -114,3 +375,85 @@ pub type ClientPostOffice = PostOffice<SessionKind, ClientMsg, ServerMsg>;
 
 pub type ServerPostBox = PostBox<SessionKind, ServerMsg, ClientMsg>;
 pub type ClientPostBox = PostBox<SessionKind, ClientMsg, ServerMsg>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A step of a 32 block chunk along each axis, plus what's lost getting back to an f32
+    const TOLERANCE: f32 = 0.001;
+
+    fn round_trips(pos: Vec3<f32>) -> bool { NetPos::from_pos(pos).to_pos().distance(pos) < TOLERANCE }
+
+    #[test]
+    fn positions_survive_the_network() {
+        for pos in &[
+            Vec3::zero(),
+            Vec3::new(0.3, -0.3, 17.123),
+            Vec3::new(4095.99, -4095.99, 500.5),
+            Vec3::new(-33.0001, 31.9999, -1.0),
+        ] {
+            assert!(round_trips(*pos), "{} didn't round trip", pos);
+        }
+        // Far out, it's as precise as the f32 it came from
+        assert!(round_trips(Vec3::new(1_000_000.0, -1_000_000.0, 0.0)));
+    }
+
+    #[test]
+    fn positions_at_chunk_boundaries_land_in_the_right_chunk() {
+        let start = NetPos::from_pos(Vec3::new(32.0, -32.0, 0.0));
+        assert_eq!(start.chunk, Vec3::new(1, -1, 0));
+        assert_eq!(start.offs, Vec3::zero());
+
+        // So close to the end of a chunk that it rounds up to the start of the next one
+        let end = NetPos::from_pos(Vec3::new(31.99999, -0.000001, 63.99999));
+        assert_eq!(end.chunk, Vec3::new(1, 0, 2));
+        assert_eq!(end.offs, Vec3::zero());
+
+        let last = NetPos::from_pos(Vec3::new(31.999, 0.0, 0.0));
+        assert_eq!(last.chunk.x, 0);
+        assert!(last.offs.x > u16::MAX - 3);
+    }
+
+    #[test]
+    fn nonsense_positions_and_velocities_are_tamed() {
+        assert_eq!(NetPos::from_pos(Vec3::broadcast(std::f32::NAN)).to_pos(), Vec3::zero());
+        let huge = NetPos::from_pos(Vec3::new(std::f32::INFINITY, 1e30, -1e30));
+        assert_eq!(huge.chunk, Vec3::new(0, i32::MAX, i32::MIN));
+
+        assert_eq!(NetVel::from_vel(Vec3::new(1.5, -60.0, 0.0)).to_vel(), Vec3::new(1.5, -60.0, 0.0));
+        let fast = NetVel::from_vel(Vec3::new(1000.0, -1000.0, std::f32::NAN)).to_vel();
+        assert_eq!(fast, Vec3::new(1000.0, -1000.0, 0.0));
+    }
+
+    fn over_the_network<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> (T, usize) {
+        let bytes = bincode::serialize(value).unwrap();
+        (bincode::deserialize(&bytes).unwrap(), bytes.len())
+    }
+
+    #[test]
+    fn nearby_motion_is_smaller_than_plain_floats() {
+        let plain = bincode::serialize(&Vec3::<f32>::zero()).unwrap().len();
+
+        let pos = NetPos::from_pos(Vec3::new(4000.0, -4000.0, 300.0));
+        let (sent, len) = over_the_network(&pos);
+        assert_eq!(sent, pos);
+        assert!(len < plain, "A position took {} bytes", len);
+
+        let vel = NetVel::from_vel(Vec3::new(100.0, -100.0, -60.0));
+        let (sent, len) = over_the_network(&vel);
+        assert_eq!(sent, vel);
+        assert!(len < plain, "A velocity took {} bytes", len);
+    }
+
+    #[test]
+    fn far_out_chunks_survive_the_network() {
+        // Well past where a chunk coordinate fits in an `i16`
+        for pos in &[Vec3::new(2_000_000.0, -2_000_000.0, 0.0), Vec3::new(4100.0, 0.0, -4100.0)] {
+            let net = NetPos::from_pos(*pos);
+            assert_eq!(over_the_network(&net).0, net);
+            assert_eq!(net.to_pos(), *pos);
+        }
+        assert_eq!(NetPos::from_pos(Vec3::new(2_000_000.0, 0.0, 0.0)).chunk.x, 62_500);
+    }
+}
@@ -5,7 +5,7 @@ use std::{
     net::{TcpStream, ToSocketAddrs},
     sync::{
         atomic::{AtomicU64, Ordering},
-        mpsc::{self, RecvError, RecvTimeoutError, SendError},
+        mpsc::{self, RecvError, RecvTimeoutError, SendError, TryRecvError},
         Arc,
     },
     time::Duration,
@@ -17,7 +17,7 @@ use serde_derive::{Deserialize, Serialize};
 
 // Local
 use crate::{
-    net::{Connection, Error, Message, UdpMgr},
+    net::{Connection, ConnectionStats, Encryption, Error, Message, UdpMgr},
     util::manager::{Managed, Manager},
 };
 
@@ -49,6 +49,19 @@ pub enum Letter<SK, M> {
 
 impl<SK: Message, M: Message> Message for Letter<SK, M> {}
 
+// Posted
+
+/// A letter on its way to the connection, and whether it goes straight out rather than waiting for the next flush
+#[derive(Debug)]
+pub struct Posted<SK, M> {
+    letter: Letter<SK, M>,
+    urgent: bool,
+}
+
+type Outgoing<SK, M> = Result<Posted<SK, M>, ()>;
+
+fn batched<SK, M>(letter: Letter<SK, M>) -> Outgoing<SK, M> { Ok(Posted { letter, urgent: false }) }
+
 // PostBoxSession
 
 #[derive(Debug)]
@@ -65,28 +78,40 @@ pub struct PostBox<SK: Message, SM: Message, RM: Message> {
     // The recv end for the incoming mpsc
     recv: mpsc::Receiver<RM>,
     // The send end for the PostOffice outgoing mpsc
-    po_send: mpsc::Sender<Result<Letter<SK, SM>, ()>>,
+    po_send: mpsc::Sender<Outgoing<SK, SM>>,
 }
 
 impl<SK: Message, SM: Message, RM: Message> PostBox<SK, SM, RM> {
-    pub fn send(&self, msg: SM) -> Result<(), SendError<Result<Letter<SK, SM>, ()>>> {
-        self.po_send.send(Ok(Letter::Message {
+    pub fn send(&self, msg: SM) -> Result<(), SendError<Outgoing<SK, SM>>> {
+        self.po_send.send(batched(Letter::Message {
             uid: self.uid,
             payload: msg,
         }))
     }
 
+    /// Send a message straight away rather than with the rest of the tick's, for when it matters how long it takes
+    /// to arrive
+    pub fn send_urgent(&self, msg: SM) -> Result<(), SendError<Outgoing<SK, SM>>> {
+        self.po_send.send(Ok(Posted {
+            letter: Letter::Message {
+                uid: self.uid,
+                payload: msg,
+            },
+            urgent: true,
+        }))
+    }
+
     pub fn recv(&self) -> Result<RM, RecvError> { self.recv.recv() }
 
     pub fn recv_timeout(&self, duration: Duration) -> Result<RM, RecvTimeoutError> { self.recv.recv_timeout(duration) }
 
-    pub fn close(self) -> Result<(), SendError<Result<Letter<SK, SM>, ()>>> {
-        self.po_send.send(Ok(Letter::CloseBox(self.uid)))
+    pub fn close(self) -> Result<(), SendError<Outgoing<SK, SM>>> {
+        self.po_send.send(batched(Letter::CloseBox(self.uid)))
     }
 }
 
 impl<SK: Message, SM: Message, RM: Message> Drop for PostBox<SK, SM, RM> {
-    fn drop(&mut self) { let _ = self.po_send.send(Ok(Letter::CloseBox(self.uid))); }
+    fn drop(&mut self) { let _ = self.po_send.send(batched(Letter::CloseBox(self.uid))); }
 }
 
 // PostOffice
@@ -96,8 +121,8 @@ pub struct PostOffice<SK: Message, SM: Message, RM: Message> {
     uid_counter: AtomicU64,
 
     // The send + recv ends of the outgoing mpsc, used for cloning and passing to postboxes
-    outgoing_send: Mutex<mpsc::Sender<Result<Letter<SK, SM>, ()>>>,
-    outgoing_recv: Mutex<mpsc::Receiver<Result<Letter<SK, SM>, ()>>>,
+    outgoing_send: Mutex<mpsc::Sender<Outgoing<SK, SM>>>,
+    outgoing_recv: Mutex<mpsc::Receiver<Outgoing<SK, SM>>>,
 
     // The send + recv ends of the incoming mpsc, used for cloning and passing to postboxes
     incoming_send: Mutex<mpsc::Sender<Result<Incoming<SK, SM, RM>, ()>>>,
@@ -118,22 +143,30 @@ pub enum Incoming<SK: Message, SM: Message, RM: Message> {
 
 impl<SK: Message, SM: Message, RM: Message> PostOffice<SK, SM, RM> {
     // Create a postoffice that runs on the client, talking to a server
-    pub fn to_server<U: ToSocketAddrs>(remote_addr: U) -> Result<Manager<PostOffice<SK, SM, RM>>, Error> {
+    pub fn to_server<U: ToSocketAddrs>(
+        remote_addr: U,
+        encryption: Encryption,
+    ) -> Result<Manager<PostOffice<SK, SM, RM>>, Error> {
         // Client-side UIDs start from 1 and count odds
         Ok(Manager::init(PostOffice::new_internal(
             1,
             //TcpStream::connect(remote_addr)?,
-            Connection::new(&remote_addr, UdpMgr::new())?,
+            Connection::new(&remote_addr, UdpMgr::new(), encryption)?,
         )?))
     }
 
-    // Create a postoffice that runs on the server, talking to a client
-    pub fn to_client(stream: TcpStream) -> Result<Manager<PostOffice<SK, SM, RM>>, Error> {
+    // Create a postoffice that runs on the server, talking to a client. The client is offered UDP if `udp` is
+    // listening for it. On an encrypted connection, datagrams are sealed too.
+    pub fn to_client(
+        stream: TcpStream,
+        udp: Arc<UdpMgr>,
+        encryption: Encryption,
+    ) -> Result<Manager<PostOffice<SK, SM, RM>>, Error> {
         // Server-side UIDs start from 0 and count evens
         Ok(Manager::init(PostOffice::new_internal(
             0,
             //stream,
-            Connection::new_stream(stream, UdpMgr::new())?,
+            Connection::new_stream(stream, udp, encryption)?,
         )?))
     }
 
@@ -185,7 +218,7 @@ impl<SK: Message, SM: Message, RM: Message> PostOffice<SK, SM, RM> {
         let _ = self
             .outgoing_send
             .lock()
-            .send(Ok(Letter::OpenBox::<SK, SM> { uid, kind }));
+            .send(batched(Letter::OpenBox::<SK, SM> { uid, kind }));
         self.create_postbox_with_uid(uid)
     }
 
@@ -200,14 +233,16 @@ impl<SK: Message, SM: Message, RM: Message> PostOffice<SK, SM, RM> {
     }
 
     // Send a single one-off message to the remote postoffice
-    pub fn send_one(&self, msg: SM) -> Result<(), SendError<Result<Letter<SK, SM>, ()>>> {
-        self.outgoing_send.lock().send(Ok(Letter::OneShot(msg)))
+    pub fn send_one(&self, msg: SM) -> Result<(), SendError<Outgoing<SK, SM>>> {
+        self.outgoing_send.lock().send(batched(Letter::OneShot(msg)))
     }
 
+    pub fn stats(&self) -> ConnectionStats { self.conn.stats() }
+
     // Stop the PostOffice
     pub fn stop(&self) {
         // Send shutdown message to the remote (we don't care if this fails)
-        let _ = self.outgoing_send.lock().send(Ok(Letter::Shutdown));
+        let _ = self.outgoing_send.lock().send(batched(Letter::Shutdown));
         // Close the connection
         let _ = self.outgoing_send.lock().send(Err(()));
         let _ = self.incoming_send.lock().send(Err(()));
@@ -220,11 +255,25 @@ impl<SK: Message, SM: Message, RM: Message> Managed for PostOffice<SK, SM, RM> {
         Manager::add_worker(mgr, |po, running, _| {
             // Hold the outgoing receiver permanently
             let outgoing_recv = po.outgoing_recv.lock();
-            while running.load(Ordering::Relaxed) {
-                match outgoing_recv.recv() {
-                    Ok(Ok(letter)) => po.conn.send(letter),
-                    Ok(Err(_)) | Err(_) => break,
-                };
+            'relay: while running.load(Ordering::Relaxed) {
+                // Letters posted while others were being relayed are batched together, and flushed once there are no
+                // more waiting. A tick's worth of messages usually goes out in a packet or two.
+                let mut next = outgoing_recv.recv().map_err(|_| TryRecvError::Disconnected);
+                loop {
+                    match next {
+                        Ok(Ok(Posted { letter, urgent: false })) => po.conn.send(letter),
+                        // What was posted before it goes first, so that it only overtakes whatever the socket can't
+                        // take yet. That keeps it from arriving before the box it's for is opened.
+                        Ok(Ok(Posted { letter, urgent: true })) => {
+                            po.conn.flush();
+                            po.conn.send_urgent(letter);
+                        },
+                        Err(TryRecvError::Empty) => break,
+                        Ok(Err(_)) | Err(TryRecvError::Disconnected) => break 'relay,
+                    };
+                    next = outgoing_recv.try_recv();
+                }
+                po.conn.flush();
             }
 
             // Stop the connection, terminating communication
@@ -0,0 +1,259 @@
+// Standard
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, prelude::*, BufWriter},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+// Library
+use bincode;
+use parking_lot::{Mutex, RwLock};
+use serde_derive::{Deserialize, Serialize};
+use vek::*;
+
+// Project
+use crate::{
+    ecs::phys::MoveMode,
+    physics::{config::PhysicsConfig, physics},
+    terrain::{
+        chunk::{Block, Chunk, ChunkContainer, CHUNK_SIZE},
+        BodyState, ChunkMgr, Entity, VolCluster, VolGen, VolOffs, VoxAbs,
+    },
+    Uid,
+};
+
+/*
+ A recording is everything that decided how the player moved on the client: the input for each physics step, the
+ terrain they moved through and the times the server moved them. Replaying it runs the same steps again, so that a
+ desync can be stepped through away from the game. The file is a version number followed by bincoded events.
+*/
+
+/// Bumped whenever events change, since older recordings can't be read after that
+pub const RECORDING_VERSION: u32 = 2;
+/// How far a replay can stray from a recording before it counts as diverged, in blocks
+pub const DEFAULT_THRESHOLD: f32 = 0.01;
+
+// The replayed player. Other entities aren't recorded.
+const PLAYER: Uid = 0;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The recording was made by a different version, whose events can't be read
+    WrongVersion(u32),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error { Error::Io(e) }
+}
+
+/// What the player was doing during a physics step
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Input {
+    pub ctrl_acc: Vec3<f32>,
+    pub look_dir: Vec2<f32>,
+    pub jump: bool,
+    pub move_mode: MoveMode,
+}
+
+impl Input {
+    pub fn of<P: Send + Sync + 'static>(entity: &Entity<P>) -> Input {
+        Input {
+            ctrl_acc: *entity.ctrl_acc(),
+            look_dir: *entity.look_dir(),
+            jump: entity.jump(),
+            move_mode: entity.move_mode(),
+        }
+    }
+
+    pub fn apply<P: Send + Sync + 'static>(&self, entity: &mut Entity<P>) {
+        *entity.ctrl_acc_mut() = self.ctrl_acc;
+        *entity.look_dir_mut() = self.look_dir;
+        *entity.jump_mut() = self.jump;
+        *entity.move_mode_mut() = self.move_mode;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    Physics(PhysicsConfig),
+    /// A chunk as it was sent by the server
+    Chunk {
+        pos: Vec3<VolOffs>,
+        data: Vec<u8>,
+    },
+    Block {
+        pos: Vec3<VoxAbs>,
+        block: Block,
+    },
+    /// Where the player was before the first step
+    Start {
+        pos: Vec3<f32>,
+        vel: Vec3<f32>,
+        body: BodyState,
+    },
+    /// A physics step, and where it left the player
+    Step {
+        dt: Duration,
+        input: Input,
+        pos: Vec3<f32>,
+        vel: Vec3<f32>,
+    },
+    /// The player was moved by something other than physics, e.g: the server correcting them
+    Correction {
+        pos: Vec3<f32>,
+        vel: Vec3<f32>,
+    },
+}
+
+/// Writes a recording as it's made, so that as much as possible survives the game crashing
+pub struct Recorder {
+    out: BufWriter<File>,
+    started: bool,
+}
+
+impl Recorder {
+    pub fn create<F: AsRef<Path>>(path: F, physics: PhysicsConfig) -> io::Result<Recorder> {
+        let mut recorder = Recorder {
+            out: BufWriter::new(File::create(path)?),
+            started: false,
+        };
+        bincode::serialize_into(&mut recorder.out, &RECORDING_VERSION).map_err(to_io)?;
+        recorder.record(&Event::Physics(physics))?;
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, event: &Event) -> io::Result<()> {
+        bincode::serialize_into(&mut self.out, event).map_err(to_io)?;
+        self.out.flush()
+    }
+
+    /// Call before each physics step, with the player as they are before it
+    pub fn before_step<P: Send + Sync + 'static>(&mut self, player: &Entity<P>) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        self.record(&Event::Start {
+            pos: *player.pos(),
+            vel: *player.vel(),
+            body: *player.body(),
+        })
+    }
+
+    /// Call after each physics step, with the player as it left them
+    pub fn after_step<P: Send + Sync + 'static>(&mut self, dt: Duration, player: &Entity<P>) -> io::Result<()> {
+        self.record(&Event::Step {
+            dt,
+            input: Input::of(player),
+            pos: *player.pos(),
+            vel: *player.vel(),
+        })
+    }
+}
+
+fn to_io(e: bincode::Error) -> io::Error { io::Error::new(io::ErrorKind::Other, e) }
+
+pub fn load<F: AsRef<Path>>(path: F) -> Result<Vec<Event>, Error> { parse(&fs::read(path)?) }
+
+/// Read the events of a recording. A recording that was cut short part way through an event, e.g: by the game
+/// crashing, is read up to there.
+pub fn parse(mut data: &[u8]) -> Result<Vec<Event>, Error> {
+    let version: u32 = bincode::deserialize_from(&mut data)
+        .map_err(|_| io::Error::new(io::ErrorKind::UnexpectedEof, "recording is empty"))?;
+    if version != RECORDING_VERSION {
+        return Err(Error::WrongVersion(version));
+    }
+
+    let mut events = vec![];
+    while !data.is_empty() {
+        match bincode::deserialize_from(&mut data) {
+            Ok(event) => events.push(event),
+            Err(_) => {
+                warn!("Recording ends part way through an event after {}, ignoring the rest", events.len());
+                break;
+            },
+        }
+    }
+    Ok(events)
+}
+
+/// Where a replay first strayed from the recording
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// Which step, counting from 0
+    pub step: usize,
+    pub recorded: Vec3<f32>,
+    pub replayed: Vec3<f32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    /// How many steps matched the recording
+    pub steps: usize,
+    /// How many steps had been replayed when each correction came, and how far it moved the player
+    pub corrections: Vec<(usize, f32)>,
+    pub divergence: Option<Divergence>,
+}
+
+fn no_chunk(_pos: Vec3<VolOffs>, _con: Arc<Mutex<Option<ChunkContainer<()>>>>) {}
+
+fn no_payload(_pos: Vec3<VolOffs>, _con: &ChunkContainer<()>, _: &HashMap<Vec3<VolOffs>, Arc<ChunkContainer<()>>>) {}
+
+/// Run the physics steps of a recording again, stopping at the first where the player ends up more than `threshold`
+/// blocks from where they were recorded to
+pub fn replay(events: &[Event], threshold: f32) -> Replay {
+    let chunk_mgr = ChunkMgr::new(CHUNK_SIZE, VolGen::new(no_chunk, no_payload, |_, _| {}, |_, _| {}));
+    let mut config = PhysicsConfig::default();
+    let mut entities: HashMap<Uid, Arc<RwLock<Entity<()>>>> = HashMap::new();
+    let mut replay = Replay::default();
+
+    for event in events {
+        match event {
+            Event::Physics(new) => config = *new,
+            Event::Chunk { pos, data } => match Chunk::from_bytes(data) {
+                Ok(chunk) => chunk_mgr.insert(*pos, chunk),
+                Err(_) => warn!("Recorded chunk {} can't be read, leaving it out", pos),
+            },
+            Event::Block { pos, block } => {
+                chunk_mgr.set_block(*pos, *block);
+            },
+            Event::Start { pos, vel, body } => {
+                let mut player = Entity::new(*pos, *vel, Vec3::zero(), Vec2::zero());
+                *player.body_mut() = *body;
+                entities.insert(PLAYER, Arc::new(RwLock::new(player)));
+            },
+            Event::Correction { pos, vel } => {
+                if let Some(player) = entities.get(&PLAYER) {
+                    let mut player = player.write();
+                    replay.corrections.push((replay.steps, player.pos().distance(*pos)));
+                    *player.pos_mut() = *pos;
+                    *player.vel_mut() = *vel;
+                }
+            },
+            Event::Step { dt, input, pos, .. } => {
+                let player = match entities.get(&PLAYER) {
+                    Some(player) => player,
+                    None => continue,
+                };
+                input.apply(&mut player.write());
+                physics::tick(entities.iter(), &chunk_mgr, &config, *dt);
+
+                let replayed = *player.read().pos();
+                if !(replayed.distance(*pos) <= threshold) {
+                    replay.divergence = Some(Divergence {
+                        step: replay.steps,
+                        recorded: *pos,
+                        replayed,
+                    });
+                    break;
+                }
+                replay.steps += 1;
+            },
+        }
+    }
+    replay
+}
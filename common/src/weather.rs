@@ -0,0 +1,63 @@
+// Library
+use serde_derive::{Deserialize, Serialize};
+use vek::*;
+
+// Project
+use crate::terrain::VoxAbs;
+
+// Constants
+/// How wide the regions that each have their own weather are, in blocks
+pub const WEATHER_REGION_SIZE: VoxAbs = 512;
+
+/// What the sky is doing over a region
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Weather {
+    Clear,
+    Rain,
+    Storm,
+    Snow,
+}
+
+/// What falls from the sky
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Precipitation {
+    Rain,
+    Snow,
+}
+
+impl Weather {
+    pub fn precipitation(&self) -> Option<Precipitation> {
+        match self {
+            Weather::Clear => None,
+            Weather::Rain | Weather::Storm => Some(Precipitation::Rain),
+            Weather::Snow => Some(Precipitation::Snow),
+        }
+    }
+
+    /// How heavy the weather is once it has fully set in, from 0 to 1. Weather eases into and out of this, rather than
+    /// starting or stopping all at once.
+    pub fn peak_intensity(&self) -> f32 {
+        match self {
+            Weather::Clear => 0.0,
+            Weather::Rain => 0.5,
+            Weather::Storm => 1.0,
+            Weather::Snow => 0.6,
+        }
+    }
+
+    /// How much the sky is darkened by this weather at `intensity`, from 0 to 1. Rain and storms darken it alike for
+    /// the same intensity, so that rain building into a storm darkens the sky gradually.
+    pub fn gloom(&self, intensity: f32) -> f32 {
+        let factor = match self.precipitation() {
+            None => 0.0,
+            Some(Precipitation::Rain) => 0.8,
+            Some(Precipitation::Snow) => 0.4,
+        };
+        (intensity * factor).max(0.0).min(1.0)
+    }
+}
+
+/// The weather region a position is in
+pub fn weather_region(pos: Vec3<f32>) -> Vec2<i32> {
+    Vec2::new(pos.x, pos.y).map(|e| (e / WEATHER_REGION_SIZE as f32).floor() as i32)
+}
@@ -10,8 +10,8 @@ use vek::*;
 
 // Project
 use common::terrain::{
-    chunk::{Block, BlockMat, BlockRle, Chunk, HeterogeneousData, RleData},
-    ConstructVolume, PersState, ReadWriteVolume, VolCluster, Voxel,
+    chunk::{Block, BlockMat, BlockRle, Chunk, HeterogeneousData, RleData, CHUNK_SIZE},
+    ConstructVolume, PersState, ReadVolume, ReadWriteVolume, VolCluster, Voxel,
 };
 
 /* Reference Chunk
@@ -225,3 +225,76 @@ fn rle_to_raw_speed(b: &mut Bencher) {
         con.convert(PersState::Hetero);
     });
 }
+
+// A full size chunk of hills, for timing reads of a whole chunk the way the mesher does
+fn gen_hills() -> HeterogeneousData {
+    let mut result = HeterogeneousData::empty(CHUNK_SIZE);
+    for x in 0..CHUNK_SIZE.x {
+        for y in 0..CHUNK_SIZE.y {
+            for z in 0..(x + y) / 3 + 4 {
+                result.replace_at_unchecked(Vec3::new(x, y, z), Block::STONE);
+            }
+        }
+    }
+    result
+}
+
+#[bench]
+fn read_chunk_per_voxel(b: &mut Bencher) {
+    let vol = gen_hills();
+    b.iter(|| {
+        let mut solid = 0;
+        for x in 0..CHUNK_SIZE.x as i64 {
+            for y in 0..CHUNK_SIZE.y as i64 {
+                for z in 0..CHUNK_SIZE.z as i64 {
+                    if vol.at_conv(Vec3::new(x, y, z)).unwrap().is_solid() {
+                        solid += 1;
+                    }
+                }
+            }
+        }
+        solid
+    });
+}
+
+#[bench]
+fn read_chunk_by_slice(b: &mut Bencher) {
+    let vol = gen_hills();
+    let mut voxels = vec![Block::empty(); CHUNK_SIZE.map(|e| e as usize).product()];
+    b.iter(|| {
+        vol.fill_slice(Vec3::new(0, 0, 0), CHUNK_SIZE, &mut voxels);
+        voxels.iter().filter(|b| b.is_solid()).count()
+    });
+}
+
+#[bench]
+fn read_rle_chunk_per_voxel(b: &mut Bencher) {
+    let mut con = Chunk::Hetero(gen_hills());
+    con.convert(PersState::Rle);
+    let vol = con.get(PersState::Rle).unwrap();
+    b.iter(|| {
+        let mut solid = 0;
+        for x in 0..CHUNK_SIZE.x {
+            for y in 0..CHUNK_SIZE.y {
+                for z in 0..CHUNK_SIZE.z {
+                    if vol.at_unchecked(Vec3::new(x, y, z)).is_solid() {
+                        solid += 1;
+                    }
+                }
+            }
+        }
+        solid
+    });
+}
+
+#[bench]
+fn read_rle_chunk_by_slice(b: &mut Bencher) {
+    let mut con = Chunk::Hetero(gen_hills());
+    con.convert(PersState::Rle);
+    let vol = con.get(PersState::Rle).unwrap();
+    let mut voxels = vec![Block::empty(); CHUNK_SIZE.map(|e| e as usize).product()];
+    b.iter(|| {
+        vol.fill_slice(Vec3::new(0, 0, 0), CHUNK_SIZE, &mut voxels);
+        voxels.iter().filter(|b| b.is_solid()).count()
+    });
+}
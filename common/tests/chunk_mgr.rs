@@ -0,0 +1,120 @@
+#![feature(test)]
+
+extern crate test;
+
+// Standard
+use std::{collections::HashMap, sync::Arc, thread, time::Duration};
+
+// Library
+use parking_lot::Mutex;
+use test::Bencher;
+use vek::*;
+
+// Project
+use common::terrain::{
+    self,
+    chunk::{Block, Chunk, ChunkContainer, HeterogeneousData, HomogeneousData, CHUNK_SIZE},
+    ChunkMgr, ConstructVolume, ReadVolume, ReadWriteVolume, VolGen, VolOffs, Volume, VoxAbs,
+};
+
+fn gen_air(_pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<()>>>>) {
+    *con.lock() = Some(ChunkContainer::new(Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR))));
+}
+
+// Blocks that change along every axis, so that a sample mixing up chunks or offsets gets them wrong
+fn gen_pattern(pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<()>>>>) {
+    let mut vol = HeterogeneousData::empty(CHUNK_SIZE);
+    for x in 0..CHUNK_SIZE.x {
+        for y in 0..CHUNK_SIZE.y {
+            for z in 0..CHUNK_SIZE.z {
+                let rel = Vec3::new(x, y, z);
+                let abs = terrain::voloffs_to_voxabs(pos, CHUNK_SIZE) + rel.map(|e| e as VoxAbs);
+                let block = if (abs.x + abs.y * 2 + abs.z * 3) % 5 == 0 {
+                    Block::STONE
+                } else if abs.z % 4 == 0 {
+                    Block::WATER
+                } else {
+                    Block::AIR
+                };
+                vol.replace_at_unchecked(rel, block);
+            }
+        }
+    }
+    *con.lock() = Some(ChunkContainer::new(Chunk::Hetero(vol)));
+}
+
+fn gen_nothing(_: Vec3<VolOffs>, _: &ChunkContainer<()>, _: &HashMap<Vec3<VolOffs>, Arc<ChunkContainer<()>>>) {}
+
+// A manager with 1000 chunks loaded, as many as a render loop might go through
+fn loaded_mgr() -> ChunkMgr<()> { mgr_with(gen_air, 10) }
+
+// A manager with the cube of `n` chunks on each side from the origin loaded
+fn mgr_with(gen: fn(Vec3<VolOffs>, Arc<Mutex<Option<ChunkContainer<()>>>>), n: VolOffs) -> ChunkMgr<()> {
+    let mgr = ChunkMgr::new(CHUNK_SIZE, VolGen::new(gen, gen_nothing, |_, _| {}, |_, _| {}));
+    for x in 0..n {
+        for y in 0..n {
+            for z in 0..n {
+                mgr.gen(Vec3::new(x, y, z));
+            }
+        }
+    }
+    // Generation happens on other threads
+    while mgr.pending_count() > 0 {
+        thread::sleep(Duration::from_millis(10));
+        mgr.maintain();
+    }
+    assert_eq!(mgr.loaded_count(), (n * n * n) as usize);
+    mgr
+}
+
+#[test]
+fn sample_fill_slice_matches_per_block_reads() {
+    let mgr = mgr_with(gen_pattern, 2);
+    // Straddles the chunk boundary on every axis
+    let sample = mgr.get_sample(Vec3::new(20, 25, 3), Vec3::new(40, 35, 50)).unwrap();
+    let size = sample.size();
+    let mut out = vec![Block::AIR; size.map(|e| e as usize).product()];
+    sample.fill_slice(Vec3::new(0, 0, 0), size, &mut out);
+    for x in 0..size.x {
+        for y in 0..size.y {
+            for z in 0..size.z {
+                let off = Vec3::new(x, y, z);
+                let abs = Vec3::new(20, 25, 3) + off.map(|e| e as VoxAbs);
+                assert_eq!(out[terrain::slice_index(off, size)], sample.at_abs_unchecked(abs));
+                assert_eq!(Some(out[terrain::slice_index(off, size)]), sample.at_abs(abs));
+            }
+        }
+    }
+}
+
+#[bench]
+fn iterate_loaded_by_copying_map(b: &mut Bencher) {
+    let mgr = loaded_mgr();
+    b.iter(|| {
+        let mut n = 0;
+        for (pos, _) in mgr.pers(|pos| pos.z < 5).iter() {
+            n += pos.x;
+        }
+        n
+    });
+}
+
+#[bench]
+fn iterate_loaded_by_snapshot(b: &mut Bencher) {
+    let mgr = loaded_mgr();
+    b.iter(|| {
+        let mut n = 0;
+        mgr.for_each_loaded(|pos, _| {
+            if pos.z < 5 {
+                n += pos.x;
+            }
+        });
+        n
+    });
+}
+
+#[bench]
+fn list_loaded_positions(b: &mut Bencher) {
+    let mgr = loaded_mgr();
+    b.iter(|| mgr.positions().len());
+}
@@ -0,0 +1,102 @@
+// Standard
+use std::{collections::HashMap, sync::Arc, thread, time::Duration};
+
+// Library
+use parking_lot::Mutex;
+use vek::*;
+
+// Project
+use common::terrain::{
+    chunk::{Block, Chunk, ChunkContainer, HeterogeneousData, HomogeneousData, CHUNK_SIZE},
+    ChunkMgr, ConstructVolume, FnGenFunc, ReadWriteVolume, VolGen, VolOffs, VoxAbs,
+};
+
+// Solid stone with a tunnel running along x, and glowstone in the tunnel a few blocks before the end of chunk 0
+fn gen_tunnel(pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<()>>>>) {
+    let mut blocks = HeterogeneousData::filled(CHUNK_SIZE, Block::STONE);
+    for x in 0..CHUNK_SIZE.x {
+        blocks.set_at(Vec3::new(x, 5, 5), Block::AIR);
+    }
+    if pos.x == 0 {
+        blocks.set_at(Vec3::new(28, 5, 5), Block::GLOWSTONE);
+    }
+    *con.lock() = Some(ChunkContainer::new(Chunk::Hetero(blocks)));
+}
+
+fn gen_air(_pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<()>>>>) {
+    *con.lock() = Some(ChunkContainer::new(Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR))));
+}
+
+// Open air under a stone roof
+fn gen_roofed(pos: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<()>>>>) {
+    let block = if pos.z > 0 { Block::STONE } else { Block::AIR };
+    *con.lock() = Some(ChunkContainer::new(Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, block))));
+}
+
+fn gen_nothing(_: Vec3<VolOffs>, _: &ChunkContainer<()>, _: &HashMap<Vec3<VolOffs>, Arc<ChunkContainer<()>>>) {}
+
+fn mgr<G: FnGenFunc<Vec3<VolOffs>, ChunkContainer<()>>>(gen: G) -> ChunkMgr<()> {
+    ChunkMgr::new(CHUNK_SIZE, VolGen::new(gen, gen_nothing, |_, _| {}, |_, _| {}))
+}
+
+// Generate chunks and wait for them all to arrive, which is when light crosses the borders between them
+fn load(mgr: &ChunkMgr<()>, chunks: &[Vec3<VolOffs>]) {
+    for chunk in chunks {
+        mgr.gen(*chunk);
+    }
+    while chunks.iter().any(|chunk| !mgr.exists_chunk(*chunk)) {
+        thread::sleep(Duration::from_millis(10));
+        mgr.maintain();
+    }
+}
+
+#[test]
+fn block_light_fades_along_a_tunnel_into_the_next_chunk() {
+    let mgr = mgr(gen_tunnel);
+    load(&mgr, &[Vec3::new(0, 0, 0), Vec3::new(1, 0, 0)]);
+    let block_light = |x: VoxAbs| mgr.get_light(Vec3::new(x, 5, 5)).unwrap().block;
+
+    for x in 29..44 {
+        assert_eq!(block_light(x), 15 - (x - 28) as u8);
+    }
+    assert_eq!(block_light(50), 0);
+    // The tunnel is walled off from the sky
+    assert_eq!(mgr.get_light(Vec3::new(30, 5, 5)).unwrap().sun, 0);
+
+    // Moving the glowstone into the other chunk moves the light with it, back across the border
+    assert!(mgr.set_block(Vec3::new(28, 5, 5), Block::AIR));
+    assert!((20..64).all(|x| block_light(x) == 0));
+    assert!(mgr.set_block(Vec3::new(40, 5, 5), Block::GLOWSTONE));
+    assert_eq!(block_light(32), 7);
+    assert_eq!(block_light(28), 3);
+}
+
+#[test]
+fn covering_a_column_darkens_it_all_the_way_down() {
+    let mgr = mgr(gen_air);
+    load(&mgr, &[Vec3::new(0, 0, 0), Vec3::new(0, 0, 1)]);
+    let sun = |z: VoxAbs| mgr.get_light(Vec3::new(5, 5, z)).unwrap().sun;
+    assert!((0..64).all(|z| sun(z) == 15));
+
+    // Light still comes in from the side, but no longer falls straight down
+    assert!(mgr.set_block(Vec3::new(5, 5, 40), Block::STONE));
+    assert_eq!(sun(41), 15);
+    assert_eq!(sun(40), 0);
+    assert!((0..40).all(|z| sun(z) == 14));
+    assert_eq!(mgr.get_light(Vec3::new(6, 5, 39)).unwrap().sun, 15);
+
+    assert!(mgr.set_block(Vec3::new(5, 5, 40), Block::AIR));
+    assert!((0..64).all(|z| sun(z) == 15));
+}
+
+#[test]
+fn sunlight_is_taken_back_once_the_chunk_above_turns_out_to_cover_it() {
+    let mgr = mgr(gen_roofed);
+    load(&mgr, &[Vec3::new(0, 0, 0)]);
+    // Nothing is known about what's above yet, so the sky is taken to be open
+    assert_eq!(mgr.get_light(Vec3::new(5, 5, 5)).unwrap().sun, 15);
+
+    load(&mgr, &[Vec3::new(0, 0, 1)]);
+    assert_eq!(mgr.get_light(Vec3::new(5, 5, 5)).unwrap().sun, 0);
+    assert_eq!(mgr.get_light(Vec3::new(5, 5, 31)).unwrap().sun, 0);
+}
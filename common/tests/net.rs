@@ -6,7 +6,7 @@ use serde_derive::{Deserialize, Serialize};
 
 // Project
 use common::{
-    net::Message,
+    net::{Encryption, Message, UdpMgr},
     util::{
         manager::Manager,
         post::{Incoming, PostBox, PostOffice},
@@ -40,14 +40,15 @@ fn post_office() {
     let listener = TcpListener::bind(&server_addr).unwrap();
     thread::spawn(move || match listener.incoming().next() {
         Some(Ok(stream)) => {
-            thread::spawn(move || handle_client(PostOffice::to_client(stream).unwrap()));
+            let po = PostOffice::to_client(stream, UdpMgr::new(), Encryption::Preferred).unwrap();
+            thread::spawn(move || handle_client(po));
         },
         Some(Err(e)) => panic!("Connection error: {}", e),
         None => panic!("No client received"),
     });
 
     // Client
-    handle_remote(PostOffice::to_server(&server_addr).unwrap());
+    handle_remote(PostOffice::to_server(&server_addr, Encryption::Preferred).unwrap());
 }
 
 fn handle_client(postoffice: Manager<PostOffice<SessionKind, ServerMsg, ClientMsg>>) {
@@ -80,7 +81,8 @@ fn handle_remote(po: Manager<PostOffice<SessionKind, ClientMsg, ServerMsg>>) {
     for _ in 0..10 {
         let pb_r = po.create_postbox(SessionKind::PingPong);
 
-        let _ = pb_r.send(ClientMsg::Ping);
+        // Urgent messages skip the batch, but still arrive after the box they're for is opened
+        let _ = pb_r.send_urgent(ClientMsg::Ping);
         let msg = pb_r.recv().unwrap();
         assert_eq!(ServerMsg::Pong, msg);
 
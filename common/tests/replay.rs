@@ -0,0 +1,82 @@
+// Standard
+use std::fs;
+
+// Library
+use tempfile;
+use vek::*;
+
+// Project
+use common::{
+    physics::config::PhysicsConfig,
+    terrain::chunk::Block,
+    util::recording::{self, Event, Recorder, RECORDING_VERSION},
+};
+
+// A short session: the player walks off into open air and falls, steering, until the server moves them and they
+// carry on sprinting
+const FALL: &[u8] = include_bytes!("data/fall.rec");
+
+#[test]
+fn recorded_session_replays_without_diverging() {
+    let events = recording::parse(FALL).unwrap();
+    let replay = recording::replay(&events, 0.001);
+
+    assert_eq!(replay.divergence, None);
+    assert_eq!(replay.steps, 35);
+    assert_eq!(replay.corrections.len(), 1);
+    let (step, dist) = replay.corrections[0];
+    assert_eq!(step, 20);
+    assert!(dist > 3.0 && dist < 3.5);
+}
+
+#[test]
+fn replay_finds_where_a_recording_diverges() {
+    let mut events = recording::parse(FALL).unwrap();
+    {
+        let tampered = events
+            .iter_mut()
+            .filter_map(|event| match event {
+                Event::Step { pos, .. } => Some(pos),
+                _ => None,
+            })
+            .nth(12)
+            .unwrap();
+        tampered.x += 0.1;
+    }
+
+    let divergence = recording::replay(&events, 0.01).divergence.unwrap();
+    assert_eq!(divergence.step, 12);
+    assert!((divergence.recorded.distance(divergence.replayed) - 0.1).abs() < 0.001);
+}
+
+#[test]
+fn recordings_are_read_back_as_they_were_written() {
+    // Removed when it goes out of scope
+    let file = tempfile::Builder::new().suffix(".rec").tempfile().unwrap();
+    let path = file.path();
+    let events = vec![
+        Event::Physics(PhysicsConfig::default()),
+        Event::Block {
+            pos: Vec3::new(-3, 4, 70),
+            block: Block::STONE,
+        },
+        Event::Correction {
+            pos: Vec3::new(1.5, -2.0, 64.0),
+            vel: Vec3::zero(),
+        },
+    ];
+    {
+        let mut recorder = Recorder::create(path, PhysicsConfig::default()).unwrap();
+        for event in events[1..].iter() {
+            recorder.record(event).unwrap();
+        }
+    }
+    assert_eq!(recording::load(path).unwrap(), events);
+
+    // A recording cut off part way through an event is read up to there
+    let mut data = fs::read(path).unwrap();
+    assert_eq!(recording::parse(&data[..data.len() - 1]).unwrap(), events[..2].to_vec());
+
+    data[0] = RECORDING_VERSION as u8 + 1;
+    assert!(recording::parse(&data).is_err());
+}
@@ -5,10 +5,17 @@
 extern crate log;
 
 // Standard
-use std::{io, sync::Arc};
+use std::{
+    collections::HashMap,
+    env, io,
+    path::Path,
+    process,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
 // Library
-use parking_lot::Mutex;
 use syrup::Window;
 use vek::*;
 
@@ -16,13 +23,19 @@ use vek::*;
 use client::{Client, ClientEvent, PlayMode};
 use common::{
     audio::{AudioGen, Buffer, Stream},
-    terrain::{chunk::ChunkContainer, VolOffs},
+    net::Encryption,
+    terrain::{chunk::ChunkContainer, Container, VolOffs},
+    util::recording,
 };
 
 struct NoAudio {}
 impl AudioGen for NoAudio {
     fn gen_stream(&self, id: u64, buffer: &Buffer, stream: &Stream) {}
 
+    fn update_stream(&self, id: u64, buffer: &Buffer, stream: &Stream) {}
+
+    fn set_listener(&self, pos: Vec3<f32>, ori: Vec3<f32>) {}
+
     fn gen_buffer(&self, id: u64, buffer: &Buffer) {}
 
     fn drop_stream(&self, id: u64, buffer: &Buffer, stream: &Stream) {}
@@ -37,11 +50,112 @@ impl client::Payloads for Payloads {
     type Audio = NoAudio;
 }
 
-fn gen_payload(_key: Vec3<VolOffs>, _con: Arc<Mutex<Option<ChunkContainer<<Payloads as client::Payloads>::Chunk>>>>) {}
+// There's nothing to draw, but the client waits for every chunk around the player to have a payload before it moves
+fn gen_payload(
+    _key: Vec3<VolOffs>,
+    con: &ChunkContainer<<Payloads as client::Payloads>::Chunk>,
+    _neighbours: &HashMap<Vec3<VolOffs>, Arc<ChunkContainer<<Payloads as client::Payloads>::Chunk>>>,
+) {
+    *con.payload_mut() = Some(());
+}
 
 fn drop_payload(_key: Vec3<VolOffs>, _con: Arc<ChunkContainer<<Payloads as client::Payloads>::Chunk>>) {}
 
+// Run the physics of a recording made with `--record` again, and say where it went differently. Returns the exit code.
+fn replay(args: &[String]) -> i32 {
+    let path = match args.get(0) {
+        Some(path) => path,
+        None => {
+            println!("Usage: headless --replay <recording> [threshold in blocks]");
+            return 2;
+        },
+    };
+    let threshold = match args.get(1).map(|t| t.parse::<f32>()) {
+        None => recording::DEFAULT_THRESHOLD,
+        Some(Ok(threshold)) => threshold,
+        Some(Err(_)) => {
+            println!("Invalid threshold '{}'", args[1]);
+            return 2;
+        },
+    };
+    let events = match recording::load(path) {
+        Ok(events) => events,
+        Err(e) => {
+            println!("Couldn't read {}: {:?}", path, e);
+            return 2;
+        },
+    };
+
+    let replay = recording::replay(&events, threshold);
+    for (step, dist) in replay.corrections.iter() {
+        println!("Corrected by {} blocks after step {}", dist, step);
+    }
+    match replay.divergence {
+        Some(d) => {
+            println!(
+                "Diverged at step {}: recorded at {}, replayed to {} ({} blocks away)",
+                d.step,
+                d.recorded,
+                d.replayed,
+                d.recorded.distance(d.replayed)
+            );
+            1
+        },
+        None => {
+            println!("Replayed {} steps without diverging", replay.steps);
+            0
+        },
+    }
+}
+
+// Load the world around spawn for a seed, generated here with no server, and say how long it took. Returns the exit
+// code.
+fn local(args: &[String]) -> i32 {
+    let seed = match args.get(0).map(|s| s.parse::<u64>()) {
+        Some(Ok(seed)) => seed,
+        _ => {
+            println!("Usage: headless --local <seed>");
+            return 2;
+        },
+    };
+
+    let start = Instant::now();
+    let client = Client::<Payloads>::local(
+        seed,
+        common::util::names::generate().to_string(),
+        gen_payload,
+        drop_payload,
+        Arc::new(NoAudio {}),
+        0,
+    );
+    while let Some(progress) = client.loading() {
+        debug!("Loaded {} of {} chunks", progress.loaded, progress.required);
+        thread::sleep(Duration::from_millis(10));
+    }
+    let spawn = client.player_entity().map(|player| *player.read().pos());
+    println!("Loaded the world around {:?} for seed {} in {:?}", spawn, seed, start.elapsed());
+    0
+}
+
 fn main() {
+    let args = env::args().collect::<Vec<_>>();
+    match args.get(1).map(|a| a.as_str()) {
+        Some("--replay") => process::exit(replay(&args[2..])),
+        Some("--local") => process::exit(local(&args[2..])),
+        _ => {},
+    }
+    // The player's movement can be recorded, to replay later
+    let record = match args.get(1).map(|a| a.as_str()) {
+        Some("--record") => match args.get(2) {
+            Some(path) => Some(Path::new(path)),
+            None => {
+                println!("Usage: headless --record <recording>");
+                process::exit(2);
+            },
+        },
+        _ => None,
+    };
+
     info!("Starting headless client...");
 
     let mut remote_addr = String::new();
@@ -67,20 +181,31 @@ fn main() {
         PlayMode::Headless,
         alias,
         &remote_addr.trim(),
+        Encryption::Preferred,
         gen_payload,
         drop_payload,
         Arc::new(NoAudio {}),
         0,
+        record,
     )
     .expect("error when attempting to initiate the client");
 
+    let events = client.subscribe();
+
     let mut win = Window::initscr();
     win.writeln("Welcome to the Veloren headless client.");
 
     loop {
-        for event in client.get_events() {
+        for event in events.drain() {
             match event {
-                ClientEvent::RecvChatMsg { text } => win.writeln(text),
+                ClientEvent::ChatReceived { text } => win.writeln(text),
+                ClientEvent::Kicked { reason } => win.writeln(format!("Disconnected: {}", reason)),
+                ClientEvent::ServerInfo { info } => {
+                    for line in info.motd.lines() {
+                        win.writeln(line);
+                    }
+                },
+                _ => {},
             }
         }
 
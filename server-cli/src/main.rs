@@ -1,18 +1,37 @@
 extern crate clap;
+#[macro_use]
+extern crate log;
 use clap::{App, Arg};
 
+// Standard
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+};
+
 // Project
-use server::{api::Api, net::DisconnectReason, player::Player, specs::Entity, Manager, Server};
+use common::{
+    net::Encryption,
+    util::logging::{self, FileSink, LogConfig},
+};
+use server::{
+    api::Api, block_tick::BlockTicks, config::ServerConfig, net::DisconnectReason, player::Player, specs::Entity, Error,
+    Manager, Server,
+};
+
+struct Payloads {
+    metrics_addr: Option<SocketAddr>,
+    encryption: Encryption,
+}
 
-struct Payloads;
 impl server::Payloads for Payloads {
     type Chunk = ();
     type Entity = ();
     type Client = ();
 
     fn on_player_connect(&self, api: &Api, player: Entity) {
-        println!(
-            "[INFO] {} connected",
+        info!(
+            "{} connected",
             api.world()
                 .read_storage::<Player>()
                 .get(player)
@@ -24,8 +43,8 @@ impl server::Payloads for Payloads {
     }
 
     fn on_player_disconnect(&self, api: &Api, player: Entity, reason: DisconnectReason) {
-        println!(
-            "[INFO] {} disconnected: {}",
+        info!(
+            "{} disconnected: {}",
             api.world()
                 .read_storage::<Player>()
                 .get(player)
@@ -38,9 +57,27 @@ impl server::Payloads for Payloads {
     fn on_chat_msg(&self, api: &Api, player: Entity, text: &str) -> Option<String> {
         let store = api.world().read_storage::<Player>();
         let alias = store.get(player).map(|p| p.alias.as_str()).unwrap_or("<none");
-        println!("[CHAT] {}: {}", alias, text);
+        info!(target: "chat", "{}: {}", alias, text);
         Some(format!("{}: {}", alias, text))
     }
+
+    fn console_enabled(&self) -> bool { true }
+
+    fn permissions_file(&self) -> Option<PathBuf> { Some(PathBuf::from("permissions.toml")) }
+
+    fn access_file(&self) -> Option<PathBuf> { Some(PathBuf::from("access.toml")) }
+
+    fn player_db_file(&self) -> Option<PathBuf> { Some(PathBuf::from("players.toml")) }
+
+    fn physics_file(&self) -> Option<PathBuf> { Some(PathBuf::from("physics.toml")) }
+
+    fn recipes_file(&self) -> Option<PathBuf> { Some(PathBuf::from("recipes.toml")) }
+
+    fn metrics_addr(&self) -> Option<SocketAddr> { self.metrics_addr }
+
+    fn encryption(&self) -> Encryption { self.encryption }
+
+    fn block_ticks(&self) -> BlockTicks { BlockTicks::grass() }
 }
 
 fn main() {
@@ -52,25 +89,90 @@ fn main() {
             .as_str(),
         )
         .arg(
-            Arg::with_name("addr")
-                .short("a")
-                .long("address")
-                .value_name("ADDR")
-                .help("Sets the listening address")
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("PATH")
+                .help("Sets where the server config is read from, writing the defaults there if there's nothing yet")
                 .takes_value(true)
-                .default_value("0.0.0.0"),
+                .default_value("server.toml"),
         )
         .arg(
-            Arg::with_name("port")
-                .short("p")
-                .long("port")
+            Arg::with_name("metrics-port")
+                .long("metrics-port")
                 .value_name("PORT")
-                .help("Sets the listening port")
+                .help("Serves metrics for scraping over HTTP on this port")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("encryption")
+                .long("encryption")
+                .value_name("MODE")
+                .help("Sets whether connections are encrypted")
+                .takes_value(true)
+                .possible_values(&["off", "preferred", "required"])
+                .default_value("off"),
+        )
+        .arg(
+            Arg::with_name("log")
+                .long("log")
+                .value_name("FILTERS")
+                .help("Sets log levels, e.g. 'warn,server=info'. Overrides VELOREN_LOG and RUST_LOG.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .value_name("PATH")
+                .help("Sets where to write the log, which is rotated as it grows")
                 .takes_value(true)
-                .default_value("59003"),
+                .default_value("server.log"),
         )
         .get_matches();
-    let addr = args.value_of("addr").unwrap().to_owned() + ":" + args.value_of("port").unwrap(); //safe because of default_value
-    println!("[INFO] Starting server on {}", addr);
-    Manager::await_shutdown(Server::<Payloads>::new(Payloads, addr).expect("Could not start server"));
+
+    let mut log_config = LogConfig::from_env().with_file(FileSink::new(args.value_of("log-file").unwrap()));
+    if let Some(filters) = args.value_of("log") {
+        log_config = log_config.with_filters(filters);
+    }
+    logging::init(log_config).expect("Could not open the log file");
+
+    let config = match ServerConfig::load(PathBuf::from(args.value_of("config").unwrap())) {
+        Ok(config) => config,
+        Err(e) => {
+            match e {
+                Error::InvalidConfig(msg) => error!("The server config won't work: {}", msg),
+                Error::TomlDeErr(e) => error!("Could not read the server config: {}", e),
+                e => error!("Could not load the server config: {}", e),
+            }
+            return;
+        },
+    };
+    info!("Starting server on {}:{}", config.address, config.port);
+    let metrics_addr = args.value_of("metrics-port").map(|port| {
+        (config.address.clone() + ":" + port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .expect("Invalid metrics port")
+    });
+    if let Some(addr) = metrics_addr {
+        info!("Serving metrics on http://{}/metrics", addr);
+    }
+    info!("Generating the world from seed {}", config.world_seed);
+    let encryption = match args.value_of("encryption").unwrap() {
+        "preferred" => Encryption::Preferred,
+        "required" => Encryption::Required,
+        _ => Encryption::Off,
+    };
+    println!("Type 'help' for a list of console commands");
+    Manager::await_shutdown(
+        Server::with_config(
+            Payloads {
+                metrics_addr,
+                encryption,
+            },
+            config,
+        )
+        .expect("Could not start server"),
+    );
 }
@@ -0,0 +1,301 @@
+// Standard
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, Read, Write},
+    net::IpAddr,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Library
+use serde_derive::{Deserialize, Serialize};
+
+// Local
+use crate::Error;
+
+/// Why, and for how long, a player isn't allowed to connect
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Ban {
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// When the ban is over, in seconds since the Unix epoch. Without one, the ban lasts until it's lifted.
+    #[serde(default)]
+    pub until: Option<u64>,
+    /// The address the player was connecting from when they were banned. Anyone connecting from it is turned away
+    /// too, since a new alias is only a reconnect away.
+    #[serde(default)]
+    pub ip: Option<IpAddr>,
+}
+
+impl Ban {
+    pub fn is_over(&self, now: u64) -> bool { self.until.map_or(false, |until| until <= now) }
+
+    // What a banned player is told when they're turned away
+    fn message(&self, now: u64) -> String {
+        let mut msg = match self.until {
+            Some(until) => format!(
+                "You are banned from this server for another {}",
+                describe_duration(Duration::from_secs(until.saturating_sub(now)))
+            ),
+            None => "You are banned from this server".to_string(),
+        };
+        if let Some(reason) = &self.reason {
+            msg += &format!(": {}", reason);
+        }
+        msg
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct AccessFile {
+    #[serde(default)]
+    whitelisting: bool,
+    #[serde(default)]
+    whitelist: BTreeSet<String>,
+    #[serde(default)]
+    bans: BTreeMap<String, Ban>,
+}
+
+/// Who may connect: everyone but those banned, or while whitelisting is on, only those on the whitelist.
+///
+/// Like permissions, this is keyed by alias until accounts exist, so bans also remember the banned player's address.
+pub struct Access {
+    path: Option<PathBuf>,
+    file: AccessFile,
+}
+
+impl Access {
+    /// Access that's only kept in memory
+    pub fn new() -> Access {
+        Access {
+            path: None,
+            file: AccessFile::default(),
+        }
+    }
+
+    /// Load the whitelist and bans from a file, which changes are written back to. A missing file means anyone may
+    /// connect.
+    pub fn load(path: PathBuf) -> Result<Access, Error> {
+        let file = match File::open(&path) {
+            Ok(mut file) => {
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                Access::parse(&content)?
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => AccessFile::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Access { path: Some(path), file })
+    }
+
+    fn parse(content: &str) -> Result<AccessFile, Error> { Ok(toml::from_str(content)?) }
+
+    fn to_toml(&self) -> Result<String, Error> { Ok(toml::to_string(&self.file)?) }
+
+    /// Whether someone connecting as `alias` from `ip` may join at `now`, in seconds since the Unix epoch. If they
+    /// may not, returns what to tell them.
+    pub fn check(&self, alias: &str, ip: Option<IpAddr>, now: u64) -> Result<(), String> {
+        let by_alias = self.file.bans.get(alias);
+        let by_ip = || self.file.bans.values().find(|ban| ip.is_some() && ban.ip == ip && !ban.is_over(now));
+        if let Some(ban) = by_alias.filter(|ban| !ban.is_over(now)).or_else(by_ip) {
+            return Err(ban.message(now));
+        }
+
+        if self.file.whitelisting && !self.file.whitelist.contains(alias) {
+            return Err("This server is whitelisted, and you're not on the whitelist".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn is_whitelisting(&self) -> bool { self.file.whitelisting }
+
+    /// The aliases on the whitelist, in order
+    pub fn whitelisted<'a>(&'a self) -> impl Iterator<Item = &'a str> + 'a {
+        self.file.whitelist.iter().map(|alias| alias.as_str())
+    }
+
+    /// Turn whitelisting on or off. Players who are already online aren't affected.
+    pub fn set_whitelisting(&mut self, on: bool) -> Result<(), Error> {
+        self.file.whitelisting = on;
+        self.save()
+    }
+
+    /// Add an alias to the whitelist, or take it off. Returns whether that changed anything.
+    pub fn set_whitelisted(&mut self, alias: &str, listed: bool) -> Result<bool, Error> {
+        let changed = if listed {
+            self.file.whitelist.insert(alias.to_string())
+        } else {
+            self.file.whitelist.remove(alias)
+        };
+        self.save()?;
+        Ok(changed)
+    }
+
+    /// Ban an alias, replacing any ban it already has
+    pub fn ban(&mut self, alias: &str, ban: Ban) -> Result<(), Error> {
+        self.file.bans.insert(alias.to_string(), ban);
+        self.save()
+    }
+
+    /// Lift an alias's ban, along with its address. Returns whether the alias was banned.
+    pub fn unban(&mut self, alias: &str) -> Result<bool, Error> {
+        let banned = self.file.bans.remove(alias).map_or(false, |ban| !ban.is_over(unix_now()));
+        self.save()?;
+        Ok(banned)
+    }
+
+    // Bans that have run out are forgotten whenever the file is written, so it doesn't grow forever
+    fn save(&mut self) -> Result<(), Error> {
+        let now = unix_now();
+        let bans = std::mem::replace(&mut self.file.bans, BTreeMap::new());
+        self.file.bans = bans.into_iter().filter(|(_, ban)| !ban.is_over(now)).collect();
+
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        File::create(path)?.write_all(self.to_toml()?.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// The current time, in seconds since the Unix epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// A length of time in its largest whole unit, rounded up, like "3 hours"
+pub fn describe_duration(duration: Duration) -> String {
+    const UNITS: [(&str, u64); 5] = [
+        ("week", 7 * 24 * 60 * 60),
+        ("day", 24 * 60 * 60),
+        ("hour", 60 * 60),
+        ("minute", 60),
+        ("second", 1),
+    ];
+    let secs = duration.as_secs().max(1);
+    let (name, size) = UNITS.iter().find(|(_, size)| secs >= *size).cloned().unwrap_or(UNITS[4]);
+    let count = (secs + size - 1) / size;
+    format!("{} {}{}", count, name, if count == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> Option<IpAddr> { Some(addr.parse().unwrap()) }
+
+    #[test]
+    fn bans_are_checked_by_alias_and_address() {
+        let mut access = Access::new();
+        let ban = Ban {
+            reason: Some("Flattening the spawn".to_string()),
+            until: None,
+            ip: ip("10.0.0.1"),
+        };
+        access.ban("griefer", ban).unwrap();
+
+        let refused = "You are banned from this server: Flattening the spawn".to_string();
+        assert_eq!(access.check("griefer", None, 0), Err(refused.clone()));
+        // A new alias from the same address doesn't get round it
+        assert_eq!(access.check("newcomer", ip("10.0.0.1"), 0), Err(refused));
+        assert_eq!(access.check("newcomer", ip("10.0.0.2"), 0), Ok(()));
+        assert_eq!(access.check("newcomer", None, 0), Ok(()));
+
+        assert!(access.unban("griefer").unwrap());
+        assert!(!access.unban("griefer").unwrap());
+        assert_eq!(access.check("griefer", ip("10.0.0.1"), 0), Ok(()));
+    }
+
+    #[test]
+    fn bans_run_out() {
+        let mut access = Access::new();
+        let now = unix_now();
+        let ban = Ban {
+            until: Some(now + 90 * 60),
+            ip: ip("10.0.0.1"),
+            ..Ban::default()
+        };
+        access.ban("griefer", ban).unwrap();
+
+        let refused = "You are banned from this server for another 2 hours".to_string();
+        assert_eq!(access.check("griefer", None, now), Err(refused.clone()));
+        assert_eq!(access.check("other", ip("10.0.0.1"), now + 60), Err(refused));
+        assert_eq!(access.check("griefer", None, now + 90 * 60), Ok(()));
+        assert_eq!(access.check("other", ip("10.0.0.1"), now + 90 * 60), Ok(()));
+
+        // Bans that are over are dropped the next time anything changes
+        let old = Ban {
+            until: Some(now - 1),
+            ..Ban::default()
+        };
+        access.ban("reformed", old).unwrap();
+        access.set_whitelisting(false).unwrap();
+        assert!(!access.file.bans.contains_key("reformed"));
+        assert!(access.file.bans.contains_key("griefer"));
+    }
+
+    #[test]
+    fn only_the_whitelisted_get_in_while_whitelisting() {
+        let mut access = Access::new();
+        assert!(access.set_whitelisted("friend", true).unwrap());
+        assert!(!access.set_whitelisted("friend", true).unwrap());
+        // The whitelist does nothing until it's turned on
+        assert_eq!(access.check("stranger", None, 0), Ok(()));
+
+        access.set_whitelisting(true).unwrap();
+        assert_eq!(access.check("friend", None, 0), Ok(()));
+        assert!(access.check("stranger", None, 0).is_err());
+        // Being whitelisted doesn't get round a ban
+        access.ban("friend", Ban::default()).unwrap();
+        assert!(access.check("friend", None, 0).is_err());
+
+        assert!(access.set_whitelisted("friend", false).unwrap());
+        assert_eq!(access.whitelisted().count(), 0);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let path = std::env::temp_dir().join(format!("veloren-access-{}.toml", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        // A missing file is fine, and is created on the first change
+        let mut access = Access::load(path.clone()).unwrap();
+        assert_eq!(access.check("griefer", None, 0), Ok(()));
+        access.set_whitelisted("friend", true).unwrap();
+        access.set_whitelisting(true).unwrap();
+        let ban = Ban {
+            reason: Some("Spamming".to_string()),
+            until: Some(unix_now() + 3600),
+            ip: ip("::1"),
+        };
+        access.ban("griefer", ban).unwrap();
+
+        let loaded = Access::load(path.clone()).unwrap();
+        assert_eq!(loaded.file, access.file);
+        assert!(loaded.is_whitelisting());
+        assert_eq!(loaded.whitelisted().collect::<Vec<_>>(), vec!["friend"]);
+        assert_eq!(Access::parse("").unwrap(), AccessFile::default());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn durations_are_described_in_their_largest_unit() {
+        assert_eq!(describe_duration(Duration::from_secs(0)), "1 second");
+        assert_eq!(describe_duration(Duration::from_secs(45)), "45 seconds");
+        assert_eq!(describe_duration(Duration::from_secs(60)), "1 minute");
+        assert_eq!(describe_duration(Duration::from_secs(61)), "2 minutes");
+        assert_eq!(describe_duration(Duration::from_secs(3 * 24 * 60 * 60)), "3 days");
+        assert_eq!(describe_duration(Duration::from_secs(14 * 24 * 60 * 60)), "2 weeks");
+    }
+}
@@ -1,35 +1,139 @@
+// Standard
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
 // Library
-use specs::{prelude::*, saveload::Marker};
+use rand;
+use specs::{
+    prelude::*,
+    saveload::{MarkedBuilder, Marker},
+};
+use vek::*;
 
 // Project
-use common::{ecs::net::UidMarker, util::msg::ServerMsg};
+use common::{
+    audio::SoundId,
+    ecs::{
+        character::Health,
+        net::UidMarker,
+        phys::{Pos, Vel},
+        CreateUtil,
+    },
+    terrain::{chunk::Block, VoxAbs},
+    util::msg::{ServerMsg, SessionKind},
+};
 
 // Local
 use crate::{
+    cmd::{Cmd, CmdHandler, Sender},
     net::{Client, DisconnectReason},
+    permission::Permission,
     player::Player,
+    schedule::TaskHandle,
+    sys::{hurt, Dead, Outbox, PosHistory, Projectile, ProjectileSpec, Wander, HISTORY_LENGTH},
     Payloads, Server,
 };
 
+// Constants
+// How fast non-player characters walk about, in blocks per second
+const NPC_SPEED: f32 = 2.0;
+
+/// What payloads can do with the server. Every method is called with the server locked, exclusively for `&mut self`
+/// methods, but only shared for `&self` ones, which may be running on several threads at once. Payload hooks are handed
+/// an `&dyn Api`, so anything they change that needs the server to itself is queued up for the start of the next tick.
 pub trait Api {
     fn disconnect_player(&mut self, player: Entity, reason: DisconnectReason);
+    /// Like `disconnect_player`, but the player is disconnected at the start of the next tick
+    fn disconnect(&self, player: Entity, reason: DisconnectReason);
     fn send_chat_msg(&self, player: Entity, text: &str);
     fn send_net_msg(&self, player: Entity, msg: ServerMsg);
     fn broadcast_chat_msg(&self, text: &str);
     fn broadcast_net_msg(&self, msg: ServerMsg);
+    /// Send a command's output back to whoever ran it
+    fn reply(&self, sender: Sender, text: &str);
 
     fn world(&self) -> &World;
     fn world_mut(&mut self) -> &mut World;
 
     fn is_valid_alias(&self, alias: &str) -> bool;
+    fn permission_of(&self, entity: Entity) -> Permission;
+
+    /// Every connected player. Players whose connection dropped aren't included, even while they're kept around in case
+    /// they reconnect.
+    fn players(&self) -> Vec<Entity>;
+
+    /// Every entity with a position no further than `radius` blocks from `pos`
+    fn entities_in_radius(&self, pos: Vec3<f32>, radius: f32) -> Vec<Entity>;
+
+    /// Where entities were `ago` before the last tick, for checking hits against where an attacker saw things rather
+    /// than where they are now. Positions are only remembered for `HISTORY_LENGTH`, so looking further back than that
+    /// gives the oldest there is. Entities that had no position as of the last tick are left out.
+    fn entities_at(&self, ago: Duration) -> HashMap<Entity, Vec3<f32>>;
+
+    /// Change a block at the start of the next tick, and let clients know. Changes to chunks that aren't loaded by then
+    /// are dropped.
+    fn set_block(&self, pos: Vec3<VoxAbs>, block: Block);
+
+    /// Move an entity, overriding its client's own idea of where it is. If the destination chunk isn't loaded, it's
+    /// generated first so the entity doesn't fall through the world, and a destination inside solid terrain is moved up
+    /// to the surface, and one beyond the world border is brought back inside it. Returns `false` if the entity has no
+    /// position or `pos` isn't finite.
+    fn set_entity_pos(&mut self, entity: Entity, pos: Vec3<f32>) -> bool;
+
+    /// Launch a projectile from `origin`. It flies under gravity until it hits terrain or something with health, or its
+    /// lifetime runs out. It can't hit its owner until it's had a moment to get clear of them. A player's projectiles
+    /// hit things where the player saw them, allowing for their latency.
+    fn fire_projectile(
+        &mut self,
+        owner: Entity,
+        origin: Vec3<f32>,
+        velocity: Vec3<f32>,
+        spec: ProjectileSpec,
+    ) -> Entity;
+
+    /// Create a character at `pos` that wanders about by itself
+    fn spawn_npc(&mut self, name: &str, pos: Vec3<f32>) -> Entity;
+
+    /// Take `amount` from an entity's health. If it runs out, the entity dies, and if it's a player they're shown
+    /// `cause` until they respawn. Returns whether it died. Entities without health, or that are dead already, aren't
+    /// hurt.
+    fn damage(&mut self, entity: Entity, amount: u32, cause: &str) -> bool;
+
+    /// Have the players in hearing range of `pos` hear a sound there. Players too far away aren't sent it at all.
+    fn play_sound_at(&self, sound: SoundId, pos: Vec3<f32>, volume: f32);
+
+    /// Run `f` at the start of the first tick after `delay` has passed. Time is counted in ticks, not by the wall
+    /// clock, so a slow server runs tasks late but never out of step with everything else. Tasks due on the same tick
+    /// run in the order they were scheduled.
+    fn run_in(&self, delay: Duration, f: Box<dyn FnOnce(&dyn Api) + Send>);
+    /// Run `f` every `interval` of tick time, at most once a tick, until the handle returned is cancelled
+    fn run_every(&self, interval: Duration, f: Box<dyn FnMut(&dyn Api) + Send>) -> TaskHandle;
+
+    /// Add a command, run by `handler` once its arguments have been checked against `cmd`'s usages. Players who can use
+    /// it are sent it for completion, and it's listed by `help`. Returns `false` if its name or one of its aliases is
+    /// already taken.
+    fn register_command(&self, cmd: Cmd, handler: CmdHandler) -> bool;
 }
 
 impl<P: Payloads> Api for Server<P> {
     fn disconnect_player(&mut self, player: Entity, reason: DisconnectReason) {
-        // Stop the postoffice
+        // Stop the postoffice, first telling the client why unless it asked to leave
         if let Some(client) = self.world.read_storage::<Client>().get(player) {
+            match reason {
+                DisconnectReason::Kicked(_) | DisconnectReason::Shutdown | DisconnectReason::Flooding => {
+                    let _ = client
+                        .postoffice
+                        .create_postbox(SessionKind::Disconnect)
+                        .send(ServerMsg::Disconnect {
+                            reason: reason.to_string(),
+                        });
+                },
+                _ => {},
+            }
             let _ = client.postoffice.stop(); // We don't care if this fails
         }
+        self.suspended.remove(&player);
+
+        self.save_player(player, true);
 
         if let Some(player_comp) = self.world.read_storage::<Player>().get(player) {
             self.broadcast_chat_msg(&format!("[{} disconnected: {}]", player_comp.alias, reason));
@@ -43,6 +147,8 @@ impl<P: Payloads> Api for Server<P> {
         let _ = self.world.delete_entity(player);
     }
 
+    fn disconnect(&self, player: Entity, reason: DisconnectReason) { self.disconnects.lock().push((player, reason)); }
+
     fn send_chat_msg(&self, player: Entity, text: &str) {
         self.send_net_msg(player, ServerMsg::ChatMsg { text: text.to_string() });
     }
@@ -64,9 +170,131 @@ impl<P: Payloads> Api for Server<P> {
         }
     }
 
+    fn reply(&self, sender: Sender, text: &str) {
+        match sender {
+            Sender::Player(player) => self.send_chat_msg(player, text),
+            Sender::Console => println!("{}", text),
+        }
+    }
+
     fn world(&self) -> &World { &self.world }
 
     fn world_mut(&mut self) -> &mut World { &mut self.world }
 
     fn is_valid_alias(&self, alias: &str) -> bool { alias.len() > 0 }
+
+    fn permission_of(&self, entity: Entity) -> Permission {
+        self.world
+            .read_storage::<Permission>()
+            .get(entity)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn players(&self) -> Vec<Entity> {
+        (
+            &self.world.entities(),
+            &self.world.read_storage::<Player>(),
+            &self.world.read_storage::<Client>(),
+        )
+            .join()
+            .map(|(entity, _, _)| entity)
+            .collect()
+    }
+
+    fn entities_in_radius(&self, pos: Vec3<f32>, radius: f32) -> Vec<Entity> {
+        (&self.world.entities(), &self.world.read_storage::<Pos>())
+            .join()
+            .filter(|(_, p)| p.0.distance(pos) <= radius)
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    fn entities_at(&self, ago: Duration) -> HashMap<Entity, Vec3<f32>> {
+        self.world.read_resource::<PosHistory>().entities_at(ago.min(HISTORY_LENGTH))
+    }
+
+    fn set_block(&self, pos: Vec3<VoxAbs>, block: Block) { self.block_changes.lock().push((pos, block)); }
+
+    fn set_entity_pos(&mut self, entity: Entity, pos: Vec3<f32>) -> bool {
+        if !pos.map(|e| e.is_finite()).reduce_and() || self.world.read_storage::<Pos>().get(entity).is_none() {
+            return false;
+        }
+
+        let pos = self.inside_border(pos);
+        match self.find_surface(pos) {
+            Ok(pos) => self.teleport_now(entity, pos),
+            Err(chunk) => {
+                self.request_chunk(chunk);
+                self.teleports.insert(entity, pos);
+                true
+            },
+        }
+    }
+
+    fn fire_projectile(
+        &mut self,
+        owner: Entity,
+        origin: Vec3<f32>,
+        velocity: Vec3<f32>,
+        spec: ProjectileSpec,
+    ) -> Entity {
+        // A player's projectiles hit things where they saw them, which was about a round trip ago
+        let latency = self.world.read_storage::<Client>().get(owner).and_then(|c| c.latency);
+        let mut projectile = Projectile::new(owner, spec);
+        projectile.rewind = latency.unwrap_or_default().min(HISTORY_LENGTH);
+
+        // Clients find out about the projectile when entities are next synced
+        self.world
+            .create_entity()
+            .with(Pos(origin))
+            .with(Vel(velocity))
+            .with(projectile)
+            .marked::<UidMarker>()
+            .build()
+    }
+
+    fn spawn_npc(&mut self, name: &str, pos: Vec3<f32>) -> Entity {
+        // Clients find out about the character when entities are next synced
+        self.world
+            .create_character(name.to_string())
+            .with(Pos(pos))
+            .with(Wander::new(NPC_SPEED, rand::random()))
+            .build()
+    }
+
+    fn damage(&mut self, entity: Entity, amount: u32, cause: &str) -> bool {
+        // Clients find out when the outbox is next sent
+        hurt(
+            entity,
+            amount,
+            cause,
+            &mut self.world.write_storage::<Health>(),
+            &mut self.world.write_storage::<Dead>(),
+            &self.world.read_storage::<UidMarker>(),
+            &self.world.read_resource::<Outbox>(),
+        )
+    }
+
+    fn play_sound_at(&self, sound: SoundId, pos: Vec3<f32>, volume: f32) {
+        // Clients hear it when the outbox is next sent
+        self.world.read_resource::<Outbox>().play_sound(sound, pos, volume);
+    }
+
+    fn run_in(&self, delay: Duration, f: Box<dyn FnOnce(&dyn Api) + Send>) { self.scheduler.lock().run_in(delay, f); }
+
+    fn run_every(&self, interval: Duration, f: Box<dyn FnMut(&dyn Api) + Send>) -> TaskHandle {
+        self.scheduler.lock().run_every(interval, f)
+    }
+
+    fn register_command(&self, mut cmd: Cmd, handler: CmdHandler) -> bool {
+        cmd.handler = Some(Arc::from(handler));
+        if !self.commands.write().register(cmd) {
+            return false;
+        }
+        for player in self.players() {
+            self.send_cmd_list(player);
+        }
+        true
+    }
 }
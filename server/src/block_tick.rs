@@ -0,0 +1,175 @@
+// Standard
+use std::collections::HashSet;
+
+// Library
+use specs::Join;
+use vek::*;
+
+// Project
+use common::terrain::{
+    chunk::{Block, CHUNK_SIZE},
+    voloffs_to_voxabs, VolOffs, VoxAbs, Voxel,
+};
+
+// Local
+use crate::{net::Client, sys::LoadedChunks, Payloads, Server};
+
+/// What a block picked to be ticked turns into, given where it is and the chunks around it. `None` leaves it be.
+pub type BlockTickRule = fn(&LoadedChunks, Vec3<VoxAbs>) -> Option<Block>;
+
+/// How blocks change by themselves over time. Each tick, some blocks are picked at random from every chunk a player
+/// has, and the rules for what they are decide what becomes of them.
+#[derive(Clone)]
+pub struct BlockTicks {
+    rules: Vec<(Block, BlockTickRule)>,
+    /// How many blocks are picked from each chunk each tick. More makes the world change faster, at the cost of CPU.
+    pub blocks_per_chunk: usize,
+    /// Decides which blocks are picked, so that the same chunk on the same tick gets the same blocks for a given seed.
+    /// The server uses the world's seed if it's left unset.
+    pub seed: Option<u64>,
+}
+
+impl Default for BlockTicks {
+    /// No rules, so nothing changes
+    fn default() -> Self {
+        Self {
+            rules: vec![],
+            blocks_per_chunk: 3,
+            seed: None,
+        }
+    }
+}
+
+impl BlockTicks {
+    /// Grass spreads onto earth out under the sky, and dies back to earth under anything solid
+    pub fn grass() -> Self {
+        let mut ticks = BlockTicks::default();
+        ticks.register_block_tick(Block::EARTH, spread_grass);
+        ticks.register_block_tick(Block::GRASS, smother_grass);
+        ticks
+    }
+
+    /// Have `rule` decide what becomes of `block` when it's ticked. Rules for the same block are tried in the order
+    /// they were registered, until one changes it.
+    pub fn register_block_tick(&mut self, block: Block, rule: BlockTickRule) { self.rules.push((block, rule)); }
+
+    /// The changes ticking `chunk` makes on the `tick`th tick, found from the chunks as they were before any of them
+    pub fn tick_chunk(&self, chunks: &LoadedChunks, chunk: Vec3<VolOffs>, tick: u64) -> Vec<(Vec3<VoxAbs>, Block)> {
+        let mut changes = vec![];
+        if self.rules.is_empty() || !chunks.0.contains_key(&chunk) {
+            return changes;
+        }
+
+        let mut rng = ChunkRng::new(self.seed.unwrap_or(0), chunk, tick);
+        let origin = voloffs_to_voxabs(chunk, CHUNK_SIZE);
+        for _ in 0..self.blocks_per_chunk {
+            let pos = origin + CHUNK_SIZE.map(|e| (rng.next() % e as u64) as VoxAbs);
+            let block = match chunks.block_at(pos) {
+                Ok(block) => block,
+                Err(_) => continue,
+            };
+            let new = self
+                .rules
+                .iter()
+                .filter(|(of, _)| *of == block)
+                .filter_map(|(_, rule)| rule(chunks, pos))
+                .find(|new| *new != block);
+            if let Some(new) = new {
+                changes.push((pos, new));
+            }
+        }
+        changes
+    }
+}
+
+// splitmix64, seeded from the world's seed, the chunk and the tick
+struct ChunkRng(u64);
+
+impl ChunkRng {
+    fn new(seed: u64, chunk: Vec3<VolOffs>, tick: u64) -> Self {
+        let mut rng = ChunkRng(seed);
+        for e in [chunk.x as u64, chunk.y as u64, chunk.z as u64, tick].iter() {
+            let mixed = rng.next() ^ *e;
+            rng.0 ^= mixed;
+        }
+        rng
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+// Earth next to grass grows grass of its own, if there's air above it and nothing solid further up. Chunks above that
+// aren't loaded are taken to be open sky.
+fn spread_grass(chunks: &LoadedChunks, pos: Vec3<VoxAbs>) -> Option<Block> {
+    if chunks.block_at(pos + Vec3::unit_z()) != Ok(Block::AIR) {
+        return None;
+    }
+    let mut near_grass = false;
+    for x in -1..2 {
+        for y in -1..2 {
+            for z in -1..2 {
+                near_grass |= chunks.block_at(pos + Vec3::new(x, y, z)) == Ok(Block::GRASS);
+            }
+        }
+    }
+    if !near_grass {
+        return None;
+    }
+    let mut above = pos + Vec3::unit_z() * 2;
+    while let Ok(block) = chunks.block_at(above) {
+        if block.is_solid() {
+            return None;
+        }
+        above.z += 1;
+    }
+    Some(Block::GRASS)
+}
+
+// Grass with something solid on top of it dies back to earth
+fn smother_grass(chunks: &LoadedChunks, pos: Vec3<VoxAbs>) -> Option<Block> {
+    match chunks.block_at(pos + Vec3::unit_z()) {
+        Ok(block) if block.is_solid() => Some(Block::EARTH),
+        _ => None,
+    }
+}
+
+/// What block ticking keeps track of between ticks
+pub(crate) struct BlockTicker {
+    ticks: BlockTicks,
+    tick: u64,
+}
+
+impl BlockTicker {
+    pub(crate) fn new(mut ticks: BlockTicks, world_seed: u64) -> Self {
+        ticks.seed = ticks.seed.or(Some(world_seed));
+        Self { ticks, tick: 0 }
+    }
+}
+
+impl<P: Payloads> Server<P> {
+    /// Tick blocks in every loaded chunk a player has. What changes goes out the way changes asked for through the
+    /// `Api` do, so it has to be done before those are applied.
+    pub(crate) fn tick_blocks(&mut self) {
+        let tick = self.block_ticker.tick;
+        self.block_ticker.tick += 1;
+
+        let known = self
+            .world
+            .read_storage::<Client>()
+            .join()
+            .flat_map(|client| client.known_chunks.keys().cloned().collect::<Vec<_>>())
+            .collect::<HashSet<_>>();
+        let chunks = self.world.read_resource::<LoadedChunks>();
+        let changes = known
+            .into_iter()
+            .flat_map(|chunk| self.block_ticker.ticks.tick_chunk(&chunks, chunk, tick))
+            .collect::<Vec<_>>();
+        self.block_changes.lock().extend(changes);
+    }
+}
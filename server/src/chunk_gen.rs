@@ -0,0 +1,253 @@
+// Standard
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// Library
+use parking_lot::{Condvar, Mutex};
+use vek::*;
+
+// Project
+use common::terrain::{chunk::Chunk, VolOffs};
+
+// Local
+use crate::world_crate::Generator;
+
+// Constants
+pub const DEFAULT_WORKERS: usize = 4;
+const GEN_TIME_SAMPLES: usize = 512;
+
+#[derive(Default)]
+struct Queue {
+    order: VecDeque<Vec3<VolOffs>>,
+    // Chunks waiting for a worker. Removing a position from here cancels it.
+    queued: HashSet<Vec3<VolOffs>>,
+    // Chunks currently being generated by a worker
+    in_flight: HashSet<Vec3<VolOffs>>,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    cvar: Condvar,
+    running: AtomicBool,
+}
+
+/// Generates chunks on a pool of worker threads. Requests are deduplicated, and finished chunks are collected with
+/// `poll`.
+pub struct ChunkGenPool {
+    shared: Arc<Shared>,
+    finished: Mutex<Receiver<(Vec3<VolOffs>, Chunk, Duration)>>,
+    workers: Vec<JoinHandle<()>>,
+    gen_times: VecDeque<Duration>,
+}
+
+impl ChunkGenPool {
+    pub fn new(workers: usize, generator: Arc<Generator>) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            cvar: Condvar::new(),
+            running: AtomicBool::new(true),
+        });
+        let (tx, rx) = channel();
+
+        Self {
+            workers: (0..workers.max(1))
+                .map(|_| {
+                    let (shared, tx, generator) = (shared.clone(), tx.clone(), generator.clone());
+                    thread::spawn(move || Self::work(shared, tx, &generator))
+                })
+                .collect(),
+            shared,
+            finished: Mutex::new(rx),
+            gen_times: VecDeque::with_capacity(GEN_TIME_SAMPLES),
+        }
+    }
+
+    fn work(shared: Arc<Shared>, tx: Sender<(Vec3<VolOffs>, Chunk, Duration)>, generator: &Generator) {
+        loop {
+            let pos = {
+                let mut queue = shared.queue.lock();
+                loop {
+                    if !shared.running.load(Ordering::Relaxed) {
+                        return;
+                    }
+
+                    // Skip over anything that was cancelled while waiting
+                    match queue.order.pop_front() {
+                        Some(pos) if queue.queued.remove(&pos) => {
+                            queue.in_flight.insert(pos);
+                            break pos;
+                        },
+                        Some(_) => {},
+                        None => shared.cvar.wait(&mut queue),
+                    }
+                }
+            };
+
+            let start = Instant::now();
+            let chunk = generator.gen_chunk(pos);
+            let elapsed = start.elapsed();
+
+            // The chunk stays in flight until it's been polled so that it can't be requested again in the meantime
+            if tx.send((pos, chunk, elapsed)).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Queue a chunk for generation. Returns false if it's already queued or being generated.
+    pub fn request(&self, pos: Vec3<VolOffs>) -> bool {
+        let mut queue = self.shared.queue.lock();
+        if queue.in_flight.contains(&pos) || !queue.queued.insert(pos) {
+            return false;
+        }
+        queue.order.push_back(pos);
+        self.shared.cvar.notify_one();
+        true
+    }
+
+    /// Drop a queued request. Returns false if the chunk wasn't queued or a worker has already started on it.
+    pub fn cancel(&self, pos: Vec3<VolOffs>) -> bool { self.shared.queue.lock().queued.remove(&pos) }
+
+    pub fn is_pending(&self, pos: Vec3<VolOffs>) -> bool {
+        let queue = self.shared.queue.lock();
+        queue.queued.contains(&pos) || queue.in_flight.contains(&pos)
+    }
+
+    pub fn queue_depth(&self) -> usize { self.shared.queue.lock().queued.len() }
+    pub fn in_flight(&self) -> usize { self.shared.queue.lock().in_flight.len() }
+
+    /// Collect all chunks that have finished generating since the last call
+    pub fn poll(&mut self) -> Vec<(Vec3<VolOffs>, Chunk)> {
+        let finished = self.finished.lock().try_iter().collect::<Vec<_>>();
+
+        let mut queue = self.shared.queue.lock();
+        for (pos, _, _) in &finished {
+            queue.in_flight.remove(pos);
+        }
+        drop(queue);
+
+        finished
+            .into_iter()
+            .map(|(pos, chunk, elapsed)| {
+                if self.gen_times.len() >= GEN_TIME_SAMPLES {
+                    self.gen_times.pop_front();
+                }
+                self.gen_times.push_back(elapsed);
+                (pos, chunk)
+            })
+            .collect()
+    }
+
+    /// Generation time percentile (0.0 - 1.0) over recently generated chunks
+    pub fn gen_time_percentile(&self, p: f32) -> Option<Duration> {
+        let mut times = self.gen_times.iter().cloned().collect::<Vec<_>>();
+        times.sort();
+        let idx = ((times.len() as f32 - 1.0) * p.max(0.0).min(1.0)).round() as usize;
+        times.get(idx).cloned()
+    }
+}
+
+impl Drop for ChunkGenPool {
+    fn drop(&mut self) {
+        {
+            // Hold the queue lock so no worker can miss the wakeup between checking `running` and waiting
+            let _queue = self.shared.queue.lock();
+            self.shared.running.store(false, Ordering::Relaxed);
+            self.shared.cvar.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_crate::GenConfig;
+
+    // A pool without workers, so that requests stay queued for as long as a test needs them to
+    fn idle_pool() -> ChunkGenPool {
+        let (_, rx) = channel();
+        ChunkGenPool {
+            shared: Arc::new(Shared {
+                queue: Mutex::new(Queue::default()),
+                cvar: Condvar::new(),
+                running: AtomicBool::new(true),
+            }),
+            finished: Mutex::new(rx),
+            workers: Vec::new(),
+            gen_times: VecDeque::new(),
+        }
+    }
+
+    #[test]
+    fn requests_are_deduplicated() {
+        let pool = idle_pool();
+        let pos = Vec3::new(1, 2, 3);
+        assert!(pool.request(pos));
+        assert!(!pool.request(pos));
+        assert!(pool.request(Vec3::new(1, 2, 4)));
+        assert_eq!(pool.queue_depth(), 2);
+        assert!(pool.is_pending(pos));
+    }
+
+    #[test]
+    fn cancelled_requests_are_skipped() {
+        let pool = idle_pool();
+        let pos = Vec3::new(0, 0, 0);
+        assert!(pool.request(pos));
+        assert!(pool.cancel(pos));
+        assert!(!pool.cancel(pos));
+        assert!(!pool.is_pending(pos));
+        assert_eq!(pool.queue_depth(), 0);
+
+        // A cancelled chunk can be asked for again
+        assert!(pool.request(pos));
+        assert_eq!(pool.queue_depth(), 1);
+    }
+
+    #[test]
+    fn chunks_stay_pending_until_polled() {
+        let mut pool = ChunkGenPool::new(1, Arc::new(Generator::new(&GenConfig::default(), 0)));
+        let pos = Vec3::new(0, 0, 0);
+        assert!(pool.request(pos));
+
+        let start = Instant::now();
+        let mut chunks = Vec::new();
+        while chunks.is_empty() {
+            // Until it's polled, a finished chunk can't be asked for again
+            assert!(!pool.request(pos));
+            assert!(start.elapsed() < Duration::from_secs(30), "The chunk was never generated");
+            thread::yield_now();
+            chunks = pool.poll();
+        }
+
+        assert_eq!(chunks.iter().map(|(pos, _)| *pos).collect::<Vec<_>>(), vec![pos]);
+        assert!(!pool.is_pending(pos));
+        assert_eq!(pool.in_flight(), 0);
+        assert!(pool.gen_time_percentile(0.5).is_some());
+    }
+
+    #[test]
+    fn percentiles_come_from_recent_gen_times() {
+        let mut pool = idle_pool();
+        assert_eq!(pool.gen_time_percentile(0.5), None);
+
+        pool.gen_times = (1..=5).rev().map(Duration::from_millis).collect();
+        assert_eq!(pool.gen_time_percentile(0.0), Some(Duration::from_millis(1)));
+        assert_eq!(pool.gen_time_percentile(0.5), Some(Duration::from_millis(3)));
+        assert_eq!(pool.gen_time_percentile(1.0), Some(Duration::from_millis(5)));
+        // Out of range percentiles are clamped
+        assert_eq!(pool.gen_time_percentile(2.0), Some(Duration::from_millis(5)));
+        assert_eq!(pool.gen_time_percentile(-1.0), Some(Duration::from_millis(1)));
+    }
+}
@@ -0,0 +1,1043 @@
+// Standard
+use std::{collections::HashMap, mem, sync::Arc, time::{self, Duration}};
+
+// Library
+use specs::prelude::*;
+use vek::*;
+
+// Project
+use common::{
+    ecs::phys::Pos,
+    physics::config::PhysicsConfig,
+    util::{
+        cmd::{parse_duration, ArgKind, ArgSpec, CmdSpec},
+        manager::Manager,
+        msg::ServerMsg,
+    },
+};
+
+// Local
+use crate::{
+    access::{describe_duration, unix_now, Ban},
+    api::Api,
+    config::ServerConfig,
+    net::{Client, DisconnectReason},
+    permission::Permission,
+    player::Player,
+    sys::LoadedChunks,
+    Error, Payloads, Server, Wrapper,
+};
+
+/// Runs a command that was added through `Api::register_command`
+pub type CmdHandler = Box<dyn Fn(&dyn Api, Sender, &Args) + Send + Sync>;
+
+/// The value given for an argument
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arg {
+    Player(Entity),
+    Integer(i64),
+    Float(f32),
+    Duration(Duration),
+    /// Words, choices and the rest of the line
+    Text(String),
+}
+
+/// The arguments a command was given, by name. Optional arguments that weren't given are missing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Args(HashMap<String, Arg>);
+
+impl Args {
+    pub fn get(&self, name: &str) -> Option<&Arg> { self.0.get(name) }
+
+    pub fn player(&self, name: &str) -> Option<Entity> {
+        match self.get(name) {
+            Some(Arg::Player(player)) => Some(*player),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self, name: &str) -> Option<i64> {
+        match self.get(name) {
+            Some(Arg::Integer(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn float(&self, name: &str) -> Option<f32> {
+        match self.get(name) {
+            Some(Arg::Float(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn duration(&self, name: &str) -> Option<Duration> {
+        match self.get(name) {
+            Some(Arg::Duration(duration)) => Some(*duration),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            Some(Arg::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Three numbers given as separate arguments, such as `x`, `y` and `z`
+    pub fn vec3(&self, names: [&str; 3]) -> Option<Vec3<f32>> {
+        Some(Vec3::new(self.float(names[0])?, self.float(names[1])?, self.float(names[2])?))
+    }
+}
+
+/// A command that can be run from chat or the console
+pub struct Cmd {
+    spec: CmdSpec,
+    /// The lowest permission level allowed to use the command
+    permission: Permission,
+    /// Whether the command acts on the sender's own entity, which the console doesn't have
+    needs_player: bool,
+    // Commands without a handler are the server's own
+    pub(crate) handler: Option<Arc<dyn Fn(&dyn Api, Sender, &Args) + Send + Sync>>,
+}
+
+impl Cmd {
+    pub fn new(name: &str, help: &str, permission: Permission) -> Self {
+        Self {
+            spec: CmdSpec {
+                name: name.to_string(),
+                aliases: vec![],
+                usages: vec![],
+                help: help.to_string(),
+            },
+            permission,
+            needs_player: false,
+            handler: None,
+        }
+    }
+
+    /// Another name the command can be run by
+    pub fn alias(mut self, alias: &str) -> Self {
+        self.spec.aliases.push(alias.to_string());
+        self
+    }
+
+    /// Add a way of using the command. When there's more than one, the first that fits what the sender typed is used.
+    /// A command without any usages takes no arguments.
+    pub fn usage(mut self, args: Vec<ArgSpec>) -> Self {
+        self.spec.usages.push(args);
+        self
+    }
+
+    /// Only let players use the command, not the console
+    pub fn players_only(mut self) -> Self {
+        self.needs_player = true;
+        self
+    }
+
+    pub fn name(&self) -> &str { &self.spec.name }
+
+    /// The command as clients are told about it
+    pub fn spec(&self) -> CmdSpec {
+        let mut spec = self.spec.clone();
+        if spec.usages.is_empty() {
+            spec.usages.push(vec![]);
+        }
+        spec
+    }
+
+    fn is_called(&self, name: &str) -> bool { self.spec.name == name || self.spec.aliases.iter().any(|a| a == name) }
+
+    /// Work out the arguments from the words the sender typed after the command's name. `find_player` looks players up
+    /// by alias. On failure, returns a message for the sender about what was wrong.
+    pub fn parse<F: Fn(&str) -> Option<Entity>>(&self, words: &[&str], find_player: F) -> Result<Args, String> {
+        let mut best: Option<ParseError> = None;
+        for usage in self.spec().usages {
+            match parse_args(&usage, words, 0, &find_player, Args::default()) {
+                Ok(args) => return Ok(args),
+                Err(e) => best = Some(ParseError::better(best, e)),
+            }
+        }
+        Err(best.map(|e| e.msg).unwrap_or_default())
+    }
+}
+
+// Where parsing went wrong and why
+struct ParseError {
+    at: usize,
+    // Whether it was only that there were words left over
+    leftover: bool,
+    msg: String,
+}
+
+impl ParseError {
+    // The error that's more likely to be about what the sender meant: the one that got further through the words, and
+    // failing that, the first one. Words being left over is the least telling of all.
+    fn better(a: Option<ParseError>, b: ParseError) -> ParseError {
+        match a {
+            Some(a) if (a.at, !a.leftover) >= (b.at, !b.leftover) => a,
+            _ => b,
+        }
+    }
+}
+
+// Match the words from `at` on up with `specs`, adding their values to `args`. Optional arguments are given a value
+// if that works out, and left out otherwise.
+fn parse_args<F: Fn(&str) -> Option<Entity>>(
+    specs: &[ArgSpec],
+    words: &[&str],
+    at: usize,
+    find_player: &F,
+    args: Args,
+) -> Result<Args, ParseError> {
+    let spec = match specs.first() {
+        Some(spec) => spec,
+        None => {
+            return match words.get(at) {
+                Some(word) => Err(ParseError {
+                    at,
+                    leftover: true,
+                    msg: format!("didn't expect '{}'", word),
+                }),
+                None => Ok(args),
+            };
+        },
+    };
+
+    let filled = match words.get(at) {
+        Some(word) => match parse_arg(&spec.kind, &words[at..], find_player) {
+            Some((value, used)) => {
+                let mut args = args.clone();
+                args.0.insert(spec.name.clone(), value);
+                parse_args(&specs[1..], words, at + used, find_player, args)
+            },
+            None => Err(ParseError {
+                at,
+                leftover: false,
+                msg: format!("expected {}, got '{}'", spec.kind.describe(), word),
+            }),
+        },
+        None => Err(ParseError {
+            at,
+            leftover: false,
+            msg: format!("expected {} for <{}>", spec.kind.describe(), spec.name),
+        }),
+    };
+
+    match filled {
+        Err(e) if spec.optional => parse_args(&specs[1..], words, at, find_player, args)
+            .map_err(|skipped| ParseError::better(Some(e), skipped)),
+        filled => filled,
+    }
+}
+
+// The value of one argument at the start of `words`, and how many words it took up
+fn parse_arg<F: Fn(&str) -> Option<Entity>>(kind: &ArgKind, words: &[&str], find_player: &F) -> Option<(Arg, usize)> {
+    let word = words[0];
+    let value = match kind {
+        ArgKind::Player => find_player(word).map(Arg::Player),
+        ArgKind::Integer => word.parse().ok().map(Arg::Integer),
+        ArgKind::Float => word.parse::<f32>().ok().filter(|v| v.is_finite()).map(Arg::Float),
+        ArgKind::Word => Some(Arg::Text(word.to_string())),
+        ArgKind::Choice(choices) => choices.iter().find(|c| *c == word).map(|c| Arg::Text(c.clone())),
+        ArgKind::Duration => parse_duration(word).map(Arg::Duration),
+        ArgKind::Rest => return Some((Arg::Text(words.join(" ")), words.len())),
+    };
+    value.map(|value| (value, 1))
+}
+
+fn arg(name: &str, kind: ArgKind) -> ArgSpec { ArgSpec::required(name, kind) }
+fn opt(name: &str, kind: ArgKind) -> ArgSpec { ArgSpec::optional(name, kind) }
+fn xyz(names: [&str; 3]) -> Vec<ArgSpec> { names.iter().map(|name| arg(name, ArgKind::Float)).collect() }
+
+/// Every command the server knows, in the order they're listed by `help`
+pub struct Commands(Vec<Cmd>);
+
+impl Commands {
+    /// The server's own commands
+    pub fn builtin() -> Self {
+        let with_target = |mut args: Vec<ArgSpec>| {
+            args.insert(0, opt("alias", ArgKind::Player));
+            args
+        };
+        let levels = ArgKind::Choice(vec!["moderator".to_string(), "admin".to_string()]);
+        let choice = |choices: &[&str]| ArgKind::Choice(choices.iter().map(|s| s.to_string()).collect());
+        let settings = ArgKind::Choice(PhysicsConfig::FIELDS.iter().map(|s| s.to_string()).collect());
+
+        Commands(vec![
+            Cmd::new("help", "Display this list, or how to use a command", Permission::Player)
+                .usage(vec![opt("command", ArgKind::Word)]),
+            Cmd::new("players", "View all online players and their latency", Permission::Player).alias("list"),
+            Cmd::new("pos", "Display your current position", Permission::Player).players_only(),
+            Cmd::new("alias", "Change your alias", Permission::Player)
+                .usage(vec![arg("alias", ArgKind::Word)])
+                .players_only(),
+            Cmd::new("warp", "Offset your position", Permission::Player)
+                .usage(xyz(["dx", "dy", "dz"]))
+                .players_only(),
+            Cmd::new("goto", "Teleport to specified position", Permission::Player)
+                .usage(xyz(["x", "y", "z"]))
+                .players_only(),
+            Cmd::new("spawn", "Teleport back to the spawn point", Permission::Player).players_only(),
+            Cmd::new(
+                "tp",
+                "Teleport a player (yourself by default) to another player or a position",
+                Permission::Player,
+            )
+            .usage(with_target(vec![arg("target", ArgKind::Player)]))
+            .usage(with_target(xyz(["x", "y", "z"]))),
+            Cmd::new("stats", "Display server statistics", Permission::Player),
+            Cmd::new("tps", "Display the server's ticks per second", Permission::Player),
+            Cmd::new("settime", "Set time to t [seconds]", Permission::Moderator)
+                .usage(vec![arg("t", ArgKind::Integer)]),
+            Cmd::new("say", "Broadcast a message to every player", Permission::Moderator)
+                .usage(vec![arg("msg", ArgKind::Rest)]),
+            Cmd::new("kick", "Disconnect a player", Permission::Moderator)
+                .usage(vec![arg("alias", ArgKind::Player), opt("reason", ArgKind::Rest)]),
+            Cmd::new("setspawn", "Move the spawn point to a position (yours by default)", Permission::Admin)
+                .usage(vec![])
+                .usage(xyz(["x", "y", "z"])),
+            Cmd::new(
+                "physics",
+                "Show the physics settings, or change one until the server restarts",
+                Permission::Admin,
+            )
+            .usage(vec![])
+            .usage(vec![arg("setting", settings), opt("value", ArgKind::Rest)]),
+            Cmd::new(
+                "worldborder",
+                "Show the world border, or move it until the server restarts",
+                Permission::Admin,
+            )
+            .usage(vec![])
+            .usage(vec![ArgSpec::literal("set"), arg("radius", ArgKind::Float)]),
+            Cmd::new("op", "Grant a player a permission level", Permission::Admin)
+                .usage(vec![arg("alias", ArgKind::Word), opt("level", levels)]),
+            Cmd::new("deop", "Revoke a player's permission level", Permission::Admin)
+                .usage(vec![arg("alias", ArgKind::Word)]),
+            // A reason has to come after a length of time or `forever`, so that a mistyped length can't make a ban
+            // last for good
+            Cmd::new(
+                "ban",
+                "Stop a player connecting, for a while or for good, and kick them if they're online",
+                Permission::Admin,
+            )
+            .usage(vec![arg("alias", ArgKind::Word)])
+            .usage(vec![
+                arg("alias", ArgKind::Word),
+                arg("duration", ArgKind::Duration),
+                opt("reason", ArgKind::Rest),
+            ])
+            .usage(vec![
+                arg("alias", ArgKind::Word),
+                ArgSpec::literal("forever"),
+                opt("reason", ArgKind::Rest),
+            ]),
+            Cmd::new("unban", "Let a banned player connect again", Permission::Admin)
+                .usage(vec![arg("alias", ArgKind::Word)]),
+            Cmd::new(
+                "whitelist",
+                "Show the whitelist, turn it on or off, or change who's on it",
+                Permission::Admin,
+            )
+            .usage(vec![])
+            .usage(vec![arg("state", choice(&["on", "off"]))])
+            .usage(vec![arg("change", choice(&["add", "remove"])), arg("alias", ArgKind::Word)]),
+            Cmd::new(
+                "reload",
+                "Read the server config again, taking on what can change while it runs",
+                Permission::Admin,
+            ),
+            Cmd::new("stop", "Disconnect everyone and shut the server down", Permission::Admin),
+        ])
+    }
+
+    /// Add a command, unless its name or one of its aliases is already taken. Returns whether it was added.
+    pub fn register(&mut self, cmd: Cmd) -> bool {
+        let mut names = Some(&cmd.spec.name).into_iter().chain(&cmd.spec.aliases);
+        if names.any(|name| self.0.iter().any(|c| c.is_called(name))) {
+            return false;
+        }
+        self.0.push(cmd);
+        true
+    }
+
+    /// Find a command and check that a sender with `permission` may use it. On failure, returns a message for the
+    /// sender.
+    pub fn find(&self, name: &str, permission: Permission, is_player: bool) -> Result<&Cmd, String> {
+        let cmd = self
+            .0
+            .iter()
+            .find(|c| c.is_called(name))
+            .ok_or_else(|| "Unrecognised command! Type 'help' for a list of commands".to_string())?;
+
+        if permission < cmd.permission {
+            Err(format!("Sorry, you need to be a {} to use '{}'", cmd.permission.name(), cmd.name()))
+        } else if cmd.needs_player && !is_player {
+            Err(format!("Only players can use '{}'", cmd.name()))
+        } else {
+            Ok(cmd)
+        }
+    }
+
+    /// The commands a sender with `permission` may use
+    pub fn usable<'a>(&'a self, permission: Permission, is_player: bool) -> impl Iterator<Item = &'a Cmd> + 'a {
+        self.0
+            .iter()
+            .filter(move |cmd| permission >= cmd.permission && (is_player || !cmd.needs_player))
+    }
+}
+
+/// Where a command came from. This decides what the command is allowed to do and where replies go.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sender {
+    Player(Entity),
+    /// The server console, which may use every command
+    Console,
+}
+
+impl Sender {
+    // What commands are typed after
+    fn prefix(&self) -> &'static str {
+        match self {
+            Sender::Player(_) => "/",
+            Sender::Console => "",
+        }
+    }
+}
+
+/// Run a command (without the leading '/') on behalf of `sender`. Chat commands and console commands both come
+/// through here.
+pub(crate) fn process_cmd<P: Payloads>(
+    srv: &Wrapper<Server<P>>,
+    text: &str,
+    sender: Sender,
+    _mgr: &Manager<Wrapper<Server<P>>>,
+) {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    let (name, words) = match words.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let found = srv.do_for(|srv| -> Result<_, Vec<String>> {
+        let cmds = srv.commands.read();
+        let cmd = cmds
+            .find(name, srv.sender_permission(sender), sender != Sender::Console)
+            .map_err(|msg| vec![msg])?;
+        let args = cmd
+            .parse(words, |alias| srv.find_player(alias))
+            .map_err(|msg| vec![msg, format!("Usage: {}", cmd.spec().usage(sender.prefix()))])?;
+        Ok((cmd.name().to_string(), cmd.needs_player, cmd.handler.clone(), args))
+    });
+    let (name, needs_player, handler, args) = match found {
+        Ok(found) => found,
+        Err(msgs) => {
+            return srv.do_for(|srv| {
+                for msg in msgs {
+                    srv.reply(sender, &msg);
+                }
+            })
+        },
+    };
+
+    match (handler, sender) {
+        (Some(handler), _) => srv.do_for(|srv| {
+            let api: &dyn Api = srv;
+            handler(api, sender, &args)
+        }),
+        (None, Sender::Player(player)) if needs_player => process_player_cmd(srv, &name, &args, player),
+        (None, _) => process_shared_cmd(srv, &name, &args, sender),
+    }
+}
+
+// Commands that don't need the sender to have an entity
+fn process_shared_cmd<P: Payloads>(srv: &Wrapper<Server<P>>, name: &str, args: &Args, sender: Sender) {
+    match name {
+        "help" => srv.do_for(|srv| {
+            // Only list the commands the sender can actually use
+            let permission = srv.sender_permission(sender);
+            let is_player = sender != Sender::Console;
+            let cmds = srv.commands.read();
+            if let Some(name) = args.text("command") {
+                match cmds.find(name, permission, is_player) {
+                    Ok(cmd) => {
+                        srv.reply(sender, &format!("Usage: {}", cmd.spec().usage(sender.prefix())));
+                        srv.reply(sender, &cmd.spec.help);
+                    },
+                    Err(msg) => srv.reply(sender, &msg),
+                }
+                return;
+            }
+
+            srv.reply(sender, "Available commands:");
+            for cmd in cmds.usable(permission, is_player) {
+                srv.reply(sender, &format!("{} - {}", cmd.spec().usage(sender.prefix()), cmd.spec.help));
+            }
+        }),
+        "players" => srv.do_for(|srv| {
+            // Find a list of player names along with their latency and format them
+            let player_names = (&srv.world.read_storage::<Player>(), &srv.world.read_storage::<Client>())
+                .join()
+                .map(|(p, c)| match c.latency {
+                    Some(latency) => format!("{} ({}ms)", p.alias, latency.as_millis()),
+                    None => p.alias.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            // Send them back to the sender
+            srv.reply(sender, &format!("Online Players: {}", player_names));
+        }),
+        "settime" => srv.do_for_mut(|srv| {
+            let t = match args.integer("t") {
+                Some(t) if t >= 0 => t as u64,
+                _ => return srv.reply(sender, "Specified time is invalid"),
+            };
+
+            //we have a time to set the server to
+            srv.set_time_of_day(time::Duration::from_secs(t));
+            srv.reply(sender, &format!("Set time to {}", t));
+            srv.broadcast_chat_msg(&format!("[{} set time to {}s]", srv.sender_name(sender), t));
+        }),
+        "stats" => srv.do_for(|srv| {
+            let fmt_time = |p| {
+                srv.chunk_gen
+                    .gen_time_percentile(p)
+                    .map(|t| format!("{}ms", t.as_millis()))
+                    .unwrap_or("-".to_string())
+            };
+
+            srv.reply(sender, &format!("Loaded chunks: {}", srv.world.read_resource::<LoadedChunks>().0.len()));
+            srv.reply(
+                sender,
+                &format!(
+                    "Chunk gen: {} queued, {} in flight",
+                    srv.chunk_gen.queue_depth(),
+                    srv.chunk_gen.in_flight()
+                ),
+            );
+            srv.reply(
+                sender,
+                &format!(
+                    "Chunk gen time: p50 {}, p90 {}, p99 {}",
+                    fmt_time(0.5),
+                    fmt_time(0.9),
+                    fmt_time(0.99)
+                ),
+            );
+        }),
+        "tps" => srv.do_for(|srv| {
+            srv.reply(sender, &format!("TPS: {:.1} (target {:.0})", srv.tps, srv.target_tps()));
+        }),
+        "tp" => srv.do_for_mut(|srv| {
+            // `tp <target>` moves the sender, `tp <alias> <target>` moves someone else
+            let subject = match (args.player("alias"), sender) {
+                (Some(player), _) | (None, Sender::Player(player)) => player,
+                (None, Sender::Console) => {
+                    return srv.reply(sender, "Say who to teleport: tp <alias> <target | x y z>");
+                },
+            };
+            if sender != Sender::Player(subject) && srv.sender_permission(sender) < Permission::Moderator {
+                return srv.reply(sender, "Sorry, you need to be a moderator to teleport other players");
+            }
+
+            let pos = match args.player("target") {
+                Some(target) => match srv.do_for_comp::<Pos, _, _>(target, |pos| pos.0) {
+                    Some(pos) => pos,
+                    None => {
+                        let msg = format!("Could not locate {}!", srv.sender_name(Sender::Player(target)));
+                        return srv.reply(sender, &msg);
+                    },
+                },
+                None => match args.vec3(["x", "y", "z"]) {
+                    Some(pos) => pos,
+                    None => return,
+                },
+            };
+
+            if !srv.set_entity_pos(subject, pos) {
+                return srv.reply(sender, "That player doesn't have a position!");
+            }
+            srv.reply(sender, &format!("Teleporting {} to {}", srv.sender_name(Sender::Player(subject)), pos));
+            if sender != Sender::Player(subject) {
+                srv.send_chat_msg(
+                    subject,
+                    &format!("You were teleported to {} by {}", pos, srv.sender_name(sender)),
+                );
+            }
+        }),
+        "setspawn" => srv.do_for_mut(|srv| {
+            let pos = match (args.vec3(["x", "y", "z"]), sender) {
+                (Some(pos), _) => pos,
+                (None, Sender::Player(player)) => match srv.do_for_comp::<Pos, _, _>(player, |pos| pos.0) {
+                    Some(pos) => pos,
+                    None => return srv.reply(sender, "You don't have a position!"),
+                },
+                (None, Sender::Console) => return srv.reply(sender, "Say where: setspawn <x y z>"),
+            };
+
+            srv.set_spawn_point(pos);
+            srv.reply(sender, &format!("Moved the spawn point to {}", pos));
+        }),
+        "physics" => srv.do_for_mut(|srv| {
+            let mut physics = srv.physics();
+            let name = match args.text("setting") {
+                Some(name) => name,
+                None => {
+                    for name in PhysicsConfig::FIELDS {
+                        srv.reply(sender, &format!("{} = {}", name, physics.get(name).unwrap_or_default()));
+                    }
+                    return;
+                },
+            };
+            let values = args
+                .text("value")
+                .map(|values| values.split_whitespace().map(|e| e.parse::<f32>()).collect::<Result<Vec<_>, _>>());
+            let values = match values {
+                Some(Ok(values)) => values,
+                Some(Err(_)) => return srv.reply(sender, "Invalid value: physics <setting> <value...>"),
+                None => return srv.reply(sender, &format!("{} = {}", name, physics.get(name).unwrap_or_default())),
+            };
+
+            if let Err(e) = physics.set(name, &values) {
+                return srv.reply(sender, &e);
+            }
+            srv.set_physics(physics);
+            let value = physics.get(name).unwrap_or_default();
+            srv.reply(sender, &format!("Set {} to {}", name, value));
+            srv.broadcast_chat_msg(&format!("[{} set {} to {}]", srv.sender_name(sender), name, value));
+        }),
+        "worldborder" => srv.do_for_mut(|srv| {
+            let mut border = srv.world_border();
+            border.radius = match args.float("radius") {
+                Some(radius) if radius > 0.0 => radius,
+                Some(_) => return srv.reply(sender, "The radius has to be a positive number: worldborder set <radius>"),
+                None => {
+                    let msg = format!("The world border is {} blocks out from {}", border.radius, border.center);
+                    return srv.reply(sender, &msg);
+                },
+            };
+
+            srv.set_world_border(border);
+            srv.reply(sender, &format!("Moved the world border to {} blocks out", border.radius));
+            srv.broadcast_chat_msg(&format!(
+                "[{} moved the world border to {} blocks out]",
+                srv.sender_name(sender),
+                border.radius
+            ));
+        }),
+        "reload" => srv.do_for_mut(|srv| {
+            let path = match srv.config.file.clone() {
+                Some(path) => path,
+                None => return srv.reply(sender, "The server wasn't started from a config file"),
+            };
+            let new = match ServerConfig::load(path) {
+                Ok(new) => new,
+                Err(e) => {
+                    let msg = format!("Could not reload the config, so nothing changed: {}", e);
+                    return srv.reply(sender, &msg);
+                },
+            };
+            let fixed = srv.config.reload(new);
+            let msg = format!("Reloaded the config. Up to {} players can be on at once.", srv.config.max_players);
+            srv.reply(sender, &msg);
+            if !fixed.is_empty() {
+                let fixed = fixed.join(", ");
+                let msg = format!("Restart the server to change {}, which can't change while it runs", fixed);
+                srv.reply(sender, &msg);
+            }
+        }),
+        "stop" => srv.do_for_mut(|srv| {
+            srv.reply(sender, "Shutting down");
+            srv.stop();
+        }),
+        "kick" => srv.do_for_mut(|srv| {
+            let player = match args.player("alias") {
+                Some(player) => player,
+                None => return,
+            };
+            let reason = args.text("reason").unwrap_or("Kicked by the server").to_string();
+
+            let alias = srv.sender_name(Sender::Player(player));
+            srv.disconnect_player(player, DisconnectReason::Kicked(reason));
+            srv.reply(sender, &format!("Kicked {}", alias));
+        }),
+        "say" => srv.do_for(|srv| {
+            let text = args.text("msg").unwrap_or_default();
+            srv.broadcast_chat_msg(&format!("[Server] {}", text));
+            srv.reply(sender, &format!("[Server] {}", text));
+        }),
+        "op" => {
+            let alias = args.text("alias").unwrap_or_default();
+            let permission = args
+                .text("level")
+                .and_then(Permission::from_name)
+                .unwrap_or(Permission::Admin);
+            set_permission(srv, alias, permission, sender);
+        },
+        "deop" => set_permission(srv, args.text("alias").unwrap_or_default(), Permission::Player, sender),
+        "ban" => srv.do_for_mut(|srv| {
+            let alias = args.text("alias").unwrap_or_default();
+            let duration = args.duration("duration");
+            let reason = args.text("reason").map(|reason| reason.to_string());
+            // Banning someone who's online also bans where they're connecting from
+            let player = srv.find_player(alias);
+            let ban = Ban {
+                reason: reason.clone(),
+                until: duration.map(|duration| unix_now().saturating_add(duration.as_secs())),
+                ip: player.and_then(|player| srv.world.read_storage::<Client>().get(player).map(|c| c.ip)),
+            };
+            if let Err(e) = srv.access.ban(alias, ban) {
+                return srv.reply(sender, &format!("Could not save bans: {:?}", e));
+            }
+
+            let length = match duration {
+                Some(duration) => format!("for {}", describe_duration(duration)),
+                None => "for good".to_string(),
+            };
+            if let Some(player) = player {
+                let msg = match reason {
+                    Some(reason) => format!("Banned {}: {}", length, reason),
+                    None => format!("Banned {}", length),
+                };
+                srv.disconnect_player(player, DisconnectReason::Kicked(msg));
+            }
+            match player {
+                Some(_) => srv.reply(sender, &format!("Banned {} {}", alias, length)),
+                None => srv.reply(
+                    sender,
+                    &format!(
+                        "Banned {} {}. They aren't online, so where they connect from isn't banned too",
+                        alias, length
+                    ),
+                ),
+            }
+        }),
+        "unban" => srv.do_for_mut(|srv| {
+            let alias = args.text("alias").unwrap_or_default();
+            match srv.access.unban(alias) {
+                Ok(true) => srv.reply(sender, &format!("Unbanned {}", alias)),
+                Ok(false) => srv.reply(sender, &format!("{} isn't banned", alias)),
+                Err(e) => srv.reply(sender, &format!("Could not save bans: {:?}", e)),
+            }
+        }),
+        "whitelist" => srv.do_for_mut(|srv| {
+            let alias = args.text("alias").unwrap_or_default();
+            let result = match (args.text("state"), args.text("change")) {
+                (Some(state), _) => srv
+                    .access
+                    .set_whitelisting(state == "on")
+                    .map(|()| format!("Whitelisting is now {}", state)),
+                (_, Some(change)) => srv
+                    .access
+                    .set_whitelisted(alias, change == "add")
+                    .map(|changed| match (change, changed) {
+                        ("add", true) => format!("Added {} to the whitelist", alias),
+                        ("add", false) => format!("{} is already on the whitelist", alias),
+                        (_, true) => format!("Took {} off the whitelist", alias),
+                        (_, false) => format!("{} isn't on the whitelist", alias),
+                    }),
+                (None, None) => {
+                    let state = if srv.access.is_whitelisting() { "on" } else { "off" };
+                    let listed = srv.access.whitelisted().collect::<Vec<_>>().join(", ");
+                    let listed = if listed.is_empty() { "nobody" } else { &listed };
+                    Ok(format!("Whitelisting is {}, and the whitelist has {}", state, listed))
+                },
+            };
+            match result {
+                Ok(msg) => srv.reply(sender, &msg),
+                Err(e) => srv.reply(sender, &format!("Could not save the whitelist: {:?}", e)),
+            }
+        }),
+        _ => srv.do_for(|srv| srv.reply(sender, "Unrecognised command!")),
+    }
+}
+
+fn set_permission<P: Payloads>(srv: &Wrapper<Server<P>>, alias: &str, permission: Permission, sender: Sender) {
+    srv.do_for_mut(|srv| match srv.set_permission(alias, permission) {
+        Ok(()) => srv.reply(sender, &format!("{} is now a {}", alias, permission.name())),
+        Err(e) => srv.reply(sender, &format!("Could not save permissions: {:?}", e)),
+    });
+}
+
+// Commands that act on the player's own entity
+fn process_player_cmd<P: Payloads>(srv: &Wrapper<Server<P>>, name: &str, args: &Args, player: Entity) {
+    match name {
+        "pos" => srv.do_for(|srv| {
+            if let Some(pos_comp) = srv.world.read_storage::<Pos>().get(player) {
+                srv.send_chat_msg(player, &format!("Current position: {}", pos_comp.0));
+            } else {
+                srv.send_chat_msg(player, "You don't have a position!");
+            }
+        }),
+        "alias" => srv.do_for_mut(|srv| 'nick: {
+            let alias = args.text("alias").unwrap_or_default();
+
+            // Check if the alias is already used by another player.
+            for p in (&srv.world.read_storage::<Player>()).join() {
+                if p.alias == alias {
+                    srv.send_chat_msg(player, "This alias is already in use");
+                    break 'nick;
+                }
+            }
+
+            if !srv.is_valid_alias(&alias) {
+                srv.send_chat_msg(player, "The provided alias is invalid");
+                break 'nick;
+            }
+
+            // The player's state stays saved under their old alias, and isn't saved again until they reconnect
+            srv.save_player(player, true);
+
+            // Give the player their new alias, hold on to the old one temporarily
+            if let Some(old_alias) = srv.do_for_comp_mut::<Player, _, _>(player, |player_comp| {
+                let mut alias = alias.to_string();
+                mem::swap(&mut player_comp.alias, &mut alias);
+                alias
+            }) {
+                srv.force_comp::<Pos>(player); // Force clients to update
+                srv.broadcast_chat_msg(&format!("[{} changed their alias to {}]", old_alias, alias));
+            } else {
+                srv.send_chat_msg(player, "Could not change alias");
+                break 'nick;
+            }
+        }),
+        "warp" => srv.do_for_mut(|srv| {
+            let offset = args.vec3(["dx", "dy", "dz"]).unwrap_or_default();
+            let pos = srv.do_for_comp::<Pos, _, _>(player, |pos_comp| pos_comp.0 + offset);
+            if let Some(pos) = pos.filter(|pos| srv.set_entity_pos(player, *pos)) {
+                srv.send_chat_msg(player, &format!("Warped to: {}!", pos));
+            } else {
+                srv.send_chat_msg(player, "You don't have a position!");
+            }
+        }),
+        "goto" => srv.do_for_mut(|srv| {
+            let pos = match args.vec3(["x", "y", "z"]) {
+                Some(pos) => pos,
+                None => return,
+            };
+            if srv.set_entity_pos(player, pos) {
+                srv.send_chat_msg(player, &format!("teleported to: {}!", pos));
+            } else {
+                srv.send_chat_msg(player, "You don't have a position!");
+            }
+        }),
+        "spawn" => srv.do_for_mut(|srv| {
+            let spawn = srv.spawn_point();
+            if srv.set_entity_pos(player, spawn) {
+                srv.send_chat_msg(player, "Teleported to spawn!");
+            } else {
+                srv.send_chat_msg(player, "You don't have a position!");
+            }
+        }),
+        _ => srv.do_for(|srv| srv.send_chat_msg(player, "Unrecognised command!")),
+    }
+}
+
+impl<P: Payloads> Server<P> {
+    /// Tell a player's client which commands they may use, so that it can complete them as they're typed
+    pub(crate) fn send_cmd_list(&self, player: Entity) {
+        let commands = self
+            .commands
+            .read()
+            .usable(self.permission_of(player), true)
+            .map(|cmd| cmd.spec())
+            .collect();
+        self.send_net_msg(player, ServerMsg::CommandList { commands });
+    }
+
+    fn sender_name(&self, sender: Sender) -> String {
+        match sender {
+            Sender::Player(player) => self
+                .do_for_comp::<Player, _, _>(player, |p| p.alias.clone())
+                .unwrap_or("<none>".to_string()),
+            Sender::Console => "Server".to_string(),
+        }
+    }
+
+    fn find_player(&self, alias: &str) -> Option<Entity> {
+        (&self.world.entities(), &self.world.read_storage::<Player>())
+            .join()
+            .find(|(_, p)| p.alias == alias)
+            .map(|(e, _)| e)
+    }
+
+    // The console can do anything
+    fn sender_permission(&self, sender: Sender) -> Permission {
+        match sender {
+            Sender::Player(player) => self.permission_of(player),
+            Sender::Console => Permission::Admin,
+        }
+    }
+
+    /// Change the permission level of the player with the given alias, whether or not they're online
+    pub fn set_permission(&mut self, alias: &str, permission: Permission) -> Result<(), Error> {
+        self.permissions.set(alias, permission)?;
+
+        if let Some(player) = self.find_player(alias) {
+            let _ = self.world.write_storage::<Permission>().insert(player, permission);
+            self.send_chat_msg(player, &format!("You are now a {}", permission.name()));
+            self.send_cmd_list(player);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permission_checks() {
+        let cmds = Commands::builtin();
+        assert!(cmds.find("help", Permission::Player, true).is_ok());
+        assert!(cmds.find("list", Permission::Player, true).is_ok());
+        assert!(cmds.find("kick", Permission::Player, true).is_err());
+        assert!(cmds.find("kick", Permission::Moderator, true).is_ok());
+        assert!(cmds.find("op", Permission::Moderator, true).is_err());
+        assert!(cmds.find("op", Permission::Admin, true).is_ok());
+        assert!(cmds.find("ban", Permission::Moderator, true).is_err());
+        assert!(cmds.find("whitelist", Permission::Admin, false).is_ok());
+        assert!(cmds.find("stop", Permission::Admin, false).is_ok());
+        assert!(cmds.find("nonsense", Permission::Admin, true).is_err());
+
+        assert_eq!(
+            cmds.find("settime", Permission::Player, true).err(),
+            Some("Sorry, you need to be a moderator to use 'settime'".to_string())
+        );
+        // Nor are players told about commands they can't use
+        let usable = cmds.usable(Permission::Player, true).map(|cmd| cmd.name()).collect::<Vec<_>>();
+        assert!(usable.contains(&"tp") && !usable.contains(&"settime"));
+    }
+
+    #[test]
+    fn console_cannot_use_player_cmds() {
+        let cmds = Commands::builtin();
+        assert!(cmds.find("goto", Permission::Player, true).is_ok());
+        assert!(cmds.find("goto", Permission::Admin, false).is_err());
+        assert!(cmds.find("stats", Permission::Admin, false).is_ok());
+    }
+
+    #[test]
+    fn names_and_aliases_cant_be_taken_twice() {
+        let mut cmds = Commands::builtin();
+        assert!(!cmds.register(Cmd::new("tp", "Another teleport", Permission::Player)));
+        assert!(!cmds.register(Cmd::new("who", "Who's online", Permission::Player).alias("list")));
+        assert!(cmds.register(Cmd::new("msg", "Whisper to a player", Permission::Player)));
+        assert!(cmds.find("msg", Permission::Player, true).is_ok());
+    }
+
+    // A command taking one of each kind of argument, and a world with a player called zesterer to look up
+    fn everything() -> (Cmd, World, Entity) {
+        let cmd = Cmd::new("everything", "", Permission::Player).usage(vec![
+            ArgSpec::required("who", ArgKind::Player),
+            ArgSpec::required("count", ArgKind::Integer),
+            ArgSpec::required("scale", ArgKind::Float),
+            ArgSpec::required("word", ArgKind::Word),
+            ArgSpec::required("colour", ArgKind::Choice(vec!["red".to_string(), "blue".to_string()])),
+            ArgSpec::required("note", ArgKind::Rest),
+        ]);
+        let mut world = World::new();
+        let player = world.create_entity().build();
+        (cmd, world, player)
+    }
+
+    fn parse(cmd: &Cmd, player: Entity, text: &str) -> Result<Args, String> {
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        cmd.parse(&words, |alias| if alias == "zesterer" { Some(player) } else { None })
+    }
+
+    #[test]
+    fn every_kind_of_argument_is_parsed() {
+        let (cmd, _world, player) = everything();
+
+        let args = parse(&cmd, player, "zesterer -3 2.5 hello blue  some   notes").unwrap();
+        assert_eq!(args.player("who"), Some(player));
+        assert_eq!(args.integer("count"), Some(-3));
+        assert_eq!(args.float("scale"), Some(2.5));
+        assert_eq!(args.text("word"), Some("hello"));
+        assert_eq!(args.text("colour"), Some("blue"));
+        assert_eq!(args.text("note"), Some("some notes"));
+        // Asking for an argument as the wrong kind finds nothing
+        assert_eq!(args.integer("scale"), None);
+
+        let error = |text| parse(&cmd, player, text).unwrap_err();
+        assert_eq!(error("17 1 1 a red b"), "expected a player name, got '17'");
+        assert_eq!(error("zesterer 1.5 1 a red b"), "expected a whole number, got '1.5'");
+        assert_eq!(error("zesterer 1 inf a red b"), "expected a number, got 'inf'");
+        assert_eq!(error("zesterer 1 1 a green b"), "expected one of red, blue, got 'green'");
+        assert_eq!(error("zesterer 1 1 a red"), "expected some text for <note>");
+        assert_eq!(error("zesterer 1"), "expected a number for <scale>");
+    }
+
+    #[test]
+    fn optional_arguments_are_only_filled_when_theres_room() {
+        let cmds = Commands::builtin();
+        let (_, _world, player) = everything();
+        let tp = cmds.find("tp", Permission::Player, true).unwrap();
+
+        let args = parse(tp, player, "zesterer").unwrap();
+        assert_eq!((args.player("alias"), args.player("target")), (None, Some(player)));
+        let args = parse(tp, player, "zesterer zesterer").unwrap();
+        assert_eq!((args.player("alias"), args.player("target")), (Some(player), Some(player)));
+
+        // Positions are the other way of using it
+        let args = parse(tp, player, "1 2 3").unwrap();
+        assert_eq!((args.player("alias"), args.vec3(["x", "y", "z"])), (None, Some(Vec3::new(1.0, 2.0, 3.0))));
+        let args = parse(tp, player, "zesterer 1 2 3").unwrap();
+        assert_eq!(args.player("alias"), Some(player));
+        assert_eq!(parse(tp, player, "zesterer 1 2").unwrap_err(), "expected a number for <z>");
+        assert_eq!(parse(tp, player, "zesterer zesterer zesterer").unwrap_err(), "didn't expect 'zesterer'");
+        // Nobody can be sent nowhere
+        assert_eq!(parse(tp, player, "1 NaN 3").unwrap_err(), "expected a number for <y>");
+        let goto = cmds.find("goto", Permission::Player, true).unwrap();
+        assert_eq!(parse(goto, player, "inf 2 3").unwrap_err(), "expected a number for <x>");
+        assert_eq!(parse(goto, player, "1 2 -infinity").unwrap_err(), "expected a number for <z>");
+
+        // Optional arguments at the end are left out when there's nothing for them
+        let kick = cmds.find("kick", Permission::Admin, true).unwrap();
+        assert_eq!(parse(kick, player, "zesterer").unwrap().text("reason"), None);
+        assert_eq!(parse(kick, player, "zesterer too loud").unwrap().text("reason"), Some("too loud"));
+
+        // The usage that fits best is the one errors are about
+        let border = cmds.find("worldborder", Permission::Admin, true).unwrap();
+        assert_eq!(parse(border, player, "").unwrap().float("radius"), None);
+        assert_eq!(parse(border, player, "set 50").unwrap().float("radius"), Some(50.0));
+        assert_eq!(parse(border, player, "set").unwrap_err(), "expected a number for <radius>");
+        assert_eq!(parse(border, player, "grow 50").unwrap_err(), "expected one of set, got 'grow'");
+
+        // A reason only comes after a length of time, or `forever`, so a mistyped length isn't taken as the reason
+        let ban = cmds.find("ban", Permission::Admin, true).unwrap();
+        let args = parse(ban, player, "griefer 2h flattening spawn").unwrap();
+        assert_eq!(args.duration("duration"), Some(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(args.text("reason"), Some("flattening spawn"));
+        let args = parse(ban, player, "griefer forever flattening spawn").unwrap();
+        assert_eq!((args.duration("duration"), args.text("reason")), (None, Some("flattening spawn")));
+        assert_eq!(parse(ban, player, "griefer").unwrap().duration("duration"), None);
+        assert_eq!(
+            parse(ban, player, "griefer 2 hours").unwrap_err(),
+            "expected a length of time, like 30m, got '2'"
+        );
+    }
+
+    #[test]
+    fn usage_is_generated_from_the_arguments() {
+        let cmds = Commands::builtin();
+        let spec = |name| cmds.find(name, Permission::Admin, true).unwrap().spec();
+        assert_eq!(spec("tp").usage("/"), "/tp [alias] <target> | /tp [alias] <x> <y> <z>");
+        assert_eq!(spec("kick").usage(""), "kick <alias> [reason...]");
+        assert_eq!(spec("op").usage(""), "op <alias> [moderator|admin]");
+        assert_eq!(spec("worldborder").usage(""), "worldborder | worldborder set <radius>");
+        assert_eq!(
+            spec("ban").usage(""),
+            "ban <alias> | ban <alias> <duration> [reason...] | ban <alias> forever [reason...]"
+        );
+        assert_eq!(
+            spec("whitelist").usage(""),
+            "whitelist | whitelist <on|off> | whitelist <add|remove> <alias>"
+        );
+        assert_eq!(spec("reload").usage(""), "reload");
+        assert_eq!(spec("stop").usage(""), "stop");
+    }
+}
@@ -0,0 +1,242 @@
+// Standard
+use std::{fs, io, path::PathBuf, time::Duration};
+
+// Library
+use serde_derive::Deserialize;
+
+// Local
+use crate::Error;
+
+// Constants
+const MAX_TICK_RATE: u32 = 100;
+
+/// How the server runs, as its operator sets it up. Anything a config file leaves out is left at its default.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// The address to listen for players on
+    pub address: String,
+    /// The port to listen for players on, over both TCP and UDP. 0 picks any free port.
+    pub port: u32,
+    /// How many times a second the world is updated
+    pub tick_rate: u32,
+    /// What the world is generated from. Servers with the same seed have the same terrain, and clients are told it.
+    pub world_seed: u64,
+    /// How many players can be on the server at once, counting those who lost connection and may yet come back
+    pub max_players: u32,
+    /// What the server calls itself to players and to anyone asking about it
+    pub server_name: String,
+    /// The message of the day, which players are shown when they join. It can span several lines.
+    pub motd: String,
+    /// How close to players entities may spawn, instead of what the payloads ask for. Players who see further than
+    /// usual need spawns further out, so that nothing appears in plain sight.
+    pub spawn_distance: Option<f32>,
+    /// The file the config was loaded from, which `/reload` reads again. It isn't a setting in the file itself.
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "0.0.0.0".to_string(),
+            port: 59003,
+            tick_rate: 50,
+            world_seed: 0,
+            max_players: 32,
+            server_name: "Veloren Server".to_string(),
+            motd: String::new(),
+            spawn_distance: None,
+            file: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Load the config from a file. A missing file is written out with the defaults, along with what each setting does,
+    /// for the operator to change.
+    pub fn load(path: PathBuf) -> Result<ServerConfig, Error> {
+        let mut config: ServerConfig = match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                fs::write(&path, ServerConfig::default_file())?;
+                info!("Wrote the default server config to {}", path.display());
+                ServerConfig::default()
+            },
+            Err(e) => return Err(e.into()),
+        };
+        config
+            .validate()
+            .map_err(|e| Error::InvalidConfig(format!("{}: {}", path.display(), e)))?;
+        config.file = Some(path);
+        Ok(config)
+    }
+
+    /// Check that the server could run like this, naming the first setting that it couldn't
+    pub fn validate(&self) -> Result<(), String> {
+        if self.port > u16::max_value() as u32 {
+            return Err(format!("port has to be at most {}", u16::max_value()));
+        }
+        if self.tick_rate < 1 || self.tick_rate > MAX_TICK_RATE {
+            return Err(format!("tick_rate has to be from 1 to {}", MAX_TICK_RATE));
+        }
+        if self.max_players < 1 {
+            return Err("max_players has to be at least 1".to_string());
+        }
+        if self.spawn_distance.map(|d| !(d >= 0.0 && d.is_finite())).unwrap_or(false) {
+            return Err("spawn_distance can't be negative".to_string());
+        }
+        Ok(())
+    }
+
+    /// Where to listen for players. Only meaningful once the config has been validated.
+    pub fn bind_addr(&self) -> (&str, u16) { (&self.address, self.port as u16) }
+
+    /// How long each tick is meant to take
+    pub fn tick_duration(&self) -> Duration { Duration::from_nanos(1_000_000_000 / self.tick_rate.max(1) as u64) }
+
+    /// Take on the settings from `new` that can change while the server is running. Returns the settings that differ
+    /// but can't, which are left as they were.
+    pub fn reload(&mut self, new: ServerConfig) -> Vec<&'static str> {
+        let fixed = [
+            ("address", self.address != new.address),
+            ("port", self.port != new.port),
+            ("tick_rate", self.tick_rate != new.tick_rate),
+            ("world_seed", self.world_seed != new.world_seed),
+        ];
+        self.max_players = new.max_players;
+        self.server_name = new.server_name;
+        self.motd = new.motd;
+        self.spawn_distance = new.spawn_distance;
+        fixed.iter().filter(|(_, changed)| *changed).map(|(name, _)| *name).collect()
+    }
+
+    /// The default config as it's written to a new file, with what each setting does
+    pub fn default_file() -> String {
+        let config = ServerConfig::default();
+        format!(
+            "# Where to listen for players, over both TCP and UDP. A port of 0 picks any free port.\n\
+             address = {}\n\
+             port = {}\n\
+             \n\
+             # How many times a second the world is updated, from 1 to {}\n\
+             tick_rate = {}\n\
+             \n\
+             # What the world is generated from. Changing it changes the terrain that hasn't been generated yet.\n\
+             world_seed = {}\n\
+             \n\
+             # How many players can be on the server at once. Can be changed with /reload.\n\
+             max_players = {}\n\
+             \n\
+             # What the server calls itself, and the message of the day players are shown when they join. The\n\
+             # message can span several lines between triple quotes, or be empty to show nothing. Both can be\n\
+             # changed with /reload.\n\
+             server_name = {}\n\
+             motd = {}\n\
+             \n\
+             # How close to players entities may spawn, for when they see further than the server's payloads expect.\n\
+             # Can be changed with /reload.\n\
+             # spawn_distance = 64\n",
+            toml::Value::from(config.address),
+            config.port,
+            MAX_TICK_RATE,
+            config.tick_rate,
+            config.world_seed,
+            config.max_players,
+            toml::Value::from(config.server_name),
+            toml::Value::from(config.motd),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_default_file_is_the_default_config() {
+        let config: ServerConfig = toml::from_str(&ServerConfig::default_file()).unwrap();
+        assert_eq!(config, ServerConfig::default());
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(config.tick_duration(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn settings_left_out_are_defaults() {
+        let config: ServerConfig = toml::from_str("port = 4000\nmotd = '''\nHi\nBe nice'''").unwrap();
+        assert_eq!(
+            config,
+            ServerConfig {
+                port: 4000,
+                motd: "Hi\nBe nice".to_string(),
+                ..ServerConfig::default()
+            }
+        );
+        // Misspelt settings aren't quietly left at their defaults
+        let err = toml::from_str::<ServerConfig>("max_player = 5").unwrap_err().to_string();
+        assert!(err.contains("max_player"), "{}", err);
+    }
+
+    #[test]
+    fn missing_files_are_written_with_the_defaults() {
+        let path = std::env::temp_dir().join(format!("veloren-server-{}.toml", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let config = ServerConfig::load(path.clone()).unwrap();
+        assert_eq!(config.file, Some(path.clone()));
+        assert_eq!(config.port, ServerConfig::default().port);
+        assert_eq!(fs::read_to_string(&path).unwrap(), ServerConfig::default_file());
+
+        fs::write(&path, "tick_rate = 0").unwrap();
+        match ServerConfig::load(path.clone()) {
+            Err(Error::InvalidConfig(e)) => assert!(e.ends_with(": tick_rate has to be from 1 to 100"), "{}", e),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_errors_read_as_text_naming_the_setting() {
+        let path = std::env::temp_dir().join(format!("veloren-server-errors-{}.toml", std::process::id()));
+        fs::write(&path, "max_players = 'lots'").unwrap();
+        let err = ServerConfig::load(path.clone()).unwrap_err().to_string();
+        assert!(err.contains("max_players"), "{}", err);
+
+        fs::write(&path, "max_players = 0").unwrap();
+        let err = ServerConfig::load(path.clone()).unwrap_err().to_string();
+        assert_eq!(err, format!("{}: max_players has to be at least 1", path.display()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bad_settings_are_named() {
+        let check = |text: &str| toml::from_str::<ServerConfig>(text).unwrap().validate();
+        assert_eq!(check("port = 65536"), Err("port has to be at most 65535".to_string()));
+        assert_eq!(check("port = 0"), Ok(()));
+        assert_eq!(check("tick_rate = 0"), Err("tick_rate has to be from 1 to 100".to_string()));
+        assert_eq!(check("tick_rate = 101"), Err("tick_rate has to be from 1 to 100".to_string()));
+        assert_eq!(check("max_players = 0"), Err("max_players has to be at least 1".to_string()));
+        assert_eq!(check("spawn_distance = -1.0"), Err("spawn_distance can't be negative".to_string()));
+    }
+
+    #[test]
+    fn only_some_settings_reload() {
+        let mut config = ServerConfig::default();
+        let new = ServerConfig {
+            max_players: 2,
+            server_name: "Renamed".to_string(),
+            motd: "Changed".to_string(),
+            spawn_distance: Some(100.0),
+            world_seed: 7,
+            port: 1234,
+            ..ServerConfig::default()
+        };
+        assert_eq!(config.reload(new), vec!["port", "world_seed"]);
+        assert_eq!(config.max_players, 2);
+        assert_eq!(config.server_name, "Renamed");
+        assert_eq!(config.motd, "Changed");
+        assert_eq!(config.spawn_distance, Some(100.0));
+        assert_eq!(config.world_seed, 0);
+        assert_eq!(config.port, 59003);
+    }
+}
@@ -0,0 +1,24 @@
+// Standard
+use std::{
+    io::{self, BufRead},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+/// Read lines from stdin on a thread of their own. The thread is never joined, since it may be stuck in a blocking
+/// read until the process exits. Once stdin is closed, the receiver disconnects.
+pub(crate) fn read_lines() -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            // Commands may be typed with or without the leading '/' used in chat
+            let sent = line.map(|line| tx.send(line.trim().trim_start_matches('/').to_string()));
+            if let Ok(Ok(())) = sent {
+            } else {
+                break;
+            }
+        }
+    });
+    rx
+}
@@ -0,0 +1,135 @@
+// Standard
+use std::{fs, io, path::Path};
+
+// Library
+use serde_derive::Deserialize;
+use specs::Entity;
+use vek::*;
+
+// Project
+use common::{
+    ecs::{
+        craft::{CraftError, Recipe, Station, STATION_RANGE},
+        inventory::{Inventory, Item},
+        phys::Pos,
+    },
+    terrain::VoxAbs,
+    util::msg::ServerMsg,
+};
+
+// Local
+use crate::{sys::LoadedChunks, Error, Payloads, Server};
+
+#[derive(Deserialize)]
+struct RecipeFile {
+    #[serde(default)]
+    recipe: Vec<Recipe>,
+}
+
+/// Every recipe players can craft, in the order they were listed
+#[derive(Clone, Debug, Default)]
+pub struct RecipeBook {
+    recipes: Vec<Recipe>,
+}
+
+impl RecipeBook {
+    /// Load the recipes in a file. A missing file means there are no recipes, but one that doesn't make sense is an
+    /// error, naming the file and the line the problem was found on.
+    pub fn load<T: AsRef<Path>>(path: T) -> Result<RecipeBook, Error> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                RecipeBook::parse(&content).map_err(|e| Error::InvalidConfig(format!("{}: {}", path.display(), e)))
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(RecipeBook::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Read recipes written as TOML, each under a `[[recipe]]` header
+    pub fn parse(content: &str) -> Result<RecipeBook, String> {
+        let recipes = toml::from_str::<RecipeFile>(content).map_err(|e| e.to_string())?.recipe;
+        for (i, recipe) in recipes.iter().enumerate() {
+            // Recipes are checked after parsing, so point at the line their id is on, telling apart recipes that share
+            // an id by how many came before
+            let quoted = format!("\"{}\"", recipe.id);
+            let earlier = recipes[..i].iter().filter(|r| r.id == recipe.id).count();
+            let line = content
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| line.contains(&quoted))
+                .nth(earlier)
+                .map_or(String::new(), |(line, _)| format!("line {}: ", line + 1));
+            let problem = if recipe.id.is_empty() {
+                Some("it has no id")
+            } else if earlier > 0 {
+                Some("another recipe has the same id")
+            } else if recipe.inputs.is_empty() {
+                Some("it takes nothing to make")
+            } else if recipe.inputs.iter().chain(Some(&recipe.output)).any(|item| item.amount == 0) {
+                Some("amounts must be at least 1")
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                return Err(format!("{}recipe `{}` is invalid: {}", line, recipe.id, problem));
+            }
+        }
+        Ok(RecipeBook { recipes })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Recipe> { self.recipes.iter().find(|recipe| recipe.id == id) }
+
+    pub fn recipes(&self) -> &[Recipe] { &self.recipes }
+}
+
+impl<P: Payloads> Server<P> {
+    /// Tell a player's client every recipe, so that it can show them
+    pub(crate) fn send_recipes(&self, player: Entity) {
+        let recipes = self.recipes.recipes().to_vec();
+        self.send_net_msg(player, ServerMsg::RecipeList { recipes });
+    }
+
+    /// Craft a recipe for a player, and tell them how it went
+    pub(crate) fn handle_craft(&mut self, player: Entity, recipe: String, count: u32) {
+        let result = self.craft(player, &recipe, count);
+        if result.is_ok() {
+            self.send_inventory(player);
+        }
+        self.send_net_msg(player, ServerMsg::CraftResult { recipe, result });
+    }
+
+    fn craft(&self, player: Entity, id: &str, count: u32) -> Result<Item, CraftError> {
+        let recipe = self.recipes.get(id).ok_or(CraftError::UnknownRecipe)?;
+        if let Some(station) = recipe.station {
+            let pos = self.world.read_storage::<Pos>().get(player).map(|pos| pos.0);
+            if !pos.map_or(false, |pos| self.station_near(pos, station)) {
+                return Err(CraftError::NoStation);
+            }
+        }
+
+        let mut inventories = self.world.write_storage::<Inventory>();
+        let inv = inventories.get_mut(player).ok_or(CraftError::MissingIngredients)?;
+        recipe.craft(inv, count)
+    }
+
+    /// Whether the middle of any block that's a `station` is within `STATION_RANGE` of `pos`
+    fn station_near(&self, pos: Vec3<f32>, station: Station) -> bool {
+        let chunks = self.world.read_resource::<LoadedChunks>();
+        let reach = STATION_RANGE.ceil() as VoxAbs;
+        let centre = pos.map(|e| e.floor() as VoxAbs);
+        for x in -reach..=reach {
+            for y in -reach..=reach {
+                for z in -reach..=reach {
+                    let vox = centre + Vec3::new(x, y, z);
+                    if (vox.map(|e| e as f32) + 0.5).distance(pos) <= STATION_RANGE
+                        && chunks.block_at(vox).map(|block| station.is(block)).unwrap_or(false)
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
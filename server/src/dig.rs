@@ -0,0 +1,184 @@
+// Standard
+use std::time::{Duration, Instant};
+
+// Library
+use vek::*;
+
+// Project
+use common::terrain::{chunk::Block, VoxAbs};
+
+// Constants
+// Allowed on top of the client's latency, since its messages don't all take equally long to get here
+const DIG_GRACE: Duration = Duration::from_millis(100);
+// However slow their connection, nobody gets to skip more of a dig than this
+const MAX_DIG_SLACK: Duration = Duration::from_millis(500);
+
+/// What to do about a player's progress digging a block
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DigVerdict {
+    /// They're still at it
+    Digging,
+    /// They've stopped before finishing
+    Stopped,
+    /// They've been at it long enough for the block to break
+    Broken,
+    /// They say they've finished sooner than they could have, or the block can't be dug at all
+    Rejected,
+}
+
+// A block a player is digging, and when the server heard they'd started
+#[derive(Copy, Clone, Debug)]
+struct Dig {
+    pos: Vec3<VoxAbs>,
+    block: Block,
+    started: Instant,
+}
+
+/// Keeps track of the block a player is digging, so that the server can tell whether they've spent long enough on it
+/// before it breaks. Clients only say how far they've got; the time it took is measured here.
+#[derive(Debug, Default)]
+pub struct DigTracker {
+    current: Option<Dig>,
+}
+
+impl DigTracker {
+    /// Take note of a player's `progress` digging `block` at `pos`, as of `now`. Starting on a different block, or
+    /// the block changing, starts the dig over. `latency` is the round trip time to the player's client, which is
+    /// allowed for when deciding whether a finished dig took long enough.
+    pub fn progress(
+        &mut self,
+        pos: Vec3<VoxAbs>,
+        block: Block,
+        progress: f32,
+        latency: Option<Duration>,
+        now: Instant,
+    ) -> DigVerdict {
+        if progress.is_nan() || progress <= 0.0 {
+            return self.stop();
+        }
+        if !block.is_breakable() {
+            self.current = None;
+            return DigVerdict::Rejected;
+        }
+
+        let dig = match self.current {
+            Some(dig) if dig.pos == pos && dig.block == block => dig,
+            _ => Dig { pos, block, started: now },
+        };
+        if progress < 1.0 {
+            self.current = Some(dig);
+            return DigVerdict::Digging;
+        }
+
+        self.current = None;
+        let elapsed = now.duration_since(dig.started) + slack(latency);
+        if elapsed.as_float_secs() as f32 >= block.hardness() {
+            DigVerdict::Broken
+        } else {
+            DigVerdict::Rejected
+        }
+    }
+
+    /// Forget the dig in progress, if there is one
+    pub fn stop(&mut self) -> DigVerdict {
+        self.current = None;
+        DigVerdict::Stopped
+    }
+
+    /// Where the player is digging, if they are
+    pub fn digging_at(&self) -> Option<Vec3<VoxAbs>> { self.current.map(|dig| dig.pos) }
+}
+
+// How much sooner than the block's hardness a dig may finish. The message saying a client started digging can take
+// longer to arrive than the one saying it finished, by up to about a round trip.
+fn slack(latency: Option<Duration>) -> Duration {
+    (latency.unwrap_or_default() + DIG_GRACE).min(MAX_DIG_SLACK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f32) -> Duration { Duration::from_millis((secs * 1000.0) as u64) }
+
+    // Start digging `block` at `now`, then finish after `took`
+    fn dig(block: Block, took: Duration, latency: Option<Duration>) -> DigVerdict {
+        let mut tracker = DigTracker::default();
+        let (pos, now) = (Vec3::new(1, 2, 3), Instant::now());
+        assert_eq!(tracker.progress(pos, block, 0.1, latency, now), DigVerdict::Digging);
+        tracker.progress(pos, block, 1.0, latency, now + took)
+    }
+
+    #[test]
+    fn digs_must_take_as_long_as_the_block_is_hard() {
+        let hardness = Block::STONE.hardness();
+        assert_eq!(dig(Block::STONE, secs(hardness), None), DigVerdict::Broken);
+        assert_eq!(dig(Block::STONE, secs(hardness + 1.0), None), DigVerdict::Broken);
+        assert_eq!(dig(Block::STONE, secs(hardness / 2.0), None), DigVerdict::Rejected);
+        assert_eq!(dig(Block::STONE, Duration::from_secs(0), None), DigVerdict::Rejected);
+    }
+
+    #[test]
+    fn latency_is_allowed_for() {
+        let hardness = Block::STONE.hardness();
+        let latency = Some(Duration::from_millis(200));
+
+        // A little early is fine for everyone, and a little more for slow connections
+        assert_eq!(dig(Block::STONE, secs(hardness - 0.05), None), DigVerdict::Broken);
+        assert_eq!(dig(Block::STONE, secs(hardness - 0.25), None), DigVerdict::Rejected);
+        assert_eq!(dig(Block::STONE, secs(hardness - 0.25), latency), DigVerdict::Broken);
+        assert_eq!(dig(Block::STONE, secs(hardness - 0.35), latency), DigVerdict::Rejected);
+
+        // ...but only so much more, however slow
+        let slow = Some(Duration::from_secs(5));
+        assert_eq!(dig(Block::STONE, secs(hardness - 0.45), slow), DigVerdict::Broken);
+        assert_eq!(dig(Block::STONE, secs(hardness - 0.6), slow), DigVerdict::Rejected);
+    }
+
+    #[test]
+    fn unbreakable_blocks_never_break() {
+        let forever = Duration::from_secs(1_000_000);
+        assert!(!Block::BEDROCK.is_breakable());
+        assert_eq!(dig(Block::STONE, forever, None), DigVerdict::Broken);
+
+        let mut tracker = DigTracker::default();
+        let now = Instant::now();
+        for block in &[Block::BEDROCK, Block::AIR, Block::WATER] {
+            assert_eq!(tracker.progress(Vec3::zero(), *block, 0.5, None, now), DigVerdict::Rejected);
+            assert_eq!(
+                tracker.progress(Vec3::zero(), *block, 1.0, Some(forever), now + forever),
+                DigVerdict::Rejected
+            );
+        }
+    }
+
+    #[test]
+    fn changing_target_starts_over() {
+        let mut tracker = DigTracker::default();
+        let now = Instant::now();
+        let hardness = secs(Block::STONE.hardness());
+        let (a, b) = (Vec3::new(0, 0, 0), Vec3::new(0, 0, 1));
+
+        tracker.progress(a, Block::STONE, 0.1, None, now);
+        tracker.progress(b, Block::STONE, 0.1, None, now + hardness);
+        assert_eq!(tracker.digging_at(), Some(b));
+        assert_eq!(tracker.progress(b, Block::STONE, 1.0, None, now + hardness), DigVerdict::Rejected);
+
+        // Stopping forgets the dig too, as does the block turning into something else
+        tracker.progress(a, Block::STONE, 0.1, None, now);
+        assert_eq!(tracker.progress(a, Block::STONE, 0.0, None, now), DigVerdict::Stopped);
+        assert_eq!(tracker.digging_at(), None);
+        assert_eq!(tracker.progress(a, Block::STONE, 1.0, None, now + hardness), DigVerdict::Rejected);
+
+        tracker.progress(a, Block::STONE, 0.1, None, now);
+        tracker.progress(a, Block::EARTH, 0.5, None, now + hardness);
+        assert_eq!(tracker.progress(a, Block::EARTH, 1.0, None, now + hardness), DigVerdict::Rejected);
+
+        // Nonsense stops the dig rather than finishing it
+        tracker.progress(a, Block::STONE, 0.1, None, now);
+        assert_eq!(
+            tracker.progress(a, Block::STONE, std::f32::NAN, None, now + hardness),
+            DigVerdict::Stopped
+        );
+    }
+}
@@ -1,5 +1,5 @@
 // Standard
-use std::io;
+use std::{fmt, io};
 
 #[derive(Debug)]
 pub enum Error {
@@ -7,9 +7,40 @@ pub enum Error {
     NoConnectSession,
     InvalidConnectSession,
     NoConnectMsg,
+    // The client was turned away by the whitelist, a ban or a full server, and told why
+    Refused(String),
     IoErr(io::Error),
+    TomlDeErr(toml::de::Error),
+    TomlSerErr(toml::ser::Error),
+    // A config file was read fine but made no sense
+    InvalidConfig(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ConnectionDropped => write!(f, "The connection dropped"),
+            Error::NoConnectSession => write!(f, "The client didn't start by connecting"),
+            Error::InvalidConnectSession => write!(f, "The client started with the wrong kind of session"),
+            Error::NoConnectMsg => write!(f, "The client didn't say who it was"),
+            Error::Refused(reason) => write!(f, "{}", reason),
+            Error::IoErr(e) => write!(f, "{}", e),
+            // These name the setting that couldn't be read, and where it is in the file
+            Error::TomlDeErr(e) => write!(f, "{}", e),
+            Error::TomlSerErr(e) => write!(f, "{}", e),
+            Error::InvalidConfig(msg) => write!(f, "{}", msg),
+        }
+    }
 }
 
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self { Error::IoErr(e) }
 }
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self { Error::TomlDeErr(e) }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(e: toml::ser::Error) -> Self { Error::TomlSerErr(e) }
+}
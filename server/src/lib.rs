@@ -1,15 +1,36 @@
-#![feature(integer_atomics, duration_as_u128, label_break_value, specialization)]
+#![feature(integer_atomics, duration_as_u128, duration_float, label_break_value, specialization)]
 
 // Crates
+#[macro_use]
+extern crate log;
 pub extern crate specs;
+extern crate world as world_crate;
 
 // Modules
+pub mod access;
 pub mod api;
+pub mod block_tick;
+pub mod chunk_gen;
+pub mod cmd;
+pub mod config;
+mod console;
+pub mod craft;
+pub mod dig;
 mod error;
+pub mod metrics;
 mod msg;
 pub mod net;
+pub mod permission;
 pub mod player;
+pub mod playerdb;
+pub mod rate_limit;
+pub mod schedule;
+pub mod spawn;
+pub mod sys;
+#[cfg(test)]
+mod tests;
 mod tick;
+pub mod weather;
 
 // Reexports
 pub use common::util::manager::Manager;
@@ -18,28 +39,68 @@ pub use crate::error::Error;
 
 // Standard
 use std::{
-    net::{TcpListener, ToSocketAddrs},
-    sync::atomic::Ordering,
-    time::Duration,
+    collections::HashMap,
+    fs, io, mem,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    sync::{atomic::Ordering, mpsc::RecvTimeoutError, Arc},
+    time::{Duration, Instant},
 };
 
 // Library
-use parking_lot::RwLock;
-use specs::{Entity, World};
+use parking_lot::{Mutex, RwLock};
+use specs::{Entity, Join, World};
+use vek::*;
 
 // Project
 use common::{
-    ecs,
-    util::{clock::Clock, manager::Managed, msg::ServerPostOffice},
+    ecs::{
+        self,
+        net::UidNode,
+        phys::{Pos, SpawnPoint},
+    },
+    net::{Encryption, UdpConfig, UdpMgr},
+    physics::config::PhysicsConfig,
+    terrain::{
+        chunk::{Block, Chunk, CHUNK_SIZE},
+        voxabs_to_voloffs, VolOffs, VoxAbs, WorldBorder,
+    },
+    util::{
+        clock::Clock,
+        manager::Managed,
+        msg::{ServerInfo, ServerMsg, ServerPostOffice},
+    },
 };
 
 // Local
 use crate::{
+    access::Access,
     api::Api,
+    block_tick::{BlockTicker, BlockTicks},
+    chunk_gen::{self, ChunkGenPool},
+    cmd::{process_cmd, Commands, Sender},
+    config::ServerConfig,
+    craft::RecipeBook,
+    metrics::{self, Metrics},
     net::{Client, DisconnectReason},
-    player::Player,
+    permission::{Permission, Permissions},
+    player::{BlockChangeCause, Player},
+    playerdb::PlayerDb,
+    rate_limit::RateLimits,
+    schedule::{Scheduler, ServerScheduler},
+    spawn::{SpawnRules, Spawned, Spawner},
+    sys::{LoadedChunks, TickConfig, TimeOfDay},
+    weather::WeatherSim,
+    world_crate::{GenConfig, Generator},
 };
 
+// Constants
+const CONSOLE_POLL: Duration = Duration::from_millis(500);
+// Players are also saved on disconnect, this is so that a crash doesn't lose too much
+const PLAYER_SAVE_FREQ: Duration = Duration::from_secs(60);
+// How much each tick's duration contributes to the smoothed TPS
+const TPS_SMOOTHING: f32 = 0.1;
+
 pub trait Payloads: Send + Sync + 'static {
     type Chunk: Send + Sync + 'static;
     type Entity: Send + Sync + 'static;
@@ -58,12 +119,104 @@ pub trait Payloads: Send + Sync + 'static {
             text
         ))
     }
+
+    /// Whether an entity may spawn at `pos`, once the server has found it a suitable place under `spawn_rules`
+    fn on_entity_spawn_attempt(&self, _api: &dyn Api, _pos: Vec3<f32>, _kind: &str) -> bool { true }
+
+    /// Called with each chunk once it's generated, before it's stored or sent to anyone, so that payloads can add to it
+    fn on_chunk_generated(&self, _api: &dyn Api, _pos: Vec3<VolOffs>, _chunk: &mut Chunk) {}
+
+    /// Whether a player may change the block at `pos` from `old` to `new`. Refused changes are undone on the player's
+    /// client.
+    fn on_block_change(
+        &self,
+        _api: &dyn Api,
+        _pos: Vec3<VoxAbs>,
+        _old: Block,
+        _new: Block,
+        _cause: BlockChangeCause,
+    ) -> bool {
+        true
+    }
+
+    /// Whether to read admin commands from stdin. Servers embedded in another program should leave this off.
+    fn console_enabled(&self) -> bool { false }
+
+    /// Where to load and save player permission levels. Without a file, everyone is a `Permission::Player`.
+    fn permissions_file(&self) -> Option<PathBuf> { None }
+
+    /// Where to load and save the whitelist and bans. Without a file, anyone may connect until banned, and bans only
+    /// last until the server restarts.
+    fn access_file(&self) -> Option<PathBuf> { None }
+
+    /// Where to save players' positions and health between connections. Without a file, players always start afresh.
+    fn player_db_file(&self) -> Option<PathBuf> { None }
+
+    /// Where to load the physics config that's shared with clients. Without a file, or anything missing from it, the
+    /// defaults are used.
+    fn physics_file(&self) -> Option<PathBuf> { None }
+
+    /// Where to load the recipes players can craft from. Without a file, there are none, but a file that can't be made
+    /// sense of stops the server from starting.
+    fn recipes_file(&self) -> Option<PathBuf> { None }
+
+    /// Where to serve metrics for scraping over plain HTTP. Without an address, metrics are only collected.
+    fn metrics_addr(&self) -> Option<SocketAddr> { None }
+
+    /// Whether to encrypt connections. By default nothing is.
+    fn encryption(&self) -> Encryption { Encryption::Off }
+
+    /// How fast clients may send chat, commands and movement updates
+    fn rate_limits(&self) -> RateLimits { RateLimits::default() }
+
+    /// The edge of the world. Nothing goes beyond it, and no terrain is generated there.
+    fn world_border(&self) -> WorldBorder { WorldBorder::default() }
+
+    /// Whether players may place any block without having it in their hand. By default, placing a block uses up one of
+    /// the items it's made from.
+    fn free_building(&self) -> bool { false }
+
+    /// How long dropped items lie in the world before disappearing
+    fn item_despawn(&self) -> Duration { Duration::from_secs(5 * 60) }
+
+    /// What the server spawns near players by itself. By default, nothing.
+    fn spawn_rules(&self) -> SpawnRules { SpawnRules::default() }
+
+    /// How blocks change by themselves over time. By default, nothing does. Payloads can have grass spread and die
+    /// back with `BlockTicks::grass`, and add rules of their own with `BlockTicks::register_block_tick`.
+    fn block_ticks(&self) -> BlockTicks { BlockTicks::default() }
 }
 
 pub struct Server<P: Payloads> {
     listener: TcpListener,
-    clock_tick_time: Duration,
+    // Offers clients UDP on the same port, for messages that don't need to arrive
+    udp: Arc<UdpMgr>,
     world: World,
+    config: ServerConfig,
+    generator: Arc<Generator>,
+    chunk_gen: ChunkGenPool,
+    // Ticks per second, smoothed over the last few seconds
+    tps: f32,
+    stopping: bool,
+    permissions: Permissions,
+    access: Access,
+    player_db: PlayerDb,
+    recipes: RecipeBook,
+    // Teleports waiting for their destination chunk to generate
+    teleports: HashMap<Entity, Vec3<f32>>,
+    // Players whose connection dropped, and when, kept around for a while in case they reconnect
+    suspended: HashMap<Entity, Instant>,
+    // Changes asked for through the `Api` that have to wait for the next tick
+    block_changes: Mutex<Vec<(Vec3<VoxAbs>, Block)>>,
+    disconnects: Mutex<Vec<(Entity, DisconnectReason)>>,
+    scheduler: Mutex<ServerScheduler>,
+    commands: RwLock<Commands>,
+    metrics: Arc<Metrics>,
+    metrics_listener: Option<TcpListener>,
+    rate_limits: RateLimits,
+    spawner: Spawner,
+    block_ticker: BlockTicker,
+    weather: WeatherSim,
     payload: P,
 }
 
@@ -78,18 +231,247 @@ impl<S> Wrapper<S> {
 }
 
 impl<P: Payloads> Server<P> {
+    /// Start a server listening on `bind_addr`, with the defaults for everything else a `ServerConfig` sets
     pub fn new<S: ToSocketAddrs>(payload: P, bind_addr: S) -> Result<Manager<Wrapper<Self>>, Error> {
+        Self::start(payload, TcpListener::bind(bind_addr)?, ServerConfig::default())
+    }
+
+    /// Start a server set up as `config` says, listening where it says
+    pub fn with_config(payload: P, config: ServerConfig) -> Result<Manager<Wrapper<Self>>, Error> {
+        config.validate().map_err(Error::InvalidConfig)?;
+        let listener = TcpListener::bind(config.bind_addr())?;
+        Self::start(payload, listener, config)
+    }
+
+    fn start(payload: P, listener: TcpListener, config: ServerConfig) -> Result<Manager<Wrapper<Self>>, Error> {
         let mut world = ecs::create_world();
         world.register::<Client>();
-        world.register::<Player>();
+        world.register::<Permission>();
+        world.register::<Spawned>();
+        sys::setup(&mut world);
+
+        let permissions = match payload.permissions_file() {
+            Some(path) => Permissions::load(path)?,
+            None => Permissions::new(),
+        };
+        let access = match payload.access_file() {
+            Some(path) => Access::load(path)?,
+            None => Access::new(),
+        };
+        let player_db = match payload.player_db_file() {
+            Some(path) => PlayerDb::load(path)?,
+            None => PlayerDb::new(),
+        };
+        let recipes = match payload.recipes_file() {
+            Some(path) => RecipeBook::load(path)?,
+            None => RecipeBook::default(),
+        };
+        // Carry on handing out uids from where the last run left off
+        world.add_resource(UidNode::from_state(player_db.uids().clone()));
+        world.add_resource(payload.world_border());
+        world.write_resource::<TickConfig>().item_despawn = payload.item_despawn();
+        if let Some(path) = payload.physics_file() {
+            world.add_resource(load_physics(path)?);
+        }
+
+        // Find somewhere for players to start, and get its terrain ready before anyone arrives
+        let generator = Arc::new(Generator::new(&GenConfig::default(), config.world_seed));
+        let spawn = generator.spawn_point();
+        world.add_resource(SpawnPoint(spawn));
+        let chunk_gen = ChunkGenPool::new(chunk_gen::DEFAULT_WORKERS, generator.clone());
+        chunk_gen.request(voxabs_to_voloffs(spawn.map(|e| e.floor() as VoxAbs), CHUNK_SIZE));
+
+        let metrics_listener = match payload.metrics_addr() {
+            Some(addr) => Some(TcpListener::bind(addr)?),
+            None => None,
+        };
+
+        let udp = match UdpMgr::bind(&listener.local_addr()?, UdpConfig::default()) {
+            Ok(udp) => udp,
+            Err(e) => {
+                warn!("Could not listen for UDP, so everything will go over TCP: {:?}", e);
+                UdpMgr::new()
+            },
+        };
+
+        let block_ticker = BlockTicker::new(payload.block_ticks(), config.world_seed);
 
         Ok(Manager::init(Wrapper(RwLock::new(Server {
-            listener: TcpListener::bind(bind_addr)?,
-            clock_tick_time: Duration::from_millis(0),
+            listener,
+            udp,
             world,
+            generator,
+            chunk_gen,
+            tps: config.tick_rate as f32,
+            config,
+            stopping: false,
+            permissions,
+            access,
+            player_db,
+            recipes,
+            teleports: HashMap::new(),
+            suspended: HashMap::new(),
+            block_changes: Mutex::new(vec![]),
+            disconnects: Mutex::new(vec![]),
+            scheduler: Mutex::new(Scheduler::new()),
+            commands: RwLock::new(Commands::builtin()),
+            metrics: Arc::new(Metrics::new()),
+            metrics_listener,
+            rate_limits: payload.rate_limits(),
+            spawner: Spawner::new(payload.spawn_rules()),
+            block_ticker,
+            weather: WeatherSim::new(),
             payload,
         }))))
     }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> { self.listener.local_addr() }
+
+    /// Where metrics are being served, if they are
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_listener.as_ref().and_then(|l| l.local_addr().ok())
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> { &self.metrics }
+
+    pub fn is_chunk_loaded(&self, pos: Vec3<VolOffs>) -> bool {
+        self.world.read_resource::<LoadedChunks>().0.contains_key(&pos)
+    }
+
+    /// Queue a chunk for generation if it isn't loaded or already pending. Chunks beyond the world border aren't
+    /// generated, and are filled with its barrier straight away instead.
+    pub fn request_chunk(&self, pos: Vec3<VolOffs>) {
+        if self.is_chunk_loaded(pos) {
+            return;
+        }
+        let border = self.world_border();
+        if border.is_chunk_outside(pos, CHUNK_SIZE) {
+            self.world
+                .write_resource::<LoadedChunks>()
+                .0
+                .insert(pos, border.barrier_chunk(CHUNK_SIZE));
+        } else {
+            self.chunk_gen.request(pos);
+        }
+    }
+
+    /// Drop a pending chunk request that nobody needs anymore, if generation hasn't started yet
+    pub fn cancel_chunk(&self, pos: Vec3<VolOffs>) -> bool { self.chunk_gen.cancel(pos) }
+
+    pub fn spawn_point(&self) -> Vec3<f32> { self.world.read_resource::<SpawnPoint>().0 }
+
+    /// Move the spawn point. Only players created from now on start there.
+    pub fn set_spawn_point(&mut self, pos: Vec3<f32>) { self.world.write_resource::<SpawnPoint>().0 = pos; }
+
+    pub fn time_of_day(&self) -> Duration { self.world.read_resource::<TimeOfDay>().time }
+
+    /// Change the time of day, telling every client
+    pub fn set_time_of_day(&mut self, time: Duration) {
+        self.world.write_resource::<TimeOfDay>().time = time;
+        self.sync_player_time();
+    }
+
+    pub fn world_border(&self) -> WorldBorder { *self.world.read_resource::<WorldBorder>() }
+
+    pub fn world_seed(&self) -> u64 { self.config.world_seed }
+
+    /// What the server says about itself to players who join and to anyone who asks
+    pub fn server_info(&self) -> ServerInfo {
+        ServerInfo {
+            name: self.config.server_name.clone(),
+            motd: self.config.motd.clone(),
+            player_count: self.player_count() as u32,
+            max_players: self.config.max_players,
+        }
+    }
+
+    /// How the server was set up, along with any changes `/reload` has made since
+    pub fn config(&self) -> &ServerConfig { &self.config }
+
+    /// Move the world border, telling every client. Chunks that end up beyond it are replaced with its barrier, chunks
+    /// that end up inside it are generated again, and players left beyond it are pushed back inside.
+    pub fn set_world_border(&mut self, border: WorldBorder) {
+        let old = mem::replace(&mut *self.world.write_resource::<WorldBorder>(), border);
+
+        let loaded = self.world.read_resource::<LoadedChunks>().0.keys().cloned().collect::<Vec<_>>();
+        for pos in loaded {
+            match (old.is_chunk_outside(pos, CHUNK_SIZE), border.is_chunk_outside(pos, CHUNK_SIZE)) {
+                (false, true) => self.store_chunk(pos, border.barrier_chunk(CHUNK_SIZE)),
+                (true, true) if old.barrier != border.barrier => {
+                    self.store_chunk(pos, border.barrier_chunk(CHUNK_SIZE))
+                },
+                (true, false) => {
+                    self.world.write_resource::<LoadedChunks>().0.remove(&pos);
+                    self.chunk_gen.request(pos);
+                },
+                _ => {},
+            }
+        }
+
+        let outside = (
+            &self.world.entities(),
+            &self.world.read_storage::<Pos>(),
+            &self.world.read_storage::<Client>(),
+        )
+            .join()
+            .filter(|(_, pos, _)| !border.contains(pos.0))
+            .map(|(entity, pos, _)| (entity, pos.0))
+            .collect::<Vec<_>>();
+        for (player, pos) in outside {
+            let pos = self.inside_border(pos);
+            self.teleport_now(player, pos);
+        }
+
+        self.broadcast_net_msg(ServerMsg::BorderUpdate(border));
+    }
+
+    /// How entities move, as clients were told
+    pub fn physics(&self) -> PhysicsConfig { *self.world.read_resource::<PhysicsConfig>() }
+
+    /// Change how entities move, telling every client. Changes last until the server restarts.
+    pub fn set_physics(&mut self, physics: PhysicsConfig) {
+        *self.world.write_resource::<PhysicsConfig>() = physics;
+        self.broadcast_net_msg(ServerMsg::PhysicsUpdate(physics));
+    }
+
+    pub fn tps(&self) -> f32 { self.tps }
+
+    pub fn target_tps(&self) -> f32 { self.config.tick_rate as f32 }
+
+    /// Disconnect every player and ask the server's workers to finish
+    pub fn stop(&mut self) {
+        let players: Vec<_> = (&self.world.entities(), &self.world.read_storage::<Player>())
+            .join()
+            .map(|(e, _)| e)
+            .collect();
+        for player in players {
+            self.disconnect_player(player, DisconnectReason::Shutdown);
+        }
+        self.stopping = true;
+    }
+
+    pub fn is_stopping(&self) -> bool { self.stopping }
+
+    // The incoming clients worker may be blocked in `accept`, so poke it with a connection to let it notice that it
+    // should exit
+    fn wake_listener(&self) {
+        if let Ok(addr) = self.local_addr() {
+            let _ = TcpStream::connect(addr);
+        }
+    }
+}
+
+// A missing file leaves everything at its default
+fn load_physics(path: PathBuf) -> Result<PhysicsConfig, Error> {
+    let physics: PhysicsConfig = match fs::read_to_string(&path) {
+        Ok(content) => toml::from_str(&content)?,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => PhysicsConfig::default(),
+        Err(e) => return Err(e.into()),
+    };
+    physics
+        .validate()
+        .map_err(|e| Error::InvalidConfig(format!("{}: {}", path.display(), e)))?;
+    Ok(physics)
 }
 
 impl<P: Payloads> Managed for Wrapper<Server<P>> {
@@ -97,41 +479,101 @@ impl<P: Payloads> Managed for Wrapper<Server<P>> {
         // Incoming clients worker
         Manager::add_worker(mgr, |srv, running, mut mgr| {
             let listener = srv.do_for_mut(|srv| srv.listener.try_clone().expect("Failed to clone server TcpListener"));
+            let udp = srv.do_for(|srv| srv.udp.clone());
 
-            while let (Ok((stream, _addr)), true) = (listener.accept(), running.load(Ordering::Relaxed)) {
-                // Convert the incoming stream to a postoffice ready to begin the connection handshake
-                if let Ok(po) = ServerPostOffice::to_client(stream) {
-                    Manager::add_worker(&mut mgr, move |srv, _, mgr| {
-                        if let Ok(client) = net::auth_client(srv, po) {
+            let encryption = srv.do_for(|srv| srv.payload.encryption());
+
+            while let (Ok((stream, addr)), true) = (listener.accept(), running.load(Ordering::Relaxed)) {
+                let udp = udp.clone();
+                // Agreeing on encryption waits on the client, so it's done off the accepting thread
+                Manager::add_worker(&mut mgr, move |srv, _, mgr| {
+                    // Convert the incoming stream to a postoffice ready to begin the connection handshake
+                    if let Ok(po) = ServerPostOffice::to_client(stream, udp, encryption) {
+                        if let Ok(Some(client)) = net::auth_client(srv, po, addr.ip()) {
                             net::handle_player_post(srv, client, mgr);
                         }
-                    });
-                }
+                    }
+                });
             }
         });
 
         // Tick workers
         Manager::add_worker(mgr, |srv, running, _| {
-            let mut clock = Clock::new(Duration::from_millis(20));
+            // The dispatcher isn't `Send`, so it lives on the tick thread rather than in the server
+            let mut dispatcher = sys::dispatcher();
+            let mut clock = Clock::new(srv.do_for(|srv| srv.config.tick_duration()));
+            let mut last_tick = Instant::now();
             while running.load(Ordering::Relaxed) {
-                srv.do_for_mut(|srv| srv.tick_once(clock.reference_duration()));
+                srv.do_for_mut(|srv| {
+                    let start = Instant::now();
+                    srv.tick_once(&mut dispatcher, clock.reference_duration());
+                    srv.metrics.record_tick(start.elapsed());
+                    srv.update_metrics();
+                });
                 clock.tick();
-                srv.do_for_mut(|srv| srv.clock_tick_time += clock.reference_duration());
+
+                let tick_secs = last_tick.elapsed().as_float_secs() as f32;
+                last_tick = Instant::now();
+                if tick_secs > 0.0 {
+                    srv.do_for_mut(|srv| srv.tps += (1.0 / tick_secs - srv.tps) * TPS_SMOOTHING);
+                }
             }
         });
 
-        // Sync Time worker
+        // Player save worker, which also lets go of players who didn't reconnect in time
         Manager::add_worker(mgr, |srv, running, _| {
-            let mut clock = Clock::new(Duration::from_millis(60000));
+            let mut clock = Clock::new(Duration::from_millis(500));
+            let mut last_save = Instant::now();
             while running.load(Ordering::Relaxed) {
-                srv.do_for_mut(|srv| srv.tick_time());
+                if last_save.elapsed() >= PLAYER_SAVE_FREQ {
+                    srv.do_for_mut(|srv| srv.save_players());
+                    last_save = Instant::now();
+                }
+                srv.do_for_mut(|srv| srv.expire_suspended());
                 clock.tick();
             }
         });
+
+        // Metrics worker, which only holds onto the metrics themselves so that scrapers can never hold up a tick
+        if let Some(listener) = self.do_for(|srv| {
+            srv.metrics_listener
+                .as_ref()
+                .map(|l| l.try_clone().expect("Failed to clone metrics TcpListener"))
+        }) {
+            let metrics = self.do_for(|srv| srv.metrics.clone());
+            Manager::add_worker(mgr, move |_, running, _| {
+                if let Err(e) = metrics::serve(listener, &metrics, running) {
+                    warn!("Stopped serving metrics: {}", e);
+                }
+            });
+        }
+
+        // Console worker
+        if self.do_for(|srv| srv.payload.console_enabled()) {
+            Manager::add_worker(mgr, |srv, running, mgr| {
+                // Reading stdin blocks, so it happens on its own thread and we only poll for lines here
+                let lines = console::read_lines();
+                while running.load(Ordering::Relaxed) {
+                    match lines.recv_timeout(CONSOLE_POLL) {
+                        Ok(line) => process_cmd(srv, &line, Sender::Console, &mgr),
+                        Err(RecvTimeoutError::Timeout) => {},
+                        // stdin was closed, keep the server running without a console
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+
+                    if srv.do_for(|srv| srv.is_stopping()) {
+                        running.store(false, Ordering::Relaxed);
+                        srv.do_for(|srv| srv.wake_listener());
+                    }
+                }
+            });
+        }
     }
 
     fn on_drop(&self, _: &mut Manager<Self>) {
         self.do_for(|srv| srv.listener.set_nonblocking(true))
             .expect("Failed to set nonblocking = true on server TcpListener");
+
+        self.do_for(|srv| srv.wake_listener());
     }
 }
@@ -0,0 +1,264 @@
+// Standard
+use std::{
+    fmt::Write as FmtWrite,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+// Project
+use common::net::traffic;
+
+// Local
+use crate::{
+    rate_limit::{MsgKind, RateStats},
+    world_crate::profile::{self, Stage},
+};
+
+// Constants
+// Upper bounds of the tick duration histogram's buckets, in seconds
+const TICK_BUCKET_COUNT: usize = 8;
+const TICK_BUCKETS: [f64; TICK_BUCKET_COUNT] = [0.005, 0.01, 0.02, 0.03, 0.05, 0.1, 0.25, 1.0];
+// How often the endpoint checks for scrapers, and whether it should stop
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+// A scraper that takes longer than this to send its request or read the reply is abandoned
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_REQUEST_LEN: usize = 8192;
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Numbers describing the server's health. Everything is an atomic so that updating them is cheap and reading them
+/// never needs the server lock.
+pub struct Metrics {
+    players: AtomicUsize,
+    loaded_chunks: AtomicUsize,
+    entities: AtomicUsize,
+    tick_buckets: [AtomicUsize; TICK_BUCKET_COUNT],
+    tick_count: AtomicUsize,
+    tick_micros: AtomicU64,
+    rate_limited: Arc<RateStats>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            players: AtomicUsize::new(0),
+            loaded_chunks: AtomicUsize::new(0),
+            entities: AtomicUsize::new(0),
+            tick_buckets: Default::default(),
+            tick_count: AtomicUsize::new(0),
+            tick_micros: AtomicU64::new(0),
+            rate_limited: Arc::new(RateStats::default()),
+        }
+    }
+
+    pub fn set_players(&self, n: usize) { self.players.store(n, Ordering::Relaxed); }
+
+    pub fn set_loaded_chunks(&self, n: usize) { self.loaded_chunks.store(n, Ordering::Relaxed); }
+
+    pub fn set_entities(&self, n: usize) { self.entities.store(n, Ordering::Relaxed); }
+
+    /// How many messages every client has had dropped for sending them too fast, which rate limiters count into
+    pub fn rate_limited(&self) -> &Arc<RateStats> { &self.rate_limited }
+
+    /// Add how long a tick spent working to the histogram
+    pub fn record_tick(&self, duration: Duration) {
+        let secs = duration.as_float_secs();
+        if let Some(bucket) = TICK_BUCKETS.iter().position(|le| secs <= *le) {
+            self.tick_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.tick_count.fetch_add(1, Ordering::Relaxed);
+        self.tick_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Everything, in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        gauge(&mut out, "veloren_players", "Connected players", self.players.load(Ordering::Relaxed));
+        gauge(&mut out, "veloren_loaded_chunks", "Chunks in memory", self.loaded_chunks.load(Ordering::Relaxed));
+        gauge(&mut out, "veloren_entities", "Entities in the world", self.entities.load(Ordering::Relaxed));
+
+        let name = "veloren_tick_duration_seconds";
+        let _ = writeln!(out, "# HELP {} Time spent working on each tick", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        // Buckets are cumulative, each one counting every tick at or below its bound
+        let mut total = 0;
+        for (le, bucket) in TICK_BUCKETS.iter().zip(self.tick_buckets.iter()) {
+            total += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, total);
+        }
+        let count = self.tick_count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, self.tick_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0);
+        let _ = writeln!(out, "{}_count {}", name, count);
+
+        counter(&mut out, "veloren_net_sent_bytes_total", "Bytes sent to clients", traffic::bytes_sent());
+        counter(&mut out, "veloren_net_received_bytes_total", "Bytes received from clients", traffic::bytes_received());
+        counter(
+            &mut out,
+            "veloren_net_corrupt_frames_total",
+            "Frames from clients thrown away as damaged or malformed",
+            traffic::corrupt_frames(),
+        );
+        counter(
+            &mut out,
+            "veloren_net_bad_packets_total",
+            "Packets from clients thrown away for not holding a valid message",
+            traffic::bad_packets(),
+        );
+
+        let name = "veloren_rate_limited_messages_total";
+        let _ = writeln!(out, "# HELP {} Messages from clients dropped for coming too fast", name);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for kind in MsgKind::ALL.iter() {
+            let _ = writeln!(out, "{}{{kind=\"{}\"}} {}", name, kind.name(), self.rate_limited.dropped(*kind));
+        }
+
+        // Only there when the world was built to time its generation
+        if let Some(stats) = profile::stats() {
+            counter(&mut out, "veloren_worldgen_chunks_total", "Chunks generated", stats.chunks as usize);
+
+            let name = "veloren_worldgen_seconds_total";
+            let _ = writeln!(out, "# HELP {} Time spent generating chunks", name);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, stats.total.as_float_secs());
+
+            // The stages don't add up to the total, which also counts the time between them
+            let name = "veloren_worldgen_stage_seconds_total";
+            let _ = writeln!(out, "# HELP {} Time spent in each stage of generating chunks", name);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for stage in Stage::ALL.iter() {
+                let secs = stats.stage(*stage).as_float_secs();
+                let _ = writeln!(out, "{}{{stage=\"{}\"}} {}", name, stage.name(), secs);
+            }
+        }
+        out
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: usize) {
+    let _ = write!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}\n", name, help, name, name, value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: usize) {
+    let _ = write!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}\n", name, help, name, name, value);
+}
+
+/// Answer every HTTP request on `listener` with the current metrics until `running` is cleared. Scrapers are served one
+/// at a time on the calling thread, so a slow one can only hold up other scrapers.
+pub fn serve(listener: TcpListener, metrics: &Metrics, running: &AtomicBool) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+    while running.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let _ = scrape(stream, metrics);
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+fn scrape(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+    stream.set_write_timeout(Some(SCRAPE_TIMEOUT))?;
+
+    // Whatever was asked for, the answer is the same, so the request only needs reading up to the end of its headers
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") && request.len() < MAX_REQUEST_LEN {
+        match stream.read(&mut buf)? {
+            0 => break,
+            n => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    let body = metrics.render();
+    write!(
+        stream,
+        "HTTP/1.0 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    use crate::rate_limit::{RateLimiter, RateLimits, Verdict};
+
+    fn value<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+        text.lines()
+            .find(|line| line.starts_with(name) && line[name.len()..].starts_with(' '))
+            .map(|line| &line[name.len() + 1..])
+    }
+
+    #[test]
+    fn tick_buckets_are_cumulative() {
+        let metrics = Metrics::new();
+        metrics.record_tick(Duration::from_millis(3));
+        metrics.record_tick(Duration::from_millis(15));
+        metrics.record_tick(Duration::from_secs(2));
+
+        let text = metrics.render();
+        let bucket = |le: &str| value(&text, &format!("veloren_tick_duration_seconds_bucket{{le=\"{}\"}}", le));
+        assert_eq!(bucket("0.005"), Some("1"));
+        assert_eq!(bucket("0.01"), Some("1"));
+        assert_eq!(bucket("0.02"), Some("2"));
+        assert_eq!(bucket("1"), Some("2"));
+        // Ticks slower than every bound only show up in the last bucket
+        assert_eq!(bucket("+Inf"), Some("3"));
+        assert_eq!(value(&text, "veloren_tick_duration_seconds_count"), Some("3"));
+        assert_eq!(value(&text, "veloren_tick_duration_seconds_sum"), Some("2.018"));
+    }
+
+    #[test]
+    fn gauges_hold_their_latest_value() {
+        let metrics = Metrics::new();
+        metrics.set_players(4);
+        metrics.set_players(2);
+        metrics.set_loaded_chunks(12);
+        let text = metrics.render();
+        assert_eq!(value(&text, "veloren_players"), Some("2"));
+        assert_eq!(value(&text, "veloren_loaded_chunks"), Some("12"));
+        assert_eq!(value(&text, "veloren_entities"), Some("0"));
+    }
+
+    #[test]
+    fn rate_limited_messages_are_counted_by_kind() {
+        let metrics = Metrics::new();
+        let stats = Arc::new(RateStats::default());
+        let mut limiter = RateLimiter::new(&RateLimits::default(), stats, metrics.rate_limited().clone());
+        let now = Instant::now();
+        while limiter.check(MsgKind::Chat, now) != Verdict::Warn {}
+        limiter.check(MsgKind::Chat, now);
+
+        let text = metrics.render();
+        let dropped = |kind: &str| value(&text, &format!("veloren_rate_limited_messages_total{{kind=\"{}\"}}", kind));
+        assert_eq!(dropped("chat"), Some("2"));
+        assert_eq!(dropped("cmd"), Some("0"));
+        assert_eq!(dropped("movement"), Some("0"));
+    }
+
+    #[cfg(feature = "worldgen-profiling")]
+    #[test]
+    fn worldgen_stages_are_reported() {
+        let text = Metrics::new().render();
+        assert!(value(&text, "veloren_worldgen_chunks_total").is_some());
+        assert!(value(&text, "veloren_worldgen_seconds_total").is_some());
+        for stage in &["overworld", "structures", "blocks"] {
+            let name = format!("veloren_worldgen_stage_seconds_total{{stage=\"{}\"}}", stage);
+            assert!(value(&text, &name).is_some(), "Missing {}", name);
+        }
+    }
+}
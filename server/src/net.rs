@@ -1,41 +1,103 @@
 // Standard
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     fmt,
+    net::IpAddr,
     sync::{atomic::Ordering, Arc},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 // Library
 use specs::{saveload::Marker, Builder, Component, Entity, Join, VecStorage};
+use vek::*;
 
 // Project
 use common::{
     ecs::{
         net::UidMarker,
-        phys::{Dir, Pos, Vel},
+        phys::{Pos, Vel},
         NetComp,
     },
+    terrain::{VolOffs, VoxAbs, Voxel},
     util::{
         manager::Manager,
-        msg::{ClientMsg, ServerMsg, ServerPostOffice, SessionKind},
+        msg::{ClientMsg, ServerMsg, ServerPostOffice, SessionKind, LONG_TELEPORT, MAX_CHUNK_REQUEST},
         post::Incoming,
     },
 };
 
 // Local
-use crate::{api::Api, msg::process_chat_msg, Error, Payloads, Server, Wrapper};
+use crate::{
+    access::unix_now,
+    api::Api,
+    dig::DigTracker,
+    msg::process_chat_msg,
+    player::Player,
+    rate_limit::{MsgKind, RateLimiter, RateStats, Verdict},
+    sys::LoadedChunks,
+    Error, Payloads, Server, Wrapper,
+};
 
 // Constants
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 const PING_TIMEOUT: Duration = Duration::from_secs(10);
 const PING_FREQ: Duration = Duration::from_secs(2);
+// How far above a teleport destination to look for somewhere that isn't solid terrain
+const MAX_SURFACE_SEARCH: usize = 256;
 
 // Server
 
 #[derive(Debug)]
 pub struct Client {
     pub postoffice: Arc<Manager<ServerPostOffice>>,
+    /// Where the client connected from, so that banning the player turns away that address too
+    pub ip: IpAddr,
+    pub chunk_requests: VecDeque<Vec3<VolOffs>>,
+    /// The chunks the client has been sent and hasn't unloaded since, with the version it was sent. They're only sent
+    /// again once they've changed.
+    pub known_chunks: HashMap<Vec3<VolOffs>, u64>,
+    /// The entities that are only synced to players near them, like dropped items, that the client was told about on
+    /// the last tick. The client is told to remove any that have gone out of range since.
+    pub nearby_entities: HashSet<u64>,
+    /// Round trip time of the last ping
+    pub latency: Option<Duration>,
+    /// A forced position the client hasn't caught up with yet. Until it does, its position updates predate the teleport
+    /// and are ignored.
+    pub teleport: Option<Vec3<f32>>,
+    /// Since when the player has been held in place, waiting for the client to load the chunks around them after
+    /// joining or being moved far away. Their position updates are ignored until the client says it's ready.
+    pub loading: Option<Instant>,
+    /// When the player last attacked, to enforce a cooldown between attacks
+    pub last_attack: Option<Instant>,
+    /// The block the player is digging, to check they take long enough over it
+    pub digging: DigTracker,
+    /// When the player's position was last updated, to tell how far they could have moved since
+    pub last_move: Option<Instant>,
+    /// When the player was caught moving somewhere they couldn't have, oldest first. Only recent ones are kept.
+    pub violations: VecDeque<Instant>,
+    /// How many of the client's messages were dropped for coming too fast
+    pub rate_stats: Arc<RateStats>,
+}
+
+impl Client {
+    pub fn new(po: Manager<ServerPostOffice>, ip: IpAddr) -> Self {
+        Self {
+            postoffice: Arc::new(po),
+            ip,
+            chunk_requests: VecDeque::new(),
+            known_chunks: HashMap::new(),
+            nearby_entities: HashSet::new(),
+            latency: None,
+            teleport: None,
+            loading: Some(Instant::now()),
+            last_attack: None,
+            digging: DigTracker::default(),
+            last_move: None,
+            violations: VecDeque::new(),
+            rate_stats: Arc::new(RateStats::default()),
+        }
+    }
 }
 
 impl Component for Client {
@@ -48,6 +110,9 @@ pub enum DisconnectReason {
     Logout,
     Timeout,
     Kicked(String),
+    Shutdown,
+    /// The client kept sending messages far faster than it's allowed to
+    Flooding,
 }
 
 impl fmt::Display for DisconnectReason {
@@ -59,16 +124,20 @@ impl fmt::Display for DisconnectReason {
                 DisconnectReason::Logout => format!("Logout"),
                 DisconnectReason::Timeout => format!("Timedout"),
                 DisconnectReason::Kicked(msg) => format!("Kicked ({})", msg),
+                DisconnectReason::Shutdown => format!("Server shutting down"),
+                DisconnectReason::Flooding => format!("Sending too many messages"),
             }
         )
     }
 }
 
-// Authenticate a client. If authentication is successful,
+// Authenticate a client. If authentication is successful, returns the player's entity, or nothing if the client only
+// asked about the server.
 pub(crate) fn auth_client<P: Payloads>(
     srv: &Wrapper<Server<P>>,
     po: Manager<ServerPostOffice>,
-) -> Result<Entity, Error> {
+    ip: IpAddr,
+) -> Result<Option<Entity>, Error> {
     // Perform a connection handshake. If everything works out, create the player
     // First, wait for the correct `Connect` session
     let session = if let Ok(Incoming::Session(s)) = po.await_incoming() {
@@ -77,45 +146,106 @@ pub(crate) fn auth_client<P: Payloads>(
         return Err(Error::NoConnectSession);
     };
 
-    // Verify that the first session is a SessionKind::Connect
-    if let SessionKind::Connect = session.kind {
-    } else {
-        return Err(Error::InvalidConnectSession);
+    // Verify that the first session is a SessionKind::Connect, unless the client only wants to know about the server.
+    // That's answered without a player, so it doesn't take up a place on the server.
+    match session.kind {
+        SessionKind::Connect => {},
+        SessionKind::Status => {
+            if let Ok(ClientMsg::Status) = session.postbox.recv_timeout(CONNECT_TIMEOUT) {
+                let _ = session.postbox.send(ServerMsg::ServerInfo(srv.do_for(|srv| srv.server_info())));
+            }
+            return Ok(None);
+        },
+        _ => return Err(Error::InvalidConnectSession),
     }
 
     // Wait for a ClientMsg::Connect, thereby committing the client to connecting
-    let (alias, mode) = if let Ok(ClientMsg::Connect { alias, mode }) = session.postbox.recv_timeout(CONNECT_TIMEOUT) {
-        (alias, mode)
+    // Login tokens are ignored until there's an authentication service to check them with
+    let (alias, mode, resume) = if let Ok(ClientMsg::Connect {
+        alias,
+        mode,
+        session: resume,
+        ..
+    }) = session.postbox.recv_timeout(CONNECT_TIMEOUT)
+    {
+        (alias, mode, resume)
     } else {
         return Err(Error::NoConnectMsg);
     };
 
-    // Create the player's entity and return it
-    let (player, player_uid) = srv.do_for_mut(|srv| {
-        // Notify all other players
-        srv.broadcast_chat_msg(&format!("[{} has joined the server]", alias));
-
-        // Create a new player
-        let player = srv.create_player(alias.clone(), mode, po).build();
-
-        // Force an update to the player position to inform them where they are
-        srv.force_comp::<Pos>(player);
+    // Turn away anyone who's banned, or isn't whitelisted while the server is, telling them why
+    if let Err(reason) = srv.do_for(|srv| srv.access.check(&alias, Some(ip), unix_now())) {
+        let _ = session.postbox.send(ServerMsg::Disconnect { reason: reason.clone() });
+        return Err(Error::Refused(reason));
+    }
 
-        // Run the connecting player past the payload interface
-        srv.payload.on_player_connect(srv, player);
+    // Create the player's entity, or hand them back the one they left behind if they're reconnecting, and return it.
+    // Players are turned away once the server is full, unless they're coming back to the place they left. That's
+    // decided along with creating them, so that two players joining at once can't both take the last place. The
+    // postoffice is handed back to a player who's turned away, to tell them why.
+    let joined = srv.do_for_mut(|srv| {
+        let player = match resume.and_then(|token| srv.resume_player(&alias, mode, token)) {
+            Some(player) => {
+                let _ = srv.world.write_storage::<Client>().insert(player, Client::new(po, ip));
+                srv.broadcast_chat_msg(&format!("[{} has reconnected]", alias));
+
+                // Whatever the client predicted while it was cut off is out of date
+                srv.force_comp::<Pos>(player);
+                player
+            },
+            None if srv.player_count() >= srv.config.max_players as usize => return Err(po),
+            None => {
+                // Notify all other players
+                srv.broadcast_chat_msg(&format!("[{} has joined the server]", alias));
+
+                // Create a new player
+                let player = srv.create_player(alias.clone(), mode, po, ip).build();
+
+                // Put returning players back where they left off
+                srv.restore_player(player);
+
+                // Force an update to the player position to inform them where they are
+                srv.force_comp::<Pos>(player);
+
+                // Run the connecting player past the payload interface
+                srv.payload.on_player_connect(srv, player);
+                player
+            },
+        };
 
         // Find the uid for the player's character entity (if the player has a character)
         let player_uid = srv.world.read_storage::<UidMarker>().get(player).map(|sm| sm.id());
-        (player, player_uid)
+        let token = srv.world.read_storage::<Player>().get(player).map(|p| p.session).unwrap_or(0);
+        Ok((player, player_uid, token))
     });
+    let (player, player_uid, token) = match joined {
+        Ok(joined) => joined,
+        Err(_po) => {
+            let reason = "The server is full".to_string();
+            let _ = session.postbox.send(ServerMsg::Disconnect { reason: reason.clone() });
+            return Err(Error::Refused(reason));
+        },
+    };
 
     // Inform the client that they've successfully connected
     let _ = session.postbox.send(ServerMsg::Connected {
         player_uid,
-        time: srv.do_for(|srv| srv.clock_tick_time),
+        time: srv.do_for(|srv| srv.time_of_day()),
+        session: token,
+        physics: srv.do_for(|srv| srv.physics()),
+        border: srv.do_for(|srv| srv.world_border()),
+        world_seed: srv.do_for(|srv| srv.world_seed()),
     });
 
-    Ok(player)
+    // Only now does the client know which entity is theirs
+    srv.do_for(|srv| {
+        srv.send_net_msg(player, ServerMsg::ServerInfo(srv.server_info()));
+        srv.send_inventory(player);
+        srv.send_cmd_list(player);
+        srv.send_recipes(player);
+    });
+
+    Ok(Some(player))
 }
 
 pub(crate) fn handle_player_post<P: Payloads>(
@@ -123,53 +253,79 @@ pub(crate) fn handle_player_post<P: Payloads>(
     player: Entity,
     mut mgr: Manager<Wrapper<Server<P>>>,
 ) {
+    // This connection's postoffice. The player may reconnect on another one, after which this one no longer speaks
+    // for them.
+    let (po, mut limiter) = match srv.do_for(|srv| {
+        srv.world
+            .read_storage::<Client>()
+            .get(player)
+            .map(|p| {
+                let totals = srv.metrics.rate_limited().clone();
+                let limiter = RateLimiter::new(&srv.rate_limits, p.rate_stats.clone(), totals);
+                (p.postoffice.clone(), limiter)
+            })
+    }) {
+        Some(found) => found,
+        None => return,
+    };
+
     // Ping worker
+    let ping_po = po.clone();
     Manager::add_worker(&mut mgr, move |srv, running, _| {
-        if let Some(pb) = srv.do_for(|srv| {
-            srv.world
-                .read_storage::<Client>()
-                .get(player)
-                .map(|p| p.postoffice.create_postbox(SessionKind::Ping))
-        }) {
-            // Wait for pings, respond with another ping
-            while running.load(Ordering::Relaxed) {
-                thread::sleep(PING_FREQ);
-
-                // Send a ping response
-                if let Err(_) = pb.send(ServerMsg::Ping) {
-                    break;
-                }
+        let pb = ping_po.create_postbox(SessionKind::Ping);
 
-                // Await a ping response from the client
-                match pb.recv_timeout(PING_TIMEOUT) {
-                    Ok(ClientMsg::Ping) => {},
-                    _ => break, // Anything other than a ping over this session is invalid
-                }
+        // Wait for pings, respond with another ping
+        while running.load(Ordering::Relaxed) {
+            thread::sleep(PING_FREQ);
+
+            // Send a ping response, urgently so that the latency measured isn't how long it sat in a batch
+            let sent = Instant::now();
+            if let Err(_) = pb.send_urgent(ServerMsg::Ping) {
+                break;
             }
 
-            // Kick the player if the ping expires
-            srv.do_for_mut(|srv| srv.disconnect_player(player, DisconnectReason::Timeout));
+            // Await a ping response from the client
+            match pb.recv_timeout(PING_TIMEOUT) {
+                Ok(ClientMsg::Ping) => srv.do_for_mut(|srv| {
+                    if let Some(client) = srv.world.write_storage::<Client>().get_mut(player) {
+                        client.latency = Some(sent.elapsed());
+                    }
+                }),
+                _ => break, // Anything other than a ping over this session is invalid
+            }
         }
+
+        // The ping expired, but the player may just have lost their connection for a moment
+        srv.do_for_mut(|srv| srv.suspend_player(player, &ping_po));
     });
 
     // Await incoming sessions and one-shot messages
-    if let Some(po) = srv.do_for(|srv| {
-        srv.world
-            .read_storage::<Client>()
-            .get(player)
-            .map(|p| p.postoffice.clone())
-    }) {
-        while let Ok(msg) = po.await_incoming() {
-            match msg {
-                Incoming::Session(_session) => {}, // TODO: Something here
-                Incoming::Msg(msg) => handle_oneshot(srv, msg, player, &mgr),
-                Incoming::End => break,
-            }
+    while let Ok(msg) = po.await_incoming() {
+        match msg {
+            Incoming::Session(session) => match session.kind {
+                SessionKind::Disconnect => {
+                    srv.do_for_mut(|srv| srv.disconnect_player(player, DisconnectReason::Logout));
+                    return;
+                },
+                _ => {}, // TODO: Something here
+            },
+            Incoming::Msg(msg) => match MsgKind::of(&msg).map(|kind| limiter.check(kind, Instant::now())) {
+                None | Some(Verdict::Allow) => handle_oneshot(srv, msg, player, &mgr),
+                Some(Verdict::Drop) => {},
+                Some(Verdict::Warn) => {
+                    srv.do_for(|srv| srv.send_chat_msg(player, "[You're sending messages too fast, slow down]"))
+                },
+                Some(Verdict::Abuse) => {
+                    srv.do_for_mut(|srv| srv.disconnect_player(player, DisconnectReason::Flooding));
+                    return;
+                },
+            },
+            Incoming::End => break,
         }
     }
 
-    // Disconnect the client
-    srv.do_for_mut(|srv| srv.disconnect_player(player, DisconnectReason::Logout));
+    // The connection dropped without the player logging out, so keep them around in case they come back
+    srv.do_for_mut(|srv| srv.suspend_player(player, &po));
 }
 
 pub(crate) fn handle_oneshot<P: Payloads>(
@@ -180,14 +336,55 @@ pub(crate) fn handle_oneshot<P: Payloads>(
 ) {
     match msg {
         ClientMsg::ChatMsg { text } => process_chat_msg(srv, text, player, mgr),
-        ClientMsg::PlayerEntityUpdate { pos, vel, dir } => {
-            // Update the player's entity
-            srv.do_for_mut(|srv| {
-                srv.update_comp(player, Pos(pos));
-                srv.update_comp(player, Vel(vel));
-                srv.update_comp(player, Dir(dir));
-            });
+        ClientMsg::PlayerEntityUpdate {
+            pos,
+            vel,
+            dir,
+            move_mode,
+            jumping,
+        } => srv.do_for_mut(|srv| srv.handle_player_update(player, pos, vel, dir, move_mode, jumping)),
+        ClientMsg::RequestChunks { mut positions } => srv.do_for_mut(|srv| {
+            if positions.len() > MAX_CHUNK_REQUEST {
+                warn!("{:?} asked for {} chunks at once, ignoring the excess", player, positions.len());
+                positions.truncate(MAX_CHUNK_REQUEST);
+            }
+            for pos in positions.iter() {
+                srv.request_chunk(*pos);
+            }
+
+            if let Some(client) = srv.world.write_storage::<Client>().get_mut(player) {
+                for pos in positions {
+                    if !client.chunk_requests.contains(&pos) {
+                        client.chunk_requests.push_back(pos);
+                    }
+                }
+            }
+        }),
+        ClientMsg::ForgetChunks { positions } => srv.do_for_mut(|srv| {
+            if let Some(client) = srv.world.write_storage::<Client>().get_mut(player) {
+                client.chunk_requests.retain(|pos| !positions.contains(pos));
+                for pos in positions.iter() {
+                    client.known_chunks.remove(pos);
+                }
+            }
+            // Chunks nobody is waiting for anymore needn't be generated
+            let clients = srv.world.read_storage::<Client>();
+            for pos in positions {
+                if !clients.join().any(|client| client.chunk_requests.contains(&pos)) {
+                    srv.cancel_chunk(pos);
+                }
+            }
+        }),
+        ClientMsg::InventoryAction(action) => srv.do_for_mut(|srv| srv.handle_inventory_action(player, action)),
+        ClientMsg::SelectSlot { slot } => srv.do_for_mut(|srv| srv.handle_select_slot(player, slot)),
+        ClientMsg::Craft { recipe, count } => srv.do_for_mut(|srv| srv.handle_craft(player, recipe, count)),
+        ClientMsg::Attack { dir } => srv.do_for_mut(|srv| srv.handle_attack(player, dir)),
+        ClientMsg::SetBlock { pos, block } => srv.do_for_mut(|srv| srv.handle_set_block(player, pos, block)),
+        ClientMsg::DigProgress { pos, progress } => {
+            srv.do_for_mut(|srv| srv.handle_dig_progress(player, pos, progress))
         },
+        ClientMsg::Ready => srv.do_for_mut(|srv| srv.handle_player_ready(player)),
+        ClientMsg::Respawn => srv.do_for_mut(|srv| srv.handle_respawn(player)),
         _ => {},
     }
 }
@@ -218,43 +415,6 @@ impl<P: Payloads> Server<P> {
         self.world.read_storage::<T>().get(entity).map(|c| f(c))
     }
 
-    /// Update clients of a component's value, excepting those clients for whom that component is attributed
-    /// (e.g: a client won't get it's own player position sent back to it)
-    #[allow(dead_code)]
-    pub(crate) fn notify_comp<T: NetComp>(&self, entity: Entity) {
-        // Convert the component (if it exists and if it support it) to a CompStore
-        let store = if let Some(Some(s)) = self.world.read_storage::<T>().get(entity).map(|c| c.to_store()) {
-            s
-        } else {
-            return;
-        };
-
-        // Find the UID of the entity we're notifying clients of
-        let entity_uid = if let Some(u) = self.world.read_storage::<UidMarker>().get(entity) {
-            u.id()
-        } else {
-            return;
-        };
-
-        // Send the store to all clients that need it
-        for (client_uid, client) in (
-            &self.world.read_storage::<UidMarker>(),
-            &self.world.read_storage::<Client>(),
-        )
-            .join()
-        {
-            let client_uid = client_uid.id();
-
-            // Don't notify a client of information concerning itself
-            if client_uid != entity_uid {
-                let _ = client.postoffice.send_one(ServerMsg::CompUpdate {
-                    uid: entity_uid,
-                    store: store.clone(),
-                });
-            }
-        }
-    }
-
     /// Update *all* clients of a component's value, overriding any other values a client may have had
     #[allow(dead_code)]
     pub(crate) fn force_comp<T: NetComp + Clone>(&self, entity: Entity) {
@@ -276,19 +436,68 @@ impl<P: Payloads> Server<P> {
         self.broadcast_net_msg(ServerMsg::CompUpdate {
             uid: entity_uid,
             store: store.clone(),
+            forced: true,
         });
     }
 
-    pub(crate) fn sync_players(&self) {
-        // For each entity in the world...
-        // TODO: Add a notion of range? Don't update clients of entities that are nowhere near them
-        for entity in self.world.entities().join() {
-            // Notify clients of the following components...
-            self.notify_comp::<Pos>(entity);
-            self.notify_comp::<Vel>(entity);
-            self.notify_comp::<Dir>(entity);
+    /// Move an entity now, telling every client including its own. A player moved far is held in place until their
+    /// client has loaded the chunks around them. Returns `false` if the entity has no position.
+    pub(crate) fn teleport_now(&mut self, entity: Entity, pos: Vec3<f32>) -> bool {
+        let old = match self.do_for_comp(entity, |old: &Pos| old.0) {
+            Some(old) => old,
+            None => return false,
+        };
+        self.update_comp(entity, Pos(pos));
+        self.update_comp(entity, Vel(Vec3::zero()));
+        if let Some(client) = self.world.write_storage::<Client>().get_mut(entity) {
+            client.teleport = Some(pos);
+            if old.distance(pos) > LONG_TELEPORT {
+                client.loading = Some(Instant::now());
+            }
+        }
+        self.force_comp::<Pos>(entity);
+        true
+    }
+
+    /// Carry out pending teleports whose destination chunk has finished generating
+    pub(crate) fn apply_teleports(&mut self) {
+        let teleports = self.teleports.drain().collect::<Vec<_>>();
+        let mut waiting = HashMap::new();
+        for (entity, pos) in teleports {
+            match self.find_surface(pos) {
+                Ok(pos) => {
+                    self.teleport_now(entity, pos);
+                },
+                Err(chunk) => {
+                    if self.world.is_alive(entity) {
+                        // Ask again in case the request was cancelled
+                        self.request_chunk(chunk);
+                        waiting.insert(entity, pos);
+                    }
+                },
+            }
+        }
+        self.teleports = waiting;
+    }
+
+    /// Find somewhere an entity moved to `pos` can stand: `pos` itself, or the first spot above it that isn't inside
+    /// solid terrain. Fails with the position of a chunk that has to be loaded before we can tell.
+    pub(crate) fn find_surface(&self, pos: Vec3<f32>) -> Result<Vec3<f32>, Vec3<VolOffs>> {
+        let chunks = self.world.read_resource::<LoadedChunks>();
+        let start = pos.map(|e| e.floor() as VoxAbs);
+        let mut vox = start;
+        for _ in 0..MAX_SURFACE_SEARCH {
+            if !chunks.block_at(vox)?.is_solid() && !chunks.block_at(vox + Vec3::unit_z())?.is_solid() {
+                return Ok(if vox == start {
+                    pos
+                } else {
+                    Vec3::new(pos.x, pos.y, vox.z as f32)
+                });
+            }
+            vox.z += 1;
         }
+        Ok(pos)
     }
 
-    pub(crate) fn sync_player_time(&self) { self.broadcast_net_msg(ServerMsg::TimeUpdate(self.clock_tick_time)); }
+    pub(crate) fn sync_player_time(&self) { self.broadcast_net_msg(ServerMsg::TimeUpdate(self.time_of_day())); }
 }
@@ -0,0 +1,178 @@
+// Standard
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+// Library
+use serde_derive::{Deserialize, Serialize};
+use specs::{Component, VecStorage};
+
+// Local
+use crate::Error;
+
+/// What a player is allowed to do. Levels are ordered, so each level can do everything the ones below it can.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Player,
+    Moderator,
+    Admin,
+}
+
+impl Default for Permission {
+    fn default() -> Self { Permission::Player }
+}
+
+impl Permission {
+    pub fn from_name(name: &str) -> Option<Permission> {
+        match name {
+            "player" => Some(Permission::Player),
+            "moderator" => Some(Permission::Moderator),
+            "admin" => Some(Permission::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Permission::Player => "player",
+            Permission::Moderator => "moderator",
+            Permission::Admin => "admin",
+        }
+    }
+}
+
+impl Component for Permission {
+    type Storage = VecStorage<Self>;
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PermissionsFile {
+    #[serde(default)]
+    players: BTreeMap<String, Permission>,
+}
+
+/// The permission level of each player that has been granted one, keyed by alias. Anyone not listed is a
+/// `Permission::Player`.
+///
+/// Aliases aren't authenticated yet, so anybody can connect under an operator's alias. This should be keyed by account
+/// once accounts exist.
+pub struct Permissions {
+    path: Option<PathBuf>,
+    levels: BTreeMap<String, Permission>,
+}
+
+impl Permissions {
+    /// Permissions that are only kept in memory
+    pub fn new() -> Permissions {
+        Permissions {
+            path: None,
+            levels: BTreeMap::new(),
+        }
+    }
+
+    /// Load permissions from a file, which changes are written back to. A missing file means nobody has been granted
+    /// anything yet.
+    pub fn load(path: PathBuf) -> Result<Permissions, Error> {
+        let levels = match File::open(&path) {
+            Ok(mut file) => {
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                Permissions::parse(&content)?
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Permissions {
+            path: Some(path),
+            levels,
+        })
+    }
+
+    fn parse(content: &str) -> Result<BTreeMap<String, Permission>, Error> {
+        Ok(toml::from_str::<PermissionsFile>(content)?.players)
+    }
+
+    fn to_toml(&self) -> Result<String, Error> {
+        Ok(toml::to_string(&PermissionsFile {
+            players: self.levels.clone(),
+        })?)
+    }
+
+    pub fn get(&self, alias: &str) -> Permission { self.levels.get(alias).cloned().unwrap_or_default() }
+
+    /// Change a player's permission level, saving the change if the permissions came from a file
+    pub fn set(&mut self, alias: &str, permission: Permission) -> Result<(), Error> {
+        if permission == Permission::Player {
+            self.levels.remove(alias);
+        } else {
+            self.levels.insert(alias.to_string(), permission);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        File::create(path)?.write_all(self.to_toml()?.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_are_ordered() {
+        assert!(Permission::Player < Permission::Moderator);
+        assert!(Permission::Moderator < Permission::Admin);
+        assert_eq!(Permission::from_name(Permission::Moderator.name()), Some(Permission::Moderator));
+        assert_eq!(Permission::from_name("superuser"), None);
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let mut perms = Permissions::new();
+        perms.set("zesterer", Permission::Admin).unwrap();
+        perms.set("terah", Permission::Moderator).unwrap();
+        perms.set("nobody", Permission::Player).unwrap();
+
+        let content = perms.to_toml().unwrap();
+        let levels = Permissions::parse(&content).unwrap();
+        assert_eq!(levels, perms.levels);
+        assert!(!levels.contains_key("nobody"));
+        assert_eq!(Permissions::parse("").unwrap(), BTreeMap::new());
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let path = std::env::temp_dir().join(format!("veloren-permissions-{}.toml", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        // A missing file is fine, and is created on the first change
+        let mut perms = Permissions::load(path.clone()).unwrap();
+        assert_eq!(perms.get("zesterer"), Permission::Player);
+        perms.set("zesterer", Permission::Admin).unwrap();
+        perms.set("terah", Permission::Moderator).unwrap();
+
+        let mut perms = Permissions::load(path.clone()).unwrap();
+        assert_eq!(perms.get("zesterer"), Permission::Admin);
+        assert_eq!(perms.get("terah"), Permission::Moderator);
+
+        perms.set("zesterer", Permission::Player).unwrap();
+        assert_eq!(Permissions::load(path.clone()).unwrap().get("zesterer"), Permission::Player);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,21 +1,78 @@
 // Standard
-use std::sync::Arc;
+use std::{
+    f32::consts::PI,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 // Library
-use specs::{Builder, Component, EntityBuilder, VecStorage};
+use rand;
+use specs::{saveload::{MarkedBuilder, Marker}, Builder, Component, Entity, EntityBuilder, Join, VecStorage};
 use vek::*;
 
 // Project
 use common::{
-    ecs::{phys::Pos, CreateUtil, NetComp},
+    audio::SoundId,
+    ecs::{
+        character::{Health, MAX_HEALTH},
+        inventory::{HeldItem, Inventory, InventoryAction, HOTBAR_SLOTS},
+        net::{UidMarker, UidNode},
+        phys::{Dir, MoveMode, Pos, Vel},
+        CreateUtil, NetComp,
+    },
+    physics::config::PhysicsConfig,
+    terrain::{chunk::Block, VoxAbs, Voxel},
     util::{
         manager::Manager,
-        msg::{CompStore, PlayMode, ServerPostOffice},
+        msg::{CompStore, PlayMode, ServerMsg, ServerPostOffice},
     },
 };
 
 // Local
-use crate::{net::Client, Payloads, Server};
+use crate::{
+    api::Api,
+    dig::DigVerdict,
+    net::{Client, DisconnectReason},
+    playerdb::PlayerData,
+    sys::{Dead, ItemDrop, LoadedChunks, Outbox, ProjectileSpec, Target},
+    Payloads, Server,
+};
+
+// Constants
+// How far in front of a player the items they drop land
+const DROP_DIST: f32 = 2.0;
+// How long a player has to wait between attacks
+const ATTACK_COOLDOWN: Duration = Duration::from_millis(500);
+// How far to the side of where they're facing a player may aim
+const MAX_AIM_ANGLE: f32 = PI / 3.0;
+// How high above a player's feet their projectiles are launched from
+const ATTACK_HEIGHT: f32 = 1.5;
+const PROJECTILE_SPEED: f32 = 40.0;
+// How long a player whose connection dropped is kept in the world, waiting for them to reconnect
+pub(crate) const SESSION_GRACE: Duration = Duration::from_secs(60);
+// How close a client must report itself to a forced position before its position updates are trusted again
+const TELEPORT_ACK_DIST: f32 = 4.0;
+// How long a player is held in place waiting for their client to load the chunks around them, in case it never says
+const LOAD_TIMEOUT: Duration = Duration::from_secs(15);
+// How much faster than the physics config allows a player may move, since their client's ticks aren't the same length
+// as the ones the limits are worked out for
+const MOVE_LEEWAY: f32 = 1.1;
+// Allowed on top of the time between updates, since updates can bunch up on their way here or while the server is busy
+const MOVE_GRACE: Duration = Duration::from_millis(250);
+// How far inside the world border a player who reached it is put back
+const BORDER_PUSHBACK: f32 = 1.0;
+// A player who goes quiet for longer doesn't get to cover any more ground for it
+const MAX_MOVE_INTERVAL: Duration = Duration::from_secs(1);
+// How far above a player's feet to check they aren't inside terrain. Low enough to fit under anything they can crouch
+// beneath.
+const BODY_CHECK_HEIGHT: f32 = 0.5;
+// Players caught moving impossibly this many times within `VIOLATION_WINDOW` are kicked
+pub(crate) const MAX_VIOLATIONS: usize = 5;
+const VIOLATION_WINDOW: Duration = Duration::from_secs(30);
+// How far from a block a player can change it. Clients only let players reach 6 blocks, but the server's idea of where
+// the player is can lag behind.
+const MAX_BUILD_REACH: f32 = 10.0;
 
 // Player
 
@@ -23,6 +80,8 @@ use crate::{net::Client, Payloads, Server};
 pub struct Player {
     pub alias: String,
     pub mode: PlayMode,
+    /// Handed to the client when it connects, so that it can prove who it is if it has to reconnect
+    pub session: u64,
 }
 
 impl Component for Player {
@@ -38,6 +97,15 @@ impl NetComp for Player {
     }
 }
 
+/// How a player is changing a block, as asked of `Payloads::on_block_change`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlockChangeCause {
+    /// The player put a block there, or cleared it in one go
+    Placed(Entity),
+    /// The player finished digging the block out
+    Dug(Entity),
+}
+
 // Server
 
 impl<P: Payloads> Server<P> {
@@ -46,15 +114,505 @@ impl<P: Payloads> Server<P> {
         alias: String,
         mode: PlayMode,
         po: Manager<ServerPostOffice>,
+        ip: IpAddr,
     ) -> EntityBuilder {
+        let permission = self.permissions.get(&alias);
+        let spawn = self.spawn_point();
         match mode {
             PlayMode::Headless => self.world.create_entity(),
             PlayMode::Character => self.world.create_character(alias.clone()),
         }
-        .with(Player { alias, mode })
-        .with(Client {
-            postoffice: Arc::new(po),
+        .with(Player {
+            alias,
+            mode,
+            session: rand::random(),
+        })
+        .with(Client::new(po, ip))
+        .with(Pos(spawn))
+        .with(permission)
+    }
+
+    /// Give a newly connected player back the state they had when they last left. Players that connected under the same
+    /// alias as someone who's still online start afresh.
+    pub(crate) fn restore_player(&mut self, player: Entity) {
+        let (alias, mode) = match self.world.read_storage::<Player>().get(player) {
+            Some(p) => (p.alias.clone(), p.mode),
+            None => return,
+        };
+
+        // A headless session's position is no use to a character, and vice versa. Someone who left while dead comes
+        // back respawned.
+        let data = match self.player_db.claim(&alias, player) {
+            Some(data) if data.mode == mode && data.health != Some(0) => data,
+            _ => return,
+        };
+
+        self.update_comp(player, Dir(data.dir));
+        if let (Some(health), Some(comp)) = (data.health, self.world.write_storage::<Health>().get_mut(player)) {
+            comp.0 = health;
+        }
+        self.set_entity_pos(player, data.pos);
+    }
+
+    /// Hold on to a player whose connection `po` dropped without them logging out, so that they can pick up where they
+    /// left off if they reconnect within `SESSION_GRACE`. Nothing happens if the player has already moved on to another
+    /// connection, or been disconnected.
+    pub(crate) fn suspend_player(&mut self, player: Entity, po: &Arc<Manager<ServerPostOffice>>) {
+        match self.world.read_storage::<Client>().get(player) {
+            Some(client) if Arc::ptr_eq(&client.postoffice, po) => client.postoffice.stop(),
+            _ => return,
+        }
+        self.world.write_storage::<Client>().remove(player);
+        self.save_player(player, false);
+        self.suspended.insert(player, Instant::now());
+
+        if let Some(player_comp) = self.world.read_storage::<Player>().get(player) {
+            self.broadcast_chat_msg(&format!("[{} lost connection]", player_comp.alias));
+        }
+    }
+
+    /// Find the suspended player a reconnecting client is asking to resume. The client must be connecting under the
+    /// same alias and in the same mode as before.
+    pub(crate) fn find_suspended(&self, alias: &str, mode: PlayMode, session: u64) -> Option<Entity> {
+        self.suspended.keys().cloned().find(|player| {
+            self.world
+                .read_storage::<Player>()
+                .get(*player)
+                .map(|p| p.alias == alias && p.mode == mode && p.session == session)
+                .unwrap_or(false)
         })
-        .with(Pos(Vec3::new(0.0, 0.0, 215.0)))
+    }
+
+    /// Find the suspended player a reconnecting client is asking to resume, and take them out of suspension
+    pub(crate) fn resume_player(&mut self, alias: &str, mode: PlayMode, session: u64) -> Option<Entity> {
+        let player = self.find_suspended(alias, mode, session)?;
+        self.suspended.remove(&player);
+        Some(player)
+    }
+
+    /// How many players there are, counting those who lost connection and may yet come back
+    pub fn player_count(&self) -> usize { self.world.read_storage::<Player>().join().count() }
+
+    /// Disconnect suspended players who haven't come back in time
+    pub(crate) fn expire_suspended(&mut self) {
+        let expired = self
+            .suspended
+            .iter()
+            .filter(|(_, since)| since.elapsed() >= SESSION_GRACE)
+            .map(|(player, _)| *player)
+            .collect::<Vec<_>>();
+        for player in expired {
+            self.disconnect_player(player, DisconnectReason::Timeout);
+        }
+    }
+
+    fn player_data(&self, player: Entity) -> Option<(String, PlayerData)> {
+        let player_comp = self.world.read_storage::<Player>().get(player)?.clone();
+        // A player waiting on a teleport belongs at its destination, not wherever they're waiting
+        let pos = match self.teleports.get(&player) {
+            Some(pos) => *pos,
+            None => self.world.read_storage::<Pos>().get(player)?.0,
+        };
+
+        Some((
+            player_comp.alias,
+            PlayerData {
+                pos,
+                dir: self
+                    .world
+                    .read_storage::<Dir>()
+                    .get(player)
+                    .map(|dir| dir.0)
+                    .unwrap_or(Vec2::unit_y()),
+                health: self.world.read_storage::<Health>().get(player).map(|health| health.0),
+                mode: player_comp.mode,
+            },
+        ))
+    }
+
+    /// Save a player's current state. Releasing them lets the next player to connect under their alias have it.
+    pub(crate) fn save_player(&mut self, player: Entity, release: bool) {
+        if let Some((alias, data)) = self.player_data(player) {
+            self.player_db.update(&alias, player, data);
+            if release {
+                self.player_db.release(&alias, player);
+            }
+            self.write_player_db();
+        }
+    }
+
+    /// Save the state of every connected player
+    pub(crate) fn save_players(&mut self) {
+        let players = (&self.world.entities(), &self.world.read_storage::<Player>())
+            .join()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+        for player in players {
+            if let Some((alias, data)) = self.player_data(player) {
+                self.player_db.update(&alias, player, data);
+            }
+        }
+        self.write_player_db();
+    }
+
+    fn write_player_db(&mut self) {
+        self.player_db.set_uids(self.world.read_resource::<UidNode>().state());
+        if let Err(e) = self.player_db.save() {
+            warn!("Could not save players: {:?}", e);
+        }
+    }
+
+    /// Carry out something a player asked to do with their inventory. Anything they aren't allowed to do is refused
+    /// with a chat message, and they're sent their inventory again in case they thought it held something it doesn't.
+    pub(crate) fn handle_inventory_action(&mut self, player: Entity, action: InventoryAction) {
+        let result = match self.world.write_storage::<Inventory>().get_mut(player) {
+            Some(inv) => match action {
+                InventoryAction::Drop { slot, amount } => inv.take(slot, amount).map(Some),
+                InventoryAction::Swap { a, b } => inv.swap(a, b).map(|_| None),
+            },
+            None => return,
+        };
+
+        match result {
+            Ok(Some(item)) => {
+                // Thrown the way the player is facing, or dropped at their feet if they aren't facing any way
+                let (pos, ahead) = match (
+                    self.world.read_storage::<Pos>().get(player),
+                    self.world.read_storage::<Dir>().get(player),
+                ) {
+                    (Some(pos), Some(dir)) => (pos.0, Vec2::new(dir.0.x.sin(), dir.0.x.cos())),
+                    (Some(pos), None) => (pos.0, Vec2::zero()),
+                    _ => (Vec3::zero(), Vec2::zero()),
+                };
+                let drop_pos = pos + Vec3::from(ahead * DROP_DIST);
+
+                // Clients find out about the item when entities are next synced
+                self.world
+                    .create_entity()
+                    .with(Pos(drop_pos))
+                    .with(ItemDrop::new(item))
+                    .marked::<UidMarker>()
+                    .build();
+            },
+            Ok(None) => {},
+            Err(e) => self.send_chat_msg(player, &format!("[{}]", e)),
+        }
+        self.send_inventory(player);
+    }
+
+    /// Hold the items in one of a player's hotbar slots. Other players find out when entities are next synced.
+    pub(crate) fn handle_select_slot(&mut self, player: Entity, slot: usize) {
+        if slot < HOTBAR_SLOTS {
+            self.update_comp(player, HeldItem(slot));
+        }
+    }
+
+    /// Tell a player what's in their inventory
+    pub(crate) fn send_inventory(&self, player: Entity) {
+        let uid = match self.world.read_storage::<UidMarker>().get(player) {
+            Some(uid) => uid.id(),
+            None => return,
+        };
+        if let Some(store) = self.world.read_storage::<Inventory>().get(player).and_then(|inv| inv.to_store()) {
+            self.send_net_msg(
+                player,
+                ServerMsg::CompUpdate {
+                    uid,
+                    store,
+                    forced: true,
+                },
+            );
+        }
+    }
+
+    /// Take a player's word for where they are and how they're moving, as long as they could have got there. A player
+    /// who moved further than they could have since their last update, or into solid terrain, is put back where they
+    /// were, and kicked if they keep doing it. Updates the client sent before it caught up with a forced position are
+    /// ignored, and the update that catches up is taken as is.
+    pub(crate) fn handle_player_update(
+        &mut self,
+        player: Entity,
+        pos: Vec3<f32>,
+        vel: Vec3<f32>,
+        dir: Vec2<f32>,
+        move_mode: MoveMode,
+        jumping: bool,
+    ) {
+        // The dead lie where they fell
+        if self.world.read_storage::<Dead>().contains(player) {
+            return;
+        }
+
+        let now = Instant::now();
+        let elapsed = match self.world.write_storage::<Client>().get_mut(player) {
+            // Held in place until the client has the terrain around them, so that nobody sees them fall through it
+            Some(client) if client.loading.is_some() => return,
+            Some(client) => {
+                let elapsed = match client.teleport {
+                    Some(tgt) if pos.distance(tgt) > TELEPORT_ACK_DIST => return,
+                    Some(_) => None,
+                    None => Some(client.last_move.map(|last| now - last).unwrap_or(MAX_MOVE_INTERVAL)),
+                };
+                client.teleport = None;
+                elapsed
+            },
+            None => return,
+        };
+
+        if let Some(elapsed) = elapsed {
+            let old = match self.world.read_storage::<Pos>().get(player) {
+                Some(old) => old.0,
+                None => return,
+            };
+            if !self.is_possible_move(old, pos, elapsed, jumping) {
+                self.reject_player_move(player, old, now);
+                return;
+            }
+        }
+
+        // Running into the border isn't cheating, but it's as far as anyone gets
+        if !self.world_border().contains(pos) {
+            let pos = self.inside_border(pos);
+            self.teleport_now(player, pos);
+            return;
+        }
+
+        if let Some(client) = self.world.write_storage::<Client>().get_mut(player) {
+            client.last_move = Some(now);
+        }
+        self.update_comp(player, Pos(pos));
+        self.update_comp(player, Vel(vel));
+        self.update_comp(player, Dir(dir));
+        self.update_comp(player, move_mode);
+    }
+
+    /// The closest point to `pos` that's comfortably inside the world border
+    pub(crate) fn inside_border(&self, pos: Vec3<f32>) -> Vec3<f32> { self.world_border().clamp(pos, BORDER_PUSHBACK) }
+
+    /// Let a player move, now that their client has loaded the chunks around them
+    pub(crate) fn handle_player_ready(&mut self, player: Entity) {
+        if let Some(client) = self.world.write_storage::<Client>().get_mut(player) {
+            client.loading = None;
+        }
+    }
+
+    /// Stop holding players in place whose clients have been loading for too long
+    pub(crate) fn expire_loading(&mut self) {
+        for client in (&mut self.world.write_storage::<Client>()).join() {
+            if client.loading.map(|since| since.elapsed() > LOAD_TIMEOUT).unwrap_or(false) {
+                debug!("A client took too long to load the chunks around its player, letting it move anyway");
+                client.loading = None;
+            }
+        }
+    }
+
+    /// Whether a player could have moved from `from` to `to` in `elapsed`, jumping or not. Terrain that isn't loaded
+    /// doesn't get in the way.
+    fn is_possible_move(&self, from: Vec3<f32>, to: Vec3<f32>, elapsed: Duration, jumping: bool) -> bool {
+        let secs = (elapsed.min(MAX_MOVE_INTERVAL) + MOVE_GRACE).as_float_secs() as f32;
+        let offs = to - from;
+        let physics = *self.world.read_resource::<PhysicsConfig>();
+        // Hopping up onto a block can happen on top of a jump
+        let climb = physics.block_hop_speed.max(physics.swim_speed());
+        let max_rise = if jumping { climb + physics.jump_speed() } else { climb };
+        // Written so that NaNs fail
+        let in_reach = Vec2::from(offs).magnitude() <= physics.top_speed() * MOVE_LEEWAY * secs
+            && offs.z <= max_rise * MOVE_LEEWAY * secs
+            && -offs.z <= physics.terminal_velocity * MOVE_LEEWAY * secs;
+
+        let body = (to + Vec3::unit_z() * BODY_CHECK_HEIGHT).map(|e| e.floor() as VoxAbs);
+        let in_terrain = self
+            .world
+            .read_resource::<LoadedChunks>()
+            .block_at(body)
+            .map(|block| block.is_solid())
+            .unwrap_or(false);
+
+        in_reach && !in_terrain
+    }
+
+    /// Put a player who moved impossibly back at `pos`, or kick them if they've been doing it too often
+    fn reject_player_move(&mut self, player: Entity, pos: Vec3<f32>, now: Instant) {
+        let violations = match self.world.write_storage::<Client>().get_mut(player) {
+            Some(client) => {
+                client.violations.push_back(now);
+                while client
+                    .violations
+                    .front()
+                    .map(|first| now - *first > VIOLATION_WINDOW)
+                    .unwrap_or(false)
+                {
+                    client.violations.pop_front();
+                }
+                client.violations.len()
+            },
+            None => return,
+        };
+
+        let alias = self
+            .world
+            .read_storage::<Player>()
+            .get(player)
+            .map(|p| p.alias.clone())
+            .unwrap_or_default();
+        if violations >= MAX_VIOLATIONS {
+            warn!("Kicking {} for repeatedly moving somewhere they couldn't have", alias);
+            self.disconnect_player(player, DisconnectReason::Kicked("Impossible movement".to_string()));
+        } else {
+            debug!("Putting {} back after moving somewhere they couldn't have", alias);
+            self.teleport_now(player, pos);
+        }
+    }
+
+    /// Fire a projectile for a player in the direction they asked. Attacks that come too soon after the last one, or
+    /// that aim somewhere the player isn't facing, are ignored, as are attacks from dead players.
+    pub(crate) fn handle_attack(&mut self, player: Entity, dir: Vec3<f32>) {
+        if self.world.read_storage::<Dead>().contains(player) {
+            return;
+        }
+        let dir = match dir.try_normalized() {
+            Some(dir) => dir,
+            None => return,
+        };
+        let (pos, yaw) = match (
+            self.world.read_storage::<Pos>().get(player),
+            self.world.read_storage::<Dir>().get(player),
+        ) {
+            (Some(pos), Some(dir)) => (pos.0, dir.0.x),
+            _ => return,
+        };
+
+        // Straight up and down is always allowed, since there's no way to tell which way that's facing
+        let facing = Vec2::new(yaw.sin(), yaw.cos());
+        if let Some(aim) = Vec2::from(dir).try_normalized() {
+            if aim.dot(facing) < MAX_AIM_ANGLE.cos() {
+                return;
+            }
+        }
+
+        let now = Instant::now();
+        match self.world.write_storage::<Client>().get_mut(player) {
+            Some(Client {
+                last_attack: Some(last),
+                ..
+            }) if now.duration_since(*last) < ATTACK_COOLDOWN => return,
+            Some(client) => client.last_attack = Some(now),
+            None => return,
+        }
+
+        self.fire_projectile(
+            player,
+            pos + Vec3::unit_z() * ATTACK_HEIGHT,
+            dir * PROJECTILE_SPEED,
+            ProjectileSpec::default(),
+        );
+    }
+
+    /// Bring a dead player back to life with full health at the spawn point. Their client takes back control of them
+    /// once it's told where that is. Players that aren't dead are left alone.
+    pub(crate) fn handle_respawn(&mut self, player: Entity) {
+        if self.world.write_storage::<Dead>().remove(player).is_none() {
+            return;
+        }
+        self.update_comp(player, Health(MAX_HEALTH));
+        self.force_comp::<Health>(player);
+        let spawn = self.spawn_point();
+        self.set_entity_pos(player, spawn);
+    }
+    /// Change a block for a player. Anything but air has to come out of the stack in their hand, and solid blocks can
+    /// only be taken away by digging them out. Changes out of their reach, or that they have nothing to place with, are
+    /// refused, and their client is told what the block really is, since it will have made the change already.
+    pub(crate) fn handle_set_block(&mut self, player: Entity, pos: Vec3<VoxAbs>, block: Block) {
+        let dist = match self.world.read_storage::<Pos>().get(player) {
+            Some(p) => p.0.distance(pos.map(|e| e as f32 + 0.5)),
+            None => return,
+        };
+        let old = self.world.read_resource::<LoadedChunks>().block_at(pos);
+
+        // Blocks that can't be dug can't be built over either, and the payload may protect others. Clearing a solid
+        // block would skip the time it takes to dig it, so that goes through `handle_dig_progress` instead.
+        let allowed = dist <= MAX_BUILD_REACH
+            && old.map(|old| old.is_breakable() || !old.is_solid()).unwrap_or(true)
+            && old.map(|old| block != Block::AIR || !old.is_solid()).unwrap_or(true)
+            && old
+                .map(|old| self.payload_allows(pos, old, block, BlockChangeCause::Placed(player)))
+                .unwrap_or(true);
+        if allowed && (block == Block::AIR || self.payload.free_building() || self.use_held_block(player, block)) {
+            self.set_block(pos, block);
+            self.play_sound_at(SoundId::PLACE_BLOCK, pos.map(|e| e as f32 + 0.5), 1.0);
+        } else if let Ok(block) = old {
+            self.send_net_msg(player, ServerMsg::BlockUpdate { pos, block });
+        }
+    }
+
+    // Whether the payload lets a player's change to a block go ahead
+    fn payload_allows(&self, pos: Vec3<VoxAbs>, old: Block, new: Block, cause: BlockChangeCause) -> bool {
+        self.payload.on_block_change(self, pos, old, new, cause)
+    }
+
+    /// Use up one of the items in a player's hand to place `block`. Fails if they aren't holding anything that places
+    /// it, or if the stack has run out. Either way they're sent their inventory, so their hotbar shows what's left.
+    fn use_held_block(&mut self, player: Entity, block: Block) -> bool {
+        let slot = match self.world.read_storage::<HeldItem>().get(player) {
+            Some(held) => held.0,
+            None => return false,
+        };
+        let used = match self.world.write_storage::<Inventory>().get_mut(player) {
+            Some(inv) => inv.get(slot).and_then(|item| item.kind.block()) == Some(block) && inv.take(slot, 1).is_ok(),
+            None => false,
+        };
+        self.send_inventory(player);
+        used
+    }
+
+    /// Keep track of how far a player has got digging out a block, and break it once they're done if they've taken
+    /// long enough. A dig that finishes too soon, or out of reach, leaves the block where it was and the player's
+    /// client is told so. Nearby players are told how the dig is going, so they can see it.
+    pub(crate) fn handle_dig_progress(&mut self, player: Entity, pos: Vec3<VoxAbs>, progress: f32) {
+        let center = pos.map(|e| e as f32 + 0.5);
+        let (in_reach, uid) = match (
+            self.world.read_storage::<Pos>().get(player),
+            self.world.read_storage::<UidMarker>().get(player),
+        ) {
+            (Some(p), Some(uid)) => (p.0.distance(center) <= MAX_BUILD_REACH, uid.id()),
+            _ => return,
+        };
+        let block = match self.world.read_resource::<LoadedChunks>().block_at(pos) {
+            Ok(block) => block,
+            Err(_) => return,
+        };
+        let alive = !self.world.read_storage::<Dead>().contains(player);
+
+        let now = Instant::now();
+        let verdict = match self.world.write_storage::<Client>().get_mut(player) {
+            Some(client) if in_reach && alive => client.digging.progress(pos, block, progress, client.latency, now),
+            Some(client) if progress >= 1.0 => {
+                client.digging.stop();
+                DigVerdict::Rejected
+            },
+            Some(client) => client.digging.stop(),
+            None => return,
+        };
+
+        let progress = match verdict {
+            DigVerdict::Digging => progress,
+            DigVerdict::Stopped => 0.0,
+            DigVerdict::Broken if self.payload_allows(pos, block, Block::AIR, BlockChangeCause::Dug(player)) => {
+                self.set_block(pos, Block::AIR);
+                self.play_sound_at(SoundId::PLACE_BLOCK, center, 1.0);
+                0.0
+            },
+            DigVerdict::Broken => {
+                self.send_net_msg(player, ServerMsg::BlockUpdate { pos, block });
+                0.0
+            },
+            DigVerdict::Rejected => {
+                self.send_net_msg(player, ServerMsg::BlockUpdate { pos, block });
+                0.0
+            },
+        };
+        self.world
+            .read_resource::<Outbox>()
+            .send(Target::Near(center), ServerMsg::DigProgress { uid, pos, progress });
     }
 }
@@ -0,0 +1,310 @@
+// Standard
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+// Library
+use serde_derive::{Deserialize, Serialize};
+use specs::Entity;
+use vek::*;
+
+// Project
+use common::{ecs::net::UidState, util::msg::PlayMode};
+
+// Local
+use crate::Error;
+
+/// What's remembered about a player between connections
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerData {
+    pub pos: Vec3<f32>,
+    pub dir: Vec2<f32>,
+    pub health: Option<u32>,
+    pub mode: PlayMode,
+}
+
+// How a player is written to the file. Plain values rather than tables keep TOML happy with the field order.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    pos: [f32; 3],
+    dir: [f32; 2],
+    #[serde(default)]
+    health: Option<u32>,
+    mode: PlayMode,
+}
+
+impl From<&PlayerData> for Entry {
+    fn from(data: &PlayerData) -> Self {
+        Entry {
+            pos: data.pos.into_array(),
+            dir: data.dir.into_array(),
+            health: data.health,
+            mode: data.mode,
+        }
+    }
+}
+
+impl From<Entry> for PlayerData {
+    fn from(entry: Entry) -> Self {
+        PlayerData {
+            pos: Vec3::from(entry.pos),
+            dir: Vec2::from(entry.dir),
+            health: entry.health,
+            mode: entry.mode,
+        }
+    }
+}
+
+// Where the uid allocator's state is kept, next to the players. It's saved with them so that uids handed out before a
+// restart aren't handed out again after it.
+fn uids_path(path: &Path) -> PathBuf { path.with_extension("uids.toml") }
+
+// Write a file through a temporary one first, so that a crash mid-save doesn't lose everything
+fn write_file(path: &Path, content: &str) -> Result<(), Error> {
+    let tmp = path.with_extension("tmp");
+    File::create(&tmp)?.write_all(content.as_bytes())?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Saved player state, keyed by alias.
+///
+/// Only one connected entity may own an alias's state at a time. Anyone else connecting under the same alias gets a
+/// fresh spawn and isn't saved, so they can't overwrite the first player's progress.
+pub struct PlayerDb {
+    path: Option<PathBuf>,
+    players: BTreeMap<String, PlayerData>,
+    owners: HashMap<String, Entity>,
+    uids: UidState,
+}
+
+impl PlayerDb {
+    /// A database that is only kept in memory
+    pub fn new() -> PlayerDb {
+        PlayerDb {
+            path: None,
+            players: BTreeMap::new(),
+            owners: HashMap::new(),
+            uids: UidState::default(),
+        }
+    }
+
+    /// Load players from a file, which `save` writes back to. A missing file means nobody has played yet. Entries that
+    /// can't be read are discarded with a warning rather than stopping the server from starting.
+    pub fn load(path: PathBuf) -> Result<PlayerDb, Error> {
+        let players = match File::open(&path) {
+            Ok(mut file) => {
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                let (players, corrupt) = PlayerDb::parse(&content)?;
+                for alias in corrupt {
+                    warn!("Discarding unreadable saved data for player '{}'", alias);
+                }
+                players
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let uids = match File::open(uids_path(&path)) {
+            Ok(mut file) => {
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                toml::from_str(&content).unwrap_or_else(|_| {
+                    warn!("Discarding unreadable uid allocator state, so old uids may be handed out again");
+                    UidState::default()
+                })
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => UidState::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(PlayerDb {
+            path: Some(path),
+            players,
+            owners: HashMap::new(),
+            uids,
+        })
+    }
+
+    // Returns the readable players along with the aliases of any that weren't
+    fn parse(content: &str) -> Result<(BTreeMap<String, PlayerData>, Vec<String>), Error> {
+        let entries = toml::from_str::<BTreeMap<String, toml::Value>>(content)?;
+
+        let mut players = BTreeMap::new();
+        let mut corrupt = vec![];
+        for (alias, value) in entries {
+            match value.try_into::<Entry>() {
+                Ok(entry) => {
+                    players.insert(alias, entry.into());
+                },
+                Err(_) => corrupt.push(alias),
+            }
+        }
+        Ok((players, corrupt))
+    }
+
+    fn to_toml(&self) -> Result<String, Error> {
+        let entries = self
+            .players
+            .iter()
+            .map(|(alias, data)| (alias.clone(), Entry::from(data)))
+            .collect::<BTreeMap<_, _>>();
+        Ok(toml::to_string(&entries)?)
+    }
+
+    pub fn get(&self, alias: &str) -> Option<&PlayerData> { self.players.get(alias) }
+
+    /// The uid allocator's state as of the last save
+    pub fn uids(&self) -> &UidState { &self.uids }
+
+    /// Record the uid allocator's state, to be written with the players
+    pub fn set_uids(&mut self, uids: UidState) { self.uids = uids; }
+
+    /// Take ownership of an alias's state for a newly connected entity, returning what was saved for it. Returns `None`
+    /// without taking ownership if another entity already owns the alias.
+    pub fn claim(&mut self, alias: &str, entity: Entity) -> Option<PlayerData> {
+        if self.owners.get(alias).map(|owner| *owner != entity).unwrap_or(false) {
+            return None;
+        }
+        self.owners.insert(alias.to_string(), entity);
+        self.players.get(alias).cloned()
+    }
+
+    pub fn is_owner(&self, alias: &str, entity: Entity) -> bool { self.owners.get(alias) == Some(&entity) }
+
+    /// Record an entity's current state, if it owns the alias. Nothing is written to disk until `save`.
+    pub fn update(&mut self, alias: &str, entity: Entity, data: PlayerData) {
+        if self.is_owner(alias, entity) {
+            self.players.insert(alias.to_string(), data);
+        }
+    }
+
+    /// Give up ownership of an alias, so that the next entity to connect under it is restored
+    pub fn release(&mut self, alias: &str, entity: Entity) {
+        if self.is_owner(alias, entity) {
+            self.owners.remove(alias);
+        }
+    }
+
+    /// Write every player to the file, along with the uid allocator's state, if the database came from one
+    pub fn save(&self) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        write_file(path, &self.to_toml()?)?;
+        write_file(&uids_path(path), &toml::to_string(&self.uids)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::{Builder, World};
+
+    fn data(x: f32) -> PlayerData {
+        PlayerData {
+            pos: Vec3::new(x, 2.0, 300.5),
+            dir: Vec2::new(0.0, 1.0),
+            health: Some(75),
+            mode: PlayMode::Character,
+        }
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let mut world = World::new();
+        let (a, b) = (world.create_entity().build(), world.create_entity().build());
+
+        let mut db = PlayerDb::new();
+        db.claim("zesterer", a);
+        db.update("zesterer", a, data(1.0));
+        db.claim("terah", b);
+        db.update(
+            "terah",
+            b,
+            PlayerData {
+                health: None,
+                mode: PlayMode::Headless,
+                ..data(-4.0)
+            },
+        );
+
+        let (players, corrupt) = PlayerDb::parse(&db.to_toml().unwrap()).unwrap();
+        assert_eq!(players, db.players);
+        assert!(corrupt.is_empty());
+    }
+
+    #[test]
+    fn corrupt_entries_are_discarded() {
+        let content = r#"
+            [zesterer]
+            pos = [1.0, 2.0, 3.0]
+            dir = [0.0, 1.0]
+            health = 100
+            mode = "Character"
+
+            [terah]
+            pos = "somewhere"
+        "#;
+        let (players, corrupt) = PlayerDb::parse(content).unwrap();
+        assert_eq!(players.get("zesterer").map(|p| p.pos), Some(Vec3::new(1.0, 2.0, 3.0)));
+        assert!(!players.contains_key("terah"));
+        assert_eq!(corrupt, vec!["terah".to_string()]);
+    }
+
+    #[test]
+    fn only_one_owner_per_alias() {
+        let mut world = World::new();
+        let (first, second) = (world.create_entity().build(), world.create_entity().build());
+
+        let mut db = PlayerDb::new();
+        db.players.insert("zesterer".to_string(), data(1.0));
+
+        // The second connection gets a fresh spawn and can't overwrite the saved state
+        assert_eq!(db.claim("zesterer", first), Some(data(1.0)));
+        assert_eq!(db.claim("zesterer", second), None);
+        db.update("zesterer", second, data(99.0));
+        assert_eq!(db.get("zesterer"), Some(&data(1.0)));
+
+        // Once the first leaves, the alias can be claimed again
+        db.update("zesterer", first, data(2.0));
+        db.release("zesterer", first);
+        assert_eq!(db.claim("zesterer", second), Some(data(2.0)));
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let path = std::env::temp_dir().join(format!("veloren-players-{}.toml", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut world = World::new();
+        let player = world.create_entity().build();
+
+        let uids = UidState {
+            next: 12,
+            free: vec![3, 7],
+        };
+        let mut db = PlayerDb::load(path.clone()).unwrap();
+        assert_eq!(db.claim("zesterer", player), None);
+        assert_eq!(db.uids(), &UidState::default());
+        db.update("zesterer", player, data(5.0));
+        db.set_uids(uids.clone());
+        db.save().unwrap();
+
+        let db = PlayerDb::load(path.clone()).unwrap();
+        assert_eq!(db.get("zesterer"), Some(&data(5.0)));
+        assert_eq!(db.uids(), &uids);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(uids_path(&path)).unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+// Standard
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+// Project
+use common::util::msg::ClientMsg;
+
+// Constants
+// Clients that keep sending this many times faster than they're allowed to...
+const ABUSE_FACTOR: f32 = 10.0;
+// ...for this long are disconnected
+const ABUSE_WINDOW: Duration = Duration::from_secs(10);
+
+/// A number of messages per period. Up to `count` can be sent at once, after which they're allowed at an even rate.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rate {
+    pub count: u32,
+    pub per: Duration,
+}
+
+impl Rate {
+    pub fn new(count: u32, per: Duration) -> Self { Self { count, per } }
+
+    fn per_sec(&self) -> f32 { self.count as f32 / self.per.as_float_secs() as f32 }
+}
+
+/// How fast each client may send each kind of message. Anything faster is dropped.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RateLimits {
+    pub chat: Rate,
+    pub cmds: Rate,
+    /// Clients send their player's position every tick, so this needs to allow for a little more than their tick rate
+    pub movement: Rate,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            chat: Rate::new(4, Duration::from_secs(5)),
+            cmds: Rate::new(8, Duration::from_secs(5)),
+            movement: Rate::new(100, Duration::from_secs(1)),
+        }
+    }
+}
+
+/// The kinds of message that are rate limited
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MsgKind {
+    Chat,
+    Cmd,
+    Movement,
+}
+
+impl MsgKind {
+    pub const ALL: [MsgKind; 3] = [MsgKind::Chat, MsgKind::Cmd, MsgKind::Movement];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MsgKind::Chat => "chat",
+            MsgKind::Cmd => "cmd",
+            MsgKind::Movement => "movement",
+        }
+    }
+
+    /// The kind of a message, if it's one that's rate limited
+    pub fn of(msg: &ClientMsg) -> Option<MsgKind> {
+        match msg {
+            ClientMsg::ChatMsg { text } if text.starts_with('/') => Some(MsgKind::Cmd),
+            ClientMsg::ChatMsg { .. } => Some(MsgKind::Chat),
+            ClientMsg::Cmd { .. } => Some(MsgKind::Cmd),
+            ClientMsg::PlayerEntityUpdate { .. } => Some(MsgKind::Movement),
+            _ => None,
+        }
+    }
+}
+
+/// What to do with a message
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Verdict {
+    Allow,
+    Drop,
+    /// Drop the message, and tell the client they're sending messages too fast
+    Warn,
+    /// The client has been flooding the server for a while, and should be disconnected
+    Abuse,
+}
+
+/// How many of a client's messages were dropped for coming too fast, or of every client's
+#[derive(Debug, Default)]
+pub struct RateStats {
+    chat: AtomicUsize,
+    cmds: AtomicUsize,
+    movement: AtomicUsize,
+}
+
+impl RateStats {
+    pub fn dropped(&self, kind: MsgKind) -> usize { self.counter(kind).load(Ordering::Relaxed) }
+
+    fn counter(&self, kind: MsgKind) -> &AtomicUsize {
+        match kind {
+            MsgKind::Chat => &self.chat,
+            MsgKind::Cmd => &self.cmds,
+            MsgKind::Movement => &self.movement,
+        }
+    }
+}
+
+// Holds up to `capacity` tokens, refilling continuously
+struct Bucket {
+    capacity: f32,
+    per_sec: f32,
+    tokens: f32,
+    last: Instant,
+}
+
+impl Bucket {
+    fn full(capacity: f32, per_sec: f32, now: Instant) -> Self {
+        Self {
+            capacity,
+            per_sec,
+            tokens: capacity,
+            last: now,
+        }
+    }
+
+    // Take a token if there's one to take
+    fn take(&mut self, now: Instant) -> bool {
+        if now > self.last {
+            let elapsed = (now - self.last).as_float_secs() as f32;
+            self.tokens = (self.tokens + elapsed * self.per_sec).min(self.capacity);
+            self.last = now;
+        }
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// A kind of message's budget, and a much bigger one that every message counts against. The bigger one refills at half
+// the abusive rate, so a client has to keep sending faster than that to run it down, and runs out after `ABUSE_WINDOW`
+// of sending at the abusive rate.
+struct Limit {
+    budget: Bucket,
+    abuse: Bucket,
+}
+
+impl Limit {
+    fn new(rate: Rate, now: Instant) -> Self {
+        let abuse_rate = rate.per_sec() * ABUSE_FACTOR / 2.0;
+        Self {
+            budget: Bucket::full(rate.count as f32, rate.per_sec(), now),
+            abuse: Bucket::full(abuse_rate * ABUSE_WINDOW.as_float_secs() as f32, abuse_rate, now),
+        }
+    }
+}
+
+/// Keeps track of how fast one client is sending messages
+pub struct RateLimiter {
+    chat: Limit,
+    cmds: Limit,
+    movement: Limit,
+    // Whether the client has been told about its chat being dropped since it last got a message through
+    warned: bool,
+    stats: Arc<RateStats>,
+    // The whole server's count, which outlives the client's
+    totals: Arc<RateStats>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &RateLimits, stats: Arc<RateStats>, totals: Arc<RateStats>) -> Self {
+        let now = Instant::now();
+        Self {
+            chat: Limit::new(limits.chat, now),
+            cmds: Limit::new(limits.cmds, now),
+            movement: Limit::new(limits.movement, now),
+            warned: false,
+            stats,
+            totals,
+        }
+    }
+
+    /// Decide what to do with a message of the given kind that arrived at `now`. Dropped chat is only warned about once
+    /// until some gets through again; other kinds of message are dropped silently.
+    pub fn check(&mut self, kind: MsgKind, now: Instant) -> Verdict {
+        let limit = match kind {
+            MsgKind::Chat => &mut self.chat,
+            MsgKind::Cmd => &mut self.cmds,
+            MsgKind::Movement => &mut self.movement,
+        };
+
+        if !limit.abuse.take(now) {
+            return Verdict::Abuse;
+        }
+        if limit.budget.take(now) {
+            if kind == MsgKind::Chat {
+                self.warned = false;
+            }
+            return Verdict::Allow;
+        }
+
+        self.stats.counter(kind).fetch_add(1, Ordering::Relaxed);
+        self.totals.counter(kind).fetch_add(1, Ordering::Relaxed);
+        if kind == MsgKind::Chat && !self.warned {
+            self.warned = true;
+            Verdict::Warn
+        } else {
+            Verdict::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> (RateLimiter, Arc<RateStats>) {
+        let stats = Arc::new(RateStats::default());
+        let limiter = RateLimiter::new(&RateLimits::default(), stats.clone(), Arc::new(RateStats::default()));
+        (limiter, stats)
+    }
+
+    #[test]
+    fn bursts_are_allowed_up_to_the_budget() {
+        let (mut limiter, stats) = limiter();
+        let now = Instant::now();
+
+        let verdicts = (0..6).map(|_| limiter.check(MsgKind::Chat, now)).collect::<Vec<_>>();
+        assert_eq!(
+            verdicts,
+            vec![
+                Verdict::Allow,
+                Verdict::Allow,
+                Verdict::Allow,
+                Verdict::Allow,
+                Verdict::Warn,
+                Verdict::Drop
+            ]
+        );
+        assert_eq!(stats.dropped(MsgKind::Chat), 2);
+
+        // Each kind of message has its own budget
+        assert_eq!(limiter.check(MsgKind::Cmd, now), Verdict::Allow);
+        assert_eq!(limiter.check(MsgKind::Movement, now), Verdict::Allow);
+        assert_eq!(stats.dropped(MsgKind::Cmd), 0);
+    }
+
+    #[test]
+    fn budgets_refill_over_time() {
+        let (mut limiter, _) = limiter();
+        let now = Instant::now();
+        while limiter.check(MsgKind::Chat, now) == Verdict::Allow {}
+
+        assert_eq!(limiter.check(MsgKind::Chat, now + Duration::from_millis(100)), Verdict::Drop);
+        // Once there's been time for another message, one gets through, and the next one dropped is warned about again
+        let later = now + Duration::from_secs(2);
+        assert_eq!(limiter.check(MsgKind::Chat, later), Verdict::Allow);
+        assert_eq!(limiter.check(MsgKind::Chat, later), Verdict::Warn);
+    }
+
+    #[test]
+    fn sustained_flooding_is_abuse() {
+        let (mut limiter, _) = limiter();
+        let start = Instant::now();
+
+        // Ten times the allowed rate of chat, which is 0.8 messages a second
+        let mut abused = None;
+        for i in 0..200 {
+            let now = start + Duration::from_millis(125 * i);
+            if limiter.check(MsgKind::Chat, now) == Verdict::Abuse {
+                abused = Some(now - start);
+                break;
+            }
+        }
+        let abused = abused.expect("Flooding was never noticed");
+        assert!(abused >= ABUSE_WINDOW - Duration::from_secs(1) && abused <= ABUSE_WINDOW + Duration::from_secs(1));
+
+        // Going a little over the limit is only ever dropped
+        let (mut limiter, _) = limiter();
+        for i in 0..1000 {
+            let now = start + Duration::from_millis(500 * i);
+            assert_ne!(limiter.check(MsgKind::Chat, now), Verdict::Abuse);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+// Standard
+use std::{
+    collections::BTreeMap,
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+// Library
+use parking_lot::Mutex;
+
+// Local
+use crate::api::Api;
+
+// Constants
+// Tasks that take longer than this are warned about, since the whole tick waits for them
+const SLOW_TASK: Duration = Duration::from_millis(5);
+
+/// Stops a repeating task. Dropping the handle leaves the task running.
+#[derive(Clone, Debug)]
+pub struct TaskHandle(Arc<AtomicBool>);
+
+impl TaskHandle {
+    /// Stop the task from running again. A task can cancel itself, in which case the run it's in is its last.
+    pub fn cancel(&self) { self.0.store(true, Ordering::Relaxed); }
+    pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::Relaxed) }
+}
+
+enum Task<O, E> {
+    Once(O),
+    Every { interval: Duration, f: E, handle: TaskHandle },
+}
+
+/// Work waiting to be done on the tick thread. Tasks that run once are `O`s and repeating ones are `E`s, which
+/// `run_due` is told how to call. Time is the server's own, moved on by each tick's length rather than by the wall
+/// clock, so tasks always run on the same ticks however long those ticks really took.
+pub struct Scheduler<O, E> {
+    now: Duration,
+    // Ordered by when they're due, then by when they were scheduled
+    tasks: BTreeMap<(Duration, u64), Task<O, E>>,
+    next_seq: u64,
+}
+
+/// The server's own scheduler, whose tasks are handed the server when they run
+pub type ServerScheduler = Scheduler<Box<dyn FnOnce(&dyn Api) + Send>, Box<dyn FnMut(&dyn Api) + Send>>;
+
+impl<O, E> Scheduler<O, E> {
+    pub fn new() -> Self {
+        Self {
+            now: Duration::from_secs(0),
+            tasks: BTreeMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    /// How much server time has passed, as of the start of the current tick
+    pub fn now(&self) -> Duration { self.now }
+
+    /// Run `f` once `delay` has passed. Tasks due on the same tick run in the order they were scheduled, and a task
+    /// scheduled without a delay runs at the start of the next tick.
+    pub fn run_in(&mut self, delay: Duration, f: O) {
+        let deadline = self.now + delay;
+        self.insert(deadline, Task::Once(f));
+    }
+
+    /// Run `f` every `interval`, starting once the first has passed. An interval shorter than a tick runs it once a
+    /// tick.
+    pub fn run_every(&mut self, interval: Duration, f: E) -> TaskHandle {
+        let handle = TaskHandle(Arc::new(AtomicBool::new(false)));
+        // Repeats are worked out by dividing by the interval, so it can't be zero
+        let interval = interval.max(Duration::from_nanos(1));
+        let deadline = self.now + interval;
+        self.insert(
+            deadline,
+            Task::Every {
+                interval,
+                f,
+                handle: handle.clone(),
+            },
+        );
+        handle
+    }
+
+    fn insert(&mut self, deadline: Duration, task: Task<O, E>) {
+        self.tasks.insert((deadline, self.next_seq), task);
+        self.next_seq += 1;
+    }
+
+    // Move time on by `dt` and take every task that's now due, in the order they should run
+    fn advance(&mut self, dt: Duration) -> Vec<(Duration, Task<O, E>)> {
+        self.now += dt;
+        let later = self.tasks.split_off(&(self.now, u64::max_value()));
+        let due = mem::replace(&mut self.tasks, later);
+        due.into_iter().map(|((deadline, _), task)| (deadline, task)).collect()
+    }
+
+    // Put a repeating task that was due at `deadline` back in for its next run. Its deadlines stay whole intervals
+    // apart so that it doesn't drift, but runs that would already be due again are skipped rather than run late.
+    fn repeat(&mut self, deadline: Duration, interval: Duration, task: Task<O, E>) {
+        let behind = (self.now - deadline).as_nanos() / interval.as_nanos();
+        let next = deadline + Duration::from_nanos(((behind + 1) * interval.as_nanos()) as u64);
+        self.insert(next, task);
+    }
+}
+
+/// Move `scheduler`'s time on by `dt` and run every task that's due, with `once` and `every`. The scheduler isn't
+/// locked while tasks run, so they're free to schedule more.
+pub fn run_due<O, E, F, G>(scheduler: &Mutex<Scheduler<O, E>>, dt: Duration, mut once: F, mut every: G)
+where
+    F: FnMut(O),
+    G: FnMut(&mut E),
+{
+    let due = scheduler.lock().advance(dt);
+    for (deadline, task) in due {
+        let start = Instant::now();
+        match task {
+            Task::Once(f) => once(f),
+            Task::Every { handle, .. } if handle.is_cancelled() => {},
+            Task::Every {
+                interval,
+                mut f,
+                handle,
+            } => {
+                every(&mut f);
+                if !handle.is_cancelled() {
+                    scheduler
+                        .lock()
+                        .repeat(deadline, interval, Task::Every { interval, f, handle });
+                }
+            },
+        }
+
+        let took = start.elapsed();
+        if took > SLOW_TASK {
+            warn!("A scheduled task held up the tick for {:?}", took);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(20);
+
+    type CtxScheduler = Scheduler<Box<dyn FnOnce(&Ctx) + Send>, Box<dyn FnMut(&Ctx) + Send>>;
+
+    // What tasks are handed when they run. It holds the scheduler, so tasks can schedule more.
+    struct Ctx {
+        scheduler: Mutex<CtxScheduler>,
+        log: Mutex<Vec<(&'static str, Duration)>>,
+    }
+
+    impl Ctx {
+        fn new() -> Self {
+            Self {
+                scheduler: Mutex::new(Scheduler::new()),
+                log: Mutex::new(vec![]),
+            }
+        }
+
+        fn record(&self, name: &'static str) {
+            let now = self.scheduler.lock().now();
+            self.log.lock().push((name, now));
+        }
+
+        fn tick(&self, ticks: u32) {
+            for _ in 0..ticks {
+                run_due(&self.scheduler, TICK, |f| f(self), |f| f(self));
+            }
+        }
+
+        fn names(&self) -> Vec<&'static str> { self.log.lock().iter().map(|(name, _)| *name).collect() }
+    }
+
+    fn ms(ms: u64) -> Duration { Duration::from_millis(ms) }
+
+    #[test]
+    fn tasks_run_by_deadline_then_in_the_order_they_were_scheduled() {
+        let ctx = Ctx::new();
+        {
+            let mut scheduler = ctx.scheduler.lock();
+            scheduler.run_in(ms(50), Box::new(|ctx: &Ctx| ctx.record("late")));
+            scheduler.run_in(ms(30), Box::new(|ctx: &Ctx| ctx.record("first")));
+            scheduler.run_in(ms(30), Box::new(|ctx: &Ctx| ctx.record("second")));
+            // Due on the same tick as the others, but after them
+            scheduler.run_in(ms(35), Box::new(|ctx: &Ctx| ctx.record("third")));
+        }
+
+        ctx.tick(1);
+        assert!(ctx.log.lock().is_empty());
+        ctx.tick(1);
+        assert_eq!(ctx.names(), vec!["first", "second", "third"]);
+        ctx.tick(1);
+        assert_eq!(ctx.log.lock()[3], ("late", ms(60)));
+    }
+
+    #[test]
+    fn tasks_scheduled_by_tasks_run_on_a_later_tick() {
+        let ctx = Ctx::new();
+        ctx.scheduler.lock().run_in(
+            ms(0),
+            Box::new(|ctx: &Ctx| {
+                ctx.record("outer");
+                ctx.scheduler
+                    .lock()
+                    .run_in(ms(0), Box::new(|ctx: &Ctx| ctx.record("inner")));
+            }),
+        );
+
+        ctx.tick(2);
+        assert_eq!(*ctx.log.lock(), vec![("outer", ms(20)), ("inner", ms(40))]);
+    }
+
+    #[test]
+    fn cancelled_tasks_stop_running() {
+        let ctx = Ctx::new();
+        let never = ctx
+            .scheduler
+            .lock()
+            .run_every(ms(20), Box::new(|ctx: &Ctx| ctx.record("never")));
+        never.cancel();
+
+        let runs = Arc::new(Mutex::new(0));
+        let handle = Arc::new(Mutex::new(None::<TaskHandle>));
+        let (runs_, handle_) = (runs.clone(), handle.clone());
+        let twice = ctx.scheduler.lock().run_every(
+            ms(20),
+            Box::new(move |ctx: &Ctx| {
+                ctx.record("twice");
+                *runs_.lock() += 1;
+                if *runs_.lock() == 2 {
+                    handle_.lock().as_ref().unwrap().cancel();
+                }
+            }),
+        );
+        *handle.lock() = Some(twice);
+
+        ctx.tick(10);
+        assert_eq!(ctx.names(), vec!["twice", "twice"]);
+    }
+
+    #[test]
+    fn repeating_tasks_dont_drift() {
+        let ctx = Ctx::new();
+        ctx.scheduler
+            .lock()
+            .run_every(ms(50), Box::new(|ctx: &Ctx| ctx.record("every")));
+
+        // 50 doesn't divide into whole ticks, so each run is a little late, but never by a tick or more, and the
+        // lateness doesn't build up
+        ctx.tick(500);
+        let log = ctx.log.lock();
+        assert_eq!(log.len(), 200);
+        for (i, (_, at)) in log.iter().enumerate() {
+            let due = ms(50) * (i as u32 + 1);
+            assert!(*at >= due && *at < due + TICK, "run {} was due at {:?} but ran at {:?}", i, due, at);
+        }
+    }
+
+    #[test]
+    fn intervals_shorter_than_a_tick_run_once_a_tick() {
+        let ctx = Ctx::new();
+        ctx.scheduler
+            .lock()
+            .run_every(ms(0), Box::new(|ctx: &Ctx| ctx.record("often")));
+
+        ctx.tick(5);
+        let times = ctx.log.lock().iter().map(|(_, at)| *at).collect::<Vec<_>>();
+        assert_eq!(times, vec![ms(20), ms(40), ms(60), ms(80), ms(100)]);
+    }
+}
@@ -0,0 +1,366 @@
+// Standard
+use std::{f32::consts::PI, time::Duration};
+
+// Library
+use rand;
+use specs::{saveload::Marker, Component, Join, VecStorage};
+use vek::*;
+
+// Project
+use common::{
+    ecs::{
+        net::UidMarker,
+        phys::{CollisionBox, Pos},
+    },
+    terrain::{chunk::Block, VoxAbs, Voxel},
+    util::msg::ServerMsg,
+};
+
+// Local
+use crate::{
+    api::Api,
+    net::Client,
+    player::Player,
+    sys::{LoadedChunks, Outbox, Target, TimeOfDay},
+    Payloads, Server,
+};
+
+// Constants
+// How far above and below a player's height the ground is looked for
+const GROUND_SEARCH: VoxAbs = 16;
+
+/// When in the day an entity may spawn
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpawnTime {
+    Any,
+    Day,
+    Night,
+}
+
+impl SpawnTime {
+    fn allows(self, daylight: f32) -> bool {
+        match self {
+            SpawnTime::Any => true,
+            SpawnTime::Day => daylight > 0.0,
+            SpawnTime::Night => daylight <= 0.0,
+        }
+    }
+}
+
+/// Where and when one kind of entity spawns
+#[derive(Clone, Debug)]
+pub struct SpawnRule {
+    /// What the entity is called, and what `Payloads::on_entity_spawn_attempt` is told it is
+    pub kind: String,
+    /// Most entities of this kind there may be at once
+    pub max: usize,
+    /// The blocks the entity may spawn on, which is how one biome is told from another: sand for deserts, snow for
+    /// mountain tops... Any solid block will do if this is empty.
+    pub ground: Vec<Block>,
+    pub time: SpawnTime,
+    /// How big the entity is. It needs as many blocks of air above the ground as it is tall.
+    pub collision_box: CollisionBox,
+}
+
+impl SpawnRule {
+    /// A kind of character-sized entity that spawns on any ground, at any time
+    pub fn new(kind: &str, max: usize) -> Self {
+        Self {
+            kind: kind.to_string(),
+            max,
+            ground: vec![],
+            time: SpawnTime::Any,
+            collision_box: CollisionBox::default(),
+        }
+    }
+}
+
+/// What the server spawns near players, and how much of it
+#[derive(Clone, Debug)]
+pub struct SpawnRules {
+    pub rules: Vec<SpawnRule>,
+    /// Most spawned entities there may be at once, whatever their kind
+    pub max_total: usize,
+    /// Most spawned entities there may be within `max_dist` of any one player
+    pub max_per_player: usize,
+    /// How far from players entities spawn. No player may be nearer than `min_dist`, so that nobody sees an entity
+    /// appear: clients fade into fog from 80% of their view distance, which is 80 blocks by default. The server's
+    /// config can set it instead, for players who see further.
+    pub min_dist: f32,
+    pub max_dist: f32,
+    /// Spawned entities that have been further than `despawn_dist` from every player for `despawn_after` are removed
+    pub despawn_dist: f32,
+    pub despawn_after: Duration,
+    /// How many places are tried for a spawn each tick
+    pub attempts_per_tick: usize,
+    /// Decides where entities spawn, so that spawning goes the same way every time for a given seed
+    pub seed: u32,
+}
+
+impl Default for SpawnRules {
+    fn default() -> Self {
+        Self {
+            rules: vec![],
+            max_total: 64,
+            max_per_player: 8,
+            min_dist: 64.0,
+            max_dist: 80.0,
+            despawn_dist: 128.0,
+            despawn_after: Duration::from_secs(30),
+            attempts_per_tick: 2,
+            seed: rand::random(),
+        }
+    }
+}
+
+/// An entity the server spawned because of a `SpawnRule`
+#[derive(Clone, Debug)]
+pub struct Spawned {
+    pub kind: String,
+    // How long it's been far from every player
+    far_for: Duration,
+}
+
+impl Component for Spawned {
+    type Storage = VecStorage<Self>;
+}
+
+// xorshift32, like wandering entities use
+struct SpawnRng(u32);
+
+impl SpawnRng {
+    // A number in [0, 1)
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 as f64 / (u32::max_value() as f64 + 1.0)) as f32
+    }
+
+    fn index(&mut self, len: usize) -> usize { ((self.next() * len as f32) as usize).min(len - 1) }
+}
+
+/// What spawning keeps track of between ticks
+pub(crate) struct Spawner {
+    rules: SpawnRules,
+    rng: SpawnRng,
+}
+
+impl Spawner {
+    pub(crate) fn new(rules: SpawnRules) -> Self {
+        let rng = SpawnRng(rules.seed.max(1));
+        Self { rules, rng }
+    }
+}
+
+// Where an entity following `rule` could stand in the column through `pos`, trying the highest ground within
+// `GROUND_SEARCH` blocks of it first. Fails if the column has no such place, or isn't loaded.
+fn find_ground(chunks: &LoadedChunks, pos: Vec3<f32>, rule: &SpawnRule) -> Option<Vec3<f32>> {
+    let column = pos.map(|e| e.floor() as VoxAbs);
+    let height = rule.collision_box.0.z.ceil() as VoxAbs;
+    // Everything from the ground under the lowest place tried to the headroom over the highest, in one go
+    let low = column.z - GROUND_SEARCH - 1;
+    let blocks = chunks.column(Vec2::from(column), low, column.z + GROUND_SEARCH + height);
+    let block_at = |z: VoxAbs| blocks[(z - low) as usize];
+    'search: for z in (column.z - GROUND_SEARCH..column.z + GROUND_SEARCH).rev() {
+        let ground = block_at(z - 1)?;
+        if !ground.is_solid() {
+            continue;
+        }
+        for h in 0..height {
+            if block_at(z + h)?.is_solid() {
+                continue 'search;
+            }
+        }
+
+        // Ground of the wrong kind rules the whole column out, rather than looking for the right kind in a cave below
+        return if rule.ground.is_empty() || rule.ground.contains(&ground) {
+            Some(Vec3::new(pos.x, pos.y, z as f32))
+        } else {
+            None
+        };
+    }
+    None
+}
+
+impl<P: Payloads> Server<P> {
+    /// Remove spawned entities that have been away from players for too long, then try to spawn more near players
+    pub(crate) fn update_spawns(&mut self, dt: Duration) {
+        self.despawn_far_entities(dt);
+
+        for (pos, kind, collision_box) in self.spawn_candidates() {
+            if self.payload.on_entity_spawn_attempt(self, pos, &kind) {
+                let entity = self.spawn_npc(&kind, pos);
+                let _ = self.world.write_storage::<CollisionBox>().insert(entity, collision_box);
+                let _ = self.world.write_storage::<Spawned>().insert(
+                    entity,
+                    Spawned {
+                        kind,
+                        far_for: Duration::default(),
+                    },
+                );
+            }
+        }
+    }
+
+    fn player_positions(&self) -> Vec<Vec3<f32>> {
+        (
+            &self.world.read_storage::<Player>(),
+            &self.world.read_storage::<Client>(),
+            &self.world.read_storage::<Pos>(),
+        )
+            .join()
+            .map(|(_, _, pos)| pos.0)
+            .collect()
+    }
+
+    fn despawn_far_entities(&mut self, dt: Duration) {
+        let players = self.player_positions();
+        let rules = &self.spawner.rules;
+
+        let mut far = vec![];
+        for (entity, spawned, pos) in (
+            &self.world.entities(),
+            &mut self.world.write_storage::<Spawned>(),
+            &self.world.read_storage::<Pos>(),
+        )
+            .join()
+        {
+            if players.iter().any(|p| p.distance(pos.0) <= rules.despawn_dist) {
+                spawned.far_for = Duration::default();
+            } else {
+                spawned.far_for += dt;
+                if spawned.far_for >= rules.despawn_after {
+                    far.push(entity);
+                }
+            }
+        }
+
+        for entity in far {
+            if let Some(uid) = self.world.read_storage::<UidMarker>().get(entity) {
+                let outbox = self.world.read_resource::<Outbox>();
+                outbox.send(Target::All, ServerMsg::EntityDeleted { uid: uid.id() });
+            }
+            let _ = self.world.delete_entity(entity);
+        }
+    }
+
+    // Pick places near players where the rules allow something to spawn, and what would spawn there and how big it is
+    fn spawn_candidates(&mut self) -> Vec<(Vec3<f32>, String, CollisionBox)> {
+        let players = self.player_positions();
+        let Spawner { rules, rng } = &mut self.spawner;
+        if players.is_empty() || rules.rules.is_empty() {
+            return vec![];
+        }
+        let min_dist = self.config.spawn_distance.unwrap_or(rules.min_dist);
+        // However far out the config pushes spawns, they still have a ring to happen in
+        let max_dist = rules.max_dist.max(min_dist);
+
+        let chunks = self.world.read_resource::<LoadedChunks>();
+        let daylight = self.world.read_resource::<TimeOfDay>().daylight();
+        let mut spawned = (
+            &self.world.read_storage::<Spawned>(),
+            &self.world.read_storage::<Pos>(),
+        )
+            .join()
+            .map(|(spawned, pos)| (spawned.kind.clone(), pos.0))
+            .collect::<Vec<_>>();
+
+        let mut candidates = vec![];
+        for _ in 0..rules.attempts_per_tick {
+            if spawned.len() >= rules.max_total {
+                break;
+            }
+
+            let rule = &rules.rules[rng.index(rules.rules.len())];
+            let of_kind = spawned.iter().filter(|(kind, _)| *kind == rule.kind).count();
+            if !rule.time.allows(daylight) || of_kind >= rule.max {
+                continue;
+            }
+
+            let player = players[rng.index(players.len())];
+            let angle = rng.next() * PI * 2.0;
+            let dist = min_dist + rng.next() * (max_dist - min_dist);
+            let pos = match find_ground(&chunks, player + Vec3::new(angle.cos(), angle.sin(), 0.0) * dist, rule) {
+                Some(pos) => pos,
+                None => continue,
+            };
+
+            // Nobody may be close enough to see it appear, or already have as many spawned entities around them as
+            // they're allowed
+            let crowded = players.iter().any(|p| {
+                let near = spawned.iter().filter(|(_, s)| s.distance(*p) <= max_dist).count();
+                p.distance(pos) < min_dist || (p.distance(pos) <= max_dist && near >= rules.max_per_player)
+            });
+            if crowded {
+                continue;
+            }
+
+            spawned.push((rule.kind.clone(), pos));
+            candidates.push((pos, rule.kind.clone(), rule.collision_box));
+        }
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::terrain::{
+        chunk::{Chunk, HomogeneousData, CHUNK_SIZE},
+        ConstructVolume,
+    };
+
+    // Grass below z = 0, and air from there up
+    fn meadow() -> LoadedChunks {
+        let mut chunks = LoadedChunks::default();
+        chunks.0.insert(Vec3::new(0, 0, -1), filled(Block::GRASS));
+        chunks.0.insert(Vec3::new(0, 0, 0), filled(Block::AIR));
+        chunks.0.insert(Vec3::new(0, 0, 1), filled(Block::AIR));
+        chunks
+    }
+
+    fn filled(block: Block) -> Chunk { Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, block)) }
+
+    #[test]
+    fn entities_spawn_on_the_ground() {
+        let chunks = meadow();
+        let rule = SpawnRule::new("sheep", 1);
+        assert_eq!(find_ground(&chunks, Vec3::new(3.5, 4.5, 10.0), &rule), Some(Vec3::new(3.5, 4.5, 0.0)));
+        // The ground has to be near enough, and loaded
+        assert_eq!(find_ground(&chunks, Vec3::new(3.5, 4.5, 40.0), &rule), None);
+        assert_eq!(find_ground(&chunks, Vec3::new(-3.5, 4.5, 10.0), &rule), None);
+    }
+
+    #[test]
+    fn entities_need_the_right_ground_and_headroom() {
+        let mut chunks = meadow();
+        let pos = Vec3::new(3.5, 4.5, 10.0);
+        let camel = SpawnRule {
+            ground: vec![Block::SAND],
+            ..SpawnRule::new("camel", 1)
+        };
+        assert_eq!(find_ground(&chunks, pos, &camel), None);
+
+        // Under a roof 32 blocks up there's room for a sheep, but not for something 40 blocks tall
+        chunks.0.insert(Vec3::new(0, 0, 1), filled(Block::STONE));
+        assert_eq!(find_ground(&chunks, pos, &SpawnRule::new("sheep", 1)), Some(Vec3::new(3.5, 4.5, 0.0)));
+        let giant = SpawnRule {
+            collision_box: CollisionBox(Vec3::new(0.9, 0.9, 40.0)),
+            ..SpawnRule::new("giant", 1)
+        };
+        assert_eq!(find_ground(&chunks, pos, &giant), None);
+    }
+
+    #[test]
+    fn spawn_times_follow_the_sun() {
+        let at = |secs| TimeOfDay {
+            time: Duration::from_secs(secs),
+            ..TimeOfDay::default()
+        };
+        assert!(SpawnTime::Day.allows(at(0).daylight()));
+        assert!(!SpawnTime::Night.allows(at(0).daylight()));
+        assert!(SpawnTime::Night.allows(at(60).daylight()));
+        assert!(SpawnTime::Any.allows(at(60).daylight()));
+    }
+}
@@ -0,0 +1,57 @@
+// Standard
+use std::collections::VecDeque;
+
+// Library
+use specs::{Entities, Join, ReadExpect, System, WriteExpect, WriteStorage};
+
+// Project
+use common::{terrain::VolCluster, util::msg::ServerMsg};
+
+// Local
+use super::{ChunkVersions, LoadedChunks, Outbox, Target, TickConfig};
+use crate::net::Client;
+
+/// Sends each client the chunks they've asked for once they've finished generating, unless they already have the
+/// latest version
+pub struct ChunkInterest;
+
+impl<'a> System<'a> for ChunkInterest {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Client>,
+        WriteExpect<'a, LoadedChunks>,
+        ReadExpect<'a, ChunkVersions>,
+        ReadExpect<'a, TickConfig>,
+        ReadExpect<'a, Outbox>,
+    );
+
+    fn run(&mut self, (entities, mut clients, mut chunks, versions, config, outbox): Self::SystemData) {
+        for (entity, client) in (&entities, &mut clients).join() {
+            let mut sent = 0;
+            let mut waiting = VecDeque::new();
+
+            while let Some(pos) = client.chunk_requests.pop_front() {
+                let version = versions.get(pos);
+                if client.known_chunks.get(&pos) == Some(&version) {
+                    continue;
+                }
+                if sent >= config.chunks_per_tick {
+                    waiting.push_back(pos);
+                    continue;
+                }
+
+                match chunks.0.get_mut(&pos).map(|chunk| chunk.to_bytes()) {
+                    Some(Ok(data)) => {
+                        outbox.send(Target::Client(entity), ServerMsg::ChunkData { pos, data });
+                        client.known_chunks.insert(pos, version);
+                        sent += 1;
+                    },
+                    Some(Err(_)) => {}, // Unserializable chunks are dropped, the client will ask again
+                    None => waiting.push_back(pos),
+                }
+            }
+
+            client.chunk_requests = waiting;
+        }
+    }
+}
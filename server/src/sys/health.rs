@@ -0,0 +1,72 @@
+// Library
+use specs::{saveload::Marker, Component, Entity, HashMapStorage, ReadStorage, WriteStorage};
+
+// Project
+use common::{
+    ecs::{character::Health, net::UidMarker, NetComp},
+    util::msg::ServerMsg,
+};
+
+// Local
+use super::{Outbox, Target};
+
+/// Something whose health ran out. Nothing can hurt it any more, and a dead player can't move or attack until they
+/// respawn.
+#[derive(Clone, Debug)]
+pub struct Dead {
+    /// What the player is told killed them
+    pub cause: String,
+}
+
+impl Component for Dead {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// Take `amount` from an entity's health, letting every client know how much it has left. If that's none, it dies of
+/// `cause`, which its own client is told. Returns whether it died.
+pub fn hurt(
+    entity: Entity,
+    amount: u32,
+    cause: &str,
+    healths: &mut WriteStorage<Health>,
+    deaths: &mut WriteStorage<Dead>,
+    uids: &ReadStorage<UidMarker>,
+    outbox: &Outbox,
+) -> bool {
+    if deaths.contains(entity) {
+        return false;
+    }
+    let health = match healths.get_mut(entity) {
+        Some(health) => health,
+        None => return false,
+    };
+
+    let left = health.damage(amount);
+    if let (Some(uid), Some(store)) = (uids.get(entity), health.to_store()) {
+        outbox.send(
+            Target::All,
+            ServerMsg::CompUpdate {
+                uid: uid.id(),
+                store,
+                forced: true,
+            },
+        );
+    }
+    if left > 0 {
+        return false;
+    }
+
+    let _ = deaths.insert(
+        entity,
+        Dead {
+            cause: cause.to_string(),
+        },
+    );
+    outbox.send(
+        Target::Client(entity),
+        ServerMsg::Died {
+            cause: cause.to_string(),
+        },
+    );
+    true
+}
@@ -0,0 +1,93 @@
+// Standard
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+// Library
+use specs::{Entities, Entity, Join, ReadExpect, ReadStorage, System, WriteExpect};
+use vek::*;
+
+// Project
+use common::ecs::phys::Pos;
+
+// Local
+use super::{DeltaTime, Projectile};
+
+// Constants
+/// How far back entities' positions are remembered. Players with more latency than this are treated as if they had
+/// only this much.
+pub const HISTORY_LENGTH: Duration = Duration::from_millis(500);
+// However short ticks get, no entity has more snapshots than this kept
+const MAX_SNAPSHOTS: usize = 64;
+
+/// Where entities have been over the last `HISTORY_LENGTH`, a snapshot each tick. Hits can be checked against where
+/// an attacker saw things when they attacked, rather than where they've got to by the time the server hears of it.
+#[derive(Default)]
+pub struct PosHistory {
+    // How much tick time has passed since the history started
+    now: Duration,
+    snapshots: HashMap<Entity, VecDeque<(Duration, Vec3<f32>)>>,
+}
+
+impl PosHistory {
+    /// Take a snapshot of where entities are, `dt` after the last one. Entities that aren't among `positions` any more
+    /// are forgotten.
+    pub fn record(&mut self, dt: Duration, positions: impl IntoIterator<Item = (Entity, Vec3<f32>)>) {
+        self.now += dt;
+        let now = self.now;
+
+        let mut snapshots = HashMap::with_capacity(self.snapshots.len());
+        for (entity, pos) in positions {
+            let mut history = self.snapshots.remove(&entity).unwrap_or_default();
+            history.push_back((now, pos));
+            while history.len() > MAX_SNAPSHOTS || history.front().map_or(false, |(t, _)| now - *t > HISTORY_LENGTH) {
+                history.pop_front();
+            }
+            snapshots.insert(entity, history);
+        }
+        self.snapshots = snapshots;
+    }
+
+    /// Where an entity was `ago` before the latest snapshot, between snapshots if need be. Times from before the
+    /// entity's oldest snapshot give that one. Entities with no snapshots give `None`.
+    pub fn pos_at(&self, entity: Entity, ago: Duration) -> Option<Vec3<f32>> {
+        let history = self.snapshots.get(&entity)?;
+        let when = self.now.checked_sub(ago).unwrap_or_default();
+        match history.iter().position(|(t, _)| *t >= when) {
+            Some(0) => history.front().map(|(_, pos)| *pos),
+            Some(i) => {
+                let ((t0, p0), (t1, p1)) = (history[i - 1], history[i]);
+                let f = ((when - t0).as_float_secs() / (t1 - t0).as_float_secs()) as f32;
+                Some(p0 + (p1 - p0) * f)
+            },
+            None => history.back().map(|(_, pos)| *pos),
+        }
+    }
+
+    /// Where every entity with any snapshots was `ago` before the latest one, as `pos_at` gives it
+    pub fn entities_at(&self, ago: Duration) -> HashMap<Entity, Vec3<f32>> {
+        self.snapshots
+            .keys()
+            .filter_map(|entity| self.pos_at(*entity, ago).map(|pos| (*entity, pos)))
+            .collect()
+    }
+}
+
+/// Takes a snapshot of where everything but projectiles is, for `PosHistory`
+pub struct PosHistorySys;
+
+impl<'a> System<'a> for PosHistorySys {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, DeltaTime>,
+        WriteExpect<'a, PosHistory>,
+        ReadStorage<'a, Pos>,
+        ReadStorage<'a, Projectile>,
+    );
+
+    fn run(&mut self, (entities, dt, mut history, positions, projectiles): Self::SystemData) {
+        let positions = (&entities, &positions, !&projectiles).join().map(|(e, pos, _)| (e, pos.0));
+        history.record(dt.0, positions);
+    }
+}
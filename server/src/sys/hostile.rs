@@ -0,0 +1,251 @@
+// Standard
+use std::time::Duration;
+
+// Library
+use specs::{Component, Entities, Entity, Join, ReadExpect, ReadStorage, System, VecStorage, WriteStorage};
+use vek::*;
+
+// Project
+use common::{
+    audio::SoundId,
+    ecs::{
+        character::{Character, Health},
+        net::UidMarker,
+        phys::{Pos, Vel},
+    },
+    terrain::{ray_cast, VoxAbs, Voxel},
+    util::msg::PlayMode,
+};
+
+// Local
+use super::{hurt, Dead, DeltaTime, LoadedChunks, Outbox, Path, PathRequest};
+use crate::player::Player;
+
+// Constants
+const ATTACK_COOLDOWN: Duration = Duration::from_secs(1);
+// A new path to a target is looked for this often at most, and only once the target has moved this far since the last
+const REPATH_INTERVAL: Duration = Duration::from_secs(1);
+const REPATH_DISTANCE: f32 = 2.0;
+// How long a target can go unseen before it's given up on
+const FORGET_TIME: Duration = Duration::from_secs(3);
+// Sight lines run between eyes, this far above the feet
+const EYE_HEIGHT: f32 = 1.5;
+
+// Who a hostile is after
+#[derive(Clone, Debug)]
+struct Aggro {
+    target: Entity,
+    last_seen: Vec3<f32>,
+    unseen: Duration,
+    // Where the hostile last set off towards, and how long ago
+    chased_to: Vec3<f32>,
+    since_chase: Duration,
+}
+
+/// Makes an entity go after players that come within `aggro_radius` of it, as long as it can see them. It hits them
+/// for `damage` whenever they're in reach, and once it's lost sight of them for a while it goes back to where it
+/// started.
+#[derive(Clone, Debug)]
+pub struct Hostile {
+    pub aggro_radius: f32,
+    pub attack_range: f32,
+    pub damage: u32,
+    /// How fast it chases, in blocks per second
+    pub speed: f32,
+    // Where it first was, which it goes back to after a chase
+    home: Option<Vec3<f32>>,
+    aggro: Option<Aggro>,
+    cooldown: Duration,
+}
+
+impl Hostile {
+    pub fn new(aggro_radius: f32, attack_range: f32, damage: u32, speed: f32) -> Self {
+        Self {
+            aggro_radius,
+            attack_range,
+            damage,
+            speed,
+            home: None,
+            aggro: None,
+            cooldown: Duration::from_secs(0),
+        }
+    }
+
+    /// Who it's after, if anyone
+    pub fn target(&self) -> Option<Entity> { self.aggro.as_ref().map(|aggro| aggro.target) }
+}
+
+impl Component for Hostile {
+    type Storage = VecStorage<Self>;
+}
+
+// Whether the eyes of something standing at `from` can see those of something at `to`. Terrain that isn't loaded
+// blocks the view.
+fn in_sight(chunks: &LoadedChunks, from: Vec3<f32>, to: Vec3<f32>) -> bool {
+    let eye = Vec3::unit_z() * EYE_HEIGHT;
+    let (from, to) = (from + eye, to + eye);
+    ray_cast(from, to - from, from.distance(to), |vox| {
+        chunks.block_at(vox).map(|block| block.is_solid()).unwrap_or(true)
+    })
+    .is_none()
+}
+
+fn goal(pos: Vec3<f32>) -> Vec3<VoxAbs> { pos.map(|e| e.floor() as VoxAbs) }
+
+/// Sends hostile entities after the players they can see, and has them attack those in reach. Only players in a body
+/// and still alive are gone after.
+pub struct HostileSys;
+
+impl<'a> System<'a> for HostileSys {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, DeltaTime>,
+        ReadExpect<'a, LoadedChunks>,
+        ReadExpect<'a, Outbox>,
+        ReadStorage<'a, Pos>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Character>,
+        ReadStorage<'a, UidMarker>,
+        WriteStorage<'a, Hostile>,
+        WriteStorage<'a, Health>,
+        WriteStorage<'a, Dead>,
+        WriteStorage<'a, Vel>,
+        WriteStorage<'a, PathRequest>,
+        WriteStorage<'a, Path>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            dt,
+            chunks,
+            outbox,
+            positions,
+            players,
+            characters,
+            uids,
+            mut hostiles,
+            mut healths,
+            mut deaths,
+            mut velocities,
+            mut requests,
+            mut paths,
+        ): Self::SystemData,
+    ) {
+        let dt = dt.0;
+        let prey = (&entities, &positions, &players, !&deaths)
+            .join()
+            .filter(|(_, _, player, _)| player.mode == PlayMode::Character)
+            .map(|(entity, pos, _, _)| (entity, pos.0))
+            .collect::<Vec<_>>();
+
+        let mut attacks = vec![];
+        for (entity, pos, hostile) in (&entities, &positions, &mut hostiles).join() {
+            if deaths.contains(entity) {
+                continue;
+            }
+            let pos = pos.0;
+            let home = *hostile.home.get_or_insert(pos);
+            hostile.cooldown = hostile.cooldown.checked_sub(dt).unwrap_or(Duration::from_secs(0));
+            let radius = hostile.aggro_radius;
+            let sees = |target: Vec3<f32>| pos.distance(target) <= radius && in_sight(&chunks, pos, target);
+
+            let mut go_home = false;
+            // Targets that died or left can't be chased any more
+            if let Some(target) = hostile.target() {
+                if !prey.iter().any(|(e, _)| *e == target) {
+                    hostile.aggro = None;
+                    go_home = true;
+                }
+            }
+            if hostile.aggro.is_none() {
+                let nearest = prey
+                    .iter()
+                    .filter(|(_, target_pos)| sees(*target_pos))
+                    .min_by(|(_, a), (_, b)| pos.distance(*a).partial_cmp(&pos.distance(*b)).unwrap());
+                hostile.aggro = nearest.map(|(target, target_pos)| Aggro {
+                    target: *target,
+                    last_seen: *target_pos,
+                    unseen: Duration::from_secs(0),
+                    chased_to: *target_pos,
+                    since_chase: REPATH_INTERVAL,
+                });
+                if hostile.aggro.is_some() {
+                    outbox.play_sound(SoundId::GROWL, pos, 1.0);
+                }
+            }
+
+            let target_pos = hostile
+                .target()
+                .and_then(|target| prey.iter().find(|(e, _)| *e == target))
+                .map(|(_, target_pos)| *target_pos);
+            if let (Some(aggro), Some(target_pos)) = (hostile.aggro.as_mut(), target_pos) {
+                if sees(target_pos) {
+                    aggro.last_seen = target_pos;
+                    aggro.unseen = Duration::from_secs(0);
+                } else {
+                    aggro.unseen += dt;
+                }
+                aggro.since_chase += dt;
+            }
+            if hostile.aggro.as_ref().map(|aggro| aggro.unseen >= FORGET_TIME).unwrap_or(false) {
+                hostile.aggro = None;
+                go_home = true;
+            }
+
+            if go_home {
+                paths.remove(entity);
+                let _ = requests.insert(
+                    entity,
+                    PathRequest {
+                        goal: goal(home),
+                        speed: hostile.speed,
+                    },
+                );
+            }
+            let (speed, attack_range, damage) = (hostile.speed, hostile.attack_range, hostile.damage);
+            let aggro = match hostile.aggro.as_mut() {
+                Some(aggro) => aggro,
+                None => continue,
+            };
+
+            // Stand and fight anything in reach
+            if aggro.unseen == Duration::from_secs(0) && pos.distance(aggro.last_seen) <= attack_range {
+                paths.remove(entity);
+                requests.remove(entity);
+                if let Some(vel) = velocities.get_mut(entity) {
+                    vel.0 = Vec3::zero();
+                }
+                if hostile.cooldown == Duration::from_secs(0) {
+                    attacks.push((aggro.target, damage, entity));
+                    hostile.cooldown = ATTACK_COOLDOWN;
+                }
+                continue;
+            }
+
+            // Head for where the target was last seen, finding a new way there now and then as it moves
+            let moved = aggro.last_seen.distance(aggro.chased_to) > REPATH_DISTANCE;
+            let lost = !paths.contains(entity) && !requests.contains(entity);
+            if (moved || lost) && aggro.since_chase >= REPATH_INTERVAL {
+                aggro.chased_to = aggro.last_seen;
+                aggro.since_chase = Duration::from_secs(0);
+                let _ = requests.insert(
+                    entity,
+                    PathRequest {
+                        goal: goal(aggro.last_seen),
+                        speed,
+                    },
+                );
+            }
+        }
+
+        for (target, damage, attacker) in attacks {
+            let cause = match characters.get(attacker) {
+                Some(character) => format!("You were mauled by {}", character.name),
+                None => "You were mauled".to_string(),
+            };
+            hurt(target, damage, &cause, &mut healths, &mut deaths, &uids, &outbox);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+// Modules
+mod chunks;
+mod health;
+mod history;
+mod hostile;
+mod movement;
+mod path;
+mod pickup;
+mod projectile;
+mod sync;
+#[cfg(test)]
+mod tests;
+mod time;
+mod wander;
+
+// Reexports
+pub use self::{
+    chunks::ChunkInterest,
+    health::{hurt, Dead},
+    history::{PosHistory, PosHistorySys, HISTORY_LENGTH},
+    hostile::{Hostile, HostileSys},
+    movement::Movement,
+    path::{find_path, FollowPath, Path, PathConfig, PathRequest, PathResult, Pathfind},
+    pickup::{ItemDrop, ItemMerge, Pickup},
+    projectile::{Projectile, ProjectileSpec, ProjectileSys},
+    sync::EntitySync,
+    time::TimeOfDaySys,
+    wander::{Wander, WanderSys},
+};
+
+// Standard
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::PI,
+    iter, mem,
+    time::Duration,
+};
+
+// Library
+use parking_lot::Mutex;
+use specs::{Dispatcher, DispatcherBuilder, Entity, Join, World};
+use vek::*;
+
+// Project
+use common::{
+    audio::SoundId,
+    ecs::phys::Pos,
+    physics::config::PhysicsConfig,
+    terrain::{
+        chunk::{Block, Chunk, CHUNK_SIZE},
+        voxabs_to_voloffs, voxabs_to_voxrel, ReadVolume, VolCluster, VolOffs, VoxAbs, VoxRel, WorldBorder,
+        WriteVolume,
+    },
+    util::msg::ServerMsg,
+};
+
+// Local
+use crate::{api::Api, net::Client, player::Player, Payloads, Server};
+
+// Constants
+// How long a day lasts, in seconds. Clients draw the sky on the same cycle.
+const DAY_LENGTH: f32 = 120.0;
+
+// Resources
+
+/// How much time the current tick covers
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DeltaTime(pub Duration);
+
+/// The in-game clock that decides the time of day
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TimeOfDay {
+    pub time: Duration,
+    // How long it's been since clients were told the time
+    pub since_sync: Duration,
+}
+
+impl TimeOfDay {
+    /// How high the sun is, from 1 at noon to -1 at midnight
+    pub fn daylight(&self) -> f32 { (self.time.as_float_secs() as f32 / DAY_LENGTH * PI * 2.0).cos() }
+}
+
+/// Tuning parameters for the tick's systems
+#[derive(Copy, Clone, Debug)]
+pub struct TickConfig {
+    /// How many chunks each client is sent per tick, to avoid flooding the connection on join
+    pub chunks_per_tick: usize,
+    /// How often clients are told the time, so their clocks don't drift
+    pub time_sync_freq: Duration,
+    /// How far away, in blocks, sounds are sent to players from. Sounds further away than this aren't sent at all.
+    pub hearing_range: f32,
+    /// How far away, in blocks, players are told about dropped items. Items are small, so there's no point syncing them
+    /// to players who couldn't see them anyway.
+    pub item_sync_range: f32,
+    /// How long a dropped item lies before it disappears
+    pub item_despawn: Duration,
+    /// How close dropped stacks of the same kind have to be to merge into one
+    pub item_merge_radius: f32,
+    /// How many ticks apart dropped items look for stacks to merge with
+    pub item_merge_ticks: u32,
+}
+
+impl Default for TickConfig {
+    fn default() -> Self {
+        Self {
+            chunks_per_tick: 4,
+            time_sync_freq: Duration::from_secs(60),
+            hearing_range: 64.0,
+            item_sync_range: 64.0,
+            item_despawn: Duration::from_secs(5 * 60),
+            item_merge_radius: 1.5,
+            item_merge_ticks: 10,
+        }
+    }
+}
+
+/// The chunks the server has generated, by position
+#[derive(Default)]
+pub struct LoadedChunks(pub HashMap<Vec3<VolOffs>, Chunk>);
+
+impl LoadedChunks {
+    /// Fails with the position of the block's chunk if it isn't loaded
+    pub fn block_at(&self, vox: Vec3<VoxAbs>) -> Result<Block, Vec3<VolOffs>> {
+        let chunk = voxabs_to_voloffs(vox, CHUNK_SIZE);
+        let block = self
+            .0
+            .get(&chunk)
+            .ok_or(chunk)?
+            .prefered()
+            .and_then(|vol| vol.at(voxabs_to_voxrel(vox, CHUNK_SIZE)))
+            .unwrap_or(Block::AIR);
+        Ok(block)
+    }
+
+    /// The blocks in the column through `pos` from `low` up to but not including `high`, read a chunk at a time rather
+    /// than block by block. Blocks in chunks that aren't loaded are `None`.
+    pub fn column(&self, pos: Vec2<VoxAbs>, low: VoxAbs, high: VoxAbs) -> Vec<Option<Block>> {
+        let mut blocks = Vec::with_capacity((high - low).max(0) as usize);
+        let mut z = low;
+        while z < high {
+            let vox = Vec3::new(pos.x, pos.y, z);
+            let rel = voxabs_to_voxrel(vox, CHUNK_SIZE);
+            let len = (CHUNK_SIZE.z - rel.z).min((high - z) as VoxRel) as usize;
+            match self.0.get(&voxabs_to_voloffs(vox, CHUNK_SIZE)).map(|chunk| chunk.prefered()) {
+                Some(Some(vol)) => blocks.extend(vol.column(rel.x, rel.y).skip(rel.z as usize).take(len).map(Some)),
+                Some(None) => blocks.extend(iter::repeat(Some(Block::AIR)).take(len)),
+                None => blocks.extend(iter::repeat(None).take(len)),
+            }
+            z += len as VoxAbs;
+        }
+        blocks
+    }
+
+    /// Fails with the position of the block's chunk if it isn't loaded
+    pub fn set_block(&mut self, vox: Vec3<VoxAbs>, block: Block) -> Result<(), Vec3<VolOffs>> {
+        let pos = voxabs_to_voloffs(vox, CHUNK_SIZE);
+        let chunk = self.0.get_mut(&pos).ok_or(pos)?;
+        // The offset always falls within the chunk
+        let _ = chunk.set(voxabs_to_voxrel(vox, CHUNK_SIZE), block);
+        Ok(())
+    }
+}
+
+/// How many times each chunk has been edited, so that clients can be sent what changed in the chunks they have
+#[derive(Default)]
+pub struct ChunkVersions(pub HashMap<Vec3<VolOffs>, u64>);
+
+impl ChunkVersions {
+    pub fn get(&self, pos: Vec3<VolOffs>) -> u64 { self.0.get(&pos).cloned().unwrap_or(0) }
+
+    /// Note an edit to a chunk, returning its new version
+    pub fn bump(&mut self, pos: Vec3<VolOffs>) -> u64 {
+        let version = self.0.entry(pos).or_insert(0);
+        *version += 1;
+        *version
+    }
+}
+
+/// Who an outgoing message is for
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Target {
+    Client(Entity),
+    All,
+    // Every client but the given entity's own
+    AllExcept(Entity),
+    // Every client whose player is within hearing range of a position, and that has the chunk it's in
+    Near(Vec3<f32>),
+    // Every client whose player is within the given distance of a position, and that has the chunk it's in
+    InRange(Vec3<f32>, f32),
+}
+
+/// Messages for clients. Systems queue messages here and they're sent once every system has run, so that systems never
+/// do network I/O themselves. The queue has its own lock so that systems can share it while running in parallel.
+#[derive(Default)]
+pub struct Outbox(Mutex<Vec<(Target, ServerMsg)>>);
+
+impl Outbox {
+    pub fn send(&self, target: Target, msg: ServerMsg) { self.0.lock().push((target, msg)); }
+
+    pub fn drain(&self) -> Vec<(Target, ServerMsg)> { mem::replace(&mut *self.0.lock(), vec![]) }
+
+    /// Have the clients in hearing range of `pos` play a sound there once
+    pub fn play_sound(&self, sound: SoundId, pos: Vec3<f32>, volume: f32) {
+        let msg = ServerMsg::SoundEvent {
+            sound,
+            pos,
+            volume,
+            pitch: 1.0,
+        };
+        self.send(Target::Near(pos), msg);
+    }
+}
+
+/// Add the resources the tick's systems use, and register any components only they use
+pub fn setup(world: &mut World) {
+    world.register::<Player>();
+    world.register::<Wander>();
+    world.register::<Hostile>();
+    world.register::<ItemDrop>();
+    world.register::<Projectile>();
+    world.register::<Dead>();
+    world.register::<PathRequest>();
+    world.register::<Path>();
+    world.add_resource(DeltaTime::default());
+    world.add_resource(TimeOfDay::default());
+    world.add_resource(TickConfig::default());
+    world.add_resource(PhysicsConfig::default());
+    world.add_resource(PathConfig::default());
+    world.add_resource(WorldBorder::default());
+    world.add_resource(LoadedChunks::default());
+    world.add_resource(ChunkVersions::default());
+    world.add_resource(PosHistory::default());
+    world.add_resource(Outbox::default());
+}
+
+/// The systems that run each tick. Systems without a dependency between them may run in parallel.
+pub fn dispatcher() -> Dispatcher<'static, 'static> {
+    DispatcherBuilder::new()
+        .with(WanderSys, "wander", &[])
+        .with(HostileSys, "hostile", &[])
+        .with(Pathfind::default(), "pathfind", &["hostile"])
+        .with(FollowPath, "follow_path", &["pathfind"])
+        .with(Movement, "movement", &["wander", "follow_path"])
+        .with(Pickup, "pickup", &["movement"])
+        .with(ItemMerge::default(), "item_merge", &["pickup"])
+        .with(PosHistorySys, "history", &["movement"])
+        .with(ProjectileSys, "projectile", &["history"])
+        .with(EntitySync::default(), "sync", &["movement", "projectile"])
+        .with(ChunkInterest, "chunk_interest", &[])
+        .with(TimeOfDaySys, "time_of_day", &[])
+        .build()
+}
+
+impl<P: Payloads> Server<P> {
+    /// Send everything the systems queued up during the tick
+    pub(crate) fn flush_outbox(&self) {
+        let msgs = self.world.read_resource::<Outbox>().drain();
+        // Deletions go out together once everything else has, so nothing about a deleted entity arrives after it
+        let mut deleted = vec![];
+        // The entities only synced to nearby players that each client is told about this tick
+        let mut nearby = HashMap::<Entity, HashSet<u64>>::new();
+        for (target, msg) in msgs {
+            match (target, msg) {
+                (Target::All, ServerMsg::EntityDeleted { uid }) => deleted.push(uid),
+                (Target::InRange(pos, range), ServerMsg::CompUpdate { uid, store, forced }) => {
+                    for entity in self.clients_in_range(pos, range) {
+                        nearby.entry(entity).or_default().insert(uid);
+                        self.send_net_msg(entity, ServerMsg::CompUpdate {
+                            uid,
+                            store: store.clone(),
+                            forced,
+                        });
+                    }
+                },
+                (target, msg) => self.send_to(target, msg),
+            }
+        }
+
+        // Clients forget the entities that went out of their range, like they do those that were deleted
+        let mut clients = self.world.write_storage::<Client>();
+        for (entity, client) in (&self.world.entities(), &mut clients).join() {
+            let now = nearby.remove(&entity).unwrap_or_default();
+            let gone = client
+                .nearby_entities
+                .difference(&now)
+                .filter(|uid| !deleted.contains(uid))
+                .cloned()
+                .collect::<Vec<_>>();
+            client.nearby_entities = now;
+            let msg = match gone.len() {
+                0 => continue,
+                1 => ServerMsg::EntityDeleted { uid: gone[0] },
+                _ => ServerMsg::EntitiesDeleted { uids: gone },
+            };
+            let _ = client.postoffice.send_one(msg); // We don't care if this fails
+        }
+        drop(clients);
+
+        match deleted.len() {
+            0 => {},
+            1 => self.broadcast_net_msg(ServerMsg::EntityDeleted { uid: deleted[0] }),
+            _ => self.broadcast_net_msg(ServerMsg::EntitiesDeleted { uids: deleted }),
+        }
+    }
+
+    // The clients whose player is within `range` of `pos`, and that have the chunk it's in
+    fn clients_in_range(&self, pos: Vec3<f32>, range: f32) -> Vec<Entity> {
+        let chunk = voxabs_to_voloffs(pos.map(|e| e.floor() as VoxAbs), CHUNK_SIZE);
+        let (clients, positions) = (self.world.read_storage::<Client>(), self.world.read_storage::<Pos>());
+        (&self.world.entities(), &clients, &positions)
+            .join()
+            .filter(|(_, client, player_pos)| {
+                player_pos.0.distance(pos) <= range && client.known_chunks.contains_key(&chunk)
+            })
+            .map(|(entity, _, _)| entity)
+            .collect()
+    }
+
+    fn send_to(&self, target: Target, msg: ServerMsg) {
+        match target {
+            Target::Client(entity) => self.send_net_msg(entity, msg),
+            Target::All => self.broadcast_net_msg(msg),
+            Target::AllExcept(except) => {
+                for (entity, client) in (&self.world.entities(), &self.world.read_storage::<Client>()).join() {
+                    if entity != except {
+                        let _ = client.postoffice.send_one(msg.clone()); // We don't care if this fails
+                    }
+                }
+            },
+            Target::Near(pos) => {
+                let range = self.world.read_resource::<TickConfig>().hearing_range;
+                self.send_to(Target::InRange(pos, range), msg);
+            },
+            Target::InRange(pos, range) => {
+                for entity in self.clients_in_range(pos, range) {
+                    self.send_net_msg(entity, msg.clone());
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// Library
+use specs::{Join, ReadExpect, ReadStorage, System, WriteStorage};
+use vek::*;
+
+// Project
+use common::{
+    ecs::phys::{MoveMode, Pos, Vel},
+    physics::config::PhysicsConfig,
+    terrain::WorldBorder,
+};
+
+// Local
+use super::{DeltaTime, Projectile};
+use crate::net::Client;
+
+/// Moves entities along their velocity, covering ground faster or slower depending on their move mode. Players' clients
+/// move them, so only entities the server controls are moved. Projectiles fly themselves. Nothing walks beyond the
+/// world border.
+pub struct Movement;
+
+impl<'a> System<'a> for Movement {
+    type SystemData = (
+        ReadExpect<'a, DeltaTime>,
+        ReadExpect<'a, WorldBorder>,
+        ReadExpect<'a, PhysicsConfig>,
+        WriteStorage<'a, Pos>,
+        ReadStorage<'a, Vel>,
+        ReadStorage<'a, MoveMode>,
+        ReadStorage<'a, Client>,
+        ReadStorage<'a, Projectile>,
+    );
+
+    fn run(
+        &mut self,
+        (dt, border, physics, mut positions, velocities, move_modes, clients, projectiles): Self::SystemData,
+    ) {
+        let dt = dt.0.as_float_secs() as f32;
+        for (pos, vel, move_mode, _, _) in (
+            &mut positions,
+            &velocities,
+            move_modes.maybe(),
+            !&clients,
+            !&projectiles,
+        )
+            .join()
+        {
+            let speed = move_mode.map(|m| physics.speed_factor(*m)).unwrap_or(1.0);
+            pos.0 = border.clamp(pos.0 + vel.0 * Vec3::new(speed, speed, 1.0) * dt, 0.0);
+        }
+    }
+}
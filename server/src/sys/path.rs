@@ -0,0 +1,410 @@
+// Standard
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    f32,
+};
+
+// Library
+use specs::{
+    prelude::ParallelIterator, BitSet, Component, Entities, HashMapStorage, Join, ParJoin, ReadExpect, ReadStorage,
+    System, WriteStorage,
+};
+use vek::*;
+
+// Project
+use common::{
+    ecs::phys::{Dir, Pos, Vel},
+    terrain::{chunk::Block, VolOffs, VoxAbs, Voxel},
+};
+
+// Local
+use super::{DeltaTime, LoadedChunks};
+
+// Constants
+// What each kind of move costs. Climbing and jumping are a little dearer than walking, so they're avoided when walking
+// around doesn't take much longer.
+const WALK_COST: u32 = 2;
+const STEP_COST: u32 = 3;
+const JUMP_COST: u32 = 2;
+const DIRS: [Vec3<VoxAbs>; 4] = [
+    Vec3 { x: 1, y: 0, z: 0 },
+    Vec3 { x: -1, y: 0, z: 0 },
+    Vec3 { x: 0, y: 1, z: 0 },
+    Vec3 { x: 0, y: -1, z: 0 },
+];
+// How close an entity has to get to a waypoint to have reached it, in blocks
+const ARRIVE_RADIUS: f32 = 0.2;
+// How long an entity can go without getting any closer to its next waypoint before it finds a new path, in seconds
+const STALL_TIME: f32 = 1.0;
+
+/// How the pathfinder searches
+#[derive(Copy, Clone, Debug)]
+pub struct PathConfig {
+    /// How wide a gap entities can jump across, in blocks
+    pub jump_distance: u32,
+    /// How many blocks a search looks at before giving up
+    pub max_nodes: usize,
+    /// How many searches are run each tick. Any more wait for the next tick.
+    pub paths_per_tick: usize,
+}
+
+impl Default for PathConfig {
+    fn default() -> Self {
+        Self {
+            jump_distance: 1,
+            max_nodes: 4096,
+            paths_per_tick: 8,
+        }
+    }
+}
+
+/// Asks for a path to `goal`, the block the entity's feet should end up in. Once one is found, the entity follows it
+/// at `speed` blocks per second.
+#[derive(Copy, Clone, Debug)]
+pub struct PathRequest {
+    pub goal: Vec3<VoxAbs>,
+    pub speed: f32,
+}
+
+impl Component for PathRequest {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// The way to a goal, which the entity is following. It's taken away once the entity arrives.
+#[derive(Clone, Debug)]
+pub struct Path {
+    /// The blocks the entity's feet pass through, in order. The last is the goal, unless the path is partial.
+    pub waypoints: Vec<Vec3<VoxAbs>>,
+    pub goal: Vec3<VoxAbs>,
+    pub speed: f32,
+    /// Whether the path stops short of the goal where the terrain isn't loaded, to be carried on from once it is
+    pub partial: bool,
+    // The closest the entity has been to its next waypoint, and how long ago that was
+    closest: f32,
+    stalled: f32,
+}
+
+impl Path {
+    fn new(request: PathRequest, waypoints: Vec<Vec3<VoxAbs>>, partial: bool) -> Self {
+        Self {
+            waypoints,
+            goal: request.goal,
+            speed: request.speed,
+            partial,
+            closest: f32::INFINITY,
+            stalled: 0.0,
+        }
+    }
+
+    fn request(&self) -> PathRequest {
+        PathRequest {
+            goal: self.goal,
+            speed: self.speed,
+        }
+    }
+}
+
+impl Component for Path {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// What a search for a path came up with. Paths don't include where they start from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathResult {
+    Found(Vec<Vec3<VoxAbs>>),
+    /// The search ran into terrain that isn't loaded, so this goes as close to the goal as it could tell
+    Partial(Vec<Vec3<VoxAbs>>),
+    /// The search ran into terrain that isn't loaded before it could get any closer, so it's worth trying again once
+    /// that terrain is there
+    Unloaded,
+    /// There's no way to the goal, or none was found before the search gave up
+    NotFound,
+}
+
+// Whether something can stand with its feet in `vox`: two blocks of room, with something solid to stand on
+fn walkable<F>(block_at: &F, vox: Vec3<VoxAbs>) -> Result<bool, Vec3<VolOffs>>
+where
+    F: Fn(Vec3<VoxAbs>) -> Result<Block, Vec3<VolOffs>>,
+{
+    Ok(clear(block_at, vox)? && block_at(vox - Vec3::unit_z())?.is_solid())
+}
+
+// Whether something two blocks tall fits with its feet in `vox`
+fn clear<F>(block_at: &F, vox: Vec3<VoxAbs>) -> Result<bool, Vec3<VolOffs>>
+where
+    F: Fn(Vec3<VoxAbs>) -> Result<Block, Vec3<VolOffs>>,
+{
+    Ok(!block_at(vox)?.is_solid() && !block_at(vox + Vec3::unit_z())?.is_solid())
+}
+
+// Where something standing at `from` can get to in one move, and what it costs. Anything that can't be told because
+// the terrain isn't loaded is left out, and noted in `unloaded`.
+fn moves<F>(block_at: &F, from: Vec3<VoxAbs>, config: &PathConfig, unloaded: &mut bool) -> Vec<(Vec3<VoxAbs>, u32)>
+where
+    F: Fn(Vec3<VoxAbs>) -> Result<Block, Vec3<VolOffs>>,
+{
+    let mut known = |result: Result<bool, Vec3<VolOffs>>| {
+        result.unwrap_or_else(|_| {
+            *unloaded = true;
+            false
+        })
+    };
+    let up = Vec3::unit_z();
+
+    let mut moves = vec![];
+    for dir in DIRS.iter() {
+        let side = from + *dir;
+        if known(walkable(block_at, side)) {
+            moves.push((side, WALK_COST));
+        } else if known(walkable(block_at, side + up)) && known(clear(block_at, from + up)) {
+            // Stepping up needs room above the entity's head
+            moves.push((side + up, STEP_COST));
+        } else if known(walkable(block_at, side - up)) && known(clear(block_at, side)) {
+            moves.push((side - up, STEP_COST));
+        } else {
+            // Jump a gap, as long as there's room to fly over it
+            for gap in 1..=config.jump_distance {
+                let over = from + *dir * gap as VoxAbs;
+                if !known(clear(block_at, over)) {
+                    break;
+                }
+                let land = over + *dir;
+                if known(walkable(block_at, land)) {
+                    moves.push((land, WALK_COST * (gap + 1) + JUMP_COST));
+                    break;
+                }
+            }
+        }
+    }
+    moves
+}
+
+/// Search for a path over the terrain from `start` to `goal`, which are where the feet of something two blocks tall
+/// would be. It can walk, step up or down a block at a time, and jump gaps up to `config.jump_distance` wide.
+/// `block_at` fails for blocks that aren't loaded, like `LoadedChunks::block_at`.
+pub fn find_path<F>(start: Vec3<VoxAbs>, goal: Vec3<VoxAbs>, config: &PathConfig, block_at: F) -> PathResult
+where
+    F: Fn(Vec3<VoxAbs>) -> Result<Block, Vec3<VolOffs>>,
+{
+    // Don't bother searching for a way to somewhere nothing can stand
+    if let Ok(false) = walkable(&block_at, goal) {
+        return PathResult::NotFound;
+    }
+
+    // Every move covers at least one block across or up for at least `WALK_COST`, so this never overestimates
+    let estimate = |vox: Vec3<VoxAbs>| {
+        let dist = (vox - goal).map(|e| e.abs() as u32);
+        (dist.x + dist.y).max(dist.z) * WALK_COST
+    };
+
+    // The open set holds indices into `nodes`, since positions can't be ordered
+    let mut nodes = vec![(start, 0)];
+    let mut open = BinaryHeap::new();
+    open.push(Reverse((estimate(start), estimate(start), 0)));
+    let mut costs = HashMap::new();
+    costs.insert(start, 0);
+    let mut came_from = HashMap::new();
+    let (mut closest, mut closest_estimate) = (start, estimate(start));
+    let mut unloaded = false;
+    let mut searched = 0;
+
+    while let Some(Reverse((_, node_estimate, index))) = open.pop() {
+        let (node, cost) = nodes[index];
+        // There's a cheaper way here, which has been searched from already
+        if cost > costs[&node] {
+            continue;
+        }
+        if node == goal {
+            return PathResult::Found(path_to(&came_from, node));
+        }
+        if node_estimate < closest_estimate {
+            closest = node;
+            closest_estimate = node_estimate;
+        }
+
+        searched += 1;
+        if searched > config.max_nodes {
+            return PathResult::NotFound;
+        }
+
+        for (next, move_cost) in moves(&block_at, node, config, &mut unloaded) {
+            let cost = cost + move_cost;
+            if costs.get(&next).map(|&c| cost < c).unwrap_or(true) {
+                costs.insert(next, cost);
+                came_from.insert(next, node);
+                nodes.push((next, cost));
+                open.push(Reverse((cost + estimate(next), estimate(next), nodes.len() - 1)));
+            }
+        }
+    }
+
+    if unloaded && closest != start {
+        PathResult::Partial(path_to(&came_from, closest))
+    } else if unloaded {
+        PathResult::Unloaded
+    } else {
+        PathResult::NotFound
+    }
+}
+
+fn path_to(came_from: &HashMap<Vec3<VoxAbs>, Vec3<VoxAbs>>, mut node: Vec3<VoxAbs>) -> Vec<Vec3<VoxAbs>> {
+    let mut path = vec![node];
+    while let Some(prev) = came_from.get(&node) {
+        node = *prev;
+        path.push(node);
+    }
+    // The start is where the entity already is
+    path.pop();
+    path.reverse();
+    path
+}
+
+/// Finds paths for entities that asked for them, a few each tick, running the searches in parallel. Entities without
+/// a path to their goal are left where they are. Those waiting on terrain to load keep asking until it has.
+#[derive(Default)]
+pub struct Pathfind {
+    // Where the last tick's searches left off, so that requests waiting on terrain don't keep the rest from a turn
+    next: u32,
+}
+
+impl<'a> System<'a> for Pathfind {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, PathConfig>,
+        ReadExpect<'a, LoadedChunks>,
+        ReadStorage<'a, Pos>,
+        WriteStorage<'a, PathRequest>,
+        WriteStorage<'a, Path>,
+    );
+
+    fn run(&mut self, (entities, config, chunks, positions, mut requests, mut paths): Self::SystemData) {
+        // Plain references can be shared between the threads searching
+        let config: &PathConfig = &config;
+        let chunks: &LoadedChunks = &chunks;
+
+        let asking = (&entities, &requests, &positions).join().map(|(entity, _, _)| entity.id());
+        let (later, earlier): (Vec<u32>, Vec<u32>) = asking.partition(|id| *id >= self.next);
+        let mut chosen = BitSet::new();
+        for id in later.into_iter().chain(earlier).take(config.paths_per_tick) {
+            chosen.add(id);
+            self.next = id + 1;
+        }
+        let results = (&chosen, &entities, &requests, &positions)
+            .par_join()
+            .map(|(_, entity, request, pos)| {
+                let start = pos.0.map(|e| e.floor() as VoxAbs);
+                let result = find_path(start, request.goal, config, |vox| chunks.block_at(vox));
+                (entity, *request, result)
+            })
+            .collect::<Vec<_>>();
+
+        for (entity, request, result) in results {
+            let path = match result {
+                PathResult::Found(waypoints) => Path::new(request, waypoints, false),
+                PathResult::Partial(waypoints) => Path::new(request, waypoints, true),
+                // The request stays to be tried again
+                PathResult::Unloaded => continue,
+                PathResult::NotFound => {
+                    debug!("No path for {:?} to {}", entity, request.goal);
+                    requests.remove(entity);
+                    continue;
+                },
+            };
+            requests.remove(entity);
+            let _ = paths.insert(entity, path);
+        }
+    }
+}
+
+/// Walks entities along their paths. If the way ahead gets blocked, or they stop getting anywhere, they ask for a new
+/// path, as they do at the end of a partial one.
+pub struct FollowPath;
+
+impl<'a> System<'a> for FollowPath {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, DeltaTime>,
+        ReadExpect<'a, LoadedChunks>,
+        ReadStorage<'a, Pos>,
+        WriteStorage<'a, Vel>,
+        WriteStorage<'a, Dir>,
+        WriteStorage<'a, Path>,
+        WriteStorage<'a, PathRequest>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, dt, chunks, positions, mut velocities, mut dirs, mut paths, mut requests): Self::SystemData,
+    ) {
+        let dt = dt.0.as_float_secs() as f32;
+        let block_at = |vox: Vec3<VoxAbs>| chunks.block_at(vox);
+
+        let (mut arrived, mut replan) = (vec![], vec![]);
+        for (entity, pos, vel, dir, path) in
+            (&entities, &positions, &mut velocities, (&mut dirs).maybe(), &mut paths).join()
+        {
+            // Feet go in the middle of the bottom of each waypoint's block
+            let target = |vox: Vec3<VoxAbs>| vox.map(|e| e as f32) + Vec3::new(0.5, 0.5, 0.0);
+            while let Some(next) = path.waypoints.first().map(|vox| target(*vox)) {
+                let reached =
+                    Vec2::from(next).distance(Vec2::from(pos.0)) < ARRIVE_RADIUS && (next.z - pos.0.z).abs() < 0.5;
+                if !reached {
+                    break;
+                }
+                path.waypoints.remove(0);
+                path.closest = f32::INFINITY;
+                path.stalled = 0.0;
+            }
+
+            let next = match path.waypoints.first() {
+                // Terrain can change after a path is found
+                Some(next) if walkable(&block_at, *next) == Ok(false) => None,
+                Some(next) => Some(target(*next)),
+                None => None,
+            };
+            let next = match next {
+                Some(next) => next,
+                None => {
+                    vel.0 = Vec3::zero();
+                    if path.waypoints.is_empty() && !path.partial {
+                        arrived.push(entity);
+                    } else {
+                        replan.push((entity, path.request()));
+                    }
+                    continue;
+                },
+            };
+
+            let dist = next.distance(pos.0);
+            if dist < path.closest - ARRIVE_RADIUS / 2.0 {
+                path.closest = dist;
+                path.stalled = 0.0;
+            } else {
+                path.stalled += dt;
+                if path.stalled > STALL_TIME {
+                    vel.0 = Vec3::zero();
+                    replan.push((entity, path.request()));
+                    continue;
+                }
+            }
+
+            // Don't overshoot the waypoint
+            let speed = if dt > 0.0 { path.speed.min(dist / dt) } else { path.speed };
+            vel.0 = (next - pos.0) / dist.max(0.001) * speed;
+            // Face the way they're walking, leaning no differently than before
+            let heading = Vec2::from(next - pos.0);
+            if let (Some(dir), true) = (dir, heading.magnitude_squared() > 0.0) {
+                dir.0.x = heading.x.atan2(heading.y);
+            }
+        }
+
+        for entity in arrived {
+            paths.remove(entity);
+        }
+        for (entity, request) in replan {
+            paths.remove(entity);
+            let _ = requests.insert(entity, request);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// Standard
+use std::cmp::Ordering;
+
+// Library
+use specs::{
+    saveload::Marker, Component, Entities, Entity, Join, ReadExpect, ReadStorage, System, VecStorage, WriteStorage,
+};
+use vek::*;
+
+// Project
+use common::{
+    ecs::{
+        inventory::{Inventory, Item},
+        net::UidMarker,
+        phys::Pos,
+        NetComp,
+    },
+    util::msg::{CompStore, ServerMsg},
+};
+
+// Local
+use super::{DeltaTime, Outbox, Target, TickConfig};
+
+// Constants
+// How long a dropped item lies before it can be picked up, so that whoever dropped it doesn't grab it straight back
+const PICKUP_DELAY: f32 = 1.5;
+// How close an entity has to be to an item to pick it up
+const PICKUP_RADIUS: f32 = 1.5;
+
+/// An item lying in the world, waiting to be picked up
+#[derive(Clone, Debug)]
+pub struct ItemDrop {
+    pub item: Item,
+    // How long the item has been lying there, in seconds
+    age: f32,
+}
+
+impl ItemDrop {
+    pub fn new(item: Item) -> Self { Self { item, age: 0.0 } }
+}
+
+impl Component for ItemDrop {
+    type Storage = VecStorage<Self>;
+}
+
+impl NetComp for ItemDrop {
+    fn to_store(&self) -> Option<CompStore> { Some(CompStore::Item(self.item)) }
+}
+
+/// Moves dropped items into the inventories of entities that walk over them, closest first, and clears away items that
+/// have lain unclaimed for too long
+pub struct Pickup;
+
+impl<'a> System<'a> for Pickup {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, DeltaTime>,
+        ReadExpect<'a, TickConfig>,
+        ReadStorage<'a, Pos>,
+        ReadStorage<'a, UidMarker>,
+        WriteStorage<'a, ItemDrop>,
+        WriteStorage<'a, Inventory>,
+        ReadExpect<'a, Outbox>,
+    );
+
+    fn run(&mut self, (entities, dt, config, positions, uids, mut drops, mut inventories, outbox): Self::SystemData) {
+        let dt = dt.0.as_float_secs() as f32;
+        let despawn = config.item_despawn.as_float_secs() as f32;
+        let mut removed = vec![];
+        for (drop_entity, drop, drop_pos) in (&entities, &mut drops, &positions).join() {
+            drop.age += dt;
+            if drop.age >= despawn {
+                remove_drop(&entities, &uids, &outbox, drop_entity);
+                removed.push(drop_entity);
+                continue;
+            }
+            if drop.age < PICKUP_DELAY {
+                continue;
+            }
+
+            // The closest entity gets first pick, with ties going the same way every time
+            let mut holders = (&entities, &positions, &inventories)
+                .join()
+                .map(|(holder, pos, _)| (holder, pos.0.distance(drop_pos.0)))
+                .filter(|(_, dist)| *dist <= PICKUP_RADIUS)
+                .collect::<Vec<_>>();
+            holders.sort_by(|a, b| {
+                a.1.partial_cmp(&b.1)
+                    .unwrap_or(Ordering::Equal)
+                    .then(a.0.id().cmp(&b.0.id()))
+            });
+
+            // Hand out as much of the item as fits to whoever is close enough
+            for (holder, _) in holders {
+                let inv = match inventories.get_mut(holder) {
+                    Some(inv) => inv,
+                    None => continue,
+                };
+                let left = inv.insert(drop.item);
+                if left == Some(drop.item) {
+                    continue; // Nothing fit
+                }
+                if let (Some(uid), Some(store)) = (uids.get(holder), inv.to_store()) {
+                    outbox.send(
+                        Target::Client(holder),
+                        ServerMsg::CompUpdate {
+                            uid: uid.id(),
+                            store,
+                            forced: true,
+                        },
+                    );
+                }
+
+                match left {
+                    Some(left) => drop.item = left,
+                    None => {
+                        remove_drop(&entities, &uids, &outbox, drop_entity);
+                        removed.push(drop_entity);
+                        break;
+                    },
+                }
+            }
+        }
+
+        // Deleted entities linger until the end of the tick, so make sure nothing else treats them as items meanwhile
+        for entity in removed {
+            drops.remove(entity);
+        }
+    }
+}
+
+/// Every few ticks, merges dropped stacks of the same kind that lie close together, so that a pile of drops becomes
+/// one entity. The stack that was dropped first is kept, and takes as much of the others as fits.
+#[derive(Default)]
+pub struct ItemMerge {
+    ticks: u32,
+}
+
+impl<'a> System<'a> for ItemMerge {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, TickConfig>,
+        ReadStorage<'a, Pos>,
+        ReadStorage<'a, UidMarker>,
+        WriteStorage<'a, ItemDrop>,
+        ReadExpect<'a, Outbox>,
+    );
+
+    fn run(&mut self, (entities, config, positions, uids, mut drops, outbox): Self::SystemData) {
+        let due = self.ticks % config.item_merge_ticks.max(1) == 0;
+        self.ticks = self.ticks.wrapping_add(1);
+        if !due {
+            return;
+        }
+
+        // Go through drops in the order they were made so that merging doesn't depend on storage order
+        let mut piles: Vec<(Entity, Vec3<f32>)> = (&entities, &drops, &positions)
+            .join()
+            .map(|(entity, _, pos)| (entity, pos.0))
+            .collect();
+        piles.sort_by_key(|(entity, _)| (uids.get(*entity).map(|uid| uid.id()), entity.id()));
+        let mut gone = vec![false; piles.len()];
+
+        for i in 0..piles.len() {
+            if gone[i] {
+                continue;
+            }
+            let (into, into_pos) = piles[i];
+            for j in i + 1..piles.len() {
+                let (from, from_pos) = piles[j];
+                if gone[j] || from_pos.distance(into_pos) > config.item_merge_radius {
+                    continue;
+                }
+                let from_drop = match drops.get(from) {
+                    Some(drop) => drop.clone(),
+                    None => continue,
+                };
+                let moved = match drops.get_mut(into) {
+                    Some(drop) => {
+                        if drop.item.kind != from_drop.item.kind {
+                            continue;
+                        }
+                        let room = drop.item.kind.max_stack().saturating_sub(drop.item.amount);
+                        let moved = from_drop.item.amount.min(room);
+                        if moved > 0 {
+                            drop.item.amount += moved;
+                            // Neither stack should be picked up or despawn any sooner for having been merged
+                            drop.age = drop.age.min(from_drop.age);
+                        }
+                        moved
+                    },
+                    None => continue,
+                };
+                if moved == 0 {
+                    continue;
+                }
+
+                if moved == from_drop.item.amount {
+                    remove_drop(&entities, &uids, &outbox, from);
+                    drops.remove(from);
+                    gone[j] = true;
+                } else if let Some(drop) = drops.get_mut(from) {
+                    drop.item.amount -= moved;
+                }
+            }
+        }
+    }
+}
+
+/// Delete a dropped item, and tell everyone it's gone
+fn remove_drop(entities: &Entities, uids: &ReadStorage<UidMarker>, outbox: &Outbox, entity: Entity) {
+    let _ = entities.delete(entity);
+    if let Some(uid) = uids.get(entity) {
+        outbox.send(Target::All, ServerMsg::EntityDeleted { uid: uid.id() });
+    }
+}
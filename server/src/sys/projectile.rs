@@ -0,0 +1,247 @@
+// Standard
+use std::{cmp::Ordering, collections::HashMap, time::Duration};
+
+// Library
+use specs::{
+    saveload::Marker, Component, Entities, Entity, Join, ReadExpect, ReadStorage, System, VecStorage, WriteStorage,
+};
+use vek::*;
+
+// Project
+use common::{
+    audio::SoundId,
+    ecs::{
+        character::{Character, Health},
+        net::UidMarker,
+        phys::{CollisionBox, MoveMode, Pos, Vel},
+        NetComp,
+    },
+    physics::{
+        collision::{Primitive, ResolutionTti},
+        config::PhysicsConfig,
+    },
+    terrain::{VoxAbs, Voxel},
+    util::msg::{CompStore, ServerMsg},
+};
+
+// Local
+use super::{hurt, Dead, DeltaTime, LoadedChunks, Outbox, PosHistory, Target};
+
+// Constants
+const PROJECTILE_RADIUS: f32 = 0.1;
+// How long a projectile ignores whoever fired it, so that it doesn't hit them on its way out
+const OWNER_IMMUNITY: f32 = 0.1;
+
+/// The kind of projectile to fire
+#[derive(Copy, Clone, Debug)]
+pub struct ProjectileSpec {
+    pub damage: u32,
+    /// How long the projectile lasts if it doesn't hit anything, in seconds
+    pub lifetime: f32,
+}
+
+impl Default for ProjectileSpec {
+    fn default() -> Self {
+        Self {
+            damage: 10,
+            lifetime: 3.0,
+        }
+    }
+}
+
+/// Something flying through the air that hurts whatever it hits, then disappears
+#[derive(Clone, Debug)]
+pub struct Projectile {
+    pub owner: Entity,
+    pub damage: u32,
+    pub lifetime: f32,
+    /// How far back in `PosHistory` to look for what the projectile hits, so that it hits things where whoever fired
+    /// it saw them rather than where they've got to since
+    pub rewind: Duration,
+    // How long the projectile has been flying, in seconds
+    age: f32,
+}
+
+impl Projectile {
+    pub fn new(owner: Entity, spec: ProjectileSpec) -> Self {
+        Self {
+            owner,
+            damage: spec.damage,
+            lifetime: spec.lifetime,
+            rewind: Duration::default(),
+            age: 0.0,
+        }
+    }
+}
+
+impl Component for Projectile {
+    type Storage = VecStorage<Self>;
+}
+
+impl NetComp for Projectile {
+    fn to_store(&self) -> Option<CompStore> { Some(CompStore::Projectile) }
+}
+
+// How far through `movement` a primitive moving along it hits `obstacle`, if it does this tick
+fn impact(obstacle: &Primitive, prim: &Primitive, movement: &Vec3<f32>) -> Option<f32> {
+    match obstacle.time_to_impact(prim, movement)? {
+        ResolutionTti::WillCollide { tti, .. } if tti <= 1.0 => Some(tti),
+        ResolutionTti::Overlapping { .. } => Some(0.0),
+        _ => None,
+    }
+}
+
+// The solid blocks a projectile moving between two points could hit. Terrain that isn't loaded can't be hit.
+fn solid_blocks(chunks: &LoadedChunks, from: Vec3<f32>, to: Vec3<f32>) -> Vec<Primitive> {
+    let low = from.map2(to, |a, b| (a.min(b) - PROJECTILE_RADIUS).floor() as VoxAbs);
+    let high = from.map2(to, |a, b| (a.max(b) + PROJECTILE_RADIUS).floor() as VoxAbs);
+
+    let mut blocks = vec![];
+    for x in low.x..=high.x {
+        for y in low.y..=high.y {
+            for z in low.z..=high.z {
+                let vox = Vec3::new(x, y, z);
+                if chunks.block_at(vox).map(|b| b.is_solid()).unwrap_or(false) {
+                    blocks.push(Primitive::new_cuboid(vox.map(|e| e as f32) + 0.5, Vec3::broadcast(0.5)));
+                }
+            }
+        }
+    }
+    blocks
+}
+
+// What a projectile's target is told killed them
+fn cause_of_death(owner: Entity, target: Entity, characters: &ReadStorage<Character>) -> String {
+    match characters.get(owner) {
+        _ if owner == target => "You shot yourself".to_string(),
+        Some(character) => format!("You were shot by {}", character.name),
+        None => "You were shot".to_string(),
+    }
+}
+
+/// Flies projectiles along their path under gravity. Each is swept against terrain and anything with health that's
+/// still alive, as of however long ago it rewinds to, and disappears when it hits something or runs out of time.
+pub struct ProjectileSys;
+
+impl<'a> System<'a> for ProjectileSys {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, DeltaTime>,
+        ReadExpect<'a, PhysicsConfig>,
+        ReadExpect<'a, LoadedChunks>,
+        ReadExpect<'a, PosHistory>,
+        WriteStorage<'a, Pos>,
+        WriteStorage<'a, Vel>,
+        WriteStorage<'a, Projectile>,
+        WriteStorage<'a, Health>,
+        WriteStorage<'a, Dead>,
+        ReadStorage<'a, Character>,
+        ReadStorage<'a, CollisionBox>,
+        ReadStorage<'a, MoveMode>,
+        ReadStorage<'a, UidMarker>,
+        ReadExpect<'a, Outbox>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            dt,
+            physics,
+            chunks,
+            history,
+            mut positions,
+            mut vels,
+            mut projectiles,
+            mut healths,
+            mut deaths,
+            characters,
+            collision_boxes,
+            move_modes,
+            uids,
+            outbox,
+        ): Self::SystemData,
+    ) {
+        let dt = dt.0.as_float_secs() as f32;
+        let gravity = Vec3::unit_z() * physics.gravity;
+
+        // Anything without a size of its own is taken to be the size of a character
+        let targets = (
+            &entities,
+            &positions,
+            &healths,
+            !&deaths,
+            collision_boxes.maybe(),
+            move_modes.maybe(),
+        )
+            .join()
+            .map(|(e, pos, _, _, collision_box, mode)| {
+                let size = collision_box
+                    .cloned()
+                    .unwrap_or_default()
+                    .size(mode.cloned().unwrap_or(MoveMode::Walk));
+                (e, pos.0, size)
+            })
+            .collect::<Vec<_>>();
+
+        let despawn = |entity: Entity| {
+            let _ = entities.delete(entity);
+            if let Some(uid) = uids.get(entity) {
+                outbox.send(Target::All, ServerMsg::EntityDeleted { uid: uid.id() });
+            }
+        };
+
+        // Where targets were as of each rewind any projectile uses, worked out once for all of them
+        let mut rewound: HashMap<Duration, HashMap<Entity, Vec3<f32>>> = HashMap::new();
+        let mut hits = vec![];
+        for (entity, proj, pos, vel) in (&entities, &mut projectiles, &mut positions, &mut vels).join() {
+            proj.age += dt;
+            if proj.age > proj.lifetime {
+                despawn(entity);
+                continue;
+            }
+
+            vel.0 += gravity * dt;
+            let movement = vel.0 * dt;
+            let prim = Primitive::new_cuboid(pos.0, Vec3::broadcast(PROJECTILE_RADIUS));
+
+            // Find whatever the projectile hits first, if anything
+            let terrain_hit = solid_blocks(&chunks, pos.0, pos.0 + movement)
+                .into_iter()
+                .filter_map(|block| impact(&block, &prim, &movement))
+                .map(|tti| (tti, None));
+            let seen = if proj.rewind > Duration::default() {
+                Some(&*rewound.entry(proj.rewind).or_insert_with(|| history.entities_at(proj.rewind)))
+            } else {
+                None
+            };
+            let target_hit = targets
+                .iter()
+                .filter(|(target, _, _)| *target != proj.owner || proj.age >= OWNER_IMMUNITY)
+                .filter_map(|(target, target_pos, size)| {
+                    let target_pos = seen.and_then(|seen| seen.get(target)).cloned().unwrap_or(*target_pos);
+                    let target_prim = Primitive::new_cuboid(target_pos + Vec3::unit_z() * size.z / 2.0, *size / 2.0);
+                    impact(&target_prim, &prim, &movement).map(|tti| (tti, Some(*target)))
+                });
+            let first_hit = terrain_hit
+                .chain(target_hit)
+                .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+            match first_hit {
+                Some((tti, target)) => {
+                    outbox.play_sound(SoundId::PROJECTILE_HIT, pos.0 + movement * tti, 1.0);
+                    if let Some(target) = target {
+                        hits.push((target, proj.damage, proj.owner));
+                    }
+                    despawn(entity);
+                },
+                None => pos.0 += movement,
+            }
+        }
+
+        for (target, damage, owner) in hits {
+            let cause = cause_of_death(owner, target, &characters);
+            hurt(target, damage, &cause, &mut healths, &mut deaths, &uids, &outbox);
+        }
+    }
+}
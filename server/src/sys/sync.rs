@@ -0,0 +1,135 @@
+// Standard
+use std::collections::{HashMap, HashSet};
+
+// Library
+use specs::{saveload::Marker, Entities, Entity, Join, ReadExpect, ReadStorage, System};
+
+// Project
+use common::{
+    ecs::{
+        character::Character,
+        inventory::{HeldItem, Inventory},
+        net::UidMarker,
+        phys::{CollisionBox, Dir, MoveMode, Pos, Vel},
+        NetComp,
+    },
+    util::msg::{CompStore, ServerMsg},
+};
+
+// Local
+use super::{ItemDrop, Outbox, Projectile, Target, TickConfig};
+use crate::net::Client;
+
+/// Tells clients where every entity is and how it's moving, including whether it's sprinting or crouching, what
+/// characters are called and holding, and what any items lying around or projectiles in flight are. A client isn't
+/// sent its own player's state, since it knows better, except for its size, which it needs to move itself around.
+/// Sizes hardly ever change, so they're only sent when they do, and to clients that have just joined. Dropped items are
+/// only synced to players within `TickConfig::item_sync_range` of them, and removed from clients whose players leave
+/// that range.
+// TODO: Extend the notion of range to other entities? Don't update clients of entities that are nowhere near them
+#[derive(Default)]
+pub struct EntitySync {
+    // The size every client was last sent for each entity
+    boxes: HashMap<Entity, CollisionBox>,
+    // The clients that were around last tick, so that new ones can be told every size
+    clients: HashSet<Entity>,
+}
+
+impl<'a> System<'a> for EntitySync {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, UidMarker>,
+        ReadStorage<'a, Pos>,
+        ReadStorage<'a, Vel>,
+        ReadStorage<'a, Dir>,
+        ReadStorage<'a, MoveMode>,
+        ReadStorage<'a, Character>,
+        ReadStorage<'a, CollisionBox>,
+        ReadStorage<'a, HeldItem>,
+        ReadStorage<'a, Inventory>,
+        ReadStorage<'a, ItemDrop>,
+        ReadStorage<'a, Projectile>,
+        ReadStorage<'a, Client>,
+        ReadExpect<'a, TickConfig>,
+        ReadExpect<'a, Outbox>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            uids,
+            positions,
+            velocities,
+            dirs,
+            move_modes,
+            characters,
+            collision_boxes,
+            held_items,
+            inventories,
+            drops,
+            projectiles,
+            clients,
+            config,
+            outbox,
+        ): Self::SystemData,
+    ) {
+        let current: HashSet<Entity> = (&entities, &clients).join().map(|(entity, _)| entity).collect();
+        let joined: Vec<Entity> = current.difference(&self.clients).cloned().collect();
+        self.clients = current;
+        self.boxes.retain(|entity, _| collision_boxes.get(*entity).is_some());
+
+        for (entity, uid) in (&entities, &uids).join() {
+            let stores = [
+                positions.get(entity).and_then(|c| c.to_store()),
+                velocities.get(entity).and_then(|c| c.to_store()),
+                dirs.get(entity).and_then(|c| c.to_store()),
+                move_modes.get(entity).and_then(|c| c.to_store()),
+                characters.get(entity).and_then(|c| c.to_store()),
+                // Only the kind of item is sent, since how many they have is nobody else's business
+                held_items.get(entity).map(|held| CompStore::HeldItem {
+                    slot: held.0,
+                    item: inventories.get(entity).and_then(|inv| inv.get(held.0)).map(|item| item.kind),
+                }),
+                drops.get(entity).and_then(|c| c.to_store()),
+                projectiles.get(entity).and_then(|c| c.to_store()),
+            ];
+            let target = match (drops.get(entity), positions.get(entity)) {
+                (Some(_), Some(pos)) => Target::InRange(pos.0, config.item_sync_range),
+                _ => Target::AllExcept(entity),
+            };
+            // Players need their own size too. Entities that are only synced nearby send theirs with the rest of their
+            // state, since players come into range of them all the time.
+            let mut box_targets = vec![];
+            if let Some(collision_box) = collision_boxes.get(entity) {
+                match target {
+                    Target::AllExcept(_) => {
+                        if self.boxes.insert(entity, *collision_box) != Some(*collision_box) {
+                            box_targets.push(Target::All);
+                        } else {
+                            box_targets.extend(joined.iter().map(|client| Target::Client(*client)));
+                        }
+                    },
+                    target => box_targets.push(target),
+                }
+            }
+            let collision_box = collision_boxes.get(entity).and_then(|c| c.to_store());
+            let targets = stores
+                .iter()
+                .cloned()
+                .filter_map(|s| s)
+                .map(|s| (target, s))
+                .chain(box_targets.into_iter().filter_map(|t| collision_box.clone().map(|s| (t, s))));
+            for (target, store) in targets {
+                outbox.send(
+                    target,
+                    ServerMsg::CompUpdate {
+                        uid: uid.id(),
+                        store,
+                        forced: false,
+                    },
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,999 @@
+// Standard
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// Library
+use serde_derive::{Deserialize, Serialize};
+use specs::{saveload::MarkedBuilder, Builder, Entity, RunNow, System, World};
+use vek::*;
+
+// Project
+use common::{
+    ecs::{
+        self,
+        character::Health,
+        inventory::{Inventory, Item, ItemKind},
+        net::UidMarker,
+        phys::{CollisionBox, Dir, MoveMode, Pos, Vel},
+        CreateUtil,
+    },
+    net::Message,
+    terrain::{
+        chunk::{Block, Chunk, HomogeneousData, CHUNK_SIZE},
+        voxabs_to_voloffs, ConstructVolume, VolOffs, VoxAbs, Voxel,
+    },
+    util::msg::{CompStore, PlayMode},
+};
+
+// Local
+use super::*;
+
+fn world() -> World {
+    let mut world = ecs::create_world();
+    world.register::<Client>();
+    setup(&mut world);
+    world
+}
+
+fn run<S: for<'a> System<'a>>(world: &mut World, mut sys: S, dt: Duration) {
+    world.write_resource::<DeltaTime>().0 = dt;
+    sys.run_now(&world.res);
+    world.maintain();
+}
+
+#[test]
+fn movement_follows_velocity() {
+    let mut world = world();
+    let npc = world
+        .create_entity()
+        .with(Pos(Vec3::new(1.0, 2.0, 3.0)))
+        .with(Vel(Vec3::new(2.0, 0.0, -1.0)))
+        .build();
+
+    run(&mut world, Movement, Duration::from_millis(500));
+    assert_eq!(world.read_storage::<Pos>().get(npc).unwrap().0, Vec3::new(2.0, 2.0, 2.5));
+}
+
+#[test]
+fn movement_depends_on_move_mode() {
+    let mut world = world();
+    let (sprinter, croucher) = (
+        world
+            .create_entity()
+            .with(Pos(Vec3::zero()))
+            .with(Vel(Vec3::new(2.0, 0.0, -1.0)))
+            .with(MoveMode::Sprint)
+            .build(),
+        world
+            .create_entity()
+            .with(Pos(Vec3::zero()))
+            .with(Vel(Vec3::new(2.0, 0.0, -1.0)))
+            .with(MoveMode::Crouch)
+            .build(),
+    );
+
+    run(&mut world, Movement, Duration::from_secs(1));
+    let positions = world.read_storage::<Pos>();
+    let (sprinted, crouched) = (positions.get(sprinter).unwrap().0, positions.get(croucher).unwrap().0);
+    let physics = PhysicsConfig::default();
+    assert_eq!(sprinted.x, 2.0 * physics.speed_factor(MoveMode::Sprint));
+    assert_eq!(crouched.x, 2.0 * physics.speed_factor(MoveMode::Crouch));
+    // Falling isn't any faster for sprinting
+    assert_eq!(sprinted.z, -1.0);
+    assert_eq!(crouched.z, -1.0);
+}
+
+#[test]
+fn sync_queues_updates_for_other_clients() {
+    let mut world = world();
+    let character = world.create_character("zesterer".to_string()).build();
+
+    run(&mut world, EntitySync::default(), Duration::from_millis(20));
+    let msgs = world.read_resource::<Outbox>().drain();
+
+    // One message each for position, velocity, direction, move mode, name and held item, none of them for the
+    // character's own client, and one for its size, which its client needs too
+    assert_eq!(msgs.len(), 7);
+    for (target, msg) in msgs {
+        match msg {
+            ServerMsg::CompUpdate {
+                store: CompStore::CollisionBox(_),
+                forced,
+                ..
+            } => {
+                assert_eq!(target, Target::All);
+                assert!(!forced);
+            },
+            ServerMsg::CompUpdate { forced, .. } => {
+                assert_eq!(target, Target::AllExcept(character));
+                assert!(!forced);
+            },
+            _ => panic!("Expected a component update"),
+        }
+    }
+}
+
+#[test]
+fn sizes_are_only_synced_when_they_change() {
+    let mut world = world();
+    let character = world.create_character("zesterer".to_string()).build();
+    let mut sync = EntitySync::default();
+    let mut boxes_sent = |world: &mut World| {
+        world.write_resource::<DeltaTime>().0 = Duration::from_millis(20);
+        sync.run_now(&world.res);
+        world
+            .read_resource::<Outbox>()
+            .drain()
+            .into_iter()
+            .filter(|(_, msg)| match msg {
+                ServerMsg::CompUpdate {
+                    store: CompStore::CollisionBox(_),
+                    ..
+                } => true,
+                _ => false,
+            })
+            .count()
+    };
+
+    assert_eq!(boxes_sent(&mut world), 1);
+    assert_eq!(boxes_sent(&mut world), 0);
+    world
+        .write_storage::<CollisionBox>()
+        .insert(character, CollisionBox(Vec3::new(2.0, 2.0, 3.0)))
+        .unwrap();
+    assert_eq!(boxes_sent(&mut world), 1);
+    assert_eq!(boxes_sent(&mut world), 0);
+}
+
+// How positions and velocities would go out as plain `f32`s, to compare against
+#[derive(Serialize, Deserialize)]
+enum PlainStore {
+    Pos(Vec3<f32>),
+    Vel(Vec3<f32>),
+}
+
+#[derive(Serialize, Deserialize)]
+enum PlainMsg {
+    CompUpdate {
+        uid: u64,
+        store: PlainStore,
+        forced: bool,
+    },
+}
+
+impl Message for PlainMsg {}
+
+#[test]
+fn motion_syncs_in_fewer_bytes_than_plain_floats() {
+    let mut world = world();
+    let (pos, vel) = (Vec3::new(3000.123, -1234.5, 61.75), Vec3::new(4.25, -7.5, -30.0));
+    let character = world.create_character("zesterer".to_string()).build();
+    world.write_storage::<Pos>().insert(character, Pos(pos)).unwrap();
+    world.write_storage::<Vel>().insert(character, Vel(vel)).unwrap();
+
+    run(&mut world, EntitySync::default(), Duration::from_millis(20));
+    let (mut sent, mut plain) = (0, 0);
+    for (_, msg) in world.read_resource::<Outbox>().drain() {
+        let (uid, store) = match &msg {
+            ServerMsg::CompUpdate { uid, store, .. } => (*uid, store),
+            _ => continue,
+        };
+        let store = match store {
+            CompStore::Pos(net) => {
+                assert!(net.to_pos().distance(pos) < 0.001);
+                PlainStore::Pos(pos)
+            },
+            CompStore::Vel(net) => {
+                assert_eq!(net.to_vel(), vel);
+                PlainStore::Vel(vel)
+            },
+            _ => continue,
+        };
+        sent += msg.to_bytes().unwrap().len();
+        let plain_msg = PlainMsg::CompUpdate {
+            uid,
+            store,
+            forced: false,
+        };
+        plain += plain_msg.to_bytes().unwrap().len();
+    }
+    assert!(sent > 0 && sent < plain, "Sent {} bytes where plain floats take {}", sent, plain);
+}
+
+#[test]
+fn time_of_day_syncs_regularly() {
+    let mut world = world();
+    world.write_resource::<TickConfig>().time_sync_freq = Duration::from_secs(10);
+
+    let mut syncs = 0;
+    for _ in 0..25 {
+        run(&mut world, TimeOfDaySys, Duration::from_secs(1));
+        syncs += world.read_resource::<Outbox>().drain().len();
+    }
+    assert_eq!(world.read_resource::<TimeOfDay>().time, Duration::from_secs(25));
+    assert_eq!(syncs, 2);
+}
+
+#[test]
+fn wandering_entities_walk_at_their_speed() {
+    let mut world = world();
+    let npc = world
+        .create_entity()
+        .with(Pos(Vec3::zero()))
+        .with(Vel(Vec3::new(0.0, 0.0, -3.0)))
+        .with(Dir(Vec2::new(0.0, 0.3)))
+        .with(Wander::new(5.0, 42))
+        .build();
+
+    run(&mut world, WanderSys, Duration::from_millis(20));
+    let vel = world.read_storage::<Vel>().get(npc).unwrap().0;
+    assert!((Vec2::from(vel).magnitude() - 5.0).abs() < 0.001);
+    assert_eq!(vel.z, -3.0);
+    // They face the way they walk, as a yaw, and keep their lean
+    let dir = world.read_storage::<Dir>().get(npc).unwrap().0;
+    assert!((Vec2::new(dir.x.sin(), dir.x.cos()) - Vec2::from(vel) / 5.0).magnitude() < 0.001);
+    assert_eq!(dir.y, 0.3);
+}
+
+#[test]
+fn items_are_picked_up_into_matching_stacks() {
+    let mut world = world();
+    let character = world.create_character("zesterer".to_string()).build();
+    world
+        .write_storage::<Inventory>()
+        .get_mut(character)
+        .unwrap()
+        .insert(Item::new(ItemKind::Stone, 10));
+    let drop = world
+        .create_entity()
+        .with(Pos(Vec3::new(1.0, 0.0, 0.0)))
+        .with(ItemDrop::new(Item::new(ItemKind::Stone, 5)))
+        .build();
+
+    // Freshly dropped items are left alone for a moment
+    run(&mut world, Pickup, Duration::from_millis(500));
+    assert!(world.is_alive(drop));
+    world.read_resource::<Outbox>().drain();
+
+    run(&mut world, Pickup, Duration::from_secs(2));
+    assert!(!world.is_alive(drop));
+    let inv = world.read_storage::<Inventory>().get(character).unwrap().clone();
+    assert_eq!(inv.get(0), Some(Item::new(ItemKind::Stone, 15)));
+    assert_eq!(inv.get(1), None);
+
+    // The new inventory goes to its owner only
+    let msgs = world.read_resource::<Outbox>().drain();
+    assert!(msgs.iter().any(|(target, msg)| match msg {
+        ServerMsg::CompUpdate { forced, .. } => *target == Target::Client(character) && *forced,
+        _ => false,
+    }));
+}
+
+#[test]
+fn items_out_of_reach_stay_put() {
+    let mut world = world();
+    let character = world.create_character("zesterer".to_string()).build();
+    let drop = world
+        .create_entity()
+        .with(Pos(Vec3::new(5.0, 0.0, 0.0)))
+        .with(ItemDrop::new(Item::new(ItemKind::Apple, 1)))
+        .build();
+
+    run(&mut world, Pickup, Duration::from_secs(2));
+    assert!(world.is_alive(drop));
+    assert_eq!(world.read_storage::<Inventory>().get(character).unwrap().get(0), None);
+}
+
+#[test]
+fn the_closest_entity_picks_items_up() {
+    let mut world = world();
+    // The further character joins first, so it would win if entities were simply taken in order
+    let far = world.create_character("zesterer".to_string()).build();
+    let near = world.create_character("forest".to_string()).build();
+    world.write_storage::<Pos>().insert(far, Pos(Vec3::new(1.0, 0.0, 0.0))).unwrap();
+    world.write_storage::<Pos>().insert(near, Pos(Vec3::new(0.0, 0.5, 0.0))).unwrap();
+    world
+        .create_entity()
+        .with(Pos(Vec3::zero()))
+        .with(ItemDrop::new(Item::new(ItemKind::Apple, 1)))
+        .build();
+
+    run(&mut world, Pickup, Duration::from_secs(2));
+    let inventories = world.read_storage::<Inventory>();
+    assert_eq!(inventories.get(near).unwrap().get(0), Some(Item::new(ItemKind::Apple, 1)));
+    assert_eq!(inventories.get(far).unwrap().get(0), None);
+}
+
+#[test]
+fn unclaimed_items_despawn() {
+    let mut world = world();
+    world.write_resource::<TickConfig>().item_despawn = Duration::from_secs(10);
+    let drop = world
+        .create_entity()
+        .with(Pos(Vec3::zero()))
+        .with(ItemDrop::new(Item::new(ItemKind::Wood, 3)))
+        .marked::<UidMarker>()
+        .build();
+    let uid = world.read_storage::<UidMarker>().get(drop).unwrap().id();
+
+    run(&mut world, Pickup, Duration::from_secs(9));
+    assert!(world.is_alive(drop));
+    assert!(world.read_resource::<Outbox>().drain().is_empty());
+
+    run(&mut world, Pickup, Duration::from_secs(1));
+    assert!(!world.is_alive(drop));
+    assert!(world.read_resource::<Outbox>().drain().iter().any(|(target, msg)| match msg {
+        ServerMsg::EntityDeleted { uid: deleted } => *target == Target::All && *deleted == uid,
+        _ => false,
+    }));
+}
+
+#[test]
+fn nearby_drops_of_a_kind_merge() {
+    let mut world = world();
+    let mut drop_at = |pos, item| {
+        world
+            .create_entity()
+            .with(Pos(pos))
+            .with(ItemDrop::new(item))
+            .marked::<UidMarker>()
+            .build()
+    };
+    let first = drop_at(Vec3::zero(), Item::new(ItemKind::Stone, 5));
+    let second = drop_at(Vec3::new(1.0, 0.0, 0.0), Item::new(ItemKind::Stone, 7));
+    let other_kind = drop_at(Vec3::new(0.0, 1.0, 0.0), Item::new(ItemKind::Wood, 2));
+    let far_away = drop_at(Vec3::new(10.0, 0.0, 0.0), Item::new(ItemKind::Stone, 1));
+    let uid = world.read_storage::<UidMarker>().get(second).unwrap().id();
+
+    run(&mut world, ItemMerge::default(), Duration::from_millis(20));
+    assert!(!world.is_alive(second));
+    assert!(world.is_alive(other_kind) && world.is_alive(far_away));
+    assert_eq!(
+        world.read_storage::<ItemDrop>().get(first).unwrap().item,
+        Item::new(ItemKind::Stone, 12)
+    );
+    let msgs = world.read_resource::<Outbox>().drain();
+    assert_eq!(msgs.len(), 1);
+    assert_eq!(msgs[0].0, Target::All);
+    match msgs[0].1 {
+        ServerMsg::EntityDeleted { uid: deleted } => assert_eq!(deleted, uid),
+        ref msg => panic!("Expected the merged drop to be deleted, got {:?}", msg),
+    }
+}
+
+fn projectile(world: &mut World, owner: Entity, pos: Vec3<f32>, vel: Vec3<f32>) -> Entity {
+    world
+        .create_entity()
+        .with(Pos(pos))
+        .with(Vel(vel))
+        .with(Projectile::new(owner, ProjectileSpec::default()))
+        .marked::<UidMarker>()
+        .build()
+}
+
+#[test]
+fn projectiles_fall_until_they_expire() {
+    let mut world = world();
+    let owner = world.create_entity().build();
+    let arrow = projectile(&mut world, owner, Vec3::new(0.0, 0.0, 100.0), Vec3::new(10.0, 0.0, 0.0));
+
+    for _ in 0..10 {
+        run(&mut world, ProjectileSys, Duration::from_millis(50));
+    }
+    let pos = world.read_storage::<Pos>().get(arrow).unwrap().0;
+    assert!((pos.x - 5.0).abs() < 0.001);
+    assert!(pos.z < 100.0);
+    assert!(world.read_storage::<Vel>().get(arrow).unwrap().0.z < 0.0);
+
+    run(&mut world, ProjectileSys, Duration::from_secs(3));
+    assert!(!world.is_alive(arrow));
+}
+
+#[test]
+fn projectiles_damage_what_they_hit() {
+    let mut world = world();
+    let owner = world.create_entity().build();
+    let target = world.create_character("zesterer".to_string()).build();
+    world.write_storage::<Pos>().insert(target, Pos(Vec3::new(5.0, 0.0, 0.0))).unwrap();
+    let arrow = projectile(&mut world, owner, Vec3::new(0.0, 0.0, 1.0), Vec3::new(50.0, 0.0, 0.0));
+
+    run(&mut world, ProjectileSys, Duration::from_millis(100));
+    assert!(!world.is_alive(arrow));
+    assert_eq!(world.read_storage::<Health>().get(target).unwrap().0, 90);
+
+    let msgs = world.read_resource::<Outbox>().drain();
+    assert!(msgs.iter().any(|(_, msg)| match msg {
+        ServerMsg::CompUpdate {
+            store: CompStore::Health(90),
+            ..
+        } => true,
+        _ => false,
+    }));
+    assert!(msgs.iter().any(|(_, msg)| match msg {
+        ServerMsg::EntityDeleted { .. } => true,
+        _ => false,
+    }));
+}
+
+#[test]
+fn the_dead_are_told_who_killed_them_and_cant_be_hit_again() {
+    let mut world = world();
+    let owner = world.create_character("zesterer".to_string()).build();
+    let target = world.create_character("forest".to_string()).with(Health(5)).build();
+    world.write_storage::<Pos>().insert(target, Pos(Vec3::new(5.0, 0.0, 0.0))).unwrap();
+    projectile(&mut world, owner, Vec3::new(0.0, 0.0, 1.0), Vec3::new(50.0, 0.0, 0.0));
+
+    run(&mut world, ProjectileSys, Duration::from_millis(100));
+    assert_eq!(world.read_storage::<Dead>().get(target).unwrap().cause, "You were shot by zesterer");
+    assert!(world.read_resource::<Outbox>().drain().iter().any(|(to, msg)| match msg {
+        ServerMsg::Died { cause } => *to == Target::Client(target) && cause.contains("zesterer"),
+        _ => false,
+    }));
+
+    // Shots fly straight through the body
+    let arrow = projectile(&mut world, owner, Vec3::new(0.0, 0.0, 1.0), Vec3::new(50.0, 0.0, 0.0));
+    run(&mut world, ProjectileSys, Duration::from_millis(100));
+    assert!(world.is_alive(arrow));
+    assert_eq!(world.read_storage::<Health>().get(target).unwrap().0, 0);
+}
+
+#[test]
+fn projectiles_spare_their_owner_at_first() {
+    let mut world = world();
+    let owner = world.create_character("zesterer".to_string()).build();
+    let arrow = projectile(&mut world, owner, Vec3::new(0.0, 0.0, 1.0), Vec3::new(20.0, 0.0, 0.0));
+
+    run(&mut world, ProjectileSys, Duration::from_millis(20));
+    assert!(world.is_alive(arrow));
+    assert_eq!(world.read_storage::<Health>().get(owner).unwrap().0, 100);
+}
+
+#[test]
+fn projectiles_stop_at_terrain() {
+    let mut world = world();
+    world.write_resource::<LoadedChunks>().0.insert(
+        Vec3::new(1, 0, 0),
+        Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::STONE)),
+    );
+    let owner = world.create_entity().build();
+    let start = Vec3::new(CHUNK_SIZE.x as f32 - 2.0, 10.0, 10.0);
+    let arrow = projectile(&mut world, owner, start, Vec3::new(40.0, 0.0, 0.0));
+
+    run(&mut world, ProjectileSys, Duration::from_millis(50));
+    assert!(!world.is_alive(arrow));
+}
+
+// A character running along y, a block each 50ms tick, with each tick's position recorded. It ends up at y = 10.
+fn moving_target(world: &mut World) -> Entity {
+    let target = world.create_character("forest".to_string()).build();
+    for y in 0..=10 {
+        world.write_storage::<Pos>().insert(target, Pos(Vec3::new(5.0, y as f32, 0.0))).unwrap();
+        run(world, PosHistorySys, Duration::from_millis(50));
+    }
+    target
+}
+
+#[test]
+fn recent_positions_are_remembered() {
+    let mut world = world();
+    let target = moving_target(&mut world);
+    let still = world.create_entity().with(Pos(Vec3::new(1.0, 2.0, 3.0))).build();
+    let nowhere = world.create_entity().build();
+    run(&mut world, PosHistorySys, Duration::from_millis(50));
+
+    {
+        let history = world.read_resource::<PosHistory>();
+        let at = |entity, ago| history.pos_at(entity, Duration::from_millis(ago));
+        assert_eq!(at(target, 0), Some(Vec3::new(5.0, 10.0, 0.0)));
+        assert_eq!(at(target, 250), Some(Vec3::new(5.0, 6.0, 0.0)));
+        // Between ticks, the position is somewhere in between
+        assert!(at(target, 275).unwrap().distance(Vec3::new(5.0, 5.5, 0.0)) < 0.001);
+        // Only so far back is remembered
+        assert_eq!(at(target, 10_000), Some(Vec3::new(5.0, 1.0, 0.0)));
+        assert_eq!(at(still, 10_000), Some(Vec3::new(1.0, 2.0, 3.0)));
+        assert_eq!(at(nowhere, 0), None);
+
+        // All at once, for everything that had a position
+        let seen = history.entities_at(Duration::from_millis(250));
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen.get(&target), Some(&Vec3::new(5.0, 6.0, 0.0)));
+        assert_eq!(seen.get(&still), Some(&Vec3::new(1.0, 2.0, 3.0)));
+    }
+
+    // Entities that lose their position are forgotten
+    world.write_storage::<Pos>().remove(still);
+    run(&mut world, PosHistorySys, Duration::from_millis(50));
+    assert_eq!(world.read_resource::<PosHistory>().pos_at(still, Duration::default()), None);
+}
+
+#[test]
+fn projectiles_hit_targets_where_their_owner_saw_them() {
+    let mut world = world();
+    let owner = world.create_entity().build();
+    let target = moving_target(&mut world);
+
+    // Fired at where the target was 200ms ago, by someone who saw it there because of their latency
+    let aim = Vec3::new(0.0, 6.0, 1.0);
+    let arrow = projectile(&mut world, owner, aim, Vec3::new(50.0, 0.0, 0.0));
+    world.write_storage::<Projectile>().get_mut(arrow).unwrap().rewind = Duration::from_millis(200);
+    run(&mut world, ProjectileSys, Duration::from_millis(100));
+    assert!(!world.is_alive(arrow));
+    assert_eq!(world.read_storage::<Health>().get(target).unwrap().0, 90);
+    let hits = world
+        .read_resource::<Outbox>()
+        .drain()
+        .into_iter()
+        .filter_map(|(_, msg)| match msg {
+            ServerMsg::SoundEvent { pos, .. } => Some(pos),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // It hit the front of the target as it was then, just short of x = 4.55 because the projectile has a size
+    assert_eq!(hits.len(), 1);
+    assert!((hits[0].y - aim.y).abs() < 0.001 && (hits[0].x - 4.45).abs() < 0.01);
+
+    // Without allowing for latency, the target has moved on by then
+    let arrow = projectile(&mut world, owner, aim, Vec3::new(50.0, 0.0, 0.0));
+    run(&mut world, ProjectileSys, Duration::from_millis(100));
+    assert!(world.is_alive(arrow));
+    assert_eq!(world.read_storage::<Health>().get(target).unwrap().0, 90);
+}
+
+// Waits a little while for the other probe to start, recording whether it did
+struct Probe {
+    started: Arc<AtomicBool>,
+    other: Arc<AtomicBool>,
+    saw_other: Arc<AtomicBool>,
+}
+
+impl<'a> System<'a> for Probe {
+    type SystemData = ();
+
+    fn run(&mut self, _: ()) {
+        self.started.store(true, Ordering::SeqCst);
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(2) {
+            if self.other.load(Ordering::SeqCst) {
+                self.saw_other.store(true, Ordering::SeqCst);
+                return;
+            }
+            thread::yield_now();
+        }
+    }
+}
+
+#[test]
+fn independent_systems_run_in_parallel() {
+    let (a, b) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+    let (a_saw_b, b_saw_a) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+
+    let world = world();
+    // Use two threads whatever the machine, so that this doesn't depend on how many cores there are
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    let mut dispatcher = DispatcherBuilder::new()
+        .with_pool(Arc::new(pool))
+        .with(
+            Probe {
+                started: a.clone(),
+                other: b.clone(),
+                saw_other: a_saw_b.clone(),
+            },
+            "a",
+            &[],
+        )
+        .with(
+            Probe {
+                started: b.clone(),
+                other: a.clone(),
+                saw_other: b_saw_a.clone(),
+            },
+            "b",
+            &[],
+        )
+        .build();
+    dispatcher.dispatch(&world.res);
+
+    // Each probe can only see the other start if they're running at the same time
+    assert!(a_saw_b.load(Ordering::SeqCst) && b_saw_a.load(Ordering::SeqCst));
+}
+
+#[test]
+fn tick_dispatches_every_system() {
+    let mut world = world();
+    let npc = world
+        .create_entity()
+        .with(Pos(Vec3::zero()))
+        .with(Vel(Vec3::zero()))
+        .with(Wander::new(2.0, 7))
+        .build();
+
+    world.write_resource::<DeltaTime>().0 = Duration::from_secs(1);
+    dispatcher().dispatch(&world.res);
+    world.maintain();
+
+    // The wanderer picked a direction and then moved along it in the same tick
+    assert!((world.read_storage::<Pos>().get(npc).unwrap().0.magnitude() - 2.0).abs() < 0.001);
+    assert_eq!(world.read_resource::<TimeOfDay>().time, Duration::from_secs(1));
+}
+
+// A stone floor at z = 0 for x and y from 0 to 15, with `solid` blocks on top. Beyond that is solid stone, except from
+// x = `loaded_to` on, which isn't loaded.
+fn terrain(solid: &[Vec3<VoxAbs>], loaded_to: VoxAbs) -> impl Fn(Vec3<VoxAbs>) -> Result<Block, Vec3<VolOffs>> {
+    let solid = solid.iter().cloned().collect::<HashSet<_>>();
+    move |vox| {
+        if vox.x >= loaded_to {
+            Err(voxabs_to_voloffs(vox, CHUNK_SIZE))
+        } else if vox.x < 0 || vox.x > 15 || vox.y < 0 || vox.y > 15 || vox.z <= 0 || solid.contains(&vox) {
+            Ok(Block::STONE)
+        } else {
+            Ok(Block::AIR)
+        }
+    }
+}
+
+// A wall of solid blocks `height` high along x = `x`, from y = `from_y` to `to_y`
+fn wall(x: VoxAbs, from_y: VoxAbs, to_y: VoxAbs, height: VoxAbs) -> Vec<Vec3<VoxAbs>> {
+    let mut blocks = vec![];
+    for y in from_y..=to_y {
+        for z in 1..=height {
+            blocks.push(Vec3::new(x, y, z));
+        }
+    }
+    blocks
+}
+
+fn found(result: PathResult) -> Vec<Vec3<VoxAbs>> {
+    match result {
+        PathResult::Found(path) => path,
+        result => panic!("expected a path, got {:?}", result),
+    }
+}
+
+#[test]
+fn paths_go_around_walls_too_high_to_climb() {
+    let block_at = terrain(&wall(8, 0, 11, 2), 100);
+    let start = Vec3::new(2, 2, 1);
+    let path = found(find_path(start, Vec3::new(13, 2, 1), &PathConfig::default(), &block_at));
+
+    assert_eq!(path.last(), Some(&Vec3::new(13, 2, 1)));
+    let mut prev = start;
+    for vox in path {
+        assert!(!block_at(vox).unwrap().is_solid() && block_at(vox - Vec3::unit_z()).unwrap().is_solid());
+        assert_eq!((vox - prev).map(|e| e.abs()).sum(), 1);
+        // The wall can only be passed at its end
+        assert!(vox.x != 8 || vox.y > 11);
+        prev = vox;
+    }
+}
+
+#[test]
+fn paths_climb_stairs_a_step_at_a_time() {
+    let mut blocks = wall(4, 0, 15, 1);
+    blocks.extend(wall(5, 0, 15, 2));
+    for x in 6..=15 {
+        blocks.extend(wall(x, 0, 15, 3));
+    }
+    let block_at = terrain(&blocks, 100);
+    let start = Vec3::new(1, 5, 1);
+    let path = found(find_path(start, Vec3::new(10, 5, 4), &PathConfig::default(), &block_at));
+
+    assert_eq!(path.last(), Some(&Vec3::new(10, 5, 4)));
+    let mut prev = start;
+    for vox in path {
+        assert!((vox.z - prev.z).abs() <= 1);
+        prev = vox;
+    }
+}
+
+#[test]
+fn gaps_are_jumped_if_theyre_narrow_enough() {
+    // Two raised platforms with a gap two blocks deep between them
+    let mut blocks = vec![];
+    for x in (0..=15).filter(|x| *x != 7) {
+        blocks.extend(wall(x, 0, 15, 2));
+    }
+    let block_at = terrain(&blocks, 100);
+    let (start, goal) = (Vec3::new(2, 2, 3), Vec3::new(12, 2, 3));
+
+    let path = found(find_path(start, goal, &PathConfig::default(), &block_at));
+    assert!(path.contains(&Vec3::new(8, 2, 3)) && !path.iter().any(|vox| vox.x == 7));
+
+    let config = PathConfig {
+        jump_distance: 0,
+        ..PathConfig::default()
+    };
+    assert_eq!(find_path(start, goal, &config, &block_at), PathResult::NotFound);
+}
+
+#[test]
+fn unreachable_goals_fail_without_searching_forever() {
+    // The goal is walled in too high to climb over
+    let mut blocks = vec![];
+    for x in 9..=11 {
+        blocks.extend(wall(x, 9, 11, 2));
+    }
+    blocks.retain(|vox| vox.x != 10 || vox.y != 10);
+    let block_at = terrain(&blocks, 100);
+
+    let start = Instant::now();
+    let config = PathConfig {
+        max_nodes: usize::max_value(),
+        ..PathConfig::default()
+    };
+    assert_eq!(
+        find_path(Vec3::new(2, 2, 1), Vec3::new(10, 10, 1), &config, &block_at),
+        PathResult::NotFound
+    );
+    assert!(start.elapsed() < Duration::from_secs(1));
+    // Nothing can stand inside a wall
+    assert_eq!(
+        find_path(Vec3::new(2, 2, 1), Vec3::new(9, 9, 1), &config, &block_at),
+        PathResult::NotFound
+    );
+}
+
+#[test]
+fn unloaded_terrain_gives_a_partial_path() {
+    let block_at = terrain(&[], 10);
+    match find_path(Vec3::new(2, 2, 1), Vec3::new(14, 2, 1), &PathConfig::default(), &block_at) {
+        PathResult::Partial(path) => assert_eq!(path.last(), Some(&Vec3::new(9, 2, 1))),
+        result => panic!("expected a partial path, got {:?}", result),
+    }
+    // From the end of that path there's nowhere closer to go until more is loaded
+    assert_eq!(
+        find_path(Vec3::new(9, 2, 1), Vec3::new(14, 2, 1), &PathConfig::default(), &block_at),
+        PathResult::Unloaded
+    );
+}
+
+// Load a chunk of open air over a chunk of stone
+fn flat_ground(world: &mut World) {
+    let mut chunks = world.write_resource::<LoadedChunks>();
+    chunks.0.insert(Vec3::new(0, 0, -1), Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::STONE)));
+    chunks.0.insert(Vec3::new(0, 0, 0), Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)));
+}
+
+#[test]
+fn columns_read_the_same_as_each_block() {
+    let mut world = world();
+    flat_ground(&mut world);
+    world.write_resource::<LoadedChunks>().set_block(Vec3::new(3, 4, 5), Block::GRASS).unwrap();
+
+    let chunks = world.read_resource::<LoadedChunks>();
+    let column = chunks.column(Vec2::new(3, 4), -40, 40);
+    assert_eq!(column.len(), 80);
+    for (z, block) in (-40..40).zip(column) {
+        assert_eq!(block, chunks.block_at(Vec3::new(3, 4, z)).ok(), "at z = {}", z);
+    }
+    assert!(chunks.column(Vec2::new(3, 4), 10, 10).is_empty());
+}
+
+#[test]
+fn entities_walk_to_where_they_asked_to_go() {
+    let mut world = world();
+    flat_ground(&mut world);
+    let npc = world
+        .create_entity()
+        .with(Pos(Vec3::new(0.5, 0.5, 0.0)))
+        .with(Vel(Vec3::zero()))
+        .with(PathRequest {
+            goal: Vec3::new(5, 3, 0),
+            speed: 4.0,
+        })
+        .build();
+
+    run(&mut world, Pathfind::default(), Duration::from_millis(20));
+    assert!(world.read_storage::<PathRequest>().get(npc).is_none());
+    assert_eq!(
+        world.read_storage::<Path>().get(npc).unwrap().waypoints.last(),
+        Some(&Vec3::new(5, 3, 0))
+    );
+
+    for _ in 0..200 {
+        run(&mut world, FollowPath, Duration::from_millis(20));
+        run(&mut world, Movement, Duration::from_millis(20));
+    }
+    assert!(world.read_storage::<Path>().get(npc).is_none());
+    assert!(world.read_storage::<Pos>().get(npc).unwrap().0.distance(Vec3::new(5.5, 3.5, 0.0)) < 0.2);
+}
+
+#[test]
+fn entities_face_the_way_they_follow_their_path() {
+    let mut world = world();
+    flat_ground(&mut world);
+    let npc = world
+        .create_entity()
+        .with(Pos(Vec3::new(0.5, 0.5, 0.0)))
+        .with(Vel(Vec3::zero()))
+        .with(Dir(Vec2::new(0.0, 0.3)))
+        .with(PathRequest {
+            goal: Vec3::new(5, 0, 0),
+            speed: 4.0,
+        })
+        .build();
+
+    run(&mut world, Pathfind::default(), Duration::from_millis(20));
+    run(&mut world, FollowPath, Duration::from_millis(20));
+    // Heading along x is a quarter turn of yaw, and the lean is left alone
+    let dir = world.read_storage::<Dir>().get(npc).unwrap().0;
+    assert!((dir.x - std::f32::consts::FRAC_PI_2).abs() < 0.001 && dir.y == 0.3, "faced {}", dir);
+}
+
+#[test]
+fn entities_waiting_on_terrain_find_their_way_once_its_loaded() {
+    let mut world = world();
+    flat_ground(&mut world);
+    // At the edge of the loaded terrain, with the goal beyond it
+    let npc = world
+        .create_entity()
+        .with(Pos(Vec3::new(31.5, 2.5, 0.0)))
+        .with(Vel(Vec3::zero()))
+        .with(PathRequest {
+            goal: Vec3::new(40, 2, 0),
+            speed: 4.0,
+        })
+        .build();
+
+    run(&mut world, Pathfind::default(), Duration::from_millis(20));
+    assert!(world.read_storage::<PathRequest>().get(npc).is_some());
+    assert!(world.read_storage::<Path>().get(npc).is_none());
+
+    {
+        let mut chunks = world.write_resource::<LoadedChunks>();
+        chunks.0.insert(Vec3::new(1, 0, -1), Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::STONE)));
+        chunks.0.insert(Vec3::new(1, 0, 0), Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)));
+    }
+    run(&mut world, Pathfind::default(), Duration::from_millis(20));
+    assert!(world.read_storage::<PathRequest>().get(npc).is_none());
+    assert_eq!(
+        world.read_storage::<Path>().get(npc).unwrap().waypoints.last(),
+        Some(&Vec3::new(40, 2, 0))
+    );
+}
+
+#[test]
+fn waiting_requests_dont_keep_others_from_a_turn() {
+    let mut world = world();
+    flat_ground(&mut world);
+    let config = PathConfig {
+        paths_per_tick: 1,
+        ..PathConfig::default()
+    };
+    world.add_resource(config);
+    let request = |goal| PathRequest { goal, speed: 4.0 };
+    let waiting = world
+        .create_entity()
+        .with(Pos(Vec3::new(31.5, 2.5, 0.0)))
+        .with(request(Vec3::new(40, 2, 0)))
+        .build();
+    let walker = world
+        .create_entity()
+        .with(Pos(Vec3::new(0.5, 0.5, 0.0)))
+        .with(request(Vec3::new(5, 3, 0)))
+        .build();
+
+    let mut pathfind = Pathfind::default();
+    pathfind.run_now(&world.res);
+    world.maintain();
+    pathfind.run_now(&world.res);
+    world.maintain();
+    assert!(world.read_storage::<PathRequest>().get(waiting).is_some());
+    assert!(world.read_storage::<Path>().get(walker).is_some());
+}
+
+fn hostile(world: &mut World, pos: Vec3<f32>) -> Entity {
+    world
+        .create_entity()
+        .with(Pos(pos))
+        .with(Vel(Vec3::zero()))
+        .with(Hostile::new(10.0, 1.5, 10, 4.0))
+        .build()
+}
+
+fn player(world: &mut World, pos: Vec3<f32>, mode: PlayMode) -> Entity {
+    world
+        .create_entity()
+        .with(Pos(pos))
+        .with(Health(100))
+        .with(Player {
+            alias: "prey".to_string(),
+            mode,
+            session: 0,
+        })
+        .build()
+}
+
+// Run a tick of everything hostiles need to get about
+fn hostile_tick(world: &mut World) {
+    let dt = Duration::from_millis(20);
+    run(world, HostileSys, dt);
+    run(world, Pathfind::default(), dt);
+    run(world, FollowPath, dt);
+    run(world, Movement, dt);
+}
+
+fn target_of(world: &World, hostile: Entity) -> Option<Entity> {
+    world.read_storage::<Hostile>().get(hostile).unwrap().target()
+}
+
+#[test]
+fn hostiles_chase_players_and_attack_on_a_steady_beat() {
+    let mut world = world();
+    flat_ground(&mut world);
+    let wolf = hostile(&mut world, Vec3::new(2.5, 2.5, 0.0));
+    let prey = player(&mut world, Vec3::new(9.5, 2.5, 0.0), PlayMode::Character);
+
+    let mut hits = vec![];
+    let mut health = 100;
+    for tick in 0..300 {
+        hostile_tick(&mut world);
+        let now = world.read_storage::<Health>().get(prey).unwrap().0;
+        if now < health {
+            hits.push(tick);
+            health = now;
+        }
+    }
+
+    // It had to walk over before the first hit, then hit once a second
+    assert_eq!(target_of(&world, wolf), Some(prey));
+    assert!(hits.len() >= 3 && hits[0] > 25, "hit on ticks {:?}", hits);
+    assert!(hits.windows(2).all(|pair| pair[1] - pair[0] == 50), "hit on ticks {:?}", hits);
+    assert_eq!(health, 100 - 10 * hits.len() as u32);
+    let wolf_pos = world.read_storage::<Pos>().get(wolf).unwrap().0;
+    assert!(wolf_pos.distance(Vec3::new(9.5, 2.5, 0.0)) <= 1.5);
+}
+
+#[test]
+fn hostiles_go_home_once_theyve_lost_sight_of_their_target() {
+    let mut world = world();
+    flat_ground(&mut world);
+    let wolf = hostile(&mut world, Vec3::new(2.5, 2.5, 0.0));
+    let prey = player(&mut world, Vec3::new(6.5, 2.5, 0.0), PlayMode::Character);
+    hostile_tick(&mut world);
+    assert_eq!(target_of(&world, wolf), Some(prey));
+
+    // The player runs out of sight, which takes three seconds to give up on
+    world.write_storage::<Pos>().get_mut(prey).unwrap().0 = Vec3::new(28.5, 28.5, 0.0);
+    for _ in 0..149 {
+        hostile_tick(&mut world);
+    }
+    assert_eq!(target_of(&world, wolf), Some(prey));
+    hostile_tick(&mut world);
+    assert_eq!(target_of(&world, wolf), None);
+
+    for _ in 0..300 {
+        hostile_tick(&mut world);
+    }
+    let wolf_pos = world.read_storage::<Pos>().get(wolf).unwrap().0;
+    assert!(wolf_pos.distance(Vec3::new(2.5, 2.5, 0.0)) < 0.3);
+}
+
+#[test]
+fn hostiles_ignore_headless_players_and_those_behind_walls() {
+    let mut world = world();
+    flat_ground(&mut world);
+    for y in 0..6 {
+        for z in 0..3 {
+            let _ = world.write_resource::<LoadedChunks>().set_block(Vec3::new(5, y, z), Block::STONE);
+        }
+    }
+    let wolf = hostile(&mut world, Vec3::new(2.5, 2.5, 0.0));
+    let hidden = player(&mut world, Vec3::new(8.5, 2.5, 0.0), PlayMode::Character);
+    player(&mut world, Vec3::new(3.5, 4.5, 0.0), PlayMode::Headless);
+
+    for _ in 0..10 {
+        hostile_tick(&mut world);
+    }
+    assert_eq!(target_of(&world, wolf), None);
+
+    world.write_storage::<Pos>().get_mut(hidden).unwrap().0 = Vec3::new(4.5, 2.5, 0.0);
+    hostile_tick(&mut world);
+    assert_eq!(target_of(&world, wolf), Some(hidden));
+}
@@ -0,0 +1,29 @@
+// Library
+use specs::{ReadExpect, System, WriteExpect};
+
+// Project
+use common::util::msg::ServerMsg;
+
+// Local
+use super::{DeltaTime, Outbox, Target, TickConfig, TimeOfDay};
+
+/// Advances the time of day, and regularly tells clients what it is
+pub struct TimeOfDaySys;
+
+impl<'a> System<'a> for TimeOfDaySys {
+    type SystemData = (
+        ReadExpect<'a, DeltaTime>,
+        WriteExpect<'a, TimeOfDay>,
+        ReadExpect<'a, TickConfig>,
+        ReadExpect<'a, Outbox>,
+    );
+
+    fn run(&mut self, (dt, mut tod, config, outbox): Self::SystemData) {
+        tod.time += dt.0;
+        tod.since_sync += dt.0;
+        if tod.since_sync >= config.time_sync_freq {
+            outbox.send(Target::All, ServerMsg::TimeUpdate(tod.time));
+            tod.since_sync = Default::default();
+        }
+    }
+}
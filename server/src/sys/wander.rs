@@ -0,0 +1,85 @@
+// Standard
+use std::f32::consts::PI;
+
+// Library
+use specs::{Component, Join, ReadExpect, ReadStorage, System, VecStorage, WriteStorage};
+use vek::*;
+
+// Project
+use common::ecs::phys::{Dir, Vel};
+
+// Local
+use super::{DeltaTime, Hostile, Path};
+
+// Constants
+// How long a wandering entity keeps going in one direction, in seconds
+const WANDER_INTERVAL: f32 = 4.0;
+
+/// Makes an entity walk in a random direction, changing direction every few seconds
+#[derive(Clone, Debug)]
+pub struct Wander {
+    pub speed: f32,
+    timer: f32,
+    rng: u32,
+}
+
+impl Wander {
+    /// `seed` decides the directions the entity picks, so entities given different seeds go different ways
+    pub fn new(speed: f32, seed: u32) -> Self {
+        Self {
+            speed,
+            timer: 0.0,
+            rng: seed.max(1),
+        }
+    }
+
+    // xorshift32, which is plenty random enough to pick a direction
+    fn next_angle(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f32 / u32::max_value() as f32 * PI * 2.0
+    }
+}
+
+impl Component for Wander {
+    type Storage = VecStorage<Self>;
+}
+
+/// Picks new directions for wandering entities, unless they have somewhere to be or someone to fight
+pub struct WanderSys;
+
+impl<'a> System<'a> for WanderSys {
+    type SystemData = (
+        ReadExpect<'a, DeltaTime>,
+        WriteStorage<'a, Wander>,
+        WriteStorage<'a, Vel>,
+        WriteStorage<'a, Dir>,
+        ReadStorage<'a, Path>,
+        ReadStorage<'a, Hostile>,
+    );
+
+    fn run(&mut self, (dt, mut wanders, mut velocities, mut dirs, paths, hostiles): Self::SystemData) {
+        let dt = dt.0.as_float_secs() as f32;
+        for (wander, vel, dir, _, hostile) in
+            (&mut wanders, &mut velocities, (&mut dirs).maybe(), !&paths, hostiles.maybe()).join()
+        {
+            if hostile.and_then(|hostile| hostile.target()).is_some() {
+                continue;
+            }
+            wander.timer -= dt;
+            if wander.timer > 0.0 {
+                continue;
+            }
+            wander.timer = WANDER_INTERVAL;
+
+            let angle = wander.next_angle();
+            let heading = Vec2::new(angle.cos(), angle.sin());
+            vel.0 = Vec3::new(heading.x * wander.speed, heading.y * wander.speed, vel.0.z);
+            // Face the way they're walking, leaning no differently than before
+            if let Some(dir) = dir {
+                dir.0.x = heading.x.atan2(heading.y);
+            }
+        }
+    }
+}
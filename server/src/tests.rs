@@ -0,0 +1,1621 @@
+// Standard
+use std::{
+    f32::consts::{FRAC_PI_2, PI},
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Project
+use common::{
+    audio::SoundId,
+    ecs::{
+        craft::CraftError,
+        inventory::{HeldItem, Inventory, InventoryAction, Item, ItemKind, HOTBAR_SLOTS},
+        net::UidMarker,
+        phys::{CollisionBox, Dir, MoveMode, Pos},
+    },
+    net::Encryption,
+    terrain::{
+        chunk::{Block, Chunk, HomogeneousData},
+        voloffs_to_voxabs, Barrier, ConstructVolume, VolCluster, WorldBorder, WriteVolume,
+    },
+    util::{
+        cmd::{ArgKind, ArgSpec},
+        msg::{
+            ClientMsg, ClientPostOffice, CompStore, PlayMode, ServerInfo, ServerMsg, SessionKind, MAX_CHUNK_REQUEST,
+        },
+        post::Incoming,
+    },
+    weather::{weather_region, WEATHER_REGION_SIZE},
+};
+
+// Local
+use super::*;
+use crate::{
+    cmd::{Args, Cmd, CmdHandler},
+    craft::RecipeBook,
+    spawn::SpawnRule,
+    sys::{ItemDrop, Outbox, Target, TickConfig},
+};
+
+// Constants
+const TIMEOUT: Duration = Duration::from_secs(10);
+// Somewhere far from any terrain the server might be generating
+const FAR_AWAY: Vec3<f32> = Vec3 {
+    x: 10000.5,
+    y: 10000.5,
+    z: 5000.0,
+};
+
+// A border well beyond `FAR_AWAY`, so that tests can move about out there
+fn roomy_border() -> WorldBorder {
+    WorldBorder {
+        radius: 100_000.0,
+        ..WorldBorder::default()
+    }
+}
+
+struct TestPayloads;
+impl Payloads for TestPayloads {
+    type Chunk = ();
+    type Entity = ();
+    type Client = ();
+
+    fn world_border(&self) -> WorldBorder { roomy_border() }
+}
+
+struct MetricsPayloads;
+impl Payloads for MetricsPayloads {
+    type Chunk = ();
+    type Entity = ();
+    type Client = ();
+
+    fn metrics_addr(&self) -> Option<SocketAddr> { Some("127.0.0.1:0".parse().unwrap()) }
+}
+
+// Tries the `Api` out from a payload hook, the way a game built on the server would
+#[derive(Default)]
+struct ApiPayloads {
+    // Who each connecting player found online, and next to them
+    found: Arc<Mutex<Vec<(Vec<Entity>, Vec<Entity>)>>>,
+}
+impl Payloads for ApiPayloads {
+    type Chunk = ();
+    type Entity = ();
+    type Client = ();
+
+    fn world_border(&self) -> WorldBorder { roomy_border() }
+
+    fn on_player_connect(&self, api: &dyn Api, player: Entity) {
+        let pos = api.world().read_storage::<Pos>().get(player).unwrap().0;
+        self.found.lock().push((api.players(), api.entities_in_radius(pos, 1.0)));
+        api.set_block(far_away_block(), Block::STONE);
+
+        let alias = api.world().read_storage::<Player>().get(player).unwrap().alias.clone();
+        if alias == "unwelcome" {
+            api.disconnect(player, DisconnectReason::Kicked("Not welcome".into()));
+        }
+    }
+}
+
+// Keeps anyone from changing the blocks just east of `FAR_AWAY`, and puts gold in the corner of every chunk generated
+#[derive(Default)]
+struct ProtectedPayloads {
+    // Every change it was asked about: where, from what to what, and why
+    asked: Arc<Mutex<Vec<(Vec3<VoxAbs>, Block, Block, BlockChangeCause)>>>,
+}
+impl Payloads for ProtectedPayloads {
+    type Chunk = ();
+    type Entity = ();
+    type Client = ();
+
+    fn world_border(&self) -> WorldBorder { roomy_border() }
+
+    fn free_building(&self) -> bool { true }
+
+    fn on_chunk_generated(&self, _api: &dyn Api, _pos: Vec3<VolOffs>, chunk: &mut Chunk) {
+        let _ = chunk.set(Vec3::zero(), Block::GOLD);
+    }
+
+    fn on_block_change(
+        &self,
+        _api: &dyn Api,
+        pos: Vec3<VoxAbs>,
+        old: Block,
+        new: Block,
+        cause: BlockChangeCause,
+    ) -> bool {
+        self.asked.lock().push((pos, old, new, cause));
+        let off = pos - far_away_block();
+        !(off.x >= 2 && off.x <= 4 && off.y.abs() <= 2 && off.z.abs() <= 2)
+    }
+}
+
+// Spawns monsters near players, though never dragons
+struct SpawnPayloads;
+impl Payloads for SpawnPayloads {
+    type Chunk = ();
+    type Entity = ();
+    type Client = ();
+
+    fn world_border(&self) -> WorldBorder { roomy_border() }
+
+    fn on_entity_spawn_attempt(&self, _api: &dyn Api, _pos: Vec3<f32>, kind: &str) -> bool { kind != "dragon" }
+
+    fn spawn_rules(&self) -> SpawnRules {
+        SpawnRules {
+            rules: vec![
+                SpawnRule::new("goblin", 10),
+                SpawnRule::new("wolf", 1),
+                SpawnRule::new("dragon", 10),
+            ],
+            max_total: 5,
+            max_per_player: 3,
+            min_dist: 4.0,
+            max_dist: 12.0,
+            despawn_dist: 24.0,
+            despawn_after: Duration::from_secs(1),
+            attempts_per_tick: 4,
+            seed: 1234,
+        }
+    }
+}
+
+// Ticks a lot of blocks, so that grass spreads within a test's patience
+struct TickingPayloads;
+impl Payloads for TickingPayloads {
+    type Chunk = ();
+    type Entity = ();
+    type Client = ();
+
+    fn world_border(&self) -> WorldBorder { roomy_border() }
+
+    fn block_ticks(&self) -> BlockTicks { fast_ticks() }
+}
+
+fn fast_ticks() -> BlockTicks {
+    let mut ticks = BlockTicks::grass();
+    ticks.blocks_per_chunk = 4096;
+    ticks.seed = Some(99);
+    ticks
+}
+
+fn far_away_block() -> Vec3<VoxAbs> { FAR_AWAY.map(|e| e.floor() as VoxAbs) }
+
+fn server() -> (Manager<Wrapper<Server<TestPayloads>>>, SocketAddr) {
+    let server = Server::new(TestPayloads, "127.0.0.1:0").unwrap();
+    let addr = server.do_for(|srv| srv.local_addr()).unwrap();
+    (server, addr)
+}
+
+// A server set up by `config`, listening on any free local port
+fn configured_server(config: ServerConfig) -> (Manager<Wrapper<Server<TestPayloads>>>, SocketAddr) {
+    let config = ServerConfig {
+        address: "127.0.0.1".to_string(),
+        port: 0,
+        ..config
+    };
+    let server = Server::with_config(TestPayloads, config).unwrap();
+    let addr = server.do_for(|srv| srv.local_addr()).unwrap();
+    (server, addr)
+}
+
+// Log in, returning the connection, the player's uid and their session
+fn connect(addr: SocketAddr, alias: &str, session: Option<u64>) -> (Manager<ClientPostOffice>, Option<u64>, u64) {
+    let po = ClientPostOffice::to_server(addr, Encryption::Preferred).unwrap();
+    let pb = po.create_postbox(SessionKind::Connect);
+    pb.send(ClientMsg::Connect {
+        alias: alias.to_string(),
+        mode: PlayMode::Character,
+        session,
+        login_token: None,
+    })
+    .unwrap();
+
+    match pb.recv_timeout(TIMEOUT).unwrap() {
+        ServerMsg::Connected { player_uid, session, .. } => (po, player_uid, session),
+        msg => panic!("Unexpected reply: {:?}", msg),
+    }
+}
+
+fn wait_until<F: Fn() -> bool>(f: F) {
+    let start = Instant::now();
+    while !f() {
+        assert!(start.elapsed() < TIMEOUT, "Timed out");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn suspended(server: &Wrapper<Server<TestPayloads>>) -> usize { server.do_for(|srv| srv.suspended.len()) }
+
+#[test]
+fn clients_are_told_the_world_seed() {
+    let (_server, addr) = configured_server(ServerConfig {
+        world_seed: 42,
+        ..ServerConfig::default()
+    });
+
+    let po = ClientPostOffice::to_server(addr, Encryption::Preferred).unwrap();
+    let pb = po.create_postbox(SessionKind::Connect);
+    pb.send(ClientMsg::Connect {
+        alias: "seeker".to_string(),
+        mode: PlayMode::Character,
+        session: None,
+        login_token: None,
+    })
+    .unwrap();
+    match pb.recv_timeout(TIMEOUT).unwrap() {
+        ServerMsg::Connected { world_seed, .. } => assert_eq!(world_seed, 42),
+        msg => panic!("Unexpected reply: {:?}", msg),
+    }
+}
+
+#[test]
+fn dropped_players_resume_their_session() {
+    let (server, addr) = server();
+
+    let (po, uid, session) = connect(addr, "resumer", None);
+    assert!(uid.is_some());
+    po.stop();
+    wait_until(|| suspended(&server) == 1);
+
+    let (_po, resumed_uid, resumed_session) = connect(addr, "resumer", Some(session));
+    assert_eq!(resumed_uid, uid);
+    assert_eq!(resumed_session, session);
+    assert_eq!(suspended(&server), 0);
+}
+
+#[test]
+fn sessions_belong_to_their_player() {
+    let (server, addr) = server();
+
+    let (po, uid, session) = connect(addr, "owner", None);
+    po.stop();
+    wait_until(|| suspended(&server) == 1);
+
+    // Someone else can't take the player over, even with the right session
+    let (_other, other_uid, _) = connect(addr, "impostor", Some(session));
+    assert_ne!(other_uid, uid);
+    // ...and the right player with the wrong session starts afresh
+    let (_fresh, fresh_uid, _) = connect(addr, "owner", Some(session.wrapping_add(1)));
+    assert_ne!(fresh_uid, uid);
+    assert_eq!(suspended(&server), 1);
+}
+
+#[test]
+fn logging_out_ends_the_session() {
+    let (server, addr) = server();
+
+    let (po, uid, session) = connect(addr, "leaver", None);
+    let _ = po.create_postbox(SessionKind::Disconnect).send(ClientMsg::Disconnect {
+        reason: "Logging out".into(),
+    });
+    wait_until(|| server.do_for(|srv| srv.world.read_storage::<Player>().join().count() == 0));
+    assert_eq!(suspended(&server), 0);
+
+    let (_po, new_uid, _) = connect(addr, "leaver", Some(session));
+    assert_ne!(new_uid, uid);
+}
+
+fn player_named<P: Payloads>(server: &Wrapper<Server<P>>, alias: &str) -> Option<Entity> {
+    server.do_for(|srv| {
+        (&srv.world.entities(), &srv.world.read_storage::<Player>())
+            .join()
+            .find(|(_, p)| p.alias == alias)
+            .map(|(e, _)| e)
+    })
+}
+
+fn pos_of(server: &Wrapper<Server<TestPayloads>>, player: Entity) -> Vec3<f32> {
+    server.do_for(|srv| srv.world.read_storage::<Pos>().get(player).unwrap().0)
+}
+
+// Act like the player's client, reporting that it has moved to `pos`
+fn report_pos(server: &Wrapper<Server<TestPayloads>>, player: Entity, pos: Vec3<f32>) {
+    report_move(server, player, pos, false);
+}
+
+fn report_move(server: &Wrapper<Server<TestPayloads>>, player: Entity, pos: Vec3<f32>, jumping: bool) {
+    server.do_for_mut(|srv| srv.handle_player_update(player, pos, Vec3::zero(), Vec2::unit_y(), MoveMode::Walk, jumping));
+}
+
+// Connect a player whose client has loaded the chunks around them, and put them at `FAR_AWAY`
+fn connect_far_away<P: Payloads>(
+    server: &Wrapper<Server<P>>,
+    addr: SocketAddr,
+    alias: &str,
+) -> (Manager<ClientPostOffice>, Entity) {
+    let (po, _, _) = connect(addr, alias, None);
+    let player = player_named(server, alias).unwrap();
+    server.do_for_mut(|srv| {
+        srv.handle_player_ready(player);
+        srv.update_comp(player, Pos(FAR_AWAY))
+    });
+    (po, player)
+}
+
+#[test]
+fn players_are_held_in_place_until_loaded() {
+    let (server, addr) = server();
+    let (_po, _, _) = connect(addr, "newcomer", None);
+    let player = player_named(&server, "newcomer").unwrap();
+    server.do_for_mut(|srv| srv.update_comp(player, Pos(FAR_AWAY)));
+
+    // Falling while the terrain loads doesn't count
+    report_pos(&server, player, FAR_AWAY - Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(pos_of(&server, player), FAR_AWAY);
+
+    server.do_for_mut(|srv| srv.handle_player_ready(player));
+    report_pos(&server, player, FAR_AWAY - Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(pos_of(&server, player), FAR_AWAY - Vec3::new(0.0, 0.0, 1.0));
+
+    // Moving far means waiting for the terrain there, but moving a little doesn't
+    let dest = FAR_AWAY + Vec3::new(3000.0, 0.0, 0.0);
+    server.do_for_mut(|srv| srv.teleport_now(player, dest));
+    report_pos(&server, player, dest - Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(pos_of(&server, player), dest);
+
+    server.do_for_mut(|srv| {
+        srv.handle_player_ready(player);
+        srv.teleport_now(player, dest + Vec3::new(2.0, 0.0, 0.0))
+    });
+    report_pos(&server, player, dest + Vec3::new(2.0, 0.0, -1.0));
+    assert_eq!(pos_of(&server, player), dest + Vec3::new(2.0, 0.0, -1.0));
+}
+
+#[test]
+fn players_cant_move_impossibly_far() {
+    let (server, addr) = server();
+    let (_po, player) = connect_far_away(&server, addr, "speeder");
+
+    let step = FAR_AWAY + Vec3::new(1.0, 0.5, 0.0);
+    report_pos(&server, player, step);
+    assert_eq!(pos_of(&server, player), step);
+
+    // They're put back where they were, and have to catch up with that before anything else they say counts
+    report_pos(&server, player, step + Vec3::new(200.0, 0.0, 0.0));
+    assert_eq!(pos_of(&server, player), step);
+    report_pos(&server, player, step + Vec3::new(0.0, 0.0, 50.0));
+    assert_eq!(pos_of(&server, player), step);
+    report_pos(&server, player, step);
+    report_pos(&server, player, step + Vec3::new(0.0, 1.0, 0.0));
+    assert_eq!(pos_of(&server, player), step + Vec3::new(0.0, 1.0, 0.0));
+
+    // Nonsense is as impossible as anything else
+    report_pos(&server, player, Vec3::broadcast(std::f32::NAN));
+    assert_eq!(pos_of(&server, player), step + Vec3::new(0.0, 1.0, 0.0));
+}
+
+#[test]
+fn players_only_rise_quickly_when_jumping() {
+    let (server, addr) = server();
+    let (_po, player) = connect_far_away(&server, addr, "jumper");
+    report_pos(&server, player, FAR_AWAY);
+
+    // Too quick for climbing onto a block...
+    let up = FAR_AWAY + Vec3::new(0.0, 0.0, 4.5);
+    report_move(&server, player, up, false);
+    assert_eq!(pos_of(&server, player), FAR_AWAY);
+
+    // ...but not for a jump
+    report_pos(&server, player, FAR_AWAY);
+    report_move(&server, player, up, true);
+    assert_eq!(pos_of(&server, player), up);
+}
+
+#[test]
+fn how_far_players_can_move_follows_the_physics() {
+    let (server, addr) = server();
+    let (_po, player) = connect_far_away(&server, addr, "sprinter");
+    report_pos(&server, player, FAR_AWAY);
+
+    let dash = FAR_AWAY + Vec3::new(8.0, 0.0, 0.0);
+    report_pos(&server, player, dash);
+    assert_eq!(pos_of(&server, player), FAR_AWAY);
+
+    // Twice as fast a sprint covers twice the ground
+    server.do_for_mut(|srv| {
+        let mut physics = srv.physics();
+        physics.sprint_speed *= 2.0;
+        srv.set_physics(physics);
+    });
+    report_pos(&server, player, FAR_AWAY);
+    report_pos(&server, player, dash);
+    assert_eq!(pos_of(&server, player), dash);
+}
+
+#[test]
+fn players_cant_move_into_terrain() {
+    let (server, addr) = server();
+    let (_po, player) = connect_far_away(&server, addr, "ghost");
+
+    let wall = FAR_AWAY + Vec3::new(1.0, 0.0, 0.0);
+    server.do_for_mut(|srv| {
+        srv.world.write_resource::<LoadedChunks>().0.insert(
+            voxabs_to_voloffs(wall.map(|e| e.floor() as VoxAbs), CHUNK_SIZE),
+            Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::STONE)),
+        );
+    });
+
+    report_pos(&server, player, wall);
+    assert_eq!(pos_of(&server, player), FAR_AWAY);
+}
+
+#[test]
+fn repeat_cheaters_are_kicked() {
+    let (server, addr) = server();
+    let (_po, player) = connect_far_away(&server, addr, "cheater");
+
+    for _ in 0..player::MAX_VIOLATIONS - 1 {
+        report_pos(&server, player, FAR_AWAY + Vec3::new(500.0, 0.0, 0.0));
+        report_pos(&server, player, FAR_AWAY);
+    }
+    assert!(server.do_for(|srv| srv.world.is_alive(player)));
+
+    report_pos(&server, player, FAR_AWAY + Vec3::new(500.0, 0.0, 0.0));
+    assert!(server.do_for(|srv| !srv.world.is_alive(player)));
+}
+
+#[test]
+fn teleports_arent_movement() {
+    let (server, addr) = server();
+    let (_po, player) = connect_far_away(&server, addr, "traveller");
+
+    let dest = FAR_AWAY + Vec3::new(3000.0, 0.0, 0.0);
+    server.do_for_mut(|srv| srv.teleport_now(player, dest));
+    // Updates from before the client heard about the teleport don't drag the player back
+    report_pos(&server, player, FAR_AWAY);
+    assert_eq!(pos_of(&server, player), dest);
+
+    server.do_for_mut(|srv| srv.handle_player_ready(player));
+    report_pos(&server, player, dest);
+    report_pos(&server, player, dest + Vec3::new(1.0, 0.0, 0.0));
+    assert_eq!(pos_of(&server, player), dest + Vec3::new(1.0, 0.0, 0.0));
+}
+
+// Move the world border in to just past `FAR_AWAY`
+fn border_near(server: &Wrapper<Server<TestPayloads>>, barrier: Barrier) -> WorldBorder {
+    let border = WorldBorder {
+        center: Vec2::from(FAR_AWAY) - Vec2::new(20.0, 0.0),
+        radius: 24.0,
+        barrier,
+    };
+    server.do_for_mut(|srv| srv.set_world_border(border));
+    border
+}
+
+#[test]
+fn players_cant_pass_the_world_border() {
+    let (server, addr) = server();
+    let (_po, player) = connect_far_away(&server, addr, "explorer");
+    let border = border_near(&server, Barrier::Wall);
+
+    // Walking along, a step at a time, ends up pressed against the border but never past it
+    for _ in 0..20 {
+        let pos = pos_of(&server, player);
+        report_pos(&server, player, pos + Vec3::new(1.0, 0.0, 0.0));
+        assert!(border.contains(pos_of(&server, player)));
+    }
+    let pos = pos_of(&server, player);
+    assert!(pos.x > FAR_AWAY.x);
+    assert!(border.distance(pos) <= 2.0);
+
+    // ...and nobody can be sent beyond it either
+    server.do_for_mut(|srv| srv.set_entity_pos(player, FAR_AWAY + Vec3::new(500.0, 0.0, 0.0)));
+    let dest = server.do_for(|srv| srv.teleports.get(&player).cloned());
+    assert!(border.contains(dest.unwrap_or_else(|| pos_of(&server, player))));
+
+    // ...or nowhere at all
+    for nowhere in &[Vec3::new(std::f32::NAN, 0.0, 0.0), Vec3::new(0.0, std::f32::INFINITY, 0.0)] {
+        assert!(!server.do_for_mut(|srv| srv.set_entity_pos(player, *nowhere)));
+    }
+    assert!(pos_of(&server, player).map(|e| e.is_finite()).reduce_and());
+}
+
+#[test]
+fn nothing_is_generated_beyond_the_world_border() {
+    let (server, _) = server();
+    let border = border_near(&server, Barrier::Void);
+
+    let outside = voxabs_to_voloffs(far_away_block() + Vec3::new(100, 0, 0), CHUNK_SIZE);
+    assert!(border.is_chunk_outside(outside, CHUNK_SIZE));
+    server.do_for(|srv| srv.request_chunk(outside));
+    assert!(server.do_for(|srv| !srv.chunk_gen.is_pending(outside)));
+    assert_eq!(server.do_for(|srv| srv.chunk_gen.queue_depth()), 0);
+
+    // It's filled with the border's barrier instead
+    let block = far_away_block() + Vec3::new(100, 0, 0);
+    assert_eq!(server.do_for(|srv| srv.world.read_resource::<LoadedChunks>().block_at(block)), Ok(Block::AIR));
+}
+
+#[test]
+fn payloads_can_use_the_api() {
+    let payloads = ApiPayloads::default();
+    let found = payloads.found.clone();
+    let server = Server::new(payloads, "127.0.0.1:0").unwrap();
+    let addr = server.do_for(|srv| srv.local_addr()).unwrap();
+    server.do_for_mut(|srv| {
+        srv.world.write_resource::<LoadedChunks>().0.insert(
+            voxabs_to_voloffs(far_away_block(), CHUNK_SIZE),
+            Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)),
+        );
+    });
+
+    let (po, _, _) = connect(addr, "builder", None);
+    let builder = server.do_for(|srv| {
+        (&srv.world.entities(), &srv.world.read_storage::<Player>())
+            .join()
+            .map(|(e, _)| e)
+            .next()
+            .unwrap()
+    });
+    {
+        let found = found.lock();
+        assert_eq!(found[0].0, vec![builder]);
+        assert!(found[0].1.contains(&builder));
+    }
+
+    // The change reaches the server's copy of the chunk, and the client gets the changed chunk when it asks for it
+    let chunk = voxabs_to_voloffs(far_away_block(), CHUNK_SIZE);
+    wait_until(|| {
+        let block = server.do_for(|srv| srv.world.read_resource::<LoadedChunks>().block_at(far_away_block()));
+        block == Ok(Block::STONE)
+    });
+    po.send_one(ClientMsg::RequestChunks { positions: vec![chunk] }).unwrap();
+    let data = await_msg(&po, |msg| match msg {
+        ServerMsg::ChunkData { pos, data } => Some(data).filter(|_| pos == chunk),
+        _ => None,
+    });
+    let mut received = LoadedChunks::default();
+    received.0.insert(chunk, Chunk::from_bytes(&data).unwrap());
+    assert_eq!(received.block_at(far_away_block()), Ok(Block::STONE));
+
+    let (_po, _, _) = connect(addr, "unwelcome", None);
+    wait_until(|| server.do_for(|srv| srv.world.read_storage::<Player>().join().count() == 1));
+    assert_eq!(found.lock()[1].0.len(), 2);
+}
+
+// Read one-shot messages until `f` picks one out
+fn await_msg<T, F: FnMut(ServerMsg) -> Option<T>>(po: &Manager<ClientPostOffice>, mut f: F) -> T {
+    loop {
+        match po.await_incoming() {
+            Ok(Incoming::Msg(msg)) => {
+                if let Some(found) = f(msg) {
+                    return found;
+                }
+            },
+            Ok(_) => {},
+            Err(_) => panic!("Connection ended"),
+        }
+    }
+}
+
+#[test]
+fn chat_floods_are_contained() {
+    let (server, addr) = server();
+    let (listener, _, _) = connect(addr, "listener", None);
+    let (spammer, _, _) = connect(addr, "spammer", None);
+    let spam = |n| {
+        for _ in 0..n {
+            let _ = spammer.send_one(ClientMsg::ChatMsg { text: "spam".into() });
+        }
+    };
+
+    // Going over the limit gets the spammer told to slow down...
+    spam(20);
+    await_msg(&spammer, |msg| match &msg {
+        ServerMsg::ChatMsg { text } if text.contains("too fast") => Some(()),
+        _ => None,
+    });
+    // ...and keeping at it gets them thrown out
+    spam(1000);
+    wait_until(|| player_named(&server, "spammer").is_none());
+
+    // Everyone else only saw what got through. The spammer leaving is announced after all of it.
+    let mut received = 0;
+    await_msg(&listener, |msg| match msg {
+        ServerMsg::ChatMsg { text } => {
+            if text.ends_with("spam") {
+                received += 1;
+            }
+            if text.contains("spammer disconnected") {
+                Some(())
+            } else {
+                None
+            }
+        },
+        _ => None,
+    });
+    // The budget is 4 messages, and it refills a little while the test runs
+    assert!(received >= 4 && received <= 6, "{} messages got through", received);
+}
+
+#[test]
+fn payloads_can_add_commands() {
+    let (server, addr) = server();
+    let greet = Cmd::new("greet", "Say hello to someone", Permission::Player)
+        .usage(vec![ArgSpec::required("who", ArgKind::Player)]);
+    let handler: CmdHandler = Box::new(|api: &dyn Api, sender: Sender, args: &Args| {
+        let who = args.player("who").unwrap();
+        let alias = api.world().read_storage::<Player>().get(who).unwrap().alias.clone();
+        api.reply(sender, &format!("Hello, {}!", alias));
+    });
+    assert!(server.do_for(|srv| srv.register_command(greet, handler)));
+    let taken = Cmd::new("greet", "Say hello again", Permission::Player);
+    assert!(!server.do_for(|srv| srv.register_command(taken, Box::new(|_: &dyn Api, _: Sender, _: &Args| {}))));
+
+    // Players are told about it when they join, so their client can complete it
+    let (po, _, _) = connect(addr, "greeter", None);
+    let commands = await_msg(&po, |msg| match msg {
+        ServerMsg::CommandList { commands } => Some(commands),
+        _ => None,
+    });
+    let greet = commands.iter().find(|cmd| cmd.name == "greet").unwrap();
+    assert_eq!(greet.usage("/"), "/greet <who>");
+    assert!(!commands.iter().any(|cmd| cmd.name == "stop"));
+
+    // Its arguments are checked before it runs
+    let send = |text: &str| po.send_one(ClientMsg::ChatMsg { text: text.into() }).unwrap();
+    let reply = || {
+        await_msg(&po, |msg| match msg {
+            ServerMsg::ChatMsg { text } if !text.starts_with('[') => Some(text),
+            _ => None,
+        })
+    };
+    send("/greet greeter");
+    assert_eq!(reply(), "Hello, greeter!");
+    send("/greet 17");
+    assert_eq!(reply(), "expected a player name, got '17'");
+    assert_eq!(reply(), "Usage: /greet <who>");
+    send("/settime 5");
+    assert_eq!(reply(), "Sorry, you need to be a moderator to use 'settime'");
+}
+
+// Try to log in, expecting to be turned away. Returns why.
+fn refused(addr: SocketAddr, alias: &str) -> String {
+    let po = ClientPostOffice::to_server(addr, Encryption::Preferred).unwrap();
+    let pb = po.create_postbox(SessionKind::Connect);
+    pb.send(ClientMsg::Connect {
+        alias: alias.to_string(),
+        mode: PlayMode::Character,
+        session: None,
+        login_token: None,
+    })
+    .unwrap();
+
+    match pb.recv_timeout(TIMEOUT).unwrap() {
+        ServerMsg::Disconnect { reason } => reason,
+        msg => panic!("Unexpected reply: {:?}", msg),
+    }
+}
+
+#[test]
+fn banned_and_unlisted_players_are_turned_away() {
+    let (server, addr) = server();
+    let console = |text: &str| process_cmd(&server, text, Sender::Console, &server);
+
+    // Banning someone who's online kicks them, and keeps them out under other aliases from the same address
+    let (_po, _, _) = connect(addr, "griefer", None);
+    console("ban griefer 1h flattening spawn");
+    wait_until(|| player_named(&server, "griefer").is_none());
+    let reason = "You are banned from this server for another 1 hour: flattening spawn";
+    assert_eq!(refused(addr, "griefer"), reason);
+    assert_eq!(refused(addr, "newcomer"), reason);
+    assert_eq!(player_named(&server, "newcomer"), None);
+
+    console("unban griefer");
+    let (_po, uid, _) = connect(addr, "griefer", None);
+    assert!(uid.is_some());
+
+    // Only those on the whitelist get in while it's on
+    console("whitelist add friend");
+    console("whitelist on");
+    assert_eq!(refused(addr, "stranger"), "This server is whitelisted, and you're not on the whitelist");
+    let (_po, uid, _) = connect(addr, "friend", None);
+    assert!(uid.is_some());
+    console("whitelist off");
+    let (_po, uid, _) = connect(addr, "stranger", None);
+    assert!(uid.is_some());
+}
+
+#[test]
+fn full_servers_turn_new_players_away() {
+    let (server, addr) = configured_server(ServerConfig {
+        max_players: 1,
+        ..ServerConfig::default()
+    });
+
+    let (po, _, session) = connect(addr, "first", None);
+    assert_eq!(refused(addr, "second"), "The server is full");
+
+    // A player who lost connection keeps their place, and can come back to it
+    po.stop();
+    wait_until(|| suspended(&server) == 1);
+    assert_eq!(refused(addr, "second"), "The server is full");
+    let (_po, uid, _) = connect(addr, "first", Some(session));
+    assert!(uid.is_some());
+}
+
+#[test]
+fn reloading_changes_only_what_can_change_while_running() {
+    let path = std::env::temp_dir().join(format!("veloren-reload-{}.toml", std::process::id()));
+    fs::write(&path, "address = \"127.0.0.1\"\nport = 0\nmax_players = 1\n").unwrap();
+    let server = Server::with_config(TestPayloads, ServerConfig::load(path.clone()).unwrap()).unwrap();
+    let addr = server.do_for(|srv| srv.local_addr()).unwrap();
+    let console = |text: &str| process_cmd(&server, text, Sender::Console, &server);
+
+    let (_first, _, _) = connect(addr, "first", None);
+    assert_eq!(refused(addr, "second"), "The server is full");
+
+    // The seed is left as it was, but there's room for another player
+    fs::write(&path, "address = \"127.0.0.1\"\nport = 0\nmax_players = 2\nworld_seed = 9\n").unwrap();
+    console("reload");
+    let (_second, uid, _) = connect(addr, "second", None);
+    assert!(uid.is_some());
+    assert_eq!(server.do_for(|srv| srv.world_seed()), 0);
+    assert_eq!(server.do_for(|srv| srv.config().max_players), 2);
+
+    fs::remove_file(&path).unwrap();
+}
+
+// Ask about the server without joining it
+fn status(addr: SocketAddr) -> ServerInfo {
+    let po = ClientPostOffice::to_server(addr, Encryption::Preferred).unwrap();
+    let pb = po.create_postbox(SessionKind::Status);
+    pb.send(ClientMsg::Status).unwrap();
+    match pb.recv_timeout(TIMEOUT).unwrap() {
+        ServerMsg::ServerInfo(info) => info,
+        msg => panic!("Unexpected reply: {:?}", msg),
+    }
+}
+
+#[test]
+fn players_are_told_about_the_server_when_they_join() {
+    let (_server, addr) = configured_server(ServerConfig {
+        server_name: "Testing Grounds".to_string(),
+        motd: "Welcome!\nNo griefing".to_string(),
+        ..ServerConfig::default()
+    });
+
+    let (po, _, _) = connect(addr, "newcomer", None);
+    let info = await_msg(&po, |msg| match msg {
+        ServerMsg::ServerInfo(info) => Some(info),
+        _ => None,
+    });
+    assert_eq!(
+        info,
+        ServerInfo {
+            name: "Testing Grounds".to_string(),
+            motd: "Welcome!\nNo griefing".to_string(),
+            player_count: 1,
+            max_players: 32,
+        }
+    );
+}
+
+#[test]
+fn players_are_told_the_size_of_everyone_already_there() {
+    let (server, addr) = server();
+    let (early, early_uid, _) = connect(addr, "early", None);
+    let early_uid = early_uid.unwrap();
+    let player = player_named(&server, "early").unwrap();
+    let size = Vec3::new(2.0, 2.0, 3.0);
+    server.do_for_mut(|srv| srv.update_comp(player, CollisionBox(size)));
+    let size_of_early = |po: &Manager<ClientPostOffice>| {
+        await_msg(po, |msg| match msg {
+            ServerMsg::CompUpdate {
+                uid,
+                store: CompStore::CollisionBox(sent),
+                ..
+            } if uid == early_uid && sent == size => Some(()),
+            _ => None,
+        })
+    };
+    // The size has gone out to everyone there at the time once the player has heard about it
+    size_of_early(&early);
+
+    let (late, _, _) = connect(addr, "late", None);
+    size_of_early(&late);
+}
+
+#[test]
+fn anyone_can_ask_about_a_server_without_joining() {
+    let (server, addr) = configured_server(ServerConfig {
+        server_name: "Testing Grounds".to_string(),
+        max_players: 1,
+        ..ServerConfig::default()
+    });
+
+    let info = status(addr);
+    assert_eq!(info.name, "Testing Grounds");
+    assert_eq!((info.player_count, info.max_players), (0, 1));
+    // Asking doesn't make a player, so there's still room for one
+    assert_eq!(server.do_for(|srv| srv.player_count()), 0);
+    let (_po, uid, _) = connect(addr, "player", None);
+    assert!(uid.is_some());
+
+    // Nor does it need room on the server
+    assert_eq!(status(addr).player_count, 1);
+    assert_eq!(server.do_for(|srv| srv.player_count()), 1);
+}
+
+// Ask for chunks, then for a chunk that hasn't been asked for before. Returns which chunks were sent before that one.
+fn chunks_sent(
+    po: &Manager<ClientPostOffice>,
+    positions: &[Vec3<VolOffs>],
+    marker: Vec3<VolOffs>,
+) -> Vec<Vec3<VolOffs>> {
+    let mut requested = positions.to_vec();
+    requested.push(marker);
+    po.send_one(ClientMsg::RequestChunks { positions: requested }).unwrap();
+
+    let mut sent = vec![];
+    await_msg(po, |msg| match &msg {
+        ServerMsg::ChunkData { pos, .. } if *pos == marker => Some(()),
+        ServerMsg::ChunkData { pos, .. } => {
+            sent.push(*pos);
+            None
+        },
+        _ => None,
+    });
+    sent
+}
+
+#[test]
+fn chunks_are_only_sent_again_once_forgotten_or_changed() {
+    let (server, addr) = server();
+    let (po, _, _) = connect(addr, "walker", None);
+
+    // Two chunks either side of a border, and a marker chunk for each time the client asks for chunks
+    let west = voxabs_to_voloffs(far_away_block(), CHUNK_SIZE);
+    let east = west + Vec3::unit_x();
+    let markers = (1..30).map(|i| west + Vec3::new(0, i, 0)).collect::<Vec<_>>();
+    server.do_for_mut(|srv| {
+        let mut chunks = srv.world.write_resource::<LoadedChunks>();
+        for pos in markers.iter().chain(&[west, east]) {
+            chunks.0.insert(*pos, Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)));
+        }
+    });
+    let mut markers = markers.into_iter();
+    let mut walk = |positions: &[Vec3<VolOffs>]| chunks_sent(&po, positions, markers.next().unwrap());
+
+    // A client that keeps both chunks loaded gets each of them once, however often it asks for them
+    let mut sent = vec![];
+    for _ in 0..5 {
+        sent.extend(walk(&[west, east]));
+        sent.extend(walk(&[east, west]));
+    }
+    assert_eq!(sent, vec![west, east]);
+
+    // A client that only keeps the chunk it's in gets the other one again each time it crosses the border
+    let mut sent = vec![];
+    for _ in 0..3 {
+        po.send_one(ClientMsg::ForgetChunks { positions: vec![west] }).unwrap();
+        sent.extend(walk(&[east]));
+        po.send_one(ClientMsg::ForgetChunks { positions: vec![east] }).unwrap();
+        sent.extend(walk(&[west]));
+    }
+    assert_eq!(sent, vec![west, east, west, east, west]);
+
+    // A small edit to a chunk the client has is sent block by block...
+    server.do_for(|srv| srv.set_block(far_away_block(), Block::STONE));
+    let changed = await_msg(&po, |msg| match msg {
+        ServerMsg::BlockUpdate { pos, .. } => Some(pos),
+        ServerMsg::ChunkData { .. } => panic!("A small edit was sent as a whole chunk"),
+        _ => None,
+    });
+    assert_eq!(changed, far_away_block());
+    assert_eq!(walk(&[west]), vec![]);
+
+    // ...a big one as the whole chunk...
+    let origin = west.map(|e| e as VoxAbs) * CHUNK_SIZE.map(|e| e as VoxAbs);
+    server.do_for(|srv| {
+        for x in 0..20 {
+            srv.set_block(origin + Vec3::new(x, 0, 0), Block::STONE);
+        }
+    });
+    let resent = await_msg(&po, |msg| match msg {
+        ServerMsg::ChunkData { pos, .. } => Some(pos),
+        ServerMsg::BlockUpdate { .. } => panic!("A big edit was sent block by block"),
+        _ => None,
+    });
+    assert_eq!(resent, west);
+    assert_eq!(walk(&[west]), vec![]);
+
+    // ...and a chunk the client doesn't have is sent with its changes when it's next asked for
+    server.do_for(|srv| srv.set_block(origin + Vec3::new(CHUNK_SIZE.x as VoxAbs, 0, 0), Block::STONE));
+    assert_eq!(walk(&[west, east]), vec![east]);
+}
+
+#[test]
+fn clients_cant_ask_for_too_many_chunks_at_once() {
+    let (server, addr) = server();
+    let (po, _, _) = connect(addr, "greedy", None);
+
+    let first = voxabs_to_voloffs(far_away_block(), CHUNK_SIZE);
+    let positions = (0..MAX_CHUNK_REQUEST as VolOffs + 10)
+        .map(|i| first + Vec3::new(i, 0, 0))
+        .collect::<Vec<_>>();
+    let marker = first + Vec3::unit_y();
+    server.do_for_mut(|srv| {
+        let mut chunks = srv.world.write_resource::<LoadedChunks>();
+        for pos in positions.iter().chain(&[marker]) {
+            chunks.0.insert(*pos, Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)));
+        }
+    });
+
+    // Only as many as one request can hold are sent, the rest have to be asked for again
+    po.send_one(ClientMsg::RequestChunks { positions: positions.clone() }).unwrap();
+    assert_eq!(chunks_sent(&po, &[], marker), positions[..MAX_CHUNK_REQUEST].to_vec());
+}
+
+#[test]
+fn sounds_are_only_sent_to_players_in_hearing_range() {
+    let (server, addr) = server();
+    let (near, near_player) = connect_far_away(&server, addr, "near");
+    let (far, far_player) = connect_far_away(&server, addr, "far");
+    // In range, but without the chunk the sound is in
+    let (unloaded, _) = connect_far_away(&server, addr, "unloaded");
+
+    let range = server.do_for(|srv| srv.world.read_resource::<TickConfig>().hearing_range);
+    let chunk = voxabs_to_voloffs(far_away_block(), CHUNK_SIZE);
+    server.do_for_mut(|srv| {
+        srv.update_comp(far_player, Pos(FAR_AWAY + Vec3::new(range + 10.0, 0.0, 0.0)));
+        let mut clients = srv.world.write_storage::<Client>();
+        for player in &[near_player, far_player] {
+            clients.get_mut(*player).unwrap().known_chunks.insert(chunk, 0);
+        }
+    });
+
+    // Everyone gets the marker after the sound, so anyone who sees the marker first was never sent the sound
+    server.do_for(|srv| {
+        srv.play_sound_at(SoundId::GROWL, FAR_AWAY, 0.5);
+        let marker = ServerMsg::ChatMsg { text: "marker".into() };
+        srv.world.read_resource::<Outbox>().send(Target::All, marker);
+    });
+    let heard = |po: &Manager<ClientPostOffice>| {
+        await_msg(po, |msg| match &msg {
+            ServerMsg::SoundEvent { sound, .. } => Some(Some(*sound)),
+            ServerMsg::ChatMsg { text } if text.contains("marker") => Some(None),
+            _ => None,
+        })
+    };
+    assert_eq!(heard(&near), Some(SoundId::GROWL));
+    assert_eq!(heard(&far), None);
+    assert_eq!(heard(&unloaded), None);
+}
+
+#[test]
+fn players_are_told_the_weather_where_they_are() {
+    let (server, addr) = server();
+    let (po, player) = connect_far_away(&server, addr, "forecaster");
+    let weather_in = |po: &Manager<ClientPostOffice>| {
+        await_msg(po, |msg| match msg {
+            ServerMsg::WeatherUpdate { region, intensity, .. } => Some((region, intensity)),
+            _ => None,
+        })
+    };
+
+    let (region, intensity) = weather_in(&po);
+    assert_eq!(region, weather_region(FAR_AWAY));
+    assert!(intensity >= 0.0 && intensity <= 1.0);
+
+    // Moving into the next region brings news of its weather
+    let next = FAR_AWAY + Vec3::new(WEATHER_REGION_SIZE as f32, 0.0, 0.0);
+    server.do_for_mut(|srv| srv.update_comp(player, Pos(next)));
+    let next_region = weather_region(next);
+    assert_eq!(next_region, region + Vec2::new(1, 0));
+    while weather_in(&po).0 != next_region {}
+    assert!(server.do_for(|srv| srv.weather.region(next_region).is_some()));
+}
+
+#[test]
+fn blocks_only_break_once_dug_for_long_enough() {
+    let (server, addr) = server();
+    let (miner, player) = connect_far_away(&server, addr, "miner");
+    let (onlooker, onlooker_player) = connect_far_away(&server, addr, "onlooker");
+
+    let (leaf, stone, bedrock) = (
+        far_away_block() + Vec3::new(1, 0, 0),
+        far_away_block() + Vec3::new(0, 1, 0),
+        far_away_block() + Vec3::new(-1, 0, 0),
+    );
+    let chunk = voxabs_to_voloffs(far_away_block(), CHUNK_SIZE);
+    server.do_for_mut(|srv| {
+        let mut chunks = srv.world.write_resource::<LoadedChunks>();
+        chunks.0.insert(chunk, Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)));
+        for (pos, block) in &[(leaf, Block::LEAF), (stone, Block::STONE), (bedrock, Block::BEDROCK)] {
+            chunks.set_block(*pos, *block).unwrap();
+        }
+        srv.world.write_storage::<Client>().get_mut(onlooker_player).unwrap().known_chunks.insert(chunk, 0);
+    });
+    let dig = |pos, progress| server.do_for_mut(|srv| srv.handle_dig_progress(player, pos, progress));
+    let put_back = |po: &Manager<ClientPostOffice>| {
+        await_msg(po, |msg| match msg {
+            ServerMsg::BlockUpdate { pos, block } => Some((pos, block)),
+            _ => None,
+        })
+    };
+
+    // Finishing straight away is caught, and the miner's client told to put the block back
+    dig(stone, 0.1);
+    dig(stone, 1.0);
+    assert_eq!(put_back(&miner), (stone, Block::STONE));
+
+    // ...as is clearing it without digging at all
+    server.do_for_mut(|srv| srv.handle_set_block(player, stone, Block::AIR));
+    assert_eq!(put_back(&miner), (stone, Block::STONE));
+
+    // Bedrock can't be dug however long it takes, or built over
+    dig(bedrock, 0.1);
+    assert_eq!(put_back(&miner), (bedrock, Block::BEDROCK));
+    server.do_for_mut(|srv| srv.handle_set_block(player, bedrock, Block::AIR));
+    assert_eq!(put_back(&miner), (bedrock, Block::BEDROCK));
+
+    // Nearby players see the dig, and the block breaks once it's been dug for long enough
+    dig(leaf, 0.5);
+    let seen = await_msg(&onlooker, |msg| match msg {
+        ServerMsg::DigProgress { pos, progress, .. } if pos == leaf => Some(progress),
+        _ => None,
+    });
+    assert!(seen > 0.0);
+    thread::sleep(Duration::from_millis((Block::LEAF.hardness() * 1000.0) as u64));
+    dig(leaf, 1.0);
+    wait_until(|| server.do_for(|srv| srv.world.read_resource::<LoadedChunks>().block_at(leaf)) == Ok(Block::AIR));
+}
+
+#[test]
+fn selected_slots_reach_other_players() {
+    let (server, addr) = server();
+    let (po, uid, _) = connect(addr, "holder", None);
+    let holder = player_named(&server, "holder").unwrap();
+    let (onlooker, _) = connect_far_away(&server, addr, "onlooker");
+    server.do_for_mut(|srv| {
+        let mut inv = Inventory::new();
+        inv.insert(Item::new(ItemKind::Wood, 5));
+        inv.swap(0, 2).unwrap();
+        srv.update_comp(holder, inv);
+    });
+    let held = || server.do_for(|srv| srv.world.read_storage::<HeldItem>().get(holder).cloned());
+
+    po.send_one(ClientMsg::SelectSlot { slot: 2 }).unwrap();
+    wait_until(|| held() == Some(HeldItem(2)));
+    // Everyone else is told what kind of item it is
+    let seen = await_msg(&onlooker, |msg| match msg {
+        ServerMsg::CompUpdate {
+            uid: from,
+            store: CompStore::HeldItem { slot, item },
+            ..
+        } if Some(from) == uid && slot == 2 => Some(item),
+        _ => None,
+    });
+    assert_eq!(seen, Some(ItemKind::Wood));
+
+    // There's no slot past the end of the hotbar to hold
+    server.do_for_mut(|srv| srv.handle_select_slot(holder, HOTBAR_SLOTS));
+    assert_eq!(held(), Some(HeldItem(2)));
+}
+
+#[test]
+fn items_are_thrown_the_way_the_player_faces() {
+    let (server, addr) = server();
+    let (_po, player) = connect_far_away(&server, addr, "thrower");
+    let drops = server.do_for_mut(|srv| {
+        let mut inv = Inventory::new();
+        inv.insert(Item::new(ItemKind::Wood, 2));
+        srv.update_comp(player, inv);
+
+        // Facing east, and looking down a little, which doesn't matter
+        let mut drops = vec![];
+        for dir in &[Vec2::new(FRAC_PI_2, -0.4), Vec2::new(PI, 0.0)] {
+            srv.update_comp(player, Dir(*dir));
+            srv.handle_inventory_action(player, InventoryAction::Drop { slot: 0, amount: 1 });
+            let dropped = (&srv.world.read_storage::<Pos>(), &srv.world.read_storage::<ItemDrop>())
+                .join()
+                .map(|(pos, _)| Vec2::from(pos.0))
+                .find(|pos| !drops.contains(pos));
+            drops.push(dropped.unwrap());
+        }
+        drops
+    });
+    assert!(drops[0].distance(Vec2::from(FAR_AWAY) + Vec2::new(2.0, 0.0)) < 0.001);
+    assert!(drops[1].distance(Vec2::from(FAR_AWAY) + Vec2::new(0.0, -2.0)) < 0.001);
+}
+
+#[test]
+fn items_are_removed_from_clients_that_leave_their_range() {
+    let (server, addr) = server();
+    let (po, player) = connect_far_away(&server, addr, "walker");
+    let item = server.do_for_mut(|srv| {
+        let mut inv = Inventory::new();
+        inv.insert(Item::new(ItemKind::Wood, 1));
+        srv.update_comp(player, inv);
+        srv.handle_inventory_action(player, InventoryAction::Drop { slot: 0, amount: 1 });
+
+        let (pos, uid) = (
+            &srv.world.read_storage::<Pos>(),
+            &srv.world.read_storage::<UidMarker>(),
+            &srv.world.read_storage::<ItemDrop>(),
+        )
+            .join()
+            .map(|(pos, uid, _)| (pos.0, uid.id()))
+            .next()
+            .unwrap();
+        let chunk = voxabs_to_voloffs(pos.map(|e| e.floor() as VoxAbs), CHUNK_SIZE);
+        srv.world.write_storage::<Client>().get_mut(player).unwrap().known_chunks.insert(chunk, 0);
+        uid
+    });
+    await_msg(&po, |msg| match msg {
+        ServerMsg::CompUpdate { uid, .. } if uid == item => Some(()),
+        _ => None,
+    });
+
+    // Walking away makes the item disappear, though it's still there for anyone nearby
+    let range = server.do_for(|srv| srv.world.read_resource::<TickConfig>().item_sync_range);
+    server.do_for_mut(|srv| srv.update_comp(player, Pos(FAR_AWAY + Vec3::new(2.0 * range, 0.0, 0.0))));
+    await_msg(&po, |msg| match msg {
+        ServerMsg::EntityDeleted { uid } if uid == item => Some(()),
+        ServerMsg::EntitiesDeleted { uids } if uids.contains(&item) => Some(()),
+        _ => None,
+    });
+    assert_eq!(server.do_for(|srv| srv.world.read_storage::<ItemDrop>().join().count()), 1);
+}
+
+#[test]
+fn placing_blocks_uses_up_the_held_stack() {
+    let (server, addr) = server();
+    let (_builder, player) = connect_far_away(&server, addr, "builder");
+    let chunk = voxabs_to_voloffs(far_away_block(), CHUNK_SIZE);
+    server.do_for_mut(|srv| {
+        srv.world
+            .write_resource::<LoadedChunks>()
+            .0
+            .insert(chunk, Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)));
+        let mut inv = Inventory::new();
+        inv.insert(Item::new(ItemKind::Stone, 2));
+        srv.update_comp(player, inv);
+    });
+    let place = |pos| server.do_for_mut(|srv| srv.handle_set_block(player, pos, Block::STONE));
+    let held = || server.do_for(|srv| srv.world.read_storage::<Inventory>().get(player).unwrap().get(0));
+    let placed = |pos| server.do_for(|srv| srv.world.read_resource::<LoadedChunks>().block_at(pos)) == Ok(Block::STONE);
+    let (a, b) = (far_away_block() + Vec3::new(1, 0, 0), far_away_block() + Vec3::new(0, 1, 0));
+
+    // Each block takes one from the stack in the builder's hand
+    place(a);
+    assert_eq!(held(), Some(Item::new(ItemKind::Stone, 1)));
+    place(b);
+    assert_eq!(held(), None);
+    wait_until(|| placed(a) && placed(b));
+}
+
+#[test]
+fn placing_is_refused_once_the_stack_runs_out() {
+    let (server, addr) = server();
+    let (builder, player) = connect_far_away(&server, addr, "builder");
+    let chunk = voxabs_to_voloffs(far_away_block(), CHUNK_SIZE);
+    server.do_for_mut(|srv| {
+        srv.world
+            .write_resource::<LoadedChunks>()
+            .0
+            .insert(chunk, Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)));
+        let mut inv = Inventory::new();
+        inv.insert(Item::new(ItemKind::Stone, 1));
+        srv.update_comp(player, inv);
+    });
+    let place = |pos, block| server.do_for_mut(|srv| srv.handle_set_block(player, pos, block));
+    let put_back = |pos| {
+        await_msg(&builder, |msg| match msg {
+            ServerMsg::BlockUpdate { pos: at, block } if at == pos => Some(block),
+            _ => None,
+        })
+    };
+    let block_at = |pos| server.do_for(|srv| srv.world.read_resource::<LoadedChunks>().block_at(pos));
+    let (a, b) = (far_away_block() + Vec3::new(1, 0, 0), far_away_block() + Vec3::new(0, 1, 0));
+
+    // Stone doesn't make logs
+    place(a, Block::LOG);
+    assert_eq!(put_back(a), Block::AIR);
+
+    // The builder is told when their hand is empty
+    place(a, Block::STONE);
+    let inv = await_msg(&builder, |msg| match msg {
+        ServerMsg::CompUpdate {
+            store: CompStore::Inventory(inv),
+            ..
+        } => Some(inv),
+        _ => None,
+    });
+    assert_eq!(inv.get(0), None);
+    wait_until(|| block_at(a) == Ok(Block::STONE));
+
+    // With the stack gone there's nothing left to build with
+    place(b, Block::STONE);
+    assert_eq!(put_back(b), Block::AIR);
+    assert_eq!(block_at(b), Ok(Block::AIR));
+}
+
+// The kind and position of every spawned entity
+fn spawned(server: &Wrapper<Server<SpawnPayloads>>) -> Vec<(String, Vec3<f32>)> {
+    server.do_for(|srv| {
+        (&srv.world.read_storage::<Spawned>(), &srv.world.read_storage::<Pos>())
+            .join()
+            .map(|(spawned, pos)| (spawned.kind.clone(), pos.0))
+            .collect()
+    })
+}
+
+#[test]
+fn spawning_stays_within_its_caps() {
+    let server = Server::new(SpawnPayloads, "127.0.0.1:0").unwrap();
+    let addr = server.do_for(|srv| srv.local_addr()).unwrap();
+
+    // Two players too far apart to share spawns, each on ground covering all of the ring around them that spawns happen
+    // in
+    let (here, there) = (FAR_AWAY, FAR_AWAY + Vec3::new(96.0, 0.0, 0.0));
+    server.do_for_mut(|srv| {
+        let mut chunks = srv.world.write_resource::<LoadedChunks>();
+        for pos in [here, there].iter() {
+            let chunk = voxabs_to_voloffs(pos.map(|e| e.floor() as VoxAbs), CHUNK_SIZE);
+            chunks.0.insert(chunk, Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)));
+            chunks.0.insert(
+                chunk - Vec3::unit_z(),
+                Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::GRASS)),
+            );
+        }
+    });
+    let (_po_here, _, _) = connect(addr, "here", None);
+    let (_po_there, _, _) = connect(addr, "there", None);
+    let (player_here, player_there) = (
+        player_named(&server, "here").unwrap(),
+        player_named(&server, "there").unwrap(),
+    );
+    server.do_for_mut(|srv| {
+        srv.update_comp(player_here, Pos(here));
+        srv.update_comp(player_there, Pos(there));
+    });
+
+    let near = |spawned: &[(String, Vec3<f32>)], pos: Vec3<f32>, dist: f32| {
+        spawned.iter().filter(|(_, p)| p.distance(pos) <= dist).count()
+    };
+    let check_caps = |spawned: &[(String, Vec3<f32>)]| {
+        assert!(spawned.len() <= 5, "{} entities spawned", spawned.len());
+        assert!(spawned.iter().filter(|(kind, _)| kind == "wolf").count() <= 1);
+        assert!(spawned.iter().all(|(kind, _)| kind != "dragon"));
+    };
+
+    // Neither player gets more than their share of the spawns
+    wait_until(|| spawned(&server).len() == 5);
+    let first = spawned(&server);
+    assert!(near(&first, here, 12.0) <= 3 && near(&first, there, 12.0) <= 3);
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        check_caps(&spawned(&server));
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // Once a player leaves, what spawned around them is removed, and nothing spawns where there's no ground
+    server.do_for_mut(|srv| srv.update_comp(player_there, Pos(FAR_AWAY + Vec3::new(0.0, 5000.0, 0.0))));
+    wait_until(|| near(&spawned(&server), there, 24.0) == 0);
+    check_caps(&spawned(&server));
+}
+
+#[test]
+fn the_config_can_push_spawns_further_out() {
+    let config = ServerConfig {
+        address: "127.0.0.1".to_string(),
+        port: 0,
+        spawn_distance: Some(8.0),
+        ..ServerConfig::default()
+    };
+    let server = Server::with_config(SpawnPayloads, config).unwrap();
+    let addr = server.do_for(|srv| srv.local_addr()).unwrap();
+
+    server.do_for_mut(|srv| {
+        let mut chunks = srv.world.write_resource::<LoadedChunks>();
+        let chunk = voxabs_to_voloffs(FAR_AWAY.map(|e| e.floor() as VoxAbs), CHUNK_SIZE);
+        chunks.0.insert(chunk, Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)));
+        chunks.0.insert(chunk - Vec3::unit_z(), Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::GRASS)));
+    });
+    let (_po, _, _) = connect(addr, "watcher", None);
+    let player = player_named(&server, "watcher").unwrap();
+    server.do_for_mut(|srv| srv.update_comp(player, Pos(FAR_AWAY)));
+
+    // The payload would let them spawn from 4 blocks away
+    wait_until(|| spawned(&server).len() == 3);
+    assert!(spawned(&server).iter().all(|(_, pos)| pos.distance(FAR_AWAY) >= 8.0));
+}
+
+// Fetch the metrics page, returning each metric's value by name
+fn scrape(addr: SocketAddr) -> HashMap<String, f64> {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).unwrap();
+
+    assert!(reply.starts_with("HTTP/1.0 200 OK"));
+    let body = &reply[reply.find("\r\n\r\n").unwrap() + 4..];
+    body.lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| {
+            let (name, value) = line.split_at(line.rfind(' ').unwrap());
+            (name.to_string(), value.trim().parse().unwrap())
+        })
+        .collect()
+}
+
+#[test]
+fn metrics_can_be_scraped() {
+    let server = Server::new(MetricsPayloads, "127.0.0.1:0").unwrap();
+    let addr = server.do_for(|srv| srv.local_addr()).unwrap();
+    let metrics_addr = server.do_for(|srv| srv.metrics_addr()).unwrap();
+
+    let before = scrape(metrics_addr);
+    for name in &[
+        "veloren_players",
+        "veloren_loaded_chunks",
+        "veloren_entities",
+        "veloren_tick_duration_seconds_bucket{le=\"+Inf\"}",
+        "veloren_tick_duration_seconds_sum",
+        "veloren_tick_duration_seconds_count",
+        "veloren_net_sent_bytes_total",
+        "veloren_net_received_bytes_total",
+        "veloren_net_corrupt_frames_total",
+        "veloren_net_bad_packets_total",
+    ] {
+        assert!(before.contains_key(*name), "Missing metric {}", name);
+    }
+
+    let (_po, _, _) = connect(addr, "scraper", None);
+    wait_until(|| scrape(metrics_addr)["veloren_players"] == 1.0);
+
+    // Counters only ever go up
+    let after = scrape(metrics_addr);
+    for name in &[
+        "veloren_tick_duration_seconds_count",
+        "veloren_net_sent_bytes_total",
+        "veloren_net_received_bytes_total",
+    ] {
+        assert!(after[*name] > before[*name], "{} didn't grow", name);
+    }
+}
+
+const SWORD_RECIPE: &str = r#"
+[[recipe]]
+id = "sword"
+inputs = [{ kind = "Stone", amount = 2 }, { kind = "Wood", amount = 1 }]
+output = { kind = "Sword", amount = 1 }
+station = "log"
+"#;
+
+#[test]
+fn crafting_needs_a_station_nearby_and_takes_nothing_when_it_fails() {
+    let (server, addr) = server();
+    let (crafter, player) = connect_far_away(&server, addr, "crafter");
+    let chunk = voxabs_to_voloffs(far_away_block(), CHUNK_SIZE);
+    server.do_for_mut(|srv| {
+        srv.recipes = RecipeBook::parse(SWORD_RECIPE).unwrap();
+        srv.world
+            .write_resource::<LoadedChunks>()
+            .0
+            .insert(chunk, Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)));
+        let mut inv = Inventory::new();
+        inv.insert(Item::new(ItemKind::Stone, 4));
+        inv.insert(Item::new(ItemKind::Wood, 1));
+        srv.update_comp(player, inv);
+    });
+    let set_log = |offset| {
+        server.do_for_mut(|srv| {
+            let _ = srv.world.write_resource::<LoadedChunks>().set_block(far_away_block() + offset, Block::LOG);
+        })
+    };
+    let craft = |count| {
+        server.do_for_mut(|srv| srv.handle_craft(player, "sword".to_string(), count));
+        await_msg(&crafter, |msg| match msg {
+            ServerMsg::CraftResult { result, .. } => Some(result),
+            _ => None,
+        })
+    };
+    let inv = || server.do_for(|srv| srv.world.read_storage::<Inventory>().get(player).unwrap().clone());
+    let before = inv();
+
+    // A log too far away doesn't count
+    set_log(Vec3::new(6, 0, 0));
+    assert_eq!(craft(1), Err(CraftError::NoStation));
+    assert_eq!(inv(), before);
+
+    // With one close enough, two swords still take more wood than there is, so neither is made
+    set_log(Vec3::new(3, 0, 0));
+    assert_eq!(craft(2), Err(CraftError::MissingIngredients));
+    assert_eq!(inv(), before);
+
+    assert_eq!(craft(1), Ok(Item::new(ItemKind::Sword, 1)));
+    let inv = inv();
+    assert_eq!(inv.count(ItemKind::Stone), 2);
+    assert_eq!(inv.count(ItemKind::Wood), 0);
+    assert_eq!(inv.count(ItemKind::Sword), 1);
+}
+
+#[test]
+fn unknown_recipes_cant_be_crafted() {
+    let (server, addr) = server();
+    let (crafter, player) = connect_far_away(&server, addr, "crafter");
+    server.do_for_mut(|srv| srv.handle_craft(player, "philosophers_stone".to_string(), 1));
+    let result = await_msg(&crafter, |msg| match msg {
+        ServerMsg::CraftResult { recipe, result } => Some((recipe, result)),
+        _ => None,
+    });
+    assert_eq!(result, ("philosophers_stone".to_string(), Err(CraftError::UnknownRecipe)));
+}
+
+#[test]
+fn bad_recipe_files_say_where_the_problem_is() {
+    assert_eq!(RecipeBook::parse(SWORD_RECIPE).unwrap().recipes().len(), 1);
+    assert!(RecipeBook::parse("").unwrap().recipes().is_empty());
+
+    // The same recipe twice points at the second one
+    let err = RecipeBook::parse(&format!("{}{}", SWORD_RECIPE, SWORD_RECIPE)).unwrap_err();
+    assert_eq!(err, "line 9: recipe `sword` is invalid: another recipe has the same id");
+
+    let nothing_in = "[[recipe]]\nid = \"air\"\ninputs = []\noutput = { kind = \"Stone\", amount = 1 }\n";
+    assert_eq!(
+        RecipeBook::parse(nothing_in).unwrap_err(),
+        "line 2: recipe `air` is invalid: it takes nothing to make"
+    );
+
+    // Syntax errors keep the parser's own line numbers
+    let err = RecipeBook::parse("[[recipe]]\nid = \"sword\"\ninputs = [\n").unwrap_err();
+    assert!(err.contains("line"), "{}", err);
+}
+
+#[test]
+fn payloads_can_protect_blocks() {
+    let payloads = ProtectedPayloads::default();
+    let asked = payloads.asked.clone();
+    let server = Server::new(payloads, "127.0.0.1:0").unwrap();
+    let addr = server.do_for(|srv| srv.local_addr()).unwrap();
+    let (builder, player) = connect_far_away(&server, addr, "builder");
+    server.do_for_mut(|srv| {
+        srv.world.write_resource::<LoadedChunks>().0.insert(
+            voxabs_to_voloffs(far_away_block(), CHUNK_SIZE),
+            Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR)),
+        );
+    });
+    let place = |pos| server.do_for_mut(|srv| srv.handle_set_block(player, pos, Block::STONE));
+    let block_at = |pos| server.do_for(|srv| srv.world.read_resource::<LoadedChunks>().block_at(pos));
+    let (open, protected) = (far_away_block() + Vec3::new(-2, 0, 0), far_away_block() + Vec3::new(3, 0, 0));
+
+    place(open);
+    wait_until(|| block_at(open) == Ok(Block::STONE));
+
+    // The builder's client will have put the block down already, so it's told to take it away again
+    place(protected);
+    let corrected = await_msg(&builder, |msg| match msg {
+        ServerMsg::BlockUpdate { pos, block } if pos == protected => Some(block),
+        _ => None,
+    });
+    assert_eq!(corrected, Block::AIR);
+    assert_eq!(
+        asked.lock().last().cloned(),
+        Some((protected, Block::AIR, Block::STONE, BlockChangeCause::Placed(player)))
+    );
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(block_at(protected), Ok(Block::AIR));
+}
+
+#[test]
+fn payloads_can_add_to_generated_chunks() {
+    let server = Server::new(ProtectedPayloads::default(), "127.0.0.1:0").unwrap();
+    let spawn = server.do_for(|srv| srv.spawn_point()).map(|e| e.floor() as VoxAbs);
+    let chunk = voxabs_to_voloffs(spawn, CHUNK_SIZE);
+    wait_until(|| server.do_for(|srv| srv.is_chunk_loaded(chunk)));
+
+    let corner = voloffs_to_voxabs(chunk, CHUNK_SIZE);
+    assert_eq!(
+        server.do_for(|srv| srv.world.read_resource::<LoadedChunks>().block_at(corner)),
+        Ok(Block::GOLD)
+    );
+}
+
+// A chunk of air with an 8 by 8 plateau of earth on its floor, with grass on one corner
+fn plateau() -> Chunk {
+    let mut chunk = Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR));
+    for x in 0..8 {
+        for y in 0..8 {
+            let _ = chunk.set(Vec3::new(x, y, 0), Block::EARTH);
+        }
+    }
+    let _ = chunk.set(Vec3::zero(), Block::GRASS);
+    chunk
+}
+
+fn grass_on_plateau(chunks: &LoadedChunks, chunk: Vec3<VolOffs>) -> usize {
+    let corner = voloffs_to_voxabs(chunk, CHUNK_SIZE);
+    (0..64)
+        .filter(|i| chunks.block_at(corner + Vec3::new(*i % 8, *i / 8, 0)) == Ok(Block::GRASS))
+        .count()
+}
+
+// Tick a plateau until it's grown over, returning how many ticks it took
+fn grow_over(ticks: &BlockTicks, chunks: &mut LoadedChunks, chunk: Vec3<VolOffs>) -> u64 {
+    for tick in 0..300 {
+        if grass_on_plateau(chunks, chunk) == 64 {
+            return tick;
+        }
+        // Grass can only spread a block a tick, so the far corner takes at least 7 ticks to reach
+        if tick < 7 {
+            assert_eq!(chunks.block_at(voloffs_to_voxabs(chunk, CHUNK_SIZE) + Vec3::new(7, 7, 0)), Ok(Block::EARTH));
+        }
+        for (pos, block) in ticks.tick_chunk(chunks, chunk, tick) {
+            chunks.set_block(pos, block).unwrap();
+        }
+    }
+    panic!("The plateau didn't grow over");
+}
+
+#[test]
+fn grass_spreads_over_earth_under_the_sky() {
+    let chunk = Vec3::new(3, -2, 1);
+    let ticks = fast_ticks();
+    let mut chunks = LoadedChunks::default();
+    chunks.0.insert(chunk, plateau());
+    let took = grow_over(&ticks, &mut chunks, chunk);
+
+    // The same seed picks the same blocks, so it goes the same way again
+    let mut again = LoadedChunks::default();
+    again.0.insert(chunk, plateau());
+    assert_eq!(grow_over(&ticks, &mut again, chunk), took);
+
+    // Covered grass dies back to earth
+    let covered = voloffs_to_voxabs(chunk, CHUNK_SIZE) + Vec3::new(3, 3, 0);
+    chunks.set_block(covered + Vec3::unit_z(), Block::STONE).unwrap();
+    let mut tick = 0;
+    while chunks.block_at(covered) == Ok(Block::GRASS) {
+        assert!(tick < 300, "The covered grass didn't die back");
+        for (pos, block) in ticks.tick_chunk(&chunks, chunk, tick) {
+            chunks.set_block(pos, block).unwrap();
+        }
+        tick += 1;
+    }
+    assert_eq!(chunks.block_at(covered), Ok(Block::EARTH));
+
+    // Grass only grows where it's been asked to
+    let mut ticks = BlockTicks::default();
+    ticks.blocks_per_chunk = 4096;
+    let mut bare = LoadedChunks::default();
+    bare.0.insert(chunk, plateau());
+    assert!((0..10).all(|tick| ticks.tick_chunk(&bare, chunk, tick).is_empty()));
+}
+
+#[test]
+fn blocks_only_tick_in_chunks_players_have() {
+    let server = Server::new(TickingPayloads, "127.0.0.1:0").unwrap();
+    let addr = server.do_for(|srv| srv.local_addr()).unwrap();
+    let (po, player) = connect_far_away(&server, addr, "gardener");
+
+    let near = voxabs_to_voloffs(far_away_block(), CHUNK_SIZE) + Vec3::new(0, 0, 4);
+    let far = near + Vec3::new(10, 0, 0);
+    server.do_for_mut(|srv| {
+        let mut chunks = srv.world.write_resource::<LoadedChunks>();
+        chunks.0.insert(near, plateau());
+        chunks.0.insert(far, plateau());
+        srv.world
+            .write_storage::<Client>()
+            .get_mut(player)
+            .unwrap()
+            .known_chunks
+            .insert(near, 0);
+    });
+
+    // The player is told about the grass growing on the chunk they have
+    let corner = voloffs_to_voxabs(near, CHUNK_SIZE);
+    await_msg(&po, |msg| match msg {
+        ServerMsg::BlockUpdate { pos, block } if block == Block::GRASS && pos.z == corner.z => Some(()),
+        _ => None,
+    });
+    let grass = |chunk| server.do_for(|srv| grass_on_plateau(&srv.world.read_resource::<LoadedChunks>(), chunk));
+    wait_until(|| grass(near) == 64);
+    assert_eq!(grass(far), 1);
+}
@@ -1,20 +1,179 @@
+// Standard
+use std::{collections::HashMap, mem, time::Duration};
+
+// Library
+use specs::{saveload::MarkerAllocator, Dispatcher, Join};
+use vek::*;
+
+// Project
+use common::{
+    ecs::net::{UidMarker, UidNode},
+    terrain::{
+        chunk::{Block, Chunk, CHUNK_SIZE},
+        voxabs_to_voloffs, VolCluster, VolOffs, VoxAbs, WriteVolume,
+    },
+    util::msg::ServerMsg,
+};
+
 // Local
-use crate::{Payloads, Server};
+use crate::{
+    api::Api,
+    net::Client,
+    schedule::run_due,
+    sys::{ChunkVersions, DeltaTime, LoadedChunks, Outbox, Target},
+    Payloads, Server,
+};
 
-use std::time::Duration;
+// Constants
+// Edits to more blocks of a chunk than this in one tick are sent as the whole chunk
+const MAX_BLOCK_UPDATES: usize = 16;
 
 // Server
 
 impl<P: Payloads> Server<P> {
-    pub fn tick_once(&mut self, _dt: Duration) {
-        // Sync entities with connected players
-        self.sync_players();
+    pub fn tick_once(&mut self, dispatcher: &mut Dispatcher, dt: Duration) {
+        // Run the tasks payloads scheduled for this tick, so that whatever they ask of the `Api` is done this tick too
+        let api: &dyn Api = self;
+        run_due(&self.scheduler, dt, |f| f(api), |f| f(api));
+
+        // Collect freshly generated chunks, unless the world border has moved past them since they were requested.
+        // The payload gets to add to each one before anyone sees it.
+        for (pos, mut chunk) in self.chunk_gen.poll() {
+            let border = self.world_border();
+            if border.is_chunk_outside(pos, CHUNK_SIZE) {
+                self.store_chunk(pos, border.barrier_chunk(CHUNK_SIZE));
+            } else {
+                self.payload.on_chunk_generated(self, pos, &mut chunk);
+                self.store_chunk(pos, chunk);
+            }
+        }
+
+        // Move entities whose destination has loaded
+        self.apply_teleports();
+        self.expire_loading();
+
+        // Carry out what was asked for through the `Api` since the last tick, along with what ticking blocks changed
+        self.tick_blocks();
+        self.apply_block_changes();
+        for (player, reason) in mem::replace(self.disconnects.get_mut(), vec![]) {
+            if self.world.is_alive(player) {
+                self.disconnect_player(player, reason);
+            }
+        }
+
+        // Populate the world around players, and move their weather on
+        self.update_spawns(dt);
+        self.update_weather(dt);
+
+        // Run the systems, then send whatever they have for clients
+        self.world.write_resource::<DeltaTime>().0 = dt;
+        dispatcher.dispatch(&self.world.res);
+        self.flush_outbox();
 
         self.world.maintain();
+        // Free the uids of entities deleted this tick
+        self.world
+            .write_resource::<UidNode>()
+            .maintain(&self.world.entities(), &self.world.read_storage::<UidMarker>());
+    }
+
+    /// Put a whole chunk in place, sending it to the clients that already have an older version of it
+    pub(crate) fn store_chunk(&mut self, pos: Vec3<VolOffs>, mut chunk: Chunk) {
+        let mut clients = self.world.write_storage::<Client>();
+        let entities = self.world.entities();
+        let outbox = self.world.read_resource::<Outbox>();
+
+        let knowing = (&entities, &mut clients)
+            .join()
+            .filter(|(_, client)| client.known_chunks.contains_key(&pos))
+            .collect::<Vec<_>>();
+        if !knowing.is_empty() {
+            let version = self.world.write_resource::<ChunkVersions>().bump(pos);
+            match chunk.to_bytes() {
+                Ok(data) => {
+                    for (entity, client) in knowing {
+                        client.known_chunks.insert(pos, version);
+                        outbox.send(
+                            Target::Client(entity),
+                            ServerMsg::ChunkData {
+                                pos,
+                                data: data.clone(),
+                            },
+                        );
+                    }
+                },
+                Err(e) => warn!("Couldn't serialize chunk {} to send it: {:?}", pos, e),
+            }
+        }
+        self.world.write_resource::<LoadedChunks>().0.insert(pos, chunk);
+    }
+
+    /// Change blocks as asked for through the `Api`, letting clients that have a changed chunk know what changed in it.
+    /// Clients that don't have the chunk get the changed one when they ask for it.
+    fn apply_block_changes(&mut self) {
+        let mut chunks = self.world.write_resource::<LoadedChunks>();
+        let mut versions = self.world.write_resource::<ChunkVersions>();
+        let mut clients = self.world.write_storage::<Client>();
+        let entities = self.world.entities();
+        let outbox = self.world.read_resource::<Outbox>();
+
+        let mut changed = HashMap::<Vec3<VolOffs>, Vec<(Vec3<VoxAbs>, Block)>>::new();
+        for (pos, block) in mem::replace(self.block_changes.get_mut(), vec![]) {
+            match chunks.set_block(pos, block) {
+                Ok(()) => changed
+                    .entry(voxabs_to_voloffs(pos, CHUNK_SIZE))
+                    .or_insert_with(Vec::new)
+                    .push((pos, block)),
+                Err(_) => debug!("Dropping a change to block {} since its chunk isn't loaded", pos),
+            }
+        }
+
+        for (chunk, blocks) in changed {
+            // Changes that left every block as it was have nothing to tell anyone
+            if chunks.0.get_mut(&chunk).and_then(|c| c.take_dirty_region()).is_none() {
+                continue;
+            }
+            let version = versions.bump(chunk);
+            // A chunk that changed a lot is smaller to send whole than block by block
+            let data = if blocks.len() > MAX_BLOCK_UPDATES {
+                chunks.0.get_mut(&chunk).and_then(|c| c.to_bytes().ok())
+            } else {
+                None
+            };
+
+            for (entity, client) in (&entities, &mut clients).join() {
+                match client.known_chunks.get_mut(&chunk) {
+                    Some(known) => *known = version,
+                    None => continue,
+                }
+                match &data {
+                    Some(data) => outbox.send(
+                        Target::Client(entity),
+                        ServerMsg::ChunkData {
+                            pos: chunk,
+                            data: data.clone(),
+                        },
+                    ),
+                    None => {
+                        for (pos, block) in blocks.iter() {
+                            outbox.send(
+                                Target::Client(entity),
+                                ServerMsg::BlockUpdate {
+                                    pos: *pos,
+                                    block: *block,
+                                },
+                            );
+                        }
+                    },
+                }
+            }
+        }
     }
 
-    pub fn tick_time(&mut self) {
-        // Sync entities with current time
-        self.sync_player_time();
+    /// Bring the gauges in the server's metrics up to date
+    pub(crate) fn update_metrics(&self) {
+        self.metrics.set_players(self.world.read_storage::<Client>().join().count());
+        self.metrics.set_loaded_chunks(self.world.read_resource::<LoadedChunks>().0.len());
+        self.metrics.set_entities(self.world.entities().join().count());
     }
 }
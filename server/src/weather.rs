@@ -0,0 +1,333 @@
+// Standard
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+// Library
+use rand;
+use specs::{Entity, Join};
+use vek::*;
+
+// Project
+use common::{
+    ecs::phys::Pos,
+    util::msg::ServerMsg,
+    weather::{weather_region, Weather, WEATHER_REGION_SIZE},
+};
+
+// Local
+use crate::{
+    net::Client,
+    sys::{Outbox, Target},
+    world_crate::Climate,
+    Payloads, Server,
+};
+
+// Constants
+// How long each spell of weather lasts before the next is picked, in seconds
+const MIN_SPELL: f32 = 120.0;
+const MAX_SPELL: f32 = 480.0;
+// How fast weather sets in and clears up, in intensity per second. Storms take most of a minute to build.
+const RAMP_RATE: f32 = 0.02;
+// Where it's colder than this, snow falls instead of rain. This is also where the ground turns snowy.
+const SNOW_TEMP: f32 = 0.35;
+// How often rain and snow come, from the driest regions to the wettest, and how much of the rain is stormy
+const DRY_PRECIPITATION: f32 = 0.15;
+const WET_PRECIPITATION: f32 = 0.65;
+const STORM_CHANCE: f32 = 0.3;
+// The fastest the wind blows, in blocks per second, and how much it changes by over a second
+const MAX_WIND: f32 = 16.0;
+const GUSTINESS: f32 = 1.0;
+// How likely the weather upwind is to blow in when the next weather is picked, with the wind at its fastest
+const MAX_CARRY: f32 = 0.75;
+// How often players are told the weather where they are, besides when they arrive somewhere new
+const WEATHER_SYNC_FREQ: Duration = Duration::from_secs(2);
+
+/// The weather over one region, and what it's turning into
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RegionWeather {
+    pub state: Weather,
+    /// How heavy the weather is right now, from 0 to 1
+    pub intensity: f32,
+    next: Weather,
+    // Seconds until the next weather is picked
+    until_change: f32,
+}
+
+impl RegionWeather {
+    // Clear skies, with the next weather to be picked straight away
+    fn new() -> Self {
+        Self {
+            state: Weather::Clear,
+            intensity: 0.0,
+            next: Weather::Clear,
+            until_change: 0.0,
+        }
+    }
+
+    // Ease towards the next weather. Whatever is falling has to stop before something else can start falling, but
+    // rain can build into a storm, or a storm die down into rain, without stopping.
+    fn ramp(&mut self, dt: f32) {
+        let can_change = self.next.precipitation() == self.state.precipitation() || self.intensity <= 0.0;
+        if self.next != self.state && can_change {
+            self.state = self.next;
+        }
+
+        let target = if self.state == self.next {
+            self.state.peak_intensity()
+        } else {
+            0.0
+        };
+        let step = RAMP_RATE * dt;
+        self.intensity = if self.intensity < target {
+            (self.intensity + step).min(target)
+        } else {
+            (self.intensity - step).max(target)
+        };
+    }
+}
+
+// The weather to move on to in a region with `climate`, with `upwind` being the weather the wind is blowing in from,
+// which blows in more often the faster the wind is. Otherwise rain and snow come more often the wetter the region is.
+// `roll` gives random numbers from 0 to 1.
+fn pick(climate: Climate, upwind: Option<Weather>, wind: f32, roll: &mut dyn FnMut() -> f32) -> Weather {
+    let carry = (wind / MAX_WIND).min(1.0) * MAX_CARRY;
+    let weather = match upwind {
+        Some(upwind) if roll() < carry => upwind,
+        _ => {
+            let wet = (1.0 - climate.dry).max(0.0).min(1.0);
+            if roll() >= DRY_PRECIPITATION + (WET_PRECIPITATION - DRY_PRECIPITATION) * wet {
+                Weather::Clear
+            } else if roll() < STORM_CHANCE {
+                Weather::Storm
+            } else {
+                Weather::Rain
+            }
+        },
+    };
+
+    // Whatever falls is snow where it's cold, and rain where it isn't
+    match (weather, climate.temp < SNOW_TEMP) {
+        (Weather::Rain, true) | (Weather::Storm, true) => Weather::Snow,
+        (Weather::Snow, false) => Weather::Rain,
+        (weather, _) => weather,
+    }
+}
+
+/// Weather for the regions around players. Each region has spells of weather that ease into one another, picked
+/// according to the region's climate and to the weather the wind blows in from the region upwind. Regions nobody is
+/// near are forgotten, and start out clear again when somebody comes back.
+pub(crate) struct WeatherSim {
+    regions: HashMap<Vec2<i32>, RegionWeather>,
+    /// Which way the wind blows and how fast, in blocks per second
+    wind: Vec2<f32>,
+    since_sync: Duration,
+    // The region each player was last told the weather of
+    told: HashMap<Entity, Vec2<i32>>,
+}
+
+impl WeatherSim {
+    pub(crate) fn new() -> Self {
+        Self {
+            regions: HashMap::new(),
+            wind: Vec2::zero(),
+            since_sync: Duration::default(),
+            told: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn region(&self, region: Vec2<i32>) -> Option<RegionWeather> { self.regions.get(&region).cloned() }
+
+    // Move the weather on by `dt` in the `active` regions, forgetting every other region. `climate` gives the climate
+    // of a region.
+    fn update(
+        &mut self,
+        dt: Duration,
+        active: &HashSet<Vec2<i32>>,
+        climate: &dyn Fn(Vec2<i32>) -> Climate,
+        roll: &mut dyn FnMut() -> f32,
+    ) {
+        let dt = dt.as_float_secs() as f32;
+
+        // The wind wanders about, a little each tick
+        let gust = Vec2::new(roll() - 0.5, roll() - 0.5) * 2.0 * GUSTINESS * dt.sqrt();
+        self.wind += gust;
+        if self.wind.magnitude() > MAX_WIND {
+            self.wind = self.wind.normalized() * MAX_WIND;
+        }
+
+        self.regions.retain(|region, _| active.contains(region));
+        for region in active {
+            self.regions.entry(*region).or_insert_with(RegionWeather::new);
+        }
+
+        // Weather blows in from whichever neighbour the wind is coming from, as it was before this update
+        let upwind_offset = self.wind.try_normalized().map(|dir| dir.map(|e| e.round() as i32));
+        let heading = self.regions.iter().map(|(r, w)| (*r, w.next)).collect::<HashMap<_, _>>();
+        let wind = self.wind.magnitude();
+        for (region, weather) in self.regions.iter_mut() {
+            weather.until_change -= dt;
+            if weather.until_change <= 0.0 {
+                let upwind = upwind_offset.and_then(|offset| heading.get(&(*region - offset)).cloned());
+                weather.next = pick(climate(*region), upwind, wind, roll);
+                weather.until_change = MIN_SPELL + roll() * (MAX_SPELL - MIN_SPELL);
+            }
+            weather.ramp(dt);
+        }
+    }
+}
+
+impl<P: Payloads> Server<P> {
+    /// Move the weather on around players, telling each player the weather where they are when they arrive in a new
+    /// region, and regularly while they stay
+    pub(crate) fn update_weather(&mut self, dt: Duration) {
+        let players = (
+            &self.world.entities(),
+            &self.world.read_storage::<Client>(),
+            &self.world.read_storage::<Pos>(),
+        )
+            .join()
+            .map(|(entity, _, pos)| (entity, weather_region(pos.0)))
+            .collect::<Vec<_>>();
+
+        // Regions next to players are kept going too, so that crossing into one doesn't start its weather over
+        let mut active = HashSet::new();
+        for (_, region) in &players {
+            for x in -1..=1 {
+                for y in -1..=1 {
+                    active.insert(*region + Vec2::new(x, y));
+                }
+            }
+        }
+
+        let generator = self.generator.clone();
+        let climate = |region: Vec2<i32>| {
+            let middle = region.map(|e| (e as i64 * 2 + 1) * WEATHER_REGION_SIZE as i64 / 2);
+            generator.climate(middle)
+        };
+        self.weather.update(dt, &active, &climate, &mut rand::random::<f32>);
+
+        self.weather.since_sync += dt;
+        let sync = self.weather.since_sync >= WEATHER_SYNC_FREQ;
+        if sync {
+            self.weather.since_sync = Duration::default();
+        }
+        let outbox = self.world.read_resource::<Outbox>();
+        let mut told = HashMap::with_capacity(players.len());
+        for (player, region) in players {
+            let weather = match self.weather.region(region) {
+                Some(weather) => weather,
+                None => continue,
+            };
+            if sync || self.weather.told.get(&player) != Some(&region) {
+                let msg = ServerMsg::WeatherUpdate {
+                    region,
+                    state: weather.state,
+                    intensity: weather.intensity,
+                };
+                outbox.send(Target::Client(player), msg);
+            }
+            told.insert(player, region);
+        }
+        self.weather.told = told;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MILD: Climate = Climate { temp: 0.6, dry: 0.5 };
+    const COLD: Climate = Climate { temp: 0.2, dry: 0.5 };
+
+    // Rolls the given numbers in turn, then 0.5 forever
+    fn rolls(rolls: &[f32]) -> impl FnMut() -> f32 {
+        let mut rolls = rolls.to_vec().into_iter();
+        move || rolls.next().unwrap_or(0.5)
+    }
+
+    // Ramps `weather` for `secs` seconds a tenth of a second at a time, checking it never changes suddenly
+    fn ramp_for(weather: &mut RegionWeather, secs: u32) {
+        for _ in 0..secs * 10 {
+            let before = weather.intensity;
+            weather.ramp(0.1);
+            assert!((weather.intensity - before).abs() <= RAMP_RATE * 0.1 + 0.0001);
+        }
+    }
+
+    #[test]
+    fn weather_eases_in_and_out() {
+        let mut weather = RegionWeather::new();
+        weather.next = Weather::Rain;
+        ramp_for(&mut weather, 10);
+        assert_eq!(weather.state, Weather::Rain);
+        assert!(weather.intensity > 0.1 && weather.intensity < 0.5);
+        ramp_for(&mut weather, 60);
+        assert!((weather.intensity - Weather::Rain.peak_intensity()).abs() < 0.0001);
+
+        // Rain builds into a storm without stopping first
+        weather.next = Weather::Storm;
+        ramp_for(&mut weather, 1);
+        assert_eq!(weather.state, Weather::Storm);
+        assert!(weather.intensity > Weather::Rain.peak_intensity());
+
+        // Snow only starts once the storm has died away
+        weather.next = Weather::Snow;
+        ramp_for(&mut weather, 10);
+        assert_eq!(weather.state, Weather::Storm);
+        ramp_for(&mut weather, 60);
+        assert_eq!(weather.state, Weather::Snow);
+        assert!(weather.intensity > 0.0);
+        ramp_for(&mut weather, 60);
+        assert!((weather.intensity - Weather::Snow.peak_intensity()).abs() < 0.0001);
+    }
+
+    #[test]
+    fn what_falls_depends_on_the_climate() {
+        // A low roll means rain, or snow where it's cold, and a high one a clear sky
+        assert_eq!(pick(MILD, None, 0.0, &mut rolls(&[0.1, 0.9])), Weather::Rain);
+        assert_eq!(pick(MILD, None, 0.0, &mut rolls(&[0.1, 0.1])), Weather::Storm);
+        assert_eq!(pick(COLD, None, 0.0, &mut rolls(&[0.1, 0.1])), Weather::Snow);
+        assert_eq!(pick(MILD, None, 0.0, &mut rolls(&[0.9])), Weather::Clear);
+
+        // It's clear more often where it's dry
+        let desert = Climate { temp: 0.9, dry: 1.0 };
+        let swamp = Climate { temp: 0.9, dry: 0.0 };
+        assert_eq!(pick(desert, None, 0.0, &mut rolls(&[0.3])), Weather::Clear);
+        assert_eq!(pick(swamp, None, 0.0, &mut rolls(&[0.3, 0.9])), Weather::Rain);
+    }
+
+    #[test]
+    fn weather_blows_in_on_the_wind() {
+        let upwind = Some(Weather::Storm);
+        assert_eq!(pick(MILD, upwind, MAX_WIND, &mut rolls(&[0.1])), Weather::Storm);
+        // The storm comes down as snow where it's cold
+        assert_eq!(pick(COLD, upwind, MAX_WIND, &mut rolls(&[0.1])), Weather::Snow);
+        // Without wind, the region's own weather is picked
+        assert_eq!(pick(MILD, upwind, 0.0, &mut rolls(&[0.1, 0.1, 0.9])), Weather::Rain);
+    }
+
+    #[test]
+    fn regions_are_only_kept_near_players() {
+        let mut sim = WeatherSim::new();
+        let (a, b) = (Vec2::new(0, 0), Vec2::new(5, -3));
+        let climate = |_: Vec2<i32>| MILD;
+        let dt = Duration::from_secs(1);
+
+        // Rain is picked for both regions, and starts to fall
+        let active = [a, b].iter().cloned().collect::<HashSet<_>>();
+        sim.update(dt, &active, &climate, &mut rolls(&[0.5, 0.5, 0.1, 0.9, 0.5, 0.1, 0.9]));
+        for region in &[a, b] {
+            let weather = sim.region(*region).unwrap();
+            assert_eq!(weather.state, Weather::Rain);
+            assert!(weather.intensity > 0.0);
+        }
+
+        // Once nobody's near, a region is forgotten
+        let active = [a].iter().cloned().collect::<HashSet<_>>();
+        sim.update(dt, &active, &climate, &mut rolls(&[]));
+        assert!(sim.region(a).is_some());
+        assert_eq!(sim.region(b), None);
+    }
+}
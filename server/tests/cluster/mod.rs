@@ -0,0 +1,197 @@
+// A server and a handful of headless clients, all running in the test process
+
+// Standard
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+// Library
+use parking_lot::Mutex;
+use vek::*;
+
+// Project
+use client::{Client, PlayMode};
+use common::{
+    audio::{AudioGen, Buffer, Stream},
+    ecs::{net::UidMarker, phys::Pos},
+    net::Encryption,
+    terrain::{
+        chunk::{Block, ChunkContainer},
+        Container, VolOffs, VoxAbs,
+    },
+};
+use server::{
+    api::Api,
+    net::{Client as ServerClient, DisconnectReason},
+    player::Player,
+    specs::{saveload::Marker, Entity, Join},
+    sys::LoadedChunks,
+    Manager, Server, Wrapper,
+};
+
+// Constants
+pub const TIMEOUT: Duration = Duration::from_secs(20);
+const POLL: Duration = Duration::from_millis(20);
+
+/// A payload hook the server called, with the alias of the player it was called for
+#[derive(Clone, Debug, PartialEq)]
+pub enum Hook {
+    Connected(String),
+    Disconnected(String),
+}
+
+pub struct TestPayloads {
+    hooks: Arc<Mutex<Vec<Hook>>>,
+}
+
+fn alias_of(api: &dyn Api, player: Entity) -> String {
+    api.world()
+        .read_storage::<Player>()
+        .get(player)
+        .map(|p| p.alias.clone())
+        .unwrap_or_default()
+}
+
+impl server::Payloads for TestPayloads {
+    type Chunk = ();
+    type Entity = ();
+    type Client = ();
+
+    fn on_player_connect(&self, api: &dyn Api, player: Entity) {
+        self.hooks.lock().push(Hook::Connected(alias_of(api, player)));
+    }
+
+    fn on_player_disconnect(&self, api: &dyn Api, player: Entity, _reason: DisconnectReason) {
+        self.hooks.lock().push(Hook::Disconnected(alias_of(api, player)));
+    }
+
+    // Players build with whatever blocks the tests need, and undoing puts back blocks no item places
+    fn free_building(&self) -> bool { true }
+}
+
+pub struct NoAudio;
+impl AudioGen for NoAudio {
+    fn gen_stream(&self, _id: u64, _buffer: &Buffer, _stream: &Stream) {}
+    fn update_stream(&self, _id: u64, _buffer: &Buffer, _stream: &Stream) {}
+    fn set_listener(&self, _pos: Vec3<f32>, _ori: Vec3<f32>) {}
+    fn gen_buffer(&self, _id: u64, _buffer: &Buffer) {}
+    fn drop_stream(&self, _id: u64, _buffer: &Buffer, _stream: &Stream) {}
+    fn drop_buffer(&self, _id: u64, _buffer: &Buffer) {}
+}
+
+pub struct TestClientPayloads;
+impl client::Payloads for TestClientPayloads {
+    type Chunk = ();
+    type Entity = ();
+    type Audio = NoAudio;
+}
+
+pub type TestClient = Manager<Client<TestClientPayloads>>;
+
+type TestChunk = ChunkContainer<<TestClientPayloads as client::Payloads>::Chunk>;
+
+// Clients wait for the chunks around their player to have payloads, even if there's nothing in them
+fn gen_payload(_key: Vec3<VolOffs>, con: &TestChunk, _neighbours: &HashMap<Vec3<VolOffs>, Arc<TestChunk>>) {
+    *con.payload_mut() = Some(());
+}
+
+fn drop_payload(_key: Vec3<VolOffs>, _con: Arc<TestChunk>) {}
+
+pub struct TestCluster {
+    // Fields are dropped in order, so the clients log out before the server shuts down. Dropping a `Manager` joins
+    // all of its threads, so once the cluster is gone nothing is left running.
+    pub clients: Vec<TestClient>,
+    pub server: Manager<Wrapper<Server<TestPayloads>>>,
+    pub addr: SocketAddr,
+    hooks: Arc<Mutex<Vec<Hook>>>,
+}
+
+impl TestCluster {
+    /// Start a server on a free localhost port and connect `n_clients` players to it, named `player0`, `player1`...
+    pub fn new(n_clients: usize) -> Self {
+        let hooks = Arc::new(Mutex::new(vec![]));
+        let server = Server::new(TestPayloads { hooks: hooks.clone() }, "127.0.0.1:0").expect("Could not start server");
+        let addr = server.do_for(|srv| srv.local_addr()).unwrap();
+
+        let mut cluster = Self {
+            clients: vec![],
+            server,
+            addr,
+            hooks,
+        };
+        for i in 0..n_clients {
+            let client = cluster.connect(&format!("player{}", i), PlayMode::Character);
+            cluster.clients.push(client);
+        }
+        cluster
+    }
+
+    /// Connect another client. It's up to the caller to keep it alive.
+    pub fn connect(&self, alias: &str, mode: PlayMode) -> TestClient {
+        Client::new(
+            mode,
+            alias.to_string(),
+            self.addr,
+            Encryption::Preferred,
+            gen_payload,
+            drop_payload,
+            Arc::new(NoAudio),
+            0,
+            None,
+        )
+        .expect("Could not connect to server")
+    }
+
+    /// Every payload hook the server has called so far
+    pub fn hooks(&self) -> Vec<Hook> { self.hooks.lock().clone() }
+
+    /// A block as the server has it, if its chunk is loaded
+    pub fn block_at(&self, pos: Vec3<VoxAbs>) -> Option<Block> {
+        self.server.do_for(|srv| srv.world().read_resource::<LoadedChunks>().block_at(pos).ok())
+    }
+
+    /// Where the server has the player with entity `uid`, and whether it's still waiting for them to load
+    pub fn player_state(&self, uid: u64) -> Option<(Vec3<f32>, bool)> {
+        self.server.do_for(|srv| {
+            let world = srv.world();
+            (
+                &world.read_storage::<UidMarker>(),
+                &world.read_storage::<Pos>(),
+                &world.read_storage::<ServerClient>(),
+            )
+                .join()
+                .find(|(marker, _, _)| marker.id() == uid)
+                .map(|(_, pos, client)| (pos.0, client.loading.is_some()))
+        })
+    }
+
+    /// The server's entity for the entity with `uid`
+    pub fn entity(&self, uid: u64) -> Option<Entity> {
+        self.server.do_for(|srv| {
+            let world = srv.world();
+            (&world.entities(), &world.read_storage::<UidMarker>())
+                .join()
+                .find(|(_, marker)| marker.id() == uid)
+                .map(|(entity, _)| entity)
+        })
+    }
+
+    /// Log a client out, waiting until its threads have finished
+    pub fn disconnect(&mut self, index: usize) { drop(self.clients.remove(index)); }
+}
+
+/// Poll `condition` until it holds, giving up after `timeout`. Returns whether it held.
+pub fn wait_for<F: FnMut() -> bool>(mut condition: F, timeout: Duration) -> bool {
+    let start = Instant::now();
+    while !condition() {
+        if start.elapsed() > timeout {
+            return false;
+        }
+        thread::sleep(POLL);
+    }
+    true
+}
@@ -0,0 +1,265 @@
+mod cluster;
+
+// Library
+use vek::*;
+
+// Project
+use client::{ClientEvent, PlayMode};
+use common::{
+    ecs::character::{Health, MAX_HEALTH},
+    terrain::{chunk::Block, VoxAbs},
+};
+use server::api::Api;
+
+// Local
+use crate::cluster::{wait_for, Hook, TestCluster, TIMEOUT};
+
+#[test]
+fn connecting_and_disconnecting_fire_hooks() {
+    let mut cluster = TestCluster::new(2);
+    assert!(wait_for(
+        || {
+            let hooks = cluster.hooks();
+            hooks.contains(&Hook::Connected("player0".into())) && hooks.contains(&Hook::Connected("player1".into()))
+        },
+        TIMEOUT
+    ));
+
+    cluster.disconnect(0);
+    assert!(wait_for(|| cluster.hooks().contains(&Hook::Disconnected("player0".into())), TIMEOUT));
+    assert!(!cluster.hooks().contains(&Hook::Disconnected("player1".into())));
+}
+
+#[test]
+fn chat_reaches_other_clients() {
+    let cluster = TestCluster::new(2);
+    let events = cluster.clients[1].subscribe();
+
+    cluster.clients[0].send_chat_msg("hello there".into());
+    assert!(wait_for(
+        || events.drain().into_iter().any(|event| match event {
+            ClientEvent::ChatReceived { text } => text.contains("player0") && text.contains("hello there"),
+            _ => false,
+        }),
+        TIMEOUT
+    ));
+}
+
+#[test]
+fn positions_propagate_between_clients() {
+    let cluster = TestCluster::new(2);
+    let (mover, watcher) = (&cluster.clients[0], &cluster.clients[1]);
+
+    let uid = mover.player().entity_uid.expect("Player has no entity");
+    assert!(wait_for(|| mover.player_entity().is_some(), TIMEOUT));
+    let target = Vec2::from(*mover.player_entity().unwrap().read().pos()) + Vec2::new(2.0, -3.0);
+
+    // The mover's own physics keeps running, so keep putting it back in place. Only x and y are compared, since
+    // it may be falling.
+    assert!(wait_for(
+        || {
+            if let Some(entity) = mover.player_entity() {
+                let mut entity = entity.write();
+                entity.pos_mut().x = target.x;
+                entity.pos_mut().y = target.y;
+                *entity.vel_mut() = Vec3::zero();
+            }
+            watcher
+                .entity(uid)
+                .map(|entity| Vec2::from(*entity.read().pos()).distance(target) < 0.5)
+                .unwrap_or(false)
+        },
+        TIMEOUT
+    ));
+}
+
+#[test]
+fn look_directions_propagate_between_clients() {
+    let cluster = TestCluster::new(2);
+    let (turner, watcher) = (&cluster.clients[0], &cluster.clients[1]);
+
+    let uid = turner.player().entity_uid.expect("Player has no entity");
+    let target = Vec2::new(2.0, 0.25);
+    assert!(wait_for(
+        || {
+            if let Some(entity) = turner.player_entity() {
+                *entity.write().look_dir_mut() = target;
+            }
+            watcher
+                .entity(uid)
+                .map(|entity| entity.read().look_dir().distance(target) < 0.01)
+                .unwrap_or(false)
+        },
+        TIMEOUT
+    ));
+}
+
+#[test]
+fn players_see_each_others_names() {
+    let cluster = TestCluster::new(2);
+    let uid = cluster.clients[0].player().entity_uid.expect("Player has no entity");
+    assert!(wait_for(
+        || cluster.clients[1]
+            .entity(uid)
+            .map(|entity| entity.read().name() == &Some("player0".to_string()))
+            .unwrap_or(false),
+        TIMEOUT
+    ));
+}
+
+#[test]
+fn disconnected_players_vanish_for_everyone_else() {
+    let mut cluster = TestCluster::new(2);
+    let uid = cluster.clients[0].player().entity_uid.expect("Player has no entity");
+    let events = cluster.clients[1].subscribe();
+    assert!(wait_for(|| cluster.clients[1].entity(uid).is_some(), TIMEOUT));
+
+    cluster.disconnect(0);
+    let watcher = &cluster.clients[0];
+    assert!(wait_for(|| watcher.entity(uid).is_none(), TIMEOUT));
+    assert!(events.drain().into_iter().any(|event| match event {
+        ClientEvent::EntityRemoved { uid: removed } => removed == uid,
+        _ => false,
+    }));
+
+    // Only the player that left is gone
+    let own_uid = watcher.player().entity_uid.expect("Player has no entity");
+    assert!(watcher.entity(own_uid).is_some());
+}
+
+#[test]
+fn block_edits_can_be_undone_and_redone() {
+    let cluster = TestCluster::new(2);
+    let (builder, other) = (&cluster.clients[0], &cluster.clients[1]);
+
+    // A block next to the builder, once both players have its chunk
+    assert!(wait_for(|| builder.player_entity().is_some(), TIMEOUT));
+    let feet = builder.player_entity().unwrap().read().pos().map(|e| e.floor() as VoxAbs);
+    let pos = feet + Vec3::new(2, 0, 1);
+    assert!(wait_for(
+        || builder.chunk_mgr().get_block(pos).is_some() && other.chunk_mgr().get_block(pos).is_some(),
+        TIMEOUT
+    ));
+    let original = cluster.block_at(pos).expect("The server doesn't have the block's chunk");
+    let placed = if original == Block::STONE { Block::GRASS } else { Block::STONE };
+    // Both the server and the builder's client end up with `block`
+    let settled = |block| {
+        wait_for(
+            || cluster.block_at(pos) == Some(block) && builder.chunk_mgr().get_block(pos) == Some(block),
+            TIMEOUT,
+        )
+    };
+
+    assert!(builder.set_block(pos, placed));
+    assert!(settled(placed));
+    assert!(builder.undo_last_edit());
+    assert!(settled(original));
+    assert!(builder.redo_last_edit());
+    assert!(settled(placed));
+    assert!(!builder.redo_last_edit());
+
+    // Once someone else has changed the block, undoing leaves their change be, and forgets the edit
+    assert!(other.set_block(pos, Block::SAND));
+    assert!(settled(Block::SAND));
+    assert!(!builder.undo_last_edit());
+    assert_eq!(cluster.block_at(pos), Some(Block::SAND));
+    assert!(!builder.undo_last_edit());
+}
+
+#[test]
+fn players_dont_fall_before_the_terrain_loads() {
+    let cluster = TestCluster::new(1);
+    let client = &cluster.clients[0];
+    let uid = client.player().entity_uid.expect("Player has no entity");
+
+    // Neither the server nor the client may move the player until the client says it's ready
+    let (mut server_z, mut client_z) = (None, None);
+    assert!(wait_for(
+        || match cluster.player_state(uid) {
+            Some((pos, true)) => {
+                assert!(pos.z >= *server_z.get_or_insert(pos.z), "The server moved a loading player");
+                // The client only knows where it is once the server has told it
+                if let Some(own) = client.player_entity().map(|e| *e.read().pos()) {
+                    if client.loading().is_some() && own.distance(pos) < 0.5 {
+                        assert!(own.z >= *client_z.get_or_insert(own.z), "A loading player fell");
+                    }
+                }
+                false
+            },
+            Some((_, false)) => true,
+            None => false,
+        },
+        TIMEOUT
+    ));
+    assert!(client.loading().is_none());
+}
+
+#[test]
+fn clients_get_the_terrain_around_them_from_the_server() {
+    let cluster = TestCluster::new(1);
+    let client = &cluster.clients[0];
+    assert!(wait_for(|| client.loading().is_none(), TIMEOUT));
+
+    // The column the player stands in, and the ground beneath, is the same as the server's
+    let pos = client.player_entity().unwrap().read().pos().map(|e| e.floor() as VoxAbs);
+    for z in -8..8 {
+        let block = pos + Vec3::new(0, 0, z);
+        assert_eq!(client.chunk_mgr().get_block(block), cluster.block_at(block), "{} differs", block);
+    }
+    assert!(client.chunk_mgr().get_block(pos - Vec3::unit_z() * 8).is_some());
+}
+
+#[test]
+fn physics_changes_reach_clients_without_reconnecting() {
+    let cluster = TestCluster::new(1);
+    let mut physics = cluster.server.do_for(|srv| srv.physics());
+    assert_eq!(cluster.clients[0].physics(), physics);
+
+    physics.set("gravity", &[-50.0]).unwrap();
+    physics.set("jump_height", &[3.0]).unwrap();
+    cluster.server.do_for_mut(|srv| srv.set_physics(physics));
+    assert!(wait_for(|| cluster.clients[0].physics() == physics, TIMEOUT));
+
+    // ...and players joining later are told about it when they connect
+    let latecomer = cluster.connect("latecomer", PlayMode::Character);
+    assert_eq!(latecomer.physics(), physics);
+}
+
+#[test]
+fn dead_players_stay_down_until_they_respawn() {
+    let cluster = TestCluster::new(1);
+    let client = &cluster.clients[0];
+    let events = client.subscribe();
+    let uid = client.player().entity_uid.expect("Player has no entity");
+    let player = cluster.entity(uid).expect("The server has no entity for the player");
+    assert!(wait_for(|| client.loading().is_none(), TIMEOUT));
+
+    assert!(!cluster.server.do_for_mut(|srv| srv.damage(player, MAX_HEALTH / 2, "Stubbed a toe")));
+    assert!(cluster.server.do_for_mut(|srv| srv.damage(player, MAX_HEALTH, "Crushed by an anvil")));
+    assert!(wait_for(|| client.is_dead(), TIMEOUT));
+    assert!(events.drain().into_iter().any(|event| match event {
+        ClientEvent::Died { cause } => cause == "Crushed by an anvil",
+        _ => false,
+    }));
+
+    // Being moved about by the server doesn't bring them back
+    let spawn = cluster.server.do_for(|srv| srv.spawn_point());
+    let elsewhere = spawn + Vec3::new(8.0, 0.0, 0.0);
+    cluster.server.do_for_mut(|srv| srv.set_entity_pos(player, elsewhere));
+    assert!(wait_for(
+        || Vec2::from(*client.player_entity().unwrap().read().pos()).distance(Vec2::from(elsewhere)) < 0.5,
+        TIMEOUT
+    ));
+    assert!(client.is_dead());
+
+    client.respawn();
+    assert!(wait_for(|| !client.is_dead(), TIMEOUT));
+    assert!(events.drain().into_iter().any(|event| match event {
+        ClientEvent::Respawned => true,
+        _ => false,
+    }));
+    let pos = *client.player_entity().unwrap().read().pos();
+    assert!(Vec2::from(pos).distance(Vec2::from(spawn)) < 0.5);
+    let health = cluster.server.do_for(|srv| srv.world().read_storage::<Health>().get(player).map(|h| h.0));
+    assert_eq!(health, Some(MAX_HEALTH));
+}
@@ -0,0 +1,34 @@
+#version 330 core
+
+in vec3 frag_pos;
+
+layout (std140)
+uniform global_consts {
+	mat4 view_mat;
+	mat4 proj_mat;
+	vec4 cam_origin;
+	vec4 play_origin;
+	vec4 view_distance;
+	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
+};
+
+out vec4 target;
+
+// How far from the player the wall fades out completely
+const float FADE_DIST = 24.0;
+const float STRIPE_WIDTH = 2.0;
+const float STRIPE_SPEED = 0.5;
+
+void main() {
+	float fade = 1.0 - smoothstep(0.0, FADE_DIST, distance(frag_pos.xy, play_origin.xy));
+
+	// Diagonal stripes that drift upwards, so the wall reads as a wall rather than as fog
+	vec3 world_pos = frag_pos + render_origin.xyz;
+	float stripe = fract((world_pos.x + world_pos.y + world_pos.z) / STRIPE_WIDTH - time.x * STRIPE_SPEED);
+	float alpha = mix(0.15, 0.45, step(0.5, stripe));
+
+	target = vec4(0.4, 0.7, 1.0, alpha * fade);
+}
@@ -0,0 +1,28 @@
+#version 330 core
+
+in vec3 vert_pos;
+
+layout (std140)
+uniform model_consts {
+	mat4 model_mat;
+};
+
+layout (std140)
+uniform global_consts {
+	mat4 view_mat;
+	mat4 proj_mat;
+	vec4 cam_origin;
+	vec4 play_origin;
+	vec4 view_distance;
+	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
+};
+
+out vec3 frag_pos;
+
+void main() {
+	frag_pos = (model_mat * vec4(vert_pos, 1)).xyz;
+	gl_Position = proj_mat * view_mat * vec4(frag_pos, 1);
+}
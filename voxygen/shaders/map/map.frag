@@ -0,0 +1,42 @@
+#version 330 core
+
+#include <luts.glsl>
+
+in vec2 frag_uv;
+in vec2 frag_local;
+in vec4 frag_col;
+
+uniform sampler2D t_Map;
+
+out vec4 target;
+
+// Higher ground is drawn lighter
+const float LOW_SHADE = 0.5;
+const float HIGH_SHADE = 1.3;
+const vec4 RIM_COLOR = vec4(0.0, 0.0, 0.0, 0.7);
+const float RIM_WIDTH = 0.03;
+
+void main() {
+	// The map is round, with a rim around it
+	float dist = length(frag_local);
+	if (dist > 1.0) {
+		discard;
+	}
+	if (dist > 1.0 - RIM_WIDTH) {
+		target = RIM_COLOR;
+		return;
+	}
+
+	// Fog and the player marker have no tile, and are drawn in their own colour. So are columns without anything in
+	// them, which are fogged over.
+	vec4 texel = frag_uv.x < 0.0 ? vec4(0.0) : texture(t_Map, frag_uv);
+	if (texel.a == 0.0) {
+		target = frag_col;
+		return;
+	}
+
+	// The palette attribute of the block at the top of the column, and half its height
+	uint attr = (uint(round(texel.g * 255.0)) << 8) | uint(round(texel.r * 255.0));
+	float shade = mix(LOW_SHADE, HIGH_SHADE, texel.b);
+	target = vec4(min(get_color_from_attr(attr).rgb * shade, vec3(1.0)), 1.0);
+}
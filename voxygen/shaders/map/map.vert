@@ -0,0 +1,18 @@
+#version 330 core
+
+in vec2 vert_pos;
+in vec2 vert_uv;
+in vec2 vert_local;
+in vec4 vert_col;
+
+out vec2 frag_uv;
+out vec2 frag_local;
+out vec4 frag_col;
+
+void main() {
+	frag_uv = vert_uv;
+	frag_local = vert_local;
+	frag_col = vert_col;
+	// Positions go from (0, 0) at the top left of the screen to (1, 1) at the bottom right, as in the rest of the UI
+	gl_Position = vec4(vec2(2.0, -2.0) * vert_pos + vec2(-1.0, 1.0), 0.0, 1.0);
+}
@@ -0,0 +1,7 @@
+#version 330 core
+
+out vec4 target;
+
+void main() {
+	target = vec4(0.0, 0.0, 0.0, 0.6);
+}
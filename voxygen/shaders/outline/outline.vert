@@ -0,0 +1,29 @@
+#version 330 core
+
+in vec3 vert_pos;
+
+layout (std140)
+uniform model_consts {
+	mat4 model_mat;
+};
+
+layout (std140)
+uniform global_consts {
+	mat4 view_mat;
+	mat4 proj_mat;
+	vec4 cam_origin;
+	vec4 play_origin;
+	vec4 view_distance;
+	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
+};
+
+// Pulls the outline slightly towards the camera so it doesn't z-fight with the block's faces
+const float DEPTH_BIAS = 0.0002;
+
+void main() {
+	gl_Position = proj_mat * view_mat * model_mat * vec4(vert_pos, 1);
+	gl_Position.z -= DEPTH_BIAS * gl_Position.w;
+}
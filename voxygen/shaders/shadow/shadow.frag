@@ -0,0 +1,12 @@
+#version 330 core
+
+in vec2 frag_offs;
+in float frag_strength;
+
+out vec4 target;
+
+void main() {
+	// Darkest in the middle, fading out to nothing at the edge
+	float dist = length(frag_offs);
+	target = vec4(0.0, 0.0, 0.0, frag_strength * (1.0 - smoothstep(0.3, 1.0, dist)));
+}
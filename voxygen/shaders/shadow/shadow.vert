@@ -0,0 +1,31 @@
+#version 330 core
+
+in vec3 vert_pos;
+in vec2 vert_offs;
+in float vert_strength;
+
+layout (std140)
+uniform global_consts {
+	mat4 view_mat;
+	mat4 proj_mat;
+	vec4 cam_origin;
+	vec4 play_origin;
+	vec4 view_distance;
+	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
+};
+
+out vec2 frag_offs;
+out float frag_strength;
+
+// Pulls shadows slightly towards the camera so they don't z-fight with the ground
+const float DEPTH_BIAS = 0.0002;
+
+void main() {
+	frag_offs = vert_offs;
+	frag_strength = vert_strength;
+	gl_Position = proj_mat * view_mat * vec4(vert_pos, 1);
+	gl_Position.z -= DEPTH_BIAS * gl_Position.w;
+}
@@ -13,12 +13,15 @@ uniform global_consts {
 	vec4 play_origin;
 	vec4 view_distance;
 	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
 };
 
 out vec4 target;
 
 void main() {
 	float tod = get_time_of_day(time.x);
-	target = vec4(get_skybox(normalize(frag_pos), tod), 1.0);
+	target = vec4(get_skybox(normalize(frag_pos), tod, weather.x), 1.0);
 	// target = vec4(vec3(0.5), 1.0);
 }
@@ -10,6 +10,9 @@ uniform global_consts {
 	vec4 play_origin;
 	vec4 view_distance;
 	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
 };
 
 out vec3 frag_pos;
@@ -17,5 +20,7 @@ out vec3 frag_pos;
 void main() {
 	frag_pos = vert_pos;
 
-	gl_Position = proj_mat * view_mat * vec4(3000 * vert_pos + cam_origin.xyz, 1);
+	// The sky is centred on the camera and forced onto the far plane (z = w), so it's never clipped by the near
+	// plane or by terrain at the edge of the view distance, even when the camera is inside terrain
+	gl_Position = (proj_mat * view_mat * vec4(vert_pos + cam_origin.xyz, 1)).xyww;
 }
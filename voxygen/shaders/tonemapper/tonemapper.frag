@@ -15,6 +15,9 @@ uniform global_consts {
 	vec4 play_origin;
 	vec4 view_distance;
 	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
 };
 
 // ACES fit by Stephen Hill (@self_shadow), adapted from the HLSL implementation
@@ -117,13 +120,9 @@ out vec4 target;
 void main() {
     vec3 hdrColor = texture(t_Hdr, uv.xy).rgb;
 
-    // exposure correction. Varies between F/16 at midday and F/2.8 at night.
+    // exposure correction
     float tod = get_time_of_day(time.x);
-    float day_part = saturate(cos(PI * tod));
-	float x = clamp(tod * 2.0 - 2.0, -1.0, 1.0);
-	float night_part = 1.0 - pow(max0(abs(x) * 2.0 - 1.0), 6.0);
-    float denom = 3.0 + (0.2 + 0.8 * day_part - 0.2 * night_part) * 60000.0;
-    float exposure = 1.0 / denom;
+    float exposure = 1.0 / get_exposure_range(tod);
     vec3 mapped = hdrColor * exposure;
 
     // tone map
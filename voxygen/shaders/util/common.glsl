@@ -16,3 +16,8 @@ float max0(float v) {
 vec3 max0(vec3 v) {
     return max(v, vec3(0.0));
 }
+
+// How bright a light level looks, from 0 to 1 for levels 0 to 15. Each level is a fifth dimmer than the one above it.
+float light_brightness(float level) {
+    return pow(0.8, 15.0 * (1.0 - level));
+}
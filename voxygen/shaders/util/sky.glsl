@@ -23,6 +23,14 @@ float day_anticycle(float c, float factor, float time) {
 	return cos(PI * c * time + PI) * factor + 1.0 - factor;
 }
 
+// The brightness that exposure maps to white. Varies between F/16 at midday and F/2.8 at night.
+float get_exposure_range(float time) {
+	float day_part = saturate(cos(PI * time));
+	float x = clamp(time * 2.0 - 2.0, -1.0, 1.0);
+	float night_part = 1.0 - pow(max0(abs(x) * 2.0 - 1.0), 6.0);
+	return 3.0 + (0.2 + 0.8 * day_part - 0.2 * night_part) * 60000.0;
+}
+
 vec3 get_sun_color(float time) {
 	return vec3(sunrise_cycle(1, 0.25, time) + 1.1, sunrise_anticycle(1, 0.2, time) + 0.2, sunrise_anticycle(1, 0.4, time) - 0.2);
 }
@@ -102,12 +110,32 @@ const float midnight_horiz_halo_strength = 0;
 // Universal
 const float horiz_halo_bloom = 6;
 
+////// Star params: //////
+const vec3 star_col = vec3(0.9, 0.95, 1.0);
+const float star_strength = 4.0;
+// Cells per unit of view direction, and the fraction of cells that contain a star
+const float star_density = 300.0;
+const float star_frequency = 0.0015;
+
+////// Storm params: //////
+// The colour the sky turns under heavy cloud, and how much of its light is lost
+const vec3 storm_col = vec3(0.55, 0.58, 0.62);
+const float storm_dimming = 0.75;
+// How much of the sun's light gets through the heaviest cloud
+const float storm_sun_through = 0.15;
+
 #define OUTPUT_GRADIENT
 #define OUTPUT_DISC
 #define OUTPUT_SUN_HALO
 #define OUTPUT_HORIZ_HALO
 
-vec3 get_sky(vec3 dir, float time, bool sun) {
+// How much of the sun's light gets through the cloud, given how gloomy the weather makes the sky (0 to 1)
+float get_sun_through_clouds(float gloom) {
+	return mix(1.0, storm_sun_through, saturate(gloom));
+}
+
+// The sky seen in direction `dir`, greyed and darkened by `gloom` (0 to 1), which comes from the weather
+vec3 get_sky(vec3 dir, float time, bool sun, float gloom) {
 	// Noon to sunset
 	float nts = saturate(time * 2.0);
 	// Sunset to midnight
@@ -162,6 +190,12 @@ vec3 get_sky(vec3 dir, float time, bool sun) {
 	// output_col += mid_col * mid_strength * omdb * omdb * ssdb;
 	#endif
 
+	// Cloud cover washes the colour out of the gradient and hides the sun behind it
+	float overcast = saturate(gloom);
+	float luma = dot(output_col, vec3(0.2126, 0.7152, 0.0722));
+	output_col = mix(output_col, storm_col * luma, overcast) * (1.0 - storm_dimming * overcast);
+	float sun_through = get_sun_through_clouds(overcast);
+
 	// Sun disc builder
 	vec3 sun_dir = get_sun_dir(time);
 	float ds = dot(sun_dir, dir);
@@ -170,7 +204,7 @@ vec3 get_sky(vec3 dir, float time, bool sun) {
 	float disc_factor = smoothstep(d - sun_bloom / 1000, d, dotsun) * ssds;
 
 	#ifdef OUTPUT_DISC
-	output_col += sun ? sun_col * sun_strength * disc_factor : vec3(0.0);
+	output_col += sun ? sun_col * sun_strength * disc_factor * sun_through : vec3(0.0);
 	#endif
 
 	// Sun halo builder
@@ -179,24 +213,46 @@ vec3 get_sky(vec3 dir, float time, bool sun) {
 	halo_factor *= ssds;
 
 	#ifdef OUTPUT_SUN_HALO
-	output_col += sun_halo_col * sun_halo_strength * saturate(halo_factor);
+	output_col += sun_halo_col * sun_halo_strength * saturate(halo_factor) * sun_through;
 	#endif
 
 	// horizon halo builder
 	float horiz_halo_factor = pow(1 - abs(dottop), 1 / horiz_halo_bloom * 100);
 	float sun_fac = saturate((PI - acos(ds)) / PI);
 	#ifdef OUTPUT_HORIZ_HALO
-	output_col += horiz_halo_col * horiz_halo_strength * horiz_halo_factor * sun_fac * sun_fac;
+	output_col += horiz_halo_col * horiz_halo_strength * horiz_halo_factor * sun_fac * sun_fac * sun_through;
 	#endif
 
 	return output_col;
 	// return sun_col * 10000;
 }
 
-vec3 get_sky_chroma(vec3 dir, float time) {
-	return get_sky(dir, time, false) * 3.0 * vec3(0.4, 0.65, 1.5);
+float star_hash(vec3 p) {
+	return fract(sin(dot(p, vec3(12.9898, 78.233, 37.719))) * 43758.5453);
+}
+
+// Stars fade in as the sun drops below the horizon, and are hidden by cloud
+vec3 get_stars(vec3 dir, float time, float gloom) {
+	float night = saturate(-get_sun_dir(time).z * 4.0);
+	float above_horizon = smoothstep(0.0, 0.1, dir.z);
+
+	vec3 cell = floor(dir * star_density);
+	float star = step(1.0 - star_frequency, star_hash(cell));
+	float twinkle = 0.75 + 0.25 * sin(time * 400.0 + star_hash(cell + 1.0) * 2.0 * PI);
+
+	return star_col * star_strength * star * twinkle * night * above_horizon * (1.0 - saturate(gloom));
+}
+
+// How much a fragment at `dist` from the player is hidden by fog, given the fog's start and end distances. The fog
+// colour is the sky behind the fragment, so it follows the time of day.
+float get_fog(float dist, vec4 fog) {
+	return fog.y > fog.x ? saturate((dist - fog.x) / (fog.y - fog.x)) : 0.0;
+}
+
+vec3 get_sky_chroma(vec3 dir, float time, float gloom) {
+	return get_sky(dir, time, false, gloom) * 3.0 * vec3(0.4, 0.65, 1.5);
 }
 
-vec3 get_skybox(vec3 dir, float time) {
-	return get_sky(dir, time, true) * 3.0 * vec3(0.4, 0.65, 1.5);
+vec3 get_skybox(vec3 dir, float time, float gloom) {
+	return get_sky(dir, time, true, gloom) * 3.0 * vec3(0.4, 0.65, 1.5) + get_stars(dir, time, gloom);
 }
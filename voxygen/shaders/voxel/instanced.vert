@@ -0,0 +1,66 @@
+#version 330 core
+
+#include <noise.glsl>
+#include <luts.glsl>
+
+in vec3 vert_pos;
+in uint vert_attrib;
+in uint vert_light;
+
+// Each instance's model matrix, a column at a time, and what its colour is multiplied by
+in vec4 inst_mat0;
+in vec4 inst_mat1;
+in vec4 inst_mat2;
+in vec4 inst_mat3;
+in vec4 inst_tint;
+
+layout (std140)
+uniform global_consts {
+	mat4 view_mat;
+	mat4 proj_mat;
+	vec4 cam_origin;
+	vec4 play_origin;
+	vec4 view_distance;
+	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
+};
+
+out vec3 frag_pos;
+out vec3 frag_world_pos;
+out float frag_ao;
+out vec2 frag_light;
+flat out vec3 frag_norm;
+flat out uint frag_mat;
+flat out uint frag_col_attr;
+flat out vec4 frag_tint;
+
+void main() {
+	uvec4 attr = (uvec4(vert_attrib) >> uvec4(
+		0,
+		16,
+		20,
+		24
+	)) & uvec4(
+		0xFFFFu,
+		0x0F,
+		0x0F,
+		0xFF
+	);
+
+	mat4 inst_mat = mat4(inst_mat0, inst_mat1, inst_mat2, inst_mat3);
+	vec3 world_pos = (inst_mat * vec4(vert_pos, 1)).xyz;
+
+	frag_pos = vert_pos;
+	frag_world_pos = world_pos;
+	frag_ao = float(attr.y);
+	frag_light = vec2(vert_light & 0xFFu, (vert_light >> 8) & 0xFFu) / 255.0;
+	// The fragment shader's model matrix is left as the identity, so the normal is turned here instead
+	frag_norm = normalize((inst_mat * vec4(norm_lut[attr.z], 0)).xyz);
+	frag_mat = attr.w;
+	frag_col_attr = attr.x;
+	frag_tint = inst_tint;
+
+	gl_Position = proj_mat * view_mat * vec4(world_pos, 1);
+}
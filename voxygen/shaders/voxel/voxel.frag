@@ -11,9 +11,11 @@ in vec3 frag_pos;
 in vec3 frag_world_pos;
 //in vec4 frag_col;
 in float frag_ao;
+in vec2 frag_light;
 flat in vec3 frag_norm;
 flat in uint frag_mat;
 flat in uint frag_col_attr;
+flat in vec4 frag_tint;
 
 layout (std140)
 uniform model_consts {
@@ -28,17 +30,15 @@ uniform global_consts {
 	vec4 play_origin;
 	vec4 view_distance;
 	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
 };
 
 out vec4 target;
 
-float diffuse_factor = 0.5;
-float ambiant_factor = 0.2;
-vec3  sun_direction = normalize(vec3(-1.5, -0.8, -1));
-vec3  sun_colorr     = vec3(1, 1, 1);
-float sun_factor    = 50;
-float sun_shine     = 0;
-
+// The colour of the light given off by blocks like glowstone
+const vec3 BLOCK_LIGHT_COLOR = vec3(1.0, 0.6, 0.3);
 
 void main() {
 	if (length(play_origin.xyz - frag_world_pos.xyz) > view_distance.x) {
@@ -46,10 +46,10 @@ void main() {
 		return;
 	}
 
-	vec4 frag_col = get_color_from_attr(frag_col_attr);
+	vec4 frag_col = get_color_from_attr(frag_col_attr) * frag_tint;
 
 	Material mat = mat_lut[frag_mat];
-	// Sunlight
+	// Sunlight, using the same sun as the skybox so lighting matches the sky
 	float sunAngularRadius = 0.017; // 1 degree radius, 2 degree diameter (not realistic, irl sun is ~0.5 deg diameter)
 	float time_of_day = get_time_of_day(time.x);
 	vec3 sun_color = get_sun_color(time_of_day);
@@ -73,7 +73,7 @@ void main() {
 	float LdotH = saturate(dot(L, H));
 	float NdotH = clamp(dot(N, H), 0.0, 0.99999995);// fix artifact
 
-	vec3 atmos_color = get_sky(N, time_of_day, false);
+	vec3 atmos_color = get_sky(N, time_of_day, false, weather.x);
 
 	vec3 col_noise = vec3(0,0,0);
 
@@ -108,24 +108,27 @@ void main() {
 	vec3 diffuse = fD * col.rgb * omm * ao;
 
 	float sun_level = saturate(day_cycle(1, 0.9, time_of_day));
-	float sun_intensity = sun_level * 80000;
+	float sun_intensity = sun_level * 80000 * get_sun_through_clouds(weather.x);
 	vec3 sun_illuminance = sun_color * sun_intensity;
 
     float ambient_intensity = 2.0 * omm; // TODO: have specular ambient so that we don't have to hack this
 	vec3 ambient = col.rgb * ambient_intensity * atmos_color;
 
-	vec3 lighted = ambient * ao + (saturate((diffuse + specular) * NdotL) * sun_illuminance * ao);
+	// Light from the sky only reaches as far in as sunlight does, and blocks like glowstone light up what's around
+	// them. Block light is kept about as bright on screen whatever the exposure is.
+	float sky_light = light_brightness(frag_light.x);
+	float block_light = max0(light_brightness(frag_light.y) - light_brightness(0.0));
+	vec3 block_illuminance = BLOCK_LIGHT_COLOR * block_light * get_exposure_range(time_of_day) * 0.5;
+
+	vec3 lighted = (ambient * ao + (saturate((diffuse + specular) * NdotL) * sun_illuminance * ao)) * sky_light;
+	lighted += col.rgb * omm * ao * block_illuminance;
 	//vec3 lighted = ambient + ((diffuse + specular) * sun_illuminance) * ao;
 
-	// Mist
-	float mist_start = view_distance.x * 0.9;// + snoise(vec4(world_pos, time) * 0.02) * 50.0;
-	float mist_end = view_distance.x;// + snoise(vec4(world_pos, -time) * 0.02) * 50.0;
-	float mist_delta = mist_end - mist_start;
+	// Fog
 	float play_dist = length(play_origin.xyz - frag_world_pos.xyz);
-	float dist = max(play_dist - mist_start, 0);
-	float mist_value = saturate(dist / mist_delta);
+	float mist_value = get_fog(play_dist, fog);
 
-	vec3 sky_chroma = get_sky_chroma(-V, time_of_day);
+	vec3 sky_chroma = get_sky_chroma(-V, time_of_day, weather.x);
     float smax = max(specular.r, max(specular.g, specular.b));
     float a = clamp(smax + frag_col.a, 0, 1);
 	target = mix(vec4(lighted, a), vec4(sky_chroma, 1.0), mist_value);
@@ -10,6 +10,7 @@ in vec3 frag_pos;
 in vec3 frag_world_pos;
 in vec4 frag_col;
 in float frag_ao;
+in vec2 frag_light;
 flat in vec3 frag_norm;
 flat in uint frag_mat;
 
@@ -26,10 +27,16 @@ uniform global_consts {
 	vec4 play_origin;
 	vec4 view_distance;
 	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
 };
 
 out vec4 target;
 
+// The colour of the light given off by blocks like glowstone
+const vec3 BLOCK_LIGHT_COLOR = vec3(1.0, 0.6, 0.3);
+
 const vec3 off = vec3(-1, 0, 1);
 const vec2 offsets[5] = vec2[5](
     off.yy, off.xy, off.zy, off.yx, off.yz
@@ -49,7 +56,7 @@ void main() {
 	vec2 size = vec2(scale * 0.01, 0.0);
 	for (int i = 0; i < 5; i++) {
 			vec2 offset = offsets[i] * size.x;
-			s[i] = snoise(vec3((frag_world_pos.xy * scale) + offset, time_of_day * 60)) * 0.5 + 1.0;
+			s[i] = snoise(vec3(((frag_world_pos.xy + render_origin.xy) * scale) + offset, time_of_day * 60)) * 0.5 + 1.0;
 	}
 	vec3 va = normalize(vec3(size.xy,s[2]-s[1]));
 	vec3 vb = normalize(vec3(size.yx,s[4]-s[3]));
@@ -76,8 +83,8 @@ void main() {
 	float VdotH = clamp(dot(V, H), 0.0, 1.0);
 	float NdotH = clamp(dot(N, H), 0.0, 0.99999995);// fix artifact
 
-	vec3 sky_chroma = get_sky_chroma(-V, time_of_day);
-	vec3 atmos_color = get_sky_chroma(N, time_of_day);
+	vec3 sky_chroma = get_sky_chroma(-V, time_of_day, weather.x);
+	vec3 atmos_color = get_sky_chroma(N, time_of_day, weather.x);
 	atmos_color.r *= 0.5 + 0.5 * clamp(sunrise_anticycle(1, 0.9, time_of_day), 0, 1); // TODO: make less janky
 
 	float ao = (frag_ao / 3.0);
@@ -102,18 +109,20 @@ void main() {
 	vec3 diffuse = fD * frag_col.rgb;
 
 	float sun_level = clamp(day_cycle(1, 0.9, time_of_day), 0.0, 1);
-	float sun_intensity = sun_level * 80000;
+	float sun_intensity = sun_level * 80000 * get_sun_through_clouds(weather.x);
 	float sun_illuminance = sun_intensity * 1.0;//NdotL;
 
-	vec3 lighted = ambient + ((diffuse + specular) * sun_color * sun_illuminance * ao);
+	// Lit the same way as other terrain, see voxel.frag
+	float sky_light = light_brightness(frag_light.x);
+	float block_light = max0(light_brightness(frag_light.y) - light_brightness(0.0));
+	vec3 block_illuminance = BLOCK_LIGHT_COLOR * block_light * get_exposure_range(time_of_day) * 0.5;
+
+	vec3 lighted = (ambient + ((diffuse + specular) * sun_color * sun_illuminance * ao)) * sky_light;
+	lighted += frag_col.rgb * ao * block_illuminance;
 
-	// Mist
-	float mist_start = view_distance.x * 0.7;// + snoise(vec4(world_pos, time) * 0.02) * 50.0;
-	float mist_end = view_distance.x;// + snoise(vec4(world_pos, -time) * 0.02) * 50.0;
-	float mist_delta = mist_end - mist_start;
+	// Fog
 	float play_dist = length(play_origin.xyz - frag_world_pos.xyz);
-	float dist = max(play_dist - mist_start, 0);
-	float percent = clamp(dist / mist_delta, 0, 1);
+	float percent = get_fog(play_dist, fog);
 	float mist_value = percent * percent * percent;
 
 	float fres_n = f_Schlick(f0, f90, NdotV).r;
@@ -5,6 +5,7 @@
 
 in vec3 vert_pos;
 in uint vert_attrib;
+in uint vert_light;
 
 layout (std140)
 uniform model_consts {
@@ -19,12 +20,16 @@ uniform global_consts {
 	vec4 play_origin;
 	vec4 view_distance;
 	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
 };
 
 out vec3 frag_pos;
 out vec3 frag_world_pos;
 out vec4 frag_col;
 out float frag_ao;
+out vec2 frag_light;
 flat out vec3 frag_norm;
 flat out uint frag_mat;
 
@@ -86,6 +91,7 @@ void main() {
 	frag_world_pos = world_pos;
     frag_col = get_color(attr.x);
     frag_ao = float(attr.y);
+	frag_light = vec2(vert_light & 0xFFu, (vert_light >> 8) & 0xFFu) / 255.0;
 	frag_norm = norm_lut[attr.z];
 	frag_mat = attr.w;
 
@@ -0,0 +1,28 @@
+#version 330 core
+
+#include <common.glsl>
+#include <sky.glsl>
+
+in vec4 frag_tint;
+
+layout (std140)
+uniform global_consts {
+	mat4 view_mat;
+	mat4 proj_mat;
+	vec4 cam_origin;
+	vec4 play_origin;
+	vec4 view_distance;
+	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
+};
+
+out vec4 target;
+
+void main() {
+	// Lit only by the sky above, the way terrain's ambient light is, so rain and snow darken with it
+	float tod = get_time_of_day(time.x);
+	vec3 sky_light = get_sky(vec3(0.0, 0.0, 1.0), tod, false, weather.x) * 2.0;
+	target = vec4(frag_tint.rgb * sky_light, frag_tint.a);
+}
@@ -0,0 +1,24 @@
+#version 330 core
+
+in vec3 vert_pos;
+in vec4 vert_tint;
+
+layout (std140)
+uniform global_consts {
+	mat4 view_mat;
+	mat4 proj_mat;
+	vec4 cam_origin;
+	vec4 play_origin;
+	vec4 view_distance;
+	vec4 time;
+	vec4 fog;
+	vec4 render_origin;
+	vec4 weather;
+};
+
+out vec4 frag_tint;
+
+void main() {
+	frag_tint = vert_tint;
+	gl_Position = proj_mat * view_mat * vec4(vert_pos, 1);
+}
@@ -0,0 +1,118 @@
+// Standard
+use std::f32::consts::PI;
+
+// Library
+use vek::*;
+
+// Project
+use common::ecs::phys::MoveMode;
+
+// Constants
+// Below this horizontal speed, in blocks per second, an entity is standing still
+const MIN_WALK_SPEED: f32 = 0.5;
+// How far through a stride an entity gets per block moved, in radians. A stride is two steps.
+const STRIDE: f32 = PI / 2.0;
+// How high a step lifts the model, in blocks
+const WALK_BOB: f32 = 0.08;
+const RUN_BOB: f32 = 0.16;
+// How far the model rocks from side to side with each step, in radians
+const SWAY: f32 = 0.05;
+// How far the arms and legs of segmented models swing either way with each step, in radians
+const WALK_SWING: f32 = 0.5;
+const RUN_SWING: f32 = 0.8;
+// How far forward a running model leans, in radians
+const RUN_LEAN: f32 = 0.15;
+// Leaning back with the legs tucked up while in the air
+const JUMP_LEAN: f32 = -0.1;
+const JUMP_OFFSET: f32 = 0.1;
+// Game time can jump when it's synced with the server, and animations shouldn't jump with it
+const MAX_STEP: f32 = 0.25;
+
+/// What an entity is doing, as far as animating it goes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AnimState {
+    Idle,
+    Walk,
+    Run,
+    Jump,
+}
+
+impl AnimState {
+    /// Work out what an entity is doing from how it's moving. Falling counts as jumping.
+    pub fn of(vel: Vec3<f32>, move_mode: MoveMode, grounded: bool) -> AnimState {
+        if !grounded {
+            AnimState::Jump
+        } else if Vec2::from(vel).magnitude() < MIN_WALK_SPEED {
+            AnimState::Idle
+        } else if move_mode == MoveMode::Sprint {
+            AnimState::Run
+        } else {
+            AnimState::Walk
+        }
+    }
+}
+
+/// How an entity's model is moved away from where the entity is, on top of the direction it faces
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Pose {
+    /// How far the model is raised, in blocks
+    pub offset: f32,
+    /// How far the model leans forward, in radians
+    pub pitch: f32,
+    /// How far the model rocks to one side, in radians
+    pub roll: f32,
+    /// How far the arms and legs swing around their pivots, for models that have them, in radians
+    pub swing: f32,
+}
+
+/// Where an entity is in its walk cycle. Each entity has its own, so that they don't all step in time.
+pub struct Animation {
+    phase: f32,
+    last_time: Option<f32>,
+}
+
+impl Animation {
+    /// A walk cycle starting somewhere that depends on the entity's uid
+    pub fn new(uid: u64) -> Animation {
+        Animation {
+            phase: (uid % 8) as f32 * PI / 4.0,
+            last_time: None,
+        }
+    }
+
+    /// Move the walk cycle on to game time `time`, in seconds, at `speed` blocks per second, and find the pose for
+    /// `state`. The pose depends on how far the entity has moved rather than on how often it's updated.
+    pub fn update(&mut self, state: AnimState, speed: f32, time: f32) -> Pose {
+        let dt = self.last_time.map(|last| (time - last).max(0.0).min(MAX_STEP)).unwrap_or(0.0);
+        self.last_time = Some(time);
+
+        match state {
+            AnimState::Walk | AnimState::Run => self.phase = (self.phase + speed * STRIDE * dt) % (2.0 * PI),
+            AnimState::Idle | AnimState::Jump => {},
+        }
+
+        // The model is highest as its legs pass each other, and rocks towards the foot that's down
+        let (step, lift) = (self.phase.sin(), self.phase.cos().abs());
+        match state {
+            AnimState::Idle => Pose::default(),
+            AnimState::Walk => Pose {
+                offset: WALK_BOB * lift,
+                pitch: 0.0,
+                roll: SWAY * step,
+                swing: WALK_SWING * step,
+            },
+            AnimState::Run => Pose {
+                offset: RUN_BOB * lift,
+                pitch: RUN_LEAN,
+                roll: SWAY * step,
+                swing: RUN_SWING * step,
+            },
+            AnimState::Jump => Pose {
+                offset: JUMP_OFFSET,
+                pitch: JUMP_LEAN,
+                roll: 0.0,
+                swing: 0.0,
+            },
+        }
+    }
+}
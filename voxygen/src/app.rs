@@ -0,0 +1,191 @@
+// Standard
+use std::{path::PathBuf, rc::Rc};
+
+// Library
+use vek::*;
+
+// Project
+use client::PlayMode;
+
+// Local
+use crate::{
+    cli::{self, Target, DEFAULT_PORT, DEFAULT_SERVER},
+    game::{Exit, Game},
+    menu::{MainMenu, MenuEvent},
+    settings::Settings,
+    singleplayer::LocalServer,
+    window::{Event, RenderWindow},
+    RENDERER_INFO,
+};
+
+// Constants
+const MENU_BACKGROUND: Vec3<f32> = Vec3 { x: 0.1, y: 0.1, z: 0.15 };
+
+/// What the player is doing
+pub enum State {
+    /// At the main menu, showing an error if something went wrong before returning to it
+    Menu { error: Option<String> },
+    Playing { alias: String, target: Target },
+    Quit,
+}
+
+/// Owns the window for as long as voxygen runs, moving between the main menu and the game
+pub struct App {
+    window: Rc<RenderWindow>,
+    settings: Settings,
+    // Where to record the player's movement in every game played, if anywhere
+    record: Option<PathBuf>,
+}
+
+impl App {
+    pub fn new(settings: Settings, record: Option<PathBuf>) -> App {
+        let window = RenderWindow::new(&settings.graphics);
+        let info = window.get_renderer_info();
+        println!(
+            "Graphics card info - vendor: {} model: {} OpenGL: {}",
+            info.vendor, info.model, info.gl_version
+        );
+        *RENDERER_INFO.lock() = Some(info);
+
+        App {
+            window: Rc::new(window),
+            settings,
+            record,
+        }
+    }
+
+    /// Run from `state` until the player quits
+    pub fn run(&mut self, mut state: State) {
+        loop {
+            state = match state {
+                State::Menu { error } => self.run_menu(error),
+                State::Playing { alias, target } => self.play(&alias, target),
+                State::Quit => break,
+            };
+        }
+
+        // Remember how the window was left for next time
+        let placement = self.window.placement();
+        self.settings.graphics.fullscreen = self.window.is_fullscreen();
+        self.settings.graphics.vsync = self.window.is_vsync();
+        self.settings.graphics.window_size = placement.size;
+        self.settings.graphics.window_pos = placement.pos;
+        if let Err(e) = self.settings.save() {
+            warn!("failed to save settings: {}", e);
+        }
+    }
+
+    fn run_menu(&mut self, error: Option<String>) -> State {
+        let network = &self.settings.network;
+        let addr = network.recent_servers.first().map(|s| s.as_str()).unwrap_or(DEFAULT_SERVER);
+        let mut menu = MainMenu::new(&network.alias, addr, &network.recent_servers, error);
+        self.window.untrap_cursor();
+
+        loop {
+            let mut close = false;
+            let window = &self.window;
+            window.handle_events(|event| {
+                if let Event::CloseRequest = event {
+                    close = true;
+                }
+                menu.handle_event(&event, &mut window.renderer_mut());
+                // The cursor stays free while in the menu
+                true
+            });
+            if close {
+                return State::Quit;
+            }
+
+            for event in menu.get_events() {
+                let (alias, target) = match event {
+                    MenuEvent::Quit => return State::Quit,
+                    MenuEvent::Singleplayer { alias } => (alias, Target::Singleplayer),
+                    MenuEvent::Connect { alias, addr } => match cli::resolve_addr(&addr, DEFAULT_PORT) {
+                        Ok(resolved) => {
+                            self.settings.add_recent_server(&addr);
+                            (alias, Target::Remote(resolved))
+                        },
+                        Err(e) => {
+                            menu.set_status(e);
+                            continue;
+                        },
+                    },
+                };
+
+                if !alias.is_empty() {
+                    self.settings.network.alias = alias.clone();
+                }
+                // Connecting blocks, so let the player know why nothing is happening
+                menu.set_status("Connecting...".to_string());
+                self.draw_menu(&mut menu);
+                return State::Playing {
+                    alias: pick_alias(alias),
+                    target,
+                };
+            }
+
+            self.draw_menu(&mut menu);
+        }
+    }
+
+    fn draw_menu(&self, menu: &mut MainMenu) {
+        let mut renderer = self.window.renderer_mut();
+        renderer.begin_frame(Some(MENU_BACKGROUND));
+        menu.render(&mut renderer);
+        self.window.swap_buffers();
+        renderer.end_frame();
+    }
+
+    fn play(&mut self, alias: &str, target: Target) -> State {
+        let (remote_addr, local_server) = match target {
+            Target::Singleplayer => match LocalServer::start() {
+                Ok(server) => (server.addr(), Some(server)),
+                Err(e) => {
+                    return State::Menu {
+                        error: Some(format!("Could not start singleplayer server: {:?}", e)),
+                    };
+                },
+            },
+            Target::Remote(addr) => (addr, None),
+        };
+
+        println!("Connecting to {}", remote_addr);
+
+        // The game (and with it, the client) must be dropped before the local server to disconnect cleanly
+        let exit = match Game::new(
+            PlayMode::Character,
+            alias,
+            remote_addr,
+            self.window.clone(),
+            self.settings.clone(),
+            self.record.as_ref().map(|path| path.as_path()),
+        ) {
+            Ok(mut game) => {
+                let exit = game.run();
+                // Anything reloaded while playing is kept
+                self.settings = game.settings().clone();
+                exit
+            },
+            Err(e) => Exit::ToMenu(Some(e)),
+        };
+
+        if let Some(server) = local_server {
+            server.shutdown();
+        }
+
+        match exit {
+            Exit::ToMenu(error) => State::Menu { error },
+            Exit::Quit => State::Quit,
+        }
+    }
+}
+
+/// Use `alias` as the player name, or a random one if it's empty
+pub fn pick_alias(alias: String) -> String {
+    if alias.is_empty() {
+        println!("No name chosen, generating random one...");
+        common::util::names::generate().to_string()
+    } else {
+        alias
+    }
+}
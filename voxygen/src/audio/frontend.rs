@@ -2,15 +2,18 @@ use common::{
     audio::{audio_gen::AudioGen, Buffer, Stream},
     util::manager::{Managed, Manager},
 };
-use parking_lot::{Mutex, RwLock};
+use parking_lot::RwLock;
 use rodio::{Decoder, Device, Source, SpatialSink};
-use std::{collections::HashMap, fs::File, io::BufReader, sync::Arc, thread::sleep, time::Duration};
+use std::{collections::HashMap, fs::File, io::BufReader};
 use vek::*;
 
+// Scales world distances down so sounds fall off over a sensible number of blocks
+const FALLOFF: f32 = 0.13;
+
 pub struct AudioFrontend {
     device: Device,
     pos: RwLock<Vec3<f32>>,
-    ori: RwLock<Mat4<f32>>,
+    ori: RwLock<Vec3<f32>>,
     streams: RwLock<HashMap<u64, InternalStream>>, //always use SpatialSink even if no possition is used for now
     buffers: RwLock<HashMap<u64, Buffer>>,
 }
@@ -27,77 +30,80 @@ impl AudioFrontend {
         Manager::init(AudioFrontend {
             device,
             pos: RwLock::new(Vec3::new(0.0, 0.0, 0.0)),
-            ori: RwLock::new(Mat4::identity()),
+            ori: RwLock::new(Vec3::unit_y()),
             streams: RwLock::new(HashMap::new()),
             buffers: RwLock::new(HashMap::new()),
         })
     }
 
-    pub fn set_pos(&self, pos: Vec3<f32>, _vel: Vec3<f32>, ori: Mat4<f32>) {
-        *self.pos.write() = pos;
-        *self.ori.write() = ori;
-        let mut slock = self.streams.write();
-        for (id, int) in slock.iter_mut() {
-            self.adjust(&int.settings, &mut int.sink);
-        }
-    }
-
     fn adjust(&self, stream: &Stream, sink: &mut SpatialSink) {
-        const FALLOFF: f32 = 0.13;
         if let Some(pos) = &stream.positional {
-            if pos.relative {
-                sink.set_emitter_position([pos.pos.x * FALLOFF, pos.pos.y * FALLOFF, pos.pos.z * FALLOFF]);
+            let emitter = if pos.relative {
+                pos.pos
             } else {
-                let lpos = *self.pos.read();
-                sink.set_emitter_position([
-                    (pos.pos.x - lpos.x) * FALLOFF,
-                    (pos.pos.y - lpos.y) * FALLOFF,
-                    (pos.pos.z - lpos.z) * FALLOFF,
-                ]);
+                pos.pos - *self.pos.read()
+            };
+            sink.set_emitter_position((emitter * FALLOFF).into_array());
+
+            // The ears sit either side of the listener, perpendicular to the way they're facing
+            let right = self.ori.read().cross(Vec3::unit_z()).normalized();
+            if right.x.is_finite() {
+                sink.set_left_ear_position((-right).into_array());
+                sink.set_right_ear_position(right.into_array());
             }
-            let lori = *self.ori.read();
-            //let mut xyz = lori * Vec4::new(pos.pos.x, pos.pos.y, pos.pos.z , 100.5);
-            //TODO: FIXME: Wowowowow, thats some ugly code below to get the relative head direction of the camera working.
-            // It works on a flat horizontal plane (which will be enought for 90% of people) but we should have someone with a vector math brain look over it...
-            let x = lori.into_row_array();
-            let mut xy = Vec3::new(x[0] / 0.813, x[1] / 1.3155, 0.0);
-            xy.normalize();
-            let mut left_ear = Mat3::rotation_z(3.14) * xy;
-            let mut right_ear = xy;
-            sink.set_left_ear_position(left_ear.into_array());
-            sink.set_right_ear_position(right_ear.into_array());
         }
         sink.set_volume(stream.volume);
     }
 
-    fn create_source(&self, buffer: &Buffer) -> Decoder<BufReader<File>> {
+    fn create_source(&self, buffer: &Buffer) -> Result<Decoder<BufReader<File>>, String> {
         match buffer {
             Buffer::File(file) => {
-                let file = std::fs::File::open(file).unwrap();
-                rodio::Decoder::new(BufReader::new(file)).unwrap()
-            },
-            Buffer::Raw(..) => {
-                panic!("raw buffers not implemented yet");
+                let file = File::open(file).map_err(|e| e.to_string())?;
+                rodio::Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())
             },
+            Buffer::Raw(..) => Err("raw buffers not implemented yet".to_string()),
         }
     }
 }
 
 impl AudioGen for AudioFrontend {
-    fn gen_stream(&self, id: u64, buffer: &Buffer, stream: &Stream) {
-        let mut slock = self.streams.write();
-        let lock = self.buffers.read();
-        if let Some(buffer) = lock.get(&stream.buffer) {
-            let src = self.create_source(buffer);
-            let mut sink = rodio::SpatialSink::new(&self.device, [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [-1.0, 0.0, 0.0]);
-            self.adjust(stream, &mut sink);
-            sink.append(src);
-            let mut internal = InternalStream {
-                sink,
-                settings: stream.clone(),
-            };
-            //p.src.play();
-            slock.insert(id, internal);
+    fn gen_stream(&self, id: u64, _buffer: &Buffer, stream: &Stream) {
+        let buffer = match self.buffers.read().get(&stream.buffer) {
+            Some(buffer) => buffer.clone(),
+            None => return,
+        };
+
+        match self.create_source(&buffer) {
+            Ok(src) => {
+                let mut sink =
+                    rodio::SpatialSink::new(&self.device, [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [-1.0, 0.0, 0.0]);
+                self.adjust(stream, &mut sink);
+                sink.append(src.speed(stream.pitch));
+                self.streams.write().insert(id, InternalStream {
+                    sink,
+                    settings: stream.clone(),
+                });
+            },
+            Err(e) => {
+                // Forget the buffer so a broken file is only reported once
+                warn!("could not play {:?}: {}, disabling the sound", buffer, e);
+                self.buffers.write().remove(&stream.buffer);
+            },
+        }
+    }
+
+    fn update_stream(&self, id: u64, _buffer: &Buffer, stream: &Stream) {
+        if let Some(internal) = self.streams.write().get_mut(&id) {
+            internal.settings = stream.clone();
+            self.adjust(stream, &mut internal.sink);
+        }
+    }
+
+    fn set_listener(&self, pos: Vec3<f32>, ori: Vec3<f32>) {
+        *self.pos.write() = pos;
+        *self.ori.write() = ori;
+        for internal in self.streams.write().values_mut() {
+            self.adjust(&internal.settings, &mut internal.sink);
         }
     }
 
@@ -106,21 +112,15 @@ impl AudioGen for AudioFrontend {
         self.buffers.write().insert(id, buffer.clone());
     }
 
-    fn drop_stream(&self, id: u64, buffer: &Buffer, stream: &Stream) {
-        let mut slock = self.streams.write();
-        if let Some(p) = slock.get_mut(&id) {
-            //p.src.stop();
-            slock.remove(&id);
-        }
-    }
+    fn drop_stream(&self, id: u64, _buffer: &Buffer, _stream: &Stream) { self.streams.write().remove(&id); }
 
-    fn drop_buffer(&self, id: u64, buffer: &Buffer) {}
+    fn drop_buffer(&self, id: u64, _buffer: &Buffer) { self.buffers.write().remove(&id); }
 }
 
 impl Managed for AudioFrontend {
     fn init_workers(&self, manager: &mut Manager<Self>) {
         // Background Sound
-        Manager::add_worker(manager, |audio, running, mut mgr| {});
+        Manager::add_worker(manager, |_audio, _running, _mgr| {});
     }
 
     fn on_drop(&self, _: &mut Manager<Self>) {}
@@ -0,0 +1,51 @@
+use gfx::{
+    self,
+    buffer::Role,
+    handle::Buffer,
+    memory::{Bind, Usage},
+    traits::{FactoryExt, Pod},
+};
+use gfx_device_gl;
+
+use crate::renderer::Renderer;
+
+type VertexBuffer<T> = Buffer<gfx_device_gl::Resources, T>;
+
+/// A vertex buffer for things that change every frame. It's written over in place, and only made again when what's
+/// written no longer fits in it.
+pub struct DynamicBuffer<T: Copy + Pod> {
+    buffer: Option<VertexBuffer<T>>,
+    len: u32,
+}
+
+impl<T: Copy + Pod> DynamicBuffer<T> {
+    pub fn new() -> DynamicBuffer<T> { DynamicBuffer { buffer: None, len: 0 } }
+
+    /// Replace what's in the buffer with `data`, making it bigger first if need be
+    pub fn update(&mut self, renderer: &mut Renderer, data: &[T]) {
+        self.len = data.len() as u32;
+        if data.is_empty() {
+            return;
+        }
+        if self.buffer.as_ref().map_or(true, |buffer| buffer.len() < data.len()) {
+            // With room to spare, so that slowly growing data doesn't need a new buffer every frame
+            let size = data.len() + data.len() / 2;
+            let buffer = renderer
+                .factory_mut()
+                .create_buffer(size, Role::Vertex, Usage::Dynamic, Bind::empty())
+                .expect("Failed to create a vertex buffer");
+            self.buffer = Some(buffer);
+        }
+        if let Some(ref buffer) = self.buffer {
+            renderer.encoder_mut().update_buffer(buffer, data, 0).unwrap();
+        }
+    }
+
+    /// The buffer and how many of its vertices are in use, or `None` if there aren't any
+    pub fn get(&self) -> Option<(&VertexBuffer<T>, u32)> {
+        match self.buffer {
+            Some(ref buffer) if self.len > 0 => Some((buffer, self.len)),
+            _ => None,
+        }
+    }
+}
@@ -2,12 +2,69 @@ use std::f32::consts::PI;
 
 use vek::{Mat4, Vec2, Vec3, Vec4};
 
+// Just under 90 degrees, so that the camera can't flip over the top
+const MAX_PITCH: f32 = PI / 2.0 - 0.01;
+const MIN_ZOOM: f32 = 0.0;
+const MAX_ZOOM: f32 = 100.0;
+// The range the vertical field of view can be set within, in degrees
+const MIN_FOV: f32 = 60.0;
+const MAX_FOV: f32 = 110.0;
+// How many degrees wider the view gets while widened
+const WIDENING: f32 = 8.0;
+// How quickly the field of view eases towards what it should be: it gets about two thirds of the way in 1/FOV_EASING
+// seconds. Only widening eases; a new field of view from the settings takes effect at once.
+const FOV_EASING: f32 = 8.0;
+const DEFAULT_NEAR: f32 = 0.1;
+const DEFAULT_FAR: f32 = 10000.0;
+// How far the focus can get from the render origin before the origin moves to catch up. Chunks are a whole number of
+// steps across, so chunk models only need new constants when it moves, never new meshes.
+const ORIGIN_STEP: i64 = 512;
+
+/// The point everything is drawn relative to. The GPU only works in `f32`, which can't tell apart positions a few
+/// kilometres out well enough to draw them, so positions are made relative to somewhere close to the camera before
+/// they go into any matrix. It stays put until the camera gets far from it, so that models relative to it rarely need
+/// updating.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RenderOrigin(Vec3<i64>);
+
+impl RenderOrigin {
+    /// Move the origin to near `focus` if it's got too far away, returning whether it moved
+    pub fn follow(&mut self, focus: Vec3<f32>) -> bool {
+        if (focus.map(|e| e as i64) - self.0).map(|e| e.abs()).reduce_max() <= ORIGIN_STEP {
+            return false;
+        }
+        self.0 = focus.map(|e| (e as f64 / ORIGIN_STEP as f64).round() as i64 * ORIGIN_STEP);
+        true
+    }
+
+    /// Where the origin is in the world
+    pub fn world_pos(&self) -> Vec3<f32> { self.0.map(|e| e as f32) }
+
+    /// `pos` relative to the origin. The subtraction happens in `f64`, so a position held in `f64` keeps its detail
+    /// however far it is from the world's origin. An `f32` one comes out with only as much detail as it went in with.
+    pub fn relative<T: Into<f64> + Copy>(&self, pos: Vec3<T>) -> Vec3<f32> {
+        pos.map2(self.0, |e, o| (Into::<f64>::into(e) - o as f64) as f32)
+    }
+
+    /// The block at `pos` relative to the origin, exactly
+    pub fn relative_block(&self, pos: Vec3<i64>) -> Vec3<f32> { (pos - self.0).map(|e| e as f32) }
+}
+
 pub struct Camera {
     focus: Vec3<f32>,
     ori: Vec2<f32>,
     aspect_ratio: f32,
+    // The vertical field of view being drawn with, and the one it eases towards when not widened, in radians
     fov: f32,
+    base_fov: f32,
+    widened: bool,
+    near: f32,
+    far: f32,
     zoom: f32,
+
+    sensitivity: Vec2<f32>,
+    invert_y: bool,
+    zoom_speed: f32,
 }
 
 impl Camera {
@@ -17,11 +74,28 @@ impl Camera {
             ori: Vec2::zero(),
             aspect_ratio: 1.618,
             fov: 1.3,
+            base_fov: 1.3,
+            widened: false,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
             zoom: 10.0,
+
+            sensitivity: Vec2::broadcast(0.002),
+            invert_y: false,
+            zoom_speed: 0.25,
         }
     }
 
-    pub fn get_mats(&self) -> (Mat4<f32>, Mat4<f32>) {
+    /// The view and projection matrices in world space. Fine for working things out on the CPU, but too imprecise to
+    /// draw with far from the world's origin; use `get_render_mats` for that.
+    pub fn get_mats(&self) -> (Mat4<f32>, Mat4<f32>) { self.mats_around(self.focus) }
+
+    /// The view and projection matrices for drawing things positioned relative to `origin`
+    pub fn get_render_mats(&self, origin: &RenderOrigin) -> (Mat4<f32>, Mat4<f32>) {
+        self.mats_around(origin.relative(self.focus))
+    }
+
+    fn mats_around(&self, focus: Vec3<f32>) -> (Mat4<f32>, Mat4<f32>) {
         let mut view = Mat4::identity();
 
         view *= Mat4::<f32>::translation_3d(Vec3::new(0.0, 0.0, -self.zoom))
@@ -31,27 +105,51 @@ impl Camera {
         // Apply anti-OpenGL correction
         view *= Mat4::rotation_3d(PI / 2.0, -Vec4::unit_x());
 
-        view *= Mat4::<f32>::translation_3d(-self.focus);
+        view *= Mat4::<f32>::translation_3d(-focus);
 
-        let perspective = Mat4::<f32>::perspective_rh_no(self.fov, self.aspect_ratio, 0.1, 10000.0);
+        let perspective = Mat4::<f32>::perspective_rh_no(self.fov, self.aspect_ratio, self.near, self.far);
 
         (view, perspective)
     }
 
-    pub fn rotate_by(&mut self, dangle: Vec2<f32>) {
-        self.ori += dangle;
-        if self.ori.y < -PI / 2.0 {
-            self.ori.y = -PI / 2.0;
-        } else if self.ori.y > PI / 2.0 {
-            self.ori.y = PI / 2.0;
+    /// Rotate the camera by a mouse movement delta, scaled by the camera's sensitivity
+    pub fn rotate_by(&mut self, delta: Vec2<f32>) {
+        let mut dangle = delta * self.sensitivity;
+        if self.invert_y {
+            dangle.y = -dangle.y;
         }
+
+        self.ori += dangle;
+        self.ori.y = self.ori.y.max(-MAX_PITCH).min(MAX_PITCH);
     }
 
+    /// Zoom the camera by a mouse wheel delta, scaled by the camera's zoom speed
     pub fn zoom_by(&mut self, delta: f32) {
-        self.zoom += delta;
-        if self.zoom < 0.0 {
-            self.zoom = 0.0;
+        self.zoom = (self.zoom + delta * self.zoom_speed).max(MIN_ZOOM).min(MAX_ZOOM);
+    }
+
+    /// Ease the field of view towards what it should be, `dt` seconds since the last update
+    pub fn update(&mut self, dt: f32) {
+        let target = if self.widened {
+            self.base_fov + WIDENING.to_radians()
+        } else {
+            self.base_fov
+        };
+        self.fov += (target - self.fov) * (1.0 - (-dt * FOV_EASING).exp());
+    }
+
+    /// Where `pos` appears on screen with the matrices from `get_mats`, from (0, 0) at the top left to (1, 1) at the
+    /// bottom right. `None` if it's behind the camera or off screen.
+    pub fn project(mats: &(Mat4<f32>, Mat4<f32>), pos: Vec3<f32>) -> Option<Vec2<f32>> {
+        let clip = mats.1 * mats.0 * Vec4::new(pos.x, pos.y, pos.z, 1.0);
+        if clip.w <= 0.0 {
+            return None;
         }
+        let ndc = Vec2::new(clip.x, clip.y) / clip.w;
+        if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 {
+            return None;
+        }
+        Some(Vec2::new(ndc.x + 1.0, 1.0 - ndc.y) / 2.0)
     }
 
     pub fn get_pos(&self, mats: Option<&(Mat4<f32>, Mat4<f32>)>) -> Vec3<f32> {
@@ -69,16 +167,47 @@ impl Camera {
     #[allow(dead_code)]
     pub fn ori(&self) -> &Vec2<f32> { &self.ori }
 
+    /// Turn the camera to face `yaw` radians around its focus, keeping its pitch
+    pub fn set_yaw(&mut self, yaw: f32) { self.ori.x = yaw; }
+
+    /// Set the width of the view over its height. The vertical field of view is kept, so a wider window shows more to
+    /// the sides rather than less above and below.
+    pub fn set_aspect_ratio(&mut self, ratio: f32) {
+        if ratio.is_finite() && ratio > 0.0 {
+            self.aspect_ratio = ratio;
+        }
+    }
+
+    /// The vertical field of view being drawn with, in radians
+    pub fn get_fov(&self) -> f32 { self.fov }
+
+    /// Set the vertical field of view in degrees, between 60 and 110
+    pub fn set_fov(&mut self, degrees: f32) {
+        let fov = degrees.max(MIN_FOV).min(MAX_FOV).to_radians();
+        self.fov += fov - self.base_fov;
+        self.base_fov = fov;
+    }
+
+    /// Widen the view a little, as a cue to going fast. It eases in and out as the camera is updated.
+    pub fn set_widened(&mut self, widened: bool) { self.widened = widened; }
+
+    /// Set the distances from the camera that things are drawn between. The depth buffer's precision is spread across
+    /// them, so the far plane should be no further than the furthest thing drawn.
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        self.near = near;
+        self.far = far.max(near * 2.0);
+    }
+    pub fn get_focus(&self) -> Vec3<f32> { self.focus }
+
+    pub fn set_focus(&mut self, focus: Vec3<f32>) { self.focus = focus; }
     #[allow(dead_code)]
-    pub fn set_aspect_ratio(&mut self, ratio: f32) { self.aspect_ratio = ratio; }
-    #[allow(dead_code)]
-    pub fn get_fov(&mut self) -> f32 { self.fov }
+    pub fn get_zoom(&mut self) -> f32 { self.zoom }
     #[allow(dead_code)]
-    pub fn set_fov(&mut self, fov: f32) { self.fov = fov; }
+    pub fn set_zoom(&mut self, zoom: f32) { self.zoom = zoom.max(MIN_ZOOM).min(MAX_ZOOM); }
     #[allow(dead_code)]
-    pub fn set_focus(&mut self, focus: Vec3<f32>) { self.focus = focus; }
+    pub fn set_sensitivity(&mut self, sensitivity: Vec2<f32>) { self.sensitivity = sensitivity; }
     #[allow(dead_code)]
-    pub fn get_zoom(&mut self) -> f32 { self.zoom }
+    pub fn set_invert_y(&mut self, invert_y: bool) { self.invert_y = invert_y; }
     #[allow(dead_code)]
-    pub fn set_zoom(&mut self, zoom: f32) { self.zoom = zoom; }
+    pub fn set_zoom_speed(&mut self, zoom_speed: f32) { self.zoom_speed = zoom_speed; }
 }
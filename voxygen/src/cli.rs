@@ -0,0 +1,146 @@
+// Standard
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+};
+
+// Library
+use clap::{App, Arg, ArgMatches};
+
+// Local
+use crate::settings::Settings;
+
+// Constants
+pub const DEFAULT_SERVER: &str = "veloren.pftclan.de:38888";
+pub const DEFAULT_PORT: u16 = 59003;
+
+pub enum Target {
+    Singleplayer,
+    Remote(SocketAddr),
+}
+
+pub struct LaunchOptions {
+    /// Where to connect straight away. The main menu is shown if this isn't given.
+    pub target: Option<Target>,
+    pub alias: Option<String>,
+    /// Where to record the player's movement, to replay with `headless --replay`
+    pub record: Option<PathBuf>,
+    // Settings with any command line overrides applied. These aren't saved.
+    pub settings: Settings,
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("Voxygen")
+        .about("The Veloren game client")
+        .version(option_env!("CARGO_PKG_VERSION").unwrap_or("UNKNOWN_VERSION"))
+        .arg(
+            Arg::with_name("address")
+                .value_name("ADDRESS")
+                .help("Server to connect to straight away, with an optional port (e.g: 127.0.0.1:59003)")
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .value_name("PORT")
+                .help("Server port, used if the address doesn't specify one")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("singleplayer")
+                .short("s")
+                .long("singleplayer")
+                .help("Play offline on a server running in this process")
+                .conflicts_with_all(&["address", "port"]),
+        )
+        .arg(
+            Arg::with_name("alias")
+                .short("n")
+                .long("alias")
+                .value_name("ALIAS")
+                .help("Player name (randomly generated by default)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("view-distance")
+                .short("d")
+                .long("view-distance")
+                .value_name("BLOCKS")
+                .help("View distance in blocks")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fullscreen")
+                .short("f")
+                .long("fullscreen")
+                .help("Start in fullscreen, overriding the settings file")
+                .conflicts_with("windowed"),
+        )
+        .arg(
+            Arg::with_name("windowed")
+                .short("w")
+                .long("windowed")
+                .help("Start in a window, overriding the settings file"),
+        )
+        .arg(Arg::with_name("no-vsync").long("no-vsync").help("Disable vertical sync"))
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .value_name("FILE")
+                .help("Record the player's movement to FILE, to find where it differs from the server's")
+                .takes_value(true),
+        )
+}
+
+/// Resolve a server address, using `port` if the address doesn't include one
+pub fn resolve_addr(addr: &str, port: u16) -> Result<SocketAddr, String> {
+    let resolved = if addr.contains(':') {
+        addr.to_socket_addrs()
+    } else {
+        (addr, port).to_socket_addrs()
+    };
+
+    resolved
+        .map_err(|e| format!("Could not resolve server address '{}': {}", addr, e))?
+        .next()
+        .ok_or_else(|| format!("Server address '{}' did not resolve to anything", addr))
+}
+
+/// Parse the command line. Errors are reported as user-facing messages.
+pub fn parse(settings: Settings) -> Result<LaunchOptions, String> { from_matches(&app().get_matches(), settings) }
+
+fn from_matches(m: &ArgMatches, mut settings: Settings) -> Result<LaunchOptions, String> {
+    let port = match m.value_of("port") {
+        Some(p) => p.parse().map_err(|_| format!("Invalid port '{}'", p))?,
+        None => DEFAULT_PORT,
+    };
+
+    let target = if m.is_present("singleplayer") {
+        Some(Target::Singleplayer)
+    } else {
+        match m.value_of("address") {
+            Some(addr) => Some(Target::Remote(resolve_addr(addr, port)?)),
+            None => None,
+        }
+    };
+
+    if let Some(d) = m.value_of("view-distance") {
+        settings.graphics.view_distance = d.parse().map_err(|_| format!("Invalid view distance '{}'", d))?;
+    }
+    if m.is_present("fullscreen") {
+        settings.graphics.fullscreen = true;
+    } else if m.is_present("windowed") {
+        settings.graphics.fullscreen = false;
+    }
+    if m.is_present("no-vsync") {
+        settings.graphics.vsync = false;
+    }
+
+    Ok(LaunchOptions {
+        target,
+        alias: m.value_of("alias").map(|a| a.to_string()),
+        record: m.value_of("record").map(PathBuf::from),
+        settings,
+    })
+}
@@ -0,0 +1,115 @@
+/// Completes the word being typed in a line of chat, against the names of players and the commands the server has
+/// told us about. Completing the same word again moves on to the next candidate, until the line is changed some other
+/// way.
+pub struct Completer {
+    players: Vec<String>,
+    commands: Vec<String>,
+    cycle: Option<Cycle>,
+}
+
+// A word part way through being completed
+struct Cycle {
+    // The line either side of the word
+    before: String,
+    after: String,
+    candidates: Vec<String>,
+    selected: usize,
+    // The line and cursor as the last completion left them. Anything else means the player has moved on.
+    line: String,
+    cursor: usize,
+}
+
+impl Completer {
+    pub fn new() -> Self {
+        Self {
+            players: vec![],
+            commands: vec![],
+            cycle: None,
+        }
+    }
+
+    /// Replace the words that can be completed. Commands are given without their leading `/`.
+    pub fn set_words(&mut self, players: Vec<String>, commands: Vec<String>) {
+        self.players = players;
+        self.commands = commands;
+    }
+
+    /// Complete the word just before `cursor`, a byte offset into `line`, leaving the rest of the line alone. Returns
+    /// the new line and where the cursor goes in it, or `None` if nothing matches. `backwards` cycles the other way.
+    pub fn complete(&mut self, line: &str, cursor: usize, backwards: bool) -> Option<(String, usize)> {
+        let cycle = match self.cycle.take() {
+            Some(mut cycle) if cycle.line == line && cycle.cursor == cursor => {
+                let len = cycle.candidates.len();
+                cycle.selected = if backwards {
+                    (cycle.selected + len - 1) % len
+                } else {
+                    (cycle.selected + 1) % len
+                };
+                cycle
+            },
+            _ => {
+                let (before, word) = line[..cursor].split_at(word_start(&line[..cursor]));
+                let candidates = self.candidates_for(before, word);
+                if candidates.is_empty() {
+                    return None;
+                }
+                Cycle {
+                    before: before.to_string(),
+                    after: line[cursor..].to_string(),
+                    selected: if backwards { candidates.len() - 1 } else { 0 },
+                    candidates,
+                    line: String::new(),
+                    cursor: 0,
+                }
+            },
+        };
+
+        let completed = format!("{}{}", cycle.before, cycle.candidates[cycle.selected]);
+        let cursor = completed.len();
+        let line = completed + &cycle.after;
+        self.cycle = Some(Cycle {
+            line: line.clone(),
+            cursor,
+            ..cycle
+        });
+        Some((line, cursor))
+    }
+
+    /// Forget the word being completed, so the next completion starts afresh
+    pub fn reset(&mut self) { self.cycle = None; }
+
+    /// What the word being completed could be, in the order they're cycled through
+    pub fn candidates(&self) -> &[String] { self.cycle.as_ref().map(|c| &c.candidates[..]).unwrap_or(&[]) }
+
+    /// Which of the candidates is in the line at the moment
+    pub fn selected(&self) -> Option<usize> { self.cycle.as_ref().map(|c| c.selected) }
+
+    // Commands are only completed as the first word of the line, and player names anywhere else
+    fn candidates_for(&self, before: &str, word: &str) -> Vec<String> {
+        let mut candidates: Vec<String> = if before.trim().is_empty() && word.starts_with('/') {
+            matching(&self.commands, &word[1..])
+                .map(|cmd| format!("/{}", cmd))
+                .collect()
+        } else {
+            matching(&self.players, word).cloned().collect()
+        };
+        candidates.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()).then(a.cmp(b)));
+        candidates.dedup();
+        candidates
+    }
+}
+
+// Where the last word in `text` starts
+fn word_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0)
+}
+
+// The words that start with `prefix`, ignoring case
+fn matching<'a>(words: &'a [String], prefix: &str) -> impl Iterator<Item = &'a String> {
+    let prefix = prefix.to_lowercase();
+    words.iter().filter(move |w| w.to_lowercase().starts_with(&prefix))
+}
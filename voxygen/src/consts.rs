@@ -8,12 +8,19 @@ use crate::renderer::Renderer;
 
 gfx_defines! {
     constant GlobalConsts {
+        // Everything's drawn relative to `render_origin`, so the matrices and origins below are all relative to it
         view_mat: [[f32; 4]; 4] = "view_mat",
         proj_mat: [[f32; 4]; 4] = "proj_mat",
         cam_origin: [f32; 4] = "cam_origin",
         play_origin: [f32; 4] = "play_origin",
         view_distance: [f32; 4] = "view_distance",
         time: [f32; 4] = "time",
+        // Distance from the player at which fog starts (x) and fully hides terrain (y)
+        fog: [f32; 4] = "fog",
+        // Where in the world the render origin is, for shaders that need world positions
+        render_origin: [f32; 4] = "render_origin",
+        // How much the weather darkens the sky (x) and how heavily it's raining or snowing (y), both from 0 to 1
+        weather: [f32; 4] = "weather",
     }
 }
 
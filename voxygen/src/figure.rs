@@ -0,0 +1,279 @@
+// Standard
+use std::{collections::HashMap, f32::consts::PI, fmt, fs, io, path::Path};
+
+// Library
+use dot_vox;
+use serde_derive::Deserialize;
+use toml;
+use vek::*;
+
+// Project
+use common::{
+    ecs::inventory::ItemKind,
+    get_asset_path,
+    terrain::{chunk::Block, figure::Figure, ConstructVolume},
+};
+
+// Local
+use crate::{
+    anim::Pose,
+    consts::{ConstHandle, GlobalConsts},
+    renderer::Renderer,
+    voxel::{self, InstanceData, InstancedModel, ModelConsts, VolumePipeline},
+};
+
+// Constants
+/// How big dropped items are drawn, in blocks
+pub const ITEM_SIZE: f32 = 0.4;
+/// How far dropped items bob up off the ground, in blocks
+pub const ITEM_BOB_HEIGHT: f32 = 0.2;
+/// The manifest characters are drawn from, relative to the asset directory
+pub const CHARACTER_MANIFEST: &str = "voxygen/cosmetic/creature/friendly/knight.toml";
+// How many times a second dropped items bob up and down
+const ITEM_BOB_FREQ: f32 = 0.5;
+// How fast dropped items spin, in radians per second
+const ITEM_SPIN_SPEED: f32 = 1.5;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Vox(String),
+    Invalid(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error { Error::Io(err) }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Error { Error::Toml(err) }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Toml(e) => write!(f, "{}", e),
+            Error::Vox(e) => write!(f, "{}", e),
+            Error::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartKind {
+    Head,
+    Torso,
+    LeftArm,
+    RightArm,
+    LeftLeg,
+    RightLeg,
+}
+
+impl PartKind {
+    /// How far the part swings around its pivot in `pose`, in radians. Each arm swings the opposite way to the leg on
+    /// its side.
+    pub fn swing(&self, pose: &Pose) -> f32 {
+        match self {
+            PartKind::LeftLeg | PartKind::RightArm => pose.swing,
+            PartKind::RightLeg | PartKind::LeftArm => -pose.swing,
+            PartKind::Head | PartKind::Torso => 0.0,
+        }
+    }
+}
+
+/// One part of a segmented character, as listed in its manifest
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct PartSpec {
+    pub kind: PartKind,
+    /// The part's .vox file, relative to the asset directory
+    pub model: String,
+    /// The point the part turns around, relative to the character's feet
+    pub pivot: [f32; 3],
+    /// Where the part's model sits relative to its pivot
+    pub offset: [f32; 3],
+}
+
+/// The parts a character is made of
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Manifest {
+    pub parts: Vec<PartSpec>,
+}
+
+impl Manifest {
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Manifest, Error> {
+        let manifest: Manifest = toml::from_str(&fs::read_to_string(path)?)?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.parts.is_empty() {
+            return Err(Error::Invalid("the manifest has no parts".to_string()));
+        }
+        for (i, part) in self.parts.iter().enumerate() {
+            if self.parts[..i].iter().any(|p| p.kind == part.kind) {
+                return Err(Error::Invalid(format!("{:?} is listed more than once", part.kind)));
+            }
+            if part.pivot.iter().chain(part.offset.iter()).any(|e| !e.is_finite()) {
+                return Err(Error::Invalid(format!("{:?} isn't anywhere", part.kind)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where a part goes for an entity whose model matrix is `entity_mat`, turned around its pivot for `pose`
+pub fn part_mat(entity_mat: Mat4<f32>, kind: PartKind, pivot: Vec3<f32>, pose: &Pose) -> Mat4<f32> {
+    entity_mat * Mat4::<f32>::translation_3d(pivot) * Mat4::rotation_x(kind.swing(pose))
+}
+
+/// The models an entity is drawn with
+pub enum CharacterModel {
+    /// Separately posed parts, each with the point it turns around
+    Segmented(Vec<(PartKind, Vec3<f32>, InstancedModel)>),
+    /// A single model for characters without a manifest, which can only be posed as a whole
+    Single(InstancedModel),
+}
+
+impl CharacterModel {
+    /// Load the character described by the manifest at `manifest`. Characters without a manifest, or whose parts can't
+    /// be loaded, are drawn with the single model at `fallback` instead.
+    pub fn load(renderer: &mut Renderer, manifest: &str, fallback: &str, fallback_offset: Vec3<f32>) -> CharacterModel {
+        match CharacterModel::load_segmented(renderer, manifest) {
+            Ok(model) => model,
+            Err(e) => {
+                match e {
+                    Error::Io(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+                    e => warn!("failed to load {}: {}, using {} instead", manifest, e, fallback),
+                }
+                let model = load_vox(renderer, fallback, fallback_offset)
+                    .expect("Cannot find the character model. Make sure to start voxygen from its folder");
+                CharacterModel::Single(InstancedModel::new(model))
+            },
+        }
+    }
+
+    fn load_segmented(renderer: &mut Renderer, manifest: &str) -> Result<CharacterModel, Error> {
+        let manifest = Manifest::load_from(get_asset_path(manifest))?;
+        let parts = manifest
+            .parts
+            .iter()
+            .map(|part| {
+                let model = load_vox(renderer, &part.model, Vec3::from(part.offset))?;
+                Ok((part.kind, Vec3::from(part.pivot), InstancedModel::new(model)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(CharacterModel::Segmented(parts))
+    }
+
+    /// How many model matrices drawing the character takes, one for each part
+    pub fn part_count(&self) -> usize {
+        match self {
+            CharacterModel::Segmented(parts) => parts.len(),
+            CharacterModel::Single(_) => 1,
+        }
+    }
+
+    /// The model matrix of each part, for an entity whose model matrix is `entity_mat`
+    pub fn part_mats(&self, entity_mat: Mat4<f32>, pose: &Pose) -> Vec<Mat4<f32>> {
+        match self {
+            CharacterModel::Segmented(parts) => parts
+                .iter()
+                .map(|(kind, pivot, _)| part_mat(entity_mat, *kind, *pivot, pose))
+                .collect(),
+            CharacterModel::Single(_) => vec![entity_mat],
+        }
+    }
+
+    /// Draw each part with the matching model consts from `part_consts`
+    pub fn draw(
+        &self,
+        pipeline: &mut VolumePipeline,
+        part_consts: &[ConstHandle<ModelConsts>],
+        global_consts: &ConstHandle<GlobalConsts>,
+    ) {
+        match self {
+            CharacterModel::Segmented(parts) => {
+                for ((_, _, model), consts) in parts.iter().zip(part_consts) {
+                    pipeline.draw_model(model.model(), consts, global_consts);
+                }
+            },
+            CharacterModel::Single(model) => {
+                if let Some(consts) = part_consts.first() {
+                    pipeline.draw_model(model.model(), consts, global_consts);
+                }
+            },
+        }
+    }
+
+    /// Draw the character once for each entity in `entities`, given as the model matrix of each of its parts, with one
+    /// draw call for each part rather than for each part of each entity
+    pub fn draw_instanced(
+        &mut self,
+        pipeline: &mut VolumePipeline,
+        renderer: &mut Renderer,
+        entities: &[&[Mat4<f32>]],
+        global_consts: &ConstHandle<GlobalConsts>,
+    ) {
+        let mut draw_part = |i: usize, model: &mut InstancedModel| {
+            let instances = entities
+                .iter()
+                .filter_map(|part_mats| part_mats.get(i))
+                .map(|part_mat| InstanceData::new(*part_mat, Rgba::broadcast(1.0)))
+                .collect::<Vec<_>>();
+            pipeline.draw_instanced(renderer, model, &instances, global_consts);
+        };
+        match self {
+            CharacterModel::Segmented(parts) => {
+                for (i, (_, _, model)) in parts.iter_mut().enumerate() {
+                    draw_part(i, model);
+                }
+            },
+            CharacterModel::Single(model) => draw_part(0, model),
+        }
+    }
+}
+
+/// A cube for each kind of item, coloured like what the item is made of. Every dropped item of a kind is drawn with the
+/// same model, placed by its own model matrix. Each kind's model is made the first time one is drawn.
+#[derive(Default)]
+pub struct ItemModels(HashMap<ItemKind, InstancedModel>);
+
+impl ItemModels {
+    pub fn get(&mut self, renderer: &mut Renderer, kind: ItemKind) -> &mut InstancedModel {
+        self.0.entry(kind).or_insert_with(|| item_model(renderer, kind))
+    }
+}
+
+fn item_model(renderer: &mut Renderer, kind: ItemKind) -> InstancedModel {
+    let block = match kind {
+        ItemKind::Stone => Block::STONE,
+        ItemKind::Wood => Block::LOG,
+        ItemKind::Apple => Block::GOLD,
+        ItemKind::Sword => Block::LIGHT_COBBLE,
+    };
+    // Centred on the origin, so that it spins around its middle
+    let meshes = voxel::Mesh::from_with_offset(&Figure::filled(Vec3::one(), block), Vec3::broadcast(-0.5), false);
+    InstancedModel::new(voxel::Model::new(renderer, &meshes))
+}
+
+/// Where a dropped item lying at `pos` is drawn at `time`, in seconds: spinning, and bobbing between resting on the
+/// ground and `ITEM_BOB_HEIGHT` above it. Items are a little out of step with each other by `uid`, so that piles don't
+/// move in unison.
+pub fn item_mat(pos: Vec3<f32>, time: f32, uid: u64) -> Mat4<f32> {
+    let phase = (uid % 16) as f32 / 16.0 * 2.0 * PI;
+    let bob = ((time * ITEM_BOB_FREQ * 2.0 * PI + phase).sin() + 1.0) * 0.5 * ITEM_BOB_HEIGHT;
+    Mat4::<f32>::translation_3d(pos + Vec3::unit_z() * (ITEM_SIZE * 0.5 + bob))
+        * Mat4::rotation_z(time * ITEM_SPIN_SPEED + phase)
+        * Mat4::scaling_3d(Vec3::broadcast(ITEM_SIZE))
+}
+
+fn load_vox(renderer: &mut Renderer, path: &str, offset: Vec3<f32>) -> Result<voxel::Model, Error> {
+    let vox = dot_vox::load(get_asset_path(path).to_str().unwrap())
+        .map_err(|e| Error::Vox(format!("{}: {}", path, e)))?;
+    let meshes = voxel::Mesh::from_with_offset(&voxel::vox_to_figure(vox), offset, false);
+    Ok(voxel::Model::new(renderer, &meshes))
+}
@@ -1,17 +1,25 @@
 // Standard
 use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{HashMap, HashSet, VecDeque},
     f32::consts::PI,
+    mem,
     net::ToSocketAddrs,
+    path::Path,
+    rc::Rc,
     sync::{
         atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 // Library
-use dot_vox;
 use fnv::FnvBuildHasher;
 use fps_counter::FPSCounter;
+use gfx::Primitive;
 use glutin::ElementState;
 use indexmap::IndexMap;
 use parking_lot::Mutex;
@@ -20,63 +28,156 @@ use vek::*;
 type FnvIndexMap<K, V> = IndexMap<K, V, FnvBuildHasher>;
 
 // Project
-use client::{self, Client, ClientEvent, PlayMode, CHUNK_SIZE};
+use client::{self, Client, ClientEvent, ClientStatus, EventReceiver, LoadProgress, PlayMode, CHUNK_SIZE};
 use common::{
-    get_asset_path,
+    audio::Group,
+    ecs::{
+        inventory::{ItemKind, HOTBAR_SLOTS},
+        phys::MoveMode,
+    },
     terrain::{
         self,
         chunk::{Chunk, ChunkContainer},
-        Container, VolOffs,
+        ChunkState, Container, Light, VolCluster, VolOffs, VoxAbs, Voxel,
     },
-    util::manager::Manager,
+    physics::physics::LENGTH_OF_BLOCK,
+    util::{jobs::JobHandle, manager::Manager},
 };
 
 // Local
 use crate::{
+    anim::{AnimState, Animation},
     audio::frontend::AudioFrontend,
-    camera::Camera,
+    camera::{Camera, RenderOrigin},
     consts::{ConstHandle, GlobalConsts},
+    figure::{self, CharacterModel, ItemModels, ITEM_SIZE},
     get_shader_path,
-    hud::{Hud, HudEvent},
+    hud::{Hud, HudEvent, NameTag},
     key_state::KeyState,
     keybinds::{Keybinds, VKeyCode},
+    map::MapLayer,
     pipeline::Pipeline,
+    renderer::{DrawItem, Pass, PassQueue, Renderer},
     shader::Shader,
-    skybox, tonemapper, voxel,
+    shader_watcher::ShaderWatcher,
+    outline, shadow, skybox, tonemapper, voxel, weather,
+    settings::Settings,
     window::{Event, RenderWindow},
-    RENDERER_INFO,
 };
 
+// Fraction of the view distance at which fog starts
+const FOG_START: f32 = 0.8;
+// How much closer fog starts in the gloomiest weather, as a fraction of where it would otherwise start
+const STORM_FOG: f32 = 0.5;
+// Frames further apart than this, in seconds, only move rain and snow on this much
+const MAX_WEATHER_STEP: f32 = 0.25;
+// How far above an entity's head its name is shown
+const NAME_TAG_CLEARANCE: f32 = 0.45;
+// Names are at full size up to this far from the camera, in blocks, and shrink beyond it down to `NAME_TAG_MIN_SIZE`
+const NAME_TAG_SIZE_DIST: f32 = 16.0;
+const NAME_TAG_SIZE: f32 = 20.0;
+const NAME_TAG_MIN_SIZE: f32 = 10.0;
+// Names start fading out about 30 m from the camera, and are gone 10 m later
+const NAME_TAG_FADE_START: f32 = 30.0 / LENGTH_OF_BLOCK;
+const NAME_TAG_FADE_END: f32 = 40.0 / LENGTH_OF_BLOCK;
+// Shown instead of the world until the terrain around the player has loaded
+const LOADING_BACKGROUND: Vec3<f32> = Vec3 { x: 0.1, y: 0.1, z: 0.15 };
+// How long to wait between attempts to reconnect. Longer than a connection attempt can take, so they don't overlap.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+const MEGABYTE: usize = 1024 * 1024;
+// How many chunk models are built a frame at most. Any more are left for the frames after, so that lots of chunks
+// arriving at once don't hold a frame up.
+const MAX_CHUNK_UPLOADS: usize = 8;
+// How many stages of cracking a block goes through as it's dug
+const CRACK_STAGES: u32 = 8;
+// How close the player has to be to the world border to see it, and how far above and below them it's drawn
+const BORDER_VISIBLE_DIST: f32 = 32.0;
+const BORDER_HEIGHT: f32 = 64.0;
+// How fast the camera circles the player's body while they're dead, in radians per second
+const DEATH_ORBIT_SPEED: f32 = 0.2;
+// How fast a sprinting player has to be going for the view to widen, in blocks per second
+const SPRINT_FOV_SPEED: f32 = 4.0;
+// Nothing closer to the camera than this is drawn
+const NEAR_PLANE: f32 = 0.1;
+// How much further than the furthest terrain the far plane is, for everything drawn just past it
+const FAR_PLANE_MARGIN: f32 = 16.0;
+// How far the mouse wheel has to turn to move along the hotbar by a slot, which is a line of scrolling
+const SCROLL_PER_SLOT: f64 = 8.0;
+
 pub enum ChunkPayload {
     Meshes(FnvIndexMap<voxel::MaterialKind, voxel::Mesh>),
     Model {
         model: voxel::Model,
         model_consts: ConstHandle<voxel::ModelConsts>,
+        // What `model_consts` places the chunk relative to
+        origin: RenderOrigin,
     },
+    /// The model was dropped to stay within the GPU memory budget. The chunk is meshed again once it's back in view.
+    Evicted,
+}
+
+/// What's drawn for an entity: where each part of its model is, and how it's animated
+pub struct EntityPayload {
+    pub part_consts: Vec<ConstHandle<voxel::ModelConsts>>,
+    /// What's in `part_consts`, for drawing the entity instanced along with others that share its model
+    pub part_mats: Vec<Mat4<f32>>,
+    pub anim: Animation,
+}
+
+/// Why the game stopped running
+pub enum Exit {
+    /// Go back to the main menu, showing the reason if there's one
+    ToMenu(Option<String>),
+    Quit,
 }
 
 pub struct Payloads {}
 impl client::Payloads for Payloads {
     type Chunk = ChunkPayload;
-    type Entity = ConstHandle<voxel::ModelConsts>;
+    type Entity = EntityPayload;
     type Audio = AudioFrontend;
 }
 
 pub struct Game {
-    running: AtomicBool,
+    exit: Mutex<Option<Exit>>,
+    take_screenshot: AtomicBool,
+    // Messages from screenshots that have finished saving, to be shown in the chat
+    screenshot_msgs: Arc<Mutex<Vec<String>>>,
 
     client: Manager<Client<Payloads>>,
-    window: RenderWindow,
+    client_events: EventReceiver,
+    // The client's status as of its last `StatusChanged` event
+    client_status: ClientStatus,
+    // Whether to reconnect if the connection drops, which we don't if the server sent us away
+    reconnect: bool,
+    last_reconnect: Option<Instant>,
+    // The attempt to reconnect that's still going, if there is one
+    reconnecting: Option<JobHandle<Result<(), String>>>,
+    // When the player died and which way the camera faced then, so it can circle their body until they respawn
+    death_orbit: Option<(Instant, f32)>,
+    // When the player's dig was last kept going, to tell how long they've been at it since
+    last_dig: Mutex<Instant>,
+    // When the camera's field of view last eased towards what it should be
+    last_camera_update: Mutex<Instant>,
+    window: Rc<RenderWindow>,
 
     global_consts: ConstHandle<GlobalConsts>,
     camera: Mutex<Camera>,
+    render_origin: Mutex<RenderOrigin>,
 
     key_state: Mutex<KeyState>,
     keys: Keybinds,
+    // Scrolling that hasn't added up to moving along the hotbar yet
+    hotbar_scroll: Mutex<f64>,
 
     skybox_pipeline: Pipeline<skybox::pipeline::Init<'static>>,
     volume_pipeline: voxel::VolumePipeline,
+    outline_pipeline: Pipeline<outline::pipeline::Init<'static>>,
+    border_pipeline: Pipeline<outline::pipeline::Init<'static>>,
+    shadow_pipeline: Pipeline<shadow::pipeline::Init<'static>>,
+    weather_pipeline: Pipeline<weather::pipeline::Init<'static>>,
     tonemapper_pipeline: Pipeline<tonemapper::pipeline::Init<'static>>,
+    shader_watcher: Option<ShaderWatcher>,
 
     hud: Hud,
     audio: Manager<AudioFrontend>,
@@ -84,9 +185,30 @@ pub struct Game {
     fps: FPSCounter,
     last_fps: usize,
 
+    // The GPU memory taken up by chunk models
+    chunk_models: Mutex<voxel::ModelBudget<Vec3<VolOffs>>>,
+    // Chunks whose meshes are ready to be built into models, in the order they were meshed
+    meshed: Arc<Mutex<VecDeque<Vec3<VolOffs>>>>,
+    // Meshed chunks that were out of view, which are looked at again once the player moves into another chunk
+    out_of_view: Mutex<HashSet<Vec3<VolOffs>>>,
+    player_chunk: Mutex<Option<Vec3<VolOffs>>>,
+    chunk_states: Mutex<Receiver<(Vec3<VolOffs>, ChunkState)>>,
+
     skybox_model: skybox::Model,
-    player_model: voxel::Model,
-    other_player_model: voxel::Model,
+    outline_model: outline::Model,
+    border_model: outline::Model,
+    // One for each stage of cracking, drawn over blocks being dug
+    crack_models: Vec<outline::Model>,
+    shadow_model: shadow::Model,
+    // Rain and snow around the player, and when they were last moved on
+    weather: weather::Particles,
+    weather_model: weather::Model,
+    last_weather_update: Instant,
+    player_model: CharacterModel,
+    other_player_model: CharacterModel,
+    item_models: ItemModels,
+
+    settings: Settings,
 }
 
 fn to_4x4(v: &Mat4<f32>) -> [[f32; 4]; 4] {
@@ -99,42 +221,143 @@ fn to_4x4(v: &Mat4<f32>) -> [[f32; 4]; 4] {
     out
 }
 
-fn gen_payload(_key: Vec3<VolOffs>, con: Arc<Mutex<Option<ChunkContainer<<Payloads as client::Payloads>::Chunk>>>>) {
-    let conlock = con.lock();
-    if let Some(ref con) = *conlock {
-        *con.payload_mut() = Some(ChunkPayload::Meshes(match *con.data() {
-            Chunk::Homo(ref homo) => voxel::Mesh::from(homo),
-            Chunk::Hetero(ref hetero) => voxel::Mesh::from(hetero),
-            Chunk::Rle(ref rle) => voxel::Mesh::from(rle),
-            Chunk::HeteroAndRle(ref hetero, _) => voxel::Mesh::from(hetero),
-        }));
+// Place the chunk at `pos` relative to `origin`
+fn update_chunk_consts(
+    renderer: &mut Renderer,
+    consts: &ConstHandle<voxel::ModelConsts>,
+    pos: Vec3<VolOffs>,
+    origin: &RenderOrigin,
+) {
+    let block = terrain::voloffs_to_voxabs(pos, CHUNK_SIZE);
+    consts.update(
+        renderer,
+        voxel::ModelConsts {
+            model_mat: to_4x4(&Mat4::<f32>::translation_3d(origin.relative_block(block))),
+        },
+    );
+}
+
+fn gen_payload(
+    map_layer: &MapLayer,
+    meshed: &Mutex<VecDeque<Vec3<VolOffs>>>,
+    key: Vec3<VolOffs>,
+    con: &ChunkContainer<<Payloads as client::Payloads>::Chunk>,
+    neighbours: &HashMap<Vec3<VolOffs>, Arc<ChunkContainer<<Payloads as client::Payloads>::Chunk>>>,
+) {
+    // Blocks are locked before light, as the chunk manager does, and chunks are locked top down like it locks the
+    // chunk above before the one below, so that meshing never waits on a lock while holding one the other is after
+    let mut chunks = neighbours
+        .iter()
+        .map(|(k, c)| (*k, &**c))
+        .chain(Some((key, con)))
+        .collect::<Vec<_>>();
+    chunks.sort_by_key(|(k, _)| (Reverse(k.z), k.y, k.x));
+    let blocks = chunks.iter().map(|(k, c)| (*k, c.data())).collect::<HashMap<_, _>>();
+    let lights = chunks.iter().map(|(k, c)| (*k, c.light())).collect::<HashMap<_, _>>();
+    let data = &blocks[&key];
+    if let Some(vol) = data.prefered() {
+        map_layer.update(key, vol);
+    }
+    let origin = terrain::voloffs_to_voxabs(key, CHUNK_SIZE);
+    // Neighbours that haven't arrived yet are treated as empty. The chunk is meshed again once they do.
+    let outside = |pos: Vec3<i64>| {
+        let abs = origin + pos;
+        blocks
+            .get(&terrain::voxabs_to_voloffs(abs, CHUNK_SIZE))
+            .and_then(|chunk| chunk.prefered()?.at(terrain::voxabs_to_voxrel(abs, CHUNK_SIZE)))
+    };
+    // Like empty chunks, chunks that haven't been lit yet are taken to be out in the open
+    let light = |pos: Vec3<i64>| {
+        let abs = origin + pos;
+        let chunk = terrain::voxabs_to_voloffs(abs, CHUNK_SIZE);
+        lights
+            .get(&chunk)
+            .and_then(|light| light.as_ref())
+            .map(|light| light.get(terrain::voxabs_to_voxrel(abs, CHUNK_SIZE)))
+            .unwrap_or(Light::SUNLIT)
+    };
+
+    *con.payload_mut() = Some(ChunkPayload::Meshes(match **data {
+        Chunk::Homo(ref homo) => voxel::Mesh::from_with_neighbours(homo, outside, light),
+        Chunk::Hetero(ref hetero) => voxel::Mesh::from_with_neighbours(hetero, outside, light),
+        Chunk::Rle(ref rle) => voxel::Mesh::from_with_neighbours(rle, outside, light),
+        Chunk::HeteroAndRle(ref hetero, _) => voxel::Mesh::from_with_neighbours(hetero, outside, light),
+    }));
+    meshed.lock().push_back(key);
+}
+
+// Whether a key is the one bound to an action
+fn keypress_eq(key: &Option<VKeyCode>, input: Option<glutin::VirtualKeyCode>) -> bool {
+    if let (Some(i), Some(k)) = (input, key) {
+        k.code() == i
+    } else {
+        false
+    }
+}
+
+// The hotbar slot a number key picks, counting from 1 like the keys along the top of the keyboard
+fn hotbar_slot(key: Option<glutin::VirtualKeyCode>) -> Option<usize> {
+    use glutin::VirtualKeyCode::*;
+    match key? {
+        Key1 => Some(0),
+        Key2 => Some(1),
+        Key3 => Some(2),
+        Key4 => Some(3),
+        Key5 => Some(4),
+        Key6 => Some(5),
+        Key7 => Some(6),
+        Key8 => Some(7),
+        Key9 => Some(8),
+        _ => None,
     }
 }
 
 fn drop_payload(_key: Vec3<VolOffs>, _con: Arc<ChunkContainer<<Payloads as client::Payloads>::Chunk>>) {}
 
 impl Game {
-    pub fn new<R: ToSocketAddrs>(mode: PlayMode, alias: &str, remote_addr: R, view_distance: i64) -> Game {
-        let window = RenderWindow::new();
-        let info = window.get_renderer_info();
-        println!(
-            "Graphics card info - vendor: {} model: {} OpenGL: {}",
-            info.vendor, info.model, info.gl_version
-        );
-        *RENDERER_INFO.lock() = Some(info);
-
+    /// Connect to a server and get ready to play on it, drawing to `window`
+    pub fn new<R: ToSocketAddrs>(
+        mode: PlayMode,
+        alias: &str,
+        remote_addr: R,
+        window: Rc<RenderWindow>,
+        settings: Settings,
+        record: Option<&Path>,
+    ) -> Result<Game, String> {
         let audio = AudioFrontend::new();
 
+        // The minimap is drawn from the chunks as their payloads are generated, off the render thread
+        let map_layer = Arc::new(MapLayer::new());
+        let layer = map_layer.clone();
+        let meshed = Arc::new(Mutex::new(VecDeque::new()));
+        let ready = meshed.clone();
         let client = Client::new(
             mode,
             alias.to_string(),
             remote_addr,
-            gen_payload,
+            settings.network.encryption,
+            move |key: Vec3<VolOffs>,
+                  con: &ChunkContainer<ChunkPayload>,
+                  neighbours: &HashMap<Vec3<VolOffs>, Arc<ChunkContainer<ChunkPayload>>>| {
+                gen_payload(&layer, &ready, key, con, neighbours)
+            },
             drop_payload,
             Manager::<AudioFrontend>::internal(&audio).clone(),
-            view_distance,
+            settings.graphics.view_distance,
+            record,
         )
-        .expect("Could not create new client");
+        .map_err(|e| match e {
+            client::Error::Refused(reason) => reason,
+            e => format!("Could not connect to the server: {:?}", e),
+        })?;
+
+        let chunk_states = client.chunk_mgr().subscribe();
+        let audio_mgr = client.audio_mgr();
+        audio_mgr.set_group_volume(Group::Master, settings.audio.master_volume);
+        audio_mgr.set_group_volume(Group::Music, settings.audio.music_volume);
+        audio_mgr.set_group_volume(Group::Sfx, settings.audio.sfx_volume);
+        audio_mgr.set_group_volume(Group::Ambience, settings.audio.ambience_volume);
+        audio_mgr.mute(Group::Master, settings.audio.muted);
 
         // Contruct the UI
         let _window_dims = window.get_size();
@@ -150,6 +373,37 @@ impl Game {
             &Shader::from_file(get_shader_path("skybox/skybox.frag")).expect("Could not load skybox fragment shader"),
         );
 
+        let outline_pipeline = Pipeline::with_primitive(
+            window.renderer_mut().factory_mut(),
+            outline::pipeline::new(),
+            &Shader::from_file(get_shader_path("outline/outline.vert")).expect("Could not load outline vertex shader"),
+            &Shader::from_file(get_shader_path("outline/outline.frag"))
+                .expect("Could not load outline fragment shader"),
+            Primitive::LineList,
+        );
+
+        let border_pipeline = Pipeline::new(
+            window.renderer_mut().factory_mut(),
+            outline::pipeline::new(),
+            &Shader::from_file(get_shader_path("border/border.vert")).expect("Could not load border vertex shader"),
+            &Shader::from_file(get_shader_path("border/border.frag")).expect("Could not load border fragment shader"),
+        );
+
+        let shadow_pipeline = Pipeline::new(
+            window.renderer_mut().factory_mut(),
+            shadow::pipeline::new(),
+            &Shader::from_file(get_shader_path("shadow/shadow.vert")).expect("Could not load shadow vertex shader"),
+            &Shader::from_file(get_shader_path("shadow/shadow.frag")).expect("Could not load shadow fragment shader"),
+        );
+
+        let weather_pipeline = Pipeline::new(
+            window.renderer_mut().factory_mut(),
+            weather::pipeline::new(),
+            &Shader::from_file(get_shader_path("weather/weather.vert")).expect("Could not load weather vertex shader"),
+            &Shader::from_file(get_shader_path("weather/weather.frag"))
+                .expect("Could not load weather fragment shader"),
+        );
+
         let tonemapper_pipeline = Pipeline::new(
             window.renderer_mut().factory_mut(),
             tonemapper::pipeline::new(),
@@ -159,104 +413,245 @@ impl Game {
                 .expect("Could not load skybox fragment shader"),
         );
 
+        let shader_watcher = if ShaderWatcher::enabled() {
+            let watcher = ShaderWatcher::new();
+            watcher.watch(skybox_pipeline.sources());
+            watcher.watch(&volume_pipeline.sources());
+            watcher.watch(outline_pipeline.sources());
+            watcher.watch(border_pipeline.sources());
+            watcher.watch(shadow_pipeline.sources());
+            watcher.watch(weather_pipeline.sources());
+            watcher.watch(tonemapper_pipeline.sources());
+            Some(watcher)
+        } else {
+            None
+        };
+
         let global_consts = ConstHandle::new(&mut window.renderer_mut());
 
         let skybox_mesh = skybox::Mesh::new_skybox();
         let skybox_model = skybox::Model::new(&mut window.renderer_mut(), &skybox_mesh);
 
-        info!("trying to load model files");
-        let vox = dot_vox::load(
-            get_asset_path("voxygen/cosmetic/creature/friendly/knight.vox")
-                .to_str()
-                .unwrap(),
-        )
-        .expect("cannot find model player6.vox. Make sure to start voxygen from its folder");
-        let voxmodel = voxel::vox_to_figure(vox);
-
-        let player_meshes = voxel::Mesh::from_with_offset(&voxmodel, Vec3::new(-10.0, -4.0, 0.0), false);
-
-        let player_model = voxel::Model::new(&mut window.renderer_mut(), &player_meshes);
-
-        let vox = dot_vox::load(
-            get_asset_path("voxygen/cosmetic/creature/friendly/knight.vox")
-                .to_str()
-                .unwrap(),
-        )
-        .expect("cannot find model player7.vox. Make sure to start voxygen from its folder");
-        let voxmodel = voxel::vox_to_figure(vox);
-
-        let other_player_meshes = voxel::Mesh::from_with_offset(&voxmodel, Vec3::new(-10.0, -4.0, 0.0), false);
+        let outline_model = outline::Model::new(&mut window.renderer_mut(), &outline::Mesh::new_cube(0.005));
+        let border_model = outline::Model::new(&mut window.renderer_mut(), &outline::Mesh::new_walls());
+        let crack_models = (1..=CRACK_STAGES)
+            .map(|stage| {
+                let mesh = outline::Mesh::new_cracks(stage, CRACK_STAGES, 0.005);
+                outline::Model::new(&mut window.renderer_mut(), &mesh)
+            })
+            .collect();
 
-        let other_player_model = voxel::Model::new(&mut window.renderer_mut(), &other_player_meshes);
+        info!("trying to load model files");
+        let player_model = CharacterModel::load(
+            &mut window.renderer_mut(),
+            figure::CHARACTER_MANIFEST,
+            "voxygen/cosmetic/creature/friendly/knight.vox",
+            Vec3::new(-10.0, -4.0, 0.0),
+        );
+        let other_player_model = CharacterModel::load(
+            &mut window.renderer_mut(),
+            figure::CHARACTER_MANIFEST,
+            "voxygen/cosmetic/creature/friendly/knight.vox",
+            Vec3::new(-10.0, -4.0, 0.0),
+        );
+        let item_models = ItemModels::default();
 
-        Game {
-            running: AtomicBool::new(true),
+        Ok(Game {
+            exit: Mutex::new(None),
+            take_screenshot: AtomicBool::new(false),
+            screenshot_msgs: Arc::new(Mutex::new(vec![])),
 
+            client_events: client.subscribe(),
             client,
+            client_status: ClientStatus::Connected,
+            reconnect: true,
+            last_reconnect: None,
+            reconnecting: None,
+            death_orbit: None,
+            last_dig: Mutex::new(Instant::now()),
+            last_camera_update: Mutex::new(Instant::now()),
             window,
 
             global_consts,
-            camera: Mutex::new(Camera::new()),
+            camera: Mutex::new({
+                let mut camera = Camera::new();
+                camera.set_fov(settings.graphics.fov);
+                camera.set_sensitivity(Vec2::new(
+                    settings.controls.mouse_sensitivity_x,
+                    settings.controls.mouse_sensitivity_y,
+                ));
+                camera.set_invert_y(settings.controls.invert_mouse_y);
+                camera.set_zoom_speed(settings.controls.zoom_speed);
+                camera
+            }),
+            render_origin: Mutex::new(RenderOrigin::default()),
 
             key_state: Mutex::new(KeyState::new()),
             keys: Keybinds::new(),
+            hotbar_scroll: Mutex::new(0.0),
 
             skybox_pipeline,
             volume_pipeline,
+            outline_pipeline,
+            border_pipeline,
+            shadow_pipeline,
+            weather_pipeline,
             tonemapper_pipeline,
+            shader_watcher,
 
-            hud: Hud::new(),
+            hud: Hud::new(map_layer, settings.graphics.minimap_rotates),
             audio,
 
             fps: FPSCounter::new(),
             last_fps: 60,
 
+            chunk_models: Mutex::new(voxel::ModelBudget::new(settings.graphics.chunk_memory as usize * MEGABYTE)),
+            meshed,
+            out_of_view: Mutex::new(HashSet::new()),
+            player_chunk: Mutex::new(None),
+            chunk_states: Mutex::new(chunk_states),
+
             skybox_model,
+            outline_model,
+            border_model,
+            crack_models,
+            shadow_model: shadow::Model::new(),
+            weather: weather::Particles::new(),
+            weather_model: weather::Model::new(),
+            last_weather_update: Instant::now(),
             player_model,
             other_player_model,
+            item_models,
+
+            settings,
+        })
+    }
+
+    // Move along the hotbar a slot for each line scrolled, wrapping round at the ends. Scrolling up goes left.
+    fn scroll_hotbar(&self, dy: f64) {
+        let mut scroll = self.hotbar_scroll.lock();
+        *scroll += dy;
+        let steps = (*scroll / SCROLL_PER_SLOT).trunc();
+        *scroll -= steps * SCROLL_PER_SLOT;
+        if steps != 0.0 {
+            let slot = (self.client.held_slot() as i64 - steps as i64).mod_euc(HOTBAR_SLOTS as i64);
+            self.client.select_slot(slot as usize);
+        }
+    }
+
+    // Open the pause menu and let go of the cursor, or close it and take hold of the cursor again
+    fn toggle_pause(&self) {
+        self.hud.toggle_pause();
+        if self.hud.is_paused() || self.death_orbit.is_some() {
+            self.window.untrap_cursor();
+        } else {
+            self.window.trap_cursor();
         }
+        // Keys let go of while paused never reach us, so stop moving
+        *self.key_state.lock() = KeyState::new();
     }
 
     pub fn handle_window_events(&self) {
         self.window.handle_events(|event| {
+            // Escape (by default) opens and closes the pause menu, which takes all other input while it's open
+            if let Event::KeyboardInput { ref i, .. } = event {
+                if keypress_eq(&self.keys.general.pause, i.virtual_keycode) {
+                    if i.state == ElementState::Released {
+                        self.toggle_pause();
+                    }
+                    return true;
+                }
+            }
+
             // TODO: Experimental
             if true && self.hud.handle_event(&event, &mut self.window.renderer_mut()) {
                 return true;
             }
 
             match event {
-                Event::CloseRequest => self.running.store(false, Ordering::Relaxed),
+                Event::CloseRequest => self.stop(Exit::Quit),
                 Event::CursorMoved { dx, dy } => {
-                    if self.window.cursor_trapped().load(Ordering::Relaxed) {
-                        self.camera
-                            .lock()
-                            .rotate_by(Vec2::new(dx as f32 * 0.002, dy as f32 * 0.002));
+                    if self.window.cursor_trapped().load(Ordering::Relaxed) && self.death_orbit.is_none() {
+                        self.camera.lock().rotate_by(Vec2::new(dx as f32, dy as f32));
+                    }
+                },
+                // Scrolling moves along the hotbar, and zooms the camera while Alt is held
+                Event::MouseWheel { dy, modifiers, .. } => {
+                    if modifiers.alt {
+                        self.camera.lock().zoom_by(-dy as f32);
+                    } else {
+                        self.scroll_hotbar(dy);
                     }
                 },
-                Event::MouseWheel { dy, .. } => {
-                    self.camera.lock().zoom_by((-dy / 4.0) as f32);
+                Event::MouseButton {
+                    state,
+                    button: glutin::MouseButton::Left,
+                } => {
+                    // Clicking to take hold of the cursor doesn't start digging
+                    let trapped = self.window.cursor_trapped().load(Ordering::Relaxed);
+                    self.key_state.lock().dig = trapped && state == ElementState::Pressed;
                 },
                 Event::KeyboardInput { i, .. } => {
-                    // Helper function to determine scancode equality
-                    fn keypress_eq(key: &Option<VKeyCode>, input: Option<glutin::VirtualKeyCode>) -> bool {
-                        if let (Some(i), Some(k)) = (input, key) {
-                            k.code() == i
-                        } else {
-                            false
-                        }
-                    }
-
                     // Helper variables to clean up code. Add any new input modes here.
                     let general = &self.keys.general;
 
                     // General inputs -------------------------------------------------------------
-                    if keypress_eq(&general.pause, i.virtual_keycode) {
-                        // Default: Escape (free cursor)
-                        self.window.untrap_cursor();
+                    let toggle_fullscreen = keypress_eq(&general.fullscreen, i.virtual_keycode)
+                        || (i.modifiers.alt && i.virtual_keycode == Some(glutin::VirtualKeyCode::Return));
+                    if toggle_fullscreen {
+                        // Default: F11 or Alt+Enter (toggle fullscreen)
+                        if i.state == ElementState::Released {
+                            self.window.set_fullscreen(!self.window.is_fullscreen());
+                        }
+                    } else if keypress_eq(&general.vsync, i.virtual_keycode) {
+                        // Default: F10 (toggle vsync)
+                        if i.state == ElementState::Released {
+                            self.window.set_vsync(!self.window.is_vsync());
+                        }
+                    } else if keypress_eq(&general.screenshot, i.virtual_keycode) {
+                        // Default: F2 (take a screenshot of the next frame)
+                        if i.state == ElementState::Released {
+                            self.take_screenshot.store(true, Ordering::Relaxed);
+                        }
+                    } else if keypress_eq(&general.toggle_ui, i.virtual_keycode) {
+                        // Default: F1 (hide or show the HUD, say for screenshots)
+                        if i.state == ElementState::Released {
+                            self.window.renderer_mut().passes_mut().toggle(Pass::Ui);
+                        }
+                    } else if keypress_eq(&general.toggle_debug, i.virtual_keycode) {
+                        // Default: F3 (hide or show the debug box)
+                        if i.state == ElementState::Released {
+                            self.window.renderer_mut().passes_mut().toggle(Pass::Debug);
+                        }
                     } else if keypress_eq(&general.use_item, i.virtual_keycode) {
                         // Default: Ctrl+Q (quit) (temporary)
                         if i.modifiers.ctrl {
-                            self.running.store(false, Ordering::Relaxed);
+                            self.stop(Exit::Quit);
+                        }
+                    } else if keypress_eq(&general.undo, i.virtual_keycode) {
+                        // Default: Ctrl+Z (undo the last block change)
+                        if i.modifiers.ctrl && i.state == ElementState::Pressed {
+                            self.client.undo_last_edit();
+                        }
+                    } else if keypress_eq(&general.redo, i.virtual_keycode) {
+                        // Default: Ctrl+Y (redo the last undone block change)
+                        if i.modifiers.ctrl && i.state == ElementState::Pressed {
+                            self.client.redo_last_edit();
+                        }
+                    } else if keypress_eq(&general.map_zoom_in, i.virtual_keycode) {
+                        // Default: Page Up (zoom the minimap in)
+                        if i.state == ElementState::Pressed {
+                            self.hud.minimap().zoom_in();
+                        }
+                    } else if keypress_eq(&general.map_zoom_out, i.virtual_keycode) {
+                        // Default: Page Down (zoom the minimap out)
+                        if i.state == ElementState::Pressed {
+                            self.hud.minimap().zoom_out();
+                        }
+                    } else if let Some(slot) = hotbar_slot(i.virtual_keycode) {
+                        // 1 to 9 (hold the items in that hotbar slot)
+                        if i.state == ElementState::Pressed {
+                            self.client.select_slot(slot);
                         }
                     } else if keypress_eq(&general.chat, i.virtual_keycode) && i.state == ElementState::Released {
                         //self.ui.borrow_mut().set_show_chat(!show_chat);
@@ -294,10 +689,17 @@ impl Game {
                             ElementState::Released => false,
                         }
                     } else if keypress_eq(&general.crouch, i.virtual_keycode) {
-                        // self.key_state.lock().fall = match i.state { // Default: Shift (fall)
-                        //     ElementState::Pressed => true,
-                        //     ElementState::Released => false,
-                        // }
+                        self.key_state.lock().crouch = match i.state {
+                            // Default: LControl (crouch)
+                            ElementState::Pressed => true,
+                            ElementState::Released => false,
+                        }
+                    } else if keypress_eq(&general.sprint, i.virtual_keycode) {
+                        self.key_state.lock().sprint = match i.state {
+                            // Default: LShift (sprint)
+                            ElementState::Pressed => true,
+                            ElementState::Released => false,
+                        }
                     }
 
                     // ----------------------------------------------------------------------------
@@ -330,15 +732,21 @@ impl Game {
         const LOOKING_CTRL_ACC_FAC: f32 = 1.0;
         const MIN_LOOKING: f32 = 0.5;
         const LEANING_FAC: f32 = 0.05;
-        if let Some(player_entity) = self.client.player_entity() {
+        // The dead can't move, whatever keys are held
+        if let Some(player_entity) = self.client.player_entity().filter(|_| !self.client.is_dead()) {
             let mut player_entity = player_entity.write();
 
             // Apply acceleration
             player_entity.ctrl_acc_mut().x = mov_vec.x;
             player_entity.ctrl_acc_mut().y = mov_vec.y;
 
-            // Apply jumping
-            player_entity.ctrl_acc_mut().z = if self.key_state.lock().jump() { 1.0 } else { 0.0 };
+            // Apply jumping, which also swims upwards in water
+            let jump = self.key_state.lock().jump();
+            *player_entity.jump_mut() = jump;
+            player_entity.ctrl_acc_mut().z = if jump { 1.0 } else { 0.0 };
+
+            // Apply sprinting and crouching
+            *player_entity.move_mode_mut() = self.key_state.lock().move_mode();
 
             let looking = (*player_entity.vel() * LOOKING_VEL_FAC
                 + *player_entity.ctrl_acc_mut() * LOOKING_CTRL_ACC_FAC)
@@ -354,7 +762,48 @@ impl Game {
         }
     }
 
+    // Keep digging the block the player is looking at for as long as they hold the dig button. Letting go, looking
+    // away or dying gives up on the dig.
+    fn update_dig(&self) {
+        let now = Instant::now();
+        let dt = now - mem::replace(&mut *self.last_dig.lock(), now);
+
+        let digging = self.key_state.lock().dig
+            && self.window.cursor_trapped().load(Ordering::Relaxed)
+            && !self.client.is_dead();
+        let target = if digging {
+            let camera = self.camera.lock();
+            let dir = camera.get_mats().0.inverted() * (-Vec4::unit_z());
+            self.client.ray_cast(camera.get_focus(), Vec3::from(dir)).map(|hit| hit.pos)
+        } else {
+            None
+        };
+
+        match target {
+            Some(pos) => {
+                self.client.dig(pos, dt);
+            },
+            None => self.client.stop_digging(),
+        }
+    }
+
+    /// Build models for the chunks that have been meshed, in the order they were meshed. Only those chunks are locked,
+    /// and at most `MAX_CHUNK_UPLOADS` models are built a frame.
     pub fn update_chunks(&self) {
+        for (pos, state) in self.chunk_states.lock().try_iter() {
+            match state {
+                // New chunks are often meshed before they've been moved over from loading, so they're built once they
+                // have been
+                ChunkState::Exists => self.meshed.lock().push_back(pos),
+                // Unloaded chunks' models go with them, so they no longer count towards the memory taken up
+                ChunkState::Removed => {
+                    self.chunk_models.lock().unload(pos);
+                    self.out_of_view.lock().remove(&pos);
+                },
+                ChunkState::Loading => {},
+            }
+        }
+
         let mut renderer = self.window.renderer_mut();
         // Find the chunk the player is in
         let player_pos = self
@@ -364,52 +813,173 @@ impl Game {
             .unwrap_or(Vec3::new(0.0, 0.0, 0.0));
         let player_chunk = terrain::voxabs_to_voloffs(player_pos.map(|e| e as i64), CHUNK_SIZE);
         let squared_view_distance = (self.client.view_distance() / CHUNK_SIZE.x as f32 + 1.0).powi(2) as i32; // view_distance is vox based, but its needed vol based here
+        if mem::replace(&mut *self.player_chunk.lock(), Some(player_chunk)) != Some(player_chunk) {
+            let mut out_of_view = self.out_of_view.lock();
+            self.meshed.lock().extend(out_of_view.drain());
+        }
 
-        for (pos, con) in self
-            .client
-            .chunk_mgr()
-            .pers(|chunk_offs| player_chunk.distance_squared(*chunk_offs) < squared_view_distance)
-            .iter()
-        {
-            let trylock = &mut con.payload_try_mut(); //we try to lock it, if it is already written to we just ignore this chunk for a frame
-            if let Some(ref mut lock) = trylock {
-                //sometimes payload does not exist, dont render then
-                if let Some(ref mut payload) = **lock {
-                    if let ChunkPayload::Meshes(ref mut mesh) = payload {
-                        // Calculate chunk mode matrix
-                        let model_mat = Mat4::<f32>::translation_3d(pos.map2(CHUNK_SIZE, |p, s| (p * s as i32) as f32));
-
-                        // Create set new model constants
-                        let model_consts = ConstHandle::new(&mut renderer);
-
-                        // Update chunk model constants
-                        model_consts.update(
-                            &mut renderer,
-                            voxel::ModelConsts {
-                                model_mat: to_4x4(&model_mat),
-                            },
-                        );
-
-                        // Update the chunk payload
-                        *payload = ChunkPayload::Model {
-                            model: voxel::Model::new(&mut renderer, mesh),
-                            model_consts,
-                        };
-                    }
-                }
+        // Chunks whose payload is being written to wait for a later frame
+        let mut later = vec![];
+        let mut built = 0;
+        while built < MAX_CHUNK_UPLOADS {
+            let pos = match self.meshed.lock().pop_front() {
+                Some(pos) => pos,
+                None => break,
+            };
+            // Chunks still loading are put back once they exist, and those that have been unloaded are left out
+            let con = match self.client.chunk_mgr().get_chunk(pos) {
+                Some(con) => con,
+                None => continue,
+            };
+            if player_chunk.distance_squared(pos) >= squared_view_distance {
+                self.out_of_view.lock().insert(pos);
+                continue;
+            }
+            let mut lock = match con.payload_try_mut() {
+                Some(lock) => lock,
+                None => {
+                    later.push(pos);
+                    continue;
+                },
+            };
+            // Chunks meshed more than once before being built are only built the first time
+            if let Some(ChunkPayload::Meshes(ref mut mesh)) = *lock {
+                // Create set new model constants
+                let origin = *self.render_origin.lock();
+                let model_consts = ConstHandle::new(&mut renderer);
+                update_chunk_consts(&mut renderer, &model_consts, pos, &origin);
+
+                // Update the chunk payload
+                let model = voxel::Model::new(&mut renderer, mesh);
+                self.chunk_models.lock().insert(pos, model.bytes());
+                *lock = Some(ChunkPayload::Model {
+                    model,
+                    model_consts,
+                    origin,
+                });
+                built += 1;
             }
         }
+        self.meshed.lock().extend(later);
     }
 
     pub fn handle_client_events(&mut self) {
-        let mut events = self.client.get_events();
+        for event in self.client_events.drain() {
+            match event {
+                ClientEvent::ChatReceived { text } => self.hud.chat_box().add_chat_msg(text),
+                ClientEvent::StatusChanged { status } => self.on_status_changed(status),
+                ClientEvent::Kicked { reason } => {
+                    self.reconnect = false;
+                    self.stop(Exit::ToMenu(Some(format!("Disconnected: {}", reason))));
+                },
+                ClientEvent::Died { cause } => self.on_death(&cause),
+                ClientEvent::ServerInfo { info } => {
+                    for line in info.motd.lines() {
+                        self.hud.chat_box().add_chat_msg(line.to_string());
+                    }
+                },
+                ClientEvent::CraftResult { result: Err(e), .. } => self.hud.chat_box().add_chat_msg(format!("[{}]", e)),
+                ClientEvent::Respawned => {
+                    self.hud.hide_death();
+                    self.death_orbit = None;
+                },
+                _ => {},
+            }
+        }
 
-        events.drain(..).for_each(|event| match event {
-            ClientEvent::RecvChatMsg { text } => self.hud.chat_box().add_chat_msg(text),
-        });
+        self.maintain_connection();
+    }
+
+    // Let the player know when the connection drops or comes back
+    fn on_status_changed(&mut self, status: ClientStatus) {
+        match status {
+            ClientStatus::Disconnected if self.client_status == ClientStatus::Connected && self.reconnect => self
+                .hud
+                .chat_box()
+                .add_chat_msg("[Lost connection to the server, reconnecting...]".to_string()),
+            ClientStatus::Connected => self.hud.chat_box().add_chat_msg("[Reconnected]".to_string()),
+            _ => {},
+        }
+        self.client_status = status;
+    }
+
+    // Show the death screen, and let go of the cursor so its button can be clicked
+    fn on_death(&mut self, cause: &str) {
+        self.hud.show_death(cause);
+        self.window.untrap_cursor();
+        *self.key_state.lock() = KeyState::new();
+        self.death_orbit = Some((Instant::now(), self.camera.lock().ori().x));
+    }
+
+    // Keep trying to get the connection back, unless the server sent us away
+    fn maintain_connection(&mut self) {
+        if let Some(result) = self.reconnecting.as_mut().and_then(|job| job.try_wait(Duration::from_secs(0))) {
+            self.reconnecting = None;
+            match result {
+                Ok(Ok(())) => {},
+                Ok(Err(e)) => warn!("failed to reconnect: {}", e),
+                Err(e) => warn!("failed to reconnect: {}", e),
+            }
+        }
+
+        let due = self.last_reconnect.map(|t| t.elapsed() >= RECONNECT_DELAY).unwrap_or(true);
+        if self.reconnect && *self.client.status() == ClientStatus::Disconnected && due && self.reconnecting.is_none() {
+            self.last_reconnect = Some(Instant::now());
+            // Connecting blocks, so it happens off the render thread
+            self.reconnecting = Some(self.client.reconnect_in_background());
+        }
+    }
+
+    /// The settings as they are now, which may have been reloaded since the game started
+    pub fn settings(&self) -> &Settings { &self.settings }
+
+    /// Read the settings file again and take on what can change while playing
+    fn reload_settings(&mut self) {
+        let settings = match Settings::load_from(&Settings::path()) {
+            Ok(settings) => settings,
+            Err(e) => {
+                self.hud.chat_box().add_chat_msg(format!("[Couldn't reload the settings: {}]", e));
+                return;
+            },
+        };
+
+        self.chunk_models.lock().set_budget(settings.graphics.chunk_memory as usize * MEGABYTE);
+        {
+            let mut camera = self.camera.lock();
+            camera.set_fov(settings.graphics.fov);
+            camera.set_sensitivity(Vec2::new(
+                settings.controls.mouse_sensitivity_x,
+                settings.controls.mouse_sensitivity_y,
+            ));
+            camera.set_invert_y(settings.controls.invert_mouse_y);
+            camera.set_zoom_speed(settings.controls.zoom_speed);
+        }
+        let audio_mgr = self.client.audio_mgr();
+        audio_mgr.set_group_volume(Group::Master, settings.audio.master_volume);
+        audio_mgr.set_group_volume(Group::Music, settings.audio.music_volume);
+        audio_mgr.set_group_volume(Group::Sfx, settings.audio.sfx_volume);
+        audio_mgr.set_group_volume(Group::Ambience, settings.audio.ambience_volume);
+        audio_mgr.mute(Group::Master, settings.audio.muted);
+
+        self.settings = settings;
+        self.hud.chat_box().add_chat_msg("[Reloaded the settings]".to_string());
     }
 
     pub fn handle_hud_events(&mut self) {
+        for msg in self.screenshot_msgs.lock().drain(..) {
+            self.hud.chat_box().add_chat_msg(msg);
+        }
+
+        // Players come and go and payloads can add commands, so what the chat completes is kept up to date
+        let players = self.client.entities().values().filter_map(|e| e.read().name().clone()).collect();
+        let commands = self
+            .client
+            .commands()
+            .iter()
+            .flat_map(|cmd| Some(cmd.name.clone()).into_iter().chain(cmd.aliases.iter().cloned()))
+            .collect();
+        self.hud.set_chat_completions(players, commands);
+
         let mut events = self.hud.get_events();
 
         events.drain(..).for_each(|event| match event {
@@ -418,6 +988,15 @@ impl Game {
                     self.client.send_chat_msg(text);
                 }
             },
+            HudEvent::Respawn => self.client.respawn(),
+            HudEvent::Resume => {
+                if self.hud.is_paused() {
+                    self.toggle_pause();
+                }
+            },
+            HudEvent::ReloadSettings => self.reload_settings(),
+            HudEvent::DisconnectToMenu => self.stop(Exit::ToMenu(None)),
+            HudEvent::Quit => self.stop(Exit::Quit),
         });
     }
 
@@ -425,62 +1004,186 @@ impl Game {
         // Take the physics lock to sync client and frontend updates
         let _ = self.client.take_phys_lock();
 
-        // Set camera focus to the player's head
+        // Entities are drawn between their last two physics steps, so that they move smoothly at any frame rate
+        let alpha = self.client.tick_alpha();
+
+        // Set camera focus to the player's head, and keep what's drawn positioned relative to somewhere near it
         if let Some(player_entity) = self.client.player_entity() {
             let player_entity = player_entity.read();
-            self.camera.lock().set_focus(Vec3::<f32>::from(
-                (*player_entity.pos() + Vec3::new(0.0, 0.0, 1.75)).into_array(),
-            ));
+            let focus = player_entity.interpolated_pos(alpha) + Vec3::new(0.0, 0.0, 1.75);
+            let mut camera = self.camera.lock();
+            camera.set_focus(focus);
+            if let Some((since, yaw)) = self.death_orbit {
+                camera.set_yaw(yaw + since.elapsed().as_float_secs() as f32 * DEATH_ORBIT_SPEED);
+            }
+            let sprinting = player_entity.move_mode() == MoveMode::Sprint
+                && Vec2::from(*player_entity.vel()).magnitude() > SPRINT_FOV_SPEED;
+            camera.set_widened(sprinting && self.settings.graphics.sprint_fov);
+            self.render_origin.lock().follow(focus);
+        }
+
+        // The far plane follows the view distance, which terrain is drawn out to from the player, who the camera can be
+        // zoomed out behind
+        {
+            let now = Instant::now();
+            let dt = now.duration_since(mem::replace(&mut *self.last_camera_update.lock(), now));
+            let mut camera = self.camera.lock();
+            let far = self.client.view_distance() + camera.get_zoom() + FAR_PLANE_MARGIN;
+            camera.set_clip_planes(NEAR_PLANE, far);
+            camera.update(dt.as_float_secs() as f32);
         }
+        let origin = *self.render_origin.lock();
 
         let mut renderer = self.window.renderer_mut();
 
+        // Animations are driven by game time, so they run at the same speed whatever the frame rate
+        let time = self.client.time().as_float_secs() as f32;
+
         // Update each entity constbuffer
-        for (_, entity) in self.client.entities().iter() {
+        for (&uid, entity) in self.client.entities().iter() {
             let mut entity = entity.write();
+            let (pos, vel, look_dir) = (entity.interpolated_pos(alpha), *entity.vel(), *entity.look_dir());
+            let state = AnimState::of(vel, entity.move_mode(), entity.is_grounded());
 
-            // Calculate entity model matrix
-            let model_mat = Mat4::<f32>::translation_3d(Vec3::from(entity.pos().into_array()))
-                * Mat4::rotation_z(PI - entity.look_dir().x)
-                * Mat4::rotation_x(entity.look_dir().y);
+            let is_item = entity.item().is_some();
 
-            // Update the model const buffer (its payload)
             // TODO: Put the model into the payload so we can have per-entity models!
-            entity
-                .payload_mut()
-                .get_or_insert_with(|| ConstHandle::new(&mut renderer))
-                .update(
+            let model = self.entity_model(uid);
+            let payload = entity.payload_mut().get_or_insert_with(|| EntityPayload {
+                part_consts: vec![],
+                part_mats: vec![],
+                anim: Animation::new(uid),
+            });
+
+            // Dropped items spin and bob where they lie rather than being animated
+            if is_item {
+                if payload.part_consts.is_empty() {
+                    payload.part_consts.push(ConstHandle::new(&mut renderer));
+                }
+                let model_mat = figure::item_mat(origin.relative(pos), time, uid);
+                payload.part_consts[0].update(
                     &mut renderer,
                     voxel::ModelConsts {
                         model_mat: to_4x4(&model_mat),
                     },
                 );
+                payload.part_mats = vec![model_mat];
+                continue;
+            }
+
+            let pose = payload.anim.update(state, Vec2::from(vel).magnitude(), time);
+
+            // Calculate entity model matrix, posed on top of where the entity is and which way it's facing
+            let model_mat = Mat4::<f32>::translation_3d(origin.relative(pos) + Vec3::unit_z() * pose.offset)
+                * Mat4::rotation_z(PI - look_dir.x)
+                * Mat4::rotation_x(look_dir.y - pose.pitch)
+                * Mat4::rotation_y(pose.roll);
+
+            // Update the const buffer of each part of the model (its payload)
+            while payload.part_consts.len() < model.part_count() {
+                payload.part_consts.push(ConstHandle::new(&mut renderer));
+            }
+            payload.part_mats = model.part_mats(model_mat, &pose);
+            for (consts, part_mat) in payload.part_consts.iter().zip(payload.part_mats.iter()) {
+                consts.update(
+                    &mut renderer,
+                    voxel::ModelConsts {
+                        model_mat: to_4x4(part_mat),
+                    },
+                );
+            }
+        }
+    }
+
+    // The model the entity with `uid` is drawn with
+    fn entity_model(&self, uid: u64) -> &CharacterModel {
+        match self.client.player().entity_uid {
+            Some(player_uid) if uid == player_uid => &self.player_model,
+            _ => &self.other_player_model,
+        }
+    }
+
+    /// Move rain and snow on since the last frame, easing towards the weather the server says the player is in
+    pub fn update_weather(&mut self) {
+        let now = Instant::now();
+        let dt = (now.duration_since(self.last_weather_update).as_float_secs() as f32).min(MAX_WEATHER_STEP);
+        self.last_weather_update = now;
+
+        let player_pos = match self.client.player_entity() {
+            Some(entity) => entity.read().interpolated_pos(self.client.tick_alpha()),
+            None => return,
+        };
+        let chunk_mgr = self.client.chunk_mgr();
+        self.weather
+            .update(dt, self.client.weather(), player_pos, |low, high| chunk_mgr.surface_heights(low, high));
+    }
+
+    /// Rebuild any pipelines whose shaders have been modified since the last frame
+    pub fn reload_shaders(&mut self) {
+        let changed = match self.shader_watcher {
+            Some(ref watcher) => watcher.changed(),
+            None => return,
+        };
+        if changed.is_empty() {
+            return;
+        }
+
+        let mut renderer = self.window.renderer_mut();
+        self.skybox_pipeline
+            .reload_if_changed(renderer.factory_mut(), &changed);
+        self.volume_pipeline.reload_if_changed(&mut renderer, &changed);
+        self.outline_pipeline
+            .reload_if_changed(renderer.factory_mut(), &changed);
+        self.border_pipeline
+            .reload_if_changed(renderer.factory_mut(), &changed);
+        self.shadow_pipeline
+            .reload_if_changed(renderer.factory_mut(), &changed);
+        self.weather_pipeline
+            .reload_if_changed(renderer.factory_mut(), &changed);
+        self.tonemapper_pipeline
+            .reload_if_changed(renderer.factory_mut(), &changed);
+
+        // Reloaded shaders might include files that weren't being watched yet
+        if let Some(ref watcher) = self.shader_watcher {
+            watcher.watch(self.skybox_pipeline.sources());
+            watcher.watch(&self.volume_pipeline.sources());
+            watcher.watch(self.outline_pipeline.sources());
+            watcher.watch(self.border_pipeline.sources());
+            watcher.watch(self.shadow_pipeline.sources());
+            watcher.watch(self.weather_pipeline.sources());
+            watcher.watch(self.tonemapper_pipeline.sources());
         }
     }
 
     pub fn render_frame(&mut self) {
-        // Calculate frame constants
+        // The player can't move until the terrain around them is there, so there's nothing worth seeing yet either
+        if let Some(progress) = self.client.loading() {
+            self.render_loading(progress);
+            return;
+        }
+
+        // Calculate frame constants. Everything is drawn relative to the render origin, but worked out in world space.
+        let origin = *self.render_origin.lock();
         let camera_mats = self.camera.lock().get_mats();
+        let render_mats = self.camera.lock().get_render_mats(&origin);
         let camera_fov = self.camera.lock().get_fov();
         // TODO: Maybe rename this to cam_pos?
         let cam_origin = self.camera.lock().get_pos(Some(&camera_mats));
         let cam_zoom = self.camera.lock().get_zoom();
-        let (player_pos, player_vel, player_ori) = {
-            let e = self.client.player_entity();
-            if let Some(e) = e {
-                let lock = e.read();
-                let ld = lock.look_dir();
-                (*lock.pos(), *lock.vel(), Vec3::new(ld.x, ld.y, 0.0))
-            } else {
-                (
-                    Vec3::new(0.0, 0.0, 0.0),
-                    Vec3::new(0.0, 0.0, 0.0),
-                    Vec3::new(0.0, 0.0, 0.0),
-                )
-            }
-        };
-        let play_origin = [player_pos.x, player_pos.y, player_pos.z, 1.0];
+        let alpha = self.client.tick_alpha();
+        let player_pos = self
+            .client
+            .player_entity()
+            .map(|e| e.read().interpolated_pos(alpha))
+            .unwrap_or(Vec3::zero());
         let time = self.client.time().as_float_secs() as f32;
+        let view_distance = self.client.view_distance();
+        let gloom = self.weather.gloom();
+        let fog_start = if self.settings.graphics.fog {
+            view_distance * FOG_START * (1.0 - STORM_FOG * gloom)
+        } else {
+            view_distance
+        };
 
         // Begin rendering, don't clear the frame
         let mut renderer = self.window.renderer_mut();
@@ -490,85 +1193,280 @@ impl Game {
         self.global_consts.update(
             &mut renderer,
             GlobalConsts {
-                view_mat: to_4x4(&camera_mats.0),
-                proj_mat: to_4x4(&camera_mats.1),
-                cam_origin: [cam_origin.x, cam_origin.y, cam_origin.z, 1.0],
-                play_origin,
-                view_distance: [self.client.view_distance(); 4],
+                view_mat: to_4x4(&render_mats.0),
+                proj_mat: to_4x4(&render_mats.1),
+                cam_origin: Vec4::from_point(origin.relative(cam_origin)).into_array(),
+                play_origin: Vec4::from_point(origin.relative(player_pos)).into_array(),
+                view_distance: [view_distance; 4],
                 time: [time; 4],
+                fog: [fog_start, view_distance, 0.0, 0.0],
+                render_origin: Vec4::from_point(origin.world_pos()).into_array(),
+                weather: [gloom, self.weather.intensity(), 0.0, 0.0],
             },
         );
 
-        // Render the skybox
-        self.skybox_model
-            .render(&mut renderer, &self.skybox_pipeline, &self.global_consts);
+        // What's drawn this frame, tagged with the pass it's drawn in. Models that only need updating once a frame are
+        // updated as they're submitted, but nothing is drawn until the passes are.
+        let mut frame: PassQueue<DrawItem> = PassQueue::new();
+        let global_consts = &self.global_consts;
 
-        // Find the chunk the player is in
-        let squared_view_distance = self.client.view_distance().powi(2) as f32; // view_distance is vox based, but its needed vol based here
+        let (skybox_model, skybox_pipeline) = (&self.skybox_model, &self.skybox_pipeline);
+        frame.submit(
+            Pass::Sky,
+            Box::new(move |renderer| skybox_model.render(renderer, skybox_pipeline, global_consts)),
+        );
+
+        // Chunks that are completely fogged over aren't visible, so only render those within the view distance
+        let squared_view_distance = view_distance.powi(2);
         let cam_vec_world = camera_mats.0.inverted() * (-Vec4::unit_z());
 
         // Render each chunk
-        for (_pos, con) in self
-            .client
-            .chunk_mgr()
-            .pers(|chunk_offs| {
-                let chunk_pos = chunk_offs.map(|e| e as f32) * CHUNK_SIZE.map(|e| e as f32);
-                // This limit represents the point in the chunk that's closest to the player (0 - CHUNK_SIZE)
-                let chunk_offs_limit = Vec3::clamp(player_pos - chunk_pos, Vec3::zero(), CHUNK_SIZE.map(|e| e as f32));
-                // Check whether the chunk is within range of the view distance
-                (chunk_pos + chunk_offs_limit).distance_squared(player_pos) < squared_view_distance &&
-                // Check whether the chunk is within the frustrum of the camera (or within a certain minimum range to avoid visual artefacts)
-                (Vec4::from(chunk_pos + CHUNK_SIZE.map(|e| e as f32) / 2.0 - cam_origin)
-                        .normalized()
-                        .dot(cam_vec_world)
-                        > camera_fov.cos()
-                        || (chunk_pos + CHUNK_SIZE.map(|e| e as f32) / 2.0 - cam_origin).magnitude()
-                            < CHUNK_SIZE.x as f32 * 2.0)
-            })
-            .iter()
-        {
-            let trylock = &con.payload_try(); //we try to lock it, if it is already written to we just ignore this chunk for a frame
-            if let Some(ref lock) = trylock {
-                if let Some(ref payload) = **lock {
-                    if let ChunkPayload::Model {
+        let volume_pipeline = &mut self.volume_pipeline;
+        let mut chunk_models = self.chunk_models.lock();
+        let mut remesh = vec![];
+        self.client.chunk_mgr().for_each_loaded(|chunk_offs, con| {
+            let chunk_pos = chunk_offs.map(|e| e as f32) * CHUNK_SIZE.map(|e| e as f32);
+            // This limit represents the point in the chunk that's closest to the player (0 - CHUNK_SIZE)
+            let chunk_offs_limit = Vec3::clamp(player_pos - chunk_pos, Vec3::zero(), CHUNK_SIZE.map(|e| e as f32));
+            // Check whether the chunk is within range of the view distance
+            let visible = (chunk_pos + chunk_offs_limit).distance_squared(player_pos) < squared_view_distance &&
+            // Check whether the chunk is within the frustrum of the camera (or within a certain minimum range to avoid visual artefacts)
+            (Vec4::from(chunk_pos + CHUNK_SIZE.map(|e| e as f32) / 2.0 - cam_origin)
+                    .normalized()
+                    .dot(cam_vec_world)
+                    > camera_fov.cos()
+                    || (chunk_pos + CHUNK_SIZE.map(|e| e as f32) / 2.0 - cam_origin).magnitude()
+                        < CHUNK_SIZE.x as f32 * 2.0);
+            if !visible {
+                return;
+            }
+
+            let trylock = &mut con.payload_try_mut(); //we try to lock it, if it is already written to we just ignore this chunk for a frame
+            if let Some(ref mut lock) = trylock {
+                match **lock {
+                    Some(ChunkPayload::Model {
                         ref model,
                         ref model_consts,
-                    } = payload
-                    {
-                        self.volume_pipeline
-                            .draw_model(&model, model_consts, &self.global_consts);
+                        origin: ref mut model_origin,
+                    }) => {
+                        // The render origin has moved since the chunk was last drawn
+                        if *model_origin != origin {
+                            update_chunk_consts(&mut renderer, model_consts, chunk_offs, &origin);
+                            *model_origin = origin;
+                        }
+                        volume_pipeline.draw_model(&model, model_consts, global_consts);
+                        chunk_models.touch(chunk_offs);
+                    },
+                    Some(ChunkPayload::Evicted) => {
+                        // Nothing to show until it's been meshed again
+                        **lock = None;
+                        remesh.push(chunk_offs);
+                    },
+                    _ => {},
+                }
+            }
+        });
+        for pos in remesh {
+            self.client.chunk_mgr().regen_payload(pos);
+        }
+
+        // Drop the models of the chunks that have gone longest without being seen if they take up too much memory.
+        // Chunks whose payload is being written to are left for a later frame.
+        let chunk_mgr = self.client.chunk_mgr();
+        chunk_models.end_frame(|pos| match chunk_mgr.get_chunk(pos) {
+            Some(con) => match con.payload_try_mut() {
+                Some(mut payload) => {
+                    if let Some(ChunkPayload::Model { .. }) = *payload {
+                        *payload = Some(ChunkPayload::Evicted);
                     }
+                    true
+                },
+                None => false,
+            },
+            // It was unloaded, taking its model with it
+            None => true,
+        });
+        let (used, budget) = (chunk_models.used(), chunk_models.budget());
+        drop(chunk_models);
+
+        // Gather the entities by the model they're drawn with so that those sharing one can be drawn together, and find
+        // where each name goes while at it so the name keeps up with the model
+        let mut name_tags = vec![];
+        let mut items = HashMap::<ItemKind, Vec<(ConstHandle<voxel::ModelConsts>, Mat4<f32>)>>::new();
+        let mut other_players = vec![];
+        for (&uid, entity) in self.client.entities().iter() {
+            // The player can't see themselves in first person
+            let is_player = self.client.player().entity_uid == Some(uid);
+            if is_player && cam_zoom == 0.0 {
+                continue;
+            }
+
+            let entity = entity.read();
+            if let Some(ref payload) = entity.payload() {
+                match (entity.item(), payload.part_consts.first(), payload.part_mats.first()) {
+                    (Some(item), Some(consts), Some(mat)) => {
+                        items.entry(item.kind).or_insert(vec![]).push((consts.clone(), *mat));
+                    },
+                    (Some(_), ..) => {},
+                    (None, ..) if is_player => {
+                        self.player_model
+                            .draw(&mut self.volume_pipeline, &payload.part_consts, &self.global_consts)
+                    },
+                    (None, ..) => other_players.push((payload.part_consts.clone(), payload.part_mats.clone())),
                 }
             }
+            if let Some(ref name) = entity.name() {
+                let anchor = entity.interpolated_pos(alpha) + Vec3::unit_z() * entity.body_size().z;
+                name_tags.extend(self.name_tag(name, anchor, &camera_mats, cam_origin));
+            }
         }
+        self.hud.set_name_tags(name_tags);
+
+        // A model with just the one entity to draw is drawn like any other, otherwise they're all drawn in one go
+        for (kind, items) in items {
+            match items.as_slice() {
+                [(consts, _)] => {
+                    let model = self.item_models.get(&mut renderer, kind).model();
+                    self.volume_pipeline.draw_model(model, consts, &self.global_consts);
+                },
+                _ => {
+                    let instances = items
+                        .iter()
+                        .map(|(_, mat)| voxel::InstanceData::new(*mat, Rgba::broadcast(1.0)))
+                        .collect::<Vec<_>>();
+                    let model = self.item_models.get(&mut renderer, kind);
+                    self.volume_pipeline
+                        .draw_instanced(&mut renderer, model, &instances, &self.global_consts);
+                },
+            }
+        }
+        match other_players.as_slice() {
+            [(part_consts, _)] => {
+                self.other_player_model
+                    .draw(&mut self.volume_pipeline, part_consts, &self.global_consts)
+            },
+            _ => {
+                let part_mats = other_players
+                    .iter()
+                    .map(|(_, part_mats)| part_mats.as_slice())
+                    .collect::<Vec<_>>();
+                self.other_player_model.draw_instanced(
+                    &mut self.volume_pipeline,
+                    &mut renderer,
+                    &part_mats,
+                    &self.global_consts,
+                );
+            },
+        }
+
+        // What the volume pipeline has queued up is drawn in the opaque pass. The debug box shows how many draw calls
+        // that took last frame.
+        let draw_calls = self.volume_pipeline.draw_calls();
+        let volume_pipeline = &mut self.volume_pipeline;
+        frame.submit(Pass::Opaque, Box::new(move |renderer| volume_pipeline.flush(renderer)));
 
-        // Render each entity
+        // Blob shadows on the ground under every entity, so it's clear where they stand, all in one draw
+        let mut shadows = shadow::Mesh::new();
+        let chunk_mgr = self.client.chunk_mgr();
+        let is_solid = |pos: Vec3<VoxAbs>| chunk_mgr.get_block(pos).map_or(false, |block| block.is_solid());
         for (&uid, entity) in self.client.entities().iter() {
-            // Choose the correct model for the entity
-            let model = match self.client.player().entity_uid {
-                Some(player_uid) if uid == player_uid => {
-                    if cam_zoom == 0.0 {
-                        continue;
-                    }
-                    &self.player_model
+            // Nobody sees their own shadow in first person
+            if self.client.player().entity_uid == Some(uid) && cam_zoom == 0.0 {
+                continue;
+            }
+            let entity = entity.read();
+            let size = match entity.item() {
+                Some(_) => Vec2::broadcast(ITEM_SIZE),
+                None => Vec2::from(entity.collision_box().0),
+            };
+            shadows.add_shadow(&origin, entity.interpolated_pos(alpha), size, &is_solid);
+        }
+        self.shadow_model.update(&mut renderer, &shadows);
+        let (shadow_model, shadow_pipeline) = (&self.shadow_model, &self.shadow_pipeline);
+        frame.submit(
+            Pass::Transparent,
+            Box::new(move |renderer| shadow_model.render(renderer, shadow_pipeline, global_consts)),
+        );
+
+        // Outline the block the player is looking at. The camera always faces its focus, so a ray from the focus
+        // along the view direction goes through the crosshair.
+        let cam_focus = self.camera.lock().get_focus();
+        let outline_pipeline = &self.outline_pipeline;
+        if let Some(hit) = self.client.ray_cast(cam_focus, Vec3::from(cam_vec_world)) {
+            self.outline_model.update(
+                &mut renderer,
+                voxel::ModelConsts {
+                    model_mat: to_4x4(&Mat4::<f32>::translation_3d(origin.relative_block(hit.pos))),
                 },
-                _ => &self.other_player_model,
+            );
+            let outline_model = &self.outline_model;
+            frame.submit(
+                Pass::Transparent,
+                Box::new(move |renderer| outline_model.render(renderer, outline_pipeline, global_consts)),
+            );
+        }
+
+        // Crack the blocks being dug, by us or anyone else, more the further the dig has got
+        for dig in self.client.own_dig().into_iter().chain(self.client.others_digs()) {
+            let stage = ((dig.progress * CRACK_STAGES as f32).ceil() as usize).max(1).min(CRACK_STAGES as usize);
+            let model = &self.crack_models[stage - 1];
+            let consts = voxel::ModelConsts {
+                model_mat: to_4x4(&Mat4::<f32>::translation_3d(origin.relative_block(dig.pos))),
             };
+            // Two digs at the same stage share a model, so it's moved into place right before it's drawn
+            frame.submit(
+                Pass::Transparent,
+                Box::new(move |renderer| {
+                    model.update(renderer, consts);
+                    model.render(renderer, outline_pipeline, global_consts);
+                }),
+            );
+        }
 
-            if let Some(ref model_consts) = entity.read().payload() {
-                self.volume_pipeline
-                    .draw_model(&model, model_consts, &self.global_consts);
-            }
+        // Show the world border as a translucent wall once the player gets near it
+        let border = self.client.world_border();
+        if border.distance(player_pos) < BORDER_VISIBLE_DIST {
+            let corner = border.center - border.radius;
+            let corner = origin.relative(Vec3::new(corner.x, corner.y, player_pos.z - BORDER_HEIGHT));
+            let model_mat = Mat4::<f32>::translation_3d(corner)
+                * Mat4::scaling_3d(Vec3::new(border.radius * 2.0, border.radius * 2.0, BORDER_HEIGHT * 2.0));
+            self.border_model.update(
+                &mut renderer,
+                voxel::ModelConsts {
+                    model_mat: to_4x4(&model_mat),
+                },
+            );
+            let (border_model, border_pipeline) = (&self.border_model, &self.border_pipeline);
+            frame.submit(
+                Pass::Transparent,
+                Box::new(move |renderer| border_model.render(renderer, border_pipeline, global_consts)),
+            );
         }
 
-        // flush voxel pipeline draws
-        self.volume_pipeline.flush(&mut renderer);
+        // Rain and snow, facing the camera
+        let cam_to_world = camera_mats.0.inverted();
+        let mesh = self.weather.mesh(
+            &origin,
+            Vec3::from(cam_to_world * Vec4::unit_x()),
+            Vec3::from(cam_to_world * Vec4::unit_y()),
+        );
+        self.weather_model.update(&mut renderer, &mesh);
+        let (weather_model, weather_pipeline) = (&self.weather_model, &self.weather_pipeline);
+        frame.submit(
+            Pass::Particles,
+            Box::new(move |renderer| weather_model.render(renderer, weather_pipeline, global_consts)),
+        );
 
-        //update audio
-        self.audio
-            .set_pos(player_pos, player_vel, camera_mats.0 * camera_mats.1);
+        // Sounds are heard from the camera
+        self.client
+            .audio_mgr()
+            .set_listener(cam_origin, Vec3::from(cam_vec_world));
 
-        tonemapper::render(&mut renderer, &self.tonemapper_pipeline, &self.global_consts);
+        let tonemapper_pipeline = &self.tonemapper_pipeline;
+        frame.submit(
+            Pass::Post,
+            Box::new(move |renderer| tonemapper::render(renderer, tonemapper_pipeline, global_consts)),
+        );
 
         use crate::{get_build_time, get_git_hash};
 
@@ -588,7 +1486,7 @@ impl Game {
         self.hud
             .debug_box()
             .fps_label
-            .set_text(format!("FPS: {}", self.last_fps));
+            .set_text(format!("FPS: {} ({} volume draw calls)", self.last_fps, draw_calls));
 
         let pos_text = self
             .client
@@ -597,22 +1495,107 @@ impl Game {
             .unwrap_or("Unknown position".to_string());
         self.hud.debug_box().pos_label.set_text(pos_text);
 
-        self.hud.render(&mut renderer);
+        let facing = self
+            .client
+            .player_entity()
+            .map(|e| e.read().look_dir().x)
+            .unwrap_or(0.0);
+        self.hud
+            .minimap()
+            .set_view(Vec2::from(player_pos), facing, self.camera.lock().ori().x);
+        self.hud.debug_box().chunk_memory_label.set_text(format!(
+            "Chunk models: {} / {} MB",
+            used / MEGABYTE,
+            budget / MEGABYTE
+        ));
+
+        let held_slot = self.client.held_slot();
+        self.hud
+            .hotbar()
+            .set_slots(&self.client.inventory().slots()[..HOTBAR_SLOTS], held_slot);
+
+        // The debug box is drawn in a pass of its own, so that it can be shown without the rest of the HUD
+        let hud = RefCell::new(&mut self.hud);
+        frame.submit(Pass::Ui, Box::new(|renderer| hud.borrow_mut().render(renderer)));
+        frame.submit(Pass::Debug, Box::new(|renderer| hud.borrow_mut().render_debug(renderer)));
+
+        renderer.draw_passes(frame);
 
         self.window.swap_buffers();
         renderer.end_frame();
 
+        if self.take_screenshot.swap(false, Ordering::Relaxed) {
+            let msgs = self.screenshot_msgs.clone();
+            renderer.capture_frame(move |result| {
+                let msg = match result {
+                    Ok(path) => format!("Screenshot saved to {}", path.display()),
+                    Err(e) => format!("Failed to save screenshot: {}", e),
+                };
+                info!("{}", msg);
+                msgs.lock().push(msg);
+            });
+        }
+
         self.last_fps = self.fps.tick();
     }
 
-    pub fn run(&mut self) {
-        while self.running.load(Ordering::Relaxed) {
+    // The name shown over an entity whose head is at `head`, unless it's too far away, off screen, or behind terrain
+    fn name_tag(
+        &self,
+        name: &str,
+        head: Vec3<f32>,
+        mats: &(Mat4<f32>, Mat4<f32>),
+        cam_origin: Vec3<f32>,
+    ) -> Option<NameTag> {
+        let anchor = head + Vec3::unit_z() * NAME_TAG_CLEARANCE;
+        let dist = cam_origin.distance(anchor);
+        if dist >= NAME_TAG_FADE_END || dist < 0.01 {
+            return None;
+        }
+        let screen_pos = Camera::project(mats, anchor)?;
+
+        // Names mustn't show through walls
+        let dir = (anchor - cam_origin) / dist;
+        if self.client.chunk_mgr().ray_cast(cam_origin, dir, dist).is_some() {
+            return None;
+        }
+
+        Some(NameTag {
+            text: name.to_string(),
+            pos: screen_pos,
+            size: (NAME_TAG_SIZE * NAME_TAG_SIZE_DIST / dist).min(NAME_TAG_SIZE).max(NAME_TAG_MIN_SIZE),
+            alpha: ((NAME_TAG_FADE_END - dist) / (NAME_TAG_FADE_END - NAME_TAG_FADE_START)).min(1.0),
+        })
+    }
+
+    fn render_loading(&mut self, progress: LoadProgress) {
+        let mut renderer = self.window.renderer_mut();
+        renderer.begin_frame(Some(LOADING_BACKGROUND));
+        self.hud.render_loading(&mut renderer, progress.loaded, progress.required);
+        self.window.swap_buffers();
+        renderer.end_frame();
+
+        self.last_fps = self.fps.tick();
+    }
+
+    // Stop running once this frame is done. The first reason given wins.
+    fn stop(&self, exit: Exit) { self.exit.lock().get_or_insert(exit); }
+
+    /// Play until the player leaves or the server sends them away
+    pub fn run(&mut self) -> Exit {
+        loop {
             self.handle_window_events();
             self.handle_hud_events();
             self.handle_client_events();
+            if let Some(exit) = self.exit.lock().take() {
+                return exit;
+            }
+            self.update_dig();
             self.update_chunks();
             self.update_entities();
+            self.update_weather();
 
+            self.reload_shaders();
             self.render_frame();
         }
     }
@@ -1,67 +1,124 @@
 // Standard
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     mem,
     rc::Rc,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::Arc,
 };
 
 // Library
+use glutin::{ElementState, VirtualKeyCode};
 use vek::*;
 
+// Project
+use common::ecs::inventory::{Item, ItemKind, HOTBAR_SLOTS};
+
 // Local
 use crate::{
+    completion::Completer,
+    map::{MapLayer, Minimap},
     renderer::Renderer,
     ui::{
-        element::{HBox, Label, Rect, TextBox, VBox, WinBox},
+        element::{Button, HBox, Label, Rect, TextBox, VBox, WinBox},
         Span, Ui,
     },
     window::Event,
 };
 
+// Wide enough for any reasonable name, which is centered within it
+const NAME_TAG_WIDTH: i32 = 512;
+const MINIMAP_SIZE: i32 = 192;
+// How many completion candidates are shown at once
+const CANDIDATES_SHOWN: usize = 5;
+const HOTBAR_SLOT_SIZE: i32 = 56;
+
+/// A name shown over an entity
+pub struct NameTag {
+    pub text: String,
+    /// Where the bottom of the name goes, from (0, 0) at the top left of the screen to (1, 1) at the bottom right
+    pub pos: Vec2<f32>,
+    /// The height of the text in pixels
+    pub size: f32,
+    pub alpha: f32,
+}
+
 pub enum HudEvent {
     ChatMsgSent { text: String },
+    Respawn,
+    /// Close the pause menu
+    Resume,
+    /// Read the settings file again, for changes made to it while playing
+    ReloadSettings,
+    DisconnectToMenu,
+    Quit,
 }
 
 pub struct Hud {
     ui: Ui,
+    // Drawn beneath the rest of the HUD, and rebuilt every frame since entities move
+    name_tags_ui: Ui,
+    name_tags: Rc<WinBox>,
+    // Drawn separately from the rest of the HUD, so that it can be shown without it
+    debug_ui: Ui,
     debug_box: DebugBox,
+    hotbar: Hotbar,
     chat_box: ChatBox,
     chatbox_input: Rc<TextBox>,
+    minimap: Rc<Minimap>,
+    // Shown over everything else while the game is paused
+    pause_ui: Ui,
+    paused: Cell<bool>,
+    // Shown over the rest of the HUD while the player is dead
+    death_ui: Ui,
+    death_cause: Rc<Label>,
+    dead: Cell<bool>,
+    // Shown instead of everything else until the terrain around the player has loaded
+    loading_ui: Ui,
+    loading_label: Rc<Label>,
 
-    chat_enabled: Rc<AtomicBool>,
     events: Rc<RefCell<Vec<HudEvent>>>,
 }
 
 impl Hud {
-    pub fn new() -> Hud {
+    /// `map_layer` is what the minimap shows, which turns with the camera if `map_rotates`
+    pub fn new(map_layer: Arc<MapLayer>, map_rotates: bool) -> Hud {
         let winbox = WinBox::new();
 
-        let hotbar = HBox::new()
-            .with_color(Rgba::new(0.0, 0.0, 0.0, 0.5))
-            .with_margin(Span::px(8, 8));
-        for _ in 0..5 {
-            hotbar.push_back(
-                Rect::new()
-                    .with_color(Rgba::new(1.0, 0.8, 0.3, 1.0))
-                    .with_padding(Span::px(8, 8)),
-            );
-        }
+        let hotbar = Hotbar::new();
         winbox.add_child_at(
             Span::bottom(),
             Span::bottom() + Span::px(0, 16),
-            Span::px(296, 72),
-            hotbar,
+            Span::px(HOTBAR_SLOT_SIZE * HOTBAR_SLOTS as i32 + 16, HOTBAR_SLOT_SIZE + 16),
+            hotbar.root(),
         );
 
+        // Crosshair
+        for size in &[Span::px(16, 2), Span::px(2, 16)] {
+            winbox.add_child_at(
+                Span::center(),
+                Span::center(),
+                *size,
+                Rect::new().with_color(Rgba::new(1.0, 1.0, 1.0, 0.8)),
+            );
+        }
+
         let debug_box = DebugBox::new();
-        winbox.add_child_at(
+        let debug_winbox = WinBox::new();
+        debug_winbox.add_child_at(
             Span::top_left(),
             Span::top_left() + Span::px(-16, -16),
             Span::px(366, 112),
             debug_box.root(),
         );
 
+        let minimap = Minimap::new(map_layer).with_rotating(map_rotates);
+        winbox.add_child_at(
+            Span::top_right(),
+            Span::top_right() + Span::px(16, -16),
+            Span::px(MINIMAP_SIZE, MINIMAP_SIZE),
+            minimap.clone(),
+        );
+
         let chat_box = ChatBox::new();
         winbox.add_child_at(
             Span::bottom_left(),
@@ -69,25 +126,25 @@ impl Hud {
             Span::px(316, 176),
             chat_box.root(),
         );
+        winbox.add_child_at(
+            Span::bottom_left(),
+            Span::bottom_left() + Span::px(-16, 56),
+            Span::px(316, 28),
+            chat_box.candidates.clone(),
+        );
 
-        let chat_enabled = Rc::new(AtomicBool::new(false));
         let events = Rc::new(RefCell::new(vec![]));
-
-        let chat_enabled_ref = chat_enabled.clone();
         let events_ref = events.clone();
 
         let chatbox_input = TextBox::new()
             .with_color(Rgba::new(1.0, 1.0, 1.0, 1.0))
             .with_background_color(Rgba::new(0.0, 0.0, 0.0, 0.8))
+            .with_focus_background_color(Rgba::new(0.0, 0.0, 0.3, 0.8))
             .with_margin(Span::px(8, 8))
-            .with_return_fn(move |chatbox_input, text| {
-                if chat_enabled_ref.load(Ordering::Relaxed) {
-                    events_ref
-                        .borrow_mut()
-                        .push(HudEvent::ChatMsgSent { text: text.to_string() });
-                    chat_enabled_ref.store(false, Ordering::Relaxed);
-                }
-                chatbox_input.set_background_color(Rgba::new(0.0, 0.0, 0.0, 0.8));
+            .with_return_fn(move |_, text| {
+                events_ref
+                    .borrow_mut()
+                    .push(HudEvent::ChatMsgSent { text: text.to_string() });
             })
             .with_text("".to_string());
 
@@ -98,19 +155,54 @@ impl Hud {
             chatbox_input.clone(),
         );
 
+        let paused = Cell::new(false);
+        let pause_ui = Ui::new(pause_menu(events.clone()));
+
+        let death_cause = Label::new()
+            .with_size(Span::px(16, 16))
+            .with_color(Rgba::new(1.0, 1.0, 1.0, 0.8))
+            .with_centered(true);
+        let death_ui = Ui::new(death_screen(events.clone(), death_cause.clone()));
+
+        let loading_label = Label::new()
+            .with_size(Span::px(16, 16))
+            .with_color(Rgba::new(1.0, 1.0, 1.0, 1.0));
+        let loading_box = WinBox::new();
+        loading_box.add_child_at(Span::center(), Span::center(), Span::px(320, 16), loading_label.clone());
+
+        let name_tags = WinBox::new();
+
         Hud {
             ui: Ui::new(winbox),
+            name_tags_ui: Ui::new(name_tags.clone()),
+            name_tags,
+            debug_ui: Ui::new(debug_winbox),
             debug_box,
+            hotbar,
             chat_box,
             chatbox_input,
+            minimap,
+            pause_ui,
+            paused,
+            death_ui,
+            death_cause,
+            dead: Cell::new(false),
+            loading_ui: Ui::new(loading_box),
+            loading_label,
 
-            chat_enabled,
             events,
         }
     }
 
     pub fn debug_box(&self) -> &DebugBox { &self.debug_box }
+    pub fn hotbar(&self) -> &Hotbar { &self.hotbar }
     pub fn chat_box(&self) -> &ChatBox { &self.chat_box }
+    pub fn minimap(&self) -> &Minimap { &self.minimap }
+
+    /// Set the player names and commands (without their leading `/`) that Tab completes in the chat
+    pub fn set_chat_completions(&self, players: Vec<String>, commands: Vec<String>) {
+        self.chat_box.completer.borrow_mut().set_words(players, commands);
+    }
 
     pub fn get_events(&self) -> Vec<HudEvent> {
         let mut events = vec![];
@@ -118,41 +210,204 @@ impl Hud {
         events
     }
 
-    pub fn render(&mut self, renderer: &mut Renderer) { self.ui.render(renderer); }
+    /// Replace the names shown over entities
+    pub fn set_name_tags(&self, tags: Vec<NameTag>) {
+        self.name_tags.clear();
+        for tag in tags {
+            let size = tag.size.round() as i32;
+            self.name_tags.add_child_at(
+                Span::rel(tag.pos.x, tag.pos.y),
+                Span::bottom(),
+                Span::px(NAME_TAG_WIDTH, size),
+                Label::new()
+                    .with_text(tag.text)
+                    .with_size(Span::px(size, size))
+                    .with_color(Rgba::new(1.0, 1.0, 1.0, tag.alpha))
+                    .with_centered(true),
+            );
+        }
+    }
+
+    pub fn is_paused(&self) -> bool { self.paused.get() }
+
+    /// Open the pause menu, or close it if it's open
+    pub fn toggle_pause(&self) {
+        self.paused.set(!self.paused.get());
+        self.ui.set_focus(None);
+        self.chat_box.reset_completion();
+    }
+
+    /// Show the death screen, saying what killed the player. Chat is closed, since it can't be used while dead.
+    pub fn show_death(&self, cause: &str) {
+        self.death_cause.set_text(cause.to_string());
+        self.dead.set(true);
+        self.ui.set_focus(None);
+        self.chat_box.reset_completion();
+    }
+
+    pub fn hide_death(&self) { self.dead.set(false); }
+
+    pub fn render(&mut self, renderer: &mut Renderer) {
+        self.name_tags_ui.render(renderer);
+        self.ui.render(renderer);
+        if self.dead.get() {
+            self.death_ui.render(renderer);
+        }
+        if self.paused.get() {
+            self.pause_ui.render(renderer);
+        }
+    }
+
+    /// Draw the debug box, which isn't drawn along with the rest of the HUD
+    pub fn render_debug(&mut self, renderer: &mut Renderer) { self.debug_ui.render(renderer); }
+
+    /// Draw the loading screen in place of the usual HUD, along with the pause menu if it's open
+    pub fn render_loading(&mut self, renderer: &mut Renderer, loaded: usize, required: usize) {
+        self.loading_label
+            .set_text(format!("Loading terrain... {}/{} chunks", loaded, required));
+        self.loading_ui.render(renderer);
+        if self.paused.get() {
+            self.pause_ui.render(renderer);
+        }
+    }
+
     pub fn handle_event(&self, event: &Event, renderer: &mut Renderer) -> bool {
-        match event {
-            Event::Character { ch } => {
-                if self.chat_enabled.load(Ordering::Relaxed) {
-                    self.ui.handle_event(event, renderer)
-                } else {
-                    if *ch == '\n' || *ch == '\r' {
-                        self.chat_enabled.store(true, Ordering::Relaxed);
-                        self.chatbox_input.set_background_color(Rgba::new(0.0, 0.0, 0.3, 0.8));
-
-                        true
-                    } else {
-                        false
+        // Nothing but the pause menu takes input while it's open, but the game still needs to know about the window
+        if self.paused.get() {
+            self.pause_ui.handle_event(event, renderer);
+            return match event {
+                Event::CloseRequest | Event::Resized { .. } | Event::Raw { .. } => false,
+                _ => true,
+            };
+        }
+        // The pause menu has to know where the cursor is before it's opened
+        if let Event::CursorPosition { .. } = event {
+            self.pause_ui.handle_event(event, renderer);
+        }
+
+        // The death screen takes the place of the rest of the HUD, and Return respawns instead of opening the chat
+        if self.dead.get() {
+            return match event {
+                Event::Character { ch: '\n' } | Event::Character { ch: '\r' } => {
+                    self.events.borrow_mut().push(HudEvent::Respawn);
+                    true
+                },
+                Event::MouseButton { .. } => {
+                    self.death_ui.handle_event(event, renderer);
+                    true
+                },
+                Event::CursorPosition { .. } => self.death_ui.handle_event(event, renderer),
+                _ => false,
+            };
+        }
+
+        let chat_focus = Some(self.chatbox_input.get_focus_id());
+        if self.ui.focus() == chat_focus {
+            match event {
+                // Tab completes the word being typed in the chat instead of moving the focus on
+                Event::KeyboardInput { i, .. } if i.virtual_keycode == Some(VirtualKeyCode::Tab) => {
+                    if i.state == ElementState::Pressed {
+                        self.chat_box.complete(&self.chatbox_input, i.modifiers.shift);
                     }
-                }
-            },
-            Event::KeyboardInput { .. } => {
-                if self.chat_enabled.load(Ordering::Relaxed) {
-                    self.ui.handle_event(event, renderer)
-                } else {
-                    false
-                }
+                    return true;
+                },
+                // The tab that comes with the key press has done its job already
+                Event::Character { ch: '\t' } => return true,
+                Event::Character { .. } => self.chat_box.reset_completion(),
+                _ => {},
+            }
+        }
+
+        let used = match event {
+            // Return opens the chat when nothing else has focus, and sending a message closes it again
+            Event::Character { ch: '\n' } | Event::Character { ch: '\r' } => match self.ui.focus() {
+                None => {
+                    self.ui.set_focus(chat_focus);
+                    true
+                },
+                focus => {
+                    let used = self.ui.handle_event(event, renderer);
+                    if focus == chat_focus {
+                        self.ui.set_focus(None);
+                    }
+                    used
+                },
             },
             _ => self.ui.handle_event(event, renderer),
+        };
+        if self.ui.focus() != chat_focus {
+            self.chat_box.reset_completion();
         }
+        used
     }
 }
 
+fn pause_menu(events: Rc<RefCell<Vec<HudEvent>>>) -> Rc<WinBox> {
+    let vbox = VBox::new()
+        .with_color(Rgba::new(0.0, 0.0, 0.0, 0.5))
+        .with_margin(Span::px(8, 8));
+
+    let button = |text: &str| {
+        Button::new()
+            .with_color(Rgba::new(0.2, 0.2, 0.2, 0.8))
+            .with_hover_color(Rgba::new(0.3, 0.3, 0.5, 0.8))
+            .with_click_color(Rgba::new(0.5, 0.5, 0.8, 0.8))
+            .with_margin(Span::px(8, 8))
+            .with_label(text.to_string(), Rgba::new(1.0, 1.0, 1.0, 1.0))
+    };
+
+    let events_ref = events.clone();
+    vbox.push_back(button("Resume").with_click_fn(move |_| events_ref.borrow_mut().push(HudEvent::Resume)));
+    let events_ref = events.clone();
+    vbox.push_back(
+        button("Reload settings").with_click_fn(move |_| events_ref.borrow_mut().push(HudEvent::ReloadSettings)),
+    );
+    let events_ref = events.clone();
+    vbox.push_back(
+        button("Disconnect to menu").with_click_fn(move |_| events_ref.borrow_mut().push(HudEvent::DisconnectToMenu)),
+    );
+    vbox.push_back(button("Quit").with_click_fn(move |_| events.borrow_mut().push(HudEvent::Quit)));
+
+    let winbox = WinBox::new().with_color(Rgba::new(0.0, 0.0, 0.0, 0.3));
+    winbox.add_child_at(Span::center(), Span::center(), Span::px(256, 160), vbox);
+    winbox
+}
+
+fn death_screen(events: Rc<RefCell<Vec<HudEvent>>>, cause: Rc<Label>) -> Rc<WinBox> {
+    let vbox = VBox::new()
+        .with_color(Rgba::new(0.0, 0.0, 0.0, 0.5))
+        .with_margin(Span::px(8, 8));
+
+    vbox.push_back(
+        Label::new()
+            .with_text("You died".to_string())
+            .with_size(Span::px(24, 24))
+            .with_color(Rgba::new(1.0, 0.3, 0.2, 1.0))
+            .with_centered(true),
+    );
+    vbox.push_back(cause);
+    vbox.push_back(
+        Button::new()
+            .with_color(Rgba::new(0.2, 0.2, 0.2, 0.8))
+            .with_hover_color(Rgba::new(0.3, 0.3, 0.5, 0.8))
+            .with_click_color(Rgba::new(0.5, 0.5, 0.8, 0.8))
+            .with_margin(Span::px(8, 8))
+            .with_label("Respawn".to_string(), Rgba::new(1.0, 1.0, 1.0, 1.0))
+            .with_click_fn(move |_| events.borrow_mut().push(HudEvent::Respawn)),
+    );
+
+    let winbox = WinBox::new().with_color(Rgba::new(0.3, 0.0, 0.0, 0.3));
+    winbox.add_child_at(Span::center(), Span::center(), Span::px(384, 120), vbox);
+    winbox
+}
+
 pub struct DebugBox {
     pub version_label: Rc<Label>,
     pub githash_label: Rc<Label>,
     pub buildtime_label: Rc<Label>,
     pub fps_label: Rc<Label>,
     pub pos_label: Rc<Label>,
+    pub chunk_memory_label: Rc<Label>,
     vbox: Rc<VBox>,
 }
 
@@ -178,6 +433,7 @@ impl DebugBox {
         let buildtime_label = vbox.push_back(template_label.clone_all());
         let fps_label = vbox.push_back(template_label.clone_all());
         let pos_label = vbox.push_back(template_label.clone_all());
+        let chunk_memory_label = vbox.push_back(template_label.clone_all());
 
         Self {
             version_label,
@@ -185,6 +441,7 @@ impl DebugBox {
             buildtime_label,
             fps_label,
             pos_label,
+            chunk_memory_label,
             vbox,
         }
     }
@@ -192,9 +449,63 @@ impl DebugBox {
     fn root(&self) -> Rc<VBox> { self.vbox.clone() }
 }
 
+/// The strip of slots along the bottom of the screen, showing what's in the first slots of the inventory and which one
+/// the player is holding
+pub struct Hotbar {
+    hbox: Rc<HBox>,
+    // The frame around each slot, which lights up for the one being held, and what's in it
+    frames: Vec<Rc<HBox>>,
+    icons: Vec<Rc<Rect>>,
+}
+
+impl Hotbar {
+    fn new() -> Self {
+        let hbox = HBox::new()
+            .with_color(Rgba::new(0.0, 0.0, 0.0, 0.5))
+            .with_margin(Span::px(8, 8));
+
+        let (mut frames, mut icons) = (vec![], vec![]);
+        for _ in 0..HOTBAR_SLOTS {
+            let frame = hbox.push_back(HBox::new().with_margin(Span::px(4, 4)));
+            icons.push(frame.push_back(Rect::new().with_color(Rgba::zero()).with_padding(Span::px(8, 8))));
+            frames.push(frame);
+        }
+
+        Self { hbox, frames, icons }
+    }
+
+    /// Show what's in each slot, and which one is held
+    pub fn set_slots(&self, items: &[Option<Item>], held: usize) {
+        for (i, (frame, icon)) in self.frames.iter().zip(self.icons.iter()).enumerate() {
+            frame.set_color(if i == held {
+                Rgba::new(1.0, 1.0, 1.0, 0.8)
+            } else {
+                Rgba::zero()
+            });
+            let item = items.get(i).cloned().unwrap_or(None);
+            icon.set_color(item.map(|item| item_color(item.kind)).unwrap_or(Rgba::zero()));
+        }
+    }
+
+    fn root(&self) -> Rc<HBox> { self.hbox.clone() }
+}
+
+// Items are drawn as plain squares of colour until they have icons
+fn item_color(kind: ItemKind) -> Rgba<f32> {
+    match kind {
+        ItemKind::Stone => Rgba::new(0.5, 0.5, 0.5, 1.0),
+        ItemKind::Wood => Rgba::new(0.55, 0.35, 0.15, 1.0),
+        ItemKind::Apple => Rgba::new(0.8, 0.1, 0.1, 1.0),
+        ItemKind::Sword => Rgba::new(0.75, 0.8, 0.9, 1.0),
+    }
+}
+
 pub struct ChatBox {
     vbox: Rc<VBox>,
     template_label: Rc<Label>,
+    completer: RefCell<Completer>,
+    // Just above the input line, listing what the word being completed could be
+    candidates: Rc<WinBox>,
 }
 
 impl ChatBox {
@@ -213,7 +524,12 @@ impl ChatBox {
             vbox.push_back(template_label.clone_all());
         }
 
-        Self { vbox, template_label }
+        Self {
+            vbox,
+            template_label,
+            completer: RefCell::new(Completer::new()),
+            candidates: WinBox::new(),
+        }
     }
 
     pub fn add_chat_msg(&self, text: String) {
@@ -221,5 +537,55 @@ impl ChatBox {
         self.vbox.push_back(self.template_label.clone_all().with_text(text));
     }
 
+    // Complete the word before the input's cursor, leaving the cursor after the completed word
+    fn complete(&self, input: &TextBox, backwards: bool) {
+        let text = input.get_text().clone();
+        let completed = self.completer.borrow_mut().complete(&text, input.get_cursor(), backwards);
+        if let Some((text, cursor)) = completed {
+            input.set_text(text);
+            input.set_cursor(cursor);
+        }
+        self.show_candidates();
+    }
+
+    fn reset_completion(&self) {
+        self.completer.borrow_mut().reset();
+        self.candidates.clear();
+    }
+
+    // List the page of candidates holding the selected one, with the selected one in brackets
+    fn show_candidates(&self) {
+        self.candidates.clear();
+        let completer = self.completer.borrow();
+        let selected = match completer.selected() {
+            Some(selected) => selected,
+            None => return,
+        };
+        let page = selected / CANDIDATES_SHOWN * CANDIDATES_SHOWN;
+        let mut words: Vec<_> = completer
+            .candidates()
+            .iter()
+            .enumerate()
+            .skip(page)
+            .take(CANDIDATES_SHOWN)
+            .map(|(i, word)| if i == selected { format!("[{}]", word) } else { word.clone() })
+            .collect();
+        if completer.candidates().len() > page + CANDIDATES_SHOWN {
+            words.push("...".to_string());
+        }
+
+        let hbox = HBox::new()
+            .with_color(Rgba::new(0.0, 0.0, 0.0, 0.8))
+            .with_margin(Span::px(8, 6));
+        hbox.push_back(
+            self.template_label
+                .clone_all()
+                .with_text(words.join("  "))
+                .with_color(Rgba::new(1.0, 1.0, 1.0, 1.0)),
+        );
+        self.candidates
+            .add_child_at(Span::top_left(), Span::top_left(), Span::full(), hbox);
+    }
+
     fn root(&self) -> Rc<VBox> { self.vbox.clone() }
 }
@@ -1,3 +1,4 @@
+use common::ecs::phys::MoveMode;
 use vek::Vec2;
 
 pub struct KeyState {
@@ -6,6 +7,10 @@ pub struct KeyState {
     pub up: bool,
     pub down: bool,
     pub jump: bool,
+    pub sprint: bool,
+    pub crouch: bool,
+    /// Whether the dig button is held, which is a mouse button rather than a key
+    pub dig: bool,
 }
 
 impl KeyState {
@@ -16,6 +21,9 @@ impl KeyState {
             up: false,
             down: false,
             jump: false,
+            sprint: false,
+            crouch: false,
+            dig: false,
         }
     }
 
@@ -27,4 +35,15 @@ impl KeyState {
     }
 
     pub fn jump(&self) -> bool { self.jump }
+
+    // Crouching wins if both are held, so that sprinting into a low gap doesn't stand the player up
+    pub fn move_mode(&self) -> MoveMode {
+        if self.crouch {
+            MoveMode::Crouch
+        } else if self.sprint {
+            MoveMode::Sprint
+        } else {
+            MoveMode::Walk
+        }
+    }
 }
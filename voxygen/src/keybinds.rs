@@ -191,6 +191,20 @@ pub fn str_to_vkcode(s: &str) -> Option<VirtualKeyCode> {
         "Y" => Some(VirtualKeyCode::Y),
         "Z" => Some(VirtualKeyCode::Z),
         "Escape" => Some(VirtualKeyCode::Escape),
+        "F1" => Some(VirtualKeyCode::F1),
+        "F2" => Some(VirtualKeyCode::F2),
+        "F3" => Some(VirtualKeyCode::F3),
+        "F4" => Some(VirtualKeyCode::F4),
+        "F5" => Some(VirtualKeyCode::F5),
+        "F6" => Some(VirtualKeyCode::F6),
+        "F7" => Some(VirtualKeyCode::F7),
+        "F8" => Some(VirtualKeyCode::F8),
+        "F9" => Some(VirtualKeyCode::F9),
+        "F10" => Some(VirtualKeyCode::F10),
+        "F11" => Some(VirtualKeyCode::F11),
+        "F12" => Some(VirtualKeyCode::F12),
+        "PageDown" => Some(VirtualKeyCode::PageDown),
+        "PageUp" => Some(VirtualKeyCode::PageUp),
         "Return" => Some(VirtualKeyCode::Return),
         "Space" => Some(VirtualKeyCode::Space),
         "LControl" => Some(VirtualKeyCode::LControl),
@@ -213,6 +227,7 @@ pub struct General {
     pub left: Option<VKeyCode>,
     pub right: Option<VKeyCode>,
     pub dodge: Option<VKeyCode>,
+    pub sprint: Option<VKeyCode>,
     pub crouch: Option<VKeyCode>,
     pub jump: Option<VKeyCode>,
 
@@ -225,11 +240,23 @@ pub struct General {
     pub skill_2: Option<VKeyCode>,
     pub skill_3: Option<VKeyCode>,
     pub use_item: Option<VKeyCode>,
+    // Both with Ctrl held
+    pub undo: Option<VKeyCode>,
+    pub redo: Option<VKeyCode>,
 
     // Menus
     pub chat: Option<VKeyCode>,
     pub inventory: Option<VKeyCode>,
     pub pause: Option<VKeyCode>,
+    pub map_zoom_in: Option<VKeyCode>,
+    pub map_zoom_out: Option<VKeyCode>,
+
+    // Window
+    pub fullscreen: Option<VKeyCode>,
+    pub vsync: Option<VKeyCode>,
+    pub screenshot: Option<VKeyCode>,
+    pub toggle_ui: Option<VKeyCode>,
+    pub toggle_debug: Option<VKeyCode>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq)]
@@ -275,6 +302,7 @@ impl Keybinds {
                     left: Some(general.left.unwrap_or(default_keys.general.left.unwrap())),
                     right: Some(general.right.unwrap_or(default_keys.general.right.unwrap())),
                     dodge: Some(general.dodge.unwrap_or(default_keys.general.dodge.unwrap())),
+                    sprint: Some(general.sprint.unwrap_or(default_keys.general.sprint.unwrap())),
                     crouch: Some(general.crouch.unwrap_or(default_keys.general.crouch.unwrap())),
                     jump: Some(general.jump.unwrap_or(default_keys.general.jump.unwrap())),
                     attack_1: None,
@@ -284,10 +312,19 @@ impl Keybinds {
                     skill_2: None,
                     skill_3: None,
                     use_item: None,
+                    undo: Some(general.undo.unwrap_or(default_keys.general.undo.unwrap())),
+                    redo: Some(general.redo.unwrap_or(default_keys.general.redo.unwrap())),
                     mount: Some(general.mount.unwrap_or(default_keys.general.mount.unwrap())),
                     chat: Some(general.chat.unwrap_or(default_keys.general.chat.unwrap())),
                     inventory: Some(general.inventory.unwrap_or(default_keys.general.inventory.unwrap())),
                     pause: Some(general.pause.unwrap_or(default_keys.general.pause.unwrap())),
+                    map_zoom_in: Some(general.map_zoom_in.unwrap_or(default_keys.general.map_zoom_in.unwrap())),
+                    map_zoom_out: Some(general.map_zoom_out.unwrap_or(default_keys.general.map_zoom_out.unwrap())),
+                    fullscreen: Some(general.fullscreen.unwrap_or(default_keys.general.fullscreen.unwrap())),
+                    vsync: Some(general.vsync.unwrap_or(default_keys.general.vsync.unwrap())),
+                    screenshot: Some(general.screenshot.unwrap_or(default_keys.general.screenshot.unwrap())),
+                    toggle_ui: Some(general.toggle_ui.unwrap_or(default_keys.general.toggle_ui.unwrap())),
+                    toggle_debug: Some(general.toggle_debug.unwrap_or(default_keys.general.toggle_debug.unwrap())),
                 },
 
                 mount: Mount {
@@ -316,6 +353,7 @@ impl Keybinds {
                 left: Some(VKeyCode(VirtualKeyCode::A)),
                 right: Some(VKeyCode(VirtualKeyCode::D)),
                 dodge: Some(VKeyCode(VirtualKeyCode::LShift)),
+                sprint: Some(VKeyCode(VirtualKeyCode::LShift)),
                 crouch: Some(VKeyCode(VirtualKeyCode::LControl)),
                 jump: Some(VKeyCode(VirtualKeyCode::Space)),
 
@@ -327,10 +365,20 @@ impl Keybinds {
                 skill_2: None,
                 skill_3: None,
                 use_item: Some(VKeyCode(VirtualKeyCode::Q)),
+                undo: Some(VKeyCode(VirtualKeyCode::Z)),
+                redo: Some(VKeyCode(VirtualKeyCode::Y)),
 
                 chat: Some(VKeyCode(VirtualKeyCode::Return)),
                 inventory: Some(VKeyCode(VirtualKeyCode::I)),
                 pause: Some(VKeyCode(VirtualKeyCode::Escape)),
+                map_zoom_in: Some(VKeyCode(VirtualKeyCode::PageUp)),
+                map_zoom_out: Some(VKeyCode(VirtualKeyCode::PageDown)),
+
+                fullscreen: Some(VKeyCode(VirtualKeyCode::F11)),
+                vsync: Some(VKeyCode(VirtualKeyCode::F10)),
+                screenshot: Some(VKeyCode(VirtualKeyCode::F2)),
+                toggle_ui: Some(VKeyCode(VirtualKeyCode::F1)),
+                toggle_debug: Some(VKeyCode(VirtualKeyCode::F3)),
             },
 
             mount: Mount {
@@ -1,4 +1,5 @@
 #![feature(nll, euclidean_division, arbitrary_self_types, duration_float)]
+#![cfg_attr(test, feature(test))]
 
 // Graphics
 #[macro_use]
@@ -6,36 +7,51 @@ extern crate gfx;
 
 #[macro_use]
 extern crate log;
+#[cfg(test)]
+extern crate test;
 
 // Modules
+mod anim;
+mod app;
 mod camera;
+mod cli;
+mod completion;
+mod figure;
 mod game;
 mod key_state;
 mod keybinds;
+mod menu;
+mod settings;
+mod singleplayer;
 mod tests;
 mod ui;
 mod window;
 
 // > Rendering
+mod buffer;
 mod consts;
 mod hud;
 mod pipeline;
 mod renderer;
+mod screenshot;
 mod shader;
+mod shader_watcher;
 
 // > Pipelines
 mod audio;
+mod map;
+mod outline;
+mod shadow;
 mod skybox;
 mod tonemapper;
 mod voxel;
+mod weather;
 
 // Standard
 use std::{
-    io::{self, Write},
     panic,
     path::{Path, PathBuf},
-    thread,
-    time::Duration,
+    process,
 };
 
 // Library
@@ -43,11 +59,17 @@ use chrono::{DateTime, TimeZone, Utc};
 use parking_lot::Mutex;
 
 // Project
-use client::PlayMode;
-use common::get_version;
+use common::{
+    get_version,
+    util::logging::{self, FileSink, LogConfig},
+};
 
 // Local
-use crate::{game::Game, renderer::RendererInfo};
+use crate::{
+    app::{App, State},
+    renderer::RendererInfo,
+    settings::Settings,
+};
 
 // START Environment variables
 const GIT_HASH: Option<&'static str> = option_env!("GIT_HASH");
@@ -63,10 +85,26 @@ pub fn get_build_time() -> DateTime<Utc> { Utc.timestamp(BUILD_TIME.unwrap_or("-
 pub fn get_shader_dir() -> &'static Path { Path::new(option_env!("VOXYGEN_SHADERS").unwrap_or("shaders/")) }
 // END Environment variables
 
+const LOG_FILE: &str = "voxygen.log";
+
 pub fn get_shader_path(rpath: &str) -> PathBuf { get_shader_dir().join(rpath) }
 
 static RENDERER_INFO: Mutex<Option<RendererInfo>> = Mutex::new(None);
 
+fn init_logging() {
+    let log_config = LogConfig::from_env();
+    let log_file = dirs::data_dir().map(|dir| dir.join("veloren").join("voxygen").join("logs").join(LOG_FILE));
+    // Without a log file there's still stderr, so carry on
+    let result = match log_file {
+        Some(path) => logging::init(log_config.clone().with_file(FileSink::new(path))),
+        None => logging::init(log_config.clone()),
+    };
+    if let Err(e) = result {
+        let _ = logging::init(log_config);
+        warn!("Could not open the log file: {}", e);
+    }
+}
+
 fn set_panic_handler() {
     let default_hook = panic::take_hook();
     panic::set_hook(Box::new(move |details| {
@@ -81,71 +119,31 @@ fn set_panic_handler() {
 }
 
 fn main() {
-    pretty_env_logger::init();
+    init_logging();
     set_panic_handler();
 
     info!("Starting Voxygen... Version: {}", get_version());
 
-    let mut args = std::env::args();
-    let mut remote_addr = String::new();
-    let mut remote_choice = String::new();
-
-    // expects single command line argument that is the remote_addr
-    if args.len() == 2 {
-        remote_addr = args.nth(1).expect("No argument");
-    } else {
-        println!("");
-        println!("Which server you want to connect to?");
-        println!("    Press (1) to connect to the public veloren server (default)");
-        println!("    Press (2) to connect to localhost");
-        println!("    Press (3) to connect to another internet server");
-        println!("");
-        io::stdout().flush().expect("Failed to flush");
-        io::stdin().read_line(&mut remote_choice).unwrap();
-        let remote_choice = remote_choice.trim();
-        if remote_choice == "2" {
-            remote_addr = "127.0.0.1:59003".to_string();
-        } else if remote_choice == "3" {
-            // If args aren't correct then read from stdin
-            print!("Enter address (e.g. 127.0.0.1:59003):");
-            io::stdout().flush().expect("Failed to flush");
-            io::stdin().read_line(&mut remote_addr).unwrap();
-        } else {
-            remote_addr = "veloren.pftclan.de:38888".to_string();
-        }
-
-        remote_addr = remote_addr.trim().to_string();
-    }
-
-    println!("What name do you want to use?");
-    let mut name_choice = String::new();
-    io::stdout().flush().expect("Failed to flush");
-    io::stdin().read_line(&mut name_choice).unwrap();
-    let mut name_choice = name_choice.trim();
-    if name_choice.len() == 0 {
-        println!("No name chosen, generating random one...");
-        name_choice = common::util::names::generate();
-    }
-
-    println!("");
-    println!("What view distance do you want to use?");
-    println!("For a smooth experience on slower hardware, we recommend 80.");
-    println!("For faster computers, 400 is advised.");
-    println!("If you experience lag, restart Veloren and change this setting again.");
-    println!("");
-    let mut view_distance_choice = String::new();
-    io::stdout().flush().expect("Failed to flush");
-    io::stdin().read_line(&mut view_distance_choice).unwrap();
-    let view_distance = view_distance_choice.trim().parse::<i64>().unwrap_or_else(|_| {
-        println!("Invalid input, defaulting to 80.");
-        80
+    let opts = cli::parse(Settings::load()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
     });
-    println!("using a view distance of {}.", view_distance);
+    println!("using a view distance of {}.", opts.settings.graphics.view_distance);
 
-    println!("Connecting to {}", remote_addr);
+    // A name given on the command line is the one filled in at the main menu too
+    let mut settings = opts.settings;
+    if let Some(alias) = opts.alias {
+        settings.network.alias = alias;
+    }
 
-    // wait 100ms to give the user time to lift their finger up from the enter key so the chat isn't opened immediately after start
-    thread::sleep(Duration::from_millis(100));
+    // Go straight into the game if told where to play, otherwise start at the main menu
+    let state = match opts.target {
+        Some(target) => State::Playing {
+            alias: app::pick_alias(settings.network.alias.clone()),
+            target,
+        },
+        None => State::Menu { error: None },
+    };
 
-    Game::new(PlayMode::Character, name_choice, remote_addr, view_distance).run();
+    App::new(settings, opts.record).run(state);
 }
@@ -0,0 +1,161 @@
+// Standard
+use std::collections::HashMap;
+
+// Library
+use gfx::{
+    format::{ChannelType, Rgba8, Swizzle, R8_G8_B8_A8},
+    handle::{Sampler, ShaderResourceView, Texture},
+    memory::{Bind, Usage},
+    texture::{AaMode, FilterMethod, ImageInfoCommon, Kind, SamplerInfo, WrapMode},
+    Encoder, Factory,
+};
+use gfx_device_gl;
+use vek::*;
+
+// Project
+use common::terrain::VolOffs;
+
+// Local
+use super::{Tile, TILE_SIZE};
+
+// Constants
+// How many tiles fit along each side of the atlas. Enough for the most the map shows at once when zoomed out fully.
+const ATLAS_TILES: usize = 32;
+/// How much of the atlas each tile takes up along each side, in texture coordinates
+pub const TILE_UV: f32 = 1.0 / ATLAS_TILES as f32;
+
+pub type AtlasView = ShaderResourceView<gfx_device_gl::Resources, [f32; 4]>;
+
+// Which slot of the atlas holds each column's tile, and the frame it was last drawn in
+pub(crate) struct Slots {
+    used: HashMap<Vec2<VolOffs>, (usize, u64)>,
+    free: Vec<usize>,
+    frame: u64,
+}
+
+impl Slots {
+    pub fn new(count: usize) -> Slots {
+        Slots {
+            used: HashMap::new(),
+            free: (0..count).rev().collect(),
+            frame: 0,
+        }
+    }
+
+    pub fn next_frame(&mut self) { self.frame += 1; }
+
+    /// The slot holding the tile for `column`, if there is one, which then counts as drawn this frame
+    pub fn get(&mut self, column: Vec2<VolOffs>) -> Option<usize> {
+        let frame = self.frame;
+        self.used.get_mut(&column).map(|(slot, drawn)| {
+            *drawn = frame;
+            *slot
+        })
+    }
+
+    /// A slot for the tile for `column`. Once they're all taken, the one drawn longest ago is given up, unless it was
+    /// drawn this frame.
+    pub fn alloc(&mut self, column: Vec2<VolOffs>) -> Option<usize> {
+        if let Some(slot) = self.get(column) {
+            return Some(slot);
+        }
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => {
+                let (&oldest, &(slot, drawn)) = self.used.iter().min_by_key(|(_, (_, drawn))| *drawn)?;
+                if drawn == self.frame {
+                    return None;
+                }
+                self.used.remove(&oldest);
+                slot
+            },
+        };
+        self.used.insert(column, (slot, self.frame));
+        Some(slot)
+    }
+
+    pub fn forget(&mut self, column: Vec2<VolOffs>) {
+        if let Some((slot, _)) = self.used.remove(&column) {
+            self.free.push(slot);
+        }
+    }
+}
+
+/// The minimap's tiles on the GPU, all in one texture so that the map can be drawn in one go. Only the tiles the map
+/// has drawn lately are kept in it.
+pub struct TileAtlas {
+    texture: Texture<gfx_device_gl::Resources, R8_G8_B8_A8>,
+    view: AtlasView,
+    sampler: Sampler<gfx_device_gl::Resources>,
+    slots: Slots,
+}
+
+impl TileAtlas {
+    pub fn new(factory: &mut gfx_device_gl::Factory) -> TileAtlas {
+        let size = (ATLAS_TILES * TILE_SIZE) as u16;
+        let texture = factory
+            .create_texture::<R8_G8_B8_A8>(
+                Kind::D2(size, size, AaMode::Single),
+                1,
+                Bind::SHADER_RESOURCE | Bind::TRANSFER_DST,
+                Usage::Data,
+                Some(ChannelType::Unorm),
+            )
+            .expect("Failed to create the map atlas");
+        let view = factory
+            .view_texture_as_shader_resource::<Rgba8>(&texture, (0, 0), Swizzle::new())
+            .expect("Failed to view the map atlas");
+        // Each texel stands for a block, so they mustn't be blended together
+        let sampler = factory.create_sampler(SamplerInfo::new(FilterMethod::Scale, WrapMode::Clamp));
+
+        TileAtlas {
+            texture,
+            view,
+            sampler,
+            slots: Slots::new(ATLAS_TILES * ATLAS_TILES),
+        }
+    }
+
+    /// Start keeping track of which tiles are drawn in a new frame
+    pub fn next_frame(&mut self) { self.slots.next_frame(); }
+
+    /// Where the tile for `column` is in the atlas, as the texture coordinates of its south west corner
+    pub fn get(&mut self, column: Vec2<VolOffs>) -> Option<Vec2<f32>> { self.slots.get(column).map(slot_uv) }
+
+    /// Put the tile for `column` into the atlas, over the old one if it's there already. Returns where it went, or
+    /// `None` if there's no room left for it this frame.
+    pub fn upload(
+        &mut self,
+        encoder: &mut Encoder<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer>,
+        column: Vec2<VolOffs>,
+        tile: &Tile,
+    ) -> Option<Vec2<f32>> {
+        let slot = self.slots.alloc(column)?;
+        let info = ImageInfoCommon {
+            xoffset: ((slot % ATLAS_TILES) * TILE_SIZE) as u16,
+            yoffset: ((slot / ATLAS_TILES) * TILE_SIZE) as u16,
+            zoffset: 0,
+            width: TILE_SIZE as u16,
+            height: TILE_SIZE as u16,
+            depth: 0,
+            format: (),
+            mipmap: 0,
+        };
+        match encoder.update_texture::<R8_G8_B8_A8, Rgba8>(&self.texture, None, info, tile.texels()) {
+            Ok(()) => Some(slot_uv(slot)),
+            Err(e) => {
+                warn!("failed to upload the map tile for {}: {:?}", column, e);
+                self.slots.forget(column);
+                None
+            },
+        }
+    }
+
+    /// Drop the tile for `column`, which is out of date
+    pub fn forget(&mut self, column: Vec2<VolOffs>) { self.slots.forget(column); }
+
+    pub fn view(&self) -> &AtlasView { &self.view }
+    pub fn sampler(&self) -> &Sampler<gfx_device_gl::Resources> { &self.sampler }
+}
+
+fn slot_uv(slot: usize) -> Vec2<f32> { Vec2::new(slot % ATLAS_TILES, slot / ATLAS_TILES).map(|e| e as f32) * TILE_UV }
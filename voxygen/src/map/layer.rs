@@ -0,0 +1,136 @@
+// Standard
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    mem,
+    sync::Arc,
+};
+
+// Library
+use parking_lot::{Mutex, RwLock};
+use vek::*;
+
+// Project
+use common::terrain::{
+    chunk::{Block, CHUNK_SIZE},
+    ReadVolume, VolOffs, VoxAbs,
+};
+
+// Local
+use crate::voxel::RenderVoxel;
+
+// Constants
+/// How many texels there are along each side of a tile, one for each column of blocks in a chunk
+pub const TILE_SIZE: usize = CHUNK_SIZE.x as usize;
+
+// The top of a chunk: the highest block in each of its columns and how far up the chunk it is. Water counts, so that
+// lakes and the sea show up as water rather than as whatever is beneath them.
+#[derive(PartialEq)]
+struct Surface(Vec<Option<(Block, u8)>>);
+
+impl Surface {
+    // `None` if the chunk is empty
+    fn of(vol: &dyn ReadVolume<VoxelType = Block>) -> Option<Surface> {
+        let size = vol.size();
+        let mut tops = Vec::with_capacity((size.x * size.y) as usize);
+        for y in 0..size.y {
+            for x in 0..size.x {
+                tops.push(
+                    vol.column(x, y)
+                        .enumerate()
+                        .rev()
+                        .map(|(z, block)| (block, z as u8))
+                        .find(|(block, _)| block.is_occupied()),
+                );
+            }
+        }
+        if tops.iter().any(|top| top.is_some()) {
+            Some(Surface(tops))
+        } else {
+            None
+        }
+    }
+}
+
+/// What the map shows of a column of chunks, as the RGBA texels it's drawn from. Each texel holds the palette attribute
+/// of the block at the top of its column in red and green, half its height in blue, and whether anything was found
+/// there at all in alpha.
+pub struct Tile {
+    texels: Vec<[u8; 4]>,
+}
+
+impl Tile {
+    // The top of the highest chunk with something in each column
+    fn of(chunks: &BTreeMap<VolOffs, Surface>) -> Tile {
+        let texels = (0..TILE_SIZE * TILE_SIZE)
+            .map(|i| {
+                chunks
+                    .iter()
+                    .rev()
+                    .filter_map(|(z, surface)| {
+                        surface.0[i].map(|(block, top)| {
+                            (block, *z as VoxAbs * CHUNK_SIZE.z as VoxAbs + top as VoxAbs)
+                        })
+                    })
+                    .next()
+                    .map(|(block, height)| {
+                        let attr = block.get_palette();
+                        [attr as u8, (attr >> 8) as u8, (height / 2).max(0).min(255) as u8, 255]
+                    })
+                    .unwrap_or([0; 4])
+            })
+            .collect();
+        Tile { texels }
+    }
+
+    /// Row by row, from the south west corner
+    pub fn texels(&self) -> &[[u8; 4]] { &self.texels }
+
+    pub fn texel(&self, pos: Vec2<usize>) -> [u8; 4] { self.texels[pos.y * TILE_SIZE + pos.x] }
+}
+
+/// The top of the terrain as seen from above, one tile for each column of chunks. Tiles are made on the threads that
+/// chunk payloads are generated on, and are kept for the rest of the session, so places the player has left still
+/// show on the map.
+pub struct MapLayer {
+    // The top of each chunk that's had something in it, by column and then height
+    columns: Mutex<HashMap<Vec2<VolOffs>, BTreeMap<VolOffs, Surface>>>,
+    tiles: RwLock<HashMap<Vec2<VolOffs>, Arc<Tile>>>,
+    // Columns whose tile has been made again since the map last asked
+    changed: Mutex<HashSet<Vec2<VolOffs>>>,
+}
+
+impl MapLayer {
+    pub fn new() -> MapLayer {
+        MapLayer {
+            columns: Mutex::new(HashMap::new()),
+            tiles: RwLock::new(HashMap::new()),
+            changed: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Look over the chunk at `pos`, which has just loaded or changed. Its column's tile is only made again if the top
+    /// of the chunk is different to last time.
+    pub fn update(&self, pos: Vec3<VolOffs>, vol: &dyn ReadVolume<VoxelType = Block>) {
+        let surface = Surface::of(vol);
+        let column = Vec2::from(pos);
+
+        let mut columns = self.columns.lock();
+        let chunks = columns.entry(column).or_insert_with(BTreeMap::new);
+        if chunks.get(&pos.z) == surface.as_ref() && self.tiles.read().contains_key(&column) {
+            return;
+        }
+        match surface {
+            Some(surface) => chunks.insert(pos.z, surface),
+            None => chunks.remove(&pos.z),
+        };
+        // Still holding the columns, so that a tile made from older surfaces can't replace this one
+        self.tiles.write().insert(column, Arc::new(Tile::of(chunks)));
+        self.changed.lock().insert(column);
+    }
+
+    /// The tile for a column, if any of its chunks have been loaded this session
+    pub fn tile(&self, column: Vec2<VolOffs>) -> Option<Arc<Tile>> { self.tiles.read().get(&column).cloned() }
+
+    /// The columns whose tiles have changed since this was last called
+    pub fn take_changed(&self) -> HashSet<Vec2<VolOffs>> { mem::replace(&mut *self.changed.lock(), HashSet::new()) }
+}
@@ -0,0 +1,211 @@
+// Standard
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::Arc,
+};
+
+// Library
+use gfx::{self, preset::blend::ALPHA, state::ColorMask, traits::FactoryExt};
+use vek::*;
+
+// Project
+use common::terrain::VolOffs;
+
+// Local
+use super::{MapLayer, TILE_SIZE, TILE_UV};
+use crate::{
+    get_shader_path,
+    pipeline::Pipeline,
+    renderer::{ColorFormat, Renderer},
+    shader::Shader,
+    ui::{
+        element::{Bounds, Element},
+        rescache::ResCache,
+    },
+};
+
+// Constants
+// How many blocks each pixel of the map covers at each zoom level, from closest in to furthest out
+const ZOOM_LEVELS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const DEFAULT_ZOOM: usize = 2;
+// The most tiles sent to the GPU in a frame, so that arriving somewhere new doesn't hold a frame up
+const MAX_UPLOADS: usize = 32;
+// Drawn over anywhere that hasn't been loaded
+const FOG_COLOR: Rgba<f32> = Rgba {
+    r: 0.55,
+    g: 0.58,
+    b: 0.62,
+    a: 1.0,
+};
+const MARKER_COLOR: Rgba<f32> = Rgba {
+    r: 1.0,
+    g: 0.25,
+    b: 0.15,
+    a: 1.0,
+};
+// How far the player marker reaches from the player, in pixels
+const MARKER_SIZE: f32 = 6.0;
+// Fog and the marker have no tile, so they're given texture coordinates off the atlas
+const NO_TILE: Vec2<f32> = Vec2 { x: -1.0, y: -1.0 };
+
+gfx_defines! {
+    vertex Vertex {
+        pos: [f32; 2] = "vert_pos",
+        uv: [f32; 2] = "vert_uv",
+        local: [f32; 2] = "vert_local",
+        col: [f32; 4] = "vert_col",
+    }
+
+    pipeline pipeline {
+        vbo: gfx::VertexBuffer<Vertex> = (),
+        atlas: gfx::TextureSampler<[f32; 4]> = "t_Map",
+        out_color: gfx::BlendTarget<ColorFormat> = ("target", ColorMask::all(), ALPHA),
+    }
+}
+
+/// A round map of the terrain around the player, drawn from the tiles of a `MapLayer`. North is up, unless the map
+/// turns with the camera, in which case the way the camera faces is.
+pub struct Minimap {
+    layer: Arc<MapLayer>,
+    // Built the first time the map is drawn
+    pipeline: RefCell<Option<Pipeline<pipeline::Init<'static>>>>,
+    player_pos: Cell<Vec2<f32>>,
+    player_facing: Cell<f32>,
+    cam_yaw: Cell<f32>,
+    rotating: Cell<bool>,
+    zoom: Cell<usize>,
+}
+
+impl Minimap {
+    pub fn new(layer: Arc<MapLayer>) -> Rc<Self> {
+        Rc::new(Self {
+            layer,
+            pipeline: RefCell::new(None),
+            player_pos: Cell::new(Vec2::zero()),
+            player_facing: Cell::new(0.0),
+            cam_yaw: Cell::new(0.0),
+            rotating: Cell::new(false),
+            zoom: Cell::new(DEFAULT_ZOOM),
+        })
+    }
+
+    pub fn with_rotating(self: Rc<Self>, rotating: bool) -> Rc<Self> {
+        self.rotating.set(rotating);
+        self
+    }
+
+    /// Center the map on the player at `pos`, facing `facing` radians clockwise from north, with the camera facing
+    /// `cam_yaw` the same way
+    pub fn set_view(&self, pos: Vec2<f32>, facing: f32, cam_yaw: f32) {
+        self.player_pos.set(pos);
+        self.player_facing.set(facing);
+        self.cam_yaw.set(cam_yaw);
+    }
+
+    pub fn zoom_in(&self) { self.zoom.set(self.zoom.get().saturating_sub(1)); }
+    pub fn zoom_out(&self) { self.zoom.set((self.zoom.get() + 1).min(ZOOM_LEVELS.len() - 1)); }
+
+    fn build_pipeline(renderer: &mut Renderer) -> Pipeline<pipeline::Init<'static>> {
+        Pipeline::new(
+            renderer.factory_mut(),
+            pipeline::new(),
+            &Shader::from_file(get_shader_path("map/map.vert")).expect("Could not load map vertex shader"),
+            &Shader::from_file(get_shader_path("map/map.frag")).expect("Could not load map fragment shader"),
+        )
+    }
+}
+
+impl Element for Minimap {
+    fn deep_clone(&self) -> Rc<dyn Element> {
+        Rc::new(Self {
+            layer: self.layer.clone(),
+            pipeline: RefCell::new(None),
+            player_pos: self.player_pos.clone(),
+            player_facing: self.player_facing.clone(),
+            cam_yaw: self.cam_yaw.clone(),
+            rotating: self.rotating.clone(),
+            zoom: self.zoom.clone(),
+        })
+    }
+
+    fn render(&self, renderer: &mut Renderer, _rescache: &mut ResCache, bounds: Bounds) {
+        let res = renderer.get_view_resolution().map(|e| e as f32);
+        // The map is round, and fitted into the middle of its bounds. Everything is worked out in pixels from its
+        // center with y going up, which is north unless the map turns with the camera.
+        let center_px = (bounds.0 + bounds.1 / 2.0) * res;
+        let size_px = bounds.1 * res;
+        let radius = size_px.x.min(size_px.y) / 2.0;
+        let scale = ZOOM_LEVELS[self.zoom.get()];
+        let view_radius = radius * scale;
+        let player_pos = self.player_pos.get();
+        let turn = if self.rotating.get() { self.cam_yaw.get() } else { 0.0 };
+        let (sin, cos) = turn.sin_cos();
+
+        let to_map = |pos: Vec2<f32>| {
+            let rel = (pos - player_pos) / scale;
+            Vec2::new(rel.x * cos - rel.y * sin, rel.x * sin + rel.y * cos)
+        };
+        let vertex = |offs: Vec2<f32>, uv: Vec2<f32>, col: Rgba<f32>| Vertex {
+            pos: ((center_px + Vec2::new(offs.x, -offs.y)) / res).into_array(),
+            uv: uv.into_array(),
+            local: (offs / radius).into_array(),
+            col: col.into_array(),
+        };
+
+        renderer.map_atlas_mut().next_frame();
+        for column in self.layer.take_changed() {
+            renderer.map_atlas_mut().forget(column);
+        }
+
+        let tile_size = TILE_SIZE as f32;
+        let lo = ((player_pos - Vec2::broadcast(view_radius)) / tile_size).map(|e| e.floor() as VolOffs);
+        let hi = ((player_pos + Vec2::broadcast(view_radius)) / tile_size).map(|e| e.floor() as VolOffs);
+        let mut verts = vec![];
+        let mut uploads = 0;
+        for y in lo.y..=hi.y {
+            for x in lo.x..=hi.x {
+                let column = Vec2::new(x, y);
+                let corner = column.map(|e| e as f32) * tile_size;
+                let nearest = Vec2::clamp(player_pos, corner, corner + Vec2::broadcast(tile_size));
+                if nearest.distance(player_pos) > view_radius {
+                    continue;
+                }
+
+                // Columns that have never been loaded, or whose tile can't be uploaded yet, are fogged over
+                let uv = match renderer.map_atlas_mut().get(column) {
+                    Some(uv) => Some(uv),
+                    None if uploads < MAX_UPLOADS => self.layer.tile(column).and_then(|tile| {
+                        uploads += 1;
+                        renderer.upload_map_tile(column, &tile)
+                    }),
+                    None => None,
+                };
+                let (uv, uv_size) = uv.map(|uv| (uv, TILE_UV)).unwrap_or((NO_TILE, 0.0));
+
+                let corners = [Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), Vec2::new(1.0, 1.0), Vec2::new(0.0, 1.0)]
+                    .iter()
+                    .map(|c| vertex(to_map(corner + *c * tile_size), uv + *c * uv_size, FOG_COLOR))
+                    .collect::<Vec<_>>();
+                verts.extend_from_slice(&[corners[0], corners[1], corners[2], corners[0], corners[2], corners[3]]);
+            }
+        }
+
+        // The player is always in the middle, pointing the way they face
+        let (sin, cos) = (self.player_facing.get() - turn).sin_cos();
+        let (ahead, left) = (Vec2::new(sin, cos) * MARKER_SIZE, Vec2::new(-cos, sin) * MARKER_SIZE);
+        verts.push(vertex(ahead, NO_TILE, MARKER_COLOR));
+        verts.push(vertex((left - ahead) * 0.6, NO_TILE, MARKER_COLOR));
+        verts.push(vertex((-left - ahead) * 0.6, NO_TILE, MARKER_COLOR));
+
+        let mut pso = self.pipeline.borrow_mut();
+        let pso = pso.get_or_insert_with(|| Self::build_pipeline(renderer));
+        let (vbo, slice) = renderer.factory_mut().create_vertex_buffer_with_slice(&verts[..], ());
+        let data = pipeline::Data {
+            vbo,
+            atlas: (renderer.map_atlas().view().clone(), renderer.map_atlas().sampler().clone()),
+            out_color: renderer.color_view().clone(),
+        };
+        renderer.encoder_mut().draw(&slice, pso.pso(), &data);
+    }
+}
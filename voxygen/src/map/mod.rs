@@ -0,0 +1,12 @@
+mod atlas;
+mod layer;
+mod minimap;
+#[cfg(test)]
+mod tests;
+
+// Reexports
+pub use self::{
+    atlas::{TileAtlas, TILE_UV},
+    layer::{MapLayer, Tile, TILE_SIZE},
+    minimap::Minimap,
+};
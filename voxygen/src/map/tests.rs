@@ -0,0 +1,92 @@
+// Library
+use vek::*;
+
+// Project
+use common::terrain::{
+    chunk::{Block, HeterogeneousData, HomogeneousData, CHUNK_SIZE},
+    ConstructVolume, ReadWriteVolume,
+};
+
+// Local
+use super::{atlas::Slots, MapLayer};
+use crate::voxel::RenderVoxel;
+
+// A stone floor `height` blocks thick
+fn floor(height: u32) -> HeterogeneousData {
+    let mut blocks = HeterogeneousData::filled(CHUNK_SIZE, Block::AIR);
+    for x in 0..CHUNK_SIZE.x {
+        for y in 0..CHUNK_SIZE.y {
+            for z in 0..height {
+                blocks.set_at(Vec3::new(x, y, z), Block::STONE);
+            }
+        }
+    }
+    blocks
+}
+
+fn palette(block: Block) -> [u8; 2] {
+    let attr = block.get_palette();
+    [attr as u8, (attr >> 8) as u8]
+}
+
+#[test]
+fn tiles_show_the_highest_block_in_each_column() {
+    let layer = MapLayer::new();
+    layer.update(Vec3::new(0, 0, 0), &floor(4));
+    let mut above = HeterogeneousData::filled(CHUNK_SIZE, Block::AIR);
+    above.set_at(Vec3::new(3, 7, 5), Block::GRASS);
+    layer.update(Vec3::new(0, 0, 1), &above);
+
+    let tile = layer.tile(Vec2::zero()).unwrap();
+    // Heights are halved to fit in a byte
+    assert_eq!(tile.texel(Vec2::new(3, 7)), [palette(Block::GRASS)[0], palette(Block::GRASS)[1], 18, 255]);
+    assert_eq!(tile.texel(Vec2::new(7, 3)), [palette(Block::STONE)[0], palette(Block::STONE)[1], 1, 255]);
+
+    // Taking the grass away shows the floor again
+    layer.update(Vec3::new(0, 0, 1), &HomogeneousData::filled(CHUNK_SIZE, Block::AIR));
+    assert_eq!(layer.tile(Vec2::zero()).unwrap().texel(Vec2::new(3, 7))[2], 1);
+}
+
+#[test]
+fn columns_are_fogged_until_something_is_found_in_them() {
+    let layer = MapLayer::new();
+    assert!(layer.tile(Vec2::zero()).is_none());
+
+    layer.update(Vec3::new(0, 0, 3), &HomogeneousData::filled(CHUNK_SIZE, Block::AIR));
+    let tile = layer.tile(Vec2::zero()).unwrap();
+    assert!(tile.texels().iter().all(|texel| texel[3] == 0));
+}
+
+#[test]
+fn tiles_are_only_made_again_when_the_top_changes() {
+    let layer = MapLayer::new();
+    layer.update(Vec3::new(1, 2, 0), &floor(4));
+    assert!(layer.take_changed().contains(&Vec2::new(1, 2)));
+
+    // Digging underground leaves the map as it was
+    let mut dug = floor(4);
+    dug.set_at(Vec3::new(5, 5, 1), Block::AIR);
+    layer.update(Vec3::new(1, 2, 0), &dug);
+    assert!(layer.take_changed().is_empty());
+
+    dug.set_at(Vec3::new(5, 5, 3), Block::AIR);
+    layer.update(Vec3::new(1, 2, 0), &dug);
+    assert!(layer.take_changed().contains(&Vec2::new(1, 2)));
+}
+
+#[test]
+fn the_atlas_gives_up_the_tile_drawn_longest_ago() {
+    let mut slots = Slots::new(2);
+    let a = slots.alloc(Vec2::new(0, 0)).unwrap();
+    slots.next_frame();
+    let b = slots.alloc(Vec2::new(1, 0)).unwrap();
+    // Both have been drawn this frame, so there's no room
+    slots.get(Vec2::new(0, 0));
+    assert_eq!(slots.alloc(Vec2::new(2, 0)), None);
+
+    slots.next_frame();
+    slots.get(Vec2::new(0, 0));
+    assert_eq!(slots.alloc(Vec2::new(2, 0)), Some(b));
+    assert_eq!(slots.get(Vec2::new(1, 0)), None);
+    assert_eq!(slots.get(Vec2::new(0, 0)), Some(a));
+}
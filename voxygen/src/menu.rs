@@ -0,0 +1,127 @@
+// Standard
+use std::{cell::RefCell, mem, rc::Rc};
+
+// Library
+use vek::*;
+
+// Local
+use crate::{
+    renderer::Renderer,
+    ui::{
+        element::{Button, Label, TextBox, VBox, WinBox},
+        Span, Ui,
+    },
+    window::Event,
+};
+
+pub enum MenuEvent {
+    Connect { alias: String, addr: String },
+    Singleplayer { alias: String },
+    Quit,
+}
+
+fn button(text: &str) -> Rc<Button> {
+    Button::new()
+        .with_color(Rgba::new(0.2, 0.2, 0.2, 0.8))
+        .with_hover_color(Rgba::new(0.3, 0.3, 0.5, 0.8))
+        .with_click_color(Rgba::new(0.5, 0.5, 0.8, 0.8))
+        .with_margin(Span::px(8, 8))
+        .with_label(text.to_string(), Rgba::new(1.0, 1.0, 1.0, 1.0))
+}
+
+fn text_box(text: &str) -> Rc<TextBox> {
+    TextBox::new()
+        .with_color(Rgba::new(1.0, 1.0, 1.0, 1.0))
+        .with_background_color(Rgba::new(0.0, 0.0, 0.0, 0.8))
+        .with_focus_background_color(Rgba::new(0.0, 0.0, 0.3, 0.8))
+        .with_margin(Span::px(8, 8))
+        .with_text(text.to_string())
+}
+
+fn label(text: &str) -> Rc<Label> {
+    Label::new()
+        .with_text(text.to_string())
+        .with_size(Span::px(16, 16))
+        .with_color(Rgba::new(1.0, 1.0, 1.0, 0.7))
+}
+
+/// The screen shown before connecting, where the player picks a name and a server
+pub struct MainMenu {
+    ui: Ui,
+    status_label: Rc<Label>,
+    events: Rc<RefCell<Vec<MenuEvent>>>,
+}
+
+impl MainMenu {
+    /// A menu with the fields filled in as they were last time. `error` is shown if the menu was returned to because
+    /// something went wrong.
+    pub fn new(alias: &str, addr: &str, recent_servers: &[String], error: Option<String>) -> MainMenu {
+        let events = Rc::new(RefCell::new(vec![]));
+        let vbox = VBox::new()
+            .with_color(Rgba::new(0.0, 0.0, 0.0, 0.5))
+            .with_margin(Span::px(8, 8));
+
+        vbox.push_back(label("Veloren").with_color(Rgba::new(1.0, 1.0, 1.0, 1.0)));
+        vbox.push_back(label("Name"));
+        let alias_box = vbox.push_back(text_box(alias));
+        vbox.push_back(label("Server"));
+        let addr_box = vbox.push_back(text_box(addr));
+
+        // Pressing return in the server field connects, like the button does
+        let connect = {
+            let (events, alias_box, addr_box) = (events.clone(), alias_box.clone(), addr_box.clone());
+            move || {
+                events.borrow_mut().push(MenuEvent::Connect {
+                    alias: alias_box.get_text().clone(),
+                    addr: addr_box.get_text().clone(),
+                })
+            }
+        };
+        let connect = Rc::new(connect);
+        let on_return = connect.clone();
+        addr_box.set_return_fn(move |_, _| on_return());
+        vbox.push_back(button("Connect").with_click_fn(move |_| connect()));
+
+        let (events_ref, alias_ref) = (events.clone(), alias_box.clone());
+        vbox.push_back(button("Singleplayer").with_click_fn(move |_| {
+            events_ref.borrow_mut().push(MenuEvent::Singleplayer {
+                alias: alias_ref.get_text().clone(),
+            })
+        }));
+
+        // Picking a recent server only fills in the address, so that the name can still be changed before connecting
+        if !recent_servers.is_empty() {
+            vbox.push_back(label("Recent servers"));
+        }
+        for server in recent_servers {
+            let (addr_ref, server_ref) = (addr_box.clone(), server.clone());
+            vbox.push_back(button(server).with_click_fn(move |_| addr_ref.set_text(server_ref.clone())));
+        }
+
+        let events_ref = events.clone();
+        vbox.push_back(button("Quit").with_click_fn(move |_| events_ref.borrow_mut().push(MenuEvent::Quit)));
+        let status_label = vbox.push_back(label(&error.unwrap_or_default()).with_color(Rgba::new(1.0, 0.4, 0.4, 1.0)));
+
+        let rows = 9 + recent_servers.len() as i32 + if recent_servers.is_empty() { 0 } else { 1 };
+        let winbox = WinBox::new();
+        winbox.add_child_at(Span::center(), Span::center(), Span::px(400, rows * 36), vbox);
+
+        MainMenu {
+            ui: Ui::new(winbox),
+            status_label,
+            events,
+        }
+    }
+
+    /// Show a message under the buttons, such as why the last connection attempt failed
+    pub fn set_status(&self, text: String) { self.status_label.set_text(text); }
+
+    pub fn get_events(&self) -> Vec<MenuEvent> {
+        let mut events = vec![];
+        mem::swap(&mut *self.events.borrow_mut(), &mut events);
+        events
+    }
+
+    pub fn render(&mut self, renderer: &mut Renderer) { self.ui.render(renderer); }
+    pub fn handle_event(&self, event: &Event, renderer: &mut Renderer) -> bool { self.ui.handle_event(event, renderer) }
+}
@@ -0,0 +1,121 @@
+// Standard
+use std::f32::consts::PI;
+
+gfx_defines! {
+    vertex Vertex {
+        pos: [f32; 3] = "vert_pos",
+    }
+}
+
+pub struct Mesh {
+    verts: Vec<Vertex>,
+}
+
+impl Mesh {
+    pub fn new() -> Mesh { Mesh { verts: Vec::new() } }
+
+    /// The 12 edges of a unit cube as a line list, grown by `inflate` on every side so they don't sit exactly on the
+    /// block's faces
+    pub fn new_cube(inflate: f32) -> Mesh {
+        let (lo, hi) = (-inflate, 1.0 + inflate);
+        let corner = |i: usize| {
+            [
+                if i & 1 == 0 { lo } else { hi },
+                if i & 2 == 0 { lo } else { hi },
+                if i & 4 == 0 { lo } else { hi },
+            ]
+        };
+
+        let mut mesh = Mesh::new();
+        for i in 0..8 {
+            // Join each corner to the corners that differ from it along one axis, counting each edge once
+            for axis in &[1, 2, 4] {
+                if i & axis == 0 {
+                    mesh.add_line(corner(i), corner(i | axis));
+                }
+            }
+        }
+        mesh
+    }
+
+    /// The four sides of a unit cube, without its top and bottom, as a triangle list facing into the cube
+    pub fn new_walls() -> Mesh {
+        // Each side starts at a corner and spans two edges, in the order that makes it face inwards
+        let sides = [
+            ([0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+            ([1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ];
+        let add = |a: [f32; 3], b: [f32; 3]| [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+
+        let mut mesh = Mesh::new();
+        for (o, u, v) in sides.iter() {
+            mesh.add_triangle(*o, add(*o, *u), add(add(*o, *u), *v));
+            mesh.add_triangle(*o, add(add(*o, *u), *v), add(*o, *v));
+        }
+        mesh
+    }
+
+    /// Cracks spreading out from the middle of each face of a unit cube as a line list, grown by `inflate` like
+    /// `new_cube`. There are `stages` stages of cracking, each reaching further across the faces than the last, and
+    /// every stage's cracks carry on from the ones before it.
+    pub fn new_cracks(stage: u32, stages: u32, inflate: f32) -> Mesh {
+        const BRANCHES: u32 = 3;
+        // How far each stage's cracks wander, in radians either way
+        const WANDER: f32 = 0.6;
+
+        let (lo, hi) = (-inflate, 1.0 + inflate);
+        let step = 0.5 / stages as f32;
+
+        let mut mesh = Mesh::new();
+        for face in 0..6 {
+            // Put a point on the face into the cube, with the face's two other axes taking `u` and `v`
+            let (axis, depth) = (face as usize / 2, if face % 2 == 0 { lo } else { hi });
+            let place = |u: f32, v: f32| {
+                let mut pos = [0.0; 3];
+                pos[axis] = depth;
+                pos[(axis + 1) % 3] = u.max(0.0).min(1.0);
+                pos[(axis + 2) % 3] = v.max(0.0).min(1.0);
+                pos
+            };
+
+            for branch in 0..BRANCHES {
+                let seed = face * BRANCHES + branch;
+                let mut angle = (branch as f32 + crack_noise(seed, 0)) / BRANCHES as f32 * 2.0 * PI;
+                let mut tip = (0.5, 0.5);
+                for s in 1..=stage.min(stages) {
+                    angle += (crack_noise(seed, s) - 0.5) * 2.0 * WANDER;
+                    let next = (tip.0 + angle.cos() * step, tip.1 + angle.sin() * step);
+                    mesh.add_line(place(tip.0, tip.1), place(next.0, next.1));
+                    tip = next;
+                }
+            }
+        }
+        mesh
+    }
+
+    pub fn vert_count(&self) -> u32 { self.verts.len() as u32 }
+
+    pub fn vertices(&self) -> &Vec<Vertex> { &self.verts }
+
+    pub fn add_line(&mut self, p0: [f32; 3], p1: [f32; 3]) {
+        self.verts.push(Vertex { pos: p0 });
+        self.verts.push(Vertex { pos: p1 });
+    }
+
+    pub fn add_triangle(&mut self, p0: [f32; 3], p1: [f32; 3], p2: [f32; 3]) {
+        self.verts.push(Vertex { pos: p0 });
+        self.verts.push(Vertex { pos: p1 });
+        self.verts.push(Vertex { pos: p2 });
+    }
+}
+
+// A number from 0 to 1 that's always the same for the same inputs, so that cracks look the same every time
+fn crack_noise(seed: u32, n: u32) -> f32 {
+    let mut x = seed.wrapping_mul(0x9E37_79B9) ^ n.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x2C1B_3C6D);
+    x ^= x >> 12;
+    (x & 0xFFFF) as f32 / 0xFFFF as f32
+}
@@ -0,0 +1,8 @@
+mod mesh;
+mod model;
+
+// Reexports
+pub use self::{
+    mesh::{Mesh, Vertex},
+    model::{pipeline, Model},
+};
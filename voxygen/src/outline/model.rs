@@ -0,0 +1,68 @@
+use gfx::{self, traits::FactoryExt, IndexBuffer, Slice};
+use gfx_device_gl;
+
+use crate::{
+    consts::{ConstHandle, GlobalConsts},
+    outline::{Mesh, Vertex},
+    pipeline::Pipeline,
+    renderer::{HdrDepthFormat, HdrFormat, Renderer},
+    voxel::ModelConsts,
+};
+
+type PipelineData = pipeline::Data<gfx_device_gl::Resources>;
+type VertexBuffer = gfx::handle::Buffer<gfx_device_gl::Resources, Vertex>;
+
+gfx_defines! {
+    pipeline pipeline {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        model_consts: gfx::ConstantBuffer<ModelConsts> = "model_consts",
+        global_consts: gfx::ConstantBuffer<GlobalConsts> = "global_consts",
+        out_color: gfx::BlendTarget<HdrFormat> = ("target", gfx::state::ColorMask::all(), gfx::preset::blend::ALPHA),
+        // The outline is drawn on top of the terrain, so it's tested against the depth buffer but doesn't write to it
+        out_depth: gfx::DepthTarget<HdrDepthFormat> = gfx::preset::depth::LESS_EQUAL_TEST,
+    }
+}
+
+pub struct Model {
+    vbuf: VertexBuffer,
+    vert_count: u32,
+    model_consts: ConstHandle<ModelConsts>,
+}
+
+impl Model {
+    pub fn new(renderer: &mut Renderer, mesh: &Mesh) -> Model {
+        Model {
+            vbuf: renderer.factory_mut().create_vertex_buffer(&mesh.vertices()),
+            vert_count: mesh.vert_count(),
+            model_consts: ConstHandle::new(renderer),
+        }
+    }
+
+    /// Update the outline's model constants. The vertex buffer is kept, so this is all that changes between frames.
+    pub fn update(&self, renderer: &mut Renderer, consts: ModelConsts) { self.model_consts.update(renderer, consts); }
+
+    pub fn render(
+        &self,
+        renderer: &mut Renderer,
+        pipeline: &Pipeline<pipeline::Init<'static>>,
+        global_consts: &ConstHandle<GlobalConsts>,
+    ) {
+        let pipeline_data = PipelineData {
+            vbuf: self.vbuf.clone(),
+            model_consts: self.model_consts.buffer().clone(),
+            global_consts: global_consts.buffer().clone(),
+            out_color: renderer.hdr_render_view().clone(),
+            out_depth: renderer.hdr_depth_view().clone(),
+        };
+
+        let slice = Slice::<gfx_device_gl::Resources> {
+            start: 0,
+            end: self.vert_count,
+            base_vertex: 0,
+            instances: None,
+            buffer: IndexBuffer::Auto,
+        };
+
+        renderer.encoder_mut().draw(&slice, pipeline.pso(), &pipeline_data);
+    }
+}
@@ -1,3 +1,5 @@
+use std::{collections::HashSet, path::PathBuf};
+
 use gfx::{
     handle::Program,
     pso::{PipelineInit, PipelineState},
@@ -9,36 +11,122 @@ use gfx_device_gl;
 
 use crate::shader::Shader;
 
-pub struct Pipeline<P: PipelineInit> {
+pub struct Pipeline<P: PipelineInit + Clone> {
     #[allow(dead_code)]
     program: Program<gfx_device_gl::Resources>,
     pso: PipelineState<gfx_device_gl::Resources, P::Meta>,
+
+    // Kept so the pipeline can be rebuilt when its shaders change
+    pipe: P,
+    primitive: Primitive,
+    shader_paths: Option<(PathBuf, PathBuf)>,
+    sources: Vec<PathBuf>,
+}
+
+// The files both shaders were built from, without duplicates
+fn sources(vs: &Shader, ps: &Shader) -> Vec<PathBuf> {
+    let mut sources = vs.sources().to_vec();
+    for source in ps.sources() {
+        if !sources.contains(source) {
+            sources.push(source.clone());
+        }
+    }
+    sources
 }
 
-impl<P: PipelineInit> Pipeline<P> {
+impl<P: PipelineInit + Clone> Pipeline<P> {
     pub fn new(factory: &mut gfx_device_gl::Factory, pipe: P, vs: &Shader, ps: &Shader) -> Pipeline<P> {
+        Self::with_primitive(factory, pipe, vs, ps, Primitive::TriangleList)
+    }
+
+    pub fn with_primitive(
+        factory: &mut gfx_device_gl::Factory,
+        pipe: P,
+        vs: &Shader,
+        ps: &Shader,
+        primitive: Primitive,
+    ) -> Pipeline<P> {
+        let (program, pso) = Self::build(factory, pipe.clone(), vs, ps, primitive).unwrap_or_else(|e| panic!("{}", e));
+
+        Pipeline::<P> {
+            program,
+            pso,
+            pipe,
+            primitive,
+            // Pipelines built from shader source in code have nothing to reload
+            shader_paths: match (vs.sources().first(), ps.sources().first()) {
+                (Some(vs_path), Some(ps_path)) => Some((vs_path.clone(), ps_path.clone())),
+                _ => None,
+            },
+            sources: sources(vs, ps),
+        }
+    }
+
+    fn build(
+        factory: &mut gfx_device_gl::Factory,
+        pipe: P,
+        vs: &Shader,
+        ps: &Shader,
+        primitive: Primitive,
+    ) -> Result<(Program<gfx_device_gl::Resources>, PipelineState<gfx_device_gl::Resources, P::Meta>), String> {
         let program = factory
             .link_program(vs.bytes(), ps.bytes())
-            .expect("Failed to compile shader program");
-        Pipeline::<P> {
-            pso: factory
-                .create_pipeline_from_program(
-                    &program,
-                    Primitive::TriangleList,
-                    Rasterizer {
-                        front_face: FrontFace::CounterClockwise,
-                        cull_face: CullFace::Back,
-                        method: RasterMethod::Fill,
-                        offset: None,
-                        samples: Some(MultiSample),
+            .map_err(|e| format!("Failed to compile shader program: {}", e))?;
+        let pso = factory
+            .create_pipeline_from_program(
+                &program,
+                primitive,
+                Rasterizer {
+                    front_face: FrontFace::CounterClockwise,
+                    cull_face: match primitive {
+                        Primitive::TriangleList => CullFace::Back,
+                        _ => CullFace::Nothing,
                     },
-                    //Rasterizer::new_fill().with_cull_back(),
-                    pipe,
-                )
-                .expect("Failed to create rendering pipeline"),
-            program,
+                    method: RasterMethod::Fill,
+                    offset: None,
+                    samples: Some(MultiSample),
+                },
+                //Rasterizer::new_fill().with_cull_back(),
+                pipe,
+            )
+            .map_err(|e| format!("Failed to create rendering pipeline: {}", e))?;
+        Ok((program, pso))
+    }
+
+    /// Rebuild the pipeline if any of its shader files are in `changed`. If the new shaders fail to compile, the
+    /// error is logged and the old pipeline is kept.
+    pub fn reload_if_changed(&mut self, factory: &mut gfx_device_gl::Factory, changed: &HashSet<PathBuf>) {
+        let (vs_path, ps_path) = match self.shader_paths {
+            Some(ref paths) if self.sources.iter().any(|source| changed.contains(source)) => paths.clone(),
+            _ => return,
+        };
+
+        let result = Shader::from_file(&vs_path)
+            .and_then(|vs| Shader::from_file(&ps_path).map(|ps| (vs, ps)))
+            .map_err(|e| format!("Failed to load shader: {}", e))
+            .and_then(|(vs, ps)| {
+                Self::build(factory, self.pipe.clone(), &vs, &ps, self.primitive).map(|built| (built, vs, ps))
+            });
+
+        match result {
+            Ok(((program, pso), vs, ps)) => {
+                info!("reloaded shaders {} and {}", vs_path.display(), ps_path.display());
+                self.program = program;
+                self.pso = pso;
+                // The shaders may include different files now
+                self.sources = sources(&vs, &ps);
+            },
+            Err(e) => error!(
+                "keeping old pipeline for {} and {}: {}",
+                vs_path.display(),
+                ps_path.display(),
+                e
+            ),
         }
     }
 
+    /// The shader files the pipeline was built from
+    pub fn sources(&self) -> &[PathBuf] { &self.sources }
+
     pub fn pso(&self) -> &PipelineState<gfx_device_gl::Resources, P::Meta> { &self.pso }
 }
@@ -1,3 +1,12 @@
+// Modules
+mod pass;
+
+// Reexports
+pub use self::pass::{DrawItem, Pass, PassQueue, Passes, Target};
+
+// Standard
+use std::{io, path::PathBuf};
+
 // Library
 use gfx::{
     self,
@@ -6,9 +15,18 @@ use gfx::{
     texture::{FilterMethod, SamplerInfo, WrapMode},
     Device, Encoder, Factory,
 };
-use gfx_device_gl;
+use gfx_device_gl::{self, gl};
 use vek::*;
 
+// Project
+use common::terrain::VolOffs;
+
+// Local
+use crate::{
+    map::{Tile, TileAtlas},
+    screenshot::{self, Screenshot},
+};
+
 pub type HdrFormat = (gfx::format::R16_G16_B16_A16, gfx::format::Float);
 pub type ColorFormat = gfx::format::Srgba8;
 pub type DepthFormat = gfx::format::DepthStencil;
@@ -35,6 +53,8 @@ pub struct Renderer {
     hdr_render_view: HdrRenderView,
     hdr_depth_view: HdrDepthView,
     hdr_sampler: Sampler<gfx_device_gl::Resources>,
+    map_atlas: TileAtlas,
+    passes: Passes,
     factory: gfx_device_gl::Factory,
     encoder: Encoder<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer>,
 }
@@ -57,6 +77,8 @@ impl Renderer {
             hdr_render_view,
             hdr_depth_view,
             hdr_sampler,
+            map_atlas: TileAtlas::new(&mut factory),
+            passes: Passes::new(),
             encoder: factory.create_command_buffer().into(),
             factory,
         }
@@ -88,20 +110,69 @@ impl Renderer {
         (hdr_shader_view, hdr_render_view, hdr_depth_view, hdr_sampler)
     }
 
+    /// Start drawing a frame. Depth is cleared by the passes that need it, in `draw_passes`.
     pub fn begin_frame(&mut self, clear_color: Option<Vec3<f32>>) {
         if let Some(color) = clear_color {
             self.encoder.clear(&self.color_view, [color.x, color.y, color.z, 1.0]);
             self.encoder
                 .clear(&self.hdr_render_view, [color.x, color.y, color.z, 1.0]);
         }
-        self.encoder.clear_depth(&self.hdr_depth_view, 1.0);
     }
 
+    /// Draw everything in `queue` a pass at a time, in pass order, leaving out what's in disabled passes. Items look up
+    /// the views they draw to as they're drawn, so they always draw to the views as they are since the last resize.
+    pub fn draw_passes(&mut self, queue: PassQueue<DrawItem>) {
+        let mut items = queue.into_sorted(&self.passes);
+        for &pass in Pass::ALL.iter() {
+            if pass.clears_depth() {
+                match pass.target() {
+                    Target::Hdr => self.encoder.clear_depth(&self.hdr_depth_view, 1.0),
+                    Target::Screen => self.encoder.clear_depth(&self.depth_view, 1.0),
+                }
+            }
+            for (_, draw) in items.iter_mut().filter(|(p, _)| *p == pass) {
+                draw(self);
+            }
+        }
+    }
+
+    pub fn passes_mut(&mut self) -> &mut Passes { &mut self.passes }
+
     pub fn end_frame(&mut self) {
         self.encoder.flush(&mut self.device);
         self.device.cleanup();
     }
 
+    /// Save the frame that was just rendered as a PNG. Call this after `end_frame`, before anything else is drawn.
+    /// Only reading the frame back from the GPU happens here; it's encoded and written out on a worker thread, which
+    /// passes where it was saved to `on_saved`.
+    pub fn capture_frame<F: FnOnce(io::Result<PathBuf>) + Send + 'static>(&mut self, on_saved: F) {
+        const ALIGNMENT: usize = 4;
+
+        let (width, height, ..) = self.color_view.get_dimensions();
+        let (width, height) = (width as u32, height as u32);
+        let stride = screenshot::row_stride(width, ALIGNMENT);
+        let mut data = vec![0u8; stride * height as usize];
+
+        unsafe {
+            self.device.with_gl(|gl| {
+                gl.BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+                gl.PixelStorei(gl::PACK_ALIGNMENT, ALIGNMENT as i32);
+                gl.ReadPixels(
+                    0,
+                    0,
+                    width as i32,
+                    height as i32,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    data.as_mut_ptr() as *mut _,
+                );
+            });
+        }
+
+        Screenshot::from_gl_rows(width, height, stride, &data).save(on_saved);
+    }
+
     #[allow(dead_code)]
     pub fn encoder(&self) -> &Encoder<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer> { &self.encoder }
     #[allow(dead_code)]
@@ -124,6 +195,14 @@ impl Renderer {
     pub fn hdr_depth_view(&self) -> &HdrDepthView { &self.hdr_depth_view }
     pub fn hdr_sampler(&self) -> &Sampler<gfx_device_gl::Resources> { &self.hdr_sampler }
 
+    pub fn map_atlas(&self) -> &TileAtlas { &self.map_atlas }
+    pub fn map_atlas_mut(&mut self) -> &mut TileAtlas { &mut self.map_atlas }
+
+    /// Send the minimap's tile for `column` to the GPU, returning where it is in the atlas if there was room for it
+    pub fn upload_map_tile(&mut self, column: Vec2<VolOffs>, tile: &Tile) -> Option<Vec2<f32>> {
+        self.map_atlas.upload(&mut self.encoder, column, tile)
+    }
+
     pub fn get_view_resolution(&self) -> Vec2<u16> {
         Vec2::new(self.color_view.get_dimensions().0, self.color_view.get_dimensions().1)
     }
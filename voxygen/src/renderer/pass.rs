@@ -0,0 +1,108 @@
+// Local
+use super::Renderer;
+
+/// Something to draw in a pass, given the renderer once the pass comes around
+pub type DrawItem<'a> = Box<dyn FnMut(&mut Renderer) + 'a>;
+
+/// The passes a frame is drawn in. Passes are drawn in the order they're declared in, whatever order what's drawn in
+/// them was submitted in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Pass {
+    /// The sky, behind everything else
+    Sky,
+    /// Terrain and entities, along with the water and glass in them
+    Opaque,
+    /// Blended over what's already there, like shadows and outlines
+    Transparent,
+    /// Rain and snow
+    Particles,
+    /// The world, tonemapped from HDR onto the screen
+    Post,
+    /// The HUD
+    Ui,
+    /// Debug overlays, over everything else
+    Debug,
+}
+
+/// What a pass draws to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Target {
+    /// The HDR view the world is drawn to before it's tonemapped
+    Hdr,
+    /// The window
+    Screen,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 7] = [
+        Pass::Sky,
+        Pass::Opaque,
+        Pass::Transparent,
+        Pass::Particles,
+        Pass::Post,
+        Pass::Ui,
+        Pass::Debug,
+    ];
+
+    pub fn target(self) -> Target {
+        match self {
+            Pass::Sky | Pass::Opaque | Pass::Transparent | Pass::Particles => Target::Hdr,
+            Pass::Post | Pass::Ui | Pass::Debug => Target::Screen,
+        }
+    }
+
+    /// Whether the depth of the pass's target is cleared before it's drawn, so that nothing drawn before it hides it
+    pub fn clears_depth(self) -> bool {
+        match self {
+            Pass::Sky | Pass::Debug => true,
+            _ => false,
+        }
+    }
+}
+
+/// Which passes are drawn. Items submitted to a disabled pass are dropped, but the pass still clears what it clears so
+/// that the passes after it draw the same either way.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Passes {
+    enabled: [bool; 7],
+}
+
+impl Passes {
+    /// Every pass enabled
+    pub fn new() -> Passes { Passes { enabled: [true; 7] } }
+
+    pub fn is_enabled(&self, pass: Pass) -> bool { self.enabled[pass as usize] }
+
+    pub fn set_enabled(&mut self, pass: Pass, enabled: bool) { self.enabled[pass as usize] = enabled; }
+
+    /// Enable the pass if it's disabled and disable it if it isn't, returning whether it's now enabled
+    pub fn toggle(&mut self, pass: Pass) -> bool {
+        let enabled = !self.is_enabled(pass);
+        self.set_enabled(pass, enabled);
+        enabled
+    }
+}
+
+/// What's to be drawn this frame, tagged with the pass it's drawn in
+pub struct PassQueue<T> {
+    items: Vec<(Pass, T)>,
+}
+
+impl<T> PassQueue<T> {
+    pub fn new() -> PassQueue<T> { PassQueue { items: vec![] } }
+
+    pub fn submit(&mut self, pass: Pass, item: T) { self.items.push((pass, item)); }
+
+    /// The items in the order they're to be drawn: by pass, and in the order they were submitted within a pass. Items
+    /// in passes that aren't enabled are dropped.
+    pub fn into_sorted(self, passes: &Passes) -> Vec<(Pass, T)> {
+        let mut items = self
+            .items
+            .into_iter()
+            .filter(|(pass, _)| passes.is_enabled(*pass))
+            .collect::<Vec<_>>();
+        // Stable, so that items in the same pass stay in order
+        items.sort_by_key(|(pass, _)| *pass);
+        items
+    }
+}
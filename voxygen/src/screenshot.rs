@@ -0,0 +1,71 @@
+// Standard
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::PathBuf,
+    thread,
+};
+
+// Library
+use chrono::Local;
+use png::{self, HasParameters};
+
+// Constants
+const SCREENSHOT_DIR: &str = "screenshots";
+pub const BYTES_PER_PIXEL: usize = 4;
+
+/// A frame read back from the GPU, as tightly packed RGBA rows from the top of the image down
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    /// Build a screenshot from rows read back by OpenGL, which start at the bottom of the image and are each padded
+    /// out to `stride` bytes
+    pub fn from_gl_rows(width: u32, height: u32, stride: usize, data: &[u8]) -> Screenshot {
+        let row_len = width as usize * BYTES_PER_PIXEL;
+        let mut pixels = Vec::with_capacity(row_len * height as usize);
+        for row in (0..height as usize).rev() {
+            pixels.extend_from_slice(&data[row * stride..row * stride + row_len]);
+        }
+
+        Screenshot { width, height, pixels }
+    }
+
+    /// Encode and save the screenshot on a worker thread, then pass where it was saved to `on_saved`
+    pub fn save<F: FnOnce(io::Result<PathBuf>) + Send + 'static>(self, on_saved: F) {
+        thread::spawn(move || on_saved(self.write_to_dir()));
+    }
+
+    fn write_to_dir(&self) -> io::Result<PathBuf> {
+        let dir = dir();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(file_name());
+
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(&path)?), self.width, self.height);
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        Ok(path)
+    }
+}
+
+/// The number of bytes OpenGL uses for each row of pixels when rows are aligned to `alignment` bytes
+pub fn row_stride(width: u32, alignment: usize) -> usize {
+    let row_len = width as usize * BYTES_PER_PIXEL;
+    (row_len + alignment - 1) / alignment * alignment
+}
+
+pub fn dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("veloren").join("voxygen"))
+        .unwrap_or(PathBuf::new())
+        .join(SCREENSHOT_DIR)
+}
+
+// Timestamped down to the millisecond, so that screenshots taken in quick succession don't overwrite each other
+fn file_name() -> String { format!("screenshot_{}.png", Local::now().format("%Y-%m-%d_%H-%M-%S-%3f")) }
@@ -0,0 +1,270 @@
+// Standard
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+// Library
+use serde_derive::{Deserialize, Serialize};
+use toml::{self, Value};
+
+// Project
+use common::net::Encryption;
+
+// Constants
+const SETTINGS_FILE: &str = "settings.toml";
+const CURRENT_VERSION: u32 = 3;
+// Settings that are left out of the file while they're unset, so have no default to be merged into
+const OPTIONAL_SETTINGS: &[&str] = &["graphics.window_pos"];
+// How many servers the main menu remembers
+const MAX_RECENT_SERVERS: usize = 5;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    TomlDe(toml::de::Error),
+    TomlSer(toml::ser::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error { Error::Io(err) }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Error { Error::TomlDe(err) }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(err: toml::ser::Error) -> Error { Error::TomlSer(err) }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::TomlDe(e) => write!(f, "{}", e),
+            Error::TomlSer(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub version: u32,
+    pub graphics: Graphics,
+    pub audio: Audio,
+    pub controls: Controls,
+    pub network: Network,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Graphics {
+    pub view_distance: i64,
+    /// The vertical field of view, in degrees
+    pub fov: f32,
+    /// Widen the field of view a little while sprinting
+    pub sprint_fov: bool,
+    pub vsync: bool,
+    pub fullscreen: bool,
+    /// The size of the window when it isn't fullscreen, in logical pixels
+    pub window_size: [u32; 2],
+    /// Where the window was when the game was last closed. Left to the window manager if unset.
+    pub window_pos: Option<[i32; 2]>,
+    /// Fade out terrain at the edge of the view distance instead of cutting it off
+    pub fog: bool,
+    /// How much GPU memory terrain models may take up, in megabytes. Terrain that hasn't been in view for the longest
+    /// is dropped past this, and rebuilt once it's back in view.
+    pub chunk_memory: u32,
+    /// Turn the minimap with the camera, so that the way the camera faces is up, instead of keeping north up
+    pub minimap_rotates: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Audio {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub ambience_volume: f32,
+    pub muted: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Controls {
+    pub mouse_sensitivity_x: f32,
+    pub mouse_sensitivity_y: f32,
+    pub invert_mouse_y: bool,
+    pub zoom_speed: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Network {
+    /// The name last played under. A random one is picked if empty.
+    pub alias: String,
+    /// Servers connected to from the main menu, most recent first
+    pub recent_servers: Vec<String>,
+    /// Whether to encrypt connections to servers: "off", "preferred" or "required"
+    pub encryption: Encryption,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            version: CURRENT_VERSION,
+            graphics: Graphics {
+                view_distance: 80,
+                fov: 75.0,
+                sprint_fov: true,
+                vsync: true,
+                fullscreen: false,
+                window_size: [800, 500],
+                window_pos: None,
+                fog: true,
+                chunk_memory: 512,
+                minimap_rotates: false,
+            },
+            audio: Audio {
+                master_volume: 1.0,
+                music_volume: 1.0,
+                sfx_volume: 1.0,
+                ambience_volume: 1.0,
+                muted: false,
+            },
+            controls: Controls {
+                mouse_sensitivity_x: 0.002,
+                mouse_sensitivity_y: 0.002,
+                invert_mouse_y: false,
+                zoom_speed: 0.25,
+            },
+            network: Network {
+                alias: String::new(),
+                recent_servers: vec![],
+                encryption: Encryption::Preferred,
+            },
+        }
+    }
+}
+
+impl Settings {
+    /// Load the settings file, falling back to defaults for anything that's missing or invalid
+    pub fn load() -> Settings {
+        let path = Settings::path();
+        let settings = match Settings::load_from(&path) {
+            Ok(settings) => settings,
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => {
+                warn!("failed to load {}: {}, using default settings", path.display(), e);
+                Settings::default()
+            },
+        };
+
+        if let Err(e) = settings.save() {
+            warn!("failed to save {}: {}", path.display(), e);
+        }
+        settings
+    }
+
+    pub(crate) fn load_from(path: &PathBuf) -> Result<Settings, Error> {
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        let mut user = toml::from_str::<Value>(&content)?;
+        migrate(&mut user);
+
+        let mut merged = Value::try_from(Settings::default())?;
+        merge(&mut merged, user, "");
+
+        let mut settings = merged.try_into::<Settings>()?;
+        settings.version = CURRENT_VERSION;
+        Ok(settings)
+    }
+
+    /// Move `addr` to the top of the recent servers, forgetting the oldest if there are too many
+    pub fn add_recent_server(&mut self, addr: &str) {
+        let servers = &mut self.network.recent_servers;
+        servers.retain(|s| s != addr);
+        servers.insert(0, addr.to_string());
+        servers.truncate(MAX_RECENT_SERVERS);
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Settings::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = File::create(path)?;
+        file.write_all(toml::to_string(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn path() -> PathBuf {
+        dirs::config_dir()
+            .map(|dir| dir.join("veloren").join("voxygen"))
+            .unwrap_or(PathBuf::new())
+            .join(SETTINGS_FILE)
+    }
+}
+
+// Upgrade settings written by older versions of voxygen to the current format
+fn migrate(user: &mut Value) {
+    let version = user.get("version").and_then(|v| v.as_integer()).unwrap_or(1) as u32;
+    if version >= CURRENT_VERSION {
+        return;
+    }
+    info!("upgrading settings from version {} to {}", version, CURRENT_VERSION);
+
+    // Version 2 split mouse sensitivity into separate axes
+    if version < 2 {
+        if let Some(controls) = user.get_mut("controls").and_then(|c| c.as_table_mut()) {
+            if let Some(sensitivity) = controls.remove("mouse_sensitivity") {
+                controls.insert("mouse_sensitivity_x".to_string(), sensitivity.clone());
+                controls.insert("mouse_sensitivity_y".to_string(), sensitivity);
+            }
+        }
+    }
+
+    // Version 3 measures the field of view in degrees instead of radians
+    if version < 3 {
+        if let Some(graphics) = user.get_mut("graphics").and_then(|g| g.as_table_mut()) {
+            let radians = graphics
+                .get("fov")
+                .and_then(|fov| fov.as_float().or_else(|| fov.as_integer().map(|fov| fov as f64)));
+            if let Some(radians) = radians {
+                graphics.insert("fov".to_string(), Value::Float(radians.to_degrees()));
+            }
+        }
+    }
+}
+
+// Overwrite values in `default` with those from `user`, keeping the default for any field that has the wrong type
+fn merge(default: &mut Value, user: Value, path: &str) {
+    match (default, user) {
+        (Value::Table(default), Value::Table(user)) => {
+            for (key, val) in user {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+
+                match default.get_mut(&key) {
+                    Some(default) => merge(default, val, &path),
+                    None if OPTIONAL_SETTINGS.contains(&path.as_str()) => {
+                        default.insert(key, val);
+                    },
+                    None => warn!("ignoring unknown setting '{}'", path),
+                }
+            }
+        },
+        (Value::Float(default), Value::Integer(user)) => *default = user as f64,
+        (default, user) => {
+            if default.same_type(&user) {
+                *default = user;
+            } else {
+                warn!("setting '{}' has an invalid value ({}), using the default", path, user);
+            }
+        },
+    }
+}
@@ -1,61 +1,95 @@
 use crate::get_shader_path;
 use glsl_include;
-use std::{env, fs, io};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+// Files in the util directory that shaders can include
+const UTIL_FILES: [&str; 5] = ["common.glsl", "noise.glsl", "sky.glsl", "bsdf.glsl", "luts.glsl"];
 
 pub struct Shader {
     data: Vec<u8>,
+    sources: Vec<PathBuf>,
+}
+
+// Names of the files pulled in by `#include` directives
+fn includes(code: &str) -> Vec<String> {
+    code.lines()
+        .map(|line| line.trim())
+        .filter(|line| line.starts_with("#include"))
+        .map(|line| {
+            line["#include".len()..]
+                .trim()
+                .trim_matches(|c| c == '<' || c == '>' || c == '"')
+                .to_string()
+        })
+        .collect()
 }
 
 impl Shader {
-    pub(crate) fn expand<F>(filename: F) -> Result<String, io::Error>
+    /// Expand a shader's includes. Also returns the files the shader was built from: the shader itself followed by
+    /// every util file it pulled in, directly or through another util file.
+    pub(crate) fn expand<F>(filename: F) -> Result<(String, Vec<PathBuf>), io::Error>
     where
-        F: std::convert::AsRef<std::path::Path>,
+        F: AsRef<Path>,
     {
         // Utility files
-        let common = fs::read_to_string(get_shader_path("util/common.glsl"))?;
-        let noise = fs::read_to_string(get_shader_path("util/noise.glsl"))?;
-        let sky = fs::read_to_string(get_shader_path("util/sky.glsl"))?;
-        let bsdf = fs::read_to_string(get_shader_path("util/bsdf.glsl"))?;
-        let luts = fs::read_to_string(get_shader_path("util/luts.glsl"))?;
-
-        let shader_code = fs::read_to_string(filename)?;
-        let (expanded_code, _) = glsl_include::Context::new()
-            .include("common.glsl", &common)
-            .include("noise.glsl", &noise)
-            .include("sky.glsl", &sky)
-            .include("bsdf.glsl", &bsdf)
-            .include("luts.glsl", &luts)
+        let mut utils = Vec::with_capacity(UTIL_FILES.len());
+        for name in UTIL_FILES.iter() {
+            utils.push((*name, fs::read_to_string(get_shader_path("util").join(name))?));
+        }
+
+        let shader_code = fs::read_to_string(&filename)?;
+
+        let mut context = glsl_include::Context::new();
+        for (name, code) in &utils {
+            context.include(*name, code);
+        }
+        let (expanded_code, _) = context
             .expand_to_string(&shader_code)
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
 
-        Ok(expanded_code)
+        let mut sources = vec![filename.as_ref().to_path_buf()];
+        let mut pending = includes(&shader_code);
+        while let Some(name) = pending.pop() {
+            let path = get_shader_path("util").join(&name);
+            if !sources.contains(&path) {
+                if let Some((_, code)) = utils.iter().find(|(util, _)| *util == name) {
+                    pending.extend(includes(code));
+                }
+                sources.push(path);
+            }
+        }
+
+        Ok((expanded_code, sources))
     }
 
     pub fn from_file<F>(filename: F) -> Result<Shader, io::Error>
     where
-        F: std::convert::AsRef<std::path::Path>,
+        F: AsRef<Path>,
     {
-        let expanded_code = Shader::expand(filename)?;
+        let (expanded_code, sources) = Shader::expand(filename)?;
 
-        match env::var("VOXYGEN_DEBUG_SHADERS") {
-            Ok(val) => {
-                if val.parse::<i32>().unwrap() == 1 {
-                    println!("{}", &expanded_code);
-                }
-            },
-            _ => {},
-        };
+        if env::var("VOXYGEN_DEBUG_SHADERS").map(|val| val == "1").unwrap_or(false) {
+            println!("{}", &expanded_code);
+        }
 
         Ok(Shader {
             data: expanded_code.into_bytes(),
+            sources,
         })
     }
 
     pub fn from_str(code: &str) -> Shader {
         Shader {
             data: code.as_bytes().to_vec(),
+            sources: Vec::new(),
         }
     }
 
     pub fn bytes(&self) -> &[u8] { &self.data }
+
+    /// The files this shader was built from, empty if it wasn't loaded from a file
+    pub fn sources(&self) -> &[PathBuf] { &self.sources }
 }
@@ -0,0 +1,82 @@
+// Standard
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+// Library
+use parking_lot::Mutex;
+
+// Constants
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Shared {
+    // Last seen modification time of each watched file
+    files: Mutex<HashMap<PathBuf, Option<SystemTime>>>,
+    changed: Mutex<HashSet<PathBuf>>,
+    running: AtomicBool,
+}
+
+/// Polls shader files for changes on a background thread so pipelines can be rebuilt without restarting
+pub struct ShaderWatcher {
+    shared: Arc<Shared>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> { fs::metadata(path).and_then(|m| m.modified()).ok() }
+
+impl ShaderWatcher {
+    /// Shaders are watched in debug builds, or when `VOXYGEN_DEBUG_SHADERS` is set
+    pub fn enabled() -> bool { cfg!(debug_assertions) || env::var("VOXYGEN_DEBUG_SHADERS").is_ok() }
+
+    pub fn new() -> Self {
+        let shared = Arc::new(Shared {
+            files: Mutex::new(HashMap::new()),
+            changed: Mutex::new(HashSet::new()),
+            running: AtomicBool::new(true),
+        });
+
+        let shared_ref = shared.clone();
+        thread::spawn(move || Self::poll(shared_ref));
+
+        Self { shared }
+    }
+
+    fn poll(shared: Arc<Shared>) {
+        while shared.running.load(Ordering::Relaxed) {
+            thread::sleep(POLL_INTERVAL);
+
+            let mut files = shared.files.lock();
+            for (path, last_modified) in files.iter_mut() {
+                let now_modified = modified(path);
+                if now_modified != *last_modified {
+                    *last_modified = now_modified;
+                    shared.changed.lock().insert(path.clone());
+                }
+            }
+        }
+    }
+
+    /// Start watching the given files. Files that are already watched are left alone.
+    pub fn watch(&self, paths: &[PathBuf]) {
+        let mut files = self.shared.files.lock();
+        for path in paths {
+            if !files.contains_key(path) {
+                files.insert(path.clone(), modified(path));
+            }
+        }
+    }
+
+    /// Take the files that have changed since the last call
+    pub fn changed(&self) -> HashSet<PathBuf> { self.shared.changed.lock().drain().collect() }
+}
+
+impl Drop for ShaderWatcher {
+    fn drop(&mut self) { self.shared.running.store(false, Ordering::Relaxed); }
+}
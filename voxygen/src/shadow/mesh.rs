@@ -0,0 +1,80 @@
+// Library
+use vek::*;
+
+// Project
+use common::terrain::{ray_cast, VoxAbs};
+
+// Local
+use crate::camera::RenderOrigin;
+
+// Constants
+/// Nothing further than this above the ground casts a shadow on it
+pub const MAX_DROP: f32 = 4.0;
+// How dark a shadow is under something standing on the ground
+const OPACITY: f32 = 0.5;
+// How much wider than what casts it a shadow is, so that its soft edge reaches out past the feet
+const SPREAD: f32 = 1.3;
+// How far above the ground shadows are drawn, on top of the depth bias in the shader
+const LIFT: f32 = 0.01;
+
+gfx_defines! {
+    vertex Vertex {
+        pos: [f32; 3] = "vert_pos",
+        // Where the vertex is across the shadow, from -1 to 1 either way from its middle
+        offs: [f32; 2] = "vert_offs",
+        strength: f32 = "vert_strength",
+    }
+}
+
+pub struct Mesh {
+    verts: Vec<Vertex>,
+}
+
+impl Mesh {
+    pub fn new() -> Mesh { Mesh { verts: Vec::new() } }
+
+    pub fn vertices(&self) -> &Vec<Vertex> { &self.verts }
+
+    /// Add the shadow of something `size` across whose feet are at `pos`, on the ground under it, fading the further
+    /// above the ground it is. Nothing is added if the ground is more than `MAX_DROP` below. The shadow only covers
+    /// blocks whose tops are level with the ground under the middle, so it doesn't hang out over ledges or sink into
+    /// steps.
+    pub fn add_shadow(
+        &mut self,
+        origin: &RenderOrigin,
+        pos: Vec3<f32>,
+        size: Vec2<f32>,
+        is_solid: impl Fn(Vec3<VoxAbs>) -> bool,
+    ) {
+        // From just above the feet, so that something standing on the ground finds it
+        let hit = match ray_cast(pos + Vec3::unit_z() * LIFT, -Vec3::unit_z(), MAX_DROP + LIFT, &is_solid) {
+            // Feet inside a block have nothing to cast a shadow on
+            Some(hit) if hit.normal == Vec3::unit_z() => hit,
+            _ => return,
+        };
+        let ground = (hit.pos.z + 1) as f32;
+        let strength = OPACITY * (1.0 - (pos.z - ground) / MAX_DROP).max(0.0);
+        let (center, half) = (Vec2::from(pos), size * SPREAD / 2.0);
+        let (low, high) = (center - half, center + half);
+
+        for y in low.y.floor() as VoxAbs..high.y.ceil() as VoxAbs {
+            for x in low.x.floor() as VoxAbs..high.x.ceil() as VoxAbs {
+                if !is_solid(Vec3::new(x, y, hit.pos.z)) || is_solid(Vec3::new(x, y, hit.pos.z + 1)) {
+                    continue;
+                }
+
+                // The part of the shadow over this block
+                let block = Vec2::new(x as f32, y as f32);
+                let (lo, hi) = (low.map2(block, f32::max), high.map2(block + 1.0, f32::min));
+                let corner = |p: Vec2<f32>| Vertex {
+                    pos: origin.relative(Vec3::new(p.x, p.y, ground + LIFT)).into_array(),
+                    offs: ((p - center) / half).into_array(),
+                    strength,
+                };
+                let (a, b) = (corner(lo), corner(Vec2::new(hi.x, lo.y)));
+                let (c, d) = (corner(hi), corner(Vec2::new(lo.x, hi.y)));
+                self.verts.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+    }
+}
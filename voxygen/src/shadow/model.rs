@@ -0,0 +1,68 @@
+use gfx::{self, IndexBuffer, Slice};
+use gfx_device_gl;
+
+use crate::{
+    buffer::DynamicBuffer,
+    consts::{ConstHandle, GlobalConsts},
+    pipeline::Pipeline,
+    renderer::{HdrDepthFormat, HdrFormat, Renderer},
+    shadow::{Mesh, Vertex},
+};
+
+type PipelineData = pipeline::Data<gfx_device_gl::Resources>;
+
+gfx_defines! {
+    pipeline pipeline {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        global_consts: gfx::ConstantBuffer<GlobalConsts> = "global_consts",
+        out_color: gfx::BlendTarget<HdrFormat> = ("target", gfx::state::ColorMask::all(), gfx::preset::blend::ALPHA),
+        // Shadows are hidden by the terrain in front of them, but don't hide anything themselves
+        out_depth: gfx::DepthTarget<HdrDepthFormat> = gfx::preset::depth::LESS_EQUAL_TEST,
+    }
+}
+
+/// Every shadow in the frame, drawn in one go. Shadows are already placed relative to the render origin, so there
+/// are no model constants.
+pub struct Model {
+    vbuf: DynamicBuffer<Vertex>,
+}
+
+impl Model {
+    pub fn new() -> Model {
+        Model {
+            vbuf: DynamicBuffer::new(),
+        }
+    }
+
+    /// Replace the vertices with `mesh`'s. What casts the shadows can move every frame, so this is done every frame,
+    /// over the last ones.
+    pub fn update(&mut self, renderer: &mut Renderer, mesh: &Mesh) { self.vbuf.update(renderer, mesh.vertices()); }
+
+    pub fn render(
+        &self,
+        renderer: &mut Renderer,
+        pipeline: &Pipeline<pipeline::Init<'static>>,
+        global_consts: &ConstHandle<GlobalConsts>,
+    ) {
+        let (vbuf, vert_count) = match self.vbuf.get() {
+            Some(vbuf) => vbuf,
+            None => return,
+        };
+        let pipeline_data = PipelineData {
+            vbuf: vbuf.clone(),
+            global_consts: global_consts.buffer().clone(),
+            out_color: renderer.hdr_render_view().clone(),
+            out_depth: renderer.hdr_depth_view().clone(),
+        };
+
+        let slice = Slice::<gfx_device_gl::Resources> {
+            start: 0,
+            end: vert_count,
+            base_vertex: 0,
+            instances: None,
+            buffer: IndexBuffer::Auto,
+        };
+
+        renderer.encoder_mut().draw(&slice, pipeline.pso(), &pipeline_data);
+    }
+}
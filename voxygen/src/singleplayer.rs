@@ -0,0 +1,33 @@
+// Standard
+use std::net::SocketAddr;
+
+// Project
+use server::{Error, Manager, Server, Wrapper};
+
+struct Payloads;
+impl server::Payloads for Payloads {
+    type Chunk = ();
+    type Entity = ();
+    type Client = ();
+}
+
+/// A server running inside the voxygen process for singleplayer games
+pub struct LocalServer {
+    server: Manager<Wrapper<Server<Payloads>>>,
+    addr: SocketAddr,
+}
+
+impl LocalServer {
+    pub fn start() -> Result<LocalServer, Error> {
+        // The OS picks a free port, so there's nothing to collide with
+        let server = Server::new(Payloads, "127.0.0.1:0")?;
+        let addr = server.do_for(|srv| srv.local_addr())?;
+        info!("Started singleplayer server on {}", addr);
+        Ok(LocalServer { server, addr })
+    }
+
+    pub fn addr(&self) -> SocketAddr { self.addr }
+
+    /// Stop the server. Any client connected to it should be dropped before this is called.
+    pub fn shutdown(self) { drop(self.server); }
+}
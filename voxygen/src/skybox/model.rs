@@ -16,7 +16,8 @@ gfx_defines! {
         vbuf: gfx::VertexBuffer<Vertex> = (),
         global_consts: gfx::ConstantBuffer<GlobalConsts> = "global_consts",
         out_color: gfx::RenderTarget<HdrFormat> = "target",
-        out_depth: gfx::DepthTarget<HdrDepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        // The sky is drawn first at the far plane, so it mustn't write depth or it would hide the terrain
+        out_depth: gfx::DepthTarget<HdrDepthFormat> = gfx::preset::depth::LESS_EQUAL_TEST,
     }
 }
 
@@ -12,7 +12,25 @@ mod tests {
     use chrono::Datelike;
     use tempfile;
 
-    use crate::{get_build_time, get_git_hash, get_git_time, get_profile, shader::Shader};
+    use vek::*;
+
+    use common::{ecs::phys::MoveMode, get_asset_path, net::Encryption, weather::Weather};
+
+    use crate::{
+        anim::{AnimState, Animation, Pose},
+        camera::{Camera, RenderOrigin},
+        completion::Completer,
+        figure::{self, Manifest, PartKind},
+        get_build_time, get_git_hash, get_git_time, get_profile, get_shader_path,
+        keybinds::{str_to_vkcode, vkcode_to_str},
+        renderer::{Pass, PassQueue, Passes},
+        screenshot::{self, Screenshot},
+        settings::Settings,
+        shader::Shader,
+        shadow,
+        voxel::InstanceData,
+        weather::Particles,
+    };
 
     fn visit_dirs(dir: &Path, cb: &Fn(&DirEntry)) -> io::Result<()> {
         if dir.is_dir() {
@@ -52,7 +70,7 @@ mod tests {
     }
 
     fn validate_shader(filename: &str, shader_type: &str) -> bool {
-        let expanded_shader = Shader::expand(filename).unwrap();
+        let (expanded_shader, _) = Shader::expand(filename).unwrap();
         let tmp_file = tempfile::Builder::new()
             .suffix(&format!(".{}", shader_type))
             .tempfile()
@@ -102,4 +120,580 @@ mod tests {
         })
         .unwrap();
     }
+
+    #[test]
+    fn shader_sources_include_utils() {
+        let (_, sources) = Shader::expand(get_shader_path("voxel/voxel.frag")).unwrap();
+        assert_eq!(sources[0], get_shader_path("voxel/voxel.frag"));
+        assert!(sources.contains(&get_shader_path("util").join("sky.glsl")));
+
+        let (_, sources) = Shader::expand(get_shader_path("outline/outline.frag")).unwrap();
+        assert_eq!(sources, vec![get_shader_path("outline/outline.frag")]);
+    }
+
+    #[test]
+    fn camera_pitch_is_clamped() {
+        let mut camera = Camera::new();
+        camera.set_sensitivity(Vec2::broadcast(1.0));
+
+        camera.rotate_by(Vec2::new(0.0, 100.0));
+        assert!(camera.ori().y < std::f32::consts::PI / 2.0);
+        camera.rotate_by(Vec2::new(0.0, -200.0));
+        assert!(camera.ori().y > -std::f32::consts::PI / 2.0);
+    }
+
+    #[test]
+    fn camera_sensitivity_is_linear() {
+        let mut a = Camera::new();
+        let mut b = Camera::new();
+        a.set_sensitivity(Vec2::new(0.001, 0.002));
+        b.set_sensitivity(Vec2::new(0.003, 0.006));
+
+        a.rotate_by(Vec2::new(10.0, 10.0));
+        b.rotate_by(Vec2::new(10.0, 10.0));
+        assert!((a.ori().x * 3.0 - b.ori().x).abs() < 0.0001);
+        assert!((a.ori().y * 3.0 - b.ori().y).abs() < 0.0001);
+    }
+
+    #[test]
+    fn camera_invert_y() {
+        let mut camera = Camera::new();
+        camera.set_invert_y(true);
+        camera.rotate_by(Vec2::new(0.0, 10.0));
+        assert!(camera.ori().y < 0.0);
+    }
+
+    #[test]
+    fn camera_projects_its_focus_to_the_middle_of_the_screen() {
+        let mut camera = Camera::new();
+        camera.set_focus(Vec3::new(10.0, -4.0, 2.0));
+        camera.rotate_by(Vec2::new(100.0, 50.0));
+        let mats = camera.get_mats();
+
+        let center = Camera::project(&mats, Vec3::new(10.0, -4.0, 2.0)).unwrap();
+        assert!(center.distance(Vec2::broadcast(0.5)) < 0.001);
+
+        // Anything behind the camera isn't on screen at all
+        let pos = camera.get_pos(Some(&mats));
+        assert_eq!(Camera::project(&mats, pos * 2.0 - Vec3::new(10.0, -4.0, 2.0)), None);
+    }
+
+    #[test]
+    fn frustum_edges_follow_the_fov_and_aspect_ratio() {
+        for &(fov, aspect) in &[(60.0f32, 1.0f32), (100.0, 16.0 / 9.0)] {
+            let mut camera = Camera::new();
+            camera.set_fov(fov);
+            camera.set_aspect_ratio(aspect);
+            camera.set_clip_planes(0.5, 200.0);
+            let mats = camera.get_mats();
+            // Where a point `depth` in front of the camera, and `x` and `y` of that to the side, is in the world
+            let view_to_world = mats.0.inverted();
+            let world = |x: f32, y: f32, depth: f32| {
+                Vec3::from(view_to_world * Vec4::new(x * depth, y * depth, -depth, 1.0))
+            };
+
+            // The vertical field of view is the same whatever the aspect ratio, which widens the view instead
+            let half_height = (fov.to_radians() / 2.0).tan() * 0.999;
+            let half_width = half_height * aspect;
+            for &depth in &[1.0, 50.0] {
+                let top = Camera::project(&mats, world(0.0, half_height, depth)).unwrap();
+                assert!(top.distance(Vec2::new(0.5, 0.0)) < 0.001, "{:?} at {} degrees", top, fov);
+                let right = Camera::project(&mats, world(half_width, 0.0, depth)).unwrap();
+                assert!(right.distance(Vec2::new(1.0, 0.5)) < 0.001, "{:?} at {} degrees", right, fov);
+                let corner = Camera::project(&mats, world(-half_width, -half_height, depth)).unwrap();
+                assert!(corner.distance(Vec2::new(0.0, 1.0)) < 0.001, "{:?} at {} degrees", corner, fov);
+
+                assert_eq!(Camera::project(&mats, world(0.0, half_height * 1.02, depth)), None);
+                assert_eq!(Camera::project(&mats, world(half_width * 1.02, 0.0, depth)), None);
+            }
+
+            // Depth runs from -1 at the near plane to 1 at the far one
+            let ndc_depth = |dist: f32| {
+                let clip = mats.1 * Vec4::new(0.0, 0.0, -dist, 1.0);
+                clip.z / clip.w
+            };
+            assert!((ndc_depth(0.5) + 1.0).abs() < 0.001);
+            assert!((ndc_depth(200.0) - 1.0).abs() < 0.001);
+            assert!(ndc_depth(210.0) > 1.0);
+        }
+    }
+
+    #[test]
+    fn fov_is_clamped_and_widening_eases_in() {
+        let mut camera = Camera::new();
+        camera.set_fov(10.0);
+        assert!((camera.get_fov() - 60.0f32.to_radians()).abs() < 0.0001);
+        camera.set_fov(200.0);
+        assert!((camera.get_fov() - 110.0f32.to_radians()).abs() < 0.0001);
+
+        camera.set_fov(90.0);
+        camera.set_widened(true);
+        camera.update(0.02);
+        assert!(camera.get_fov() > 90.0f32.to_radians() && camera.get_fov() < 95.0f32.to_radians());
+        for _ in 0..100 {
+            camera.update(0.05);
+        }
+        let widened = camera.get_fov();
+        assert!(widened > 95.0f32.to_radians() && widened < 100.0f32.to_radians());
+
+        // Easing goes as fast at any frame rate
+        let (mut fast, mut slow) = (Camera::new(), Camera::new());
+        fast.set_widened(true);
+        slow.set_widened(true);
+        for _ in 0..10 {
+            fast.update(0.01);
+        }
+        slow.update(0.1);
+        assert!((fast.get_fov() - slow.get_fov()).abs() < 0.0001);
+
+        camera.set_widened(false);
+        for _ in 0..100 {
+            camera.update(0.05);
+        }
+        assert!((camera.get_fov() - 90.0f32.to_radians()).abs() < 0.001);
+    }
+
+    #[test]
+    fn far_from_spawn_is_drawn_with_small_numbers() {
+        let far = Vec3::new(1_000_000.0, -1_000_000.0, 200.0);
+        let mut camera = Camera::new();
+        camera.set_focus(far);
+        camera.rotate_by(Vec2::new(100.0, 50.0));
+        let mut origin = RenderOrigin::default();
+        assert!(origin.follow(far));
+
+        let entity = far + Vec3::new(3.5, -2.25, 0.0);
+        let model_mat = Mat4::<f32>::translation_3d(origin.relative(entity));
+        let mats = camera.get_render_mats(&origin);
+        for mat in &[model_mat, mats.0] {
+            assert!(mat.into_row_array().iter().all(|e| e.abs() < 1000.0));
+        }
+        // The relative position keeps all the detail of the world one
+        assert_eq!(origin.relative(entity) - origin.relative(far), Vec3::new(3.5, -2.25, 0.0));
+        let center = Camera::project(&mats, origin.relative(far)).unwrap();
+        assert!(center.distance(Vec2::broadcast(0.5)) < 0.001);
+
+        // Detail that an f32 can't hold this far out survives in an f64
+        let precise = Vec3::new(1_000_000.3f64, -1_000_000.3, 200.3);
+        let offset = origin.relative(precise) - origin.relative(far);
+        assert!(offset.distance(Vec3::new(0.3, -0.3, 0.3)) < 0.0001, "{:?}", offset);
+        assert_ne!(precise.map(|e| e as f32).map(|e| e as f64), precise);
+
+        // It only moves once the camera has gone far enough, so chunks rarely need placing again
+        assert!(!origin.follow(far + Vec3::new(100.0, 0.0, 0.0)));
+        assert!(origin.follow(far + Vec3::new(2000.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn anim_state_follows_motion() {
+        let walking = Vec3::new(3.0, 4.0, 0.0);
+        assert_eq!(AnimState::of(Vec3::zero(), MoveMode::Sprint, true), AnimState::Idle);
+        assert_eq!(AnimState::of(walking, MoveMode::Walk, true), AnimState::Walk);
+        assert_eq!(AnimState::of(walking, MoveMode::Sprint, true), AnimState::Run);
+        assert_eq!(AnimState::of(Vec3::new(0.0, 0.0, -5.0), MoveMode::Walk, false), AnimState::Jump);
+    }
+
+    #[test]
+    fn animations_dont_depend_on_frame_rate() {
+        let (mut fast, mut slow) = (Animation::new(3), Animation::new(3));
+        fast.update(AnimState::Walk, 5.0, 1.0);
+        slow.update(AnimState::Walk, 5.0, 1.0);
+        let fast_pose = (1..=10)
+            .map(|i| fast.update(AnimState::Walk, 5.0, 1.0 + i as f32 * 0.01))
+            .last()
+            .unwrap();
+        let slow_pose = slow.update(AnimState::Walk, 5.0, 1.1);
+        assert!((fast_pose.offset - slow_pose.offset).abs() < 0.0001);
+        assert!((fast_pose.roll - slow_pose.roll).abs() < 0.0001);
+
+        // Entities walking side by side don't step in time
+        let mut other = Animation::new(4);
+        other.update(AnimState::Walk, 5.0, 1.0);
+        assert_ne!(other.update(AnimState::Walk, 5.0, 1.1), slow_pose);
+    }
+
+    const KNIGHT_MANIFEST: &str = r#"
+        [[parts]]
+        kind = "head"
+        model = "knight/head.vox"
+        pivot = [0.0, 0.0, 13.0]
+        offset = [-3.0, -3.0, 0.0]
+
+        [[parts]]
+        kind = "torso"
+        model = "knight/torso.vox"
+        pivot = [0.0, 0.0, 6.0]
+        offset = [-4.0, -2.0, 0.0]
+
+        [[parts]]
+        kind = "left_arm"
+        model = "knight/arm.vox"
+        pivot = [-5.0, 0.0, 12.0]
+        offset = [-1.0, -1.0, -6.0]
+
+        [[parts]]
+        kind = "right_arm"
+        model = "knight/arm.vox"
+        pivot = [5.0, 0.0, 12.0]
+        offset = [-1.0, -1.0, -6.0]
+
+        [[parts]]
+        kind = "left_leg"
+        model = "knight/leg.vox"
+        pivot = [-2.0, 0.0, 6.0]
+        offset = [-1.0, -1.0, -6.0]
+
+        [[parts]]
+        kind = "right_leg"
+        model = "knight/leg.vox"
+        pivot = [2.0, 0.0, 6.0]
+        offset = [-1.0, -1.0, -6.0]
+    "#;
+
+    #[test]
+    fn character_manifests_list_parts_and_pivots() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), KNIGHT_MANIFEST).unwrap();
+        let manifest = Manifest::load_from(file.path()).unwrap();
+
+        assert_eq!(manifest.parts.len(), 6);
+        let head = &manifest.parts[0];
+        assert_eq!(head.kind, PartKind::Head);
+        assert_eq!(head.model, "knight/head.vox");
+        assert_eq!(head.pivot, [0.0, 0.0, 13.0]);
+        assert_eq!(manifest.parts[3].kind, PartKind::RightArm);
+        assert_eq!(manifest.parts[3].pivot, [5.0, 0.0, 12.0]);
+
+        // A part listed twice, or no parts at all, isn't a character
+        let torso = KNIGHT_MANIFEST.find("[[parts]]\n        kind = \"torso\"").unwrap();
+        fs::write(file.path(), format!("{}{}", KNIGHT_MANIFEST, &KNIGHT_MANIFEST[torso..])).unwrap();
+        assert!(Manifest::load_from(file.path()).is_err());
+        fs::write(file.path(), "parts = []\n").unwrap();
+        assert!(Manifest::load_from(file.path()).is_err());
+    }
+
+    #[test]
+    fn the_character_manifest_and_its_parts_load() {
+        let manifest = Manifest::load_from(get_asset_path(figure::CHARACTER_MANIFEST)).unwrap();
+        let kinds = manifest.parts.iter().map(|part| part.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                PartKind::Head,
+                PartKind::Torso,
+                PartKind::LeftArm,
+                PartKind::RightArm,
+                PartKind::LeftLeg,
+                PartKind::RightLeg
+            ]
+        );
+        for part in manifest.parts.iter() {
+            let vox = dot_vox::load(get_asset_path(&part.model).to_str().unwrap()).unwrap();
+            assert!(!vox.models[0].voxels.is_empty(), "{} has nothing in it", part.model);
+        }
+    }
+
+    #[test]
+    fn limbs_swing_around_their_pivots() {
+        let pose = Pose {
+            swing: 0.5,
+            ..Pose::default()
+        };
+        let pivot = Vec3::new(2.0, 0.0, 6.0);
+        let origin = |kind| figure::part_mat(Mat4::identity(), kind, pivot, &pose) * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let foot = |kind| figure::part_mat(Mat4::identity(), kind, pivot, &pose) * Vec4::new(0.0, 0.0, -6.0, 1.0);
+
+        // The pivot stays put, and legs swing opposite ways
+        assert_eq!(Vec3::from(origin(PartKind::LeftLeg)), pivot);
+        assert!(foot(PartKind::LeftLeg).y * foot(PartKind::RightLeg).y < 0.0);
+        assert_eq!(Vec3::from(foot(PartKind::Torso)), pivot - Vec3::new(0.0, 0.0, 6.0));
+    }
+
+    #[test]
+    fn dropped_items_bob_above_where_they_lie() {
+        let pos = Vec3::new(3.0, -2.0, 10.0);
+        for step in 0..40 {
+            let mat = figure::item_mat(pos, step as f32 * 0.1, 7);
+            // The bottom of the cube never sinks into the ground or floats off, and it stays over the same spot
+            let centre = Vec3::from(mat * Vec4::new(0.0, 0.0, 0.0, 1.0));
+            let bottom = Vec3::from(mat * Vec4::new(0.0, 0.0, -0.5, 1.0));
+            assert!(bottom.z >= pos.z - 0.0001 && bottom.z <= pos.z + figure::ITEM_BOB_HEIGHT + 0.0001);
+            assert!(Vec2::from(centre).distance(Vec2::from(pos)) < 0.0001);
+        }
+    }
+
+    #[test]
+    fn instances_are_placed_like_single_models() {
+        let mat = figure::item_mat(Vec3::new(3.0, -2.0, 10.0), 1.3, 7);
+        let inst = InstanceData::new(mat, Rgba::new(1.0, 0.5, 0.5, 1.0));
+        // The instanced shader builds its matrix from columns, as the model consts are laid out
+        assert_eq!([inst.mat0, inst.mat1, inst.mat2, inst.mat3], mat.into_col_arrays());
+        assert_eq!(Vec4::from(inst.mat3), mat * Vec4::new(0.0, 0.0, 0.0, 1.0));
+        assert_eq!(inst.tint, [1.0, 0.5, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn passes_are_drawn_in_order_whatever_order_they_were_submitted_in() {
+        let mut queue = PassQueue::new();
+        queue.submit(Pass::Ui, "hud");
+        queue.submit(Pass::Opaque, "terrain");
+        queue.submit(Pass::Sky, "sky");
+        queue.submit(Pass::Debug, "debug box");
+        queue.submit(Pass::Opaque, "entities");
+        let drawn = queue.into_sorted(&Passes::new()).into_iter().map(|(_, item)| item).collect::<Vec<_>>();
+        assert_eq!(drawn, vec!["sky", "terrain", "entities", "hud", "debug box"]);
+    }
+
+    #[test]
+    fn disabled_passes_are_left_out() {
+        let mut passes = Passes::new();
+        assert!(!passes.toggle(Pass::Ui));
+        let mut queue = PassQueue::new();
+        queue.submit(Pass::Ui, "hud");
+        queue.submit(Pass::Opaque, "terrain");
+        queue.submit(Pass::Debug, "debug box");
+        assert_eq!(queue.into_sorted(&passes), vec![(Pass::Opaque, "terrain"), (Pass::Debug, "debug box")]);
+
+        assert!(passes.toggle(Pass::Ui));
+        assert!(Pass::ALL.iter().all(|pass| passes.is_enabled(*pass)));
+    }
+
+    #[test]
+    fn window_position_is_remembered() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        // Unset by default, and left out of the file
+        fs::write(&path, "[graphics]\nfullscreen = true\n").unwrap();
+        let settings = Settings::load_from(&path).unwrap();
+        assert_eq!(settings.graphics.window_pos, None);
+        assert!(settings.graphics.fullscreen);
+
+        fs::write(&path, "[graphics]\nwindow_pos = [-20, 300]\nwindow_size = [1024, 768]\n").unwrap();
+        let settings = Settings::load_from(&path).unwrap();
+        assert_eq!(settings.graphics.window_pos, Some([-20, 300]));
+        assert_eq!(settings.graphics.window_size, [1024, 768]);
+    }
+
+    #[test]
+    fn fov_is_upgraded_to_degrees() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        fs::write(&path, "version = 2\n[graphics]\nfov = 1.5\n").unwrap();
+        let settings = Settings::load_from(&path).unwrap();
+        assert!((settings.graphics.fov - 85.94).abs() < 0.01);
+
+        // Files that are already in degrees are left alone
+        fs::write(&path, "version = 3\n[graphics]\nfov = 90\n").unwrap();
+        assert_eq!(Settings::load_from(&path).unwrap().graphics.fov, 90.0);
+    }
+
+    #[test]
+    fn bad_settings_fall_back_to_defaults() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        // Values of the wrong type and settings that don't exist are dropped, the rest are kept
+        fs::write(
+            &path,
+            "[graphics]\nview_distance = \"far\"\nfog = false\nsparkles = true\n[audio]\nmuted = 1\n",
+        )
+        .unwrap();
+        let settings = Settings::load_from(&path).unwrap();
+        let default = Settings::default();
+        assert_eq!(settings.graphics.view_distance, default.graphics.view_distance);
+        assert!(!settings.graphics.fog);
+        assert_eq!(settings.audio, default.audio);
+
+        // Integers are fine where decimals are expected
+        fs::write(&path, "[controls]\nzoom_speed = 2\n").unwrap();
+        assert_eq!(Settings::load_from(&path).unwrap().controls.zoom_speed, 2.0);
+
+        // Encryption can be turned off or required, but only by name
+        fs::write(&path, "[network]\nencryption = \"required\"\n").unwrap();
+        assert_eq!(Settings::load_from(&path).unwrap().network.encryption, Encryption::Required);
+        fs::write(&path, "[network]\nencryption = \"always\"\n").unwrap();
+        assert_eq!(Settings::load_from(&path).unwrap().network.encryption, Encryption::Preferred);
+
+        // A file that isn't TOML at all can't be loaded
+        fs::write(&path, "[graphics\n").unwrap();
+        assert!(Settings::load_from(&path).is_err());
+    }
+
+    #[test]
+    fn mouse_sensitivity_is_split_into_axes() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+
+        // Files from before versions were written are version 1
+        fs::write(&path, "[controls]\nmouse_sensitivity = 0.005\n[graphics]\nfov = 1.0\n").unwrap();
+        let settings = Settings::load_from(&path).unwrap();
+        assert_eq!(settings.controls.mouse_sensitivity_x, 0.005);
+        assert_eq!(settings.controls.mouse_sensitivity_y, 0.005);
+        assert!((settings.graphics.fov - 57.30).abs() < 0.01);
+        assert_eq!(settings.version, Settings::default().version);
+    }
+
+    #[test]
+    fn recent_servers_are_most_recent_first() {
+        let mut settings = Settings::default();
+        for addr in &["a:1", "b:2", "c:3", "d:4", "e:5", "f:6"] {
+            settings.add_recent_server(addr);
+        }
+        assert_eq!(settings.network.recent_servers, vec!["f:6", "e:5", "d:4", "c:3", "b:2"]);
+
+        // Connecting again moves a server back to the top rather than listing it twice
+        settings.add_recent_server("c:3");
+        assert_eq!(settings.network.recent_servers, vec!["c:3", "f:6", "e:5", "d:4", "b:2"]);
+    }
+
+    #[test]
+    fn function_keys_can_be_bound() {
+        for name in &["F1", "F5", "F11", "F12"] {
+            let code = str_to_vkcode(name).expect("Function key can't be parsed");
+            assert_eq!(vkcode_to_str(&code), *name);
+        }
+    }
+
+    #[test]
+    fn page_keys_can_be_bound() {
+        for name in &["PageUp", "PageDown"] {
+            let code = str_to_vkcode(name).expect("Page key can't be parsed");
+            assert_eq!(vkcode_to_str(&code), *name);
+        }
+    }
+
+    #[test]
+    fn screenshot_rows_are_padded_to_the_alignment() {
+        assert_eq!(screenshot::row_stride(4, 4), 16);
+        assert_eq!(screenshot::row_stride(3, 8), 16);
+        assert_eq!(screenshot::row_stride(1, 1), 4);
+    }
+
+    #[test]
+    fn screenshots_are_flipped_and_unpadded() {
+        // Two rows of 3 pixels each, padded to 16 bytes, bottom row first
+        let mut data = vec![0xFFu8; 32];
+        data[..12].copy_from_slice(&[1; 12]);
+        data[16..28].copy_from_slice(&[2; 12]);
+
+        let shot = Screenshot::from_gl_rows(3, 2, 16, &data);
+        assert_eq!(shot.pixels.len(), 24);
+        assert!(shot.pixels[..12].iter().all(|b| *b == 2));
+        assert!(shot.pixels[12..].iter().all(|b| *b == 1));
+    }
+
+    fn completer() -> Completer {
+        let mut completer = Completer::new();
+        let words = |ws: &[&str]| ws.iter().map(|w| w.to_string()).collect();
+        completer.set_words(words(&["bob", "Alice", "albert"]), words(&["tp", "time", "help"]));
+        completer
+    }
+
+    #[test]
+    fn completion_picks_matching_words_by_kind() {
+        let mut completer = completer();
+        assert_eq!(completer.complete("hi al", 5, false), Some(("hi albert".to_string(), 9)));
+        assert_eq!(completer.candidates(), &["albert".to_string(), "Alice".to_string()]);
+
+        // Commands are only completed at the start of the line, and names never are as commands
+        completer.reset();
+        assert_eq!(completer.complete("/t", 2, false), Some(("/time".to_string(), 5)));
+        assert_eq!(completer.candidates(), &["/time".to_string(), "/tp".to_string()]);
+        completer.reset();
+        assert_eq!(completer.complete("say /t", 6, false), None);
+        assert_eq!(completer.complete("/b", 2, false), None);
+        assert!(completer.candidates().is_empty());
+    }
+
+    #[test]
+    fn completion_cycles_until_the_line_changes() {
+        let mut completer = completer();
+        let (line, cursor) = completer.complete("/tp a", 5, false).unwrap();
+        assert_eq!(line, "/tp albert");
+        let (line, cursor) = completer.complete(&line, cursor, false).unwrap();
+        assert_eq!(line, "/tp Alice");
+        let (line, cursor) = completer.complete(&line, cursor, false).unwrap();
+        assert_eq!(line, "/tp albert");
+        let (line, _) = completer.complete(&line, cursor, true).unwrap();
+        assert_eq!((line.as_str(), completer.selected()), ("/tp Alice", Some(1)));
+
+        // Editing the line starts a new completion from what's there now
+        assert_eq!(completer.complete("/tp Alice b", 11, false), Some(("/tp Alice bob".to_string(), 13)));
+        assert_eq!(completer.candidates(), &["bob".to_string()]);
+    }
+
+    #[test]
+    fn completion_mid_line_only_replaces_the_word() {
+        let mut completer = completer();
+        let (line, cursor) = completer.complete("/tp b al", 5, false).unwrap();
+        assert_eq!((line.as_str(), cursor), ("/tp bob al", 7));
+        // Backwards from the start picks the last candidate
+        completer.reset();
+        assert_eq!(completer.complete("al and", 2, true), Some(("Alice and".to_string(), 5)));
+    }
+
+    // Ground with its top at z = 0, ending in a ledge at x = 2
+    fn ledge(pos: Vec3<i64>) -> bool { pos.z < 0 && pos.x < 2 }
+
+    fn shadow_at(pos: Vec3<f32>) -> Vec<shadow::Vertex> {
+        let mut mesh = shadow::Mesh::new();
+        mesh.add_shadow(&RenderOrigin::default(), pos, Vec2::new(0.9, 0.9), ledge);
+        mesh.vertices().clone()
+    }
+
+    #[test]
+    fn shadows_stay_on_the_ground_under_them() {
+        let standing = shadow_at(Vec3::new(1.8, 0.5, 0.0));
+        assert!(!standing.is_empty());
+        // Cut off at the ledge rather than hanging out over it
+        assert!(standing.iter().all(|v| v.pos[0] <= 2.0 && (v.pos[2] - 0.01).abs() < 0.001));
+
+        // Fainter the higher up, and gone once too far up or over the edge
+        let jumping = shadow_at(Vec3::new(1.8, 0.5, 2.0));
+        assert!(jumping[0].strength < standing[0].strength);
+        assert!(shadow_at(Vec3::new(1.8, 0.5, shadow::MAX_DROP + 1.0)).is_empty());
+        assert!(shadow_at(Vec3::new(3.5, 0.5, 0.0)).is_empty());
+    }
+
+    // A roof 10 blocks up over everything west of x = 0, and open sky everywhere else
+    fn roofed_west(low: Vec2<i64>, high: Vec2<i64>) -> Vec<Option<i64>> {
+        let mut heights = vec![];
+        for _ in low.y..high.y {
+            heights.extend((low.x..high.x).map(|x| if x < 0 { Some(10) } else { None }));
+        }
+        heights
+    }
+
+    // Where each particle is, from the middle of its quad
+    fn particle_positions(particles: &Particles) -> Vec<Vec3<f32>> {
+        let mesh = particles.mesh(&RenderOrigin::default(), Vec3::unit_x(), Vec3::unit_z());
+        mesh
+            .vertices()
+            .chunks(6)
+            .map(|quad| (Vec3::from(quad[0].pos) + Vec3::from(quad[2].pos)) / 2.0)
+            .collect()
+    }
+
+    #[test]
+    fn nothing_falls_under_cover() {
+        let mut particles = Particles::new();
+        for _ in 0..200 {
+            particles.update(0.05, (Weather::Rain, 1.0), Vec3::zero(), roofed_west);
+        }
+        let positions = particle_positions(&particles);
+        assert!(positions.iter().any(|p| p.x < 0.0), "rain should still fall on the roof");
+        assert!(positions.iter().any(|p| p.x >= 0.0 && p.z < 10.0));
+        assert!(positions.iter().all(|p| p.x >= 0.0 || p.z > 11.0));
+
+        // It eases away once the weather clears, rather than stopping all at once
+        particles.update(0.05, (Weather::Clear, 0.0), Vec3::zero(), roofed_west);
+        assert!(!particle_positions(&particles).is_empty());
+        for _ in 0..400 {
+            particles.update(0.05, (Weather::Clear, 0.0), Vec3::zero(), roofed_west);
+        }
+        assert!(particle_positions(&particles).is_empty());
+        assert!(particles.gloom() < 0.01);
+    }
 }
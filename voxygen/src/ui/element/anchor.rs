@@ -0,0 +1,125 @@
+// Standard
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+// Library
+use vek::*;
+
+// Local
+use super::{collect_focusables, Bounds, Element, Event, Focusable, ResCache, Span};
+use crate::renderer::Renderer;
+
+/// Pins a child of a fixed size to a point of its bounds, such as a corner or the middle of an edge. The child's
+/// matching point sits on the anchor, pushed inwards by the margin.
+#[allow(dead_code)]
+pub struct Anchor {
+    anchor: Cell<Vec2<f32>>,
+    margin: Cell<Vec2<Span>>,
+    size: Cell<Vec2<Span>>,
+    child: RefCell<Option<Rc<dyn Element>>>,
+}
+
+impl Anchor {
+    /// `anchor` is relative to the bounds, e.g. `(0.0, 1.0)` for the bottom left corner
+    #[allow(dead_code)]
+    pub fn new(anchor: Vec2<f32>) -> Rc<Self> {
+        Rc::new(Self {
+            anchor: Cell::new(anchor),
+            margin: Cell::new(Span::zero()),
+            size: Cell::new(Span::full()),
+            child: RefCell::new(None),
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn with_margin(self: Rc<Self>, margin: Vec2<Span>) -> Rc<Self> {
+        self.margin.set(margin);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_size(self: Rc<Self>, size: Vec2<Span>) -> Rc<Self> {
+        self.size.set(size);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_child<E: Element>(self: Rc<Self>, child: Rc<E>) -> Rc<Self> {
+        *self.child.borrow_mut() = Some(child);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_anchor(&self) -> Vec2<f32> { self.anchor.get() }
+    #[allow(dead_code)]
+    pub fn set_anchor(&self, anchor: Vec2<f32>) { self.anchor.set(anchor); }
+
+    #[allow(dead_code)]
+    pub fn get_margin(&self) -> Vec2<Span> { self.margin.get() }
+    #[allow(dead_code)]
+    pub fn set_margin(&self, margin: Vec2<Span>) { self.margin.set(margin); }
+
+    #[allow(dead_code)]
+    pub fn get_size(&self) -> Vec2<Span> { self.size.get() }
+    #[allow(dead_code)]
+    pub fn set_size(&self, size: Vec2<Span>) { self.size.set(size); }
+
+    #[allow(dead_code)]
+    pub fn get_child(&self) -> Option<Rc<dyn Element>> { self.child.borrow().as_ref().map(|c| c.clone()) }
+    #[allow(dead_code)]
+    pub fn set_child<E: Element>(&self, child: Rc<E>) -> Rc<E> {
+        *self.child.borrow_mut() = Some(child.clone());
+        child
+    }
+
+    #[allow(dead_code)]
+    pub fn clone_all(&self) -> Rc<Self> { Rc::new(self.clone()) }
+
+    pub(crate) fn bounds_for_child(&self, scr_res: Vec2<f32>, bounds: Bounds) -> Bounds {
+        let anchor = self.anchor.get();
+        let size = self.size.get().map(|e| e.rel) * bounds.1 + self.size.get().map(|e| e.px as f32) / scr_res;
+        let margin = self.margin.get().map(|e| e.rel) * bounds.1 + self.margin.get().map(|e| e.px as f32) / scr_res;
+        // Margins push away from the edge the child is anchored to, and do nothing for a centred axis
+        let inwards = Vec2::one() - anchor * 2.0;
+        (bounds.0 + (bounds.1 - size) * anchor + margin * inwards, size)
+    }
+}
+
+impl Element for Anchor {
+    fn deep_clone(&self) -> Rc<dyn Element> { self.clone_all() }
+
+    fn render(&self, renderer: &mut Renderer, rescache: &mut ResCache, bounds: Bounds) {
+        let scr_res = renderer.get_view_resolution().map(|e| e as f32);
+
+        if let Some(child) = self.child.borrow().as_ref() {
+            child.render(renderer, rescache, self.bounds_for_child(scr_res, bounds));
+        }
+    }
+
+    fn handle_event(&self, event: &Event, scr_res: Vec2<f32>, bounds: Bounds) -> bool {
+        self.child
+            .borrow()
+            .as_ref()
+            .map(|child| child.handle_event(event, scr_res, self.bounds_for_child(scr_res, bounds)))
+            .unwrap_or(false)
+    }
+
+    fn focusables(&self, scr_res: Vec2<f32>, bounds: Bounds, out: &mut Vec<Focusable>) {
+        if let Some(child) = self.child.borrow().as_ref() {
+            collect_focusables(child, scr_res, self.bounds_for_child(scr_res, bounds), out);
+        }
+    }
+}
+
+impl Clone for Anchor {
+    fn clone(&self) -> Self {
+        Self {
+            anchor: self.anchor.clone(),
+            margin: self.margin.clone(),
+            size: self.size.clone(),
+            child: RefCell::new(self.child.borrow().as_ref().map(|c| c.deep_clone())),
+        }
+    }
+}
@@ -9,7 +9,9 @@ use glutin::{ElementState, MouseButton};
 use vek::*;
 
 // Local
-use super::{primitive::draw_rectangle, Bounds, Element, Event, ResCache, Span};
+use super::{
+    collect_focusables, contains, primitive::draw_rectangle, Bounds, Element, Event, Focusable, Label, ResCache, Span,
+};
 use crate::renderer::Renderer;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -26,6 +28,8 @@ pub struct Button {
     click_col: Cell<Rgba<f32>>,
     margin: Cell<Vec2<Span>>,
     active_mode: Cell<ActiveMode>,
+    // Last cursor position in pixels, normalised when used so that it stays correct across resizes
+    cursor: Cell<Vec2<f32>>,
     click_fn: RefCell<Option<Rc<dyn Fn(&Button) + 'static>>>,
     child: RefCell<Option<Rc<dyn Element>>>,
 }
@@ -39,6 +43,7 @@ impl Button {
             click_col: Cell::new(Rgba::one()),
             margin: Cell::new(Span::zero()),
             active_mode: Cell::new(ActiveMode::None),
+            cursor: Cell::new(Vec2::zero()),
             click_fn: RefCell::new(None),
             child: RefCell::new(None),
         })
@@ -80,6 +85,12 @@ impl Button {
         self
     }
 
+    /// Give the button a text label as its child
+    #[allow(dead_code)]
+    pub fn with_label(self: Rc<Self>, text: String, col: Rgba<f32>) -> Rc<Self> {
+        self.with_child(Label::new().with_text(text).with_color(col))
+    }
+
     #[allow(dead_code)]
     pub fn get_color(&self) -> Rgba<f32> { self.col.get() }
     #[allow(dead_code)]
@@ -114,9 +125,18 @@ impl Button {
     #[allow(dead_code)]
     pub fn clone_all(&self) -> Rc<Self> { Rc::new(self.clone()) }
 
+    fn update_hover(&self, scr_res: Vec2<f32>, bounds: Bounds) {
+        let hovered = contains(bounds, self.cursor.get() / scr_res);
+        match self.active_mode.get() {
+            // Keep showing the press until the button is released, even if the cursor wanders off
+            ActiveMode::Click => {},
+            _ if hovered => self.active_mode.set(ActiveMode::Hover),
+            _ => self.active_mode.set(ActiveMode::None),
+        }
+    }
+
     fn bounds_for_child(&self, scr_res: Vec2<f32>, bounds: Bounds) -> Bounds {
-        let margin_rel =
-            self.margin.get().map(|e| e.rel) * bounds.1 * scr_res + self.margin.get().map(|e| e.px as f32) / scr_res;
+        let margin_rel = self.margin.get().map(|e| e.rel) * bounds.1 + self.margin.get().map(|e| e.px as f32) / scr_res;
         (bounds.0 + margin_rel, bounds.1 - margin_rel * 2.0)
     }
 }
@@ -157,31 +177,35 @@ impl Element for Button {
         let used = used
             | match event {
                 Event::CursorPosition { x, y } => {
-                    let cursor = Vec2::new(*x as f32, *y as f32) / scr_res;
-                    if cursor.x > bounds.0.x
-                        && cursor.y > bounds.0.y
-                        && cursor.x < bounds.0.x + bounds.1.x
-                        && cursor.y < bounds.0.y + bounds.1.y
-                    {
-                        if self.active_mode.get() == ActiveMode::None {
-                            self.active_mode.set(ActiveMode::Hover);
-                        }
-                    } else {
-                        self.active_mode.set(ActiveMode::None);
-                    }
+                    self.cursor.set(Vec2::new(*x as f32, *y as f32));
+                    self.update_hover(scr_res, bounds);
+                    false
+                },
+                Event::Resized { w, h } => {
+                    self.update_hover(Vec2::new(*w as f32, *h as f32), bounds);
                     false
                 },
-                Event::MouseButton { state, button } => {
-                    if self.active_mode.get() != ActiveMode::None && *button == MouseButton::Left {
-                        if *state == ElementState::Pressed {
+                Event::MouseButton {
+                    state,
+                    button: MouseButton::Left,
+                } => {
+                    let hovered = contains(bounds, self.cursor.get() / scr_res);
+                    match state {
+                        ElementState::Pressed if hovered => {
                             self.active_mode.set(ActiveMode::Click);
-                        } else {
-                            self.click_fn.borrow_mut().as_mut().map(|f| (*f)(self));
-                            self.active_mode.set(ActiveMode::Hover);
-                        }
-                        true
-                    } else {
-                        false
+                            true
+                        },
+                        // Only clicks that were pressed and released over the button count
+                        ElementState::Released if self.active_mode.get() == ActiveMode::Click => {
+                            if hovered {
+                                self.click_fn.borrow().as_ref().map(|f| (*f)(self));
+                                self.active_mode.set(ActiveMode::Hover);
+                            } else {
+                                self.active_mode.set(ActiveMode::None);
+                            }
+                            true
+                        },
+                        _ => false,
                     }
                 },
                 _ => false,
@@ -189,6 +213,12 @@ impl Element for Button {
 
         used
     }
+
+    fn focusables(&self, scr_res: Vec2<f32>, bounds: Bounds, out: &mut Vec<Focusable>) {
+        if let Some(child) = self.child.borrow().as_ref() {
+            collect_focusables(child, scr_res, self.bounds_for_child(scr_res, bounds), out);
+        }
+    }
 }
 
 impl Clone for Button {
@@ -199,6 +229,7 @@ impl Clone for Button {
             click_col: self.click_col.clone(),
             margin: self.margin.clone(),
             active_mode: self.active_mode.clone(),
+            cursor: self.cursor.clone(),
             click_fn: RefCell::new(self.click_fn.borrow().as_ref().map(|c| c.clone())),
             child: RefCell::new(self.child.borrow().as_ref().map(|c| c.deep_clone())),
         }
@@ -9,14 +9,23 @@ use std::{
 use vek::*;
 
 // Local
-use super::{primitive::draw_rectangle, Bounds, Element, Event, ResCache, Span};
+use super::{
+    collect_focusables, primitive::draw_rectangle, stack, Bounds, Element, Event, Focusable, ResCache, Span,
+};
 use crate::renderer::Renderer;
 
+struct HBoxChild {
+    weight: f32,
+    element: Rc<dyn Element>,
+}
+
+/// Lines its children up left to right. Each child gets a share of the width proportional to its weight.
 #[allow(dead_code)]
 pub struct HBox {
     col: Cell<Rgba<f32>>,
     margin: Cell<Vec2<Span>>,
-    children: RefCell<VecDeque<Rc<dyn Element>>>,
+    spacing: Cell<Span>,
+    children: RefCell<VecDeque<HBoxChild>>,
 }
 
 impl HBox {
@@ -25,6 +34,7 @@ impl HBox {
         Rc::new(Self {
             col: Cell::new(Rgba::zero()),
             margin: Cell::new(Span::zero()),
+            spacing: Cell::new(Span { rel: 0.0, px: 0 }),
             children: RefCell::new(VecDeque::new()),
         })
     }
@@ -41,14 +51,27 @@ impl HBox {
         self
     }
 
+    /// Space left between neighbouring children. The relative part is relative to the box's width.
+    #[allow(dead_code)]
+    pub fn with_spacing(self: Rc<Self>, spacing: Span) -> Rc<Self> {
+        self.spacing.set(spacing);
+        self
+    }
+
     #[allow(dead_code)]
-    pub fn push_back<E: Element>(&self, child: Rc<E>) -> Rc<E> {
-        self.children.borrow_mut().push_back(child.clone());
+    pub fn push_back<E: Element>(&self, child: Rc<E>) -> Rc<E> { self.push_back_weighted(child, 1.0) }
+
+    #[allow(dead_code)]
+    pub fn push_back_weighted<E: Element>(&self, child: Rc<E>, weight: f32) -> Rc<E> {
+        self.children.borrow_mut().push_back(HBoxChild {
+            weight: weight.max(0.0),
+            element: child.clone(),
+        });
         child
     }
 
     #[allow(dead_code)]
-    pub fn pop_front(&self) -> Option<Rc<dyn Element>> { self.children.borrow_mut().pop_front() }
+    pub fn pop_front(&self) -> Option<Rc<dyn Element>> { self.children.borrow_mut().pop_front().map(|c| c.element) }
 
     #[allow(dead_code)]
     pub fn get_color(&self) -> Rgba<f32> { self.col.get() }
@@ -60,16 +83,25 @@ impl HBox {
     #[allow(dead_code)]
     pub fn set_margin(&self, margin: Vec2<Span>) { self.margin.set(margin); }
 
+    #[allow(dead_code)]
+    pub fn get_spacing(&self) -> Span { self.spacing.get() }
+    #[allow(dead_code)]
+    pub fn set_spacing(&self, spacing: Span) { self.spacing.set(spacing); }
+
     #[allow(dead_code)]
     pub fn clone_all(&self) -> Rc<Self> { Rc::new(self.clone()) }
 
-    fn bounds_for_child(&self, child_index: usize, scr_res: Vec2<f32>, bounds: Bounds) -> Bounds {
+    /// The bounds of each child, in order. Worked out from the resolution every time, so it follows resizes.
+    pub(crate) fn layout(&self, scr_res: Vec2<f32>, bounds: Bounds) -> Vec<Bounds> {
         let margin_rel = self.margin.get().map(|e| e.rel) * bounds.1 + self.margin.get().map(|e| e.px as f32) / scr_res;
-        let child_bounds = (bounds.0 + margin_rel, bounds.1 - margin_rel * 2.0);
-        let child_count = self.children.borrow().len();
-        let offs = child_bounds.0 + Vec2::new(child_index as f32 * child_bounds.1.x / child_count as f32, 0.0);
-        let size = child_bounds.1 / Vec2::new(child_count as f32, 1.0);
-        (offs, size)
+        let inner = (bounds.0 + margin_rel, bounds.1 - margin_rel * 2.0);
+        let spacing = self.spacing.get().rel * inner.1.x + self.spacing.get().px as f32 / scr_res.x;
+        let weights: Vec<_> = self.children.borrow().iter().map(|c| c.weight).collect();
+
+        stack(inner.0.x, inner.1.x, spacing, &weights)
+            .into_iter()
+            .map(|(x, w)| (Vec2::new(x, inner.0.y), Vec2::new(w, inner.1.y)))
+            .collect()
     }
 }
 
@@ -81,20 +113,29 @@ impl Element for HBox {
 
         let scr_res = renderer.get_view_resolution().map(|e| e as f32);
 
-        for (i, child) in self.children.borrow().iter().enumerate() {
-            child.render(renderer, rescache, self.bounds_for_child(i, scr_res, bounds));
+        let layout = self.layout(scr_res, bounds);
+        for (child, child_bounds) in self.children.borrow().iter().zip(layout) {
+            child.element.render(renderer, rescache, child_bounds);
         }
     }
 
     fn handle_event(&self, event: &Event, scr_res: Vec2<f32>, bounds: Bounds) -> bool {
+        let layout = self.layout(scr_res, bounds);
         self.children
             .borrow()
             .iter()
-            .enumerate()
-            .fold(false, |used, (i, child)| {
-                used | child.handle_event(event, scr_res, self.bounds_for_child(i, scr_res, bounds))
+            .zip(layout)
+            .fold(false, |used, (child, child_bounds)| {
+                used | child.element.handle_event(event, scr_res, child_bounds)
             })
     }
+
+    fn focusables(&self, scr_res: Vec2<f32>, bounds: Bounds, out: &mut Vec<Focusable>) {
+        let layout = self.layout(scr_res, bounds);
+        for (child, child_bounds) in self.children.borrow().iter().zip(layout) {
+            collect_focusables(&child.element, scr_res, child_bounds, out);
+        }
+    }
 }
 
 impl Clone for HBox {
@@ -102,7 +143,17 @@ impl Clone for HBox {
         Self {
             col: self.col.clone(),
             margin: self.margin.clone(),
-            children: RefCell::new(self.children.borrow().iter().map(|c| c.deep_clone()).collect()),
+            spacing: self.spacing.clone(),
+            children: RefCell::new(
+                self.children
+                    .borrow()
+                    .iter()
+                    .map(|c| HBoxChild {
+                        weight: c.weight,
+                        element: c.element.deep_clone(),
+                    })
+                    .collect(),
+            ),
         }
     }
 }
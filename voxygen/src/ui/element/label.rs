@@ -8,7 +8,11 @@ use std::{
 use vek::*;
 
 // Local
-use super::{primitive::draw_text, Bounds, Element, ResCache, Span};
+use super::{
+    primitive::{draw_wrapped_text, measure_text},
+    text::TextStyle,
+    Bounds, Element, ResCache, Span,
+};
 use crate::renderer::Renderer;
 
 #[allow(dead_code)]
@@ -19,6 +23,8 @@ pub struct Label {
     bg_col: Cell<Rgba<f32>>,
     padding: Cell<Vec2<Span>>,
     size: Cell<Vec2<Span>>,
+    wrap: Cell<bool>,
+    centered: Cell<bool>,
 }
 
 impl Label {
@@ -30,6 +36,8 @@ impl Label {
             bg_col: Cell::new(Rgba::new(1.0, 1.0, 1.0, 1.0)),
             padding: Cell::new(Span::zero()),
             size: Cell::new(Span::px(16, 16)),
+            wrap: Cell::new(false),
+            centered: Cell::new(false),
         })
     }
 
@@ -51,6 +59,19 @@ impl Label {
         self
     }
 
+    /// Wrap the text to the width of the label
+    #[allow(dead_code)]
+    pub fn with_wrap(self: Rc<Self>, wrap: bool) -> Rc<Self> {
+        self.wrap.set(wrap);
+        self
+    }
+
+    /// Center the text horizontally within the label rather than starting it at the left
+    pub fn with_centered(self: Rc<Self>, centered: bool) -> Rc<Self> {
+        self.centered.set(centered);
+        self
+    }
+
     #[allow(dead_code)]
     pub fn get_text(&self) -> Ref<Option<String>> { self.text.borrow() }
     #[allow(dead_code)]
@@ -66,6 +87,11 @@ impl Label {
     #[allow(dead_code)]
     pub fn set_size(&self, size: Vec2<Span>) { self.size.set(size); }
 
+    #[allow(dead_code)]
+    pub fn get_wrap(&self) -> bool { self.wrap.get() }
+    #[allow(dead_code)]
+    pub fn set_wrap(&self, wrap: bool) { self.wrap.set(wrap); }
+
     #[allow(dead_code)]
     pub fn clone_all(&self) -> Rc<Self> { Rc::new(self.clone()) }
 }
@@ -77,7 +103,17 @@ impl Element for Label {
         if let Some(text) = self.text.borrow().as_ref() {
             let res = renderer.get_view_resolution().map(|e| e as f32);
             let sz = self.size.get().map(|e| e.rel) * res.map(|e| e as f32) + self.size.get().map(|e| e.px as f32);
-            draw_text(renderer, rescache, text, bounds.0, sz, self.col.get());
+            let style = TextStyle {
+                size: sz,
+                color: self.col.get(),
+            };
+            let max_width = if self.wrap.get() { Some(bounds.1.x * res.x) } else { None };
+            let mut pos = bounds.0;
+            if self.centered.get() {
+                let width = measure_text(rescache, text, &style, max_width).x;
+                pos.x += (bounds.1.x - width / res.x) / 2.0;
+            }
+            draw_wrapped_text(renderer, rescache, text, pos, &style, max_width);
         }
     }
 }
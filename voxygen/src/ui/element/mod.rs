@@ -1,14 +1,20 @@
 // Modules
+pub mod anchor;
 pub mod button;
 pub mod hbox;
 pub mod label;
+pub mod padding;
 pub mod rect;
+pub mod slider;
 pub mod textbox;
 pub mod vbox;
 pub mod winbox;
 
 // Rexports
-pub use self::{button::Button, hbox::HBox, label::Label, rect::Rect, textbox::TextBox, vbox::VBox, winbox::WinBox};
+pub use self::{
+    anchor::Anchor, button::Button, hbox::HBox, label::Label, padding::Padding, rect::Rect, slider::Slider,
+    textbox::TextBox, vbox::VBox, winbox::WinBox,
+};
 
 // Standard
 use std::rc::Rc;
@@ -17,14 +23,46 @@ use std::rc::Rc;
 use vek::*;
 
 // Local
-use super::*;
+use super::{
+    focus::{collect_focusables, FocusId, Focusable},
+    *,
+};
 use crate::window::Event;
 
 // Utility aliases
-type Bounds = (Vec2<f32>, Vec2<f32>);
+pub type Bounds = (Vec2<f32>, Vec2<f32>);
+
+/// Split a span of `len` starting at `start` into consecutive slices sized by `weights`, with `spacing` between them.
+/// Returns the start and length of each slice.
+fn stack(start: f32, len: f32, spacing: f32, weights: &[f32]) -> Vec<(f32, f32)> {
+    let total_weight: f32 = weights.iter().sum();
+    let free = (len - spacing * weights.len().saturating_sub(1) as f32).max(0.0);
+    let mut offs = start;
+    weights
+        .iter()
+        .map(|w| {
+            let size = if total_weight > 0.0 { free * w / total_weight } else { 0.0 };
+            let slice = (offs, size);
+            offs += size + spacing;
+            slice
+        })
+        .collect()
+}
+
+/// Whether a normalised screen position lies within some bounds
+pub fn contains(bounds: Bounds, pos: Vec2<f32>) -> bool {
+    pos.x > bounds.0.x && pos.y > bounds.0.y && pos.x < bounds.0.x + bounds.1.x && pos.y < bounds.0.y + bounds.1.y
+}
 
 pub trait Element: 'static {
     fn deep_clone(&self) -> Rc<dyn Element>;
     fn render(&self, renderer: &mut Renderer, rescache: &mut ResCache, bounds: Bounds);
     fn handle_event(&self, _event: &Event, _scr_res: Vec2<f32>, _bounds: Bounds) -> bool { false }
+
+    /// The id of this element if it can take keyboard focus
+    fn focus_id(&self) -> Option<FocusId> { None }
+    /// Called by the `Ui` when this element gains or loses focus
+    fn set_focused(&self, _focused: bool) {}
+    /// Append the focusable elements beneath this one to `out`, in layout order
+    fn focusables(&self, _scr_res: Vec2<f32>, _bounds: Bounds, _out: &mut Vec<Focusable>) {}
 }
@@ -0,0 +1,91 @@
+// Standard
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+// Library
+use vek::*;
+
+// Local
+use super::{collect_focusables, Bounds, Element, Event, Focusable, ResCache, Span};
+use crate::renderer::Renderer;
+
+/// Shrinks the bounds of its child by the padding on every side
+#[allow(dead_code)]
+pub struct Padding {
+    padding: Cell<Vec2<Span>>,
+    child: RefCell<Option<Rc<dyn Element>>>,
+}
+
+impl Padding {
+    #[allow(dead_code)]
+    pub fn new(padding: Vec2<Span>) -> Rc<Self> {
+        Rc::new(Self {
+            padding: Cell::new(padding),
+            child: RefCell::new(None),
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn with_child<E: Element>(self: Rc<Self>, child: Rc<E>) -> Rc<Self> {
+        *self.child.borrow_mut() = Some(child);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_padding(&self) -> Vec2<Span> { self.padding.get() }
+    #[allow(dead_code)]
+    pub fn set_padding(&self, padding: Vec2<Span>) { self.padding.set(padding); }
+
+    #[allow(dead_code)]
+    pub fn get_child(&self) -> Option<Rc<dyn Element>> { self.child.borrow().as_ref().map(|c| c.clone()) }
+    #[allow(dead_code)]
+    pub fn set_child<E: Element>(&self, child: Rc<E>) -> Rc<E> {
+        *self.child.borrow_mut() = Some(child.clone());
+        child
+    }
+
+    #[allow(dead_code)]
+    pub fn clone_all(&self) -> Rc<Self> { Rc::new(self.clone()) }
+
+    pub(crate) fn bounds_for_child(&self, scr_res: Vec2<f32>, bounds: Bounds) -> Bounds {
+        let padding = self.padding.get().map(|e| e.rel) * bounds.1 + self.padding.get().map(|e| e.px as f32) / scr_res;
+        (bounds.0 + padding, (bounds.1 - padding * 2.0).map(|e| e.max(0.0)))
+    }
+}
+
+impl Element for Padding {
+    fn deep_clone(&self) -> Rc<dyn Element> { self.clone_all() }
+
+    fn render(&self, renderer: &mut Renderer, rescache: &mut ResCache, bounds: Bounds) {
+        let scr_res = renderer.get_view_resolution().map(|e| e as f32);
+
+        if let Some(child) = self.child.borrow().as_ref() {
+            child.render(renderer, rescache, self.bounds_for_child(scr_res, bounds));
+        }
+    }
+
+    fn handle_event(&self, event: &Event, scr_res: Vec2<f32>, bounds: Bounds) -> bool {
+        self.child
+            .borrow()
+            .as_ref()
+            .map(|child| child.handle_event(event, scr_res, self.bounds_for_child(scr_res, bounds)))
+            .unwrap_or(false)
+    }
+
+    fn focusables(&self, scr_res: Vec2<f32>, bounds: Bounds, out: &mut Vec<Focusable>) {
+        if let Some(child) = self.child.borrow().as_ref() {
+            collect_focusables(child, scr_res, self.bounds_for_child(scr_res, bounds), out);
+        }
+    }
+}
+
+impl Clone for Padding {
+    fn clone(&self) -> Self {
+        Self {
+            padding: self.padding.clone(),
+            child: RefCell::new(self.child.borrow().as_ref().map(|c| c.deep_clone())),
+        }
+    }
+}
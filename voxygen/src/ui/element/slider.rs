@@ -0,0 +1,284 @@
+// Standard
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+// Library
+use glutin::{ElementState, MouseButton, VirtualKeyCode};
+use vek::*;
+
+// Local
+use super::{contains, primitive::draw_rectangle, Bounds, Element, Event, FocusId, ResCache, Span};
+use crate::renderer::Renderer;
+
+/// A horizontal slider for picking a value between `min` and `max`, snapped to multiples of `step` from `min`
+#[allow(dead_code)]
+pub struct Slider {
+    min: Cell<f32>,
+    max: Cell<f32>,
+    step: Cell<f32>,
+    value: Cell<f32>,
+    track_col: Cell<Rgba<f32>>,
+    handle_col: Cell<Rgba<f32>>,
+    active_col: Cell<Rgba<f32>>,
+    handle_width: Cell<Span>,
+    dragging: Cell<bool>,
+    focused: Cell<bool>,
+    focus_id: FocusId,
+    // Last cursor position in pixels, normalised when used so that it stays correct across resizes
+    cursor: Cell<Vec2<f32>>,
+    change_fn: RefCell<Option<Rc<dyn Fn(&Slider, f32) + 'static>>>,
+}
+
+impl Slider {
+    #[allow(dead_code)]
+    pub fn new() -> Rc<Self> {
+        Rc::new(Self {
+            min: Cell::new(0.0),
+            max: Cell::new(1.0),
+            step: Cell::new(0.0),
+            value: Cell::new(0.0),
+            track_col: Cell::new(Rgba::new(0.0, 0.0, 0.0, 0.5)),
+            handle_col: Cell::new(Rgba::one()),
+            active_col: Cell::new(Rgba::one()),
+            handle_width: Cell::new(Span { rel: 0.0, px: 12 }),
+            dragging: Cell::new(false),
+            focused: Cell::new(false),
+            focus_id: FocusId::new(),
+            cursor: Cell::new(Vec2::zero()),
+            change_fn: RefCell::new(None),
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn with_range(self: Rc<Self>, min: f32, max: f32) -> Rc<Self> {
+        self.set_range(min, max);
+        self
+    }
+
+    /// A step of 0 lets the slider take any value in its range
+    #[allow(dead_code)]
+    pub fn with_step(self: Rc<Self>, step: f32) -> Rc<Self> {
+        self.set_step(step);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_value(self: Rc<Self>, value: f32) -> Rc<Self> {
+        self.set_value(value);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_track_color(self: Rc<Self>, col: Rgba<f32>) -> Rc<Self> {
+        self.track_col.set(col);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_handle_color(self: Rc<Self>, col: Rgba<f32>) -> Rc<Self> {
+        self.handle_col.set(col);
+        self
+    }
+
+    /// The handle color used while the slider is being dragged or has focus
+    #[allow(dead_code)]
+    pub fn with_active_color(self: Rc<Self>, col: Rgba<f32>) -> Rc<Self> {
+        self.active_col.set(col);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_handle_width(self: Rc<Self>, width: Span) -> Rc<Self> {
+        self.handle_width.set(width);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_change_fn<F: Fn(&Self, f32) + 'static>(self: Rc<Self>, f: F) -> Rc<Self> {
+        *self.change_fn.borrow_mut() = Some(Rc::new(f));
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_range(&self) -> (f32, f32) { (self.min.get(), self.max.get()) }
+    #[allow(dead_code)]
+    pub fn set_range(&self, min: f32, max: f32) {
+        self.min.set(min.min(max));
+        self.max.set(max.max(min));
+        self.value.set(self.snap(self.value.get()));
+    }
+
+    #[allow(dead_code)]
+    pub fn get_step(&self) -> f32 { self.step.get() }
+    #[allow(dead_code)]
+    pub fn set_step(&self, step: f32) {
+        self.step.set(step.max(0.0));
+        self.value.set(self.snap(self.value.get()));
+    }
+
+    #[allow(dead_code)]
+    pub fn get_value(&self) -> f32 { self.value.get() }
+    /// Set the value without invoking the change function. The value is clamped to the range and snapped to the step.
+    #[allow(dead_code)]
+    pub fn set_value(&self, value: f32) { self.value.set(self.snap(value)); }
+
+    #[allow(dead_code)]
+    pub fn get_focus_id(&self) -> FocusId { self.focus_id }
+
+    #[allow(dead_code)]
+    pub fn set_change_fn<F: Fn(&Self, f32) + 'static>(&self, f: F) { *self.change_fn.borrow_mut() = Some(Rc::new(f)); }
+
+    #[allow(dead_code)]
+    pub fn clone_all(&self) -> Rc<Self> { Rc::new(self.clone()) }
+
+    fn snap(&self, value: f32) -> f32 {
+        let (min, max, step) = (self.min.get(), self.max.get(), self.step.get());
+        let value = value.max(min).min(max);
+        if step > 0.0 {
+            (min + ((value - min) / step).round() * step).min(max)
+        } else {
+            value
+        }
+    }
+
+    // Set the value as the user, invoking the change function if it changed
+    fn change(&self, value: f32) {
+        let value = self.snap(value);
+        if value != self.value.get() {
+            self.value.set(value);
+            self.change_fn.borrow().as_ref().map(|f| (*f)(self, value));
+        }
+    }
+
+    fn handle_width(&self, scr_res: Vec2<f32>, bounds: Bounds) -> f32 {
+        let width = self.handle_width.get();
+        (width.rel * bounds.1.x + width.px as f32 / scr_res.x).min(bounds.1.x)
+    }
+
+    // The value under a normalised screen position
+    fn value_at(&self, pos: Vec2<f32>, scr_res: Vec2<f32>, bounds: Bounds) -> f32 {
+        let half_handle = self.handle_width(scr_res, bounds) / 2.0;
+        let travel = bounds.1.x - half_handle * 2.0;
+        let frac = if travel > 0.0 {
+            (pos.x - bounds.0.x - half_handle) / travel
+        } else {
+            0.0
+        };
+        self.min.get() + frac * (self.max.get() - self.min.get())
+    }
+
+    fn frac(&self) -> f32 {
+        let (min, max) = (self.min.get(), self.max.get());
+        if max > min {
+            (self.value.get() - min) / (max - min)
+        } else {
+            0.0
+        }
+    }
+
+    fn key_step(&self) -> f32 {
+        if self.step.get() > 0.0 {
+            self.step.get()
+        } else {
+            (self.max.get() - self.min.get()) / 20.0
+        }
+    }
+}
+
+impl Element for Slider {
+    fn deep_clone(&self) -> Rc<dyn Element> { self.clone_all() }
+
+    fn render(&self, renderer: &mut Renderer, rescache: &mut ResCache, bounds: Bounds) {
+        let scr_res = renderer.get_view_resolution().map(|e| e as f32);
+
+        let track_height = bounds.1.y / 4.0;
+        draw_rectangle(
+            renderer,
+            rescache,
+            bounds.0 + Vec2::new(0.0, (bounds.1.y - track_height) / 2.0),
+            Vec2::new(bounds.1.x, track_height),
+            self.track_col.get(),
+        );
+
+        let handle_width = self.handle_width(scr_res, bounds);
+        draw_rectangle(
+            renderer,
+            rescache,
+            bounds.0 + Vec2::new(self.frac() * (bounds.1.x - handle_width), 0.0),
+            Vec2::new(handle_width, bounds.1.y),
+            if self.dragging.get() || self.focused.get() {
+                self.active_col.get()
+            } else {
+                self.handle_col.get()
+            },
+        );
+    }
+
+    fn handle_event(&self, event: &Event, scr_res: Vec2<f32>, bounds: Bounds) -> bool {
+        match event {
+            Event::CursorPosition { x, y } => {
+                self.cursor.set(Vec2::new(*x as f32, *y as f32));
+                if self.dragging.get() {
+                    self.change(self.value_at(self.cursor.get() / scr_res, scr_res, bounds));
+                }
+                false
+            },
+            Event::MouseButton {
+                state,
+                button: MouseButton::Left,
+            } => {
+                let cursor = self.cursor.get() / scr_res;
+                match state {
+                    ElementState::Pressed if contains(bounds, cursor) => {
+                        self.dragging.set(true);
+                        self.change(self.value_at(cursor, scr_res, bounds));
+                        true
+                    },
+                    ElementState::Released if self.dragging.get() => {
+                        self.dragging.set(false);
+                        true
+                    },
+                    _ => false,
+                }
+            },
+            // Only reaches the slider while it has focus
+            Event::KeyboardInput { i, .. } if i.state == ElementState::Pressed => match i.virtual_keycode {
+                Some(VirtualKeyCode::Left) | Some(VirtualKeyCode::Down) => {
+                    self.change(self.value.get() - self.key_step());
+                    true
+                },
+                Some(VirtualKeyCode::Right) | Some(VirtualKeyCode::Up) => {
+                    self.change(self.value.get() + self.key_step());
+                    true
+                },
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn focus_id(&self) -> Option<FocusId> { Some(self.focus_id) }
+    fn set_focused(&self, focused: bool) { self.focused.set(focused); }
+}
+
+impl Clone for Slider {
+    fn clone(&self) -> Self {
+        Self {
+            min: self.min.clone(),
+            max: self.max.clone(),
+            step: self.step.clone(),
+            value: self.value.clone(),
+            track_col: self.track_col.clone(),
+            handle_col: self.handle_col.clone(),
+            active_col: self.active_col.clone(),
+            handle_width: self.handle_width.clone(),
+            dragging: Cell::new(false),
+            focused: Cell::new(false),
+            focus_id: FocusId::new(),
+            cursor: self.cursor.clone(),
+            change_fn: RefCell::new(self.change_fn.borrow().as_ref().map(|f| f.clone())),
+        }
+    }
+}
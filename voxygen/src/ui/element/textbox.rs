@@ -5,21 +5,26 @@ use std::{
 };
 
 // Library
+use glutin::{ElementState, VirtualKeyCode};
 use vek::*;
 
 // Local
 use super::{
     primitive::{draw_rectangle, draw_text},
-    Bounds, Element, Event, ResCache, Span,
+    Bounds, Element, Event, FocusId, ResCache, Span,
 };
 use crate::renderer::Renderer;
 
 #[allow(dead_code)]
-#[derive(Clone)]
 pub struct TextBox {
     text: RefCell<String>,
+    // Where in `text` typing goes, in bytes
+    cursor: Cell<usize>,
     col: Cell<Rgba<f32>>,
     bg_col: Cell<Rgba<f32>>,
+    focus_bg_col: Cell<Rgba<f32>>,
+    focus_id: FocusId,
+    focused: Cell<bool>,
     margin: Cell<Vec2<Span>>,
     size: Cell<Vec2<Span>>,
     return_fn: RefCell<Option<Rc<dyn Fn(&TextBox, &str) + 'static>>>,
@@ -30,8 +35,12 @@ impl TextBox {
     pub fn new() -> Rc<Self> {
         Rc::new(Self {
             text: RefCell::new("".to_string()),
+            cursor: Cell::new(0),
             col: Cell::new(Rgba::new(0.0, 0.0, 0.0, 1.0)),
             bg_col: Cell::new(Rgba::new(1.0, 1.0, 1.0, 1.0)),
+            focus_bg_col: Cell::new(Rgba::new(1.0, 1.0, 1.0, 1.0)),
+            focus_id: FocusId::new(),
+            focused: Cell::new(false),
             margin: Cell::new(Span::zero()),
             size: Cell::new(Span::px(16, 16)),
             return_fn: RefCell::new(None),
@@ -40,7 +49,7 @@ impl TextBox {
 
     #[allow(dead_code)]
     pub fn with_text(self: Rc<Self>, text: String) -> Rc<Self> {
-        *self.text.borrow_mut() = text;
+        self.set_text(text);
         self
     }
 
@@ -56,6 +65,12 @@ impl TextBox {
         self
     }
 
+    #[allow(dead_code)]
+    pub fn with_focus_background_color(self: Rc<Self>, col: Rgba<f32>) -> Rc<Self> {
+        self.focus_bg_col.set(col);
+        self
+    }
+
     #[allow(dead_code)]
     pub fn with_margin(self: Rc<Self>, margin: Vec2<Span>) -> Rc<Self> {
         self.margin.set(margin);
@@ -76,8 +91,34 @@ impl TextBox {
 
     #[allow(dead_code)]
     pub fn get_text(&self) -> Ref<String> { self.text.borrow() }
+    /// Replace the text, with the cursor at the end of it
     #[allow(dead_code)]
-    pub fn set_text(&self, text: String) { *self.text.borrow_mut() = text; }
+    pub fn set_text(&self, text: String) {
+        self.cursor.set(text.len());
+        *self.text.borrow_mut() = text;
+    }
+
+    #[allow(dead_code)]
+    pub fn get_cursor(&self) -> usize { self.cursor.get() }
+    /// Move the cursor to `cursor` bytes into the text, or as close as it can go without splitting a character
+    #[allow(dead_code)]
+    pub fn set_cursor(&self, cursor: usize) {
+        let text = self.text.borrow();
+        let cursor = (0..=cursor.min(text.len())).rev().find(|i| text.is_char_boundary(*i)).unwrap_or(0);
+        self.cursor.set(cursor);
+    }
+
+    // Where the cursor would be after moving a character back or forward
+    fn char_before(&self) -> usize {
+        let text = self.text.borrow();
+        text[..self.cursor.get()].char_indices().last().map(|(i, _)| i).unwrap_or(0)
+    }
+
+    fn char_after(&self) -> usize {
+        let text = self.text.borrow();
+        let cursor = self.cursor.get();
+        text[cursor..].chars().next().map(|c| cursor + c.len_utf8()).unwrap_or(cursor)
+    }
 
     #[allow(dead_code)]
     pub fn get_color(&self) -> Rgba<f32> { self.col.get() }
@@ -89,6 +130,16 @@ impl TextBox {
     #[allow(dead_code)]
     pub fn set_background_color(&self, bg_col: Rgba<f32>) { self.bg_col.set(bg_col); }
 
+    #[allow(dead_code)]
+    pub fn get_focus_background_color(&self) -> Rgba<f32> { self.focus_bg_col.get() }
+    #[allow(dead_code)]
+    pub fn set_focus_background_color(&self, col: Rgba<f32>) { self.focus_bg_col.set(col); }
+
+    #[allow(dead_code)]
+    pub fn get_focus_id(&self) -> FocusId { self.focus_id }
+    #[allow(dead_code)]
+    pub fn is_focused(&self) -> bool { self.focused.get() }
+
     #[allow(dead_code)]
     pub fn set_return_fn<F: Fn(&Self, &str) + 'static>(&self, f: F) { *self.return_fn.borrow_mut() = Some(Rc::new(f)); }
 
@@ -105,21 +156,24 @@ impl Element for TextBox {
     fn deep_clone(&self) -> Rc<dyn Element> { self.clone_all() }
 
     fn render(&self, renderer: &mut Renderer, rescache: &mut ResCache, bounds: Bounds) {
-        draw_rectangle(renderer, rescache, bounds.0, bounds.1, self.bg_col.get());
+        let bg_col = if self.focused.get() {
+            self.focus_bg_col.get()
+        } else {
+            self.bg_col.get()
+        };
+        draw_rectangle(renderer, rescache, bounds.0, bounds.1, bg_col);
 
         let scr_res = renderer.get_view_resolution().map(|e| e as f32);
         let margin_rel = self.margin.get().map(|e| e.rel) * bounds.1 + self.margin.get().map(|e| e.px as f32) / scr_res;
 
         let child_bounds = (bounds.0 + margin_rel, bounds.1 - margin_rel * 2.0);
         let sz = self.size.get().map(|e| e.rel) * scr_res.map(|e| e as f32) + self.size.get().map(|e| e.px as f32);
-        draw_text(
-            renderer,
-            rescache,
-            &self.text.borrow(),
-            child_bounds.0,
-            sz,
-            self.col.get(),
-        );
+        // Show where typing goes while it has focus
+        let mut text = self.text.borrow().clone();
+        if self.focused.get() {
+            text.insert(self.cursor.get(), '|');
+        }
+        draw_text(renderer, rescache, &text, child_bounds.0, sz, self.col.get());
     }
 
     fn handle_event(&self, event: &Event, _scr_res: Vec2<f32>, _bounds: Bounds) -> bool {
@@ -130,18 +184,59 @@ impl Element for TextBox {
                         let mut text = self.text.borrow_mut();
                         self.return_fn.borrow_mut().as_mut().map(|f| (*f)(self, &text));
                         text.clear();
+                        self.cursor.set(0);
                     },
                     '\x08' => {
-                        self.text.borrow_mut().pop();
+                        let before = self.char_before();
+                        self.text.borrow_mut().drain(before..self.cursor.get());
+                        self.cursor.set(before);
                     },
+                    // Tabs and the like aren't text
+                    c if c.is_control() => {},
                     c => {
-                        self.text.borrow_mut().push(*c);
+                        self.text.borrow_mut().insert(self.cursor.get(), *c);
+                        self.cursor.set(self.cursor.get() + c.len_utf8());
                     },
                 }
                 true
             },
-            Event::KeyboardInput { .. } => true,
+            Event::KeyboardInput { i, .. } => {
+                if i.state == ElementState::Pressed {
+                    match i.virtual_keycode {
+                        Some(VirtualKeyCode::Left) => self.cursor.set(self.char_before()),
+                        Some(VirtualKeyCode::Right) => self.cursor.set(self.char_after()),
+                        Some(VirtualKeyCode::Home) => self.cursor.set(0),
+                        Some(VirtualKeyCode::End) => self.cursor.set(self.text.borrow().len()),
+                        Some(VirtualKeyCode::Delete) => {
+                            let after = self.char_after();
+                            self.text.borrow_mut().drain(self.cursor.get()..after);
+                        },
+                        _ => {},
+                    }
+                }
+                true
+            },
             _ => false,
         }
     }
+
+    fn focus_id(&self) -> Option<FocusId> { Some(self.focus_id) }
+    fn set_focused(&self, focused: bool) { self.focused.set(focused); }
+}
+
+impl Clone for TextBox {
+    fn clone(&self) -> Self {
+        Self {
+            text: self.text.clone(),
+            cursor: self.cursor.clone(),
+            col: self.col.clone(),
+            bg_col: self.bg_col.clone(),
+            focus_bg_col: self.focus_bg_col.clone(),
+            focus_id: FocusId::new(),
+            focused: Cell::new(false),
+            margin: self.margin.clone(),
+            size: self.size.clone(),
+            return_fn: RefCell::new(self.return_fn.borrow().as_ref().map(|f| f.clone())),
+        }
+    }
 }
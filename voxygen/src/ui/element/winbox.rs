@@ -8,7 +8,7 @@ use std::{
 use vek::*;
 
 // Local
-use super::{primitive::draw_rectangle, Bounds, Element, Event, ResCache, Span};
+use super::{collect_focusables, primitive::draw_rectangle, Bounds, Element, Event, Focusable, ResCache, Span};
 use crate::renderer::Renderer;
 
 pub struct WinBoxChild {
@@ -56,6 +56,10 @@ impl WinBox {
         child
     }
 
+    /// Remove every child
+    #[allow(dead_code)]
+    pub fn clear(&self) { self.children.borrow_mut().clear(); }
+
     #[allow(dead_code)]
     pub fn clone_all(&self) -> Rc<Self> { Rc::new(self.clone()) }
 
@@ -89,6 +93,12 @@ impl Element for WinBox {
                 .handle_event(event, scr_res, self.bounds_for_child(child, scr_res, bounds))
         })
     }
+
+    fn focusables(&self, scr_res: Vec2<f32>, bounds: Bounds, out: &mut Vec<Focusable>) {
+        for child in self.children.borrow().iter() {
+            collect_focusables(&child.element, scr_res, self.bounds_for_child(child, scr_res, bounds), out);
+        }
+    }
 }
 
 impl Clone for WinBox {
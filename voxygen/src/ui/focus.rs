@@ -0,0 +1,45 @@
+// Standard
+use std::{
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+// Library
+use vek::*;
+
+// Local
+use super::element::{contains, Bounds, Element};
+
+static NEXT_FOCUS_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Identifies a focusable element. Every focusable element (and every clone of one) gets its own id.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FocusId(usize);
+
+impl FocusId {
+    pub fn new() -> FocusId { FocusId(NEXT_FOCUS_ID.fetch_add(1, Ordering::Relaxed)) }
+}
+
+/// A focusable element along with the bounds it was laid out in
+pub struct Focusable {
+    pub id: FocusId,
+    pub element: Rc<dyn Element>,
+    pub bounds: Bounds,
+}
+
+impl Focusable {
+    pub fn contains(&self, pos: Vec2<f32>) -> bool { contains(self.bounds, pos) }
+}
+
+/// Collect `child` (if it's focusable) followed by any focusable elements beneath it, in layout order. Containers
+/// call this for each of their children from `Element::focusables`.
+pub fn collect_focusables(child: &Rc<dyn Element>, scr_res: Vec2<f32>, bounds: Bounds, out: &mut Vec<Focusable>) {
+    if let Some(id) = child.focus_id() {
+        out.push(Focusable {
+            id,
+            element: child.clone(),
+            bounds,
+        });
+    }
+    child.focusables(scr_res, bounds, out);
+}
@@ -1,29 +1,39 @@
 // Modules
 pub mod element;
+pub mod focus;
 mod primitive;
 mod render;
 pub mod rescache;
 pub mod span;
+pub mod text;
 #[cfg(test)]
 mod tests;
 
 // Reexports
-pub use self::span::Span;
+pub use self::{focus::FocusId, span::Span};
 
 // Standard
-use std::rc::Rc;
+use std::{cell::Cell, rc::Rc};
 
 // Library
+use glutin::{ElementState, MouseButton, VirtualKeyCode};
 use vek::*;
 
 // Local
-use self::{element::Element, rescache::ResCache};
+use self::{
+    element::{Bounds, Element},
+    focus::{collect_focusables, Focusable},
+    rescache::ResCache,
+};
 use crate::{renderer::Renderer, window::Event};
 
 #[allow(dead_code)]
 pub struct Ui {
     base: Rc<dyn Element>,
     rescache: ResCache,
+    focus: Cell<Option<FocusId>>,
+    // Cursor position in pixels, used to work out what a click landed on
+    cursor: Cell<Vec2<f32>>,
 }
 
 impl Ui {
@@ -32,6 +42,8 @@ impl Ui {
         Ui {
             base,
             rescache: ResCache::new(),
+            focus: Cell::new(None),
+            cursor: Cell::new(Vec2::zero()),
         }
     }
 
@@ -43,10 +55,109 @@ impl Ui {
 
     #[allow(dead_code)]
     pub fn handle_event(&self, event: &Event, renderer: &mut Renderer) -> bool {
-        self.base.handle_event(
-            event,
-            renderer.get_view_resolution().map(|e| e as f32),
-            (Vec2::zero(), Vec2::one()),
-        )
+        self.dispatch(event, renderer.get_view_resolution().map(|e| e as f32))
+    }
+
+    #[allow(dead_code)]
+    pub fn focus(&self) -> Option<FocusId> { self.focus.get() }
+
+    /// Give keyboard focus to the element with the given id, or clear it with `None`. Ids that don't belong to an
+    /// element in this `Ui` clear the focus.
+    #[allow(dead_code)]
+    pub fn set_focus(&self, id: Option<FocusId>) {
+        let focusables = self.focusables(Vec2::one());
+        let id = id.filter(|id| focusables.iter().any(|f| f.id == *id));
+        for f in &focusables {
+            f.element.set_focused(Some(f.id) == id);
+        }
+        self.focus.set(id);
+    }
+
+    fn bounds() -> Bounds { (Vec2::zero(), Vec2::one()) }
+
+    fn focusables(&self, scr_res: Vec2<f32>) -> Vec<Focusable> {
+        let mut focusables = vec![];
+        collect_focusables(&self.base, scr_res, Self::bounds(), &mut focusables);
+        focusables
+    }
+
+    // Move the focus to the next (or previous) focusable element in layout order, wrapping around at the ends
+    fn cycle_focus(&self, backwards: bool) -> bool {
+        let ids: Vec<_> = self.focusables(Vec2::one()).iter().map(|f| f.id).collect();
+        if ids.is_empty() {
+            return false;
+        }
+
+        let current = self.focus.get().and_then(|id| ids.iter().position(|i| *i == id));
+        let next = match (current, backwards) {
+            (Some(i), false) => (i + 1) % ids.len(),
+            (Some(i), true) => (i + ids.len() - 1) % ids.len(),
+            (None, false) => 0,
+            (None, true) => ids.len() - 1,
+        };
+        self.set_focus(Some(ids[next]));
+        true
+    }
+
+    fn dispatch(&self, event: &Event, scr_res: Vec2<f32>) -> bool {
+        match event {
+            Event::KeyboardInput { i, .. } => match i.virtual_keycode {
+                Some(VirtualKeyCode::Tab) => {
+                    if i.state == ElementState::Pressed {
+                        self.cycle_focus(i.modifiers.shift)
+                    } else {
+                        self.focus.get().is_some()
+                    }
+                },
+                Some(VirtualKeyCode::Escape) if self.focus.get().is_some() => {
+                    self.set_focus(None);
+                    true
+                },
+                // Keyboard input only goes to the focused element, and falls through to the game otherwise
+                _ => self.dispatch_to_focus(event, scr_res),
+            },
+            // Tab is used for navigation, never typed
+            Event::Character { ch: '\t' } => self.focus.get().is_some(),
+            Event::Character { .. } => self.dispatch_to_focus(event, scr_res),
+            Event::CursorPosition { x, y } => {
+                self.cursor.set(Vec2::new(*x as f32, *y as f32));
+                self.base.handle_event(event, scr_res, Self::bounds())
+            },
+            Event::MouseButton {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+            } => {
+                // Clicking a focusable element focuses it, clicking anywhere else clears the focus
+                let cursor = self.cursor.get() / scr_res;
+                let clicked = self
+                    .focusables(scr_res)
+                    .into_iter()
+                    .rev()
+                    .find(|f| f.contains(cursor))
+                    .map(|f| f.id);
+                self.set_focus(clicked);
+                self.base.handle_event(event, scr_res, Self::bounds()) || clicked.is_some()
+            },
+            _ => self.base.handle_event(event, scr_res, Self::bounds()),
+        }
+    }
+
+    fn dispatch_to_focus(&self, event: &Event, scr_res: Vec2<f32>) -> bool {
+        let id = match self.focus.get() {
+            Some(id) => id,
+            None => return false,
+        };
+
+        match self.focusables(scr_res).into_iter().find(|f| f.id == id) {
+            Some(f) => {
+                f.element.handle_event(event, scr_res, f.bounds);
+                // The focused element owns the keyboard, even for keys it doesn't use
+                true
+            },
+            None => {
+                self.focus.set(None);
+                false
+            },
+        }
     }
 }
@@ -1,6 +1,6 @@
 // Library
 use gfx::traits::FactoryExt;
-use gfx_glyph::{GlyphBrushBuilder, Scale, Section};
+use gfx_glyph::{GlyphBrushBuilder, Scale, Section, SectionText, VariedSection};
 use lyon::{
     math::rect,
     tessellation::{
@@ -9,12 +9,14 @@ use lyon::{
         FillOptions,
     },
 };
+use rusttype::{self, Font};
 use vek::*;
 
 // Local
 use super::{
     render::{create_fill_pso, fill_pipeline, FillVertex, VertexFactory},
     rescache::{GlyphBrushRes, RectVboRes, ResCache},
+    text::{layout, size_of, TextSpan, TextStyle},
 };
 use crate::renderer::Renderer;
 
@@ -90,3 +92,97 @@ pub(crate) fn draw_text(
         .borrow_mut()
         .draw_queued(renderer.encoder_mut(), &color_view, &depth_view);
 }
+
+fn create_font(font: &'static [u8]) -> Font<'static> { Font::from_bytes(font).expect("Failed to load UI font") }
+
+fn rt_scale(size: Vec2<f32>) -> rusttype::Scale { rusttype::Scale { x: size.x, y: size.y } }
+
+fn line_height(font: &Font, size: Vec2<f32>) -> f32 {
+    let v_metrics = font.v_metrics(rt_scale(size));
+    v_metrics.ascent - v_metrics.descent + v_metrics.line_gap
+}
+
+/// The size in pixels of some text, wrapped to `max_width` pixels if given
+pub(crate) fn measure_text(
+    rescache: &mut ResCache,
+    text: &str,
+    style: &TextStyle,
+    max_width: Option<f32>,
+) -> Vec2<f32> {
+    let font = rescache.get_or_create_font(0, || create_font(UI_FONT));
+    rescache.get_or_create_text_size(text, style.size, max_width, || {
+        let scale = rt_scale(style.size);
+        let lines = layout(&[text], max_width, |c| font.glyph(c).scaled(scale).h_metrics().advance_width);
+        size_of(&lines, line_height(&font, style.size))
+    })
+}
+
+/// Draw text made of differently colored spans, wrapped to `max_width` pixels if given
+pub(crate) fn draw_text_spans(
+    renderer: &mut Renderer,
+    rescache: &mut ResCache,
+    spans: &[TextSpan],
+    pos: Vec2<f32>,
+    size: Vec2<f32>,
+    max_width: Option<f32>,
+) {
+    let font = rescache.get_or_create_font(0, || create_font(UI_FONT));
+    let texts: Vec<_> = spans.iter().map(|s| s.text).collect();
+    let scale = rt_scale(size);
+    let lines = layout(&texts, max_width, |c| font.glyph(c).scaled(scale).h_metrics().advance_width);
+    let line_height = line_height(&font, size);
+
+    // TODO: Properly hash all unique details of this glyph brush
+    let brush = rescache.get_or_create_glyph_brush(0, || create_glyph_brush(renderer, UI_FONT));
+
+    let color_view = renderer.color_view().clone();
+    let depth_view = renderer.depth_view().clone();
+
+    // Lay the text out in whole pixels, otherwise the glyphs get resampled and blur
+    let res = renderer.get_view_resolution().map(|e| e as f32);
+    let origin = (pos * res).map(|e| e.round());
+
+    for (i, line) in lines.iter().enumerate() {
+        brush.borrow_mut().queue(VariedSection {
+            screen_position: (origin + Vec2::new(0.0, (i as f32 * line_height).round())).into_tuple(),
+            text: line
+                .runs
+                .iter()
+                .map(|(span, text)| SectionText {
+                    text,
+                    scale: Scale { x: size.x, y: size.y },
+                    color: spans[*span].color.into_array(),
+                    ..SectionText::default()
+                })
+                .collect(),
+            ..VariedSection::default()
+        });
+    }
+
+    // We don't care if this fails
+    let _ = brush
+        .borrow_mut()
+        .draw_queued(renderer.encoder_mut(), &color_view, &depth_view);
+}
+
+/// Draw text in a single style, wrapped to `max_width` pixels if given
+pub(crate) fn draw_wrapped_text(
+    renderer: &mut Renderer,
+    rescache: &mut ResCache,
+    text: &str,
+    pos: Vec2<f32>,
+    style: &TextStyle,
+    max_width: Option<f32>,
+) {
+    draw_text_spans(
+        renderer,
+        rescache,
+        &[TextSpan {
+            text,
+            color: style.color,
+        }],
+        pos,
+        style.size,
+        max_width,
+    );
+}
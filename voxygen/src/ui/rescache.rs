@@ -1,7 +1,10 @@
 // Standard
 use std::{
     cell::RefCell,
-    collections::hash_map::{DefaultHasher, HashMap},
+    collections::{
+        hash_map::{DefaultHasher, HashMap},
+        VecDeque,
+    },
     hash::{Hash, Hasher},
     rc::Rc,
 };
@@ -10,11 +13,16 @@ use std::{
 use gfx::{handle::Buffer, Slice};
 use gfx_device_gl;
 use gfx_glyph::GlyphBrush;
+use rusttype::Font;
 use vek::*;
 
 // Local
 use super::render::{FillPso, FillVertex};
 
+// Constants
+// How many text measurements are kept. Text that changes every frame, like a clock, would otherwise fill the cache.
+const MAX_TEXT_SIZES: usize = 1024;
+
 // What is this?
 // -------------
 // This is `ResCache`, a cache for UI resources. When we want a resource - let's say a rectangle
@@ -38,6 +46,11 @@ pub struct ResCache {
     rect_vbos: HashMap<u64, Rc<RectVboRes>>,
     // Glyph brushes
     glyph_brushes: HashMap<u64, Rc<RefCell<GlyphBrushRes>>>,
+    // Fonts, for measuring text without going through the GPU
+    fonts: HashMap<u64, Rc<Font<'static>>>,
+    // Text measurements, and the order they were made in so the oldest can be forgotten
+    text_sizes: HashMap<u64, Vec2<f32>>,
+    text_size_order: VecDeque<u64>,
 }
 
 impl ResCache {
@@ -46,6 +59,9 @@ impl ResCache {
             fill_pso: None,
             rect_vbos: HashMap::new(),
             glyph_brushes: HashMap::new(),
+            fonts: HashMap::new(),
+            text_sizes: HashMap::new(),
+            text_size_order: VecDeque::new(),
         }
     }
 
@@ -101,4 +117,42 @@ impl ResCache {
             .cloned()
             .expect("This panic shouldn't be possible.")
     }
+
+    pub(crate) fn get_or_create_font<F: FnOnce() -> Font<'static>>(&mut self, hash: u64, f: F) -> Rc<Font<'static>> {
+        if self.fonts.get(&hash).is_none() {
+            self.fonts.insert(hash, Rc::new(f()));
+        }
+        self.fonts
+            .get(&hash)
+            .cloned()
+            .expect("This panic shouldn't be possible.")
+    }
+
+    pub(crate) fn get_or_create_text_size<F: FnOnce() -> Vec2<f32>>(
+        &mut self,
+        text: &str,
+        size: Vec2<f32>,
+        max_width: Option<f32>,
+        f: F,
+    ) -> Vec2<f32> {
+        let mut hasher = DefaultHasher::new();
+        (text, size.map(|e| e.to_bits()), max_width.map(|e| e.to_bits())).hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if let Some(size) = self.text_sizes.get(&hash) {
+            return *size;
+        }
+        if self.text_size_order.len() >= MAX_TEXT_SIZES {
+            if let Some(oldest) = self.text_size_order.pop_front() {
+                self.text_sizes.remove(&oldest);
+            }
+        }
+        let size = f();
+        self.text_sizes.insert(hash, size);
+        self.text_size_order.push_back(hash);
+        size
+    }
+
+    #[cfg(test)]
+    pub(crate) fn text_sizes_cached(&self) -> usize { self.text_sizes.len() }
 }
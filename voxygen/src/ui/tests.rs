@@ -1,10 +1,388 @@
+// Standard
+use std::{cell::Cell, rc::Rc};
+
 // Library
+use glutin::{DeviceId, ElementState, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode};
 use vek::*;
 
 // Local
-use super::{element::WinBox, Ui};
+use super::{
+    element::{Anchor, Bounds, Button, HBox, Padding, Rect, Slider, TextBox, VBox, WinBox},
+    rescache::ResCache,
+    text::{layout, size_of, Line},
+    Span, Ui,
+};
+use crate::window::Event;
+
+const SCR_RES: Vec2<f32> = Vec2 { x: 800.0, y: 600.0 };
+
+fn key(code: VirtualKeyCode, shift: bool) -> Event {
+    Event::KeyboardInput {
+        i: KeyboardInput {
+            scancode: 0,
+            state: ElementState::Pressed,
+            virtual_keycode: Some(code),
+            modifiers: ModifiersState {
+                shift,
+                ..ModifiersState::default()
+            },
+        },
+        device: unsafe { DeviceId::dummy() },
+    }
+}
+
+fn mouse(ui: &Ui, state: ElementState, scr_res: Vec2<f32>) {
+    ui.dispatch(
+        &Event::MouseButton {
+            state,
+            button: MouseButton::Left,
+        },
+        scr_res,
+    );
+}
+
+fn move_to(ui: &Ui, x: f64, y: f64, scr_res: Vec2<f32>) { ui.dispatch(&Event::CursorPosition { x, y }, scr_res); }
+
+fn click(ui: &Ui, x: f64, y: f64) {
+    move_to(ui, x, y, SCR_RES);
+    mouse(ui, ElementState::Pressed, SCR_RES);
+}
+
+// A single element filling the middle quarter of the screen
+fn centered<E: super::element::Element>(element: Rc<E>) -> Ui {
+    let winbox = WinBox::new();
+    winbox.add_child_at(Span::center(), Span::center(), Span::rel(0.5, 0.5), element);
+    Ui::new(winbox)
+}
+
+fn assert_bounds(actual: Bounds, expected: Bounds) {
+    let close = |a: Vec2<f32>, b: Vec2<f32>| (a.x - b.x).abs() < 0.0001 && (a.y - b.y).abs() < 0.0001;
+    assert!(
+        close(actual.0, expected.0) && close(actual.1, expected.1),
+        "expected {:?}, got {:?}",
+        expected,
+        actual
+    );
+}
+
+// Two text boxes stacked in the top half of the screen, with nothing in the bottom half
+fn two_inputs() -> (Ui, Rc<TextBox>, Rc<TextBox>) {
+    let winbox = WinBox::new();
+    let vbox = winbox.add_child_at(Span::top_left(), Span::top_left(), Span::rel(1.0, 0.5), VBox::new());
+    let first = vbox.push_back(TextBox::new());
+    let second = vbox.push_back(TextBox::new());
+    (Ui::new(winbox), first, second)
+}
 
 #[test]
 fn test_winbox() {
     // TODO!
 }
+
+#[test]
+fn tab_cycles_focus_in_layout_order() {
+    let (ui, first, second) = two_inputs();
+    assert_eq!(ui.focus(), None);
+
+    assert!(ui.dispatch(&key(VirtualKeyCode::Tab, false), SCR_RES));
+    assert_eq!(ui.focus(), Some(first.get_focus_id()));
+    assert!(first.is_focused());
+
+    ui.dispatch(&key(VirtualKeyCode::Tab, false), SCR_RES);
+    assert_eq!(ui.focus(), Some(second.get_focus_id()));
+    assert!(!first.is_focused());
+    assert!(second.is_focused());
+
+    ui.dispatch(&key(VirtualKeyCode::Tab, false), SCR_RES);
+    assert_eq!(ui.focus(), Some(first.get_focus_id()));
+
+    ui.dispatch(&key(VirtualKeyCode::Tab, true), SCR_RES);
+    assert_eq!(ui.focus(), Some(second.get_focus_id()));
+}
+
+#[test]
+fn characters_only_reach_the_focused_element() {
+    let (ui, first, second) = two_inputs();
+
+    // Nothing is focused, so the game gets the input
+    assert!(!ui.dispatch(&Event::Character { ch: 'a' }, SCR_RES));
+    assert!(!ui.dispatch(&key(VirtualKeyCode::W, false), SCR_RES));
+
+    ui.set_focus(Some(second.get_focus_id()));
+    assert!(ui.dispatch(&Event::Character { ch: 'b' }, SCR_RES));
+    assert_eq!(*first.get_text(), "");
+    assert_eq!(*second.get_text(), "b");
+
+    // Tab moves the focus rather than being typed
+    ui.dispatch(&Event::Character { ch: '\t' }, SCR_RES);
+    assert_eq!(*second.get_text(), "b");
+}
+
+#[test]
+fn typing_goes_where_the_cursor_is() {
+    let (ui, first, _) = two_inputs();
+    ui.set_focus(Some(first.get_focus_id()));
+    let type_in = |text: &str| {
+        for ch in text.chars() {
+            ui.dispatch(&Event::Character { ch }, SCR_RES);
+        }
+    };
+
+    type_in("hllo");
+    for _ in 0..3 {
+        ui.dispatch(&key(VirtualKeyCode::Left, false), SCR_RES);
+    }
+    type_in("e");
+    assert_eq!((first.get_text().as_str(), first.get_cursor()), ("hello", 2));
+
+    // Backspace and delete take the characters either side of the cursor, even ones wider than a byte
+    ui.dispatch(&key(VirtualKeyCode::End, false), SCR_RES);
+    type_in("é!");
+    ui.dispatch(&key(VirtualKeyCode::Left, false), SCR_RES);
+    type_in("\x08");
+    ui.dispatch(&key(VirtualKeyCode::Delete, false), SCR_RES);
+    assert_eq!((first.get_text().as_str(), first.get_cursor()), ("hello", 5));
+
+    // Setting the text puts the cursor at its end, and it can't be put inside a character
+    first.set_text("ç".to_string());
+    assert_eq!(first.get_cursor(), 2);
+    first.set_cursor(1);
+    assert_eq!(first.get_cursor(), 0);
+}
+
+#[test]
+fn clicking_sets_and_clears_focus() {
+    let (ui, first, second) = two_inputs();
+
+    click(&ui, 400.0, 225.0);
+    assert_eq!(ui.focus(), Some(second.get_focus_id()));
+
+    click(&ui, 400.0, 75.0);
+    assert_eq!(ui.focus(), Some(first.get_focus_id()));
+
+    click(&ui, 400.0, 500.0);
+    assert_eq!(ui.focus(), None);
+    assert!(!first.is_focused());
+
+    // Escape also clears the focus
+    ui.set_focus(Some(first.get_focus_id()));
+    assert!(ui.dispatch(&key(VirtualKeyCode::Escape, false), SCR_RES));
+    assert_eq!(ui.focus(), None);
+}
+
+#[test]
+fn cloned_elements_get_their_own_focus_id() {
+    let textbox = TextBox::new();
+    assert_ne!(textbox.get_focus_id(), textbox.clone_all().get_focus_id());
+}
+
+#[test]
+fn button_clicks_when_pressed_and_released_over_it() {
+    let clicks = Rc::new(Cell::new(0));
+    let clicks_ref = clicks.clone();
+    let ui = centered(Button::new().with_click_fn(move |_| clicks_ref.set(clicks_ref.get() + 1)));
+
+    move_to(&ui, 400.0, 300.0, SCR_RES);
+    mouse(&ui, ElementState::Pressed, SCR_RES);
+    assert_eq!(clicks.get(), 0);
+    mouse(&ui, ElementState::Released, SCR_RES);
+    assert_eq!(clicks.get(), 1);
+
+    // Pressing outside and releasing over the button isn't a click
+    move_to(&ui, 50.0, 50.0, SCR_RES);
+    mouse(&ui, ElementState::Pressed, SCR_RES);
+    move_to(&ui, 400.0, 300.0, SCR_RES);
+    mouse(&ui, ElementState::Released, SCR_RES);
+    assert_eq!(clicks.get(), 1);
+
+    // Nor is pressing on the button and dragging off it
+    mouse(&ui, ElementState::Pressed, SCR_RES);
+    move_to(&ui, 50.0, 50.0, SCR_RES);
+    mouse(&ui, ElementState::Released, SCR_RES);
+    assert_eq!(clicks.get(), 1);
+}
+
+#[test]
+fn button_hit_test_follows_resize() {
+    let clicks = Rc::new(Cell::new(0));
+    let clicks_ref = clicks.clone();
+    let ui = centered(Button::new().with_click_fn(move |_| clicks_ref.set(clicks_ref.get() + 1)));
+
+    // (700, 500) is inside the button at 1600x1200 but outside it at 800x600
+    move_to(&ui, 700.0, 500.0, SCR_RES);
+    mouse(&ui, ElementState::Pressed, SCR_RES);
+    mouse(&ui, ElementState::Released, SCR_RES);
+    assert_eq!(clicks.get(), 0);
+
+    let big = Vec2::new(1600.0, 1200.0);
+    ui.dispatch(&Event::Resized { w: 1600, h: 1200 }, big);
+    mouse(&ui, ElementState::Pressed, big);
+    mouse(&ui, ElementState::Released, big);
+    assert_eq!(clicks.get(), 1);
+}
+
+#[test]
+fn slider_drags_clamps_and_snaps() {
+    let changes = Rc::new(Cell::new(0));
+    let changes_ref = changes.clone();
+    let slider = Slider::new()
+        .with_range(0.0, 10.0)
+        .with_step(2.5)
+        .with_handle_width(Span { rel: 0.0, px: 0 })
+        .with_change_fn(move |_, _| changes_ref.set(changes_ref.get() + 1));
+    let ui = centered(slider.clone());
+
+    // The slider spans x = 200..600 at 800x600
+    move_to(&ui, 300.0, 300.0, SCR_RES);
+    mouse(&ui, ElementState::Pressed, SCR_RES);
+    assert_eq!(slider.get_value(), 2.5);
+    assert_eq!(changes.get(), 1);
+
+    move_to(&ui, 410.0, 300.0, SCR_RES);
+    assert_eq!(slider.get_value(), 5.0);
+    // Moving within the same step doesn't count as a change
+    move_to(&ui, 420.0, 300.0, SCR_RES);
+    assert_eq!(changes.get(), 2);
+
+    // Dragging past the ends clamps, even outside the slider
+    move_to(&ui, 790.0, 10.0, SCR_RES);
+    assert_eq!(slider.get_value(), 10.0);
+    move_to(&ui, 0.0, 300.0, SCR_RES);
+    assert_eq!(slider.get_value(), 0.0);
+
+    // Once released, moving no longer drags
+    mouse(&ui, ElementState::Released, SCR_RES);
+    move_to(&ui, 600.0, 300.0, SCR_RES);
+    assert_eq!(slider.get_value(), 0.0);
+
+    slider.set_value(7.0);
+    assert_eq!(slider.get_value(), 7.5);
+    slider.set_value(-3.0);
+    assert_eq!(slider.get_value(), 0.0);
+}
+
+#[test]
+fn focused_slider_steps_with_arrow_keys() {
+    let slider = Slider::new().with_range(0.0, 1.0).with_step(0.25);
+    let ui = centered(slider.clone());
+
+    // Arrow keys do nothing until the slider has focus
+    assert!(!ui.dispatch(&key(VirtualKeyCode::Right, false), SCR_RES));
+    assert_eq!(slider.get_value(), 0.0);
+
+    ui.set_focus(Some(slider.get_focus_id()));
+    ui.dispatch(&key(VirtualKeyCode::Right, false), SCR_RES);
+    ui.dispatch(&key(VirtualKeyCode::Right, false), SCR_RES);
+    ui.dispatch(&key(VirtualKeyCode::Left, false), SCR_RES);
+    assert_eq!(slider.get_value(), 0.25);
+}
+
+#[test]
+fn anchor_pins_child_with_pixel_margins() {
+    let anchor = Anchor::new(Vec2::new(0.0, 1.0))
+        .with_size(Span::px(200, 100))
+        .with_margin(Span::px(16, 16));
+    let screen = (Vec2::zero(), Vec2::one());
+
+    assert_bounds(
+        anchor.bounds_for_child(SCR_RES, screen),
+        (Vec2::new(0.02, 1.0 - 1.0 / 6.0 - 16.0 / 600.0), Vec2::new(0.25, 1.0 / 6.0)),
+    );
+    assert_bounds(
+        anchor.bounds_for_child(Vec2::new(1600.0, 1200.0), screen),
+        (Vec2::new(0.01, 1.0 - 1.0 / 12.0 - 16.0 / 1200.0), Vec2::new(0.125, 1.0 / 12.0)),
+    );
+}
+
+#[test]
+fn weighted_vbox_inside_anchor() {
+    let anchor = Anchor::new(Vec2::new(1.0, 0.0))
+        .with_size(Span::rel(0.5, 0.5))
+        .with_margin(Span::px(8, 6));
+    let vbox = anchor.set_child(VBox::new().with_spacing(Span { rel: 0.0, px: 12 }));
+    vbox.push_back(Rect::new());
+    vbox.push_back_weighted(Rect::new(), 2.0);
+    let screen = (Vec2::zero(), Vec2::one());
+
+    let layout = vbox.layout(SCR_RES, anchor.bounds_for_child(SCR_RES, screen));
+    assert_bounds(layout[0], (Vec2::new(0.49, 0.01), Vec2::new(0.5, 0.16)));
+    assert_bounds(layout[1], (Vec2::new(0.49, 0.19), Vec2::new(0.5, 0.32)));
+
+    let scr_res = Vec2::new(1600.0, 1200.0);
+    let layout = vbox.layout(scr_res, anchor.bounds_for_child(scr_res, screen));
+    assert_bounds(layout[0], (Vec2::new(0.495, 0.005), Vec2::new(0.5, 0.49 / 3.0)));
+    assert_bounds(layout[1], (Vec2::new(0.495, 0.015 + 0.49 / 3.0), Vec2::new(0.5, 0.98 / 3.0)));
+}
+
+#[test]
+fn spaced_hbox_inside_padding() {
+    let padding = Padding::new(Span::px(20, 30));
+    let hbox = padding.set_child(HBox::new().with_spacing(Span { rel: 0.1, px: 0 }));
+    hbox.push_back(Rect::new());
+    hbox.push_back(Rect::new());
+    let screen = (Vec2::zero(), Vec2::one());
+
+    let layout = hbox.layout(SCR_RES, padding.bounds_for_child(SCR_RES, screen));
+    assert_bounds(layout[0], (Vec2::new(0.025, 0.05), Vec2::new(0.4275, 0.9)));
+    assert_bounds(layout[1], (Vec2::new(0.5475, 0.05), Vec2::new(0.4275, 0.9)));
+
+    let scr_res = Vec2::new(1600.0, 1200.0);
+    let layout = hbox.layout(scr_res, padding.bounds_for_child(scr_res, screen));
+    assert_bounds(layout[0], (Vec2::new(0.0125, 0.025), Vec2::new(0.43875, 0.95)));
+    assert_bounds(layout[1], (Vec2::new(0.54875, 0.025), Vec2::new(0.43875, 0.95)));
+}
+
+// Lays text out with every character one unit wide
+fn wrap(spans: &[&str], max_width: f32) -> Vec<String> {
+    layout(spans, Some(max_width), |_| 1.0).iter().map(Line::text).collect()
+}
+
+#[test]
+fn wrap_empty_text() {
+    assert!(layout(&[""], Some(10.0), |_| 1.0).is_empty());
+    assert_eq!(size_of(&[], 16.0), Vec2::zero());
+}
+
+#[test]
+fn wrap_breaks_at_whitespace() {
+    assert_eq!(wrap(&["the quick brown fox"], 10.0), vec!["the quick", "brown fox"]);
+    // Lines that fit exactly aren't broken
+    assert_eq!(wrap(&["exact fit!"], 10.0), vec!["exact fit!"]);
+    assert_eq!(wrap(&["exact fit! next"], 10.0), vec!["exact fit!", "next"]);
+    assert_eq!(wrap(&["one\ntwo"], 10.0), vec!["one", "two"]);
+}
+
+#[test]
+fn wrap_hard_breaks_long_words() {
+    assert_eq!(wrap(&["abcdefghijkl"], 5.0), vec!["abcde", "fghij", "kl"]);
+    assert_eq!(wrap(&["hi abcdefgh"], 5.0), vec!["hi", "abcde", "fgh"]);
+    // There's always at least one character per line, however narrow
+    assert_eq!(wrap(&["ab"], 0.5), vec!["a", "b"]);
+}
+
+#[test]
+fn wrap_keeps_spans_apart() {
+    let lines = layout(&["Player: ", "hello there"], Some(13.0), |_| 1.0);
+    assert_eq!(lines[0].runs, vec![(0, "Player: ".to_string()), (1, "hello".to_string())]);
+    assert_eq!(lines[1].runs, vec![(1, "there".to_string())]);
+    assert_eq!(size_of(&lines, 16.0), Vec2::new(13.0, 32.0));
+}
+
+#[test]
+fn text_measurements_are_forgotten_oldest_first() {
+    let mut cache = ResCache::new();
+    let measure = |cache: &mut ResCache, text: &str, width: f32| {
+        cache.get_or_create_text_size(text, Vec2::broadcast(12.0), None, || Vec2::new(width, 12.0))
+    };
+
+    assert_eq!(measure(&mut cache, "first", 1.0), Vec2::new(1.0, 12.0));
+    // Measured once, then remembered
+    assert_eq!(measure(&mut cache, "first", 2.0), Vec2::new(1.0, 12.0));
+
+    for i in 0..5000 {
+        measure(&mut cache, &format!("{}", i), 3.0);
+    }
+    assert_eq!(cache.text_sizes_cached(), 1024);
+    assert_eq!(measure(&mut cache, "first", 2.0), Vec2::new(2.0, 12.0));
+    assert_eq!(measure(&mut cache, "4999", 4.0), Vec2::new(3.0, 12.0));
+}
@@ -0,0 +1,124 @@
+// Standard
+use std::f32;
+
+// Library
+use vek::*;
+
+/// How a piece of text is drawn. `size` is the glyph scale in pixels, so text stays sharp whatever the resolution.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    pub size: Vec2<f32>,
+    pub color: Rgba<f32>,
+}
+
+/// A run of text in its own color, used to color parts of a line differently (player names in chat, for example)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TextSpan<'a> {
+    pub text: &'a str,
+    pub color: Rgba<f32>,
+}
+
+/// A line of laid out text. Each run is the index of the span it came from along with the text from that span.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub runs: Vec<(usize, String)>,
+    pub width: f32,
+}
+
+impl Line {
+    fn new() -> Line {
+        Line {
+            runs: vec![],
+            width: 0.0,
+        }
+    }
+
+    fn push(&mut self, span: usize, c: char, advance: f32) {
+        match self.runs.last_mut() {
+            Some((last, text)) if *last == span => text.push(c),
+            _ => self.runs.push((span, c.to_string())),
+        }
+        self.width += advance;
+    }
+
+    #[allow(dead_code)]
+    pub fn text(&self) -> String { self.runs.iter().map(|(_, text)| text.as_str()).collect() }
+}
+
+/// Break the spans into lines no wider than `max_width`, using `advance` for the width of each character.
+///
+/// Lines break at whitespace where possible, and the whitespace at a break is dropped. Words too long to fit on a line
+/// of their own are broken wherever they overflow. `\n` always starts a new line. Empty text has no lines.
+pub fn layout<F: Fn(char) -> f32>(spans: &[&str], max_width: Option<f32>, advance: F) -> Vec<Line> {
+    let max_width = max_width.unwrap_or(f32::INFINITY);
+    let chars: Vec<(usize, char)> = spans
+        .iter()
+        .enumerate()
+        .flat_map(|(i, text)| text.chars().map(move |c| (i, c)))
+        .collect();
+
+    let mut lines = vec![];
+    if chars.is_empty() {
+        return lines;
+    }
+
+    let mut line = Line::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].1 == '\n' {
+            lines.push(line);
+            line = Line::new();
+            i += 1;
+            continue;
+        }
+
+        // Gather the whitespace before the next word, then the word itself
+        let space_end = chars[i..]
+            .iter()
+            .position(|(_, c)| !c.is_whitespace() || *c == '\n')
+            .map(|n| i + n)
+            .unwrap_or(chars.len());
+        let word_end = chars[space_end..]
+            .iter()
+            .position(|(_, c)| c.is_whitespace())
+            .map(|n| space_end + n)
+            .unwrap_or(chars.len());
+        let space = &chars[i..space_end];
+        let word = &chars[space_end..word_end];
+
+        let space_width: f32 = space.iter().map(|(_, c)| advance(*c)).sum();
+        let word_width: f32 = word.iter().map(|(_, c)| advance(*c)).sum();
+
+        if line.width + space_width + word_width <= max_width {
+            for &(span, c) in space.iter().chain(word) {
+                line.push(span, c, advance(c));
+            }
+        } else {
+            // Start the word on a new line unless this one is empty, dropping the whitespace at the break
+            if !line.runs.is_empty() {
+                lines.push(line);
+                line = Line::new();
+            }
+            for &(span, c) in word {
+                let width = advance(c);
+                if !line.runs.is_empty() && line.width + width > max_width {
+                    lines.push(line);
+                    line = Line::new();
+                }
+                line.push(span, c, width);
+            }
+        }
+        i = word_end;
+    }
+    lines.push(line);
+
+    lines
+}
+
+/// The size of some laid out text, with `line_height` between each line
+pub fn size_of(lines: &[Line], line_height: f32) -> Vec2<f32> {
+    Vec2::new(
+        lines.iter().map(|l| l.width).fold(0.0, f32::max),
+        lines.len() as f32 * line_height,
+    )
+}
@@ -0,0 +1,100 @@
+// Standard
+use std::{collections::HashMap, hash::Hash};
+
+/// Keeps the GPU memory taken up by models within a budget, by picking the models that have gone longest without being
+/// rendered to be dropped. Models rendered in the current frame are never picked.
+pub struct ModelBudget<K> {
+    budget: usize,
+    used: usize,
+    frame: u64,
+    // The size of each model in bytes, and the last frame it was rendered in
+    models: HashMap<K, (usize, u64)>,
+    // Models picked to be dropped that couldn't be got at yet
+    pending: Vec<K>,
+}
+
+impl<K: Copy + Eq + Hash> ModelBudget<K> {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            used: 0,
+            frame: 0,
+            models: HashMap::new(),
+            pending: vec![],
+        }
+    }
+
+    pub fn budget(&self) -> usize { self.budget }
+
+    pub fn set_budget(&mut self, budget: usize) { self.budget = budget; }
+
+    /// How many bytes the models take up
+    pub fn used(&self) -> usize { self.used }
+
+    /// Count a model that was just built, in place of any the key had before. It counts as rendered this frame.
+    pub fn insert(&mut self, key: K, bytes: usize) {
+        self.remove(key);
+        self.pending.retain(|k| *k != key);
+        self.used += bytes;
+        self.models.insert(key, (bytes, self.frame));
+    }
+
+    /// Note that a model was rendered this frame
+    pub fn touch(&mut self, key: K) {
+        if let Some((_, last)) = self.models.get_mut(&key) {
+            *last = self.frame;
+        }
+    }
+
+    /// Stop counting a model that was dropped
+    pub fn remove(&mut self, key: K) {
+        if let Some((bytes, _)) = self.models.remove(&key) {
+            self.used -= bytes;
+        }
+    }
+
+    /// Stop counting a model whose chunk was unloaded, and don't try to drop it any more
+    pub fn unload(&mut self, key: K) {
+        self.remove(key);
+        self.pending.retain(|k| *k != key);
+    }
+
+    /// Move on to the next frame, dropping models with `evict` to get back within the budget, least recently rendered
+    /// first. `evict` returns false for a model it can't get at right now, which is tried again next frame. Returns the
+    /// models that were dropped, which are no longer counted.
+    pub fn end_frame<F>(&mut self, mut evict: F) -> Vec<K>
+    where
+        F: FnMut(K) -> bool,
+    {
+        let mut picked = self.pick_evictions();
+        picked.extend(self.pending.drain(..));
+        let (evicted, pending): (Vec<K>, Vec<K>) = picked.into_iter().partition(|key| evict(*key));
+        self.pending = pending;
+        evicted
+    }
+
+    // Move on to the next frame, picking the models that should be dropped to get back within the budget
+    fn pick_evictions(&mut self) -> Vec<K> {
+        let mut evicted = vec![];
+        if self.used > self.budget {
+            let frame = self.frame;
+            let mut unseen = self
+                .models
+                .iter()
+                .filter(|(_, (_, last))| *last < frame)
+                .map(|(key, (_, last))| (*key, *last))
+                .collect::<Vec<_>>();
+            unseen.sort_by_key(|(_, last)| *last);
+
+            for (key, _) in unseen {
+                if self.used <= self.budget {
+                    break;
+                }
+                self.remove(key);
+                evicted.push(key);
+            }
+        }
+        self.frame += 1;
+        evicted
+    }
+}
@@ -8,7 +8,7 @@ use vek::*;
 type FnvIndexMap<K, V> = IndexMap<K, V, FnvBuildHasher>;
 
 // Project
-use common::terrain::Voxel;
+use common::terrain::{slice_index, Light, VoxRel, Voxel, MAX_LIGHT};
 
 // Local
 use crate::voxel::{Material, MaterialKind, RenderVolume, RenderVoxel};
@@ -57,25 +57,29 @@ gfx_defines! {
     vertex Vertex {
         pos: [f32; 3] = "vert_pos",
         attrib: u32 = "vert_attrib",
+        light: u32 = "vert_light",
     }
 }
 
 pub(super) type VertexBuffer = gfx::handle::Buffer<gfx_device_gl::Resources, Vertex>;
 
 impl Vertex {
-    pub fn new(pos: [f32; 3], norm: NormalDirection, ao: u8, palette: u16, mat: u8) -> Vertex {
+    /// `light` is the sunlight and block light reaching the vertex, each from 0 to 255
+    pub fn new(pos: [f32; 3], norm: NormalDirection, ao: u8, light: Vec2<u8>, palette: u16, mat: u8) -> Vertex {
         let attrib: u32 = 0x00000000;
         let attrib = attrib | (palette as u32 & 0xFFFF) << 0;
         let attrib = attrib | (ao as u32 & 0x0F) << 16;
         let attrib = attrib | (norm as u32 & 0x0F) << 20;
         let attrib = attrib | (mat as u32 & 0xFF) << 24;
-        Vertex { pos, attrib }
+        let light = (light.x as u32) | (light.y as u32) << 8;
+        Vertex { pos, attrib, light }
     }
 
     pub fn scale(&self, scale: Vec3<f32>) -> Vertex {
         Vertex {
             pos: [self.pos[0] * scale.x, self.pos[1] * scale.y, self.pos[2] * scale.z],
             attrib: self.attrib,
+            light: self.light,
         }
     }
 }
@@ -124,12 +128,13 @@ impl Quad {
         col: u16,
         mat: u8,
     ) -> Quad {
+        let light = vertex_light(&[Light::SUNLIT]);
         Quad {
             verts: [
-                Vertex::new(p0, norm, ao, col, mat),
-                Vertex::new(p1, norm, ao, col, mat),
-                Vertex::new(p2, norm, ao, col, mat),
-                Vertex::new(p3, norm, ao, col, mat),
+                Vertex::new(p0, norm, ao, light, col, mat),
+                Vertex::new(p1, norm, ao, light, col, mat),
+                Vertex::new(p2, norm, ao, light, col, mat),
+                Vertex::new(p3, norm, ao, light, col, mat),
             ],
         }
     }
@@ -160,152 +165,86 @@ impl Quad {
     }
 }
 
-trait GetAO {
-    fn get_ao_at(&self, pos: Vec3<i64>, dir: Vec3<i64>) -> u8;
-    fn get_ao_quad(
-        &self,
-        pos: Vec3<i64>,
-        x_unit: Vec3<i64>,
-        y_unit: Vec3<i64>,
-        z_unit: Vec3<i64>,
-        col: u16,
-        mat: u8,
-    ) -> Quad;
-}
-impl<V: RenderVolume> GetAO for V
-where
-    V::VoxelType: RenderVoxel,
-{
-    fn get_ao_at(&self, pos: Vec3<i64>, dir: Vec3<i64>) -> u8 {
-        let vecs = if dir.x == 0 {
-            if dir.y == 0 {
-                [
-                    Vec3::new(0, 0, 0),
-                    Vec3::new(-1, 0, 0),
-                    Vec3::new(0, -1, 0),
-                    Vec3::new(-1, -1, 0),
-                ]
-            } else {
-                [
-                    Vec3::new(0, 0, 0),
-                    Vec3::new(-1, 0, 0),
-                    Vec3::new(0, 0, -1),
-                    Vec3::new(-1, 0, -1),
-                ]
-            }
-        } else {
-            [
-                Vec3::new(0, 0, 0),
-                Vec3::new(0, -1, 0),
-                Vec3::new(0, 0, -1),
-                Vec3::new(0, -1, -1),
-            ]
-        };
-
-        vecs.iter()
-            .fold(0, |acc, v| {
-                acc + if self
-                    .at((pos + *v).map(|e| e as u32))
-                    .unwrap_or_else(V::VoxelType::empty)
-                    .is_opaque()
-                {
-                    0
-                } else {
-                    1
-                }
-            })
-            .min(4)
+// The ambient occlusion of one corner of a face, from the three voxels in front of the face that touch the corner. 0 is
+// fully occluded and 3 is unoccluded. A corner between two solid voxels is fully occluded whatever is diagonal to it.
+fn vertex_ao(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - (side1 as u8 + side2 as u8 + corner as u8)
     }
+}
 
-    fn get_ao_quad(
-        &self,
-        pos: Vec3<i64>,
-        x_unit: Vec3<i64>,
-        y_unit: Vec3<i64>,
-        z_unit: Vec3<i64>,
-        col: u16,
-        mat: u8,
-    ) -> Quad {
-        let units = [Vec3::new(0, 0, 0), x_unit, x_unit + y_unit, y_unit];
-
-        let ao = [
-            self.get_ao_at(pos + units[0], z_unit),
-            self.get_ao_at(pos + units[1], z_unit),
-            self.get_ao_at(pos + units[2], z_unit),
-            self.get_ao_at(pos + units[3], z_unit),
-        ];
-
-        const AO_MAP: [u8; 5] = [0, 1, 3, 3, 4];
-        let ao_vals = [
-            AO_MAP[ao[0] as usize],
-            AO_MAP[ao[1] as usize],
-            AO_MAP[ao[2] as usize],
-            AO_MAP[ao[3] as usize],
-        ];
+// The light of a vertex, from the light of the voxels around it, scaled to 0 - 255
+fn vertex_light(samples: &[Light]) -> Vec2<u8> {
+    let scale = |sum: u32| (sum * 255 / (samples.len() as u32 * MAX_LIGHT as u32)) as u8;
+    Vec2::new(
+        scale(samples.iter().map(|l| l.sun as u32).sum()),
+        scale(samples.iter().map(|l| l.block as u32).sum()),
+    )
+}
 
-        if (ao[0] as i32 - ao[2] as i32).abs() < (ao[1] as i32 - ao[3] as i32).abs() {
-            Quad::new(
-                Vertex::new(
-                    units[0].map(|e| e as f32).into_array(),
-                    z_unit.into(),
-                    ao_vals[0],
-                    col,
-                    mat,
-                ),
-                Vertex::new(
-                    units[1].map(|e| e as f32).into_array(),
-                    z_unit.into(),
-                    ao_vals[1],
-                    col,
-                    mat,
-                ),
-                Vertex::new(
-                    units[2].map(|e| e as f32).into_array(),
-                    z_unit.into(),
-                    ao_vals[2],
-                    col,
-                    mat,
-                ),
-                Vertex::new(
-                    units[3].map(|e| e as f32).into_array(),
-                    z_unit.into(),
-                    ao_vals[3],
-                    col,
-                    mat,
-                ),
-            )
-        } else {
-            Quad::new(
-                Vertex::new(
-                    units[1].map(|e| e as f32).into_array(),
-                    z_unit.into(),
-                    ao_vals[1],
-                    col,
-                    mat,
-                ),
-                Vertex::new(
-                    units[2].map(|e| e as f32).into_array(),
-                    z_unit.into(),
-                    ao_vals[2],
-                    col,
-                    mat,
-                ),
-                Vertex::new(
-                    units[3].map(|e| e as f32).into_array(),
-                    z_unit.into(),
-                    ao_vals[3],
-                    col,
-                    mat,
-                ),
-                Vertex::new(
-                    units[0].map(|e| e as f32).into_array(),
-                    z_unit.into(),
-                    ao_vals[0],
-                    col,
-                    mat,
-                ),
-            )
+// A face of a voxel, shaded by the voxels around it and lit by the light in front of it. `pos` is the voxel in front of
+// the face, the face spans `x_unit` and `y_unit`, and `z_unit` points out of it.
+fn get_ao_quad<T: RenderVoxel>(
+    get: &impl Fn(Vec3<i64>) -> Option<T>,
+    light: &impl Fn(Vec3<i64>) -> Light,
+    pos: Vec3<i64>,
+    x_unit: Vec3<i64>,
+    y_unit: Vec3<i64>,
+    z_unit: Vec3<i64>,
+    col: u16,
+    mat: u8,
+) -> Quad {
+    let units = [Vec3::new(0, 0, 0), x_unit, x_unit + y_unit, y_unit];
+    let solid = |off: Vec3<i64>| get(pos + off).map(|v| v.is_opaque()).unwrap_or(false);
+    // Step from the voxel in front of the face towards a corner along each of the face's axes
+    let towards = |unit: Vec3<i64>| {
+        let dx = if unit.dot(x_unit) > 0 { x_unit } else { -x_unit };
+        let dy = if unit.dot(y_unit) > 0 { y_unit } else { -y_unit };
+        (dx, dy)
+    };
+    let corner_ao = |unit: Vec3<i64>| {
+        let (dx, dy) = towards(unit);
+        vertex_ao(solid(dx), solid(dy), solid(dx + dy))
+    };
+    // Light is averaged over the voxels touching the corner that it can reach, so that it fades smoothly across faces
+    let corner_light = |unit: Vec3<i64>| {
+        let (dx, dy) = towards(unit);
+        let mut samples = vec![light(pos)];
+        for off in [dx, dy].iter() {
+            if !solid(*off) {
+                samples.push(light(pos + *off));
+            }
+        }
+        if !solid(dx + dy) && !(solid(dx) && solid(dy)) {
+            samples.push(light(pos + dx + dy));
         }
+        vertex_light(&samples)
+    };
+    let ao = [
+        corner_ao(units[0]),
+        corner_ao(units[1]),
+        corner_ao(units[2]),
+        corner_ao(units[3]),
+    ];
+    let lights = [
+        corner_light(units[0]),
+        corner_light(units[1]),
+        corner_light(units[2]),
+        corner_light(units[3]),
+    ];
+
+    let vert = |i: usize| {
+        let pos = units[i].map(|e| e as f32).into_array();
+        Vertex::new(pos, z_unit.into(), ao[i], lights[i], col, mat)
+    };
+    // Split the quad along the diagonal between its darker corners. Otherwise, the occlusion of a lone dark corner is
+    // interpolated across only one of the quad's triangles, which shows up as a crease.
+    if ao[0] + ao[2] > ao[1] + ao[3] {
+        Quad::new(vert(1), vert(2), vert(3), vert(0))
+    } else {
+        Quad::new(vert(0), vert(1), vert(2), vert(3))
     }
 }
 
@@ -323,6 +262,7 @@ impl Mesh {
         Mesh::from_with_offset(vol, Vec3::new(0.0, 0.0, 0.0), true)
     }
 
+    /// Mesh a volume by itself, e.g: a figure. It's lit as if it stood in the open.
     pub fn from_with_offset<V: RenderVolume>(
         vol: &V,
         offs: Vec3<f32>,
@@ -331,15 +271,56 @@ impl Mesh {
     where
         V::VoxelType: RenderVoxel,
     {
+        Mesh::build(vol, offs, |_| None, |_| Light::SUNLIT)
+    }
+
+    /// Mesh a volume that sits among others, such as a chunk of terrain. `outside` is asked for the voxels beyond the
+    /// volume's edges, so that faces hidden by a neighbouring volume are left out and ambient occlusion carries across
+    /// the border. Voxels it doesn't know of are treated as empty. `light` is asked for the light of voxels in front of
+    /// faces, both in the volume and beyond it, with positions relative to the volume like `outside`.
+    pub fn from_with_neighbours<V, F, L>(vol: &V, outside: F, light: L) -> FnvIndexMap<MaterialKind, Mesh>
+    where
+        V: RenderVolume,
+        V::VoxelType: RenderVoxel,
+        F: Fn(Vec3<i64>) -> Option<V::VoxelType>,
+        L: Fn(Vec3<i64>) -> Light,
+    {
+        Mesh::build(vol, Vec3::new(0.0, 0.0, 0.0), outside, light)
+    }
+
+    fn build<V, F, L>(vol: &V, offs: Vec3<f32>, outside: F, light: L) -> FnvIndexMap<MaterialKind, Mesh>
+    where
+        V: RenderVolume,
+        V::VoxelType: RenderVoxel,
+        F: Fn(Vec3<i64>) -> Option<V::VoxelType>,
+        L: Fn(Vec3<i64>) -> Light,
+    {
+        // Every voxel is looked at several times over, so copy them all out in one go first
+        let size = vol.size();
+        let mut voxels = vec![V::VoxelType::empty(); size.map(|e| e as usize).product()];
+        vol.fill_slice(Vec3::new(0, 0, 0), size, &mut voxels);
+        let inside = |pos: Vec3<i64>| {
+            pos.x >= 0
+                && pos.y >= 0
+                && pos.z >= 0
+                && pos.x < size.x as i64
+                && pos.y < size.y as i64
+                && pos.z < size.z as i64
+        };
+        let get = |pos: Vec3<i64>| {
+            if inside(pos) {
+                Some(voxels[slice_index(pos.map(|e| e as VoxRel), size)])
+            } else {
+                outside(pos)
+            }
+        };
         let mut map = FnvIndexMap::with_capacity_and_hasher(4, Default::default());
         let scale = vol.scale();
 
-        for x in 0i64..vol.size().x as i64 {
-            for y in 0i64..vol.size().y as i64 {
-                for z in 0i64..vol.size().z as i64 {
-                    let vox = vol
-                        .at_conv(Vec3::new(x, y, z))
-                        .expect("Attempted to mesh voxel outside volume");
+        for x in 0i64..size.x as i64 {
+            for y in 0i64..size.y as i64 {
+                for z in 0i64..size.z as i64 {
+                    let vox = voxels[slice_index(Vec3::new(x, y, z).map(|e| e as VoxRel), size)];
                     let offset = Vec3::new(
                         (x as f32 + offs.x) * scale.x,
                         (y as f32 + offs.y) * scale.y,
@@ -358,112 +339,112 @@ impl Mesh {
                     if vox.is_occupied() {
                         let opaque = vox.is_opaque();
                         // +x
-                        if vol
-                            .at_conv(Vec3::new(x + 1, y, z))
+                        if get(Vec3::new(x + 1, y, z))
                             .map(|v| v.should_add(opaque))
                             .unwrap_or(!fake_optimize)
                         {
-                            mesh.add_quads(&[vol
-                                .get_ao_quad(
-                                    Vec3::new(x + 1, y + 0, z + 0),
-                                    Vec3::new(0, 1, 0),
-                                    Vec3::new(0, 0, 1),
-                                    Vec3::new(1, 0, 0),
-                                    palette,
-                                    mat,
-                                )
-                                .scale(Vec3::new(scale.x, scale.y, scale.z))
-                                .with_offset([offset.x + scale.x, offset.y, offset.z])]);
+                            mesh.add_quads(&[get_ao_quad(
+                                &get,
+                                &light,
+                                Vec3::new(x + 1, y + 0, z + 0),
+                                Vec3::new(0, 1, 0),
+                                Vec3::new(0, 0, 1),
+                                Vec3::new(1, 0, 0),
+                                palette,
+                                mat,
+                            )
+                            .scale(Vec3::new(scale.x, scale.y, scale.z))
+                            .with_offset([offset.x + scale.x, offset.y, offset.z])]);
                         }
                         // -x
-                        if vol
-                            .at_conv(Vec3::new(x - 1, y, z))
+                        if get(Vec3::new(x - 1, y, z))
                             .map(|v| v.should_add(opaque))
                             .unwrap_or(!fake_optimize)
                         {
-                            mesh.add_quads(&[vol
-                                .get_ao_quad(
-                                    Vec3::new(x - 1, y + 0, z + 0),
-                                    Vec3::new(0, 0, 1),
-                                    Vec3::new(0, 1, 0),
-                                    Vec3::new(-1, 0, 0),
-                                    palette,
-                                    mat,
-                                )
-                                .scale(Vec3::new(scale.x, scale.y, scale.z))
-                                .with_offset([offset.x, offset.y, offset.z])]);
+                            mesh.add_quads(&[get_ao_quad(
+                                &get,
+                                &light,
+                                Vec3::new(x - 1, y + 0, z + 0),
+                                Vec3::new(0, 0, 1),
+                                Vec3::new(0, 1, 0),
+                                Vec3::new(-1, 0, 0),
+                                palette,
+                                mat,
+                            )
+                            .scale(Vec3::new(scale.x, scale.y, scale.z))
+                            .with_offset([offset.x, offset.y, offset.z])]);
                         }
                         // +y
-                        if vol
-                            .at_conv(Vec3::new(x, y + 1, z))
+                        if get(Vec3::new(x, y + 1, z))
                             .map(|v| v.should_add(opaque))
                             .unwrap_or(!fake_optimize)
                         {
-                            mesh.add_quads(&[vol
-                                .get_ao_quad(
-                                    Vec3::new(x + 0, y + 1, z + 0),
-                                    Vec3::new(0, 0, 1),
-                                    Vec3::new(1, 0, 0),
-                                    Vec3::new(0, 1, 0),
-                                    palette,
-                                    mat,
-                                )
-                                .scale(Vec3::new(scale.x, scale.y, scale.z))
-                                .with_offset([offset.x, offset.y + scale.y, offset.z])]);
+                            mesh.add_quads(&[get_ao_quad(
+                                &get,
+                                &light,
+                                Vec3::new(x + 0, y + 1, z + 0),
+                                Vec3::new(0, 0, 1),
+                                Vec3::new(1, 0, 0),
+                                Vec3::new(0, 1, 0),
+                                palette,
+                                mat,
+                            )
+                            .scale(Vec3::new(scale.x, scale.y, scale.z))
+                            .with_offset([offset.x, offset.y + scale.y, offset.z])]);
                         }
                         // -y
-                        if vol
-                            .at_conv(Vec3::new(x, y - 1, z))
+                        if get(Vec3::new(x, y - 1, z))
                             .map(|v| v.should_add(opaque))
                             .unwrap_or(!fake_optimize)
                         {
-                            mesh.add_quads(&[vol
-                                .get_ao_quad(
-                                    Vec3::new(x + 0, y - 1, z + 0),
-                                    Vec3::new(1, 0, 0),
-                                    Vec3::new(0, 0, 1),
-                                    Vec3::new(0, -1, 0),
-                                    palette,
-                                    mat,
-                                )
-                                .scale(Vec3::new(scale.x, scale.y, scale.z))
-                                .with_offset([offset.x, offset.y, offset.z])]);
+                            mesh.add_quads(&[get_ao_quad(
+                                &get,
+                                &light,
+                                Vec3::new(x + 0, y - 1, z + 0),
+                                Vec3::new(1, 0, 0),
+                                Vec3::new(0, 0, 1),
+                                Vec3::new(0, -1, 0),
+                                palette,
+                                mat,
+                            )
+                            .scale(Vec3::new(scale.x, scale.y, scale.z))
+                            .with_offset([offset.x, offset.y, offset.z])]);
                         }
                         // +z
-                        if vol
-                            .at_conv(Vec3::new(x, y, z + 1))
+                        if get(Vec3::new(x, y, z + 1))
                             .map(|v| v.should_add(opaque))
                             .unwrap_or(!fake_optimize)
                         {
-                            mesh.add_quads(&[vol
-                                .get_ao_quad(
-                                    Vec3::new(x + 0, y + 0, z + 1),
-                                    Vec3::new(1, 0, 0),
-                                    Vec3::new(0, 1, 0),
-                                    Vec3::new(0, 0, 1),
-                                    palette,
-                                    mat,
-                                )
-                                .scale(Vec3::new(scale.x, scale.y, scale.z))
-                                .with_offset([offset.x, offset.y, offset.z + scale.z])]);
+                            mesh.add_quads(&[get_ao_quad(
+                                &get,
+                                &light,
+                                Vec3::new(x + 0, y + 0, z + 1),
+                                Vec3::new(1, 0, 0),
+                                Vec3::new(0, 1, 0),
+                                Vec3::new(0, 0, 1),
+                                palette,
+                                mat,
+                            )
+                            .scale(Vec3::new(scale.x, scale.y, scale.z))
+                            .with_offset([offset.x, offset.y, offset.z + scale.z])]);
                         }
                         // -z
-                        if vol
-                            .at_conv(Vec3::new(x, y, z - 1))
+                        if get(Vec3::new(x, y, z - 1))
                             .map(|v| v.should_add(opaque))
                             .unwrap_or(!fake_optimize)
                         {
-                            mesh.add_quads(&[vol
-                                .get_ao_quad(
-                                    Vec3::new(x + 0, y + 0, z - 1),
-                                    Vec3::new(0, 1, 0),
-                                    Vec3::new(1, 0, 0),
-                                    Vec3::new(0, 0, -1),
-                                    palette,
-                                    mat,
-                                )
-                                .scale(Vec3::new(scale.x, scale.y, scale.z))
-                                .with_offset([offset.x, offset.y, offset.z])]);
+                            mesh.add_quads(&[get_ao_quad(
+                                &get,
+                                &light,
+                                Vec3::new(x + 0, y + 0, z - 1),
+                                Vec3::new(0, 1, 0),
+                                Vec3::new(1, 0, 0),
+                                Vec3::new(0, 0, -1),
+                                palette,
+                                mat,
+                            )
+                            .scale(Vec3::new(scale.x, scale.y, scale.z))
+                            .with_offset([offset.x, offset.y, offset.z])]);
                         }
                     }
                 }
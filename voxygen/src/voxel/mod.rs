@@ -1,15 +1,19 @@
+mod budget;
 mod material;
 mod mesh;
 mod model;
 mod pipeline;
 mod render_volume;
+#[cfg(test)]
+mod tests;
 mod vox;
 
 // Reexports
 pub use self::{
+    budget::ModelBudget,
     material::{Material, MaterialKind, RenderMaterial},
     mesh::{Mesh, Vertex},
-    model::{Model, ModelConsts},
+    model::{InstanceData, InstancedModel, Model, ModelConsts},
     pipeline::VolumePipeline,
     render_volume::{RenderVolume, RenderVoxel},
     vox::vox_to_figure,
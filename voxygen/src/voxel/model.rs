@@ -1,28 +1,59 @@
+use std::mem;
+
 use fnv::FnvBuildHasher;
-use gfx::{traits::FactoryExt, IndexBuffer, Slice};
+use gfx::{self, traits::FactoryExt, IndexBuffer, Slice};
 use gfx_device_gl;
 use indexmap::IndexMap;
+use vek::*;
 
 type FnvIndexMap<K, V> = IndexMap<K, V, FnvBuildHasher>;
 
 use crate::{
+    buffer::DynamicBuffer,
     renderer::Renderer,
-    voxel::{mesh::VertexBuffer, MaterialKind, Mesh},
+    voxel::{mesh::VertexBuffer, MaterialKind, Mesh, Vertex},
 };
 
 gfx_defines! {
     constant ModelConsts {
         model_mat: [[f32; 4]; 4] = "model_mat",
     }
+
+    vertex InstanceData {
+        // The columns of the instance's model matrix
+        mat0: [f32; 4] = "inst_mat0",
+        mat1: [f32; 4] = "inst_mat1",
+        mat2: [f32; 4] = "inst_mat2",
+        mat3: [f32; 4] = "inst_mat3",
+        // What the instance's colour is multiplied by
+        tint: [f32; 4] = "inst_tint",
+    }
+}
+
+pub(super) type InstanceBuffer = gfx::handle::Buffer<gfx_device_gl::Resources, InstanceData>;
+
+impl InstanceData {
+    pub fn new(model_mat: Mat4<f32>, tint: Rgba<f32>) -> InstanceData {
+        let col = |i| [model_mat[(0, i)], model_mat[(1, i)], model_mat[(2, i)], model_mat[(3, i)]];
+        InstanceData {
+            mat0: col(0),
+            mat1: col(1),
+            mat2: col(2),
+            mat3: col(3),
+            tint: tint.into_array(),
+        }
+    }
 }
 
 pub struct Model {
     vbufs: FnvIndexMap<MaterialKind, (VertexBuffer, Slice<gfx_device_gl::Resources>)>,
+    bytes: usize,
 }
 
 impl Model {
     pub fn new(renderer: &mut Renderer, meshes: &FnvIndexMap<MaterialKind, Mesh>) -> Model {
         let mut vbufs = FnvIndexMap::with_capacity_and_hasher(4, Default::default());
+        let mut bytes = 0;
 
         meshes
             .iter()
@@ -39,11 +70,41 @@ impl Model {
                 };
 
                 vbufs.insert(*mat, (vbuf, slice));
+                bytes += mesh.vert_count() as usize * mem::size_of::<Vertex>();
             });
-        Model { vbufs }
+        Model { vbufs, bytes }
     }
 
+    /// Roughly how much GPU memory the model takes up
+    pub fn bytes(&self) -> usize { self.bytes }
+
     pub(super) fn vbufs(&self) -> &FnvIndexMap<MaterialKind, (VertexBuffer, Slice<gfx_device_gl::Resources>)> {
         &self.vbufs
     }
 }
+
+/// A model that can be drawn many times over in one go, once for each instance it's given. It can be drawn once on its
+/// own too, like any other model.
+pub struct InstancedModel {
+    model: Model,
+    instances: DynamicBuffer<InstanceData>,
+}
+
+impl InstancedModel {
+    pub fn new(model: Model) -> InstancedModel {
+        InstancedModel {
+            model,
+            instances: DynamicBuffer::new(),
+        }
+    }
+
+    pub fn model(&self) -> &Model { &self.model }
+
+    /// Replace the instances with `instances`. What's drawn can move every frame, so this is done every frame, into the
+    /// same buffer unless they no longer fit in it.
+    pub(super) fn set_instances(&mut self, renderer: &mut Renderer, instances: &[InstanceData]) {
+        self.instances.update(renderer, instances);
+    }
+
+    pub(super) fn instances(&self) -> Option<(&InstanceBuffer, u32)> { self.instances.get() }
+}
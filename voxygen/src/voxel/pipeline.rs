@@ -1,8 +1,11 @@
+use std::{collections::HashSet, path::PathBuf};
+
 use crate::get_shader_path;
 use fnv::FnvBuildHasher;
 use gfx::{self, Primitive, Slice};
 use gfx_device_gl;
 use indexmap::IndexMap;
+use vek::*;
 
 type FnvIndexMap<K, V> = IndexMap<K, V, FnvBuildHasher>;
 
@@ -11,11 +14,16 @@ use crate::{
     pipeline::Pipeline,
     renderer::{HdrDepthFormat, HdrFormat, Renderer},
     shader::Shader,
-    voxel::{mesh::VertexBuffer, MaterialKind, Model, ModelConsts, Vertex},
+    voxel::{
+        mesh::VertexBuffer,
+        model::{InstanceBuffer, InstanceData, InstancedModel},
+        MaterialKind, Model, ModelConsts, Vertex,
+    },
 };
 
 type VoxelPipelineData = voxel_pipeline::Data<gfx_device_gl::Resources>;
 type WaterPipelineData = water_pipeline::Data<gfx_device_gl::Resources>;
+type InstancedPipelineData = instanced_pipeline::Data<gfx_device_gl::Resources>;
 
 gfx_defines! {
     pipeline voxel_pipeline {
@@ -33,6 +41,16 @@ gfx_defines! {
         out_color: gfx::BlendTarget<HdrFormat> = ("target", gfx::state::ColorMask::all(), gfx::preset::blend::ALPHA),
         out_depth: gfx::DepthTarget<HdrDepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
     }
+
+    // Shares the voxel fragment shader, with the model constants left as the identity
+    pipeline instanced_pipeline {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        instances: gfx::InstanceBuffer<InstanceData> = (),
+        model_consts: gfx::ConstantBuffer<ModelConsts> = "model_consts",
+        global_consts: gfx::ConstantBuffer<GlobalConsts> = "global_consts",
+        out_color: gfx::BlendTarget<HdrFormat> = ("target", gfx::state::ColorMask::all(), gfx::preset::blend::ALPHA),
+        out_depth: gfx::DepthTarget<HdrDepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+    }
 }
 
 struct DrawPacket {
@@ -40,12 +58,19 @@ struct DrawPacket {
     slice: Slice<gfx_device_gl::Resources>,
     model_consts: gfx::handle::Buffer<gfx_device_gl::Resources, ModelConsts>,
     global_consts: gfx::handle::Buffer<gfx_device_gl::Resources, GlobalConsts>,
+    // Set for models drawn once for each instance in the buffer
+    instances: Option<InstanceBuffer>,
 }
 
 pub struct VolumePipeline {
     voxel_pipeline: Pipeline<voxel_pipeline::Init<'static>>,
     water_pipeline: Pipeline<water_pipeline::Init<'static>>,
+    instanced_pipeline: Pipeline<instanced_pipeline::Init<'static>>,
+    // The identity, for the fragment shader of instanced models, which are placed by their instances instead
+    identity_consts: ConstHandle<ModelConsts>,
     draw_queue: FnvIndexMap<MaterialKind, Vec<DrawPacket>>,
+    // How many draw calls the last flush made
+    draw_calls: usize,
 }
 
 impl VolumePipeline {
@@ -64,13 +89,48 @@ impl VolumePipeline {
             &Shader::from_file(get_shader_path("voxel/water.frag")).expect("Could not load voxel fragment shader"),
         );
 
+        let instanced_pipeline = Pipeline::new(
+            renderer.factory_mut(),
+            instanced_pipeline::new(),
+            &Shader::from_file(get_shader_path("voxel/instanced.vert"))
+                .expect("Could not load instanced vertex shader"),
+            &Shader::from_file(get_shader_path("voxel/voxel.frag")).expect("Could not load voxel fragment shader"),
+        );
+
+        let identity_consts = ConstHandle::new(renderer);
+        identity_consts.update(
+            renderer,
+            ModelConsts {
+                model_mat: Mat4::<f32>::identity().into_col_arrays(),
+            },
+        );
+
         VolumePipeline {
             voxel_pipeline,
             water_pipeline,
+            instanced_pipeline,
+            identity_consts,
             draw_queue: FnvIndexMap::with_capacity_and_hasher(4, Default::default()),
+            draw_calls: 0,
         }
     }
 
+    pub fn reload_if_changed(&mut self, renderer: &mut Renderer, changed: &HashSet<PathBuf>) {
+        self.voxel_pipeline.reload_if_changed(renderer.factory_mut(), changed);
+        self.water_pipeline.reload_if_changed(renderer.factory_mut(), changed);
+        self.instanced_pipeline.reload_if_changed(renderer.factory_mut(), changed);
+    }
+
+    pub fn sources(&self) -> Vec<PathBuf> {
+        let mut sources = self.voxel_pipeline.sources().to_vec();
+        sources.extend_from_slice(self.water_pipeline.sources());
+        sources.extend_from_slice(self.instanced_pipeline.sources());
+        sources
+    }
+
+    /// How many draw calls drawing the volumes took last frame
+    pub fn draw_calls(&self) -> usize { self.draw_calls }
+
     pub fn draw_model(
         &mut self,
         model: &Model,
@@ -87,44 +147,93 @@ impl VolumePipeline {
                     slice: slice.clone(),
                     model_consts: model_consts.buffer().clone(),
                     global_consts: global_consts.buffer().clone(),
+                    instances: None,
                 })
             }
         });
     }
 
+    /// Draw `model` once for each of `instances`, with a single draw call for each of its materials. Draws only happen
+    /// at `flush`, so a model can only be drawn this way once a frame: drawing it again replaces its instances.
+    pub fn draw_instanced(
+        &mut self,
+        renderer: &mut Renderer,
+        model: &mut InstancedModel,
+        instances: &[InstanceData],
+        global_consts: &ConstHandle<GlobalConsts>,
+    ) {
+        model.set_instances(renderer, instances);
+        let (buffer, count) = match model.instances() {
+            Some((buffer, count)) => (buffer.clone(), count),
+            None => return,
+        };
+        for (mat, (vbuf, slice)) in model.model().vbufs().iter() {
+            if slice.get_prim_count(Primitive::TriangleList) > 0 {
+                self.draw_queue.entry(*mat).or_insert(Vec::new()).push(DrawPacket {
+                    vbuf: vbuf.clone(),
+                    slice: Slice {
+                        instances: Some((count, 0)),
+                        ..slice.clone()
+                    },
+                    model_consts: self.identity_consts.buffer().clone(),
+                    global_consts: global_consts.buffer().clone(),
+                    instances: Some(buffer.clone()),
+                });
+            }
+        }
+    }
+
     pub fn flush(&mut self, renderer: &mut Renderer) {
         let out_color = renderer.hdr_render_view().clone();
         let out_depth = renderer.hdr_depth_view().clone();
         let encoder = renderer.encoder_mut();
         let vox_pso = self.voxel_pipeline.pso();
         let water_pso = self.water_pipeline.pso();
+        let instanced_pso = self.instanced_pipeline.pso();
+        let mut draw_calls = 0;
         // Sort the draw queue by draw priority. Solid -> Translucent -> Water
         self.draw_queue.sort_keys();
         // Iterate the sorted queue and draw the contained DrawPackets for each kind
         self.draw_queue.iter_mut().for_each(|(mat, ref mut packets)| {
             // Drain the vector of packets so they don't carry over to the next frame
-            packets.drain(..).for_each(|packet| match *mat {
-                MaterialKind::Water => {
-                    let pipe_data = &WaterPipelineData {
-                        vbuf: packet.vbuf,
-                        model_consts: packet.model_consts,
-                        global_consts: packet.global_consts,
-                        out_color: out_color.clone(),
-                        out_depth: out_depth.clone(),
-                    };
-                    encoder.draw(&packet.slice, water_pso, pipe_data);
-                },
-                _ => {
-                    let pipe_data = &VoxelPipelineData {
-                        vbuf: packet.vbuf,
-                        model_consts: packet.model_consts,
-                        global_consts: packet.global_consts,
-                        out_color: out_color.clone(),
-                        out_depth: out_depth.clone(),
-                    };
-                    encoder.draw(&packet.slice, vox_pso, pipe_data);
-                },
+            packets.drain(..).for_each(|packet| {
+                draw_calls += 1;
+                match (*mat, packet.instances) {
+                    // Instanced models are drawn with the voxel shader whatever they're made of, water included
+                    (_, Some(instances)) => {
+                        let pipe_data = &InstancedPipelineData {
+                            vbuf: packet.vbuf,
+                            instances,
+                            model_consts: packet.model_consts,
+                            global_consts: packet.global_consts,
+                            out_color: out_color.clone(),
+                            out_depth: out_depth.clone(),
+                        };
+                        encoder.draw(&packet.slice, instanced_pso, pipe_data);
+                    },
+                    (MaterialKind::Water, None) => {
+                        let pipe_data = &WaterPipelineData {
+                            vbuf: packet.vbuf,
+                            model_consts: packet.model_consts,
+                            global_consts: packet.global_consts,
+                            out_color: out_color.clone(),
+                            out_depth: out_depth.clone(),
+                        };
+                        encoder.draw(&packet.slice, water_pso, pipe_data);
+                    },
+                    _ => {
+                        let pipe_data = &VoxelPipelineData {
+                            vbuf: packet.vbuf,
+                            model_consts: packet.model_consts,
+                            global_consts: packet.global_consts,
+                            out_color: out_color.clone(),
+                            out_depth: out_depth.clone(),
+                        };
+                        encoder.draw(&packet.slice, vox_pso, pipe_data);
+                    },
+                }
             });
         });
+        self.draw_calls = draw_calls;
     }
 }
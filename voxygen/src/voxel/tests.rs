@@ -0,0 +1,298 @@
+// Standard
+use std::collections::HashSet;
+
+// Library
+use test::Bencher;
+use vek::*;
+
+// Project
+use common::terrain::{
+    chunk::{Block, HeterogeneousData, CHUNK_SIZE},
+    ConstructVolume, Light, PhysicalVolume, ReadVolume, ReadWriteVolume, Volume, VoxRel,
+};
+
+// Local
+use super::{InstanceData, Mesh, ModelBudget, Vertex};
+use crate::figure::item_mat;
+
+fn ao(vert: &Vertex) -> u8 { ((vert.attrib >> 16) & 0x0F) as u8 }
+
+fn is_up(vert: &Vertex) -> bool { (vert.attrib >> 20) & 0x0F == 4 }
+
+fn block_light(vert: &Vertex) -> u32 { (vert.light >> 8) & 0xFF }
+
+// A volume with a stone floor at z = 0 and the given blocks on top of it
+fn floor_with(size: Vec3<u32>, blocks: &[Vec3<u32>]) -> HeterogeneousData {
+    let mut vol = HeterogeneousData::filled(size, Block::AIR);
+    for x in 0..size.x {
+        for y in 0..size.y {
+            vol.set_at(Vec3::new(x, y, 0), Block::STONE);
+        }
+    }
+    for pos in blocks {
+        vol.set_at(*pos, Block::STONE);
+    }
+    vol
+}
+
+// The six vertices of the upward face on top of the floor block at (x, y)
+fn top_face(meshes: &[Vertex], x: f32, y: f32) -> Vec<Vertex> {
+    meshes
+        .chunks(6)
+        .find(|quad| {
+            quad.iter().all(|v| {
+                let pos = Vec3::from(v.pos);
+                is_up(v) && pos.z == 1.0 && pos.x >= x && pos.x <= x + 1.0 && pos.y >= y && pos.y <= y + 1.0
+            })
+        })
+        .expect("No such face")
+        .to_vec()
+}
+
+fn ao_at(face: &[Vertex], x: f32, y: f32) -> u8 {
+    ao(face.iter().find(|v| v.pos[0] == x && v.pos[1] == y).expect("No such vertex"))
+}
+
+fn verts(vol: &HeterogeneousData) -> Vec<Vertex> {
+    Mesh::from(vol).values().flat_map(|mesh| mesh.vertices().clone()).collect()
+}
+
+#[test]
+fn flat_ground_is_unoccluded() {
+    let face = top_face(&verts(&floor_with(Vec3::new(3, 3, 2), &[])), 1.0, 1.0);
+    assert!(face.iter().all(|v| ao(v) == 3));
+}
+
+#[test]
+fn inside_corners_are_occluded() {
+    // Walls along x = 0 and y = 2, meeting in a corner
+    let walls = (0..3)
+        .map(|i| Vec3::new(0, i, 1))
+        .chain((0..3).map(|i| Vec3::new(i, 2, 1)))
+        .collect::<Vec<_>>();
+    let face = top_face(&verts(&floor_with(Vec3::new(3, 3, 2), &walls)), 1.0, 1.0);
+
+    assert_eq!(ao_at(&face, 1.0, 2.0), 0); // In the corner
+    assert_eq!(ao_at(&face, 2.0, 2.0), 1); // Along one wall
+    assert_eq!(ao_at(&face, 1.0, 1.0), 1); // Along the other wall
+    assert_eq!(ao_at(&face, 2.0, 1.0), 3); // In the open
+}
+
+#[test]
+fn lone_occluded_corner_lies_on_the_diagonal() {
+    let face = top_face(&verts(&floor_with(Vec3::new(3, 3, 2), &[Vec3::new(0, 2, 1)])), 1.0, 1.0);
+    assert_eq!(ao_at(&face, 1.0, 2.0), 2);
+
+    // Both triangles share the diagonal's vertices, so the darkening spreads evenly over the face
+    let dark = face.iter().filter(|v| ao(v) == 2).count();
+    assert_eq!(dark, 2);
+}
+
+#[test]
+fn neighbours_hide_faces_and_occlude() {
+    let vol = floor_with(Vec3::new(1, 1, 2), &[]);
+    // The floor carries on into the neighbouring volume at x = -1, with a wall on top of it
+    let meshes = Mesh::from_with_neighbours(
+        &vol,
+        |pos: Vec3<i64>| match (pos.x, pos.z) {
+            (-1, 0) | (-1, 1) => Some(Block::STONE),
+            _ => None,
+        },
+        |_| Light::SUNLIT,
+    );
+    let verts = meshes.values().flat_map(|mesh| mesh.vertices().clone()).collect::<Vec<_>>();
+
+    // The side facing the neighbour is hidden...
+    assert!(!verts.iter().any(|v| (v.attrib >> 20) & 0x0F == 1)); // Facing -x
+    // ...and the wall shades the top
+    let face = top_face(&verts, 0.0, 0.0);
+    assert_eq!(ao_at(&face, 0.0, 0.0), 1);
+    assert_eq!(ao_at(&face, 1.0, 0.0), 3);
+}
+
+#[test]
+fn solid_chunks_leave_no_faces_between_them() {
+    let size = Vec3::new(2, 2, 2);
+    let (left, right) = (
+        HeterogeneousData::filled(size, Block::STONE),
+        HeterogeneousData::filled(size, Block::STONE),
+    );
+    // Each volume sees the other through its border, as chunks lying side by side along x do
+    let mesh_beside = |vol: &HeterogeneousData, other: &HeterogeneousData, dx: i64| {
+        let meshes = Mesh::from_with_neighbours(
+            vol,
+            |pos: Vec3<i64>| {
+                let rel = pos - Vec3::new(dx, 0, 0);
+                if rel.map2(size, |e, s| e >= 0 && e < s as i64).into_array().iter().all(|inside| *inside) {
+                    other.at(rel.map(|e| e as u32))
+                } else {
+                    None
+                }
+            },
+            |_| Light::SUNLIT,
+        );
+        meshes.values().flat_map(|mesh| mesh.vertices().clone()).collect::<Vec<_>>()
+    };
+    let facing = |verts: &[Vertex], norm: u32| verts.iter().filter(|v| (v.attrib >> 20) & 0x0F == norm).count();
+
+    let (left_verts, right_verts) = (mesh_beside(&left, &right, 2), mesh_beside(&right, &left, -2));
+    assert_eq!(facing(&left_verts, 0), 0); // Facing +x, into the right chunk
+    assert_eq!(facing(&right_verts, 1), 0); // Facing -x, into the left chunk
+    // The sides facing away from each other are still there
+    assert!(facing(&left_verts, 1) > 0);
+    assert!(facing(&right_verts, 0) > 0);
+}
+
+#[test]
+fn light_fades_smoothly_across_faces() {
+    let vol = floor_with(Vec3::new(3, 3, 2), &[]);
+    // Block light coming in from -x, getting dimmer towards +x
+    let meshes = Mesh::from_with_neighbours(
+        &vol,
+        |_| None,
+        |pos: Vec3<i64>| Light {
+            sun: 0,
+            block: (10 - 5 * pos.x).max(0) as u8,
+        },
+    );
+    let verts = meshes.values().flat_map(|mesh| mesh.vertices().clone()).collect::<Vec<_>>();
+    let light_at = |face: &[Vertex], x: f32| {
+        block_light(face.iter().find(|v| v.pos[0] == x && v.pos[1] == 1.0).expect("No such vertex"))
+    };
+
+    let (left, right) = (top_face(&verts, 0.0, 1.0), top_face(&verts, 1.0, 1.0));
+    // Corners are lit by the average of the voxels around them, so faces meeting at a corner agree on its light
+    assert_eq!(light_at(&left, 1.0), 127);
+    assert_eq!(light_at(&right, 1.0), 127);
+    assert_eq!(light_at(&right, 2.0), 42);
+    assert!(verts.iter().all(|v| v.light & 0xFF == 0));
+}
+
+// Stands in for the renderer, building a 100 byte model for each chunk in view that doesn't have one. Chunks in
+// `locked` are being written to, so their models can't be dropped.
+#[derive(Default)]
+struct MockRenderer {
+    models: HashSet<i32>,
+    built: Vec<i32>,
+    locked: HashSet<i32>,
+}
+
+impl MockRenderer {
+    // Render a frame with `visible` in view, returning the chunks whose models were dropped after it
+    fn frame(&mut self, budget: &mut ModelBudget<i32>, visible: &[i32]) -> Vec<i32> {
+        for chunk in visible {
+            if self.models.insert(*chunk) {
+                self.built.push(*chunk);
+                budget.insert(*chunk, 100);
+            } else {
+                budget.touch(*chunk);
+            }
+        }
+        let (models, locked) = (&mut self.models, &self.locked);
+        budget.end_frame(|chunk| !locked.contains(&chunk) && models.remove(&chunk))
+    }
+}
+
+#[test]
+fn least_recently_seen_models_are_evicted_first() {
+    let mut budget = ModelBudget::new(300);
+    let mut renderer = MockRenderer::default();
+    assert!(renderer.frame(&mut budget, &[1, 2]).is_empty());
+    assert!(renderer.frame(&mut budget, &[3]).is_empty());
+    assert!(renderer.frame(&mut budget, &[2]).is_empty());
+    assert_eq!(budget.used(), 300);
+
+    // Going over the budget drops only as many models as it takes to get back within it
+    assert_eq!(renderer.frame(&mut budget, &[4, 5]), vec![1, 3]);
+    assert_eq!(budget.used(), 300);
+
+    // Models in view are kept, even if that means going over the budget
+    assert!(renderer.frame(&mut budget, &[2, 4, 5, 6]).is_empty());
+    assert_eq!(budget.used(), 400);
+}
+
+#[test]
+fn evicted_models_are_rebuilt_once_back_in_view() {
+    let mut budget = ModelBudget::new(200);
+    let mut renderer = MockRenderer::default();
+    renderer.frame(&mut budget, &[1]);
+    renderer.frame(&mut budget, &[2]);
+    assert_eq!(renderer.frame(&mut budget, &[3]), vec![1]);
+
+    assert_eq!(renderer.frame(&mut budget, &[1]), vec![2]);
+    assert_eq!(renderer.built, vec![1, 2, 3, 1]);
+    assert_eq!(budget.used(), 200);
+}
+
+#[test]
+fn models_being_written_to_are_dropped_on_a_later_frame() {
+    let mut budget = ModelBudget::new(100);
+    let mut renderer = MockRenderer::default();
+    renderer.frame(&mut budget, &[1]);
+    renderer.locked.insert(1);
+    assert!(renderer.frame(&mut budget, &[2]).is_empty());
+    assert!(renderer.models.contains(&1));
+
+    renderer.locked.clear();
+    assert_eq!(renderer.frame(&mut budget, &[2]), vec![1]);
+    assert!(!renderer.models.contains(&1));
+
+    // Unloading a chunk stops its model counting towards the budget
+    budget.unload(2);
+    assert_eq!(budget.used(), 0);
+}
+
+// A full size chunk of hills, with faces to mesh all over it
+fn hills() -> HeterogeneousData {
+    let mut vol = HeterogeneousData::filled(CHUNK_SIZE, Block::AIR);
+    for x in 0..CHUNK_SIZE.x {
+        for y in 0..CHUNK_SIZE.y {
+            for z in 0..(x + y) / 3 + 4 {
+                vol.set_at(Vec3::new(x, y, z), Block::STONE);
+            }
+        }
+    }
+    vol
+}
+
+// Hides a volume's bulk reads, so that meshing it reads a voxel at a time
+struct PerVoxel<'a, V>(&'a V);
+
+impl<'a, V: ReadVolume> Volume for PerVoxel<'a, V> {
+    type VoxelType = V::VoxelType;
+
+    fn size(&self) -> Vec3<VoxRel> { self.0.size() }
+}
+
+impl<'a, V: ReadVolume> ReadVolume for PerVoxel<'a, V> {
+    fn at_unchecked(&self, off: Vec3<VoxRel>) -> V::VoxelType { self.0.at_unchecked(off) }
+}
+
+impl<'a, V: ReadVolume> PhysicalVolume for PerVoxel<'a, V> {}
+
+#[bench]
+fn mesh_chunk(b: &mut Bencher) {
+    let vol = hills();
+    b.iter(|| Mesh::from_with_neighbours(&vol, |_| None, |_| Light::SUNLIT));
+}
+
+#[bench]
+fn mesh_chunk_per_voxel(b: &mut Bencher) {
+    let vol = hills();
+    b.iter(|| Mesh::from_with_neighbours(&PerVoxel(&vol), |_| None, |_| Light::SUNLIT));
+}
+
+// What goes into the instance buffer each frame to draw a thousand dropped items of a kind in one go
+#[bench]
+fn instances_for_a_thousand_items(b: &mut Bencher) {
+    let positions = (0..1000)
+        .map(|i| Vec3::new((i % 32) as f32, (i / 32) as f32, 0.0))
+        .collect::<Vec<_>>();
+    b.iter(|| {
+        positions
+            .iter()
+            .enumerate()
+            .map(|(uid, pos)| InstanceData::new(item_mat(*pos, 1.5, uid as u64), Rgba::broadcast(1.0)))
+            .collect::<Vec<_>>()
+    });
+}
@@ -0,0 +1,30 @@
+// Library
+use vek::*;
+
+gfx_defines! {
+    vertex Vertex {
+        pos: [f32; 3] = "vert_pos",
+        tint: [f32; 4] = "vert_tint",
+    }
+}
+
+pub struct Mesh {
+    verts: Vec<Vertex>,
+}
+
+impl Mesh {
+    pub fn new() -> Mesh { Mesh { verts: Vec::new() } }
+
+    pub fn vertices(&self) -> &Vec<Vertex> { &self.verts }
+
+    /// A quad as two triangles, centred on `center` and reaching out `right` and `up` from it either way. It faces
+    /// whoever sees `right` pointing right and `up` pointing up.
+    pub fn add_quad(&mut self, center: Vec3<f32>, right: Vec3<f32>, up: Vec3<f32>, tint: [f32; 4]) {
+        let corner = |r: f32, u: f32| Vertex {
+            pos: (center + right * r + up * u).into_array(),
+            tint,
+        };
+        let (a, b, c, d) = (corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0));
+        self.verts.extend_from_slice(&[a, b, c, a, c, d]);
+    }
+}
@@ -0,0 +1,10 @@
+mod mesh;
+mod model;
+mod particles;
+
+// Reexports
+pub use self::{
+    mesh::{Mesh, Vertex},
+    model::{pipeline, Model},
+    particles::Particles,
+};
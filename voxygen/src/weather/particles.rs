@@ -0,0 +1,206 @@
+// Standard
+use std::f32::consts::PI;
+
+// Library
+use vek::*;
+
+// Project
+use common::{
+    terrain::VoxAbs,
+    weather::{Precipitation, Weather},
+};
+
+// Local
+use crate::{camera::RenderOrigin, weather::Mesh};
+
+// Constants
+// Particles fall within this far of the player sideways, from this far above them to this far below
+const RADIUS: f32 = 24.0;
+const ABOVE: f32 = 20.0;
+const BELOW: f32 = 8.0;
+// How many particles there are at full intensity
+const MAX_PARTICLES: usize = 4000;
+// How fast rain and snow fall in blocks per second, and how fast snow drifts from side to side
+const RAIN_SPEED: f32 = 24.0;
+const SNOW_SPEED: f32 = 2.0;
+const SNOW_DRIFT: f32 = 0.6;
+// How quickly what's shown catches up with what the server says the weather is, per second
+const CATCH_UP: f32 = 0.5;
+// The ground's heights are looked up again after this many seconds, or once the player is this far from where they
+// were looked up around
+const COVER_LIFETIME: f32 = 0.5;
+const COVER_MARGIN: f32 = 4.0;
+// Half the width and length of a streak of rain, and half the size of a snowflake, in blocks
+const RAIN_WIDTH: f32 = 0.01;
+const RAIN_LENGTH: f32 = 0.35;
+const SNOW_SIZE: f32 = 0.05;
+const RAIN_TINT: [f32; 4] = [0.7, 0.75, 0.85, 0.35];
+const SNOW_TINT: [f32; 4] = [1.0, 1.0, 1.0, 0.9];
+
+struct Particle {
+    pos: Vec3<f32>,
+    // Where the particle is in its drift, so that snowflakes don't all sway together
+    phase: f32,
+}
+
+// The heights of the ground around where the player was, for telling which particles have something overhead
+struct Cover {
+    center: Vec2<f32>,
+    low: Vec2<VoxAbs>,
+    size: Vec2<VoxAbs>,
+    heights: Vec<Option<VoxAbs>>,
+    age: f32,
+}
+
+impl Cover {
+    // Whether there's anything solid above or at `pos`. Columns that weren't looked up count as open sky.
+    fn covers(&self, pos: Vec3<f32>) -> bool {
+        let col = Vec2::new(pos.x.floor() as VoxAbs, pos.y.floor() as VoxAbs) - self.low;
+        if col.x < 0 || col.y < 0 || col.x >= self.size.x || col.y >= self.size.y {
+            return false;
+        }
+        self.heights[(col.y * self.size.x + col.x) as usize].map_or(false, |height| height as f32 + 1.0 > pos.z)
+    }
+}
+
+/// Rain or snow falling around the player. Nothing falls where there's something solid overhead, so sheltered
+/// places stay dry.
+pub struct Particles {
+    particles: Vec<Particle>,
+    // What's falling, kept after the weather clears so the last of it can fade out
+    kind: Option<Precipitation>,
+    // What's shown, easing towards what the server last told us
+    intensity: f32,
+    gloom: f32,
+    cover: Option<Cover>,
+    time: f32,
+    seed: u32,
+}
+
+impl Particles {
+    pub fn new() -> Particles {
+        Particles {
+            particles: vec![],
+            kind: None,
+            intensity: 0.0,
+            gloom: 0.0,
+            cover: None,
+            time: 0.0,
+            seed: 1,
+        }
+    }
+
+    /// How heavily it's raining or snowing as shown, from 0 to 1
+    pub fn intensity(&self) -> f32 { self.intensity }
+
+    /// How much the weather darkens the sky as shown, from 0 to 1
+    pub fn gloom(&self) -> f32 { self.gloom }
+
+    /// Move the particles on by `dt` seconds around `player_pos`, easing towards `weather` at `intensity`.
+    /// `surface_heights` gives the ground's heights between two corners the way `ChunkMgr::surface_heights` does, and
+    /// is only called when the heights known are out of date.
+    pub fn update(
+        &mut self,
+        dt: f32,
+        (weather, intensity): (Weather, f32),
+        player_pos: Vec3<f32>,
+        surface_heights: impl FnOnce(Vec2<VoxAbs>, Vec2<VoxAbs>) -> Vec<Option<VoxAbs>>,
+    ) {
+        let ease = (dt * CATCH_UP).min(1.0);
+        self.intensity += (intensity - self.intensity) * ease;
+        self.gloom += (weather.gloom(intensity) - self.gloom) * ease;
+        // The server only changes what falls once the last weather has died away, so there's little left to drop
+        if let Some(kind) = weather.precipitation() {
+            if self.kind != Some(kind) {
+                self.particles.clear();
+                self.kind = Some(kind);
+            }
+        }
+        self.time += dt;
+
+        let center = Vec2::from(player_pos);
+        let stale = self
+            .cover
+            .as_ref()
+            .map_or(true, |cover| cover.age > COVER_LIFETIME || cover.center.distance(center) > COVER_MARGIN);
+        if stale {
+            let reach = (RADIUS + COVER_MARGIN).ceil() as VoxAbs;
+            let mid = center.map(|e| e.floor() as VoxAbs);
+            let (low, high) = (mid - reach, mid + reach);
+            self.cover = Some(Cover {
+                center,
+                low,
+                size: high - low,
+                heights: surface_heights(low, high),
+                age: 0.0,
+            });
+        }
+        let cover = match self.cover {
+            Some(ref mut cover) => cover,
+            None => return,
+        };
+        cover.age += dt;
+
+        let (kind, time) = (self.kind, self.time);
+        for particle in &mut self.particles {
+            match kind {
+                Some(Precipitation::Snow) => {
+                    particle.pos.x += (time * 1.3 + particle.phase).sin() * SNOW_DRIFT * dt;
+                    particle.pos.y += (time * 0.9 + particle.phase * 2.0).cos() * SNOW_DRIFT * dt;
+                    particle.pos.z -= SNOW_SPEED * dt;
+                },
+                _ => particle.pos.z -= RAIN_SPEED * dt,
+            }
+        }
+
+        // Particles that have landed on something or been left behind by the player go, and new ones take their place
+        let in_range = |pos: Vec3<f32>| {
+            Vec2::<f32>::from(pos - player_pos).magnitude_squared() < RADIUS * RADIUS
+                && pos.z > player_pos.z - BELOW
+                && pos.z < player_pos.z + ABOVE
+        };
+        self.particles.retain(|p| in_range(p.pos) && !cover.covers(p.pos));
+
+        let target = match kind {
+            Some(_) => (MAX_PARTICLES as f32 * self.intensity.max(0.0).min(1.0)) as usize,
+            None => 0,
+        };
+        self.particles.truncate(target);
+        // Places with cover are left out rather than tried again, so particles are only ever in the open
+        let seed = &mut self.seed;
+        for _ in self.particles.len()..target {
+            let (angle, dist) = (roll(seed) * 2.0 * PI, roll(seed).sqrt() * RADIUS);
+            let height = roll(seed) * (ABOVE + BELOW) - BELOW;
+            let pos = player_pos + Vec3::new(angle.cos() * dist, angle.sin() * dist, height);
+            if !cover.covers(pos) {
+                self.particles.push(Particle {
+                    pos,
+                    phase: roll(seed) * 2.0 * PI,
+                });
+            }
+        }
+    }
+
+    /// A quad for each particle, facing a camera whose right and up are `cam_right` and `cam_up`. Streaks of rain stay
+    /// upright, only turning about the vertical.
+    pub fn mesh(&self, origin: &RenderOrigin, cam_right: Vec3<f32>, cam_up: Vec3<f32>) -> Mesh {
+        let mut mesh = Mesh::new();
+        let (right, up, tint) = match self.kind {
+            Some(Precipitation::Rain) => (cam_right * RAIN_WIDTH, Vec3::unit_z() * RAIN_LENGTH, RAIN_TINT),
+            Some(Precipitation::Snow) => (cam_right * SNOW_SIZE, cam_up * SNOW_SIZE, SNOW_TINT),
+            None => return mesh,
+        };
+        for particle in &self.particles {
+            mesh.add_quad(origin.relative(particle.pos), right, up, tint);
+        }
+        mesh
+    }
+}
+
+// A number from 0 to 1, moving `seed` on to the next
+fn roll(seed: &mut u32) -> f32 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 17;
+    *seed ^= *seed << 5;
+    (*seed & 0xFFFF) as f32 / 0x1_0000 as f32
+}
@@ -9,9 +9,46 @@ use glutin::{
 };
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use crate::renderer::{ColorFormat, DepthFormat, Renderer, RendererInfo};
+use crate::{
+    renderer::{ColorFormat, DepthFormat, Renderer, RendererInfo},
+    settings::Graphics,
+};
+
+use std::{
+    mem,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// The size and position of the window while it isn't fullscreen, in logical pixels
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Placement {
+    pub size: [u32; 2],
+    pub pos: Option<[i32; 2]>,
+}
+
+// Change the swap interval of the current context through whichever platform extension is available
+fn set_swap_interval(gl_window: &GlWindow, interval: i32) -> bool {
+    // Each extension, and whether it reports success with a non-zero return value
+    const EXTENSIONS: [(&str, bool); 3] = [
+        ("wglSwapIntervalEXT", true),
+        ("glXSwapIntervalMESA", false),
+        ("glXSwapIntervalSGI", false),
+    ];
 
-use std::sync::atomic::{AtomicBool, Ordering};
+    for (name, nonzero_ok) in EXTENSIONS.iter() {
+        let addr = gl_window.get_proc_address(name);
+        if addr.is_null() {
+            continue;
+        }
+        // All of them take a single integer interval and return an integer
+        let swap_interval: extern "system" fn(i32) -> i32 = unsafe { mem::transmute(addr) };
+        let ret = swap_interval(interval);
+        if (ret != 0) == *nonzero_ok {
+            return true;
+        }
+    }
+    false
+}
 
 pub enum Event {
     CloseRequest,
@@ -48,25 +85,37 @@ pub enum Event {
     },
 }
 
+/// The game's window and the renderer drawing to it.
+///
+/// Everything the game uploads to the GPU (chunk and entity models, the skybox and outline models, constant buffers,
+/// pipelines and UI textures) belongs to the GL context. Changing the window's mode keeps the context, so only the
+/// views onto the window's framebuffer have to be rebuilt, like they are on a resize. Recreating the context would
+/// throw all of those resources away, so the window never does it at runtime: settings that would need a new
+/// context are applied the next time the game starts instead.
 pub struct RenderWindow {
     events_loop: RwLock<EventsLoop>,
     gl_window: RwLock<GlWindow>,
     renderer: RwLock<Renderer>,
     cursor_trapped: AtomicBool,
+    fullscreen: AtomicBool,
+    vsync: AtomicBool,
+    // Where to put the window back when leaving fullscreen
+    windowed: RwLock<Placement>,
 }
 
 impl RenderWindow {
-    pub fn new() -> RenderWindow {
+    pub fn new(settings: &Graphics) -> RenderWindow {
         let events_loop = RwLock::new(EventsLoop::new());
+        let size = (settings.window_size[0] as f64, settings.window_size[1] as f64);
         let win_builder = WindowBuilder::new()
             .with_title("Veloren (Voxygen)")
-            .with_dimensions(LogicalSize::new(800.0, 500.0))
+            .with_dimensions(LogicalSize::new(size.0, size.1))
             .with_maximized(false);
 
         let ctx_builder = ContextBuilder::new()
             .with_gl(GlRequest::Specific(OpenGl, (3, 2)))
             .with_multisampling(4)
-            .with_vsync(true);
+            .with_vsync(settings.vsync);
 
         let (gl_window, device, factory, color_view, depth_view) =
             gfx_window_glutin::init::<ColorFormat, DepthFormat>(win_builder, ctx_builder, &events_loop.read());
@@ -74,7 +123,15 @@ impl RenderWindow {
         // Workaround for rendering issue on OSX.
         // https://github.com/tomaka/glutin/issues/1069
         events_loop.write().poll_events(|_| {});
-        gl_window.resize(glutin::dpi::PhysicalSize::new(800.0, 500.0));
+        gl_window.resize(glutin::dpi::PhysicalSize::new(size.0, size.1));
+
+        // Place the window before going fullscreen, so that it fills the monitor it was last closed on
+        if let Some([x, y]) = settings.window_pos {
+            gl_window.set_position(LogicalPosition::new(x as f64, y as f64));
+        }
+        if settings.fullscreen {
+            gl_window.set_fullscreen(Some(gl_window.get_current_monitor()));
+        }
 
         let size: (u32, u32) = gl_window
             .get_inner_size()
@@ -93,6 +150,12 @@ impl RenderWindow {
                 (size.0 as _, size.1 as _),
             )),
             cursor_trapped: AtomicBool::new(false),
+            fullscreen: AtomicBool::new(settings.fullscreen),
+            vsync: AtomicBool::new(settings.vsync),
+            windowed: RwLock::new(Placement {
+                size: settings.window_size,
+                pos: settings.window_pos,
+            }),
         };
         rw
     }
@@ -206,6 +269,68 @@ impl RenderWindow {
             .expect("Failed to swap window buffers");
     }
 
+    pub fn is_fullscreen(&self) -> bool { self.fullscreen.load(Ordering::Relaxed) }
+
+    /// Switch between a borderless fullscreen window on the current monitor and a normal window. The GL context is
+    /// kept, so loaded models survive; the resize that follows rebuilds the views.
+    pub fn set_fullscreen(&self, fullscreen: bool) {
+        if fullscreen == self.is_fullscreen() {
+            return;
+        }
+
+        let window = self.gl_window.read();
+        if fullscreen {
+            *self.windowed.write() = self.current_placement();
+            window.set_fullscreen(Some(window.get_current_monitor()));
+        } else {
+            window.set_fullscreen(None);
+            let placement = *self.windowed.read();
+            window.set_inner_size(LogicalSize::new(placement.size[0] as f64, placement.size[1] as f64));
+            if let Some([x, y]) = placement.pos {
+                window.set_position(LogicalPosition::new(x as f64, y as f64));
+            }
+        }
+        self.fullscreen.store(fullscreen, Ordering::Relaxed);
+    }
+
+    pub fn is_vsync(&self) -> bool { self.vsync.load(Ordering::Relaxed) }
+
+    /// Turn vsync on or off through the platform's swap interval extension. Returns false if there isn't one, in
+    /// which case the setting only takes effect once the game is restarted, since recreating the context would
+    /// mean uploading every model again. The swap interval doesn't affect the framebuffer, so nothing is rebuilt.
+    pub fn set_vsync(&self, vsync: bool) -> bool {
+        self.vsync.store(vsync, Ordering::Relaxed);
+        if !set_swap_interval(&self.gl_window.read(), if vsync { 1 } else { 0 }) {
+            warn!("could not change the swap interval, vsync will be changed after a restart");
+            return false;
+        }
+        true
+    }
+
+    /// Where the window is when it isn't fullscreen, to be remembered for the next start
+    pub fn placement(&self) -> Placement {
+        if self.is_fullscreen() {
+            *self.windowed.read()
+        } else {
+            self.current_placement()
+        }
+    }
+
+    fn current_placement(&self) -> Placement {
+        let window = self.gl_window.read();
+        let windowed = *self.windowed.read();
+        Placement {
+            size: window
+                .get_inner_size()
+                .map(|size| [size.width as u32, size.height as u32])
+                .unwrap_or(windowed.size),
+            pos: window
+                .get_position()
+                .map(|pos| [pos.x as i32, pos.y as i32])
+                .or(windowed.pos),
+        }
+    }
+
     pub fn get_size(&self) -> [f64; 2] {
         let window = self.gl_window.read();
         match window.get_inner_size() {
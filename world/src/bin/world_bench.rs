@@ -0,0 +1,63 @@
+#![feature(duration_float)]
+
+// Standard
+use std::{env, process};
+
+// Project
+use world::{
+    profile::{self, Stage, REFERENCE_SEED, REFERENCE_SIZE},
+    GenConfig, Generator,
+};
+
+fn usage() -> ! {
+    eprintln!("Usage: world-bench [size] [seed]");
+    eprintln!(
+        "Generates every chunk in size x size columns of chunks (default {}) from a seed (default {})",
+        REFERENCE_SIZE, REFERENCE_SEED
+    );
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let size = args.get(0).map(|s| s.parse().unwrap_or_else(|_| usage())).unwrap_or(REFERENCE_SIZE);
+    let seed = args.get(1).map(|s| s.parse().unwrap_or_else(|_| usage())).unwrap_or(REFERENCE_SEED);
+    if size <= 0 || args.len() > 2 {
+        usage();
+    }
+
+    let generator = Generator::new(&GenConfig::default(), seed);
+    let before = profile::stats();
+    let (chunks, elapsed) = profile::time_region(&generator, size);
+    let elapsed = elapsed.as_float_secs();
+
+    println!("Generated {} chunks from seed {} in {:.2}s", chunks, seed, elapsed);
+    println!("{:.1} chunks/s, {:.3} ms per chunk", chunks as f64 / elapsed, elapsed * 1000.0 / chunks as f64);
+
+    match (before, profile::stats()) {
+        (Some(before), Some(after)) => {
+            let stats = after.since(&before);
+            let total = stats.total.as_float_secs();
+            for stage in Stage::ALL.iter() {
+                let secs = stats.stage(*stage).as_float_secs();
+                println!("  {:<12} {:>8.2}s {:>5.1}%", stage.name(), secs, secs / total * 100.0);
+            }
+            let rest = total - Stage::ALL.iter().map(|s| stats.stage(*s).as_float_secs()).sum::<f64>();
+            println!("  {:<12} {:>8.2}s {:>5.1}%", "other", rest, rest / total * 100.0);
+        },
+        _ => println!("Build with the `profiling` feature to see where the time goes"),
+    }
+
+    println!("Caches:");
+    for cache in generator.cache_stats() {
+        println!(
+            "  {:<14} {:>5.1}% hits ({} hits, {} misses), {}/{} entries",
+            cache.name,
+            cache.hit_rate * 100.0,
+            cache.hits,
+            cache.misses,
+            cache.len,
+            cache.capacity
+        );
+    }
+}
@@ -11,10 +11,12 @@ use common::terrain::chunk::Block;
 // Local
 use crate::{
     cachegen::CacheGen,
-    new_seed,
+    config::GenConfig,
     overworldgen::{Out as OverworldOut, OverworldGen},
+    profile::{self, Stage},
+    seed::sub_seed,
     towngen::{self, TownGen},
-    Gen,
+    CacheStats, Climate, Gen,
 };
 
 pub struct BlockGen {
@@ -24,18 +26,23 @@ pub struct BlockGen {
 }
 
 impl BlockGen {
-    pub fn new() -> Self {
+    /// A generator for the world that comes from `seed`, which is the same every time
+    pub fn new(config: &GenConfig, seed: u64) -> Self {
         Self {
-            overworld_gen: CacheGen::new(OverworldGen::new(), 4096),
-            town_gen: TownGen::new(),
+            overworld_gen: CacheGen::new(OverworldGen::new(seed), config.overworld_cache_size),
+            town_gen: TownGen::new(config, seed),
 
-            warp_nz: HybridMulti::new().set_seed(new_seed()).set_octaves(3),
+            warp_nz: HybridMulti::new().set_seed(sub_seed(seed, "block.warp")).set_octaves(3),
         }
     }
 
     pub fn get_invariant_z(&self, pos: Vec2<i64>) -> (OverworldOut, towngen::InvariantZ) {
-        let overworld = self.overworld_gen.sample(pos, &());
+        let overworld = {
+            let _timer = profile::time(Stage::Overworld);
+            self.overworld_gen.sample(pos, &())
+        };
 
+        let _timer = profile::time(Stage::Structures);
         (
             overworld,
             self.town_gen
@@ -43,6 +50,33 @@ impl BlockGen {
         )
     }
 
+    pub fn climate(&self, pos: Vec2<i64>) -> Climate {
+        let overworld = {
+            let _timer = profile::time(Stage::Overworld);
+            self.overworld_gen.sample(pos, &())
+        };
+        Climate {
+            temp: overworld.temp as f32,
+            dry: overworld.dry as f32,
+        }
+    }
+
+    pub fn resize_caches(&self, config: &GenConfig) {
+        self.overworld_gen.resize(config.overworld_cache_size);
+        self.town_gen.resize_caches(config);
+    }
+
+    pub fn cache_stats(&self) -> Vec<CacheStats> {
+        let mut stats = vec![self.overworld_gen.stats("overworld")];
+        stats.extend(self.town_gen.cache_stats());
+        stats
+    }
+
+    pub fn reset_cache_stats(&self) {
+        self.overworld_gen.reset_stats();
+        self.town_gen.reset_cache_stats();
+    }
+
     fn get_warp(&self, pos: Vec3<f64>, dry: f64, land: f64) -> f64 {
         let scale = Vec3::new(350.0, 350.0, 350.0);
 
@@ -64,15 +98,17 @@ impl Gen<(OverworldOut, towngen::InvariantZ)> for BlockGen {
         pos: Vec3<i64>,
         (overworld, towngen_invariant_z): &(OverworldOut, towngen::InvariantZ),
     ) -> Block {
+        let town = {
+            let _timer = profile::time(Stage::Structures);
+            self.town_gen.sample(pos, &(towngen_invariant_z, overworld, self.overworld_gen.internal()))
+        };
+
+        let _timer = profile::time(Stage::Blocks);
         let pos_f64 = pos.map(|e| e as f64) * 1.0;
 
         let warp = self.get_warp(pos_f64, overworld.dry, overworld.land);
         let z_warp = warp.mul(96.0);
 
-        let town = self
-            .town_gen
-            .sample(pos, &(towngen_invariant_z, overworld, self.overworld_gen.internal()));
-
         let z_alt = overworld.z_alt + z_warp - town.surface.map(|_| 1.0).unwrap_or(0.0);
 
         const GRASS_DEPTH: f64 = 3.5;
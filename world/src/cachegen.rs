@@ -1,37 +1,154 @@
 // Standard
-use std::hash::{Hash, Hasher};
+use std::{
+    hash::Hash,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
 
 // Library
-use fnv::FnvHasher;
+use fnv::FnvHashMap;
 use parking_lot::RwLock;
 
 // Local
-use crate::Gen;
+use crate::{CacheStats, Gen};
+
+struct Slot<I, O> {
+    key: I,
+    val: O,
+    referenced: AtomicBool,
+}
+
+// A bounded cache using the CLOCK approximation of LRU. Hits only need a read lock since they just set the
+// slot's reference bit, so concurrent samplers don't serialise on each other unless they miss.
+struct Clock<I, O> {
+    slots: Vec<Slot<I, O>>,
+    index: FnvHashMap<I, usize>,
+    hand: usize,
+    capacity: usize,
+}
+
+impl<I: Eq + Hash + Clone, O> Clock<I, O> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            index: FnvHashMap::default(),
+            hand: 0,
+            capacity,
+        }
+    }
+
+    fn insert(&mut self, key: I, val: O) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        // Another sampler may have generated the same key while we weren't holding the lock
+        if let Some(&idx) = self.index.get(&key) {
+            self.slots[idx].val = val;
+            return;
+        }
+
+        let slot = Slot {
+            key: key.clone(),
+            val,
+            referenced: AtomicBool::new(false),
+        };
+
+        if self.slots.len() < self.capacity {
+            self.index.insert(key, self.slots.len());
+            self.slots.push(slot);
+            return;
+        }
+
+        // Sweep until we find a slot that hasn't been used since the hand last passed it
+        loop {
+            let idx = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+
+            if !self.slots[idx].referenced.swap(false, Ordering::Relaxed) {
+                self.index.remove(&self.slots[idx].key);
+                self.index.insert(key, idx);
+                self.slots[idx] = slot;
+                return;
+            }
+        }
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        if capacity < self.slots.len() {
+            // Keep recently used entries in preference to the rest
+            self.slots.sort_by_key(|slot| !slot.referenced.load(Ordering::Relaxed));
+            self.slots.truncate(capacity);
+
+            self.index.clear();
+            for (idx, slot) in self.slots.iter().enumerate() {
+                self.index.insert(slot.key.clone(), idx);
+            }
+        }
+
+        self.hand = 0;
+        self.capacity = capacity;
+    }
+}
 
 pub struct CacheGen<T, I, O>
 where
-    I: Eq + Hash,
+    I: Eq + Hash + Clone,
     O: 'static,
 {
-    cache: Vec<RwLock<Option<(I, O)>>>,
+    cache: RwLock<Clock<I, O>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
     gen: T,
 }
 
 impl<T, I, O> CacheGen<T, I, O>
 where
-    I: Eq + Hash,
+    I: Eq + Hash + Clone,
     O: 'static,
 {
     pub fn new(gen: T, cache_size: usize) -> Self {
-        let mut cache = vec![];
-        for _ in 0..cache_size {
-            cache.push(RwLock::new(None));
+        Self {
+            cache: RwLock::new(Clock::new(cache_size)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            gen,
         }
-
-        Self { cache, gen }
     }
 
     pub fn internal(&self) -> &T { &self.gen }
+
+    pub fn capacity(&self) -> usize { self.cache.read().capacity }
+    pub fn len(&self) -> usize { self.cache.read().slots.len() }
+
+    pub fn hits(&self) -> u64 { self.hits.load(Ordering::Relaxed) }
+    pub fn misses(&self) -> u64 { self.misses.load(Ordering::Relaxed) }
+
+    pub fn hit_rate(&self) -> f64 {
+        let (hits, misses) = (self.hits(), self.misses());
+        if hits + misses == 0 {
+            0.0
+        } else {
+            hits as f64 / (hits + misses) as f64
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self, name: &'static str) -> CacheStats {
+        CacheStats {
+            name,
+            hits: self.hits(),
+            misses: self.misses(),
+            hit_rate: self.hit_rate(),
+            len: self.len(),
+            capacity: self.capacity(),
+        }
+    }
+
+    pub fn resize(&self, cache_size: usize) { self.cache.write().resize(cache_size); }
 }
 
 impl<S, T: Gen<S>> Gen<S> for CacheGen<T, T::In, T::Out>
@@ -43,26 +160,20 @@ where
     type Out = T::Out;
 
     fn sample<'a>(&'a self, i: Self::In, supplement: &'a S) -> Self::Out {
-        let mut hasher = FnvHasher::with_key(0);
-        i.hash(&mut hasher);
-
-        let idx = hasher.finish() as usize % self.cache.len();
-
-        if let Some(Some(o)) = self.cache.get(idx).and_then(|c| {
-            c.read().clone().map(|item| {
-                let (cached_i, o) = item.clone();
-                if cached_i == i {
-                    Some(o)
-                } else {
-                    None
-                }
-            })
-        }) {
-            o
-        } else {
-            let samp = self.gen.sample(i.clone(), supplement);
-            self.cache.get(idx).map(|c| *c.write() = Some((i, samp.clone())));
-            samp
+        {
+            let cache = self.cache.read();
+            if let Some(slot) = cache.index.get(&i).map(|idx| &cache.slots[*idx]) {
+                slot.referenced.store(true, Ordering::Relaxed);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return slot.val.clone();
+            }
         }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        // Generate without holding the lock so other samplers can keep hitting the cache
+        let samp = self.gen.sample(i.clone(), supplement);
+        self.cache.write().insert(i, samp.clone());
+        samp
     }
 }
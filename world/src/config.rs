@@ -0,0 +1,19 @@
+/// Tuning parameters for world generation. Cache sizes are measured in entries.
+#[derive(Copy, Clone, Debug)]
+pub struct GenConfig {
+    pub overworld_cache_size: usize,
+    pub city_cache_size: usize,
+    pub building_cache_size: usize,
+    pub structure_cache_size: usize,
+}
+
+impl Default for GenConfig {
+    fn default() -> Self {
+        Self {
+            overworld_cache_size: 4096,
+            city_cache_size: 4096,
+            building_cache_size: 4096,
+            structure_cache_size: 256,
+        }
+    }
+}
@@ -4,20 +4,24 @@
     associated_type_defaults,
     self_struct_ctor,
     euclidean_division,
-    integer_atomics
+    integer_atomics,
+    duration_float
 )]
 
 mod blockgen;
 mod cachegen;
+mod config;
 mod overworldgen;
+pub mod profile;
+mod seed;
+mod spawn;
 mod towngen;
 mod util;
 
 // Standard
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
 
 // Library
-use lazy_static::lazy_static;
 use vek::*;
 
 // Project
@@ -29,6 +33,9 @@ use common::terrain::{
 // Local
 use crate::blockgen::BlockGen;
 
+// Reexports
+pub use crate::config::GenConfig;
+
 // Generator
 
 pub trait Gen<S> {
@@ -38,96 +45,133 @@ pub trait Gen<S> {
     fn sample<'a>(&'a self, i: Self::In, supplement: &'a S) -> Self::Out;
 }
 
-// Seed - used during worldgen initiation
-static SEED: AtomicU32 = AtomicU32::new(0);
-pub fn new_seed() -> u32 { SEED.fetch_add(1, Ordering::Relaxed) }
+/// How warm and how dry it is somewhere, each from 0 to 1. Ground colder than 0.35 is snowy.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Climate {
+    pub temp: f32,
+    pub dry: f32,
+}
 
-lazy_static! {
-    static ref GENERATOR: BlockGen = BlockGen::new();
+/// How well one of the generator caches is doing since it was made or its stats were last reset
+#[derive(Copy, Clone, Debug)]
+pub struct CacheStats {
+    pub name: &'static str,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    pub len: usize,
+    pub capacity: usize,
 }
 
-pub struct World;
+// Chunks above this are always air
+const MAX_CHUNK_Z: i32 = 512 / CHUNK_SIZE.z as i32;
 
-impl World {
-    pub fn gen_chunk(offs: Vec3<i32>) -> Chunk {
-        // If the chunk is out of bounds, just generate air
-        if offs.z < 0 || offs.z > 512 / CHUNK_SIZE.z as i32 {
-            return Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR));
-        }
+/// Generates the world that comes from a seed. Every part of generation is seeded from it, so the same seed always
+/// gives the same terrain.
+pub struct Generator(BlockGen);
 
-        let mut chunk_data = HeterogeneousData::empty(CHUNK_SIZE);
-        let generator = &GENERATOR; // Create a temporary for the generator here to avoid atomic operations for every block
+impl Generator {
+    pub fn new(config: &GenConfig, seed: u64) -> Self { Generator(BlockGen::new(config, seed)) }
 
-        // is_homogeneous, block type
-        let mut cblock = (true, None);
+    /// Resize the generator caches. Entries are kept where the new sizes allow it.
+    pub fn configure(&self, config: &GenConfig) { self.0.resize_caches(config); }
 
-        let mut gen_block_fn = |x, y, z| {
-            let pos = offs.map(|e| e as i64) * CHUNK_SIZE.map(|e| e as i64) + Vec3::new(x, y, z).map(|e| e as i64);
+    /// How each of the generator caches is doing, to see whether their sizes suit the load
+    pub fn cache_stats(&self) -> Vec<CacheStats> { self.0.cache_stats() }
 
-            let block = generator.sample(pos, &generator.get_invariant_z(Vec2::from(pos)));
+    /// Count cache hits and misses from zero again
+    pub fn reset_cache_stats(&self) { self.0.reset_cache_stats(); }
 
-            match cblock {
-                (true, None) => cblock.1 = Some(block),
-                (true, Some(b)) if b == block => {},
-                (true, Some(_)) => cblock = (false, None),
-                _ => {},
-            }
+    /// Where new players should start
+    pub fn spawn_point(&self) -> Vec3<f32> { self.0.spawn_point() }
 
-            chunk_data.set_at(Vec3::new(x, y, z), block);
-        };
+    /// How warm and how dry it is around `pos`
+    pub fn climate(&self, pos: Vec2<i64>) -> Climate { self.0.climate(pos) }
 
-        // x faces
+    pub fn gen_chunk(&self, offs: Vec3<i32>) -> Chunk {
+        let start = Instant::now();
+        let chunk = gen_chunk_data(&self.0, offs);
+        profile::chunk_done(start.elapsed());
+        chunk
+    }
+}
 
-        for x in (0..CHUNK_SIZE.x).step_by(CHUNK_SIZE.x as usize - 1) {
-            for y in 1..CHUNK_SIZE.y - 1 {
-                for z in 1..CHUNK_SIZE.z - 1 {
-                    gen_block_fn(x, y, z);
-                }
-            }
+fn gen_chunk_data(generator: &BlockGen, offs: Vec3<i32>) -> Chunk {
+    // If the chunk is out of bounds, just generate air
+    if offs.z < 0 || offs.z > MAX_CHUNK_Z {
+        return Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, Block::AIR));
+    }
+
+    let mut chunk_data = HeterogeneousData::empty(CHUNK_SIZE);
+
+    // is_homogeneous, block type
+    let mut cblock = (true, None);
+
+    let mut gen_block_fn = |x, y, z| {
+        let pos = offs.map(|e| e as i64) * CHUNK_SIZE.map(|e| e as i64) + Vec3::new(x, y, z).map(|e| e as i64);
+
+        let block = generator.sample(pos, &generator.get_invariant_z(Vec2::from(pos)));
+
+        match cblock {
+            (true, None) => cblock.1 = Some(block),
+            (true, Some(b)) if b == block => {},
+            (true, Some(_)) => cblock = (false, None),
+            _ => {},
         }
 
-        // y faces
+        chunk_data.set_at(Vec3::new(x, y, z), block);
+    };
+
+    // x faces
 
-        for x in 0..CHUNK_SIZE.x {
-            for y in (0..CHUNK_SIZE.y).step_by(CHUNK_SIZE.y as usize - 1) {
-                for z in 1..CHUNK_SIZE.z - 1 {
-                    gen_block_fn(x, y, z);
-                }
+    for x in (0..CHUNK_SIZE.x).step_by(CHUNK_SIZE.x as usize - 1) {
+        for y in 1..CHUNK_SIZE.y - 1 {
+            for z in 1..CHUNK_SIZE.z - 1 {
+                gen_block_fn(x, y, z);
             }
         }
+    }
 
-        // z faces
+    // y faces
 
-        for x in 0..CHUNK_SIZE.x {
-            for y in 0..CHUNK_SIZE.y {
-                for z in (0..CHUNK_SIZE.z).step_by(CHUNK_SIZE.z as usize - 1) {
-                    gen_block_fn(x, y, z);
-                }
+    for x in 0..CHUNK_SIZE.x {
+        for y in (0..CHUNK_SIZE.y).step_by(CHUNK_SIZE.y as usize - 1) {
+            for z in 1..CHUNK_SIZE.z - 1 {
+                gen_block_fn(x, y, z);
             }
         }
+    }
 
-        // Can we make broad assumptions about the homogenity of the chunk?
-        match cblock {
-            (true, Some(block)) => return Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, block)),
-            _ => {},
+    // z faces
+
+    for x in 0..CHUNK_SIZE.x {
+        for y in 0..CHUNK_SIZE.y {
+            for z in (0..CHUNK_SIZE.z).step_by(CHUNK_SIZE.z as usize - 1) {
+                gen_block_fn(x, y, z);
+            }
         }
+    }
+
+    // Can we make broad assumptions about the homogenity of the chunk?
+    match cblock {
+        (true, Some(block)) => return Chunk::Homo(HomogeneousData::filled(CHUNK_SIZE, block)),
+        _ => {},
+    }
 
-        // Fill in everything else
-        for x in 1..CHUNK_SIZE.x - 1 {
-            for y in 1..CHUNK_SIZE.y - 1 {
-                let pos2d = Vec2::from(offs.map(|e| e as i64)) * Vec2::from(CHUNK_SIZE.map(|e| e as i64))
-                    + Vec2::new(x, y).map(|e| e as i64);
-                let invariant_z = generator.get_invariant_z(pos2d);
+    // Fill in everything else
+    for x in 1..CHUNK_SIZE.x - 1 {
+        for y in 1..CHUNK_SIZE.y - 1 {
+            let pos2d = Vec2::from(offs.map(|e| e as i64)) * Vec2::from(CHUNK_SIZE.map(|e| e as i64))
+                + Vec2::new(x, y).map(|e| e as i64);
+            let invariant_z = generator.get_invariant_z(pos2d);
 
-                for z in 1..CHUNK_SIZE.z - 1 {
-                    let pos =
-                        offs.map(|e| e as i64) * CHUNK_SIZE.map(|e| e as i64) + Vec3::new(x, y, z).map(|e| e as i64);
+            for z in 1..CHUNK_SIZE.z - 1 {
+                let pos = offs.map(|e| e as i64) * CHUNK_SIZE.map(|e| e as i64) + Vec3::new(x, y, z).map(|e| e as i64);
 
-                    chunk_data.set_at(Vec3::new(x, y, z), generator.sample(pos, &invariant_z));
-                }
+                chunk_data.set_at(Vec3::new(x, y, z), generator.sample(pos, &invariant_z));
             }
         }
-
-        Chunk::Hetero(chunk_data)
     }
+
+    Chunk::Hetero(chunk_data)
 }
@@ -9,7 +9,7 @@ use vek::*;
 use common::terrain::chunk::Block;
 
 // Local
-use crate::{new_seed, Gen};
+use crate::{seed::sub_seed, Gen};
 
 pub struct OverworldGen {
     land_nz: HybridMulti,
@@ -40,18 +40,18 @@ pub struct Out {
 }
 
 impl OverworldGen {
-    pub fn new() -> Self {
+    pub fn new(seed: u64) -> Self {
         Self {
             // Large-scale
-            land_nz: HybridMulti::new().set_seed(new_seed()).set_octaves(8),
-            dry_nz: HybridMulti::new().set_seed(new_seed()).set_octaves(7),
-            temp_nz: HybridMulti::new().set_seed(new_seed()).set_octaves(8),
+            land_nz: HybridMulti::new().set_seed(sub_seed(seed, "overworld.land")).set_octaves(8),
+            dry_nz: HybridMulti::new().set_seed(sub_seed(seed, "overworld.dry")).set_octaves(7),
+            temp_nz: HybridMulti::new().set_seed(sub_seed(seed, "overworld.temp")).set_octaves(8),
 
             // Small-scale
-            hill_nz: HybridMulti::new().set_seed(new_seed()).set_octaves(4),
+            hill_nz: HybridMulti::new().set_seed(sub_seed(seed, "overworld.hill")).set_octaves(4),
 
-            temp_vari_nz: SuperSimplex::new().set_seed(new_seed()),
-            alt_vari_nz: SuperSimplex::new().set_seed(new_seed()),
+            temp_vari_nz: SuperSimplex::new().set_seed(sub_seed(seed, "overworld.temp_vari")),
+            alt_vari_nz: SuperSimplex::new().set_seed(sub_seed(seed, "overworld.alt_vari")),
         }
     }
 
@@ -0,0 +1,176 @@
+// Where the time generating chunks goes. Timings are gathered per thread while a chunk is generated and added to the
+// totals once it's done, so generator threads don't contend over them. Without the `profiling` feature, timers do
+// nothing and there are no totals.
+
+// Standard
+use std::time::{Duration, Instant};
+
+// Library
+use vek::*;
+
+// Local
+use crate::{Generator, MAX_CHUNK_Z};
+
+// Constants
+const STAGE_COUNT: usize = 3;
+/// The region `world-bench` times unless told otherwise, and that generation speed is compared over
+pub const REFERENCE_SIZE: i32 = 8;
+pub const REFERENCE_SEED: u64 = 1337;
+
+/// A part of generating a chunk
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Sampling the noise that shapes the land, water and climate
+    Overworld,
+    /// Placing cities and buildings, and picking the blocks they're made of
+    Structures,
+    /// Deciding on each block from the shape of the terrain around it
+    Blocks,
+}
+
+impl Stage {
+    pub const ALL: [Stage; STAGE_COUNT] = [Stage::Overworld, Stage::Structures, Stage::Blocks];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Overworld => "overworld",
+            Stage::Structures => "structures",
+            Stage::Blocks => "blocks",
+        }
+    }
+
+    fn index(&self) -> usize { *self as usize }
+}
+
+/// Time spent generating chunks, summed over every chunk generated so far
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GenStats {
+    pub chunks: u64,
+    /// The whole time spent in `gen_chunk`, which includes time outside of any stage
+    pub total: Duration,
+    stages: [Duration; STAGE_COUNT],
+}
+
+impl GenStats {
+    pub fn stage(&self, stage: Stage) -> Duration { self.stages[stage.index()] }
+
+    /// What's been added since `earlier` was taken
+    pub fn since(&self, earlier: &GenStats) -> GenStats {
+        let mut stages = self.stages;
+        for (stage, before) in stages.iter_mut().zip(earlier.stages.iter()) {
+            *stage -= *before;
+        }
+        GenStats {
+            chunks: self.chunks - earlier.chunks,
+            total: self.total - earlier.total,
+            stages,
+        }
+    }
+}
+
+/// Generate every chunk in the `size` by `size` columns of chunks from the origin to the top of the world. Returns
+/// how many chunks that was and how long it took.
+pub fn time_region(generator: &Generator, size: i32) -> (u32, Duration) {
+    let start = Instant::now();
+    let mut chunks = 0;
+    for x in 0..size {
+        for y in 0..size {
+            for z in 0..=MAX_CHUNK_Z {
+                generator.gen_chunk(Vec3::new(x, y, z));
+                chunks += 1;
+            }
+        }
+    }
+    (chunks, start.elapsed())
+}
+
+pub use self::imp::stats;
+pub(crate) use self::imp::{chunk_done, time};
+
+#[cfg(feature = "profiling")]
+mod imp {
+    // Standard
+    use std::{
+        cell::Cell,
+        sync::atomic::{AtomicU64, Ordering},
+        time::{Duration, Instant},
+    };
+
+    // Local
+    use super::{GenStats, Stage, STAGE_COUNT};
+
+    static CHUNKS: AtomicU64 = AtomicU64::new(0);
+    static TOTAL_NANOS: AtomicU64 = AtomicU64::new(0);
+    static STAGE_NANOS: [AtomicU64; STAGE_COUNT] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+    thread_local! {
+        static PENDING: Cell<[u64; STAGE_COUNT]> = Cell::new([0; STAGE_COUNT]);
+    }
+
+    /// Adds the time until it's dropped to a stage. Timers mustn't overlap, or the time is counted twice.
+    pub(crate) struct Timer {
+        stage: Stage,
+        start: Instant,
+    }
+
+    impl Drop for Timer {
+        fn drop(&mut self) {
+            let nanos = nanos(self.start.elapsed());
+            PENDING.with(|pending| {
+                let mut stages = pending.get();
+                stages[self.stage.index()] += nanos;
+                pending.set(stages);
+            });
+        }
+    }
+
+    pub(crate) fn time(stage: Stage) -> Timer {
+        Timer {
+            stage,
+            start: Instant::now(),
+        }
+    }
+
+    /// Add the stages timed on this thread to the totals, along with a chunk that took `elapsed` overall
+    pub(crate) fn chunk_done(elapsed: Duration) {
+        let stages = PENDING.with(|pending| pending.replace([0; STAGE_COUNT]));
+        for (total, nanos) in STAGE_NANOS.iter().zip(stages.iter()) {
+            total.fetch_add(*nanos, Ordering::Relaxed);
+        }
+        TOTAL_NANOS.fetch_add(nanos(elapsed), Ordering::Relaxed);
+        CHUNKS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Everything timed so far
+    pub fn stats() -> Option<GenStats> {
+        let mut stages = [Duration::default(); STAGE_COUNT];
+        for (stage, nanos) in stages.iter_mut().zip(STAGE_NANOS.iter()) {
+            *stage = Duration::from_nanos(nanos.load(Ordering::Relaxed));
+        }
+        Some(GenStats {
+            chunks: CHUNKS.load(Ordering::Relaxed),
+            total: Duration::from_nanos(TOTAL_NANOS.load(Ordering::Relaxed)),
+            stages,
+        })
+    }
+
+    fn nanos(duration: Duration) -> u64 { duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64 }
+}
+
+#[cfg(not(feature = "profiling"))]
+mod imp {
+    // Standard
+    use std::time::Duration;
+
+    // Local
+    use super::{GenStats, Stage};
+
+    pub(crate) struct Timer;
+
+    pub(crate) fn time(_: Stage) -> Timer { Timer }
+
+    pub(crate) fn chunk_done(_: Duration) {}
+
+    /// Nothing is timed without the `profiling` feature
+    pub fn stats() -> Option<GenStats> { None }
+}
@@ -0,0 +1,22 @@
+// Constants
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// The seed for one part of generation, named by `tag`, in a world generated from `seed`. Seeds come from hashing
+/// rather than counting so that adding a part, or taking one away, leaves the others as they were. The hash is written
+/// out here rather than taken from `std`, since it has to give the same answer everywhere, forever.
+pub fn sub_seed(seed: u64, tag: &str) -> u32 {
+    // FNV-1a over the seed's bytes and then the tag's
+    let mut hash = FNV_OFFSET;
+    let seed_bytes = (0..8).map(|i| (seed >> (i * 8)) as u8);
+    for byte in seed_bytes.chain(tag.bytes()) {
+        hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+    }
+
+    // FNV mixes its last few bytes poorly, and tags often differ only at the end, so finish with SplitMix64's mixer
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+
+    (hash ^ (hash >> 32)) as u32
+}
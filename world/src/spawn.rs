@@ -0,0 +1,129 @@
+// Library
+use vek::*;
+
+// Project
+use common::terrain::{chunk::Block, Voxel};
+
+// Local
+use crate::{blockgen::BlockGen, Gen};
+
+// Constants
+// Distance between the columns that are tried, in blocks
+const SEARCH_STEP: i64 = 16;
+// How many rings of columns around the origin are tried before giving up
+const SEARCH_RINGS: i64 = 256;
+// A spawn is flat if the ground this far away in each direction is within `MAX_SLOPE` blocks of its height
+const FLAT_DIST: i64 = 4;
+const MAX_SLOPE: i64 = 2;
+// How high above the sea the ground must be, so that spawns aren't on beaches that flood
+const MIN_SEA_CLEARANCE: f64 = 2.0;
+// How far above the unwarped terrain height the ground could possibly be
+const MAX_WARP: f64 = 128.0;
+// Where to spawn if nowhere suitable is found. Players fall from here to whatever is below.
+const FALLBACK_SPAWN: Vec3<f32> = Vec3 { x: 0.5, y: 0.5, z: 256.0 };
+
+/// Search outwards from the origin in a square spiral for a flat column, returning the position of the first free block
+/// above its ground. `surface` gives the height of the first free block above dry ground in a column, or `None` if the
+/// column is no good (underwater, for example).
+pub fn find_spawn<F: Fn(Vec2<i64>) -> Option<i64>>(surface: F) -> Vec3<f32> {
+    for ring in 0..SEARCH_RINGS {
+        for offs in ring_offsets(ring) {
+            let pos = offs * SEARCH_STEP;
+            let z = match surface(pos) {
+                Some(z) => z,
+                None => continue,
+            };
+
+            let flat = [Vec2::unit_x(), -Vec2::unit_x(), Vec2::unit_y(), -Vec2::unit_y()]
+                .iter()
+                .all(|dir| {
+                    surface(pos + *dir * FLAT_DIST)
+                        .map(|nz| (nz - z).abs() <= MAX_SLOPE)
+                        .unwrap_or(false)
+                });
+            if flat {
+                return Vec3::new(pos.x as f32 + 0.5, pos.y as f32 + 0.5, z as f32);
+            }
+        }
+    }
+    FALLBACK_SPAWN
+}
+
+// The points on the edge of a square with the given radius, in a fixed order
+fn ring_offsets(ring: i64) -> Vec<Vec2<i64>> {
+    if ring == 0 {
+        return vec![Vec2::zero()];
+    }
+
+    let mut offsets = Vec::with_capacity(ring as usize * 8);
+    for i in -ring..ring {
+        offsets.push(Vec2::new(i, -ring));
+        offsets.push(Vec2::new(ring, i));
+        offsets.push(Vec2::new(-i, ring));
+        offsets.push(Vec2::new(-ring, -i));
+    }
+    offsets
+}
+
+impl BlockGen {
+    /// Where new players should start: dry, reasonably flat land above sea level near the origin
+    pub fn spawn_point(&self) -> Vec3<f32> { find_spawn(|pos| self.surface(pos)) }
+
+    // The height of the first free block above dry ground that's clear of the sea
+    fn surface(&self, pos: Vec2<i64>) -> Option<i64> {
+        let invariant_z = self.get_invariant_z(pos);
+        let overworld = &invariant_z.0;
+        if overworld.z_alt < overworld.z_sea + MIN_SEA_CLEARANCE {
+            return None;
+        }
+
+        // Look down from above the highest the ground could be for the first block that isn't air
+        let top = (overworld.z_alt + MAX_WARP) as i64;
+        (0..top)
+            .rev()
+            .map(|z| (z, self.sample(Vec3::new(pos.x, pos.y, z), &invariant_z)))
+            .find(|(_, block)| *block != Block::AIR)
+            .filter(|(_, block)| block.is_solid())
+            .map(|(z, _)| z + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GenConfig;
+
+    #[test]
+    fn rings_cover_their_edge_once() {
+        assert_eq!(ring_offsets(0), vec![Vec2::zero()]);
+        for ring in 1..5 {
+            let mut offsets = ring_offsets(ring);
+            assert_eq!(offsets.len(), ring as usize * 8);
+            assert!(offsets.iter().all(|o| o.x.abs() == ring || o.y.abs() == ring));
+
+            offsets.sort_by_key(|o| (o.x, o.y));
+            offsets.dedup();
+            assert_eq!(offsets.len(), ring as usize * 8);
+        }
+    }
+
+    #[test]
+    fn spawn_is_on_solid_dry_ground() {
+        let gen = BlockGen::new(&GenConfig::default(), 1337);
+        let spawn = gen.spawn_point();
+        assert_ne!(spawn, FALLBACK_SPAWN);
+
+        let pos = spawn.map(|e| e.floor() as i64);
+        let invariant_z = gen.get_invariant_z(Vec2::from(pos));
+        let block_at = |z| gen.sample(Vec3::new(pos.x, pos.y, z), &invariant_z);
+
+        let ground = block_at(pos.z - 1);
+        assert!(ground.is_solid());
+        assert_ne!(ground, Block::WATER);
+        assert_eq!(block_at(pos.z), Block::AIR);
+        assert_eq!(block_at(pos.z + 1), Block::AIR);
+
+        // The same seed always gives the same spawn
+        assert_eq!(BlockGen::new(&GenConfig::default(), 1337).spawn_point(), spawn);
+    }
+}
@@ -15,10 +15,11 @@ use common::terrain::chunk::Block;
 // Local
 use crate::{
     cachegen::CacheGen,
-    new_seed,
+    config::GenConfig,
     overworldgen::{Out as OverworldOut, OverworldGen},
+    seed::sub_seed,
     util::structure::{dist_by_euc, StructureGen},
-    Gen,
+    CacheStats, Gen,
 };
 
 // <--- BEGIN MESS --->
@@ -530,29 +531,54 @@ pub struct TownGen {
 pub type InvariantZ = (BuildingGenOut, [BuildingGenOut; 9]);
 
 impl TownGen {
-    pub fn new() -> Self {
+    pub fn new(config: &GenConfig, seed: u64) -> Self {
         Self {
             city_gen: CacheGen::new(
                 StructureGen::new(
-                    350,         // freq
-                    256,         // warp
-                    new_seed(),  // seed
-                    dist_by_euc, // distance function
+                    350,                         // freq
+                    256,                         // warp
+                    sub_seed(seed, "town.city"), // seed
+                    dist_by_euc,                 // distance function
+                    config.structure_cache_size, // cell cache size
                 ),
-                4096,
+                config.city_cache_size,
             ),
             building_gen: CacheGen::new(
                 StructureGen::new(
-                    24,          // freq
-                    12,          // warp
-                    new_seed(),  // seed
-                    dist_by_euc, // distance function
+                    24,                              // freq
+                    12,                              // warp
+                    sub_seed(seed, "town.building"), // seed
+                    dist_by_euc,                     // distance function
+                    config.structure_cache_size,     // cell cache size
                 ),
-                4096,
+                config.building_cache_size,
             ),
         }
     }
 
+    pub fn resize_caches(&self, config: &GenConfig) {
+        self.city_gen.resize(config.city_cache_size);
+        self.city_gen.internal().resize_cache(config.structure_cache_size);
+        self.building_gen.resize(config.building_cache_size);
+        self.building_gen.internal().resize_cache(config.structure_cache_size);
+    }
+
+    pub fn cache_stats(&self) -> Vec<CacheStats> {
+        vec![
+            self.city_gen.stats("city"),
+            self.city_gen.internal().cache_stats("city cells"),
+            self.building_gen.stats("building"),
+            self.building_gen.internal().cache_stats("building cells"),
+        ]
+    }
+
+    pub fn reset_cache_stats(&self) {
+        self.city_gen.reset_stats();
+        self.city_gen.internal().reset_cache_stats();
+        self.building_gen.reset_stats();
+        self.building_gen.internal().reset_cache_stats();
+    }
+
     pub fn get_invariant_z<'a>(
         &'a self,
         pos: Vec2<i64>,
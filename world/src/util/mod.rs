@@ -1 +1,4 @@
 pub mod structure;
+
+#[cfg(test)]
+mod tests;
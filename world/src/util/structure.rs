@@ -2,7 +2,7 @@
 use vek::*;
 
 // Local
-use crate::{cachegen::CacheGen, Gen};
+use crate::{cachegen::CacheGen, CacheStats, Gen};
 
 #[allow(dead_code)]
 pub fn dist_by_euc(p: Vec2<i64>) -> i64 { (p * p).sum() }
@@ -37,16 +37,24 @@ pub struct StructureGen<O: 'static> {
 }
 
 impl<O> StructureGen<O> {
-    pub fn new(freq: u64, warp: u64, seed: u32, dist_func: fn(p: Vec2<i64>) -> i64) -> Self {
+    pub fn new(freq: u64, warp: u64, seed: u32, dist_func: fn(p: Vec2<i64>) -> i64, cache_size: usize) -> Self {
         Self {
             freq,
             warp,
             seed,
             dist_func,
-            cache: CacheGen::new(Producer, 256),
+            cache: CacheGen::new(Producer, cache_size),
         }
     }
 
+    pub fn resize_cache(&self, cache_size: usize) { self.cache.resize(cache_size); }
+
+    #[allow(dead_code)]
+    pub fn cache_hit_rate(&self) -> f64 { self.cache.hit_rate() }
+
+    pub fn cache_stats(&self, name: &'static str) -> CacheStats { self.cache.stats(name) }
+    pub fn reset_cache_stats(&self) { self.cache.reset_stats(); }
+
     pub fn throw_dice<T: Into<Vec3<i64>>>(&self, pos: T, seed: u32) -> u64 {
         // TODO: Make this actually good
         let pos = pos.into();
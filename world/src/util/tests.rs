@@ -0,0 +1,136 @@
+// Library
+use vek::*;
+
+// Project
+use common::terrain::{chunk::CHUNK_SIZE, VolCluster};
+
+// Local
+use super::structure::{dist_by_euc, StructureGen};
+#[cfg(feature = "bench-regression")]
+use crate::profile;
+use crate::{cachegen::CacheGen, seed::sub_seed, Gen, GenConfig, Generator, MAX_CHUNK_Z};
+
+struct Identity;
+
+impl Gen<()> for Identity {
+    type In = i64;
+    type Out = i64;
+
+    fn sample(&self, i: i64, _: &()) -> i64 { i }
+}
+
+#[test]
+fn cachegen_hits_and_misses() {
+    let gen = CacheGen::new(Identity, 4);
+
+    for i in 0..4 {
+        assert_eq!(gen.sample(i, &()), i);
+    }
+    for i in 0..4 {
+        assert_eq!(gen.sample(i, &()), i);
+    }
+
+    assert_eq!(gen.hits(), 4);
+    assert_eq!(gen.misses(), 4);
+    assert_eq!(gen.hit_rate(), 0.5);
+}
+
+#[test]
+fn cachegen_evicts_least_recently_used() {
+    let gen = CacheGen::new(Identity, 2);
+
+    gen.sample(0, &());
+    gen.sample(1, &());
+    gen.sample(0, &()); // 0 is now more recent than 1
+    gen.sample(2, &()); // Evicts 1
+    gen.reset_stats();
+
+    gen.sample(0, &());
+    gen.sample(2, &());
+    assert_eq!(gen.hits(), 2);
+
+    gen.sample(1, &());
+    assert_eq!(gen.misses(), 1);
+}
+
+#[test]
+fn cachegen_resize() {
+    let gen = CacheGen::new(Identity, 8);
+    for i in 0..8 {
+        gen.sample(i, &());
+    }
+    assert_eq!(gen.len(), 8);
+
+    gen.resize(3);
+    assert_eq!(gen.capacity(), 3);
+    assert_eq!(gen.len(), 3);
+
+    gen.resize(0);
+    gen.sample(0, &());
+    assert_eq!(gen.len(), 0);
+}
+
+#[test]
+fn structure_cache_hit_rate() {
+    // Same parameters as the building generator
+    let gen = StructureGen::new(24, 12, 0, dist_by_euc, 256);
+    let producer = |_: &StructureGen<Vec2<i64>>, pos: Vec2<i64>, _: &()| pos;
+
+    // Sample every column of a 16x16 chunk region, in the order chunk generation visits them
+    for cx in 0..16 {
+        for cy in 0..16 {
+            for x in 0..CHUNK_SIZE.x as i64 {
+                for y in 0..CHUNK_SIZE.y as i64 {
+                    let pos = Vec2::new(cx, cy) * Vec2::from(CHUNK_SIZE.map(|e| e as i64)) + Vec2::new(x, y);
+                    gen.sample(pos, &(&(), producer));
+                }
+            }
+        }
+    }
+
+    assert!(gen.cache_hit_rate() > 0.95, "hit rate was {}", gen.cache_hit_rate());
+}
+
+#[test]
+fn sub_seeds_dont_change() {
+    // Worlds would change under everyone's feet if these did
+    assert_eq!(sub_seed(0, "overworld.land"), 3573199465);
+    assert_eq!(sub_seed(1337, "overworld.land"), 4146887218);
+    assert_eq!(sub_seed(0, "overworld.dry"), 3590698895);
+}
+
+#[test]
+fn the_seed_decides_the_terrain() {
+    // A whole column, so that the surface is in there somewhere
+    let column = |seed| {
+        let generator = Generator::new(&GenConfig::default(), seed);
+        (0..=MAX_CHUNK_Z)
+            .flat_map(|z| generator.gen_chunk(Vec3::new(0, 0, z)).to_bytes().unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    let column_1 = column(1);
+    assert!(column_1 == column(1), "the same seed gave different terrain");
+    assert!(column_1 != column(2), "different seeds gave the same terrain");
+}
+
+// Opt in with the `bench-regression` feature, and build with `--release` if the baseline came from `world-bench`
+#[cfg(feature = "bench-regression")]
+#[test]
+fn generation_is_no_more_than_twice_as_slow_as_the_baseline() {
+    let baseline: f64 = std::env::var("WORLD_GEN_BASELINE_MS")
+        .expect("WORLD_GEN_BASELINE_MS should be the milliseconds per chunk world-bench gives for the reference region")
+        .parse()
+        .expect("WORLD_GEN_BASELINE_MS isn't a number");
+
+    let generator = Generator::new(&GenConfig::default(), profile::REFERENCE_SEED);
+    let (chunks, elapsed) = profile::time_region(&generator, profile::REFERENCE_SIZE);
+    let ms_per_chunk = elapsed.as_float_secs() * 1000.0 / chunks as f64;
+
+    assert!(
+        ms_per_chunk <= baseline * 2.0,
+        "took {:.3} ms per chunk against a baseline of {:.3}",
+        ms_per_chunk,
+        baseline
+    );
+}